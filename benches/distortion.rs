@@ -0,0 +1,43 @@
+#![feature(test)]
+
+extern crate interpreter;
+extern crate test;
+
+use interpreter::dsp::distortion::{softclip, quantize};
+use interpreter::dsp::table::Table;
+use test::{Bencher, black_box};
+
+// Each iteration processes one second of audio at 44.1kHz.
+const SAMPLES: usize = 44100;
+
+fn signal(i: usize) -> f64 {
+    (i as f64 * 0.01).sin()
+}
+
+#[bench]
+fn bench_softclip(b: &mut Bencher) {
+    b.iter(|| {
+        for i in 0..SAMPLES {
+            black_box(softclip(signal(i), 4.0));
+        }
+    });
+}
+
+#[bench]
+fn bench_waveshape(b: &mut Bencher) {
+    let table = Table::from_fn(1024, |x| (3.0 * x).tanh());
+    b.iter(|| {
+        for i in 0..SAMPLES {
+            black_box(table.lookup(signal(i)));
+        }
+    });
+}
+
+#[bench]
+fn bench_bitcrush(b: &mut Bencher) {
+    b.iter(|| {
+        for i in 0..SAMPLES {
+            black_box(quantize(signal(i), 8.0));
+        }
+    });
+}
@@ -0,0 +1,95 @@
+#![feature(test)]
+
+extern crate interpreter;
+extern crate test;
+
+use interpreter::{math, fastmath};
+use test::{Bencher, black_box};
+
+// Each iteration evaluates a function once per sample of one second of audio at 44.1kHz.
+const SAMPLES: usize = 44100;
+
+fn argument(i: usize) -> f64 {
+    i as f64 * 0.001 - 20.0
+}
+
+#[bench]
+fn bench_sin_platform(b: &mut Bencher) {
+    b.iter(|| {
+        for i in 0..SAMPLES {
+            black_box(argument(i).sin());
+        }
+    });
+}
+
+#[bench]
+fn bench_sin_portable(b: &mut Bencher) {
+    b.iter(|| {
+        for i in 0..SAMPLES {
+            black_box(math::sin(argument(i)));
+        }
+    });
+}
+
+#[bench]
+fn bench_sin_fast(b: &mut Bencher) {
+    b.iter(|| {
+        for i in 0..SAMPLES {
+            black_box(fastmath::sin(argument(i)));
+        }
+    });
+}
+
+#[bench]
+fn bench_exp_platform(b: &mut Bencher) {
+    b.iter(|| {
+        for i in 0..SAMPLES {
+            black_box(argument(i).exp());
+        }
+    });
+}
+
+#[bench]
+fn bench_exp_portable(b: &mut Bencher) {
+    b.iter(|| {
+        for i in 0..SAMPLES {
+            black_box(math::exp(argument(i)));
+        }
+    });
+}
+
+#[bench]
+fn bench_exp_fast(b: &mut Bencher) {
+    b.iter(|| {
+        for i in 0..SAMPLES {
+            black_box(fastmath::exp(argument(i)));
+        }
+    });
+}
+
+#[bench]
+fn bench_pow_platform(b: &mut Bencher) {
+    b.iter(|| {
+        for i in 0..SAMPLES {
+            black_box(argument(i).abs().powf(1.7));
+        }
+    });
+}
+
+#[bench]
+fn bench_pow_portable(b: &mut Bencher) {
+    b.iter(|| {
+        for i in 0..SAMPLES {
+            black_box(math::pow(argument(i).abs(), 1.7));
+        }
+    });
+}
+
+#[bench]
+fn bench_pow_fast(b: &mut Bencher) {
+    b.iter(|| {
+        for i in 0..SAMPLES {
+            black_box(fastmath::pow(argument(i).abs(), 1.7));
+        }
+    });
+}
@@ -0,0 +1,84 @@
+#[cfg(feature = "count-allocations")]
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+static ALLOCATIONS: AtomicUsize = ATOMIC_USIZE_INIT;
+static VIOLATIONS: AtomicUsize = ATOMIC_USIZE_INIT;
+
+// Allocations and deallocations made by the current thread. A Cell needs no destructor, so the
+// allocator can use it without allocating itself.
+thread_local!(static THREAD_HEAP_USE: Cell<usize> = Cell::new(0));
+
+/// Wraps the system allocator and counts every allocation and deallocation. Only built with the
+/// `count-allocations` feature, with which binaries and tests opt in with:
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOCATOR: CountingAllocator = CountingAllocator;
+/// ```
+#[cfg(feature = "count-allocations")]
+pub struct CountingAllocator;
+
+#[cfg(feature = "count-allocations")]
+impl CountingAllocator {
+    fn count(&self) {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        self.count_thread();
+    }
+
+    fn count_thread(&self) {
+        // the thread's storage is gone while it is being torn down
+        let _ = THREAD_HEAP_USE.try_with(|x| x.set(x.get() + 1));
+    }
+}
+
+#[cfg(feature = "count-allocations")]
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.count();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.count_thread();
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.count();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// The number of allocations made so far on any thread. Always zero unless CountingAllocator is
+/// the global allocator.
+pub fn allocations() -> usize {
+    ALLOCATIONS.load(Ordering::Relaxed)
+}
+
+/// Runs `f`, returning its result and the number of allocations and deallocations it made. Those
+/// by other threads in the meantime are not counted.
+pub fn count_allocations<F, R>(f: F) -> (R, usize) where F: FnOnce() -> R {
+    let before = THREAD_HEAP_USE.with(|x| x.get());
+    let res = f();
+    let after = THREAD_HEAP_USE.with(|x| x.get());
+    (res, after - before)
+}
+
+/// Runs code which must not use the heap, such as an audio callback, counting a violation if `f`
+/// allocated or freed memory. Nothing is reported from here, since it may be running on a thread
+/// which can't block or unwind; read violations() once it is done instead. Only noticed when
+/// CountingAllocator is the global allocator.
+pub fn check_no_alloc<F, R>(f: F) -> R where F: FnOnce() -> R {
+    let (res, count) = count_allocations(f);
+    if count > 0 {
+        VIOLATIONS.fetch_add(1, Ordering::Relaxed);
+    }
+    res
+}
+
+/// The number of calls to check_no_alloc() so far which used the heap.
+pub fn violations() -> usize {
+    VIOLATIONS.load(Ordering::Relaxed)
+}
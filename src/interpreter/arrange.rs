@@ -0,0 +1,83 @@
+use super::ast::*;
+use super::ident::Identifier;
+use super::tokens::{Number, Node, Operator, SourcePos};
+
+use std::rc::Rc;
+
+#[derive(Clone, Debug)]
+pub struct Section {
+    pub bar: Number,
+    pub func: Node<Identifier>,
+    pub pos: SourcePos,
+}
+
+/// The body of the function which plays the sections in turn, as the sum of every section
+/// weighted by how much of it is heard at the current bar. The sections must be in order, and
+/// none may be shorter than the fade.
+pub fn body(time: Identifier, bar: Identifier, sections: &[Section], fade: Number) -> Block {
+    let mut terms = sections.iter().enumerate().map(|(i, section)| {
+        let pos = section.pos;
+        let call = Expression::FunctionCall(Rc::new(Node(FunctionCall {
+            callee: Expression::Variable(section.func),
+            args: Node(vec![Argument::Expr(Expression::Variable(Node(time, pos)))], pos),
+            ty: CallType::Ordered,
+        }, pos)));
+        infix(Operator::Mul, call, weight(bar, sections, i, fade), pos)
+    });
+    let first = terms.next().expect("an arrangement has at least one section");
+    let sum = terms.fold(first, |sum, term| {
+        let pos = term.pos();
+        infix(Operator::Add, sum, term, pos)
+    });
+    vec![Statement::Expression(sum)]
+}
+
+// How much of a section is heard at the current bar, from 0 to 1, as a chain of conditionals on
+// the bar with one branch for each step of the section's envelope.
+fn weight(bar: Identifier, sections: &[Section], i: usize, fade: Number) -> Expression {
+    let pos = sections[i].pos;
+    let start = sections[i].bar;
+    let bar = Expression::Variable(Node(bar, pos));
+    let ramp = |from: Number, to: Number| {
+        let dist = infix(Operator::Sub, bar.clone(), constant(from, pos), pos);
+        infix(Operator::Div, dist, constant(to - from, pos), pos)
+    };
+
+    // each step is the bar it lasts until and the weight during it
+    let mut steps = Vec::new();
+    if i > 0 || start > 0.0 {
+        steps.push((start, constant(0.0, pos)));
+    }
+    if i > 0 && fade > 0.0 {
+        steps.push((start + fade, ramp(start, start + fade)));
+    }
+    let last = match sections.get(i + 1) {
+        Some(next) => {
+            steps.push((next.bar, constant(1.0, pos)));
+            if fade > 0.0 {
+                steps.push((next.bar + fade, ramp(next.bar + fade, next.bar)));
+            }
+            constant(0.0, pos)
+        }
+        None => constant(1.0, pos),
+    };
+    steps.into_iter().rev().fold(last, |els, (until, then)| {
+        Expression::Conditional(Rc::new(Node(Conditional {
+            cond: infix(Operator::Less, bar.clone(), constant(until, pos), pos),
+            then: then,
+            els: els,
+        }, pos)))
+    })
+}
+
+fn constant(x: Number, pos: SourcePos) -> Expression {
+    Expression::Constant(Node(x, pos))
+}
+
+fn infix(op: Operator, left: Expression, right: Expression, pos: SourcePos) -> Expression {
+    Expression::Infix(Rc::new(Node(Infix {
+        op: Node(op, pos),
+        left: left,
+        right: right,
+    }, pos)))
+}
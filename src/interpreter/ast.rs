@@ -1,9 +1,12 @@
 use super::tokens::{Number, Operator, SourcePos, Node, NodeImpl};
 use super::ident::Identifier;
+use super::types::Type;
 
+use std::fmt;
 use std::ops::Deref;
+use std::rc::Rc;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, RustcEncodable)]
 pub enum Item {
     Assignment(Node<Assignment>),
     FunctionDef(Node<FunctionDef>),
@@ -19,7 +22,7 @@ impl Item {
 }
 
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, RustcEncodable)]
 pub enum Statement {
     Assignment(Node<Assignment>),
     Expression(Expression),
@@ -38,15 +41,17 @@ pub type Root = Vec<Item>;
 
 pub type Block = Vec<Statement>;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, RustcEncodable)]
 pub struct FunctionDef {
     pub ident: Node<Identifier>,
     pub func: Node<Function>,
+    pub docs: Option<String>, // from `///` comments directly above a top level definition
 }
 
 impl FunctionDef {
     pub fn ident(&self) -> Identifier { *self.ident.item() }
     pub fn ident_pos(&self) -> SourcePos { self.ident.pos() }
+    pub fn docs(&self) -> Option<&str> { self.docs.as_ref().map(|x| &x[..]) }
 }
 
 impl Deref for FunctionDef {
@@ -57,10 +62,12 @@ impl Deref for FunctionDef {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, RustcEncodable)]
 pub struct Function {
     pub args: Node<ArgumentList>,
-    pub block: Node<Block>,
+    pub block: Rc<Node<Block>>,
+    pub arg_types: Vec<Option<Node<TypeName>>>, // parallel to args
+    pub returns: Option<Node<TypeName>>,
 }
 
 impl Function {
@@ -68,9 +75,55 @@ impl Function {
     pub fn args_pos(&self) -> SourcePos { self.args.pos() }
     pub fn block(&self) -> &Block { &self.block }
     pub fn block_pos(&self) -> SourcePos { self.block.pos() }
+    pub fn returns(&self) -> Option<Node<TypeName>> { self.returns }
+
+    /// The annotated type of the argument with the given identifier, if any.
+    pub fn arg_type(&self, id: Identifier) -> Option<Node<TypeName>> {
+        self.args.iter().position(|x| x.ident() == Some(id))
+            .and_then(|idx| self.arg_types[idx])
+    }
+}
+
+/// A type written in the source as an annotation.
+#[derive(Copy, Clone, Debug, PartialEq, RustcEncodable)]
+pub enum TypeName {
+    Number,
+    Int,
+    Boolean,
+    Function,
+}
+
+impl TypeName {
+    pub fn parse(s: &str) -> Option<TypeName> {
+        Some(match s {
+            "Number" => TypeName::Number,
+            "Int" => TypeName::Int,
+            "Boolean" => TypeName::Boolean,
+            "Function" => TypeName::Function,
+            _ => return None,
+        })
+    }
+
+    /// Checks if an inferred type satisfies the annotation.
+    pub fn matches(&self, ty: Type) -> bool {
+        match (*self, ty) {
+            (_, Type::Var(_)) => true,
+            (TypeName::Number, Type::Number) | (TypeName::Number, Type::Int) => true,
+            (TypeName::Int, Type::Int) => true,
+            (TypeName::Boolean, Type::Boolean) => true,
+            (TypeName::Function, Type::Function(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for TypeName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, RustcEncodable)]
 pub struct FunctionCall {
     pub callee: Expression,
     pub args: Node<ArgumentList>,
@@ -85,14 +138,14 @@ impl FunctionCall {
     pub fn ty(&self) -> CallType { self.ty.clone() }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, RustcEncodable)]
 pub enum CallType {
     Named,
     Ordered,
 }
 
 // At most one of the two can be None
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, RustcEncodable)]
 pub enum Argument {
     Ident(Node<Identifier>),
     Assign(Node<Identifier>, Expression),
@@ -129,20 +182,22 @@ impl Argument {
 
 pub type ArgumentList = Vec<Argument>;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, RustcEncodable)]
 pub struct Assignment {
     pub ident: Node<Identifier>,
     pub expr: Expression,
+    pub docs: Option<String>, // from `///` comments directly above a top level assignment
 }
 
 impl Assignment {
     pub fn ident(&self) -> Identifier { *self.ident.item() }
+    pub fn docs(&self) -> Option<&str> { self.docs.as_ref().map(|x| &x[..]) }
     pub fn ident_pos(&self) -> SourcePos { self.ident.pos() }
     pub fn expr(&self) -> &Expression { &self.expr }
     pub fn expr_pos(&self) -> SourcePos { self.expr.pos() }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, RustcEncodable)]
 pub struct Conditional {
     pub cond: Expression,
     pub then: Expression,
@@ -158,7 +213,61 @@ impl Conditional {
     pub fn els_pos(&self) -> SourcePos { self.els.pos() }
 }
 
-#[derive(Clone, Debug)]
+/// An expression evaluated only at the start of each block of samples, written `@block expr`.
+/// With `@smooth expr` the value ramps linearly from the previous block's value instead of
+/// jumping to it, which delays it by a block.
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct BlockRate {
+    pub smooth: bool,
+    pub expr: Expression,
+}
+
+impl BlockRate {
+    pub fn expr(&self) -> &Expression { &self.expr }
+    pub fn expr_pos(&self) -> SourcePos { self.expr.pos() }
+}
+
+/// An expression evaluated several times per sample, written `@oversample(4) expr`, so that
+/// nonlinear sections such as distortion or FM alias less. The numbers it reads from outside are
+/// interpolated between samples and its result is lowpass filtered back down.
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct Oversample {
+    pub factor: usize,
+    pub expr: Expression,
+}
+
+impl Oversample {
+    pub fn expr(&self) -> &Expression { &self.expr }
+    pub fn expr_pos(&self) -> SourcePos { self.expr.pos() }
+}
+
+/// A section which changes the spectrum of a signal, written `@fft(x) expr`. The expression is
+/// evaluated for each bin of the spectrum of `x` and gives its new value. See dsp/spectral.rs.
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct FftSection {
+    pub input: Expression,
+    pub expr: Expression,
+}
+
+impl FftSection {
+    pub fn input(&self) -> &Expression { &self.input }
+    pub fn expr(&self) -> &Expression { &self.expr }
+    pub fn expr_pos(&self) -> SourcePos { self.expr.pos() }
+}
+
+/// An expression evaluated once for each voice of the polyphony engine, written `voice { ... }`,
+/// which gives the sum of every voice. See poly.rs.
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct VoiceBlock {
+    pub expr: Expression,
+}
+
+impl VoiceBlock {
+    pub fn expr(&self) -> &Expression { &self.expr }
+    pub fn expr_pos(&self) -> SourcePos { self.expr.pos() }
+}
+
+#[derive(Clone, Debug, RustcEncodable)]
 pub struct Infix {
     pub op: Node<Operator>,
     pub left: Expression,
@@ -174,7 +283,7 @@ impl Infix {
     pub fn right_pos(&self) -> SourcePos { self.right.pos() }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, RustcEncodable)]
 pub struct Prefix {
     pub op: Node<Operator>,
     pub expr: Expression,
@@ -187,17 +296,49 @@ impl Prefix {
     pub fn expr_pos(&self) -> SourcePos { self.expr.pos() }
 }
 
-#[derive(Clone, Debug)]
+/// A record literal, written `{freq: 440, amp: 0.5}`. See record.rs.
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct Record {
+    pub fields: Vec<(Node<Identifier>, Expression)>, // in the order they were written
+}
+
+/// Reading a field of a record, written `note.freq`.
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct Field {
+    pub expr: Expression,
+    pub field: Node<Identifier>,
+}
+
+impl Field {
+    pub fn expr(&self) -> &Expression { &self.expr }
+    pub fn expr_pos(&self) -> SourcePos { self.expr.pos() }
+    pub fn field(&self) -> Identifier { *self.field.item() }
+    pub fn field_pos(&self) -> SourcePos { self.field.pos() }
+}
+
+/// Nodes below an expression are shared rather than owned, so cloning a tree, such as when a
+/// function's definition is recorded or instantiated, only copies its root. They are reference
+/// counted rather than kept in an arena, and the passes still walk them by reference; nothing
+/// hashes or deduplicates them by structure.
+#[derive(Clone, Debug, RustcEncodable)]
 pub enum Expression {
     Constant(Node<Number>),
     Boolean(Node<bool>),
-    Infix(Box<Node<Infix>>),
-    Prefix(Box<Node<Prefix>>),
+    Str(Node<usize>), // index into the string literals of the context
+    Infix(Rc<Node<Infix>>),
+    Prefix(Rc<Node<Prefix>>),
     Variable(Node<Identifier>),
-    Block(Node<Block>),
-    FunctionCall(Box<Node<FunctionCall>>),
-    Conditional(Box<Node<Conditional>>),
-    Closure(Box<Node<FunctionDef>>),
+    Block(Rc<Node<Block>>),
+    FunctionCall(Rc<Node<FunctionCall>>),
+    Conditional(Rc<Node<Conditional>>),
+    Closure(Rc<Node<FunctionDef>>),
+    BlockRate(Rc<Node<BlockRate>>),
+    Oversample(Rc<Node<Oversample>>),
+    Fft(Rc<Node<FftSection>>),
+    Voice(Rc<Node<VoiceBlock>>),
+    Array(Rc<Node<Vec<Expression>>>), // the elements of an array literal
+    Record(Rc<Node<Record>>),
+    Field(Rc<Node<Field>>),
 }
 
 impl Expression {
@@ -206,6 +347,7 @@ impl Expression {
         match *self {
             Constant(ref x) => x.pos(),
             Boolean(ref x) => x.pos(),
+            Str(ref x) => x.pos(),
             Infix(ref x) => x.pos(),
             Prefix(ref x) => x.pos(),
             Variable(ref x) => x.pos(),
@@ -213,6 +355,13 @@ impl Expression {
             FunctionCall(ref x) => x.pos(),
             Conditional(ref x) => x.pos(),
             Closure(ref x) => x.pos(),
+            BlockRate(ref x) => x.pos(),
+            Oversample(ref x) => x.pos(),
+            Fft(ref x) => x.pos(),
+            Voice(ref x) => x.pos(),
+            Array(ref x) => x.pos(),
+            Record(ref x) => x.pos(),
+            Field(ref x) => x.pos(),
         }
     }
 }
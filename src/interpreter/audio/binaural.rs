@@ -0,0 +1,121 @@
+use super::super::dsp::ambisonics;
+use super::super::tokens::Number;
+
+use std::f64::consts::PI;
+
+/// The radius of the spherical head model of Brown and Duda the responses are built from, in
+/// metres.
+pub const HEAD_RADIUS: Number = 0.0875;
+
+/// The speed of sound, in metres per second.
+pub const SPEED_OF_SOUND: Number = 343.0;
+
+// Virtual speakers in the ring the field is decoded to.
+const SPEAKERS: usize = 8;
+
+// The length of the responses, which covers the longest delay and the decay of the shadowing
+// filter at any sample rate.
+const RESPONSE_SECONDS: Number = 0.003;
+
+/// How much later a sound arrives at an ear than at the centre of the head, plus the time it takes
+/// to cross the radius, so that it is never negative. `angle` is between the direction of the
+/// source and that of the ear.
+pub fn interaural_delay(angle: Number) -> Number {
+    let angle = angle.abs();
+    let radius_time = HEAD_RADIUS / SPEED_OF_SOUND;
+    if angle < PI / 2.0 {
+        radius_time * (1.0 - angle.cos())
+    } else {
+        radius_time * (1.0 + angle - PI / 2.0)
+    }
+}
+
+/// The impulse response of an ear at `ear` radians to a source at `azimuth`, both anticlockwise
+/// from straight ahead. Its gain at 0 Hz is 1, and at high frequencies from 2 facing the source
+/// down to 0.1 at 150 degrees from it.
+pub fn head_response(azimuth: Number, ear: Number, sample_rate: u32) -> Vec<Number> {
+    let rate = sample_rate as Number;
+    let taps = (RESPONSE_SECONDS * rate).ceil() as usize;
+    // the angle between the source and the ear, from 0 to pi
+    let angle = (((azimuth - ear) % (2.0 * PI) + 3.0 * PI) % (2.0 * PI) - PI).abs();
+    // (1 + alpha s / 2w0) / (1 + s / 2w0) by the bilinear transform, where w0 = c / a
+    let alpha = 1.05 + 0.95 * (angle * 180.0 / 150.0).cos();
+    let tau_k = HEAD_RADIUS / (2.0 * SPEED_OF_SOUND) * 2.0 * rate;
+    let (b0, b1) = ((1.0 + alpha * tau_k) / (1.0 + tau_k), (1.0 - alpha * tau_k) / (1.0 + tau_k));
+    let a1 = (1.0 - tau_k) / (1.0 + tau_k);
+    // an impulse at a fractional delay, shared between the samples either side of it
+    let delay = interaural_delay(angle) * rate;
+    let (whole, fraction) = (delay.floor() as usize, delay.fract());
+    let mut response = Vec::with_capacity(taps);
+    let (mut last_x, mut last_y) = (0.0, 0.0);
+    for i in 0..taps {
+        let x = if i == whole { 1.0 - fraction } else if i == whole + 1 { fraction } else { 0.0 };
+        let y = b0 * x + b1 * last_x - a1 * last_y;
+        response.push(y);
+        last_x = x;
+        last_y = y;
+    }
+    response
+}
+
+/// Renders a first order ambisonic signal for headphones, one frame at a time.
+pub struct Binaural {
+    // for each ear, the filters taking the W, Y and X channels to it
+    filters: [[Vec<Number>; 3]; 2],
+    // the last W, Y and X samples, each written twice so the newest are always contiguous
+    history: [Vec<Number>; 3],
+    pos: usize,
+}
+
+impl Binaural {
+    pub fn new(sample_rate: u32) -> Binaural {
+        let taps = (RESPONSE_SECONDS * sample_rate as Number).ceil() as usize;
+        let filter = || [vec![0.0; taps], vec![0.0; taps], vec![0.0; taps]];
+        let mut filters = [filter(), filter()];
+        for (filters, &ear) in filters.iter_mut().zip(&[PI / 2.0, -PI / 2.0]) {
+            for k in 0..SPEAKERS {
+                let azimuth = 2.0 * PI * k as Number / SPEAKERS as Number;
+                // the gains `ambisonics::decode` gives each channel for this speaker
+                let mut gains = [0.0; 3];
+                for (gain, channel) in gains.iter_mut().zip(&[0, 1, 3]) {
+                    let mut b = [0.0; ambisonics::CHANNELS];
+                    b[*channel] = 1.0;
+                    *gain = ambisonics::decode(&b, azimuth, SPEAKERS);
+                }
+                let response = head_response(azimuth, ear, sample_rate);
+                for (filter, gain) in filters.iter_mut().zip(&gains) {
+                    for (tap, x) in filter.iter_mut().zip(&response) {
+                        *tap += gain * x;
+                    }
+                }
+            }
+        }
+        // the history runs from oldest to newest, so the filters are reversed to convolve with it
+        for filter in filters.iter_mut().flat_map(|x| x.iter_mut()) {
+            filter.reverse();
+        }
+        Binaural {
+            filters: filters,
+            history: [vec![0.0; 2 * taps], vec![0.0; 2 * taps], vec![0.0; 2 * taps]],
+            pos: 0,
+        }
+    }
+
+    /// The left and right channels for the next frame of W, Y, Z and X.
+    pub fn process(&mut self, b: &[Number]) -> (Number, Number) {
+        let taps = self.filters[0][0].len();
+        for (history, &x) in self.history.iter_mut().zip(&[b[0], b[1], b[3]]) {
+            history[self.pos] = x;
+            history[self.pos + taps] = x;
+        }
+        self.pos = (self.pos + 1) % taps;
+        let mut ears = [0.0; 2];
+        for (ear, filters) in ears.iter_mut().zip(&self.filters) {
+            for (filter, history) in filters.iter().zip(&self.history) {
+                let window = &history[self.pos..self.pos + taps];
+                *ear += filter.iter().zip(window).fold(0.0, |sum, (a, b)| sum + a * b);
+            }
+        }
+        (ears[0], ears[1])
+    }
+}
@@ -0,0 +1,246 @@
+use super::super::runtime::Program;
+use super::super::tokens::Number;
+use super::Sample;
+
+use std::cell::UnsafeCell;
+use std::mem;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+struct Ring<T> {
+    slots: Vec<UnsafeCell<Option<T>>>,
+    read: AtomicUsize,
+    write: AtomicUsize,
+}
+
+// Only the producer writes a slot between `read` and `write`, and only the consumer takes one out.
+unsafe impl<T: Send> Sync for Ring<T> { }
+
+/// Creates a ring buffer which holds up to `capacity` values. Nothing is allocated after this.
+pub fn ring<T: Send>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    // one slot is always empty, to tell a full ring from an empty one
+    let ring = Arc::new(Ring {
+        slots: (0..capacity + 1).map(|_| UnsafeCell::new(None)).collect(),
+        read: AtomicUsize::new(0),
+        write: AtomicUsize::new(0),
+    });
+    (Producer { ring: ring.clone() }, Consumer { ring: ring })
+}
+
+/// The sending end of a ring buffer.
+pub struct Producer<T> {
+    ring: Arc<Ring<T>>,
+}
+
+impl<T: Send> Producer<T> {
+    /// Adds a value to the ring, or gives it back if the ring is full.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        let ring = &*self.ring;
+        let write = ring.write.load(Ordering::Relaxed);
+        let next = (write + 1) % ring.slots.len();
+        if next == ring.read.load(Ordering::Acquire) {
+            return Err(value);
+        }
+        unsafe { *ring.slots[write].get() = Some(value); }
+        ring.write.store(next, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// The receiving end of a ring buffer.
+pub struct Consumer<T> {
+    ring: Arc<Ring<T>>,
+}
+
+impl<T: Send> Consumer<T> {
+    /// Takes the oldest value out of the ring, if there is one.
+    pub fn pop(&mut self) -> Option<T> {
+        let ring = &*self.ring;
+        let read = ring.read.load(Ordering::Relaxed);
+        if read == ring.write.load(Ordering::Acquire) {
+            return None;
+        }
+        let value = unsafe { (*ring.slots[read].get()).take() };
+        ring.read.store((read + 1) % ring.slots.len(), Ordering::Release);
+        value
+    }
+}
+
+/// A change to a running program other than setting a parameter.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Command {
+    SetTempo(Number),
+    /// Restarts the program, as if it was just created.
+    Reset,
+    /// Continues from the given sample index.
+    Seek(u64),
+    /// Plays a MIDI note number at a velocity from 0 to 1.
+    NoteOn(Number, Number),
+    NoteOff(Number),
+}
+
+// The bits of each parameter's value, in the order of Program::params().
+struct ParamTable {
+    names: Vec<String>,
+    values: Vec<AtomicU64>,
+}
+
+/// Sets the parameters of a program from any thread. Cloning the handle is cheap, and every clone
+/// refers to the same parameters.
+#[derive(Clone)]
+pub struct ParamHandle {
+    table: Arc<ParamTable>,
+}
+
+impl ParamHandle {
+    /// Changes a parameter, which the program will ramp to from the next block on. Returns false
+    /// if there is no such parameter.
+    pub fn set(&self, name: &str, value: Number) -> bool {
+        match self.table.names.iter().position(|x| x == name) {
+            Some(i) => {
+                self.table.values[i].store(to_bits(value), Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The last value a parameter was set to, which the program may still be ramping towards.
+    pub fn get(&self, name: &str) -> Option<Number> {
+        self.table.names.iter().position(|x| x == name)
+            .map(|i| from_bits(self.table.values[i].load(Ordering::Relaxed)))
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.table.names
+    }
+}
+
+fn to_bits(x: Number) -> u64 {
+    unsafe { mem::transmute(x) }
+}
+
+fn from_bits(x: u64) -> Number {
+    unsafe { mem::transmute(x) }
+}
+
+/// The controlling end, which may be moved to another thread. Commands may only be sent from one
+/// thread, but parameters can be set from any number of them through params().
+pub struct Remote {
+    params: ParamHandle,
+    commands: Producer<Command>,
+}
+
+impl Remote {
+    pub fn params(&self) -> ParamHandle {
+        self.params.clone()
+    }
+
+    pub fn set_param(&self, name: &str, value: Number) -> bool {
+        self.params.set(name, value)
+    }
+
+    /// Queues a command for the start of the next block. Returns false if the queue is full.
+    pub fn send(&mut self, command: Command) -> bool {
+        self.commands.push(command).is_ok()
+    }
+}
+
+// A linear ramp from the current value of a parameter to its target.
+struct Slew {
+    value: Number,
+    target: Number,
+    step: Number,
+    remaining: usize,
+    smoothed: bool, // by the program, so changes are not ramped
+}
+
+impl Slew {
+    fn next(&mut self) -> Number {
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            self.value = if self.remaining == 0 { self.target } else { self.value + self.step };
+        }
+        self.value
+    }
+}
+
+/// The end of the controls owned by the thread rendering the program.
+pub struct Control {
+    params: Arc<ParamTable>,
+    commands: Consumer<Command>,
+    slews: Vec<Slew>, // from the index of a parameter
+    slew_time: Number,
+}
+
+/// How many commands can be waiting at once.
+pub const COMMAND_CAPACITY: usize = 256;
+
+impl Control {
+    /// Creates the controls of a program, starting from its current parameters. Parameter
+    /// changes ramp over `slew_time` seconds, except for those the program smooths itself, which
+    /// would be smoothed twice otherwise.
+    pub fn new(program: &Program, slew_time: Number) -> (Control, Remote) {
+        let table = Arc::new(ParamTable {
+            names: program.params().iter().map(|x| x.0.clone()).collect(),
+            values: program.params().iter().map(|x| AtomicU64::new(to_bits(x.1))).collect(),
+        });
+        let (producer, consumer) = ring(COMMAND_CAPACITY);
+        let control = Control {
+            params: table.clone(),
+            commands: consumer,
+            slews: program.params().iter().enumerate().map(|(i, x)| Slew {
+                value: x.1,
+                target: x.1,
+                step: 0.0,
+                remaining: 0,
+                smoothed: program.param_smoothed(i),
+            }).collect(),
+            slew_time: slew_time,
+        };
+        let remote = Remote {
+            params: ParamHandle { table: table },
+            commands: producer,
+        };
+        (control, remote)
+    }
+
+    /// Fills the buffer with the next samples of the program, first applying any commands and
+    /// parameter changes which arrived since the last call. Never blocks or allocates.
+    pub fn fill<S: Sample>(&mut self, program: &mut Program, buffer: &mut [S]) {
+        while let Some(command) = self.commands.pop() {
+            match command {
+                Command::SetTempo(tempo) => program.set_tempo(tempo),
+                Command::Reset => program.reset(),
+                Command::Seek(index) => program.seek(index),
+                Command::NoteOn(note, velocity) => program.note_on(note, velocity),
+                Command::NoteOff(note) => program.note_off(note),
+            }
+        }
+        let ramp = (self.slew_time * program.sample_rate() as Number).max(1.0) as usize;
+        for (index, (slew, value)) in self.slews.iter_mut().zip(self.params.values.iter()).enumerate() {
+            let target = from_bits(value.load(Ordering::Relaxed));
+            if target != slew.target && slew.smoothed {
+                slew.target = target;
+                slew.value = target;
+                program.set_param_at(index, target);
+            } else if target != slew.target {
+                slew.target = target;
+                slew.step = (target - slew.value) / ramp as Number;
+                slew.remaining = ramp;
+            }
+        }
+        if self.slews.iter().all(|x| x.remaining == 0) {
+            program.fill(buffer);
+            return;
+        }
+        // the same as Program::fill(), but moving the parameters between samples
+        for i in 0..buffer.len() / program.channels() {
+            for (index, slew) in self.slews.iter_mut().enumerate() {
+                program.set_param_at(index, slew.next());
+            }
+            program.next();
+            program.write_frame(buffer, i);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::path::Path;
+
+/// The bitrate compressed files are written at unless another is asked for, in kbit/s.
+pub const DEFAULT_BITRATE: u32 = 192;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Codec {
+    Mp3,
+    Aac,
+}
+
+impl Codec {
+    /// The codec of a file with the extension of `path`, or None for a WAV or unknown file.
+    pub fn from_path(path: &Path) -> Option<Codec> {
+        let extension = path.extension().and_then(|x| x.to_str()).map(|x| x.to_lowercase());
+        match extension.as_ref().map(|x| &x[..]) {
+            Some("mp3") => Some(Codec::Mp3),
+            Some("aac") | Some("m4a") => Some(Codec::Aac),
+            _ => None,
+        }
+    }
+}
+
+/// Something which turns a WAV file into a compressed one.
+pub trait Encoder {
+    fn encode(&self, input: &Path, output: &Path, codec: Codec) -> Result<(), String>;
+}
+
+/// The encoder this build has, if any, writing at `bitrate` kbit/s.
+#[cfg(feature = "encode")]
+pub fn encoder(bitrate: u32) -> Option<Box<Encoder>> {
+    Some(Box::new(Ffmpeg::new(bitrate)))
+}
+#[cfg(not(feature = "encode"))]
+pub fn encoder(_: u32) -> Option<Box<Encoder>> {
+    None
+}
+
+/// Encodes by running ffmpeg, which must be on the path.
+#[cfg(feature = "encode")]
+pub struct Ffmpeg {
+    pub program: String,
+    pub bitrate: u32,
+}
+
+#[cfg(feature = "encode")]
+impl Ffmpeg {
+    pub fn new(bitrate: u32) -> Ffmpeg {
+        Ffmpeg {
+            program: "ffmpeg".into(),
+            bitrate: bitrate,
+        }
+    }
+
+    /// The arguments ffmpeg is run with, which overwrite the output without asking.
+    pub fn arguments(&self, input: &Path, output: &Path, codec: Codec) -> Vec<String> {
+        let codec = match codec {
+            Codec::Mp3 => "libmp3lame",
+            Codec::Aac => "aac",
+        };
+        vec!["-y".into(), "-loglevel".into(), "error".into(), "-i".into(), input.to_string_lossy().into_owned(),
+             "-c:a".into(), codec.into(), "-b:a".into(), format!("{}k", self.bitrate),
+             output.to_string_lossy().into_owned()]
+    }
+}
+
+#[cfg(feature = "encode")]
+impl Encoder for Ffmpeg {
+    fn encode(&self, input: &Path, output: &Path, codec: Codec) -> Result<(), String> {
+        use std::process::Command;
+
+        let result = try!(Command::new(&self.program).args(&self.arguments(input, output, codec)).output()
+                          .map_err(|e| format!("couldn't run {}: {}", self.program, e)));
+        if result.status.success() {
+            Ok(())
+        } else {
+            Err(format!("{} couldn't encode {}: {}", self.program, output.display(),
+                        String::from_utf8_lossy(&result.stderr).trim()))
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use super::super::buffer::Audio;
+
+use hound;
+use std::path::Path;
+
+/// Reads a WAV file, mixing its channels down to one.
+pub fn read_wav(filename: &Path) -> Result<Audio, String> {
+    let mut reader = match hound::WavReader::open(filename) {
+        Ok(reader) => reader,
+        Err(why) => return Err(format!("couldn't open {}: {}", filename.display(), why)),
+    };
+    let spec = reader.spec();
+    let channels = spec.channels.max(1) as usize;
+    let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32;
+    let mut samples = Vec::new();
+    let mut frame = 0.0;
+    for (i, sample) in reader.samples::<i32>().enumerate() {
+        match sample {
+            Ok(x) => frame += x as f32 * scale,
+            Err(why) => return Err(format!("couldn't read {}: {}", filename.display(), why)),
+        }
+        if i % channels == channels - 1 {
+            samples.push(frame / channels as f32);
+            frame = 0.0;
+        }
+    }
+    Ok(Audio {
+        samples: samples,
+        sample_rate: spec.sample_rate,
+    })
+}
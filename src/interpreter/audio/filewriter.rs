@@ -1,28 +1,90 @@
-use super::super::compiler::Compiler;
-use super::render_samples;
+use super::super::runtime::Program;
+use super::{render_samples, output_channel, RenderSettings, Precision, Sample};
+use super::wav::ExtensibleWriter;
 
 use hound;
+use std::fs::File;
+use std::io::BufWriter;
 
-pub fn write_wav(compiler: &Compiler, filename: String, length: f32) {
+/// Renders `length` seconds of the program to a WAV file. Fails if the file can't be created or
+/// the program fails before the end.
+pub fn write_wav(program: Program, filename: String, length: f32, settings: &RenderSettings) -> Result<(), String> {
+    match settings.precision {
+        Precision::Single => write::<f32>(program, filename, length, settings, 16),
+        Precision::Double => write::<f64>(program, filename, length, settings, 24),
+    }
+}
+
+// A file with a channel layout records it in its header, which hound cannot write.
+enum Writer {
+    Plain(hound::WavWriter<BufWriter<File>>),
+    Extensible(ExtensibleWriter),
+}
+
+impl Writer {
+    fn write_sample(&mut self, sample: i32, bits: u16) {
+        match *self {
+            Writer::Plain(ref mut writer) if bits == 16 => writer.write_sample(sample as i16).unwrap(),
+            Writer::Plain(ref mut writer) => writer.write_sample(sample).unwrap(),
+            Writer::Extensible(ref mut writer) => writer.write_sample(sample).unwrap(),
+        }
+    }
+
+    fn finalize(self) {
+        match self {
+            Writer::Plain(writer) => writer.finalize().unwrap(),
+            Writer::Extensible(writer) => writer.finalize().unwrap(),
+        }
+    }
+}
+
+// Samples are only rounded to integers here, at the end of the path.
+fn write<S: Sample>(program: Program, filename: String, length: f32, settings: &RenderSettings, bits: u16)
+        -> Result<(), String> {
     let spec = hound::WavSpec {
-        channels: 1,
-        sample_rate: 44100,
-        bits_per_sample: 16
+        channels: settings.channels,
+        sample_rate: settings.sample_rate,
+        bits_per_sample: bits
     };
-    let rx = render_samples(compiler, spec.sample_rate).unwrap();
+    let queue = render_samples::<S>(program, settings, None);
+    let channels = queue.channels;
 
-    let mut writer = hound::WavWriter::create(filename, spec).unwrap();
-    let mut buffer = rx.recv().unwrap();
+    let mut writer = match settings.channel_layout {
+        Some(layout) => Writer::Extensible(try!(ExtensibleWriter::create(&filename, spec.channels, spec.sample_rate,
+                                                                         bits, layout.mask())
+                                                .map_err(|e| format!("couldn't create {}: {}", filename, e)))),
+        None => Writer::Plain(try!(hound::WavWriter::create(&filename, spec)
+                                   .map_err(|e| format!("couldn't create {}: {}", filename, e)))),
+    };
+    let mut buffer = match queue.recv() {
+        Some(buffer) => buffer,
+        None => return Err(queue.stop_reason()),
+    };
     let mut buf_ptr = 0;
+    let amplitude = ((1i64 << (bits - 1)) - 1) as f64;
     for _ in 0..(length*spec.sample_rate as f32) as usize {
-        let sample = buffer[buf_ptr].max(-1.0).min(1.0);
-        let amplitude = ::std::i16::MAX as f32;
-        writer.write_sample((sample * amplitude) as i16).unwrap();
-        buf_ptr += 1;
+        for i in 0..spec.channels {
+            let sample = output_channel(&buffer[buf_ptr..buf_ptr + channels], i as usize).to_number().max(-1.0).min(1.0);
+            if bits == 16 {
+                writer.write_sample((sample * amplitude) as i32, bits);
+            } else {
+                writer.write_sample((sample * amplitude).round() as i32, bits);
+            }
+        }
+        buf_ptr += channels;
         if buf_ptr >= buffer.len() {
-            buffer = rx.recv().unwrap();
+            queue.recycle(buffer);
+            buffer = match queue.recv() {
+                Some(buffer) => buffer,
+                None => {
+                    // what was written so far is kept, with a valid header
+                    writer.finalize();
+                    return Err(queue.stop_reason());
+                }
+            };
             buf_ptr = 0;
         }
     }
-    writer.finalize().unwrap();
+    writer.finalize();
+    Ok(())
 }
@@ -0,0 +1,70 @@
+use super::super::tokens::Number;
+
+use std::f64::consts::PI;
+
+// The bits of a WAV channel mask for each speaker, in the order their channels are written.
+const FRONT_LEFT: u32 = 0x1;
+const FRONT_RIGHT: u32 = 0x2;
+const FRONT_CENTER: u32 = 0x4;
+const LOW_FREQUENCY: u32 = 0x8;
+const BACK_LEFT: u32 = 0x10;
+const BACK_RIGHT: u32 = 0x20;
+const SIDE_LEFT: u32 = 0x200;
+const SIDE_RIGHT: u32 = 0x400;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ChannelLayout {
+    Mono,
+    Stereo,
+    Quad,
+    Surround51,
+    Surround71,
+}
+
+impl ChannelLayout {
+    pub fn parse(s: &str) -> Option<ChannelLayout> {
+        Some(match s {
+            "mono" => ChannelLayout::Mono,
+            "stereo" => ChannelLayout::Stereo,
+            "quad" => ChannelLayout::Quad,
+            "5.1" => ChannelLayout::Surround51,
+            "7.1" => ChannelLayout::Surround71,
+            _ => return None,
+        })
+    }
+
+    /// The channel mask of a WAVE_FORMAT_EXTENSIBLE file in this layout.
+    pub fn mask(&self) -> u32 {
+        match *self {
+            ChannelLayout::Mono => FRONT_CENTER,
+            ChannelLayout::Stereo => FRONT_LEFT | FRONT_RIGHT,
+            ChannelLayout::Quad => FRONT_LEFT | FRONT_RIGHT | BACK_LEFT | BACK_RIGHT,
+            ChannelLayout::Surround51 =>
+                FRONT_LEFT | FRONT_RIGHT | FRONT_CENTER | LOW_FREQUENCY | BACK_LEFT | BACK_RIGHT,
+            ChannelLayout::Surround71 =>
+                FRONT_LEFT | FRONT_RIGHT | FRONT_CENTER | LOW_FREQUENCY | BACK_LEFT | BACK_RIGHT | SIDE_LEFT |
+                SIDE_RIGHT,
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.mask().count_ones() as usize
+    }
+
+    /// The azimuth of the speaker of each channel, anticlockwise from straight ahead as the
+    /// ambisonic builtins take them, or None for the LFE channel, which has no direction. The
+    /// angles are those recommended by ITU-R BS.775 and for 7.1 by Dolby.
+    pub fn azimuths(&self) -> Vec<Option<Number>> {
+        let degrees = |x: Number| Some(x * PI / 180.0);
+        let surround = [degrees(30.0), degrees(-30.0), degrees(0.0), None];
+        match *self {
+            ChannelLayout::Mono => vec![degrees(0.0)],
+            ChannelLayout::Stereo => vec![degrees(30.0), degrees(-30.0)],
+            ChannelLayout::Quad => vec![degrees(45.0), degrees(-45.0), degrees(135.0), degrees(-135.0)],
+            ChannelLayout::Surround51 => surround.iter().cloned().chain(vec![degrees(110.0), degrees(-110.0)])
+                .collect(),
+            ChannelLayout::Surround71 => surround.iter().cloned()
+                .chain(vec![degrees(150.0), degrees(-150.0), degrees(90.0), degrees(-90.0)]).collect(),
+        }
+    }
+}
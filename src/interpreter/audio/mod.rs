@@ -1,47 +1,364 @@
+use super::alloc;
+use super::dsp::ambisonics;
+use super::runtime::Program;
 use super::tokens::Number;
-use super::compiler::Compiler;
 
-use std::thread;
-use std::sync::mpsc::{sync_channel, Receiver};
-use std::slice;
+use std::f64::consts::PI;
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Add, Mul};
+use std::thread::{self, JoinHandle};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TryRecvError};
+use std::time::{Duration, Instant};
 
 mod stream;
 mod filewriter;
+mod filereader;
+pub mod resample;
+pub mod control;
+pub mod watchdog;
+pub mod binaural;
+pub mod layout;
+pub mod encode;
+pub mod icecast;
+pub mod network;
+mod wav;
 
-//TODO prefered buffer size, num threads, etc..
-fn render_samples(compiler: &Compiler, sample_rate: u32) -> Option<Receiver<Vec<f32>>> {
-    compiler.get_init_fn()(());
-    let main_fn: extern fn(Number) -> Number = unsafe { match compiler.get_fn("main") {
-        Some(f) => f,
-        None => return None,
-    }};
-
-    const POOL_SIZE: usize = 8;
-    const CHUNK_SIZE: usize = 256;
-    const BUF_SIZE: usize = CHUNK_SIZE*POOL_SIZE;
-    let (tx, rx) = sync_channel(8);
-
-    thread::spawn(move || {
-        for buf_id in 0.. {
-            let mut buffer = vec![0f32; BUF_SIZE];
-            {
-                // this is necessary because the compiler can't reason that the threads are done with
-                // the buffer after they are joined below.
-                let buffer = unsafe { slice::from_raw_parts_mut(buffer.as_mut_ptr(), buffer.len()) };
-                let mut threads = Vec::new();
-                for (chunk_id, chunk) in buffer.chunks_mut(CHUNK_SIZE).enumerate() {
-                    threads.push(thread::spawn(move || {
-                        for i in 0..CHUNK_SIZE {
-                            let time = (buf_id*BUF_SIZE + chunk_id*CHUNK_SIZE + i) as Number / sample_rate as Number;
-                            chunk[i] = main_fn(time) as f32;
-                            if !chunk[i].is_finite() && i > 0 {
-                                chunk[i] = chunk[i-1];
-                            }
-                        }
-                    }));
+use self::binaural::Binaural;
+use self::layout::ChannelLayout;
+use self::resample::Decimator;
+use self::control::Control;
+use self::watchdog::Watchdog;
+
+/// Settings shared by all ways of rendering a program.
+#[derive(Clone, Debug)]
+pub struct RenderSettings {
+    /// The sample rate of the rendered audio.
+    pub sample_rate: u32,
+    /// The program is evaluated at this multiple of the sample rate and then downsampled,
+    /// which reduces aliasing.
+    pub oversample: usize,
+    /// Number of channels written to files. A program with one channel is written to every channel,
+    /// and one with several to the channels with the same index, leaving any others silent.
+    pub channels: u16,
+    /// Seconds into the program to start from. What comes before is evaluated and thrown away,
+    /// so the audio is the same as that part of a render from the start.
+    pub start: f32,
+    /// Times the rendering of each buffer while streaming.
+    pub watchdog: Option<Arc<Watchdog>>,
+    /// The type samples are held in between the program and the output.
+    pub precision: Precision,
+    /// What the channels of the program carry.
+    pub output_format: OutputFormat,
+    /// The speakers the channels are for, which files record in their channel mask.
+    pub channel_layout: Option<ChannelLayout>,
+}
+
+impl RenderSettings {
+    pub fn new(sample_rate: u32) -> RenderSettings {
+        RenderSettings {
+            sample_rate: sample_rate,
+            oversample: 1,
+            channels: 1,
+            start: 0.0,
+            watchdog: None,
+            precision: Precision::Single,
+            output_format: OutputFormat::Channels,
+            channel_layout: None,
+        }
+    }
+}
+
+/// The precision of the samples a program renders into. The program itself always evaluates in
+/// double precision, but its output is rounded to single precision before oversampling is
+/// filtered out and the result written, unless double precision is asked for. That keeps the
+/// whole path in double precision, at the cost of buffers twice the size, and writes files with
+/// 24 bits per sample instead of 16.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Precision {
+    Single,
+    Double,
+}
+
+impl Precision {
+    pub fn parse(s: &str) -> Option<Precision> {
+        Some(match s {
+            "single" => Precision::Single,
+            "double" => Precision::Double,
+            _ => return None,
+        })
+    }
+}
+
+/// What the channels of a program carry. By default they are played or written as they are, but a
+/// program may instead give the W, Y, Z and X channels of a first order ambisonic field, which
+/// is decoded to the speakers of `RenderSettings::channel_layout`, or without one to a ring of
+/// `RenderSettings::channels` speakers, or for headphones when binaural.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+    Channels,
+    AmbisonicsFoa,
+    Binaural,
+}
+
+impl OutputFormat {
+    pub fn parse(s: &str) -> Option<OutputFormat> {
+        Some(match s {
+            "channels" => OutputFormat::Channels,
+            "ambisonics-foa" => OutputFormat::AmbisonicsFoa,
+            _ => return None,
+        })
+    }
+
+    /// Checks that a program of `channels` channels can be rendered in this format.
+    pub fn check(&self, channels: usize) -> Result<(), String> {
+        match *self {
+            OutputFormat::Channels => Ok(()),
+            _ if channels == ambisonics::CHANNELS => Ok(()),
+            _ => Err(format!("ambisonic output needs the {} channels of a first order ambisonic field, \
+                              as given by `foa`, but the program gives {}", ambisonics::CHANNELS, channels)),
+        }
+    }
+}
+
+/// The azimuths of a ring of speakers evenly spaced around the listener, in the order their
+/// channels are written: pairs from the front round to the back, left before right, after a
+/// centre speaker if there is an odd number. Four speakers are at the front left, front right,
+/// back left and back right.
+pub fn speaker_azimuths(speakers: usize) -> Vec<Number> {
+    let step = 2.0 * PI / speakers as Number;
+    let mut azimuths = Vec::with_capacity(speakers);
+    if speakers % 2 == 1 {
+        azimuths.push(0.0);
+    }
+    // with an even number the first pair is half a step either side of straight ahead
+    let offset = if speakers % 2 == 0 { 0.5 } else { 1.0 };
+    for k in 0..speakers / 2 {
+        let azimuth = (k as Number + offset) * step;
+        azimuths.push(azimuth);
+        azimuths.push(-azimuth);
+    }
+    azimuths
+}
+
+/// Turns the frames of an ambisonic program into those of the output.
+enum Decoder {
+    /// The azimuth of the speaker of each channel, None for an LFE channel, which is left silent.
+    Speakers(Vec<Option<Number>>),
+    Binaural(Binaural),
+}
+
+impl Decoder {
+    fn new(settings: &RenderSettings) -> Option<Decoder> {
+        match settings.output_format {
+            OutputFormat::Channels => None,
+            OutputFormat::AmbisonicsFoa => Some(Decoder::Speakers(match settings.channel_layout {
+                Some(layout) => layout.azimuths(),
+                None => speaker_azimuths(settings.channels as usize).into_iter().map(Some).collect(),
+            })),
+            OutputFormat::Binaural => Some(Decoder::Binaural(Binaural::new(settings.sample_rate))),
+        }
+    }
+
+    fn channels(&self) -> usize {
+        match *self {
+            Decoder::Speakers(ref azimuths) => azimuths.len(),
+            Decoder::Binaural(_) => 2,
+        }
+    }
+
+    /// Decodes interleaved frames of W, Y, Z and X, appending the result to `output`.
+    fn process<S: Sample>(&mut self, input: &[S], output: &mut Vec<S>) {
+        for frame in input.chunks(ambisonics::CHANNELS) {
+            let b = [frame[0].to_number(), frame[1].to_number(), frame[2].to_number(), frame[3].to_number()];
+            match *self {
+                Decoder::Speakers(ref azimuths) => {
+                    // the decoding is only exact for speakers evenly spaced around the listener
+                    let speakers = azimuths.iter().filter(|x| x.is_some()).count();
+                    for azimuth in azimuths {
+                        let x = azimuth.map(|azimuth| ambisonics::decode(&b, azimuth, speakers)).unwrap_or(0.0);
+                        output.push(S::from_number(x));
+                    }
+                }
+                Decoder::Binaural(ref mut binaural) => {
+                    let (left, right) = binaural.process(&b);
+                    output.push(S::from_number(left));
+                    output.push(S::from_number(right));
+                }
+            }
+        }
+    }
+}
+
+/// A type rendered samples are held in, f32 or f64 as chosen by `Precision`.
+pub trait Sample: Copy + Send + PartialEq + Add<Output=Self> + Mul<Output=Self> + 'static {
+    fn from_number(x: Number) -> Self;
+    fn to_number(self) -> Number;
+}
+
+impl Sample for f32 {
+    fn from_number(x: Number) -> f32 {
+        x as f32
+    }
+
+    fn to_number(self) -> Number {
+        self as Number
+    }
+}
+
+impl Sample for f64 {
+    fn from_number(x: Number) -> f64 {
+        x
+    }
+
+    fn to_number(self) -> Number {
+        self
+    }
+}
+
+/// The value of an output channel in a frame of a program's channels, as described for
+/// `RenderSettings::channels`.
+fn output_channel<S: Sample>(frame: &[S], channel: usize) -> S {
+    match frame.len() {
+        1 => frame[0],
+        _ => frame.get(channel).cloned().unwrap_or(S::from_number(0.0)),
+    }
+}
+
+/// Warns if audio callbacks used the heap since alloc::violations() was `before`, which the
+/// callbacks can't report themselves.
+fn warn_heap_use(before: usize) {
+    let count = alloc::violations() - before;
+    if count > 0 {
+        let _ = writeln!(io::stderr(), "warning: the audio callback used the heap {} times", count);
+    }
+}
+
+/// Buffers of samples rendered ahead on another thread, with the channels of the program
+/// interleaved. Every buffer is allocated before rendering starts, and handed back with recycle()
+/// once it has been read so that it can be filled again.
+struct RenderQueue<'c, S> {
+    rx: Receiver<Vec<S>>,
+    free: SyncSender<Vec<S>>,
+    channels: usize,
+    failure: Arc<Mutex<Option<String>>>, // set by the thread if the program failed
+    // dropped after the channels, so that the thread has stopped sending by the time it's joined
+    thread: RenderThread<'c>,
+}
+
+/// The thread a program is rendered on. The program borrows its compiler, so the thread is joined
+/// when this is dropped rather than left running after the compiler is gone.
+struct RenderThread<'c> {
+    handle: Option<JoinHandle<()>>,
+    program: PhantomData<Program<'c>>,
+}
+
+impl<'c> Drop for RenderThread<'c> {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl<'c, S: Sample> RenderQueue<'c, S> {
+    /// Waits for the next buffer. Returns None if rendering stopped.
+    fn recv(&self) -> Option<Vec<S>> {
+        self.rx.recv().ok()
+    }
+
+    /// The next buffer if one is ready, or Disconnected once rendering stopped and every buffer has
+    /// been received. Unlike recv(), this never blocks or allocates, so it can be called from an
+    /// audio callback.
+    fn try_recv(&self) -> Result<Vec<S>, TryRecvError> {
+        self.rx.try_recv()
+    }
+
+    /// Why rendering stopped, once recv() has returned None.
+    fn stop_reason(&self) -> String {
+        self.failure.lock().unwrap().clone().unwrap_or("rendering stopped unexpectedly".to_string())
+    }
+
+    fn recycle(&self, buffer: Vec<S>) {
+        // there is room for every buffer, so this only fails once rendering stopped
+        let _ = self.free.try_send(buffer);
+    }
+}
+
+//TODO prefered buffer size, etc..
+fn render_samples<'c, S: Sample>(program: Program<'c>, settings: &RenderSettings, control: Option<Control>)
+        -> RenderQueue<'c, S> {
+    const BUF_SIZE: usize = 2048;
+    const BUF_COUNT: usize = 8;
+    let (tx, rx) = sync_channel(BUF_COUNT);
+    let (free, free_rx) = sync_channel(BUF_COUNT + 2);
+    let channels = program.channels();
+    // an ambisonic program is decoded after downsampling, into buffers of the output channels
+    let mut decoder = Decoder::new(settings);
+    let output_channels = decoder.as_ref().map(|x| x.channels()).unwrap_or(channels);
+    for _ in 0..BUF_COUNT + 2 {
+        free.send(Vec::with_capacity(BUF_SIZE * output_channels)).unwrap();
+    }
+    let mut oversample = settings.oversample.max(1);
+    let sample_rate = settings.sample_rate;
+    let mut program = program;
+    // another program of the same compiler may have run since this one was set up
+    program.reset_caches();
+    program.set_sample_rate(sample_rate * oversample as u32);
+    program.fast_forward((settings.start as f64 * sample_rate as f64).round() as u64 * oversample as u64);
+    let watchdog = settings.watchdog.clone();
+    let budget = Duration::new(0, (BUF_SIZE as u64 * 1_000_000_000 / sample_rate as u64) as u32);
+
+    // Samples are rendered in order on a single thread, since intrinsics may keep state from one
+    // sample to the next. The program can be moved to it because the RenderThread joins it before
+    // the compiler's borrow ends.
+    let program: Program<'static> = unsafe { mem::transmute(program) };
+    let failure = Arc::new(Mutex::new(None));
+    let thread_failure = failure.clone();
+    let handle = thread::spawn(move || {
+        let mut program = program;
+        let mut control = control;
+        let mut decimator = Decimator::with_channels(oversample, channels);
+        let mut render_buf = vec![S::from_number(0.0); BUF_SIZE * oversample * channels];
+        let mut field = Vec::with_capacity(BUF_SIZE * channels);
+        loop {
+            let mut buffer: Vec<S> = match free_rx.recv() {
+                Ok(buffer) => buffer,
+                Err(_) => return,
+            };
+            let start = Instant::now();
+            match control {
+                Some(ref mut control) => control.fill(&mut program, &mut render_buf),
+                None => program.fill(&mut render_buf),
+            }
+            // rendering stops at a failure, which the reader of the queue reports
+            if let Some(failure) = program.failure() {
+                *thread_failure.lock().unwrap() = Some(failure.to_string());
+                return;
+            }
+            buffer.clear();
+            match decoder {
+                Some(ref mut decoder) => {
+                    field.clear();
+                    decimator.process(&render_buf, &mut field);
+                    decoder.process(&field, &mut buffer);
+                }
+                None => decimator.process(&render_buf, &mut buffer),
+            }
+            if let Some(ref watchdog) = watchdog {
+                let load = watchdog.record(start.elapsed(), budget);
+                if let Some(warning) = watchdog.warning(load) {
+                    let _ = writeln!(io::stderr(), "{}", warning);
                 }
-                for thread in threads {
-                    thread.join().unwrap();
+                // a late buffer is a dropout, so trade aliasing for keeping up
+                if load > 1.0 && watchdog.reduce_quality() && oversample > 1 {
+                    oversample /= 2;
+                    program.set_sample_rate(sample_rate * oversample as u32);
+                    decimator = Decimator::with_channels(oversample, channels);
+                    render_buf.truncate(BUF_SIZE * oversample * channels);
+                    watchdog.reduced(oversample);
                 }
             }
             match tx.send(buffer) {
@@ -51,8 +368,18 @@ fn render_samples(compiler: &Compiler, sample_rate: u32) -> Option<Receiver<Vec<
         }
     });
 
-    Some(rx)
+    RenderQueue {
+        rx: rx,
+        free: free,
+        channels: output_channels,
+        failure: failure,
+        thread: RenderThread {
+            handle: Some(handle),
+            program: PhantomData,
+        },
+    }
 }
 
-pub use self::stream::play_stream;
+pub use self::stream::{play_stream, play_stream_with_control, play_wav};
 pub use self::filewriter::write_wav;
+pub use self::filereader::read_wav;
@@ -0,0 +1,397 @@
+use super::super::alloc;
+use super::super::runtime::Program;
+use super::control;
+use super::{render_samples, output_channel, warn_heap_use, RenderSettings, Precision, Sample};
+
+use std::mem;
+use std::net::UdpSocket;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sound_stream::{CallbackFlags, CallbackResult, SoundStream, Settings, StreamParams};
+
+/// The rate audio is sent and played at, which is that of the sound device.
+pub const SAMPLE_RATE: u32 = 48000;
+
+const VERSION: u8 = 0x80;
+// the first of the payload types RTP leaves to be assigned by applications
+const PAYLOAD_TYPE: u8 = 96;
+const HEADER_SIZE: usize = 12 + 6;
+
+// Frames are sent in packets of at most this many bytes of samples, which fit in the MTU of an
+// ethernet network.
+const MAX_PAYLOAD: usize = 1200;
+
+/// A packet of frames, with the channels of each frame interleaved. It is sent with an RTP style
+/// header, the channels and the sample rate, followed by big endian 32 bit floats.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Packet {
+    pub sequence: u16,
+    pub timestamp: u32,
+    /// Identifies the sender, so that a receiver knows when a new stream starts.
+    pub source: u32,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub samples: Vec<f32>,
+}
+
+fn put_u16(bytes: &mut Vec<u8>, x: u16) {
+    bytes.push((x >> 8) as u8);
+    bytes.push(x as u8);
+}
+
+fn put_u32(bytes: &mut Vec<u8>, x: u32) {
+    put_u16(bytes, (x >> 16) as u16);
+    put_u16(bytes, x as u16);
+}
+
+fn get_u16(bytes: &[u8]) -> u16 {
+    (bytes[0] as u16) << 8 | bytes[1] as u16
+}
+
+fn get_u32(bytes: &[u8]) -> u32 {
+    (get_u16(bytes) as u32) << 16 | get_u16(&bytes[2..]) as u32
+}
+
+impl Packet {
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels as usize
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.samples.len() * 4);
+        bytes.push(VERSION);
+        bytes.push(PAYLOAD_TYPE);
+        put_u16(&mut bytes, self.sequence);
+        put_u32(&mut bytes, self.timestamp);
+        put_u32(&mut bytes, self.source);
+        put_u16(&mut bytes, self.channels);
+        put_u32(&mut bytes, self.sample_rate);
+        for &x in &self.samples {
+            put_u32(&mut bytes, unsafe { mem::transmute(x) });
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Packet, String> {
+        Packet::decode_into(bytes, Vec::new())
+    }
+
+    /// Decodes a packet whose samples are stored in `samples`, reusing its memory.
+    pub fn decode_into(bytes: &[u8], mut samples: Vec<f32>) -> Result<Packet, String> {
+        if bytes.len() < HEADER_SIZE || bytes[0] != VERSION || bytes[1] != PAYLOAD_TYPE {
+            return Err("not a synthizer audio packet".into());
+        }
+        let channels = get_u16(&bytes[12..]);
+        let payload = &bytes[HEADER_SIZE..];
+        if channels == 0 || payload.len() % (4 * channels as usize) != 0 {
+            return Err(format!("a packet of {} channels has {} bytes of samples", channels, payload.len()));
+        }
+        samples.clear();
+        samples.extend(payload.chunks(4).map(|x| unsafe { mem::transmute::<u32, f32>(get_u32(x)) }));
+        Ok(Packet {
+            sequence: get_u16(&bytes[2..]),
+            timestamp: get_u32(&bytes[4..]),
+            source: get_u32(&bytes[8..]),
+            channels: channels,
+            sample_rate: get_u32(&bytes[14..]),
+            samples: samples,
+        })
+    }
+}
+
+/// Sends the program to a receiver at `address`, as fast as it plays. It only returns if sending
+/// fails.
+pub fn send_udp(program: Program, settings: &RenderSettings, address: &str) -> Result<(), String> {
+    match settings.precision {
+        Precision::Single => send::<f32>(program, settings, address),
+        Precision::Double => send::<f64>(program, settings, address),
+    }
+}
+
+fn send<S: Sample>(program: Program, settings: &RenderSettings, address: &str) -> Result<(), String> {
+    let socket = try!(UdpSocket::bind("0.0.0.0:0").and_then(|x| x.connect(address).map(|_| x))
+                      .map_err(|e| format!("couldn't send to {}: {}", address, e)));
+    let channels = settings.channels as usize;
+    let frames_per_packet = (MAX_PAYLOAD / (4 * channels)).max(1);
+    let mut packet = Packet {
+        sequence: 0,
+        timestamp: 0,
+        source: process::id(),
+        channels: settings.channels,
+        sample_rate: settings.sample_rate,
+        samples: Vec::with_capacity(frames_per_packet * channels),
+    };
+    let queue = render_samples::<S>(program, settings, None);
+    let start = Instant::now();
+    let mut sent = 0u64;
+    while let Some(buffer) = queue.recv() {
+        for frame in buffer.chunks(queue.channels) {
+            packet.samples.extend((0..channels).map(|i| output_channel(frame, i).to_number() as f32));
+            if packet.frames() < frames_per_packet {
+                continue;
+            }
+            try!(socket.send(&packet.encode()).map_err(|e| format!("couldn't send to {}: {}", address, e)));
+            packet.sequence = packet.sequence.wrapping_add(1);
+            packet.timestamp = packet.timestamp.wrapping_add(frames_per_packet as u32);
+            packet.samples.clear();
+            // the receiver only buffers so much, so keep to the time the audio takes to play
+            sent += frames_per_packet as u64;
+            let nanos = sent * 1_000_000_000 / settings.sample_rate as u64;
+            let due = Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32);
+            let elapsed = start.elapsed();
+            if due > elapsed {
+                thread::sleep(due - elapsed);
+            }
+        }
+        queue.recycle(buffer);
+    }
+    // rendering never ends by itself
+    Err(queue.stop_reason())
+}
+
+/// Packets waiting to be played, in order of their timestamps. Playback waits until `latency`
+/// frames have arrived, and again whenever they run out, so that packets which arrive late or out
+/// of order can still be played in time. A packet which never arrives is played as silence.
+///
+/// Nothing is allocated or freed once it has been created, so it can be used from an audio
+/// callback: the buffers of packets which have been played are kept to be handed back with
+/// take_spent().
+pub struct JitterBuffer {
+    channels: usize,
+    latency: usize,
+    // by the timestamp of their first frame, counting on past where u32 wraps; it never grows past
+    // its capacity
+    packets: Vec<(i64, Vec<f32>)>,
+    spent: Vec<Vec<f32>>,
+    // the timestamp of the next frame to play, once the first packet has arrived
+    next: Option<i64>,
+    buffering: bool,
+    /// Packets which arrived after they should have been played.
+    pub late: usize,
+    /// Packets dropped since `capacity` packets were already waiting.
+    pub dropped: usize,
+    /// Frames played as silence since their packet had not arrived.
+    pub lost: usize,
+    /// Times playback ran out of packets and waited for more.
+    pub underruns: usize,
+}
+
+impl JitterBuffer {
+    /// Creates a jitter buffer which holds up to `capacity` packets.
+    pub fn new(channels: usize, latency: usize, capacity: usize) -> JitterBuffer {
+        JitterBuffer {
+            channels: channels,
+            latency: latency,
+            packets: Vec::with_capacity(capacity),
+            spent: Vec::with_capacity(2 * capacity),
+            next: None,
+            buffering: true,
+            late: 0,
+            dropped: 0,
+            lost: 0,
+            underruns: 0,
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Forgets every packet and count, to play a new stream of `channels` channels.
+    pub fn restart(&mut self, channels: usize) {
+        while let Some((_, samples)) = self.packets.pop() {
+            self.spend(samples);
+        }
+        self.channels = channels;
+        self.next = None;
+        self.buffering = true;
+        self.late = 0;
+        self.dropped = 0;
+        self.lost = 0;
+        self.underruns = 0;
+    }
+
+    pub fn push(&mut self, packet: Packet) {
+        let next = *self.next.get_or_insert(packet.timestamp as i64);
+        // the difference from the next frame is small, even when the timestamp has wrapped
+        let start = next + packet.timestamp.wrapping_sub(next as u32) as i32 as i64;
+        if start + packet.frames() as i64 <= next {
+            self.late += 1;
+            return self.spend(packet.samples);
+        }
+        let after = self.packets.iter().position(|&(x, _)| x >= start);
+        match after {
+            Some(i) if self.packets[i].0 == start => self.spend(packet.samples),
+            _ if self.packets.len() == self.packets.capacity() => {
+                self.dropped += 1;
+                self.spend(packet.samples);
+            }
+            Some(i) => self.packets.insert(i, (start, packet.samples)),
+            None => self.packets.push((start, packet.samples)),
+        }
+    }
+
+    /// A buffer of a packet which has been played or dropped, which can be reused for another.
+    pub fn take_spent(&mut self) -> Option<Vec<f32>> {
+        self.spent.pop()
+    }
+
+    fn spend(&mut self, samples: Vec<f32>) {
+        // buffers are only freed here if take_spent() isn't keeping up
+        if self.spent.len() < self.spent.capacity() {
+            self.spent.push(samples);
+        }
+    }
+
+    /// Frames from the next to be played to the end of the last packet.
+    pub fn buffered(&self) -> usize {
+        match (self.next, self.packets.last()) {
+            (Some(next), Some(&(start, ref samples))) =>
+                (start + (samples.len() / self.channels) as i64 - next).max(0) as usize,
+            _ => 0,
+        }
+    }
+
+    /// The next frame to play, or None for silence.
+    pub fn next_frame(&mut self) -> Option<&[f32]> {
+        if self.buffering {
+            if self.buffered() < self.latency.max(1) {
+                return None;
+            }
+            self.buffering = false;
+        }
+        let next = self.next.unwrap();
+        // packets are removed once they have been played
+        while !self.packets.is_empty() &&
+              self.packets[0].0 + (self.packets[0].1.len() / self.channels) as i64 <= next {
+            let (_, samples) = self.packets.remove(0);
+            self.spend(samples);
+        }
+        match self.packets.first() {
+            None => {
+                self.buffering = true;
+                self.underruns += 1;
+                None
+            }
+            Some(&(start, ref samples)) if start <= next => {
+                self.next = Some(next + 1);
+                let i = (next - start) as usize * self.channels;
+                Some(&samples[i..i + self.channels])
+            }
+            Some(_) => {
+                self.next = Some(next + 1);
+                self.lost += 1;
+                None
+            }
+        }
+    }
+}
+
+// The counts of the jitter buffer, published by the audio callback for the receiver to report.
+struct Counts {
+    late: AtomicUsize,
+    dropped: AtomicUsize,
+    lost: AtomicUsize,
+    underruns: AtomicUsize,
+}
+
+impl Counts {
+    fn get(&self) -> (usize, usize, usize, usize) {
+        (self.late.load(Ordering::Relaxed), self.dropped.load(Ordering::Relaxed),
+         self.lost.load(Ordering::Relaxed), self.underruns.load(Ordering::Relaxed))
+    }
+}
+
+// Packets from senders of up to this many channels fit in the jitter buffer with room to spare.
+const MAX_CHANNELS: usize = 8;
+
+/// Plays what is sent to `address` until the process is killed, buffering `latency` seconds of
+/// it against packets arriving unevenly.
+pub fn receive_udp(address: &str, latency: f64) -> Result<(), String> {
+    let socket = try!(UdpSocket::bind(address).map_err(|e| format!("couldn't listen on {}: {}", address, e)));
+    let latency = (latency * SAMPLE_RATE as f64) as usize;
+    // twice the latency, so that packets can arrive early too
+    let capacity = 2 * latency / (MAX_PAYLOAD / (4 * MAX_CHANNELS)) + 16;
+    // Packets are handed to the audio callback, which owns the jitter buffer, and their buffers
+    // are handed back to be filled again. There is room in the second ring for every buffer there
+    // can be: those arriving, waiting to be played and spent, and the one being received into.
+    let (mut arriving, mut arrived) = control::ring::<Packet>(capacity);
+    let (mut spent, mut recycled) = control::ring::<Vec<f32>>(4 * capacity + 1);
+    thread::spawn(move || {
+        let mut bytes = vec![0; 65536];
+        let mut warned = false;
+        while let Ok((len, _)) = socket.recv_from(&mut bytes) {
+            let samples = recycled.pop().unwrap_or_else(Vec::new);
+            let packet = match Packet::decode_into(&bytes[..len], samples) {
+                Ok(packet) => packet,
+                Err(_) => continue,
+            };
+            if packet.sample_rate != SAMPLE_RATE {
+                if !warned {
+                    println!("ignoring audio at {} Hz, since it is played at {} Hz", packet.sample_rate, SAMPLE_RATE);
+                    warned = true;
+                }
+                continue;
+            }
+            // if the callback has fallen this far behind, the packet would be dropped anyway
+            let _ = arriving.push(packet);
+        }
+    });
+
+    let counts = Arc::new(Counts {
+        late: AtomicUsize::new(0),
+        dropped: AtomicUsize::new(0),
+        lost: AtomicUsize::new(0),
+        underruns: AtomicUsize::new(0),
+    });
+    let published = counts.clone();
+    let mut jitter = JitterBuffer::new(1, latency, capacity);
+    let mut source = None;
+    let callback = Box::new(move |output: &mut[f32], settings: Settings, _: f64, _: CallbackFlags| {
+        alloc::check_no_alloc(|| {
+            while let Some(packet) = arrived.pop() {
+                // a sender which starts again starts a new stream
+                if source != Some(packet.source) || jitter.channels() != packet.channels as usize {
+                    jitter.restart(packet.channels as usize);
+                    source = Some(packet.source);
+                }
+                jitter.push(packet);
+            }
+            for frame in output.chunks_mut(settings.channels as usize) {
+                let played = jitter.next_frame();
+                for (i, channel) in frame.iter_mut().enumerate() {
+                    *channel = played.map(|x| output_channel(x, i)).unwrap_or(0.0);
+                }
+            }
+            while let Some(samples) = jitter.take_spent() {
+                let _ = spent.push(samples);
+            }
+            published.late.store(jitter.late, Ordering::Relaxed);
+            published.dropped.store(jitter.dropped, Ordering::Relaxed);
+            published.lost.store(jitter.lost, Ordering::Relaxed);
+            published.underruns.store(jitter.underruns, Ordering::Relaxed);
+        });
+        CallbackResult::Continue
+    });
+    let params = StreamParams::new().suggest_latency(0.05);
+    let stream = try!(SoundStream::new().output(params).run_callback(callback)
+                      .map_err(|e| format!("couldn't open the audio device: {:?}", e)));
+    println!("listening on {}", address);
+    let violations = alloc::violations();
+    let mut reported = (0, 0, 0, 0);
+    while let Ok(true) = stream.is_active() {
+        thread::sleep(Duration::from_secs(10));
+        let current = counts.get();
+        if current != reported {
+            println!("{} late packets, {} dropped packets, {} lost frames, {} underruns",
+                     current.0, current.1, current.2, current.3);
+            reported = current;
+        }
+    }
+    warn_heap_use(violations);
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use super::Sample;
+
+use std::f64::consts::PI;
+
+// Number of zero crossings of the sinc kernel on each side of its center.
+const ZERO_CROSSINGS: usize = 16;
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-12 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Blackman window over [-1, 1].
+fn blackman(x: f64) -> f64 {
+    if x.abs() > 1.0 {
+        0.0
+    } else {
+        let t = (x + 1.0) * 0.5;
+        0.42 - 0.5 * (2.0 * PI * t).cos() + 0.08 * (4.0 * PI * t).cos()
+    }
+}
+
+/// Windowed sinc lowpass kernel, with the cutoff given as a fraction of the nyquist frequency.
+pub fn kernel(cutoff: f64, half_width: f64, x: f64) -> f64 {
+    cutoff * sinc(cutoff * x) * blackman(x / half_width)
+}
+
+/// Converts a whole signal from one sample rate to another using windowed sinc interpolation.
+pub fn resample(input: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || input.is_empty() {
+        return input.to_vec();
+    }
+    let step = from as f64 / to as f64;
+    let cutoff = (to as f64 / from as f64).min(1.0);
+    let half_width = ZERO_CROSSINGS as f64 / cutoff;
+    let out_len = (input.len() as f64 / step).ceil() as usize;
+
+    let mut output = Vec::with_capacity(out_len);
+    for n in 0..out_len {
+        let t = n as f64 * step;
+        let start = (t - half_width).ceil().max(0.0) as usize;
+        let end = ((t + half_width).floor() as usize).min(input.len() - 1);
+        let mut sum = 0.0;
+        for i in start..end + 1 {
+            sum += input[i] as f64 * kernel(cutoff, half_width, t - i as f64);
+        }
+        output.push(sum as f32);
+    }
+    output
+}
+
+/// Streaming lowpass filter and downsampler by an integer factor, used to render at a higher
+/// internal rate. Signals of several channels are processed with their channels interleaved.
+/// The samples are f32 or f64, and filtered in that precision.
+pub struct Decimator<S> {
+    factor: usize,
+    channels: usize,
+    kernel: Vec<S>,
+    history: Vec<S>,
+    window: Vec<S>, // the history followed by the input being filtered, reused between calls
+}
+
+impl<S: Sample> Decimator<S> {
+    pub fn new(factor: usize) -> Decimator<S> {
+        Decimator::with_channels(factor, 1)
+    }
+
+    pub fn with_channels(factor: usize, channels: usize) -> Decimator<S> {
+        assert!(factor > 0 && channels > 0);
+        let half_width = (ZERO_CROSSINGS * factor) as f64;
+        let taps = 2 * ZERO_CROSSINGS * factor + 1;
+        let cutoff = 1.0 / factor as f64;
+        let kernel = (0..taps)
+            .map(|i| S::from_number(kernel(cutoff, half_width, i as f64 - half_width)))
+            .collect();
+        Decimator {
+            factor: factor,
+            channels: channels,
+            kernel: kernel,
+            history: vec![S::from_number(0.0); (taps - 1) * channels],
+            window: Vec::new(),
+        }
+    }
+
+    pub fn factor(&self) -> usize {
+        self.factor
+    }
+
+    /// Filters `input`, whose length in frames must be a multiple of the factor, and appends the
+    /// downsampled result to `output`. Nothing is allocated once an input of the same length has
+    /// been processed and `output` has room for the result.
+    pub fn process(&mut self, input: &[S], output: &mut Vec<S>) {
+        let channels = self.channels;
+        assert!(input.len() % (self.factor * channels) == 0);
+        if self.factor == 1 {
+            output.extend(input.iter().cloned());
+            return;
+        }
+        let buf = &mut self.window;
+        buf.clear();
+        buf.extend(self.history.iter().cloned());
+        buf.extend(input.iter().cloned());
+        for j in 0..input.len() / (self.factor * channels) {
+            let window = &buf[j * self.factor * channels..];
+            for c in 0..channels {
+                let sum = self.kernel.iter().enumerate()
+                              .fold(S::from_number(0.0), |acc, (i, &k)| acc + k * window[i * channels + c]);
+                output.push(sum);
+            }
+        }
+        let keep = buf.len() - self.history.len();
+        self.history.copy_from_slice(&buf[keep..]);
+    }
+}
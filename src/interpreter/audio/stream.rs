@@ -1,35 +1,143 @@
-use super::super::compiler::Compiler;
-use super::render_samples;
+use super::super::runtime::Program;
+use super::super::alloc;
+use super::{render_samples, output_channel, warn_heap_use, RenderQueue, RenderSettings, Precision, Sample};
+use super::control::Control;
+use super::resample::resample;
+
+use hound;
+use std::mem;
+use std::sync::mpsc::TryRecvError;
+use std::path::Path;
 
 use sound_stream::{CallbackFlags, CallbackResult, SoundStream, Settings, StreamParams};
 
-pub fn play_stream(compiler: &Compiler) {
-    let rx = render_samples(compiler, 48000).unwrap();
+/// Plays the program until the process is killed, or until it fails.
+pub fn play_stream(program: Program, settings: &RenderSettings) -> Result<(), String> {
+    play(program, settings, None)
+}
+
+/// Plays the program while it is changed through the Remote paired with `control`.
+pub fn play_stream_with_control(program: Program, settings: &RenderSettings, control: Control) -> Result<(), String> {
+    play(program, settings, Some(control))
+}
+
+fn play(program: Program, settings: &RenderSettings, control: Option<Control>) -> Result<(), String> {
+    match settings.precision {
+        Precision::Single => play_samples::<f32>(program, settings, control),
+        Precision::Double => play_samples::<f64>(program, settings, control),
+    }
+}
+
+// The device takes f32, so samples are converted as they are played.
+fn play_samples<S: Sample>(program: Program, settings: &RenderSettings, control: Option<Control>) -> Result<(), String> {
+    let queue = render_samples::<S>(program, settings, control);
+    // The callback must be 'static, but it's dropped with the stream before this returns, and
+    // the render thread with it.
+    let queue: RenderQueue<'static, S> = unsafe { mem::transmute(queue) };
+    let channels = queue.channels;
     let mut buf_ptr = 0usize;
-    let mut buffer = rx.recv().unwrap();
+    let mut buffer = match queue.recv() {
+        Some(buffer) => buffer,
+        None => return Err(queue.stop_reason()),
+    };
+    let failure = queue.failure.clone();
+    let violations = alloc::violations();
+    let mut stopped = false;
+    // The callback runs on the audio thread, where allocating or waiting for the renderer could
+    // cause a dropout. If the next buffer is not ready, silence is played until it is.
+    let watchdog = settings.watchdog.clone();
     let callback = Box::new(move |output: &mut[f32], settings: Settings, _: f64, _: CallbackFlags| {
-        let mut max = 0f32;
-        for frame in output.chunks_mut(settings.channels as usize) {
-            let amp = buffer[buf_ptr];
-            if amp > max {
-                max = amp;
+        alloc::check_no_alloc(|| {
+            let mut underrun = false;
+            for frame in output.chunks_mut(settings.channels as usize) {
+                if buf_ptr < buffer.len() {
+                    let source = &buffer[buf_ptr..buf_ptr + channels];
+                    for (i, channel) in frame.iter_mut().enumerate() {
+                        *channel = output_channel(source, i).to_number() as f32;
+                    }
+                } else {
+                    for channel in frame {
+                        *channel = 0.0;
+                    }
+                    underrun = true;
+                }
+                buf_ptr += channels;
+                if buf_ptr >= buffer.len() {
+                    match queue.try_recv() {
+                        Ok(next) => {
+                            queue.recycle(mem::replace(&mut buffer, next));
+                            buf_ptr = 0;
+                        }
+                        Err(TryRecvError::Disconnected) => stopped = true,
+                        Err(TryRecvError::Empty) => { },
+                    }
+                }
             }
-            for channel in frame {
-                *channel = amp;
+            if underrun {
+                if let Some(ref watchdog) = watchdog {
+                    watchdog.underrun();
+                }
             }
-            buf_ptr += 1;
-            if buf_ptr >= buffer.len() {
-                buf_ptr = 0;
-                buffer = rx.recv().unwrap();
+        });
+        if stopped { CallbackResult::Complete } else { CallbackResult::Continue }
+    });
+
+    // Construct the default, non-blocking output stream and run our callback. A layout asks the
+    // device for a channel for each of its speakers.
+    let mut params = StreamParams::new().suggest_latency(0.05);
+    if let Some(layout) = settings.channel_layout {
+        params = params.channels(layout.channels() as i32);
+    }
+    let stream = SoundStream::new().sample_hz(settings.sample_rate as f64)
+                                   .output(params).run_callback(callback).unwrap();
+
+    while let Ok(true) = stream.is_active() {}
+    if let Some(ref watchdog) = settings.watchdog {
+        println!("{}", watchdog.report());
+    }
+    warn_heap_use(violations);
+    let failure = failure.lock().unwrap().clone();
+    match failure {
+        Some(failure) => Err(failure),
+        None => Ok(()),
+    }
+}
+
+const WAV_SAMPLE_RATE: u32 = 48000;
+
+/// Plays a WAV file once, returning when it has finished. Its channels are played like those of a
+/// program, so a mono file is heard on every channel of the device.
+pub fn play_wav(filename: &Path) -> Result<(), String> {
+    let mut reader = try!(hound::WavReader::open(filename)
+                          .map_err(|e| format!("couldn't open {}: {}", filename.display(), e)));
+    let spec = reader.spec();
+    let channels = spec.channels.max(1) as usize;
+    let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32;
+    let mut samples = Vec::new();
+    for sample in reader.samples::<i32>() {
+        let sample = try!(sample.map_err(|e| format!("couldn't read {}: {}", filename.display(), e)));
+        samples.push(sample as f32 * scale);
+    }
+    // the device is opened at a fixed rate, so each channel is resampled to it
+    let tracks: Vec<Vec<f32>> = (0..channels).map(|c| {
+        let track: Vec<f32> = samples.iter().enumerate().filter(|&(i, _)| i % channels == c).map(|(_, &x)| x).collect();
+        resample(&track, spec.sample_rate, WAV_SAMPLE_RATE)
+    }).collect();
+    let length = tracks[0].len();
+    let mut frame_ptr = 0;
+    let callback = Box::new(move |output: &mut[f32], settings: Settings, _: f64, _: CallbackFlags| {
+        for frame in output.chunks_mut(settings.channels as usize) {
+            for (i, channel) in frame.iter_mut().enumerate() {
+                *channel = if frame_ptr < length { tracks[i.min(channels - 1)][frame_ptr] } else { 0.0 };
             }
+            frame_ptr += 1;
         }
-        //if max < 0.0001 { CallbackResult::Complete } else { CallbackResult::Continue }
-        CallbackResult::Continue
+        if frame_ptr >= length { CallbackResult::Complete } else { CallbackResult::Continue }
     });
 
-    // Construct the default, non-blocking output stream and run our callback.
     let params = StreamParams::new().suggest_latency(0.05);
-    let stream = SoundStream::new().output(params).run_callback(callback).unwrap();
-
+    let stream = try!(SoundStream::new().sample_hz(WAV_SAMPLE_RATE as f64).output(params).run_callback(callback)
+                      .map_err(|e| format!("couldn't open the audio device: {:?}", e)));
     while let Ok(true) = stream.is_active() {}
+    Ok(())
 }
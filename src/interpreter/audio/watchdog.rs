@@ -0,0 +1,101 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// The share of the time a buffer takes to play which rendering it may take before a warning.
+pub const WARN_LOAD: f64 = 0.8;
+
+// Buffers rendered between two warnings, so that a patch which is always close to the deadline
+// does not flood the terminal.
+const WARN_INTERVAL: usize = 50;
+
+fn nanos(duration: Duration) -> usize {
+    duration.as_secs() as usize * 1_000_000_000 + duration.subsec_nanos() as usize
+}
+
+/// Statistics shared between the renderer, the audio callback and whoever reports them.
+#[derive(Debug)]
+pub struct Watchdog {
+    reduce_quality: bool,
+    buffers: AtomicUsize,
+    busy_ns: AtomicUsize,
+    budget_ns: AtomicUsize,
+    peak_load: AtomicUsize, // in thousandths
+    last_warning: AtomicUsize, // one more than the buffer warned about, or 0
+    underruns: AtomicUsize,
+    oversample: AtomicUsize, // the factor quality was last reduced to, or 0
+}
+
+impl Watchdog {
+    pub fn new(reduce_quality: bool) -> Watchdog {
+        Watchdog {
+            reduce_quality: reduce_quality,
+            buffers: AtomicUsize::new(0),
+            busy_ns: AtomicUsize::new(0),
+            budget_ns: AtomicUsize::new(0),
+            peak_load: AtomicUsize::new(0),
+            last_warning: AtomicUsize::new(0),
+            underruns: AtomicUsize::new(0),
+            oversample: AtomicUsize::new(0),
+        }
+    }
+
+    /// Whether the renderer may lower the oversampling factor when it misses the deadline.
+    pub fn reduce_quality(&self) -> bool {
+        self.reduce_quality
+    }
+
+    /// Records that a buffer took `busy` to render and takes `budget` to play. Returns the load,
+    /// which is over 1 when the buffer was late.
+    pub fn record(&self, busy: Duration, budget: Duration) -> f64 {
+        let (busy, budget) = (nanos(busy), nanos(budget).max(1));
+        self.buffers.fetch_add(1, Ordering::Relaxed);
+        self.busy_ns.fetch_add(busy, Ordering::Relaxed);
+        self.budget_ns.fetch_add(budget, Ordering::Relaxed);
+        let load = busy as f64 / budget as f64;
+        let thousandths = (load * 1000.0) as usize;
+        if thousandths > self.peak_load.load(Ordering::Relaxed) {
+            self.peak_load.store(thousandths, Ordering::Relaxed);
+        }
+        load
+    }
+
+    /// A warning to print about the load of the last buffer recorded, if it is close to the
+    /// deadline and there was not one just before.
+    pub fn warning(&self, load: f64) -> Option<String> {
+        let buffer = self.buffers.load(Ordering::Relaxed);
+        let last = self.last_warning.load(Ordering::Relaxed);
+        if load < WARN_LOAD || (last > 0 && buffer < last + WARN_INTERVAL) {
+            return None;
+        }
+        self.last_warning.store(buffer, Ordering::Relaxed);
+        Some(if load > 1.0 {
+            format!("warning: rendering fell behind, taking {:.0}% of the time available", load * 100.0)
+        } else {
+            format!("warning: rendering is taking {:.0}% of the time available", load * 100.0)
+        })
+    }
+
+    /// Called from the audio callback when it had to play silence, which never allocates.
+    pub fn underrun(&self) {
+        self.underruns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that the oversampling factor was lowered to `factor`.
+    pub fn reduced(&self, factor: usize) {
+        self.oversample.store(factor, Ordering::Relaxed);
+    }
+
+    pub fn report(&self) -> String {
+        let buffers = self.buffers.load(Ordering::Relaxed);
+        let budget = self.budget_ns.load(Ordering::Relaxed).max(1);
+        let average = self.busy_ns.load(Ordering::Relaxed) as f64 / budget as f64;
+        let mut report = format!("{} buffers rendered, {:.1}% load on average, {:.1}% at peak, {} underruns",
+                                 buffers, average * 100.0, self.peak_load.load(Ordering::Relaxed) as f64 / 10.0,
+                                 self.underruns.load(Ordering::Relaxed));
+        match self.oversample.load(Ordering::Relaxed) {
+            0 => { },
+            factor => report.push_str(&format!(", oversampling reduced to {}x", factor)),
+        }
+        report
+    }
+}
@@ -0,0 +1,75 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+// KSDATAFORMAT_SUBTYPE_PCM, as it is laid out in the file
+const SUBFORMAT_PCM: [u8; 16] = [0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
+                                 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71];
+
+// The size of the fmt chunk, and the offsets of the sizes filled in once the data is written.
+const FMT_SIZE: u32 = 40;
+const RIFF_SIZE_OFFSET: u64 = 4;
+const DATA_SIZE_OFFSET: u64 = 12 + 8 + FMT_SIZE as u64 + 4;
+
+fn write_u16<W: Write>(w: &mut W, x: u16) -> io::Result<()> {
+    w.write_all(&[x as u8, (x >> 8) as u8])
+}
+
+fn write_u32<W: Write>(w: &mut W, x: u32) -> io::Result<()> {
+    w.write_all(&[x as u8, (x >> 8) as u8, (x >> 16) as u8, (x >> 24) as u8])
+}
+
+/// Writes integer PCM samples of 16 or 24 bits, interleaved.
+pub struct ExtensibleWriter {
+    file: BufWriter<File>,
+    bytes_per_sample: usize,
+    data_size: u32,
+}
+
+impl ExtensibleWriter {
+    pub fn create(filename: &str, channels: u16, sample_rate: u32, bits: u16, mask: u32)
+                  -> io::Result<ExtensibleWriter> {
+        let mut file = BufWriter::new(try!(File::create(filename)));
+        let block_align = channels * bits / 8;
+        try!(file.write_all(b"RIFF"));
+        try!(write_u32(&mut file, 0));
+        try!(file.write_all(b"WAVEfmt "));
+        try!(write_u32(&mut file, FMT_SIZE));
+        try!(write_u16(&mut file, WAVE_FORMAT_EXTENSIBLE));
+        try!(write_u16(&mut file, channels));
+        try!(write_u32(&mut file, sample_rate));
+        try!(write_u32(&mut file, sample_rate * block_align as u32));
+        try!(write_u16(&mut file, block_align));
+        try!(write_u16(&mut file, bits));
+        // the size of the extension, the bits of each sample which are used, and the mask
+        try!(write_u16(&mut file, 22));
+        try!(write_u16(&mut file, bits));
+        try!(write_u32(&mut file, mask));
+        try!(file.write_all(&SUBFORMAT_PCM));
+        try!(file.write_all(b"data"));
+        try!(write_u32(&mut file, 0));
+        Ok(ExtensibleWriter {
+            file: file,
+            bytes_per_sample: bits as usize / 8,
+            data_size: 0,
+        })
+    }
+
+    pub fn write_sample(&mut self, sample: i32) -> io::Result<()> {
+        let bytes = [sample as u8, (sample >> 8) as u8, (sample >> 16) as u8];
+        try!(self.file.write_all(&bytes[..self.bytes_per_sample]));
+        self.data_size += self.bytes_per_sample as u32;
+        Ok(())
+    }
+
+    /// Fills in the sizes of the chunks, which are only known once every sample is written.
+    pub fn finalize(mut self) -> io::Result<()> {
+        try!(self.file.seek(SeekFrom::Start(RIFF_SIZE_OFFSET)));
+        // the size of everything after the RIFF header
+        try!(write_u32(&mut self.file, DATA_SIZE_OFFSET as u32 - 4 + self.data_size));
+        try!(self.file.seek(SeekFrom::Start(DATA_SIZE_OFFSET)));
+        try!(write_u32(&mut self.file, self.data_size));
+        self.file.flush()
+    }
+}
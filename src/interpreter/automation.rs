@@ -0,0 +1,118 @@
+use super::common::read_file;
+use super::tokens::Number;
+
+use rustc_serialize::json;
+use std::cmp::Ordering;
+
+#[derive(Clone, Debug, PartialEq, RustcDecodable)]
+pub struct Point {
+    pub time: Number,
+    pub param: String,
+    pub value: Number,
+    pub ramp: Option<bool>,
+}
+
+// The points of one parameter, in order of time.
+#[derive(Clone, Debug)]
+struct Lane {
+    param: String,
+    index: Option<usize>, // among the params of the program, once bound
+    points: Vec<Point>,
+    cursor: usize, // the last point reached
+}
+
+impl Lane {
+    fn value_at(&mut self, time: Number) -> Option<Number> {
+        if self.points.is_empty() || time < self.points[0].time {
+            return None;
+        }
+        // time only goes backwards after a seek or reset
+        if time < self.points[self.cursor].time {
+            self.cursor = 0;
+        }
+        while self.cursor + 1 < self.points.len() && self.points[self.cursor + 1].time <= time {
+            self.cursor += 1;
+        }
+        let point = &self.points[self.cursor];
+        Some(match self.points.get(self.cursor + 1) {
+            Some(next) if next.ramp == Some(true) => {
+                let t = (time - point.time) / (next.time - point.time);
+                point.value + (next.value - point.value) * t
+            }
+            _ => point.value,
+        })
+    }
+}
+
+/// Parameter changes scheduled ahead of time, from a JSON list of points. A parameter holds the
+/// value of its last point, or ramps towards the next one if that is a ramp.
+#[derive(Clone, Debug)]
+pub struct Automation {
+    lanes: Vec<Lane>,
+}
+
+impl Automation {
+    pub fn new(points: Vec<Point>) -> Result<Automation, String> {
+        let mut lanes: Vec<Lane> = Vec::new();
+        for point in points {
+            if !point.time.is_finite() || point.time < 0.0 {
+                return Err(format!("the time of a point of `{}` must be a number of seconds, not negative",
+                                   point.param));
+            }
+            match lanes.iter().position(|x| x.param == point.param) {
+                Some(i) => lanes[i].points.push(point),
+                None => lanes.push(Lane {
+                    param: point.param.clone(),
+                    index: None,
+                    points: vec![point],
+                    cursor: 0,
+                }),
+            }
+        }
+        for lane in &mut lanes {
+            // points at the same time stay in the order they were given
+            lane.points.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal));
+        }
+        Ok(Automation {
+            lanes: lanes,
+        })
+    }
+
+    pub fn from_json(s: &str) -> Result<Automation, String> {
+        let points = try!(json::decode(s).map_err(|e| format!("invalid automation: {}", e)));
+        Automation::new(points)
+    }
+
+    pub fn load(filename: &str) -> Result<Automation, String> {
+        let source = try!(read_file(filename));
+        Automation::from_json(&source).map_err(|e| format!("{}: {}", filename, e))
+    }
+
+    /// The names of the automated parameters.
+    pub fn params(&self) -> Vec<&str> {
+        self.lanes.iter().map(|x| &x.param[..]).collect()
+    }
+
+    /// Matches each automated parameter with one of `params`, which are those of a program.
+    pub fn bind(&mut self, params: &[(String, Number)]) -> Result<(), String> {
+        for lane in &mut self.lanes {
+            match params.iter().position(|x| x.0 == lane.param) {
+                Some(i) => lane.index = Some(i),
+                None => return Err(format!("the entrypoint has no parameter `{}` to automate", lane.param)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls `f` with the index and value at the given time of every bound parameter which has
+    /// reached its first point.
+    pub fn apply<F>(&mut self, time: Number, mut f: F) where F: FnMut(usize, Number) {
+        for lane in &mut self.lanes {
+            if let Some(index) = lane.index {
+                if let Some(value) = lane.value_at(time) {
+                    f(index, value);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use super::audio::{write_wav, RenderSettings};
+use super::common::{Context, read_file};
+use super::compiler::Compiler;
+use super::config::Config;
+use super::runtime::Program;
+use super::tokens::Number;
+
+use rustc_serialize::json;
+use std::collections::BTreeMap;
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+#[derive(Clone, Debug, PartialEq, RustcDecodable)]
+pub struct Job {
+    pub input: String,
+    pub output: String,
+    pub defines: Option<BTreeMap<String, Number>>,
+    pub seed: Option<u64>,
+    /// In seconds, defaulting to the length in the config and then to 32.
+    pub length: Option<f32>,
+    pub sample_rate: Option<u32>,
+}
+
+impl Job {
+    // Jobs which can share a compilation.
+    fn compiles_like(&self, other: &Job) -> bool {
+        self.input == other.input && self.defines == other.defines && self.sample_rate == other.sample_rate
+    }
+}
+
+/// A JSON list of jobs, whose paths are relative to the directory of the manifest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Manifest {
+    pub jobs: Vec<Job>,
+}
+
+impl Manifest {
+    /// Parses a manifest, resolving its paths against `root`.
+    pub fn from_json(s: &str, root: &Path) -> Result<Manifest, String> {
+        let mut jobs: Vec<Job> = try!(json::decode(s).map_err(|e| format!("invalid manifest: {}", e)));
+        for job in &mut jobs {
+            job.input = root.join(&job.input).to_string_lossy().into_owned();
+            job.output = root.join(&job.output).to_string_lossy().into_owned();
+        }
+        Ok(Manifest {
+            jobs: jobs,
+        })
+    }
+
+    pub fn load(filename: &str) -> Result<Manifest, String> {
+        let source = try!(read_file(filename));
+        let root = Path::new(filename).parent().unwrap_or(Path::new(""));
+        Manifest::from_json(&source, root).map_err(|e| format!("{}: {}", filename, e))
+    }
+
+    /// The indices of the jobs, grouped by the compilation they share, in the order they first
+    /// appear.
+    pub fn groups(&self) -> Vec<Vec<usize>> {
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        for (i, job) in self.jobs.iter().enumerate() {
+            match groups.iter().position(|x| self.jobs[x[0]].compiles_like(job)) {
+                Some(g) => groups[g].push(i),
+                None => groups.push(vec![i]),
+            }
+        }
+        groups
+    }
+}
+
+fn load_config(input: &str) -> Result<Config, String> {
+    match Config::find(Path::new(input)) {
+        Some(path) => Config::load(&path),
+        None => Ok(Config::new(PathBuf::new())),
+    }
+}
+
+/// A program which is ready to be rendered to a file.
+pub struct Render<'c> {
+    pub index: usize,
+    /// Renders in the same group are programs of one compiler. They share its globals, so they
+    /// are rendered one after another rather than at once.
+    pub group: usize,
+    pub program: Program<'c>,
+    pub output: String,
+    /// In seconds.
+    pub length: f32,
+    pub settings: RenderSettings,
+}
+
+/// Renders every job of a manifest on up to `threads` threads. Gives the result of each job, in
+/// the order of the manifest.
+pub fn render_batch(manifest: &Manifest, threads: usize) -> Vec<Result<(), String>> {
+    let mut results: Vec<Result<(), String>> = manifest.jobs.iter().map(|_| Ok(())).collect();
+    let groups = manifest.groups();
+
+    // the programs are rendered before the contexts they were compiled in are dropped
+    let mut contexts = Vec::new();
+    for group in &groups {
+        let job = &manifest.jobs[group[0]];
+        let loaded = read_file(&job.input).and_then(|source| load_config(&job.input).map(|config| (source, config)));
+        match loaded {
+            Ok((source, config)) => contexts.push(Some((Context::new(job.input.clone(), source), config))),
+            Err(e) => {
+                for &i in group {
+                    results[i] = Err(e.clone());
+                }
+                contexts.push(None);
+            }
+        }
+    }
+    let mut compilers: Vec<_> = contexts.iter().map(|x| x.as_ref().map(|x| Compiler::new(&x.0))).collect();
+
+    let mut renders = Vec::new();
+    for (g, group) in groups.iter().enumerate() {
+        let (ctxt, config) = match contexts[g] {
+            Some((ref ctxt, ref config)) => (ctxt, config),
+            None => continue,
+        };
+        let compiler = compilers[g].as_mut().unwrap();
+        let job = &manifest.jobs[group[0]];
+        let mut settings = config.render_settings(job.sample_rate.unwrap_or(44100));
+        if let Some(sample_rate) = job.sample_rate {
+            settings.sample_rate = sample_rate;
+        }
+        let entrypoint = config.entrypoint.clone().unwrap_or("main".to_string());
+        {
+            let mut options = ctxt.options.borrow_mut();
+            options.include_paths = config.include_paths.clone();
+            options.sample_rate = Some(settings.sample_rate * settings.oversample.max(1) as u32);
+        }
+        if let Some(ref defines) = job.defines {
+            for (name, &value) in defines {
+                compiler.define_constant(name, value);
+            }
+        }
+        compiler.declare_entrypoint(&entrypoint);
+        if let Err(issues) = compiler.compile() {
+            for &i in group {
+                results[i] = Err(format!("Compile Error!\n{}", issues));
+            }
+            continue;
+        }
+        for &i in group {
+            let job = &manifest.jobs[i];
+            let mut program = Program::new(compiler, &entrypoint, settings.sample_rate).unwrap();
+            program.set_seed(job.seed.unwrap_or(0));
+            let mut settings = settings.clone();
+            if config.channels.is_none() {
+                settings.channels = program.channels() as u16;
+            }
+            renders.push(Render {
+                index: i,
+                group: g,
+                program: program,
+                output: job.output.clone(),
+                length: job.length.or(config.length).unwrap_or(32.0),
+                settings: settings,
+            });
+        }
+    }
+
+    for (index, result) in render_all(renders, threads) {
+        results[index] = result;
+    }
+    results
+}
+
+/// Writes every render to its file on up to `threads` threads, giving the index and result of
+/// each as it finishes. The renders of a group are written in order, on one thread.
+pub fn render_all(renders: Vec<Render>, threads: usize) -> Vec<(usize, Result<(), String>)> {
+    // each render runs on a thread of its own, so that one which fails doesn't take a worker
+    // down with it. Every thread is joined before this returns, so none outlives the compilers
+    // the programs borrow.
+    let renders: Vec<Render<'static>> = unsafe { mem::transmute(renders) };
+    // a worker takes a whole group at a time
+    let mut groups: Vec<Vec<Render>> = Vec::new();
+    for render in renders {
+        match groups.iter().position(|x| x[0].group == render.group) {
+            Some(i) => groups[i].push(render),
+            None => groups.push(vec![render]),
+        }
+    }
+    groups.reverse();
+    let queue = Arc::new(Mutex::new(groups));
+    let finished = Arc::new(Mutex::new(Vec::new()));
+    let workers: Vec<_> = (0..threads.max(1)).map(|_| {
+        let queue = queue.clone();
+        let finished = finished.clone();
+        thread::spawn(move || {
+            loop {
+                let group = match queue.lock().unwrap().pop() {
+                    Some(group) => group,
+                    None => return,
+                };
+                for render in group {
+                    let index = render.index;
+                    let output = render.output.clone();
+                    let result = match Path::new(&output).parent().map(|dir| fs::create_dir_all(dir)) {
+                        Some(Err(e)) => Err(format!("couldn't create the directory of {}: {}", output, e)),
+                        _ => thread::spawn(move || {
+                            write_wav(render.program, render.output, render.length, &render.settings)
+                        }).join().unwrap_or_else(|_| Err(format!("couldn't render {}", output))),
+                    };
+                    finished.lock().unwrap().push((index, result));
+                }
+            }
+        })
+    }).collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+    let results = finished.lock().unwrap().drain(..).collect();
+    results
+}
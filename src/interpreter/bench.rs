@@ -0,0 +1,68 @@
+use super::runtime::Program;
+use super::alloc;
+use super::audio::{Precision, Sample};
+
+use std::fmt;
+use std::time::Instant;
+
+pub struct BenchReport {
+    pub samples: usize,
+    pub sample_rate: u32,
+    pub seconds: f64,
+    pub allocations: usize, // always 0 without the count-allocations feature
+}
+
+impl BenchReport {
+    pub fn samples_per_sec(&self) -> f64 {
+        self.samples as f64 / self.seconds
+    }
+
+    /// How many times faster than real time the program was rendered.
+    pub fn realtime_factor(&self) -> f64 {
+        self.samples_per_sec() / self.sample_rate as f64
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "rendered {} samples in {:.3}s\n", self.samples, self.seconds));
+        try!(write!(f, "{:.0} samples/sec, {:.2}x realtime\n",
+                    self.samples_per_sec(), self.realtime_factor()));
+        if cfg!(feature = "count-allocations") {
+            write!(f, "{} allocations", self.allocations)
+        } else {
+            write!(f, "allocations not counted (build with `--features count-allocations`)")
+        }
+    }
+}
+
+/// Renders `length` seconds of the program as fast as possible, discarding the output.
+pub fn bench(program: &mut Program, length: f32, precision: Precision) -> BenchReport {
+    match precision {
+        Precision::Single => bench_samples::<f32>(program, length),
+        Precision::Double => bench_samples::<f64>(program, length),
+    }
+}
+
+fn bench_samples<S: Sample>(program: &mut Program, length: f32) -> BenchReport {
+    const BUF_SIZE: usize = 2048;
+    let total = (length * program.sample_rate() as f32) as usize;
+    let mut buffer = vec![S::from_number(0.0); BUF_SIZE];
+
+    let allocations = alloc::allocations();
+    let start = Instant::now();
+    let mut done = 0;
+    while done < total {
+        let len = BUF_SIZE.min(total - done);
+        program.fill(&mut buffer[..len]);
+        done += len;
+    }
+    let elapsed = start.elapsed();
+
+    BenchReport {
+        samples: total,
+        sample_rate: program.sample_rate(),
+        seconds: elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9,
+        allocations: alloc::allocations() - allocations,
+    }
+}
@@ -0,0 +1,123 @@
+use super::audio::resample;
+use super::dsp::trigger;
+use super::dsp::interpolate::{interpolate, Interpolation};
+use super::runtime::{self, CallSite};
+use super::tokens::{Number, SourcePos};
+
+/// The longest a buffer may be, in seconds.
+pub const MAX_SECONDS: Number = 600.0;
+
+/// Audio loaded from a file, mixed down to one channel.
+#[derive(Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct Audio {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+}
+
+/// A buffer as it is declared, with `buffer "loop" 2;` or `buffer "drums" "drums.wav";`.
+#[derive(Clone, Debug)]
+pub struct Buffer {
+    pub name: String,
+    pub seconds: Number,
+    pub pos: SourcePos,
+    pub audio: Option<Audio>,
+}
+
+/// The recorded samples of a buffer, kept in the state of a program.
+#[derive(Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct SampleBuffer {
+    pub name: String,
+    pub seconds: Number,
+    pub samples: Vec<Number>,
+    audio: Option<Audio>,
+    loaded: Vec<Number>, // the audio at the rate it was last allocated at
+    loaded_rate: u32,
+}
+
+impl SampleBuffer {
+    pub fn new(buffer: &Buffer) -> SampleBuffer {
+        SampleBuffer {
+            name: buffer.name.clone(),
+            seconds: buffer.seconds,
+            samples: Vec::new(),
+            audio: buffer.audio.clone(),
+            loaded: Vec::new(),
+            loaded_rate: 0,
+        }
+    }
+
+    /// Makes room for the buffer at the given sample rate, and empties it or fills it with the
+    /// audio it was loaded from.
+    pub fn allocate(&mut self, sample_rate: u32) {
+        if let Some(ref audio) = self.audio {
+            if self.loaded_rate != sample_rate {
+                self.loaded = resample::resample(&audio.samples, audio.sample_rate, sample_rate)
+                                  .iter().map(|&x| x as Number).collect();
+                self.loaded_rate = sample_rate;
+            }
+        }
+        let len = if self.audio.is_some() {
+            self.loaded.len().max(1)
+        } else {
+            ((self.seconds * sample_rate as Number).ceil() as usize).max(1)
+        };
+        self.samples.resize(len, 0.0);
+        self.clear();
+    }
+
+    pub fn clear(&mut self) {
+        for (i, x) in self.samples.iter_mut().enumerate() {
+            *x = self.loaded.get(i).cloned().unwrap_or(0.0);
+        }
+    }
+}
+
+/// Reads between the samples of a buffer with the given interpolation, wrapping round at either
+/// end.
+pub fn read(samples: &[Number], position: Number, mode: Interpolation) -> Number {
+    let len = samples.len() as Number;
+    let position = ((position % len) + len) % len;
+    let n = samples.len() as isize;
+    interpolate(mode, position, |i| samples[(((i % n) + n) % n) as usize])
+}
+
+/// The implementation of `record`, which gives back its signal unchanged so that it can be
+/// recorded where it is used. Records into buffers which were not declared go nowhere.
+pub extern fn record(site: CallSite, buffer: usize, signal: Number, trigger: Number) -> Number {
+    runtime::with_site_state(site, |state| {
+        // one more than the position the next sample goes to, or 0 before the first trigger
+        let next = state.memory(1)[0] as usize;
+        let pos = if trigger::fired(trigger) {
+            0
+        } else if next > 0 {
+            next - 1
+        } else {
+            return signal;
+        };
+        if let Some(samples) = state.buffer(buffer) {
+            if pos < samples.len() {
+                samples[pos] = signal;
+            }
+        }
+        state.memory(1)[0] = (pos + 2) as Number;
+        signal
+    })
+}
+
+/// The implementation of `playbuf`.
+pub extern fn playbuf(site: CallSite, buffer: usize, position: Number, rate: Number, interpolation: Number) -> Number {
+    runtime::with_site_state(site, |state| {
+        let sample_rate = state.sample_rate() as Number;
+        let offset = state.memory(1)[0]; // in samples
+        let out = match state.buffer(buffer) {
+            Some(samples) => {
+                let len = samples.len() as Number;
+                let mode = Interpolation::from_number(interpolation);
+                (read(samples, position * sample_rate + offset, mode), (offset + rate) % len)
+            }
+            None => (0.0, 0.0),
+        };
+        state.memory(1)[0] = out.1;
+        out.0
+    })
+}
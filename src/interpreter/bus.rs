@@ -0,0 +1,129 @@
+use super::ident::Identifier;
+use super::runtime::CallSite;
+use super::tokens::{Number, SourcePos};
+
+use std::cell::Cell;
+use std::ptr;
+
+/// A function of the mix of everything sent to it, processed after the entrypoint each sample.
+#[derive(Clone, Debug)]
+pub struct Bus {
+    pub name: String,
+    pub ident: Identifier,
+    pub pos: SourcePos,
+    /// The names of the buses this one sends to, as far as they are known while compiling.
+    pub sends: Vec<String>,
+}
+
+/// The order to process buses in, as indices, so that each comes after every bus which sends to
+/// it. Fails with the indices of the buses which send to each other in a cycle.
+pub fn order(buses: &[Bus]) -> Result<Vec<usize>, Vec<usize>> {
+    let sends_to = |from: usize, to: usize| buses[from].sends.contains(&buses[to].name);
+    let mut order = Vec::new();
+    let mut done = vec![false; buses.len()];
+    while order.len() < buses.len() {
+        let ready = (0..buses.len()).find(|&i| {
+            !done[i] && (0..buses.len()).all(|j| done[j] || !sends_to(j, i))
+        });
+        match ready {
+            Some(i) => {
+                done[i] = true;
+                order.push(i);
+            }
+            None => {
+                // what is left is the cycles and the buses they send to
+                let cycle = (0..buses.len()).filter(|&i| !done[i] && reaches(buses, i, i)).collect();
+                return Err(cycle);
+            }
+        }
+    }
+    Ok(order)
+}
+
+// Whether `from` sends to `to`, directly or through other buses.
+fn reaches(buses: &[Bus], from: usize, to: usize) -> bool {
+    let mut seen = vec![false; buses.len()];
+    let mut stack = vec![from];
+    while let Some(i) = stack.pop() {
+        for j in 0..buses.len() {
+            if buses[i].sends.contains(&buses[j].name) && !seen[j] {
+                if j == to {
+                    return true;
+                }
+                seen[j] = true;
+                stack.push(j);
+            }
+        }
+    }
+    false
+}
+
+/// What has been sent to each bus of a program since it was last processed, indexed in the order
+/// buses are processed.
+#[derive(Clone, Debug)]
+pub struct BusInputs {
+    values: Vec<Number>,
+    by_string: Vec<Option<usize>>, // from the index of a string literal
+}
+
+impl BusInputs {
+    pub fn new(names: &[String], strings: &[String]) -> BusInputs {
+        BusInputs {
+            values: vec![0.0; names.len()],
+            by_string: strings.iter().map(|s| names.iter().position(|x| x == s)).collect(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for x in &mut self.values {
+            *x = 0.0;
+        }
+    }
+}
+
+thread_local!(static CURRENT_BUS_INPUTS: Cell<*mut BusInputs> = Cell::new(ptr::null_mut()));
+
+/// Runs `f` with the sends of the program it evaluates on this thread going to `inputs`.
+pub fn with_bus_inputs<F, R>(inputs: &mut BusInputs, f: F) -> R where F: FnOnce() -> R {
+    let prev = CURRENT_BUS_INPUTS.with(|cur| {
+        let prev = cur.get();
+        cur.set(inputs);
+        prev
+    });
+    let res = f();
+    CURRENT_BUS_INPUTS.with(|cur| cur.set(prev));
+    res
+}
+
+fn with_current<F, R>(f: F) -> Option<R> where F: FnOnce(&mut BusInputs) -> R {
+    CURRENT_BUS_INPUTS.with(|cur| {
+        let inputs = cur.get();
+        if inputs.is_null() {
+            None
+        } else {
+            Some(f(unsafe { &mut *inputs }))
+        }
+    })
+}
+
+/// The implementation of `send`, which adds a signal to the input of the named bus. It gives 0, so
+/// that it can stand on its own in a block without changing the sum. Sends to a bus which does not
+/// exist, and sends outside of a program such as in tests, go nowhere.
+pub extern fn send(_: CallSite, bus: usize, signal: Number) -> Number {
+    with_current(|inputs| {
+        if let Some(&Some(i)) = inputs.by_string.get(bus) {
+            inputs.values[i] += signal;
+        }
+    });
+    0.0
+}
+
+/// Called by the generated code which processes the buses to take the input of each, leaving it
+/// empty for the next sample.
+pub extern fn bus_input(index: usize) -> Number {
+    with_current(|inputs| {
+        let value = inputs.values[index];
+        inputs.values[index] = 0.0;
+        value
+    }).unwrap_or(0.0)
+}
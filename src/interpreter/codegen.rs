@@ -4,17 +4,33 @@ use super::tokens::{Number, Boolean, NodeImpl, Node, Operator, SourcePos};
 use super::types::{Type, TypeTable};
 use super::scope::ScopedTable;
 use super::ident::Identifier;
-use super::functions::{self, FunctionTable, ExternalFunction, PointerFunction};
+use super::functions::{self, FunctionTable, ExternalFunction, PointerFunction, IntrinsicFunction};
+use super::runtime::{self, Intrinsic, TraceLabel, DebugLabel, Input};
+use super::source_map::{SourceMap, FunctionMap};
+use super::bus;
+use super::dsp::{oversample, spectral};
+use super::poly;
+use super::int;
+use super::memo;
+use super::hoist::{self, Hoisted};
+use super::math;
+use super::fastmath;
 
 use llvm;
 use llvm::{Compile, ExecutionEngine, CastFrom};
 use cbox::*;
 use std::cell::{RefCell, Ref};
+use std::collections::{HashMap, HashSet};
+use std::f64::{self, consts};
+use std::ffi::CStr;
 use vec_map::VecMap;
 use std::ops::Deref;
 use std::mem;
 use std::rc::Rc;
 use llvm_sys::core;
+use llvm_sys::LLVMTypeKind;
+use llvm_sys::prelude::{LLVMValueRef, LLVMModuleRef, LLVMBuilderRef};
+use llvm_sys::transforms::{ipo, scalar};
 
 #[derive(Clone, Debug)]
 struct FnArgument {
@@ -65,6 +81,34 @@ impl<'a> Deref for ValueWrapper<'a> {
 }
 
 pub const GLOBAL_INIT_FN_NAME: &'static str = "*globalinit*";
+/// Appended to the name of an entrypoint with inputs to name the function of `time` alone which
+/// calls it.
+pub const ENTRY_FN_SUFFIX: &'static str = "*entry*";
+/// The function which processes every bus once, in order, after the entrypoint has been evaluated.
+pub const BUSES_FN_NAME: &'static str = "*buses*";
+/// The function which empties the caches of memoized functions and hoisted expressions. Every
+/// program of a compiler shares them, so they're emptied before another program runs.
+pub const RESET_CACHES_FN_NAME: &'static str = "*resetcaches*";
+
+// The name of instructions built through llvm_sys rather than the builder.
+const NO_NAME: &'static [u8] = b"\0";
+
+/// The variables every program can read, with the globals which hold them. The runtime sets
+/// these before each evaluation.
+pub const BUILTIN_VARIABLES: &'static [(&'static str, &'static str)] = &[
+    ("sample_rate", "*sample_rate*"),
+    ("channel", "*channel*"),
+    ("sample_index", "*sample_index*"),
+    ("note_freq", "*note_freq*"),
+    ("note_gate", "*note_gate*"),
+    ("note_velocity", "*note_velocity*"),
+    ("bin_mag", "*bin_mag*"),
+    ("bin_phase", "*bin_phase*"),
+    ("bin_freq", "*bin_freq*"),
+];
+
+/// How far apart two numbers may be for `~=` to consider them equal.
+pub const APPROX_EQUAL_EPSILON: Number = 1e-6;
 
 pub fn codegen<'a>(ctxt: &'a Context<'a>) {
     //XXX
@@ -81,6 +125,18 @@ pub struct CodeGenerator<'a> {
     pub module: CSemiBox<'a, llvm::Module>,
     builder: CSemiBox<'a, llvm::Builder>,
     values: RefCell<ScopedTable<ValueWrapper<'a>>>,
+    call_sites: RefCell<Vec<SourcePos>>, // from CallSite
+    profile_labels: RefCell<Vec<String>>,
+    trace_labels: RefCell<Vec<TraceLabel>>,
+    debug_labels: RefCell<Vec<DebugLabel>>,
+    current_fn: RefCell<Vec<Identifier>>, // functions whose bodies are being generated
+    positions: RefCell<HashMap<usize, SourcePos>>, // from the address of an instruction
+    source_map: RefCell<Option<SourceMap>>,
+    memoized: RefCell<HashSet<Identifier>>, // functions which cache their result, see memo.rs
+    sample_index: RefCell<Option<&'a llvm::Value>>, // the global, which memoized functions read
+    hoisted: RefCell<Hoisted>, // expressions evaluated only when their inputs change, see hoist.rs
+    global_fns: RefCell<HashMap<Identifier, (&'a llvm::Value, &'a llvm::Function)>>, // struct and function
+    caches: RefCell<Vec<(&'a llvm::Value, &'a llvm::Value)>>, // the global marking a cache as filled, and its empty value
 }
 
 impl<'a> CodeGenerator<'a> {
@@ -90,10 +146,200 @@ impl<'a> CodeGenerator<'a> {
             types: ctxt.types.borrow(),
             functions: ctxt.functions.borrow(),
             llvm: &ctxt.llvm,
-            module: llvm::Module::new(&ctxt.filename, &ctxt.llvm),
+            module: llvm::Module::new(ctxt.filename(), &ctxt.llvm),
             builder: llvm::Builder::new(&ctxt.llvm),
             values: RefCell::new(ScopedTable::new()),
+            call_sites: RefCell::new(vec![SourcePos::anon()]), // site 0 is reserved for indirect calls
+            profile_labels: RefCell::new(Vec::new()),
+            trace_labels: RefCell::new(Vec::new()),
+            debug_labels: RefCell::new(Vec::new()),
+            current_fn: RefCell::new(Vec::new()),
+            positions: RefCell::new(HashMap::new()),
+            source_map: RefCell::new(None),
+            memoized: RefCell::new(HashSet::new()),
+            sample_index: RefCell::new(None),
+            hoisted: RefCell::new(Hoisted::new()),
+            global_fns: RefCell::new(HashMap::new()),
+            caches: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Names of the instrumented functions, indexed by the id passed to the profiler.
+    pub fn profile_labels(&self) -> Vec<String> {
+        self.profile_labels.borrow().clone()
+    }
+
+    /// The traced functions, indexed by the id passed to the tracer.
+    pub fn trace_labels(&self) -> Vec<TraceLabel> {
+        self.trace_labels.borrow().clone()
+    }
+
+    /// The variables and arguments recorded for the debugger, indexed by the id passed to it.
+    pub fn debug_labels(&self) -> Vec<DebugLabel> {
+        self.debug_labels.borrow().clone()
+    }
+
+    /// Where the generated code came from. Only available once codegen is done.
+    pub fn source_map(&self) -> SourceMap {
+        self.source_map.borrow().clone().unwrap_or(SourceMap::empty())
+    }
+
+    // Remembers the innermost expression which produced an instruction. Constants and arguments
+    // are not instructions, so they are skipped.
+    fn note_source(&self, value: &llvm::Value, pos: SourcePos) {
+        let value: LLVMValueRef = value.into();
+        if pos.is_anon() || unsafe { core::LLVMIsAInstruction(value) }.is_null() {
+            return;
+        }
+        self.positions.borrow_mut().entry(value as usize).or_insert(pos);
+    }
+
+    fn build_source_map(&self) -> SourceMap {
+        let positions = self.positions.borrow();
+        let mut functions = Vec::new();
+        unsafe {
+            let module: LLVMModuleRef = (&*self.module).into();
+            let mut func = core::LLVMGetFirstFunction(module);
+            while !func.is_null() {
+                let mut instructions = Vec::new();
+                let mut block = core::LLVMGetFirstBasicBlock(func);
+                while !block.is_null() {
+                    let mut inst = core::LLVMGetFirstInstruction(block);
+                    while !inst.is_null() {
+                        instructions.push(positions.get(&(inst as usize)).cloned());
+                        inst = core::LLVMGetNextInstruction(inst);
+                    }
+                    block = core::LLVMGetNextBasicBlock(block);
+                }
+                // the instructions computing the operands of an expression come before its result,
+                // so they belong to the next instruction with a position
+                let mut next = None;
+                for pos in instructions.iter_mut().rev() {
+                    match *pos {
+                        Some(_) => next = *pos,
+                        None => *pos = next,
+                    }
+                }
+                if !instructions.is_empty() {
+                    functions.push(FunctionMap {
+                        name: CStr::from_ptr(core::LLVMGetValueName(func)).to_string_lossy().into_owned(),
+                        instructions: instructions,
+                    });
+                }
+                func = core::LLVMGetNextFunction(func);
+            }
+        }
+        let call_sites = self.call_sites.borrow().iter()
+            .map(|&pos| if pos.is_anon() { None } else { Some(pos) })
+            .collect();
+        SourceMap::new(functions, call_sites)
+    }
+
+    // Emits a call to one of the profiler hooks if profiling is enabled.
+    fn codegen_profile_hook(&self, hook: extern fn(usize), id: usize) {
+        if !self.ctxt.options.borrow().profile {
+            return;
+        }
+        let unit_ty = llvm::Type::get::<()>(self.llvm);
+        let usize_ty = llvm::Type::get::<usize>(self.llvm);
+        let hook = self.codegen_const_fn(hook as usize, unit_ty, &[usize_ty]);
+        self.builder.build_call(hook, &[id.compile(self.llvm)]);
+    }
+
+    fn new_profile_label(&self, label: String) -> usize {
+        let mut labels = self.profile_labels.borrow_mut();
+        labels.push(label);
+        labels.len() - 1
+    }
+
+    // Starts tracing a call to the function being generated if it was asked for, passing each of
+    // its arguments to the tracer. Returns the id of the trace.
+    fn codegen_trace_enter(&self, func: &FunctionDef, func_args: &[Argument], llvm_func: &llvm::Function)
+            -> Option<usize> {
+        let name = self.ctxt.lookup_name(func.ident());
+        if !self.ctxt.options.borrow().trace.contains(&name) {
+            return None;
+        }
+        let ty = self.functions.get(func.ident()).unwrap().ty().unwrap();
+        // arguments are traced in the order they were declared in, rather than by identifier
+        let mut args = Vec::new();
+        for arg in func.args() {
+            let id = arg.ident().unwrap();
+            let idx = func_args.iter().position(|x| x.ident() == Some(id)).unwrap();
+            args.push((self.ctxt.lookup_name(id), idx, ty.args[id]));
+        }
+        let id = {
+            let mut labels = self.trace_labels.borrow_mut();
+            labels.push(TraceLabel {
+                name: name,
+                args: args.iter().map(|x| x.0.clone()).collect(),
+            });
+            labels.len() - 1
+        };
+        let unit_ty = llvm::Type::get::<()>(self.llvm);
+        let usize_ty = llvm::Type::get::<usize>(self.llvm);
+        let hook = self.codegen_const_fn(runtime::trace_enter as usize, unit_ty, &[usize_ty]);
+        self.builder.build_call(hook, &[id.compile(self.llvm)]);
+        for (_, idx, ty) in args {
+            self.codegen_trace_value(id, &llvm_func[idx], ty);
+        }
+        Some(id)
+    }
+
+    // Passes an argument or result of a traced function to the tracer.
+    fn codegen_trace_value(&self, id: usize, value: &llvm::Value, ty: Type) {
+        let unit_ty = llvm::Type::get::<()>(self.llvm);
+        let usize_ty = llvm::Type::get::<usize>(self.llvm);
+        let (hook, args) = match ty {
+            Type::Number => (self.codegen_const_fn(runtime::trace_number as usize, unit_ty,
+                                                   &[usize_ty, llvm::Type::get::<Number>(self.llvm)]),
+                             vec![id.compile(self.llvm), value]),
+            Type::Boolean => (self.codegen_const_fn(runtime::trace_boolean as usize, unit_ty,
+                                                    &[usize_ty, llvm::Type::get::<Boolean>(self.llvm)]),
+                              vec![id.compile(self.llvm), value]),
+            _ => (self.codegen_const_fn(runtime::trace_opaque as usize, unit_ty, &[usize_ty]),
+                  vec![id.compile(self.llvm)]),
+        };
+        self.builder.build_call(hook, &args);
+    }
+
+    // Passes the value of a variable or argument to the debugger, if the program is compiled for
+    // it. Only numbers and booleans are recorded.
+    fn codegen_debug_value(&self, name: String, pos: SourcePos, value: &llvm::Value, ty: Type) {
+        if !self.ctxt.options.borrow().debug {
+            return;
         }
+        let (hook, value_ty) = match ty {
+            Type::Number => (runtime::debug_number as usize, llvm::Type::get::<Number>(self.llvm)),
+            Type::Boolean => (runtime::debug_boolean as usize, llvm::Type::get::<Boolean>(self.llvm)),
+            _ => return,
+        };
+        let function = match self.current_fn.borrow().last() {
+            Some(&id) if self.ctxt.names.borrow().is_anon(id) == Some(true) => "<closure>".to_string(),
+            Some(&id) => self.ctxt.lookup_name(id),
+            None => "<global>".to_string(),
+        };
+        let id = {
+            let mut labels = self.debug_labels.borrow_mut();
+            labels.push(DebugLabel {
+                name: name,
+                function: function,
+                pos: pos,
+            });
+            labels.len() - 1
+        };
+        let unit_ty = llvm::Type::get::<()>(self.llvm);
+        let usize_ty = llvm::Type::get::<usize>(self.llvm);
+        let hook = self.codegen_const_fn(hook, unit_ty, &[usize_ty, value_ty]);
+        self.builder.build_call(hook, &[id.compile(self.llvm), value]);
+    }
+
+    fn codegen_trace_exit(&self, id: usize, result: &llvm::Value, ty: Type) {
+        self.codegen_trace_value(id, result, ty);
+        let unit_ty = llvm::Type::get::<()>(self.llvm);
+        let usize_ty = llvm::Type::get::<usize>(self.llvm);
+        let hook = self.codegen_const_fn(runtime::trace_exit as usize, unit_ty, &[usize_ty]);
+        self.builder.build_call(hook, &[id.compile(self.llvm)]);
     }
 
     pub fn codegen(&'a self) {
@@ -101,6 +347,43 @@ impl<'a> CodeGenerator<'a> {
 
         println!("{:?}", self.module);
         self.module.verify().unwrap();
+        *self.source_map.borrow_mut() = Some(self.build_source_map());
+        self.inline_functions();
+    }
+
+    // Marks the functions which are small enough for inlining, or were asked not to be, and runs
+    // the inliner over the module followed by passes which clean up after it, such as folding
+    // constant arguments into the inlined bodies. Calls between top level functions are direct
+    // for this, see direct_callee().
+    fn inline_functions(&self) {
+        let (threshold, fast_math) = {
+            let options = self.ctxt.options.borrow();
+            (options.inline_threshold, options.fast_math)
+        };
+        // the functions of fast math are always inlined, see codegen_fast_math()
+        if threshold == 0 && !fast_math {
+            return;
+        }
+        for (&id, &(_, func)) in self.global_fns.borrow().iter() {
+            if threshold == 0 {
+                break;
+            }
+            if self.ctxt.noinline.borrow().contains(&self.ctxt.lookup_name(id)) {
+                func.add_attributes(&[llvm::Attribute::NoInline]);
+            } else if !self.ctxt.callstack.borrow().is_recursive(id) && instruction_count(func) <= threshold {
+                func.add_attributes(&[llvm::Attribute::AlwaysInline]);
+            }
+        }
+        unsafe {
+            let module: LLVMModuleRef = (&*self.module).into();
+            let passes = core::LLVMCreatePassManager();
+            ipo::LLVMAddAlwaysInlinerPass(passes);
+            scalar::LLVMAddInstructionCombiningPass(passes);
+            scalar::LLVMAddGVNPass(passes);
+            scalar::LLVMAddCFGSimplificationPass(passes);
+            core::LLVMRunPassManager(passes, module);
+            core::LLVMDisposePassManager(passes);
+        }
     }
 
     fn codegen_root(&'a self, root: &Root) {
@@ -110,14 +393,26 @@ impl<'a> CodeGenerator<'a> {
         let block = init_fn.append("entry");
         self.builder.position_at_end(block);
 
+        self.codegen_builtin_variables();
+        if self.ctxt.options.borrow().memoize {
+            *self.memoized.borrow_mut() = memo::memoized_functions(self.ctxt);
+        }
+        if self.ctxt.options.borrow().hoist {
+            *self.hoisted.borrow_mut() = hoist::hoisted_expressions(self.ctxt);
+        }
         for (ident, func) in &self.functions.map {
             match *func {
                 functions::Function::External(ref def) => {
                     self.codegen_external_function(ident, def);
                 },
+                // some builtins are never values, see codegen_direct_builtin()
+                functions::Function::Pointer(ref def) if def.direct => { },
                 functions::Function::Pointer(ref def) => {
                     self.codegen_pointer_function(ident, def, init_fn);
                 },
+                functions::Function::Intrinsic(ref def) => {
+                    self.codegen_intrinsic_function(ident, def, init_fn);
+                },
                 _ => { },
             }
         }
@@ -143,18 +438,230 @@ impl<'a> CodeGenerator<'a> {
                 }
             }
         }
+        for (ident, inputs) in self.ctxt.inputs.borrow().iter() {
+            self.codegen_entry_fn(ident, inputs);
+        }
+        self.codegen_buses_fn();
+        self.codegen_reset_caches_fn();
 
         self.builder.build_ret_void();
     }
 
+    fn codegen_reset_caches_fn(&'a self) {
+        let unit_ty = llvm::Type::get::<()>(self.llvm);
+        let reset_fn = self.module.add_function(RESET_CACHES_FN_NAME, llvm::Type::new_function(unit_ty, &[]));
+        reset_fn.add_attributes(&[llvm::Attribute::NoUnwind]);
+        let owning_block = self.builder.get_position();
+        self.builder.position_at_end(reset_fn.append("entry"));
+        for &(global, empty) in self.caches.borrow().iter() {
+            self.builder.build_store(empty, global);
+        }
+        self.builder.build_ret_void();
+        self.builder.position_at_end(owning_block);
+    }
+
+    // Generates the function which hands each bus what was sent to it and adds what it gives to
+    // the outputs of the program, see bus.rs.
+    fn codegen_buses_fn(&'a self) {
+        let unit_ty = llvm::Type::get::<()>(self.llvm);
+        let num_ty = llvm::Type::get::<Number>(self.llvm);
+        let usize_ty = llvm::Type::get::<usize>(self.llvm);
+        let buses_fn = self.module.add_function(BUSES_FN_NAME, llvm::Type::new_function(unit_ty, &[]));
+        buses_fn.add_attributes(&[llvm::Attribute::NoUnwind]);
+        let owning_block = self.builder.get_position();
+        self.builder.position_at_end(buses_fn.append("entry"));
+        let get_input = self.codegen_const_fn(bus::bus_input as usize, num_ty, &[usize_ty]);
+        let add_output = self.codegen_const_fn(runtime::bus_output as usize, unit_ty,
+                                               &[usize_ty, usize_ty, num_ty]);
+        for (i, bus) in self.ctxt.buses.borrow().iter().enumerate() {
+            let func = match self.module.get_function(&self.ctxt.lookup_name(bus.ident)) {
+                Some(func) => func,
+                None => continue,
+            };
+            let input = self.builder.build_call(get_input, &[i.compile(self.llvm)]);
+            let res = self.builder.build_call(func, &[input]);
+            match self.channel_count(res) {
+                Some(count) => for channel in 0..count {
+                    self.builder.build_call(add_output, &[count.compile(self.llvm), channel.compile(self.llvm),
+                                                          self.builder.build_extract_value(res, channel)]);
+                },
+                None => {
+                    self.builder.build_call(add_output, &[1usize.compile(self.llvm), 0usize.compile(self.llvm), res]);
+                }
+            }
+        }
+        self.builder.build_ret_void();
+        self.builder.position_at_end(owning_block);
+    }
+
+    // Generates the function the runtime calls for an entrypoint with inputs. It passes `time`
+    // through and fetches every other input from the runtime.
+    fn codegen_entry_fn(&'a self, ident: Identifier, inputs: &[Input]) {
+        let name = self.ctxt.lookup_name(ident);
+        let func = match self.module.get_function(&name) {
+            Some(func) => func,
+            None => return,
+        };
+        let num_ty = llvm::Type::get::<Number>(self.llvm);
+        let entry_fn = self.module.add_function(&format!("{}{}", name, ENTRY_FN_SUFFIX),
+                                                llvm::Type::new_function(num_ty, &[num_ty]));
+        entry_fn.add_attributes(&[llvm::Attribute::NoUnwind]);
+        let owning_block = self.builder.get_position();
+        self.builder.position_at_end(entry_fn.append("entry"));
+        let get_input = self.codegen_const_fn(runtime::entry_input as usize, num_ty,
+                                              &[llvm::Type::get::<usize>(self.llvm)]);
+        let args: Vec<&llvm::Value> = inputs.iter().enumerate().map(|(i, input)| match *input {
+            Input::Time => &entry_fn[0],
+            _ => self.builder.build_call(get_input, &[i.compile(self.llvm)]),
+        }).collect();
+        let res = self.builder.build_call(func, &args);
+        // every channel but the first is handed over separately
+        let res = match self.channel_count(res) {
+            Some(count) => {
+                let unit_ty = llvm::Type::get::<()>(self.llvm);
+                let set_output = self.codegen_const_fn(runtime::entry_output as usize, unit_ty,
+                                                       &[llvm::Type::get::<usize>(self.llvm), num_ty]);
+                for i in 1..count {
+                    self.builder.build_call(set_output, &[i.compile(self.llvm), self.builder.build_extract_value(res, i)]);
+                }
+                self.builder.build_extract_value(res, 0)
+            }
+            None => res,
+        };
+        self.builder.build_ret(res);
+        self.builder.position_at_end(owning_block);
+    }
+
     fn codegen_external_function(&'a self, ident: Identifier, func: &ExternalFunction) -> ValueWrapper<'a> {
         let ty = Type::Function(ident);
-        let func = self.module.add_function(func.symbol, self.type_to_llvm(ty, false));
+        // builtins like `sin` and `sin_exact` may share a symbol
+        let func = match self.module.get_function(func.symbol) {
+            Some(existing) => existing,
+            None if func.symbol.starts_with(fastmath::SYMBOL_PREFIX) => self.codegen_fast_math(func.symbol),
+            None => self.module.add_function(func.symbol, self.type_to_llvm(ty, false)),
+        };
         let val = ValueWrapper::new(func, self.type_to_signature(ty));
         self.store_val(Node(ident, SourcePos::anon()), val.clone());
         val
     }
 
+    // Generates the polynomial approximation of fastmath.rs named by a symbol, as a function of the
+    // module which is always inlined, so that it is optimized and vectorized with its callers.
+    // Each follows the steps of its Rust version exactly, and so gives the same results.
+    fn codegen_fast_math(&'a self, symbol: &str) -> &'a llvm::Function {
+        if let Some(existing) = self.module.get_function(symbol) {
+            return existing;
+        }
+        let num_ty = llvm::Type::get::<Number>(self.llvm);
+        let int_ty = llvm::Type::get::<i64>(self.llvm);
+        let name = &symbol[fastmath::SYMBOL_PREFIX.len()..];
+        let arg_tys = if name == "pow" { vec![num_ty, num_ty] } else { vec![num_ty] };
+        // the functions a body calls are generated first, since generating one moves the builder
+        let callee = match name {
+            "cos" => Some(self.codegen_fast_math(&format!("{}sin", fastmath::SYMBOL_PREFIX))),
+            "exp" => Some(self.codegen_fast_math(&format!("{}exp2", fastmath::SYMBOL_PREFIX))),
+            "log" => Some(self.codegen_fast_math(&format!("{}log2", fastmath::SYMBOL_PREFIX))),
+            _ => None,
+        };
+        let (pow_exp2, pow_log2) = if name == "pow" {
+            (Some(self.codegen_fast_math(&format!("{}exp2", fastmath::SYMBOL_PREFIX))),
+             Some(self.codegen_fast_math(&format!("{}log2", fastmath::SYMBOL_PREFIX))))
+        } else {
+            (None, None)
+        };
+        let func = self.module.add_function(symbol, llvm::Type::new_function(num_ty, &arg_tys));
+        func.add_attributes(&[llvm::Attribute::NoUnwind, llvm::Attribute::AlwaysInline]);
+        let owning_block = self.builder.get_position();
+        self.builder.position_at_end(func.append("entry"));
+
+        let b = &self.builder;
+        let raw_builder: LLVMBuilderRef = (&**b).into();
+        let num = |x: Number| x.compile(self.llvm);
+        let int = |x: i64| x.compile(self.llvm);
+        let intrinsic = |name: &str, args: &[&'a llvm::Value]| {
+            let f = match self.module.get_function(name) {
+                Some(f) => f,
+                None => self.module.add_function(name, llvm::Type::new_function(num_ty, &vec![num_ty; args.len()])),
+            };
+            b.build_call(f, args)
+        };
+        let horner = |coefficients: &[Number], x: &'a llvm::Value| {
+            coefficients.iter().rev().fold(num(0.0), |p, &c| b.build_add(b.build_mul(p, x), num(c)))
+        };
+        let select = |cond: &'a llvm::Value, then: &'a llvm::Value, els: &'a llvm::Value| -> &'a llvm::Value {
+            unsafe { core::LLVMBuildSelect(raw_builder, cond.into(), then.into(), els.into(), NO_NAME.as_ptr() as *const _).into() }
+        };
+        let bit_cast = |x: &'a llvm::Value, ty: &'a llvm::Type| -> &'a llvm::Value {
+            unsafe { core::LLVMBuildBitCast(raw_builder, x.into(), ty.into(), NO_NAME.as_ptr() as *const _).into() }
+        };
+        let x = &func[0];
+        let res = match name {
+            "sin" => {
+                // into [-pi, pi], then folded into [-pi/2, pi/2] where sin is symmetric about the ends
+                let two_pi = num(2.0 * consts::PI);
+                let turns = intrinsic("llvm.round.f64", &[b.build_div(x, two_pi)]);
+                let r = b.build_sub(x, b.build_mul(turns, two_pi));
+                let r = intrinsic("llvm.minnum.f64", &[r, b.build_sub(num(consts::PI), r)]);
+                let r = intrinsic("llvm.maxnum.f64", &[r, b.build_sub(num(-consts::PI), r)]);
+                b.build_mul(r, horner(&fastmath::SIN, b.build_mul(r, r)))
+            }
+            "cos" => b.build_call(callee.unwrap(), &[b.build_add(x, num(consts::FRAC_PI_2))]),
+            "exp2" => {
+                let x = intrinsic("llvm.maxnum.f64", &[x, num(-1022.0)]);
+                let x = intrinsic("llvm.minnum.f64", &[x, num(1023.0)]);
+                let k = intrinsic("llvm.round.f64", &[x]);
+                let k_int: &llvm::Value = unsafe {
+                    core::LLVMBuildFPToSI(raw_builder, k.into(), int_ty.into(), NO_NAME.as_ptr() as *const _).into()
+                };
+                let exponent = b.build_add(k_int, int(1023));
+                let scale: &llvm::Value = unsafe {
+                    core::LLVMBuildShl(raw_builder, exponent.into(), int(52).into(), NO_NAME.as_ptr() as *const _).into()
+                };
+                b.build_mul(horner(&fastmath::EXP2, b.build_sub(x, k)), bit_cast(scale, num_ty))
+            }
+            "exp" => b.build_call(callee.unwrap(), &[b.build_mul(x, num(consts::LOG2_E))]),
+            "log2" => {
+                // x = m * 2^e, with m moved into [sqrt(1/2), sqrt(2)]
+                let bits = bit_cast(x, int_ty);
+                let m = bit_cast(b.build_or(b.build_and(bits, int((1 << 52) - 1)), int(1023 << 52)), num_ty);
+                let high = select(b.build_cmp(m, num(consts::SQRT_2), llvm::Predicate::GreaterThan), num(1.0), num(0.0));
+                let biased: &llvm::Value = unsafe {
+                    let shifted = core::LLVMBuildLShr(raw_builder, bits.into(), int(52).into(), NO_NAME.as_ptr() as *const _);
+                    let masked = core::LLVMBuildAnd(raw_builder, shifted, int(0x7ff).into(), NO_NAME.as_ptr() as *const _);
+                    core::LLVMBuildSIToFP(raw_builder, masked, num_ty.into(), NO_NAME.as_ptr() as *const _).into()
+                };
+                let e = b.build_add(b.build_sub(biased, num(1023.0)), high);
+                let m = b.build_mul(m, b.build_sub(num(1.0), b.build_mul(num(0.5), high)));
+                // ln(m) = 2 atanh(s), and |s| < 0.172 needs terms up to s^11
+                let s = b.build_div(b.build_sub(m, num(1.0)), b.build_add(m, num(1.0)));
+                let s2 = b.build_mul(s, s);
+                let series = (0..6).rev().fold(num(0.0), |p, i| {
+                    b.build_add(b.build_mul(p, s2), num(1.0 / (2 * i + 1) as Number))
+                });
+                b.build_add(e, b.build_mul(b.build_mul(num(2.0 * consts::LOG2_E), s), series))
+            }
+            "log" => b.build_mul(b.build_call(callee.unwrap(), &[x]), num(consts::LN_2)),
+            "pow" => {
+                let y = &func[1];
+                let log = b.build_call(pow_log2.unwrap(), &[intrinsic("llvm.fabs.f64", &[x])]);
+                let magnitude = b.build_call(pow_exp2.unwrap(), &[b.build_mul(y, log)]);
+                let integer = b.build_cmp(y, intrinsic("llvm.trunc.f64", &[y]), llvm::Predicate::Equal);
+                let half = intrinsic("llvm.trunc.f64", &[b.build_div(y, num(2.0))]);
+                let odd = b.build_and(integer, b.build_cmp(b.build_mul(half, num(2.0)), y, llvm::Predicate::NotEqual));
+                let zero = select(b.build_cmp(y, num(0.0), llvm::Predicate::GreaterThan), num(0.0),
+                                  select(b.build_cmp(y, num(0.0), llvm::Predicate::Equal), num(1.0), num(f64::INFINITY)));
+                let negative = select(odd, b.build_mul(magnitude, num(-1.0)), num(f64::NAN));
+                let positive = b.build_or(b.build_cmp(x, num(0.0), llvm::Predicate::GreaterThan),
+                                          b.build_and(integer, b.build_not(odd)));
+                select(b.build_cmp(x, num(0.0), llvm::Predicate::Equal), zero, select(positive, magnitude, negative))
+            }
+            _ => panic!("no fast version of `{}`", name),
+        };
+        b.build_ret(res);
+        self.builder.position_at_end(owning_block);
+        func
+    }
+
     fn codegen_pointer_function(&'a self, ident: Identifier, func: &PointerFunction,
                                 owning_fn: &llvm::Function) -> ValueWrapper<'a> {
         let ty = Type::Function(ident);
@@ -189,6 +696,116 @@ impl<'a> CodeGenerator<'a> {
         val
     }
 
+    // Intrinsics are wrapped in a function with a regular signature which packs the arguments
+    // into an array and hands them to the runtime.
+    fn codegen_intrinsic_function(&'a self, ident: Identifier, func: &IntrinsicFunction,
+                                  owning_fn: &llvm::Function) -> ValueWrapper<'a> {
+        let ty = Type::Function(ident);
+        let name = format!("*intrinsic*{}", self.ctxt.lookup_name(ident));
+        let llvm_func = self.module.add_function(&name, self.type_to_llvm(ty, false));
+        llvm_func.add_attributes(&[llvm::Attribute::NoUnwind]);
+
+        let owning_block = self.builder.get_position();
+        let entry = llvm_func.append("entry");
+        self.builder.position_at_end(entry);
+        let profile_id = self.new_profile_label(format!("{} (intrinsic)", self.ctxt.lookup_name(ident)));
+        self.codegen_profile_hook(runtime::profile_enter, profile_id);
+
+        let argc = func.ty.args.len();
+        let num_ty = llvm::Type::get::<Number>(self.llvm);
+        let array_ty = unsafe { core::LLVMArrayType(num_ty.into(), argc as u32).into() };
+        let array = self.builder.build_alloca(array_ty);
+        for (i, id) in func.ty.args.keys().enumerate() {
+            // parameters are ordered by identifier, but the intrinsic expects them in the
+            // order they were declared in
+            let idx = func.args.iter().position(|x| x.ident() == Some(id)).unwrap();
+            let ptr = self.builder.build_gep(array, &[0.compile(self.llvm),
+                                                      (idx as i32).compile(self.llvm)]);
+            self.builder.build_store(&llvm_func[i], ptr);
+        }
+        let args_ptr = self.builder.build_gep(array, &[0.compile(self.llvm), 0.compile(self.llvm)]);
+
+        let usize_ty = llvm::Type::get::<usize>(self.llvm);
+        let trampoline = self.codegen_const_fn(runtime::call_intrinsic as usize, num_ty,
+                                               &[usize_ty, llvm::Type::new_pointer(num_ty), usize_ty]);
+        let intrinsic_ptr = &*func.intrinsic as *const Intrinsic as usize;
+        let res = self.builder.build_call(trampoline, &[intrinsic_ptr.compile(self.llvm),
+                                                        args_ptr,
+                                                        argc.compile(self.llvm)]);
+        self.codegen_profile_hook(runtime::profile_exit, profile_id);
+        self.builder.build_ret(res);
+        self.builder.position_at_end(owning_block);
+
+        // like pointer functions, ones with default args are called through a struct of the
+        // function and the defaults, in the order of their signature
+        let defaults: Vec<&Expression> = func.ty.args.keys().zip(func.args.iter())
+                                             .filter_map(|(_, arg)| arg.expr()).collect();
+        if defaults.is_empty() {
+            let val = ValueWrapper::new(llvm_func, self.type_to_signature(ty));
+            self.store_val(Node(ident, SourcePos::anon()), val.clone());
+            return val;
+        }
+        let mut fn_struct_values: Vec<&llvm::Value> = Vec::new();
+        fn_struct_values.push(llvm_func);
+        for expr in defaults {
+            fn_struct_values.push(self.codegen_expr(expr, owning_fn).value);
+        }
+        let func_struct = llvm::Value::new_struct(self.llvm, &fn_struct_values, true);
+        let g_struct = self.module.add_global(&self.ctxt.lookup_name(ident), func_struct.get_type());
+        g_struct.set_initializer(func_struct);
+        let val = ValueWrapper::new(g_struct, self.type_to_signature(ty));
+        self.store_val(Node(ident, SourcePos::anon()), val.clone());
+        val
+    }
+
+    // Makes a callable function out of a pointer to a native function.
+    fn codegen_const_fn(&self, ptr: usize, ret: &llvm::Type, args: &[&llvm::Type]) -> &llvm::Function {
+        let fn_ty = llvm::Type::new_function(ret, args);
+        let ptr = unsafe {
+            core::LLVMConstIntToPtr(ptr.compile(self.llvm).into(),
+                                    llvm::Type::new_pointer(fn_ty).into()).into()
+        };
+        llvm::Function::cast(ptr).unwrap()
+    }
+
+    // Returns true if the callee of a call refers directly to a function implemented by the host,
+    // such as an intrinsic or `assert`.
+    fn is_host_callee(&self, callee: &Expression) -> bool {
+        match *callee {
+            Expression::Variable(Node(id, _)) => match self.functions.get(id) {
+                Some(&functions::Function::Intrinsic(_)) |
+                Some(&functions::Function::Pointer(_)) => true,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    // A sample rate known while compiling is folded into a constant rather than read from a
+    // global.
+    fn codegen_builtin_variables(&self) {
+        let num_ty = llvm::Type::get::<Number>(self.llvm);
+        let sample_rate = self.ctxt.options.borrow().sample_rate;
+        for &(name, global_name) in BUILTIN_VARIABLES {
+            let id = match self.ctxt.names.borrow().get_id(name) {
+                Some(id) => id,
+                None => continue,
+            };
+            let value: &llvm::Value = match (name, sample_rate) {
+                ("sample_rate", Some(rate)) => (rate as Number).compile(self.llvm),
+                _ => {
+                    let global = self.module.add_global(global_name, num_ty);
+                    global.set_initializer(0f64.compile(self.llvm));
+                    if name == "sample_index" {
+                        *self.sample_index.borrow_mut() = Some(global);
+                    }
+                    global
+                }
+            };
+            self.values.borrow_mut().set_val(id, 0, value.into());
+        }
+    }
+
     fn declare_global_function(&'a self, func: &FunctionDef, owning_fn: &llvm::Function)
             -> (Vec<Argument>, &'a llvm::Function, Rc<RefCell<FnSignature>>, &'a llvm::Value) {
         let ident = func.ident();
@@ -201,6 +818,7 @@ impl<'a> CodeGenerator<'a> {
         g_struct.set_initializer(llvm::Value::new_undef(struct_ty));
         self.builder.build_store(func_struct, g_struct);
         self.store_val(func.ident, ValueWrapper::new(g_struct, Some(sig.clone())));
+        self.global_fns.borrow_mut().insert(ident, (g_struct, llvm_func));
 
         (func_args, llvm_func, sig, g_struct)
     }
@@ -223,6 +841,7 @@ impl<'a> CodeGenerator<'a> {
                              llvm_func: &'a llvm::Function, sig: Rc<RefCell<FnSignature>>,
                              owning_block: &llvm::BasicBlock, g_struct: &'a llvm::Value) -> ValueWrapper<'a> {
         // setup args
+        self.current_fn.borrow_mut().push(func.ident());
         self.values.borrow_mut().push(func.ident_pos().index);
         for i in 0..func.args.len() {
             let ref arg = func_args[i];
@@ -234,14 +853,83 @@ impl<'a> CodeGenerator<'a> {
         // codegen block
         let entry = llvm_func.append("entry");
         self.builder.position_at_end(entry);
+        let memo = if self.memoized.borrow().contains(&func.ident()) {
+            Some(self.codegen_memo_lookup(func, llvm_func))
+        } else {
+            None
+        };
+        let label = if self.ctxt.names.borrow().is_anon(func.ident()) == Some(true) {
+            format!("<closure at {}>", func.ident_pos())
+        } else {
+            self.ctxt.lookup_name(func.ident())
+        };
+        let profile_id = self.new_profile_label(label);
+        self.codegen_profile_hook(runtime::profile_enter, profile_id);
+        let trace_id = self.codegen_trace_enter(func, &func_args, llvm_func);
+        if self.ctxt.options.borrow().debug {
+            let ty = self.functions.get(func.ident()).unwrap().ty().unwrap();
+            for arg in func.args() {
+                let id = arg.ident().unwrap();
+                let idx = func_args.iter().position(|x| x.ident() == Some(id)).unwrap();
+                self.codegen_debug_value(self.ctxt.lookup_name(id), arg.pos(), &llvm_func[idx], ty.args[id]);
+            }
+        }
         let res = self.codegen_block(&func.block, llvm_func);
+        if let Some(id) = trace_id {
+            let returns = self.functions.get(func.ident()).unwrap().ty().unwrap().returns;
+            self.codegen_trace_exit(id, res.value, returns);
+        }
+        self.codegen_profile_hook(runtime::profile_exit, profile_id);
+        if let Some(ref cache) = memo {
+            self.codegen_memo_store(cache, llvm_func, res.value);
+        }
         self.builder.build_ret(res.value);
         self.values.borrow_mut().pop();
+        self.current_fn.borrow_mut().pop();
         self.builder.position_at_end(owning_block);
 
         ValueWrapper::new(g_struct, Some(sig))
     }
 
+    // The cache of a memoized function is a global for the sample it was filled in, one for each
+    // argument and one for the result. Returns early with the result if the function was called
+    // in this sample with the same arguments, and otherwise gives the globals to fill in.
+    fn codegen_memo_lookup(&'a self, func: &FunctionDef, llvm_func: &'a llvm::Function) -> Vec<&'a llvm::Value> {
+        let num_ty = llvm::Type::get::<Number>(self.llvm);
+        let name = self.ctxt.lookup_name(func.ident());
+        let cache: Vec<&llvm::Value> = (0..func.args.len() + 2).map(|i| {
+            let global = self.module.add_global(&format!("*memo*{}*{}", name, i), num_ty);
+            // no sample has a negative index, so the cache starts out empty
+            global.set_initializer(if i == 0 { -1f64 } else { 0f64 }.compile(self.llvm));
+            global
+        }).collect();
+        self.caches.borrow_mut().push((cache[0], (-1f64).compile(self.llvm)));
+        let sample_index = self.sample_index.borrow().unwrap();
+        let mut hit = self.builder.build_cmp(self.builder.build_load(cache[0]), self.builder.build_load(sample_index),
+                                             llvm::Predicate::Equal);
+        for i in 0..func.args.len() {
+            let same = self.builder.build_cmp(self.builder.build_load(cache[i + 1]), &llvm_func[i],
+                                              llvm::Predicate::Equal);
+            hit = self.builder.build_and(hit, same);
+        }
+        let cached = llvm_func.append("cached");
+        let compute = llvm_func.append("compute");
+        self.builder.build_cond_br(hit, cached, Some(compute));
+        self.builder.position_at_end(cached);
+        self.builder.build_ret(self.builder.build_load(cache[func.args.len() + 1]));
+        self.builder.position_at_end(compute);
+        cache
+    }
+
+    fn codegen_memo_store(&'a self, cache: &[&'a llvm::Value], llvm_func: &'a llvm::Function, result: &llvm::Value) {
+        let sample_index = self.sample_index.borrow().unwrap();
+        self.builder.build_store(self.builder.build_load(sample_index), cache[0]);
+        for i in 1..cache.len() - 1 {
+            self.builder.build_store(&llvm_func[i - 1], cache[i]);
+        }
+        self.builder.build_store(result, cache[cache.len() - 1]);
+    }
+
     fn codegen_function_decl(&'a self, func: &FunctionDef, owning_fn: &llvm::Function, name: &str)
             -> (&llvm::Function, &llvm::Value, Rc<RefCell<FnSignature>>, Vec<Argument>) {
         let ident = func.ident();
@@ -309,21 +997,94 @@ impl<'a> CodeGenerator<'a> {
         let name = &self.ctxt.lookup_name(assign.ident());
         let val = self.codegen_expr(assign.expr(), func);
         val.set_name(name);
+        if let Some(ty) = self.types.get_symbol(assign.ident()).map(|x| x.val) {
+            self.codegen_debug_value(name.clone(), assign.ident_pos(), val.value, ty);
+        }
 
         self.store_val(assign.ident, val);
     }
 
     fn codegen_expr(&'a self, expr: &Expression, func: &llvm::Function) -> ValueWrapper<'a> {
+        let caller = self.current_fn.borrow().last().cloned();
+        let hoisted = match (caller, hoist::node_address(expr)) {
+            (Some(caller), Some(address)) => self.hoisted.borrow().get(&(caller, address)).cloned(),
+            _ => None,
+        };
+        let val = match hoisted {
+            Some(args) => self.codegen_hoisted(expr, &args, func),
+            None => self.codegen_expr_value(expr, func),
+        };
+        self.note_source(val.value, expr.pos());
+        val
+    }
+
+    // Keeps the value of an expression which doesn't change between samples in a global, along
+    // with the arguments it reads, and only evaluates it again when one of those is different.
+    fn codegen_hoisted(&'a self, expr: &Expression, args: &[usize], func: &llvm::Function) -> ValueWrapper<'a> {
+        let num_ty = llvm::Type::get::<Number>(self.llvm);
+        let bool_ty = llvm::Type::get::<bool>(self.llvm);
+        let name = self.ctxt.lookup_name(*self.current_fn.borrow().last().unwrap());
+        let valid = self.module.add_global(&format!("*hoisted*{}*valid", name), bool_ty);
+        valid.set_initializer(false.compile(self.llvm));
+        self.caches.borrow_mut().push((valid, false.compile(self.llvm)));
+        let keys: Vec<&llvm::Value> = args.iter().map(|_| {
+            let global = self.module.add_global(&format!("*hoisted*{}*key", name), num_ty);
+            global.set_initializer(0f64.compile(self.llvm));
+            global
+        }).collect();
+        let mut hit = self.builder.build_load(valid);
+        for (&i, &key) in args.iter().zip(&keys) {
+            let same = self.builder.build_cmp(self.builder.build_load(key), &func[i], llvm::Predicate::Equal);
+            hit = self.builder.build_and(hit, same);
+        }
+        let cached_block = func.append("hoisted_cached");
+        let compute_block = func.append("hoisted_compute");
+        let merge_block = func.append("hoisted_merge");
+        self.builder.build_cond_br(hit, cached_block, Some(compute_block));
+
+        self.builder.position_at_end(compute_block);
+        let value = self.codegen_expr_value(expr, func);
+        let ty = value.get_type();
+        let result = self.module.add_global(&format!("*hoisted*{}", name), ty);
+        result.set_initializer(llvm::Value::new_undef(ty));
+        self.builder.build_store(*value, result);
+        self.builder.build_store(true.compile(self.llvm), valid);
+        for (&i, &key) in args.iter().zip(&keys) {
+            self.builder.build_store(&func[i], key);
+        }
+        self.builder.build_br(merge_block);
+        let compute_block = self.builder.get_position();
+
+        self.builder.position_at_end(cached_block);
+        let cached = self.builder.build_load(result);
+        self.builder.build_br(merge_block);
+
+        self.builder.position_at_end(merge_block);
+        let phi = self.builder.build_phi(ty, "hoistedtmp");
+        phi.add_incoming(*value, compute_block);
+        phi.add_incoming(cached, cached_block);
+        value.map(phi)
+    }
+
+    fn codegen_expr_value(&'a self, expr: &Expression, func: &llvm::Function) -> ValueWrapper<'a> {
         match *expr {
             Expression::Constant(Node(v, _)) => v.compile(self.llvm).into(),
             Expression::Boolean(Node(v, _)) => v.compile(self.llvm).into(),
+            Expression::Str(Node(v, _)) => v.compile(self.llvm).into(),
             Expression::Infix(ref v) => self.codegen_infix(v, func),
             Expression::Prefix(ref v) => self.codegen_prefix(v, func),
             Expression::Variable(ref v) => self.codegen_var(**v, func),
             Expression::Conditional(ref v) => self.codegen_conditional(v, func),
             Expression::Block(ref v) => self.codegen_block(v, func),
             Expression::Closure(ref v) => self.codegen_closure(v, func),
+            Expression::BlockRate(ref v) => self.codegen_block_rate(v, func),
+            Expression::Oversample(ref v) => self.codegen_oversample(v, func),
+            Expression::Fft(ref v) => self.codegen_fft(v, func),
+            Expression::Voice(ref v) => self.codegen_voice(v, func),
             Expression::FunctionCall(ref v) => self.codegen_function_call(v, func),
+            Expression::Array(ref v) => self.codegen_array(v, func),
+            Expression::Record(ref v) => self.codegen_record(v, func),
+            Expression::Field(ref v) => self.codegen_field(v, func),
         }
     }
 
@@ -338,11 +1099,52 @@ impl<'a> CodeGenerator<'a> {
         }
     }
 
+    // Returns the instance of a generic function the typechecker chose for a call, if any.
+    fn instance_for(&self, call: &FunctionCall) -> Option<Identifier> {
+        match *call.callee() {
+            Expression::Variable(_) => { },
+            _ => return None,
+        }
+        let caller = self.current_fn.borrow().last().cloned();
+        self.ctxt.instances.borrow().get(&(caller, call.callee_pos().index)).cloned()
+    }
+
+    // The function a call refers to if it is a top level function which no local shadows, which
+    // is then called directly rather than through its struct so that it can be inlined.
+    fn direct_callee(&self, call: &FunctionCall) -> Option<&'a llvm::Function> {
+        let id = match (self.instance_for(call), call.callee()) {
+            (Some(id), _) | (None, &Expression::Variable(Node(id, _))) => id,
+            _ => return None,
+        };
+        let (g_struct, func) = match self.global_fns.borrow().get(&id) {
+            Some(&x) => x,
+            None => return None,
+        };
+        let values = self.values.borrow();
+        match values.get_symbol(id) {
+            Some(sym) if sym.val.value as *const llvm::Value == g_struct as *const llvm::Value => Some(func),
+            _ => None,
+        }
+    }
+
     fn codegen_function_call(&'a self, call: &FunctionCall, func: &llvm::Function) -> ValueWrapper<'a> {
-        let callee_expr = self.codegen_expr(call.callee(), func);
-        let callee = match llvm::Function::cast(callee_expr.value) {
-            Some(callee) => callee,
-            None => llvm::Function::cast(self.codegen_struct_load(callee_expr.value, 0)).unwrap(),
+        // asserts cost nothing unless they are checked
+        if let Expression::Variable(Node(id, _)) = *call.callee() {
+            let options = self.ctxt.options.borrow();
+            if self.ctxt.is_builtin(id, "assert") && !options.checked && !options.test {
+                return 0f64.compile(self.llvm).into();
+            }
+            if self.ctxt.is_direct_builtin(id) {
+                return self.codegen_direct_builtin(call, id, func);
+            }
+        }
+        let callee_expr = match self.instance_for(call) {
+            Some(instance) => self.codegen_var(instance, func),
+            None => self.codegen_expr(call.callee(), func),
+        };
+        let callee = match (self.direct_callee(call), llvm::Function::cast(callee_expr.value)) {
+            (Some(callee), _) | (None, Some(callee)) => callee,
+            (None, None) => llvm::Function::cast(self.codegen_struct_load(callee_expr.value, 0)).unwrap(),
         };
         let sig = callee_expr.sig.as_ref().unwrap().borrow();
         let mut call_args = VecMap::new();
@@ -401,9 +1203,172 @@ impl<'a> CodeGenerator<'a> {
         }
 
         let arg_vec: Vec<_> = call_args.values().map(|x| *x).collect();
+        if self.is_host_callee(call.callee()) {
+            // give each direct call its own state in the runtime
+            let site = {
+                let mut sites = self.call_sites.borrow_mut();
+                sites.push(call.callee_pos());
+                sites.len() - 1
+            };
+            let unit_ty = llvm::Type::get::<()>(self.llvm);
+            let set_site = self.codegen_const_fn(runtime::set_call_site as usize, unit_ty,
+                                                 &[llvm::Type::get::<usize>(self.llvm)]);
+            self.builder.build_call(set_site, &[site.compile(self.llvm)]);
+        }
         ValueWrapper::new(self.builder.build_call(callee, &arg_vec), sig.ret.clone())
     }
 
+    // Direct builtins are called with their call site followed by their arguments in the order
+    // they were declared in, with each array or signal of several channels passed as a pointer to
+    // its first element and its length. Ones returning several channels or a record write them to
+    // a pointer passed last.
+    fn codegen_direct_builtin(&'a self, call: &FunctionCall, id: Identifier, func: &llvm::Function)
+            -> ValueWrapper<'a> {
+        let (ptr, def_args, mut ty, outputs, channels_of) = match self.functions.get(id) {
+            Some(&functions::Function::Pointer(ref def)) =>
+                (def.ptr, def.args.clone(), def.ty.clone(), def.outputs.clone(), def.channels_of),
+            _ => unreachable!(),
+        };
+        let num_ty = llvm::Type::get::<Number>(self.llvm);
+        let usize_ty = llvm::Type::get::<usize>(self.llvm);
+        let mut arg_types = vec![usize_ty];
+        let mut arg_values = vec![];
+        for (i, arg) in def_args.iter().enumerate() {
+            let arg_id = arg.ident().unwrap();
+            let value = self.codegen_call_arg(call, i, arg_id, func);
+            match ty.args[arg_id] {
+                Type::Array(_) | Type::Channels(_) | Type::Complex => {
+                    let array = self.builder.build_alloca(value.get_type());
+                    self.builder.build_store(value, array);
+                    let len = unsafe { core::LLVMGetArrayLength(value.get_type().into()) } as usize;
+                    if channels_of == Some(arg_id) {
+                        ty.returns = Type::Channels(len);
+                    }
+                    arg_types.push(llvm::Type::new_pointer(num_ty));
+                    arg_types.push(usize_ty);
+                    arg_values.push(self.builder.build_gep(array, &[0.compile(self.llvm), 0.compile(self.llvm)]));
+                    arg_values.push(len.compile(self.llvm));
+                }
+                ty => {
+                    arg_types.push(self.type_to_llvm(ty, false));
+                    arg_values.push(value);
+                }
+            }
+        }
+        let site = {
+            let mut sites = self.call_sites.borrow_mut();
+            sites.push(call.callee_pos());
+            sites.len() - 1
+        };
+        arg_values.insert(0, site.compile(self.llvm));
+        let writes_out = match ty.returns {
+            Type::Array(_) | Type::Channels(_) | Type::Record(_) | Type::Complex => true,
+            _ => false,
+        };
+        if writes_out {
+            let out = self.builder.build_alloca(self.type_to_llvm(ty.returns, false));
+            arg_types.push(llvm::Type::new_pointer(num_ty));
+            arg_values.push(self.builder.build_gep(out, &[0.compile(self.llvm), 0.compile(self.llvm)]));
+            let builtin = self.codegen_const_fn(ptr as usize, llvm::Type::get::<()>(self.llvm), &arg_types);
+            self.builder.build_call(builtin, &arg_values);
+            let written = self.builder.build_load(out);
+            return match ty.returns {
+                Type::Record(shape) => {
+                    // from the order the builtin writes the fields to the order of the shape
+                    let fields = self.ctxt.records.borrow().fields(shape).to_vec();
+                    let values: Vec<&llvm::Value> = fields.iter().map(|field| {
+                        let index = outputs.iter().position(|x| x == field).unwrap();
+                        self.builder.build_extract_value(written, index)
+                    }).collect();
+                    self.codegen_numbers(&values).into()
+                }
+                _ => written.into(),
+            };
+        }
+        let builtin = self.codegen_const_fn(ptr as usize, num_ty, &arg_types);
+        self.builder.build_call(builtin, &arg_values).into()
+    }
+
+    // The value a direct call passes for the argument declared at `index`, which has no default.
+    fn codegen_call_arg(&'a self, call: &FunctionCall, index: usize, id: Identifier, func: &llvm::Function)
+            -> &'a llvm::Value {
+        if call.ty == CallType::Ordered {
+            return match call.args()[index] {
+                Argument::Expr(ref expr) => self.codegen_expr(expr, func).value,
+                _ => unreachable!(),
+            };
+        }
+        for arg in call.args() {
+            match *arg {
+                Argument::Assign(ident, ref expr) if *ident == id => return self.codegen_expr(expr, func).value,
+                Argument::OpAssign(ident, Node(op, _), ref expr) if *ident == id => {
+                    let lhs = self.codegen_var(*ident, func);
+                    let rhs = self.codegen_expr(expr, func);
+                    return self.codegen_binary_op(op, lhs, rhs).value;
+                }
+                Argument::Ident(ident) if *ident == id => return self.codegen_var(*ident, func).value,
+                _ => { }
+            }
+        }
+        unreachable!()
+    }
+
+    fn codegen_array(&'a self, array: &Node<Vec<Expression>>, func: &llvm::Function) -> ValueWrapper<'a> {
+        let values: Vec<&llvm::Value> = array.iter().map(|elem| *self.codegen_expr(elem, func)).collect();
+        self.codegen_numbers(&values).into()
+    }
+
+    // Fields are evaluated in the order they are written and stored in the order of their shape.
+    fn codegen_record(&'a self, record: &Node<Record>, func: &llvm::Function) -> ValueWrapper<'a> {
+        let mut fields: Vec<(Identifier, &llvm::Value)> = record.fields.iter()
+            .map(|&(ref field, ref value)| (*field.item(), *self.codegen_expr(value, func))).collect();
+        fields.sort_by(|a, b| a.0.cmp(&b.0));
+        let values: Vec<&llvm::Value> = fields.into_iter().map(|x| x.1).collect();
+        self.codegen_numbers(&values).into()
+    }
+
+    fn codegen_field(&'a self, field: &Node<Field>, func: &llvm::Function) -> ValueWrapper<'a> {
+        let record = self.codegen_expr(field.expr(), func);
+        let caller = self.current_fn.borrow().last().cloned();
+        let index = self.ctxt.records.borrow().read(caller, field.field_pos().index)
+            .expect("the typechecker records where every field is read");
+        self.codegen_struct_load(*record, index).into()
+    }
+
+    // Arrays and signals of several channels are built on the stack and passed around by value.
+    fn codegen_numbers(&self, values: &[&llvm::Value]) -> &llvm::Value {
+        let ptr = self.builder.build_alloca(self.type_to_llvm(Type::Array(values.len()), false));
+        for (i, &value) in values.iter().enumerate() {
+            let elem_ptr = self.builder.build_gep(ptr, &[0.compile(self.llvm), (i as i32).compile(self.llvm)]);
+            self.builder.build_store(value, elem_ptr);
+        }
+        self.builder.build_load(ptr)
+    }
+
+    // The number of channels of a value, if it is a signal of several channels.
+    fn channel_count(&self, value: &llvm::Value) -> Option<usize> {
+        let ty = value.get_type();
+        unsafe {
+            match core::LLVMGetTypeKind(ty.into()) {
+                LLVMTypeKind::LLVMArrayTypeKind => Some(core::LLVMGetArrayLength(ty.into()) as usize),
+                _ => None,
+            }
+        }
+    }
+
+    // Operators apply to signals of several channels one channel at a time, with a number
+    // applying to every channel.
+    fn codegen_channel_op(&self, op: Operator, lhs: &llvm::Value, rhs: &llvm::Value, count: usize) -> ValueWrapper {
+        let (lhs_channels, rhs_channels) = (self.channel_count(lhs).is_some(), self.channel_count(rhs).is_some());
+        let mut channels = Vec::with_capacity(count);
+        for i in 0..count {
+            let lhs = if lhs_channels { self.builder.build_extract_value(lhs, i) } else { lhs };
+            let rhs = if rhs_channels { self.builder.build_extract_value(rhs, i) } else { rhs };
+            channels.push(*self.codegen_binary_op(op, lhs.into(), rhs.into()));
+        }
+        self.codegen_numbers(&channels).into()
+    }
+
     fn codegen_block(&'a self, block: &Node<Block>, func: &llvm::Function) -> ValueWrapper<'a> {
         self.values.borrow_mut().push(block.pos().index);
         let mut value = None;
@@ -414,10 +1379,10 @@ impl<'a> CodeGenerator<'a> {
                 },
                 Statement::Expression(ref v) => {
                     let expr = self.codegen_expr(v, func);
-                    if expr.get_type().is_float() {
+                    if expr.get_type().is_float() || self.channel_count(*expr).is_some() {
                         value = match value {
                             None => Some(expr),
-                            Some(v) => Some(v.map(self.builder.build_add(*v, *expr))),
+                            Some(v) => Some(v.map(*self.codegen_binary_op(Operator::Add, v.clone(), expr))),
                         };
                     } else {
                         value = Some(expr);
@@ -471,15 +1436,303 @@ impl<'a> CodeGenerator<'a> {
         then_val.map(phi)
     }
 
+    // Blocks start at the multiples of the block size in `sample_index`, and the expression is
+    // only evaluated the first time it is reached in each block. Globals hold the start of the
+    // block it was last evaluated in, and the values it is held at or ramped between.
+    fn codegen_block_rate(&'a self, rate: &Node<BlockRate>, func: &llvm::Function) -> ValueWrapper<'a> {
+        let num_ty = llvm::Type::get::<Number>(self.llvm);
+        let globals: Vec<&llvm::Value> = ["start", "from", "to"].iter().map(|name| {
+            let global = self.module.add_global(&format!("*block*{}", name), num_ty);
+            // no block starts at a negative index, so the first one is always due
+            global.set_initializer(if *name == "start" { -1f64 } else { 0f64 }.compile(self.llvm));
+            global
+        }).collect();
+        let (start_global, from_global, to_global) = (globals[0], globals[1], globals[2]);
+        self.caches.borrow_mut().push((start_global, (-1f64).compile(self.llvm)));
+        let block_size = (self.ctxt.options.borrow().block_size as Number).compile(self.llvm);
+
+        let index = self.builder.build_load(self.sample_index.borrow().unwrap());
+        let phase = self.builder.build_rem(index, block_size);
+        let start = self.builder.build_sub(index, phase);
+        let last = self.builder.build_load(start_global);
+        let due = self.builder.build_cmp(last, start, llvm::Predicate::NotEqual);
+        let eval_block = func.append("block_eval");
+        let first_block = func.append("block_first");
+        let shift_block = func.append("block_shift");
+        let merge_block = func.append("block_merge");
+        self.builder.build_cond_br(due, eval_block, Some(merge_block));
+
+        self.builder.position_at_end(eval_block);
+        let value = self.codegen_expr(rate.expr(), func);
+        let prev = self.builder.build_load(to_global);
+        let first = self.builder.build_cmp(last, (-1f64).compile(self.llvm), llvm::Predicate::Equal);
+        self.builder.build_cond_br(first, first_block, Some(shift_block));
+        let eval_block = self.builder.get_position();
+
+        // the first block has nothing to ramp from
+        self.builder.position_at_end(first_block);
+        self.builder.build_br(shift_block);
+
+        self.builder.position_at_end(shift_block);
+        let from = self.builder.build_phi(num_ty, "blockfrom");
+        from.add_incoming(*value, first_block);
+        from.add_incoming(prev, eval_block);
+        self.builder.build_store(from, from_global);
+        self.builder.build_store(*value, to_global);
+        self.builder.build_store(start, start_global);
+        self.builder.build_br(merge_block);
+
+        self.builder.position_at_end(merge_block);
+        let to = self.builder.build_load(to_global);
+        if !rate.smooth {
+            return to.into();
+        }
+        let from = self.builder.build_load(from_global);
+        let ramp = self.builder.build_div(self.builder.build_mul(self.builder.build_sub(to, from), phase), block_size);
+        self.builder.build_add(from, ramp).into()
+    }
+
+    // The expression is evaluated in a loop `factor` times per sample, with every number it reads
+    // from the enclosing function replaced by one interpolated from its previous value. Each
+    // result goes to the runtime, which filters them and gives back the value for this sample.
+    fn codegen_oversample(&'a self, section: &Node<Oversample>, func: &llvm::Function) -> ValueWrapper<'a> {
+        let site = {
+            let mut sites = self.call_sites.borrow_mut();
+            sites.push(section.pos());
+            sites.len() - 1
+        };
+        let unit_ty = llvm::Type::get::<()>(self.llvm);
+        let num_ty = llvm::Type::get::<Number>(self.llvm);
+        let usize_ty = llvm::Type::get::<usize>(self.llvm);
+        let site = site.compile(self.llvm);
+        let factor = section.factor.compile(self.llvm);
+
+        let mut idents = Vec::new();
+        collect_variables(section.expr(), &mut idents);
+        let input_fn = self.codegen_const_fn(oversample::oversample_input as usize, num_ty,
+                                             &[usize_ty, usize_ty, usize_ty, num_ty]);
+        let mut inputs = Vec::new();
+        for id in idents {
+            let value = match self.values.borrow().get_symbol(id) {
+                // globals stay the same from one sample to the next
+                Some(sym) if sym.val.get_type().is_float() && llvm::GlobalValue::cast(sym.val.value).is_none() => {
+                    sym.val.clone()
+                }
+                _ => continue,
+            };
+            let prev = self.builder.build_call(input_fn, &[site, factor, inputs.len().compile(self.llvm), *value]);
+            inputs.push((id, value, prev));
+        }
+        let enter_fn = self.codegen_const_fn(oversample::oversample_enter as usize, unit_ty, &[usize_ty, usize_ty]);
+        self.builder.build_call(enter_fn, &[site, factor]);
+        let start_block = self.builder.get_position();
+        let loop_block = func.append("oversample_loop");
+        self.builder.build_br(loop_block);
+
+        self.builder.position_at_end(loop_block);
+        let step = self.builder.build_phi(num_ty, "oversample_step");
+        let next = self.builder.build_add(step, 1f64.compile(self.llvm));
+        let t = self.builder.build_div(next, (section.factor as Number).compile(self.llvm));
+        self.values.borrow_mut().push(section.pos().index);
+        for &(id, ref value, prev) in &inputs {
+            let delta = self.builder.build_sub(**value, prev);
+            let interpolated = self.builder.build_add(prev, self.builder.build_mul(delta, t));
+            self.store_val(Node(id, section.pos()), value.map(interpolated));
+        }
+        let result = self.codegen_expr(section.expr(), func);
+        self.values.borrow_mut().pop();
+        let push_fn = self.codegen_const_fn(oversample::oversample_push as usize, unit_ty,
+                                            &[usize_ty, usize_ty, num_ty]);
+        self.builder.build_call(push_fn, &[site, factor, *result]);
+        let done = self.builder.build_cmp(next, (section.factor as Number).compile(self.llvm),
+                                          llvm::Predicate::GreaterThanOrEqual);
+        let end_block = self.builder.get_position();
+        let exit_block = func.append("oversample_exit");
+        self.builder.build_cond_br(done, exit_block, Some(loop_block));
+        step.add_incoming(0f64.compile(self.llvm), start_block);
+        step.add_incoming(next, end_block);
+
+        self.builder.position_at_end(exit_block);
+        let output_fn = self.codegen_const_fn(oversample::oversample_output as usize, num_ty, &[usize_ty, usize_ty]);
+        self.builder.build_call(output_fn, &[site, factor]).into()
+    }
+
+    // Feeds the input to the runtime, which says how many bins there are to change in this sample.
+    // When there are some, loops over them, storing each in the globals of the bin variables before
+    // evaluating the body and handing back what it gives. The runtime then gives the output.
+    fn codegen_fft(&'a self, section: &Node<FftSection>, func: &llvm::Function) -> ValueWrapper<'a> {
+        let site = {
+            let mut sites = self.call_sites.borrow_mut();
+            sites.push(section.pos());
+            sites.len() - 1
+        };
+        let unit_ty = llvm::Type::get::<()>(self.llvm);
+        let num_ty = llvm::Type::get::<Number>(self.llvm);
+        let usize_ty = llvm::Type::get::<usize>(self.llvm);
+        let site = site.compile(self.llvm);
+        let bin_fns = [("bin_mag", spectral::fft_bin_mag as usize), ("bin_phase", spectral::fft_bin_phase as usize),
+                       ("bin_freq", spectral::fft_bin_freq as usize)];
+        let vars: Vec<_> = bin_fns.iter().filter_map(|&(name, ptr)| {
+            let id = match self.ctxt.names.borrow().get_id(name) {
+                Some(id) => id,
+                None => return None,
+            };
+            match self.values.borrow().get_symbol(id) {
+                Some(sym) if llvm::GlobalValue::cast(sym.val.value).is_some() => {
+                    Some((sym.val.value, self.codegen_const_fn(ptr, num_ty, &[usize_ty, num_ty])))
+                }
+                _ => None,
+            }
+        }).collect();
+
+        let input = self.codegen_expr(section.input(), func);
+        let input_fn = self.codegen_const_fn(spectral::fft_input as usize, num_ty, &[usize_ty, num_ty]);
+        let bins = self.builder.build_call(input_fn, &[site, *input]);
+        let start_block = self.builder.get_position();
+        let loop_block = func.append("fft_loop");
+        let exit_block = func.append("fft_exit");
+        let due = self.builder.build_cmp(bins, 0f64.compile(self.llvm), llvm::Predicate::GreaterThan);
+        self.builder.build_cond_br(due, loop_block, Some(exit_block));
+
+        self.builder.position_at_end(loop_block);
+        let index = self.builder.build_phi(num_ty, "fft_bin");
+        for &(global, bin_fn) in &vars {
+            let value = self.builder.build_call(bin_fn, &[site, index]);
+            self.builder.build_store(value, global);
+        }
+        let result = self.codegen_expr(section.expr(), func);
+        let set_fn = self.codegen_const_fn(spectral::fft_set_bin as usize, unit_ty,
+                                           &[usize_ty, num_ty, num_ty, num_ty]);
+        self.builder.build_call(set_fn, &[site, index, self.builder.build_extract_value(*result, 0),
+                                          self.builder.build_extract_value(*result, 1)]);
+        let next = self.builder.build_add(index, 1f64.compile(self.llvm));
+        let done = self.builder.build_cmp(next, bins, llvm::Predicate::GreaterThanOrEqual);
+        let end_block = self.builder.get_position();
+        self.builder.build_cond_br(done, exit_block, Some(loop_block));
+        index.add_incoming(0f64.compile(self.llvm), start_block);
+        index.add_incoming(next, end_block);
+
+        // outside of the body the bin variables are 0
+        self.builder.position_at_end(exit_block);
+        for &(global, _) in &vars {
+            self.builder.build_store(0f64.compile(self.llvm), global);
+        }
+        let output_fn = self.codegen_const_fn(spectral::fft_output as usize, num_ty, &[usize_ty, num_ty]);
+        self.builder.build_call(output_fn, &[site, bins]).into()
+    }
+
+    // Loops over the voices, storing the note of each in the globals of the note variables before
+    // evaluating the body, and sums the results.
+    fn codegen_voice(&'a self, voice: &Node<VoiceBlock>, func: &llvm::Function) -> ValueWrapper<'a> {
+        let site = {
+            let mut sites = self.call_sites.borrow_mut();
+            sites.push(voice.pos());
+            sites.len() - 1
+        };
+        let unit_ty = llvm::Type::get::<()>(self.llvm);
+        let num_ty = llvm::Type::get::<Number>(self.llvm);
+        let usize_ty = llvm::Type::get::<usize>(self.llvm);
+        let site = site.compile(self.llvm);
+        let note_fns = [("note_freq", poly::voice_freq as usize), ("note_gate", poly::voice_gate as usize),
+                        ("note_velocity", poly::voice_velocity as usize)];
+        let notes: Vec<_> = note_fns.iter().filter_map(|&(name, ptr)| {
+            let id = match self.ctxt.names.borrow().get_id(name) {
+                Some(id) => id,
+                None => return None,
+            };
+            match self.values.borrow().get_symbol(id) {
+                Some(sym) if llvm::GlobalValue::cast(sym.val.value).is_some() => {
+                    Some((sym.val.value, self.codegen_const_fn(ptr, num_ty, &[usize_ty, num_ty])))
+                }
+                _ => None,
+            }
+        }).collect();
+
+        let start_block = self.builder.get_position();
+        let loop_block = func.append("voice_loop");
+        self.builder.build_br(loop_block);
+
+        self.builder.position_at_end(loop_block);
+        let index = self.builder.build_phi(num_ty, "voice_index");
+        let sum = self.builder.build_phi(num_ty, "voice_sum");
+        let enter_fn = self.codegen_const_fn(poly::voice_enter as usize, unit_ty, &[usize_ty, num_ty]);
+        self.builder.build_call(enter_fn, &[site, index]);
+        for &(global, note_fn) in &notes {
+            let value = self.builder.build_call(note_fn, &[site, index]);
+            self.builder.build_store(value, global);
+        }
+        let result = self.codegen_expr(voice.expr(), func);
+        let next_sum = self.builder.build_add(sum, *result);
+        let next = self.builder.build_add(index, 1f64.compile(self.llvm));
+        let done = self.builder.build_cmp(next, (poly::VOICES as Number).compile(self.llvm),
+                                          llvm::Predicate::GreaterThanOrEqual);
+        let end_block = self.builder.get_position();
+        let exit_block = func.append("voice_exit");
+        self.builder.build_cond_br(done, exit_block, Some(loop_block));
+        index.add_incoming(0f64.compile(self.llvm), start_block);
+        index.add_incoming(next, end_block);
+        sum.add_incoming(0f64.compile(self.llvm), start_block);
+        sum.add_incoming(next_sum, end_block);
+
+        // outside of voice blocks the note variables are 0
+        self.builder.position_at_end(exit_block);
+        let exit_fn = self.codegen_const_fn(poly::voice_exit as usize, unit_ty, &[usize_ty]);
+        self.builder.build_call(exit_fn, &[site]);
+        for &(global, _) in &notes {
+            self.builder.build_store(0f64.compile(self.llvm), global);
+        }
+        next_sum.into()
+    }
+
     fn codegen_infix(&'a self, infix: &Infix, func: &llvm::Function) -> ValueWrapper<'a> {
         let lhs = self.codegen_expr(infix.left(), func);
         let rhs = self.codegen_expr(infix.right(), func);
+        let caller = self.current_fn.borrow().last().cloned();
+        if self.ctxt.complex_ops.borrow().contains(&(caller, infix.op_pos().index)) {
+            return self.codegen_complex_op(infix.op(), *lhs, *rhs);
+        }
         self.codegen_binary_op(infix.op(), lhs, rhs)
     }
 
+    // Complex numbers are stored as their real part followed by their imaginary part, and a
+    // number on either side is taken to have no imaginary part.
+    fn codegen_complex_op(&self, op: Operator, lhs: &llvm::Value, rhs: &llvm::Value) -> ValueWrapper {
+        let zero = 0f64.compile(self.llvm);
+        let (a, b) = if self.channel_count(lhs).is_some() {
+            (self.builder.build_extract_value(lhs, 0), self.builder.build_extract_value(lhs, 1))
+        } else {
+            (lhs, zero)
+        };
+        let (c, d) = if self.channel_count(rhs).is_some() {
+            (self.builder.build_extract_value(rhs, 0), self.builder.build_extract_value(rhs, 1))
+        } else {
+            (rhs, zero)
+        };
+        let b_ = &self.builder;
+        let (re, im) = match op {
+            Operator::Add => (b_.build_add(a, c), b_.build_add(b, d)),
+            Operator::Sub => (b_.build_sub(a, c), b_.build_sub(b, d)),
+            Operator::Mul => (b_.build_sub(b_.build_mul(a, c), b_.build_mul(b, d)),
+                              b_.build_add(b_.build_mul(a, d), b_.build_mul(b, c))),
+            Operator::Div => {
+                let denom = b_.build_add(b_.build_mul(c, c), b_.build_mul(d, d));
+                (b_.build_div(b_.build_add(b_.build_mul(a, c), b_.build_mul(b, d)), denom),
+                 b_.build_div(b_.build_sub(b_.build_mul(b, c), b_.build_mul(a, d)), denom))
+            }
+            _ => unreachable!(),
+        };
+        self.codegen_numbers(&[re, im]).into()
+    }
+
     fn codegen_binary_op(&self, op: Operator, lhs: ValueWrapper, rhs: ValueWrapper) -> ValueWrapper {
         let lhs = *lhs;
         let rhs = *rhs;
+        if let Some(count) = self.channel_count(lhs).or(self.channel_count(rhs)) {
+            return self.codegen_channel_op(op, lhs, rhs, count);
+        }
+        if let Some(reduced) = const_number(rhs).and_then(|x| self.codegen_strength_reduced(op, lhs, x)) {
+            return reduced.into();
+        }
         match op {
             Operator::Add => self.builder.build_add(lhs, rhs),
             Operator::Sub => self.builder.build_sub(lhs, rhs),
@@ -491,20 +1744,76 @@ impl<'a> CodeGenerator<'a> {
             Operator::GreaterEqual => self.builder.build_cmp(lhs, rhs, llvm::Predicate::GreaterThanOrEqual),
             Operator::Equal => self.builder.build_cmp(lhs, rhs, llvm::Predicate::Equal),
             Operator::NotEqual => self.builder.build_cmp(lhs, rhs, llvm::Predicate::NotEqual),
+            Operator::ApproxEqual => {
+                let abs_fn = self.module.get_function("llvm.fabs.f64").unwrap();
+                let diff = self.builder.build_call(abs_fn, &[self.builder.build_sub(lhs, rhs)]);
+                self.builder.build_cmp(diff, APPROX_EQUAL_EPSILON.compile(self.llvm),
+                                       llvm::Predicate::LessThanOrEqual)
+            }
             Operator::Or => self.builder.build_or(lhs, rhs),
             Operator::And => self.builder.build_and(lhs, rhs),
             Operator::Xor => self.builder.build_xor(lhs, rhs),
             Operator::Mod => self.builder.build_rem(lhs, rhs),
+            Operator::BitAnd | Operator::BitOr | Operator::ShiftLeft | Operator::ShiftRight => {
+                let ptr = match op {
+                    Operator::BitAnd => int::bit_and as usize,
+                    Operator::BitOr => int::bit_or as usize,
+                    Operator::ShiftLeft => int::shift_left as usize,
+                    _ => int::shift_right as usize,
+                };
+                let num_ty = llvm::Type::get::<Number>(self.llvm);
+                let bitwise_fn = self.codegen_const_fn(ptr, num_ty, &[num_ty, num_ty]);
+                self.builder.build_call(bitwise_fn, &[lhs, rhs])
+            }
             Operator::Exp => {
-                let pow_fn = self.module.get_function("llvm.pow.f64").unwrap();
+                let (deterministic, fast_math) = {
+                    let options = self.ctxt.options.borrow();
+                    (options.deterministic, options.fast_math)
+                };
+                let num_ty = llvm::Type::get::<Number>(self.llvm);
+                let pow_fn = if fast_math {
+                    self.codegen_fast_math(&format!("{}pow", fastmath::SYMBOL_PREFIX))
+                } else if deterministic {
+                    self.codegen_const_fn(math::pow as usize, num_ty, &[num_ty, num_ty])
+                } else {
+                    self.module.get_function("llvm.pow.f64").unwrap()
+                };
                 self.builder.build_call(pow_fn, &[lhs, rhs])
             }
             _ => unreachable!(),
         }.into()
     }
 
+    // Rewrites an operation whose right hand side is a constant into cheaper instructions, since
+    // these are in the inner loop of nearly every patch. Division, cubes and fourth powers can be
+    // an ulp away from what they replace, and `x % 1` gives +0 where it gave -0, but everything
+    // else is exact.
+    fn codegen_strength_reduced(&self, op: Operator, lhs: &llvm::Value, rhs: Number) -> Option<&llvm::Value> {
+        Some(match op {
+            Operator::Mul | Operator::Div | Operator::Exp if rhs == 1.0 => lhs,
+            Operator::Div if rhs != 0.0 && rhs.is_finite() =>
+                self.builder.build_mul(lhs, (1.0 / rhs).compile(self.llvm)),
+            // the same products as the portable `pow` takes by squaring
+            Operator::Exp if rhs == 2.0 => self.builder.build_mul(lhs, lhs),
+            Operator::Exp if rhs == 3.0 => self.builder.build_mul(self.builder.build_mul(lhs, lhs), lhs),
+            Operator::Exp if rhs == 4.0 => {
+                let square = self.builder.build_mul(lhs, lhs);
+                self.builder.build_mul(square, square)
+            }
+            Operator::Exp if rhs == -1.0 => self.builder.build_div(1f64.compile(self.llvm), lhs),
+            Operator::Mod if rhs == 1.0 => {
+                let trunc_fn = self.module.get_function("llvm.trunc.f64").unwrap();
+                self.builder.build_sub(lhs, self.builder.build_call(trunc_fn, &[lhs]))
+            }
+            _ => return None,
+        })
+    }
+
     fn codegen_prefix(&'a self, prefix: &Prefix, func: &llvm::Function) -> ValueWrapper<'a> {
         let expr = self.codegen_expr(prefix.expr(), func);
+        if let (Operator::Sub, Some(count)) = (prefix.op(), self.channel_count(*expr)) {
+            return self.codegen_channel_op(Operator::Sub, 0f64.compile(self.llvm), *expr, count);
+        }
         expr.map(match prefix.op() {
             Operator::Sub => self.builder.build_sub(0f64.compile(self.llvm), *expr),
             Operator::Not => self.builder.build_not(*expr),
@@ -523,7 +1832,13 @@ impl<'a> CodeGenerator<'a> {
     fn type_to_signature(&self, ty: Type) -> Option<Rc<RefCell<FnSignature>>> {
         match ty {
             Type::Number => None,
+            Type::Int => None,
             Type::Boolean => None,
+            Type::Str => None,
+            Type::Array(_) => None,
+            Type::Channels(_) => None,
+            Type::Record(_) => None,
+            Type::Complex => None,
             Type::Function(id) => {
                 let func = self.functions.get(id).unwrap();
                 let ty = func.ty().unwrap();
@@ -553,8 +1868,18 @@ impl<'a> CodeGenerator<'a> {
 
     fn type_to_llvm(&self, ty: Type, make_fn_struct: bool) -> &llvm::Type {
         match ty {
-            Type::Number => llvm::Type::get::<Number>(self.llvm),
+            // Ints are whole numbers held like any other, see int.rs
+            Type::Number | Type::Int => llvm::Type::get::<Number>(self.llvm),
             Type::Boolean => llvm::Type::get::<Boolean>(self.llvm),
+            Type::Str => llvm::Type::get::<usize>(self.llvm),
+            Type::Array(len) | Type::Channels(len) => unsafe {
+                core::LLVMArrayType(llvm::Type::get::<Number>(self.llvm).into(), len as u32).into()
+            },
+            Type::Record(shape) => {
+                let len = self.ctxt.records.borrow().fields(shape).len();
+                self.type_to_llvm(Type::Array(len), make_fn_struct)
+            }
+            Type::Complex => self.type_to_llvm(Type::Array(2), make_fn_struct),
             Type::Function(id) => {
                 let func = self.functions.get(id).unwrap();
                 let ty = func.ty().unwrap();
@@ -581,3 +1906,89 @@ impl<'a> CodeGenerator<'a> {
         }
     }
 }
+
+// Collects the variables an expression reads, including ones it defines itself.
+fn collect_variables(expr: &Expression, idents: &mut Vec<Identifier>) {
+    fn add(id: Identifier, idents: &mut Vec<Identifier>) {
+        if !idents.contains(&id) {
+            idents.push(id);
+        }
+    }
+    match *expr {
+        Expression::Variable(Node(id, _)) => add(id, idents),
+        Expression::Infix(ref x) => {
+            collect_variables(x.left(), idents);
+            collect_variables(x.right(), idents);
+        }
+        Expression::Prefix(ref x) => collect_variables(x.expr(), idents),
+        Expression::Conditional(ref x) => {
+            collect_variables(x.cond(), idents);
+            collect_variables(x.then(), idents);
+            collect_variables(x.els(), idents);
+        }
+        Expression::Block(ref x) => for stmnt in x.iter() {
+            match *stmnt {
+                Statement::Assignment(ref a) => collect_variables(a.expr(), idents),
+                Statement::Expression(ref e) => collect_variables(e, idents),
+            }
+        },
+        Expression::FunctionCall(ref x) => {
+            collect_variables(x.callee(), idents);
+            for arg in x.args().iter() {
+                match *arg {
+                    Argument::Ident(Node(id, _)) => add(id, idents),
+                    Argument::OpAssign(Node(id, _), _, ref e) => {
+                        add(id, idents);
+                        collect_variables(e, idents);
+                    }
+                    Argument::Assign(_, ref e) | Argument::Expr(ref e) => collect_variables(e, idents),
+                }
+            }
+        }
+        Expression::BlockRate(ref x) => collect_variables(x.expr(), idents),
+        Expression::Oversample(ref x) => collect_variables(x.expr(), idents),
+        Expression::Fft(ref x) => {
+            collect_variables(x.input(), idents);
+            collect_variables(x.expr(), idents);
+        }
+        Expression::Voice(ref x) => collect_variables(x.expr(), idents),
+        Expression::Array(ref x) => for elem in x.iter() {
+            collect_variables(elem, idents);
+        },
+        Expression::Record(ref x) => for &(_, ref value) in &x.fields {
+            collect_variables(value, idents);
+        },
+        Expression::Field(ref x) => collect_variables(x.expr(), idents),
+        Expression::Constant(_) | Expression::Boolean(_) | Expression::Str(_) | Expression::Closure(_) => { }
+    }
+}
+
+// The value of a constant number, such as a literal.
+fn const_number(value: &llvm::Value) -> Option<Number> {
+    unsafe {
+        let value: LLVMValueRef = value.into();
+        if core::LLVMIsAConstantFP(value).is_null() {
+            return None;
+        }
+        let mut loses_info = 0;
+        Some(core::LLVMConstRealGetDouble(value, &mut loses_info))
+    }
+}
+
+// The number of instructions in a function, which is what decides if it is small enough to inline.
+fn instruction_count(func: &llvm::Value) -> usize {
+    let mut count = 0;
+    unsafe {
+        let func: LLVMValueRef = func.into();
+        let mut block = core::LLVMGetFirstBasicBlock(func);
+        while !block.is_null() {
+            let mut inst = core::LLVMGetFirstInstruction(block);
+            while !inst.is_null() {
+                count += 1;
+                inst = core::LLVMGetNextInstruction(inst);
+            }
+            block = core::LLVMGetNextBasicBlock(block);
+        }
+    }
+    count
+}
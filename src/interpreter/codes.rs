@@ -0,0 +1,563 @@
+use std::fmt;
+
+macro_rules! codes {
+    ( $( $name:ident = $code:expr => $explanation:expr, )* ) => {
+        /// Stable identifiers for every diagnostic the compiler can emit.
+        #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+        pub enum Code {
+            $( $name, )*
+        }
+
+        impl Code {
+            pub fn all() -> &'static [Code] {
+                static ALL: &'static [Code] = &[ $( Code::$name, )* ];
+                ALL
+            }
+
+            pub fn as_str(&self) -> &'static str {
+                match *self {
+                    $( Code::$name => $code, )*
+                }
+            }
+
+            /// An extended description of the diagnostic, with an example which triggers it.
+            pub fn explanation(&self) -> &'static str {
+                let explanation: &'static str = match *self {
+                    $( Code::$name => $explanation, )*
+                };
+                explanation.trim()
+            }
+        }
+    }
+}
+
+impl Code {
+    pub fn parse(s: &str) -> Option<Code> {
+        let s = s.to_uppercase();
+        Code::all().iter().find(|x| x.as_str() == s).cloned()
+    }
+
+    /// The name of the lint which controls this warning, as used by `-W` and `-A`.
+    pub fn lint_name(&self) -> Option<&'static str> {
+        Some(match *self {
+            Code::ShadowedFunction => "shadowing",
+            Code::UnusedFunction => "unused-function",
+            Code::ReassignedType => "reassigned-type",
+            Code::UnusedVariable => "unused-variable",
+            Code::ApproxEqualConstants => "approx-equality-on-constants",
+            Code::ShadowedArgument => "shadowed-argument",
+            Code::IndexOutOfRange => "index-out-of-range",
+            _ => return None,
+        })
+    }
+
+    pub fn from_lint_name(name: &str) -> Option<Code> {
+        Code::all().iter().find(|x| x.lint_name() == Some(name)).cloned()
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+codes! {
+    UnrecognizedToken = "E001" => r"
+A character sequence in the source is not part of the language.
+
+    x = 1 $ 2;
+
+Only numbers, booleans, strings, identifiers, operators and the symbols `.,=:;?(){}[]\@`
+and `->` are recognized. Comments start with `//`.
+",
+    ExpectedSymbol = "E002" => r"
+A particular symbol was required to continue parsing, but something else was found.
+
+    x = (1 + 2;
+
+Usually a bracket is unbalanced, a statement is missing its `;`, or a conditional is
+missing its `else`.
+",
+    ExpectedIdentifier = "E003" => r"
+A name was required, such as the name of a function, argument or variable.
+
+    = 1;
+
+Every assignment and function definition starts with a name.
+",
+    ExpectedExpression = "E004" => r"
+An expression was required but the tokens found cannot start one.
+
+    x = 1 + ;
+
+Expressions start with a constant, a variable, an opening bracket, a closure or a unary
+operator.
+",
+    ExpectedOperator = "E005" => r"
+Two expressions were placed next to each other without an operator between them, or a
+unary operator was used where a binary one is needed.
+
+    x = 1 2;
+
+Join expressions with a binary operator, or separate statements with `;`.
+",
+    ExpectedItem = "E006" => r"
+The top level of a file may only contain assignments, function definitions and test blocks,
+except for an expression at the very end without a `;`, which makes the file a script.
+
+    1 + 2;
+
+Assign the value to a name, `x = 1 + 2;`, or define a function, `f { 1 + 2 }`.
+",
+    DuplicateArgument = "E007" => r"
+A function definition lists the same argument more than once.
+
+    f x, x { x }
+
+Every argument needs a distinct name.
+",
+    UnknownType = "E008" => r"
+A type annotation names a type which does not exist.
+
+    f x: Integer { x }
+
+The available types are `Number`, `Int`, `Boolean` and `Function`.
+",
+    UnterminatedComment = "E009" => r"
+A block comment was opened with `/*` but never closed with `*/`.
+
+    /* the rest of the file is commented out
+    x = 1;
+
+Block comments do not nest, so the first `*/` closes the comment.
+",
+    UnterminatedString = "E010" => r#"
+A string literal was opened with `"` but not closed on the same line.
+
+    test "decay { assert(env(1) < 0.1); }
+
+Strings may not span lines. Quotes inside a string are written as `\"`.
+"#,
+    DuplicateTest = "E011" => r#"
+Two test blocks in a file have the same name.
+
+    test "silence" { assert(f(0) == 0); }
+    test "silence" { assert(g(0) == 0); }
+
+Test names identify failures in the output of `synthizer test`, so each must be unique.
+"#,
+    InvalidNumber = "E012" => r"
+A number literal is malformed.
+
+    x = 1.5e;
+    y = 2x;
+
+An exponent must have at least one digit after the `e`, such as `1.5e3` or `1.5e-3`, and a
+number may not run into letters. Hexadecimal numbers such as `0x1F` must have at least one
+digit, digits may be separated with underscores as in `1_000`, and numbers too large for a
+64-bit float are rejected.
+",
+    InvalidEscape = "E013" => r#"
+A string literal contains a backslash which does not start a known escape sequence.
+
+    test "C:\samples" { }
+
+The escapes are `\"`, `\\`, `\n`, `\r`, `\t`, `\0`, and `\u{...}` with the hexadecimal code
+of any character.
+"#,
+    UnknownVariable = "E100" => r"
+A name was used which is not defined in the current scope.
+
+    f { y }
+
+Variables must be assigned before they are used, and the arguments of a function are only
+visible inside its body. Similar names in scope are suggested when there are any.
+",
+    TypeMismatch = "E101" => r"
+Two types which were required to be the same are different. Both of the places the types
+were inferred from are shown.
+
+    x = 1 + true;
+
+Numerical and comparison operators take numbers, logical operators take booleans, and
+blocks with several expressions add them together so each must be a number. Conditionals
+whose branches differ are reported as E128.
+",
+    NotAFunction = "E102" => r"
+A value which is not a function was called.
+
+    x = 1;
+    y = x(2);
+",
+    UnexpectedArgument = "E103" => r"
+A call passed an argument the function does not take, or passed more arguments than it
+takes.
+
+    f x { x }
+    y = f[z=1];
+
+Entrypoints such as `main` must take exactly the arguments the runtime provides.
+",
+    MissingArgument = "E104" => r"
+A call did not pass an argument which has no default value.
+
+    f x, y { x + y }
+    z = f(1);
+
+Either pass the argument or give it a default, `f x, y=0 { x + y }`.
+",
+    ArgumentTypeMismatch = "E105" => r"
+A function which cannot be made generic was called with a different argument type than it
+was called with before. Closures are checked against their first use.
+
+    g = \x { x };
+    a = g(1);
+    b = g(true);
+",
+    AnnotationMismatch = "E106" => r"
+A function's type annotations do not match how it is used or what its body returns.
+
+    f x: Number -> Boolean { x * 2 }
+
+The annotation is checked at the definition, so the error points there rather than at a
+distant call.
+",
+    AmbiguousType = "E107" => r"
+A type could not be inferred, usually because a function only ever returns the result of
+calling itself.
+
+    f x { f(x) }
+    y = f(1);
+
+Give the function a base case which returns a value, or annotate its return type.
+",
+    UndeterminedType = "E108" => r"
+The type of an expression could not be determined because of an earlier error. Fixing the
+errors reported before this one will usually resolve it.
+",
+    RedefinedFunction = "E109" => r"
+A variable is assigned in the same scope as a function of the same name, replacing it.
+
+    f x { x * 2 }
+    f = 3;
+
+Assigning to the name inside another function or block only shadows it there and is
+allowed. The original definition is shown in a note.
+",
+    ConflictingDefine = "E110" => r"
+A constant given with `--define` is also defined by the program.
+
+    synthizer write patch.syn out.wav --define voices=8
+
+    voices = 4;
+
+Remove the definition from the source, or define a different name on the command line.
+",
+    AssertionFailed = "E111" => r#"
+The condition of an `assert` is known without running the program, and it is false.
+
+    voices = 0;
+    main time { assert(voices > 0, "at least one voice is needed"); tone(time) }
+
+Conditions made of constants, and of variables assigned constant expressions, are checked
+while compiling. Other asserts are checked by `synthizer test`, or while rendering with
+`--checked`.
+"#,
+    RuntimeString = "E112" => r#"
+A string was used where its value would only be known while the program runs.
+
+    name = if time > 1 { "late" } else { "early" };
+
+Strings name things such as probes and tests, and only exist while compiling. They can be
+assigned to variables and passed to functions, but not compared, and a conditional can only
+choose between strings when its condition is a constant.
+"#,
+    UnboundInput = "E113" => r"
+An argument of the entrypoint has no value to be given when the program runs.
+
+    main time, cutoff { lowpass(saw(time), cutoff) }
+
+The arguments `time`, `sample_rate`, `beat` and `bar` are provided by the runtime. Any other
+argument is a parameter which the host can change while the program runs, and needs a
+constant default such as `cutoff = 1000` to start from.
+",
+    EmptyArray = "E114" => r"
+An array literal has no elements.
+
+    notes = [];
+
+Arrays hold at least one number, so that stepping through or choosing from one always
+gives a value.
+",
+    BuiltinValue = "E115" => r"
+A builtin which can only be called directly was used some other way.
+
+    step = seq;
+    main time { step(clock(4), [60, 62, 64]) }
+
+Builtins such as `seq` and `gate`, which accept arrays of any length, and `scale` and `chord`,
+which look up names at runtime, cannot be stored in variables or passed to functions. Call them
+where they are needed instead.
+",
+    UnknownPitchName = "E116" => r#"
+A scale or chord was named by a string the runtime does not know.
+
+    main time { mtof(scale(2, "minr", 60)) }
+
+The known scales are `major`, `minor`, `harmonic_minor`, `melodic_minor`, `dorian`, `phrygian`,
+`lydian`, `mixolydian`, `locrian`, `pentatonic`, `minor_pentatonic`, `blues`, `whole_tone` and
+`chromatic`. The known chords are `maj`, `min`, `dim`, `aug`, `sus2`, `sus4`, `maj7`, `min7`,
+`7`, `dim7` and `min7b5`.
+"#,
+    ChannelMismatch = "E117" => r"
+Signals with different numbers of channels were mixed.
+
+    main time { pan(sin(time * 2000), -0.5) + sin(time * 1000) }
+
+Adding a mono signal to a stereo one would leave it unclear where it should be heard, so it
+must be made stereo first, for example with `pan(signal, 0)` to place it in the center or
+`stereo(left, right)` to give each channel its own expression. A number may still scale
+every channel of a signal, as in `pan(signal, 0) * 0.5`.
+",
+    UnknownBus = "E118" => r#"
+A signal was sent to a bus which is not defined.
+
+    bus "reverb" input { reverb(input, 0.8, 0.5, 1) }
+    main time { s = sin(time * 2000); send("reverbb", s * 0.3); s }
+
+Buses are named by strings, and `send` must name one defined with `bus` somewhere in the
+program.
+"#,
+    BusCycle = "E119" => r#"
+Buses send to each other in a cycle.
+
+    bus "a" input { send("b", input); input }
+    bus "b" input { send("a", input * 0.5); input }
+
+Each bus is processed once per sample, after every bus which sends to it, so a cycle has no
+order to process its buses in. Feedback between buses can be made inside a single bus with a
+delay instead.
+"#,
+    InvalidBus = "E120" => r#"
+A bus was defined more than once, or without exactly one argument.
+
+    bus "reverb" { reverb(0, 0.8, 0.5, 1) }
+
+A bus is a function of the signals sent to it, which it takes as its only argument, as in
+`bus "reverb" input { reverb(input, 0.8, 0.5, 1) }`. Its name must not be used by another bus.
+"#,
+    InvalidOversampling = "E121" => r"
+An expression was oversampled by a factor which is not supported.
+
+    main time { @oversample(1.5) softclip(sin(time * 2000), 10) }
+
+The factor of `@oversample` must be written as a whole number from 2 to 16. Factors of 2 or 4
+are usually enough to keep distortion from aliasing audibly.
+",
+    InvalidBuffer = "E122" => r#"
+A buffer was declared more than once, with a length which is not supported, or from a file
+which could not be loaded.
+
+    buffer "loop" 0;
+
+A buffer is declared with its name and its length in seconds, as in `buffer "loop" 2;`, which
+must be more than 0 and at most 600, or with a WAV file to load, as in
+`buffer "drums" "drums.wav";`. Its name must not be used by another buffer.
+"#,
+    UnknownBuffer = "E123" => r#"
+A buffer was recorded to or played which is not declared.
+
+    buffer "loop" 2;
+    main time { playbuf("lop", 0, 1) }
+
+Buffers are allocated before a program starts to play, so `record` and `playbuf` must name one
+declared with `buffer` somewhere in the program.
+"#,
+    NestedVoice = "E124" => r"
+A voice block was written inside another.
+
+    main time { voice { voice { sin(time * note_freq) } } }
+
+The body of a voice block is already evaluated once for each voice, with `note_freq`,
+`note_gate` and `note_velocity` set to the note it plays, so there is nothing for a second
+block to add.
+",
+    UnknownArpMode = "E125" => r#"
+An arpeggiator was given a mode the runtime does not know.
+
+    main time { mtof(arp([60, 64, 67], clock(8), "upp")) }
+
+The known modes are `up`, `down`, `updown`, `order` and `random`.
+"#,
+    InvalidImpulse = "E126" => r#"
+The impulse response of a `convolve` could not be loaded. It must be a WAV file named by a
+constant string, relative to the directory of the main file, since it is loaded while compiling.
+
+    main time { convolve(saw(110), "no such room.wav") }
+"#,
+    PossiblyMissingArgument = "E127" => r"
+A call may reach a function which needs an argument the call leaves out. This happens when the
+function is chosen by a conditional, and the call is written for one which has a default for
+the argument.
+
+    loud x, gain = 2 { x * gain }
+    quiet x, gain { x * gain }
+    main time { f = if time > 1 { loud } else { quiet }; f(sin(time)) }
+
+Both the call and the branch the other function comes from are shown. Pass the argument in the
+call, or give every function which may be called a default for it.
+",
+    BranchTypeMismatch = "E128" => r"
+The two branches of a conditional have different types, so the conditional has no single type
+to give its value. Both branches are shown with the type of each.
+
+    level = if loud { 1 } else { false };
+    out = if wide { pan(saw(110), 0) } else { saw(110) };
+
+A number and a boolean cannot be mixed, and neither can signals with different numbers of
+channels: make the mono branch stereo as well, for example with `pan(signal, 0)`.
+",
+    InvalidTempo = "E129" => r#"
+A tempo map was declared more than once, with a tempo which is not supported, or from a file
+which could not be loaded.
+
+    tempo 0: 120, 8: 0;
+
+A tempo map is a list of times in seconds with the tempo in beats per minute at each, as in
+`tempo 0: 120, 30: 90;`, or a JSON file of such points, as in `tempo "song.json";`. Times must
+not be negative and tempos must be more than 0.
+"#,
+    InvalidArrangement = "E130" => r"
+An arrangement has no sections, its sections are not in order of the bar they start at, or its
+fade is longer than one of them.
+
+    arrange song fade 2bar { 0bar: intro; 8bar: verse; 9bar: drop; }
+
+Each section is a function of `time` written after the bar it starts at, as in `8bar: verse;`.
+A fade overlaps each section with the one before it for that many bars, so it must be no longer
+than the shortest section.
+",
+    InvalidTransition = "E131" => r"
+A call of `xfade` or `switch` is not written as they need to be.
+
+    xfade(a, b);
+    switch(index, intro, 50);
+
+`xfade(a, b, t)` takes the two functions to mix and how far to mix from the first to the second.
+`switch(selector, [fns], fade_ms)` takes its functions as an array literal. Both are given their
+arguments in order.
+",
+    ScriptWithEntrypoint = "E132" => r"
+A file ends with an expression, which would become the body of the entrypoint, but the
+entrypoint is also defined. Both the expression and the definition are shown.
+
+    main time { sin(time * 440) }
+    saw(220)
+
+A file which is just an expression, or ends with one after its other items, is run as if the
+expression were written `main time { ... }`. Remove the expression or the definition.
+",
+    ImplicitTolerance = "E133" => r"
+An approximate comparison is used with `--strict`, which requires the tolerance to be written out.
+
+    gate = 1 if x ~= 0.5 else 0;
+
+`a ~= b` is true when `a` and `b` differ by at most 1e-6, which may be too little or too much for
+the values being compared. Give the tolerance with `within`:
+
+    gate = 1 if x ~= 0.5 within 0.01 else 0;
+",
+    InvalidVersion = "E134" => r"
+A `#version` pragma names a version of the language this compiler does not know, has no number,
+or does not come first in its file.
+
+    #version 9
+    main time { 0 }
+
+A file starting with `#version 2` is read as version 2 of the language, in which `and`, `or` and
+`not` are keywords standing for `&&`, `||` and `!`. Files without the pragma are read as version 1.
+",
+    InvalidRecord = "E135" => r"
+A record gives a field twice, or a field is read which the record does not have, or from a value
+which is not a record.
+
+    note = {freq: 440, freq: 220};
+    amp = note.amp;
+    lp, peak = svf(x, 800, 1);
+
+A record is written `{name: value, ...}` and its fields are read as `record.name`, or several at
+once as `a, b = record;`, which assigns each name the field of the same name. Which fields a
+record has is known while compiling, and every value passed for the same argument or returned
+from the same function must have the same fields.
+",
+    InvalidMatrix = "E136" => r"
+The arrays and signals given to a builtin for vectors and matrices do not fit together.
+
+    matmul([1, 0, 0, 1], channels([a, b, c]));
+    dot(stereo(a, b), channels([a, b, c]));
+
+A matrix is given row after row, so one applied to a signal of n channels has n * n elements, and
+matrices are at most 8 by 8. The ambisonic builtins take signals of 4 channels, as `foa` makes.
+",
+    NestedFft = "E137" => r"
+An `@fft` section was written in the body of another.
+
+    main time { @fft(saw(110)) @fft(bin_mag) polar(bin_mag, bin_phase) }
+
+The body of a section is evaluated once for each bin, with `bin_mag`, `bin_phase` and `bin_freq`
+set to that bin, so a section inside it would have no bins of its own. Its input may be another
+section, as in `@fft(@fft(x) ...) ...`.
+",
+    ShadowedFunction = "W001" => r"
+A function definition has the same name as an earlier one, which it replaces from here on.
+The earlier definition is shown in a note.
+
+    f { 1 }
+    f { 2 }
+",
+    UnusedFunction = "W002" => r"
+A function is never called, so no code is generated for it.
+
+    f x { x * 2 }
+",
+    ReassignedType = "W003" => r"
+A variable is assigned a value of a different type than it held before.
+
+    x = 1;
+    x = true;
+",
+    UnusedVariable = "W004" => r"
+A variable is assigned but its value is never read.
+
+    f x { y = x * 2; x }
+
+Names starting with `_` are not reported.
+",
+    ApproxEqualConstants = "W005" => r"
+Both sides of an approximate comparison are constants, so the result never changes.
+
+    x = 0.1 + 0.2 ~= 0.3;
+
+Use `~=` to compare values computed while the program runs.
+",
+    ShadowedArgument = "W006" => r"
+An argument is assigned inside a block and then read after the block has ended, which reads the
+value the function was called with rather than the one assigned.
+
+    f x, gain { if x > 1 { gain = 0.5; 0 } else { 0 }; x * gain }
+
+An assignment only lasts until the end of the block it is in. Both the read and the assignment
+are shown. Assign the argument outside of the block to change it for the rest of the function.
+",
+    IndexOutOfRange = "W007" => r"
+The index of a `select` may be outside of the array it selects from.
+
+    main time { select(3, [0.5, 0.25, 1]) }
+    melody time { mtof(select(seq(clock(4), [0, 1, 2, 3]), [60, 62, 64])) }
+
+Indices start at 0, and are known while compiling when they are constants, or when they come from
+a `seq` or `choose` over an array of constants. An index outside of the array selects the nearest
+element, so the first or last one is repeated rather than silence being produced.
+",
+}
@@ -1,58 +1,340 @@
-use super::issue::{IssueTracker, Level};
-use super::tokens::{Token, SourcePos, Node};
-use super::ast::Root;
+use super::issue::{IssueTracker, Level, LintLevel};
+use super::codes::Code;
+use super::tokens::{Token, SourcePos, FileId, Node, Number};
+use super::ast::{Root, Expression};
 use super::types::{TypeTable, FunctionType};
 use super::ident::{Identifier, NameTable};
-use super::functions::{FunctionTable, CallStack};
+use super::functions::{Function, FunctionTable, CallStack, InstanceTable};
+use super::testing::TestCase;
+use super::bus::Bus;
+use super::buffer::Buffer;
+use super::dsp::convolve::Impulse;
+use super::dsp::interpolate::Interpolation;
+use super::runtime::Input;
+use super::tempo::TempoMap;
+use super::record::RecordTable;
 
 use std::cell::RefCell;
 use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::slice;
 use vec_map::VecMap;
 
 use llvm;
 use cbox::CBox;
 
-pub struct Context<'a> {
-    pub filename: String,
+/// Settings which affect how a program is compiled.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Instrument functions to record call counts and timings.
+    pub profile: bool,
+    /// Overrides the default level of lints, which is to warn.
+    pub lint_levels: HashMap<Code, LintLevel>,
+    /// Report every lint without an explicit level as an error.
+    pub deny_warnings: bool,
+    /// Report `~=` without `within` as an error, since its tolerance is hidden, and deny the
+    /// shadowing lints unless they are given another level. Conditions must be booleans either
+    /// way, as numbers are never taken to be true or false.
+    pub strict: bool,
+    /// Directories searched for files included by the program, in order.
+    pub include_paths: Vec<PathBuf>,
+    /// Compile `test` blocks, which are otherwise skipped.
+    pub test: bool,
+    /// Evaluate asserts while the program runs. Tests are always checked.
+    pub checked: bool,
+    /// Functions whose calls are logged with their arguments and result.
+    pub trace: Vec<String>,
+    /// The minimum number of samples between two logged calls of the same traced function.
+    pub trace_interval: u64,
+    /// Record the values given to variables and arguments in each sample, for the debugger.
+    pub debug: bool,
+    /// Treat identifiers which differ only in case as the same. Keywords are unaffected.
+    pub case_insensitive: bool,
+    /// The rate the program will run at, if it is known while compiling. `sample_rate` is then
+    /// a constant, and the program must not be run at any other rate.
+    pub sample_rate: Option<u32>,
+    /// How many samples a `@block` expression keeps its value for. Must be at least 1.
+    pub block_size: usize,
+    /// How many milliseconds a parameter of an entrypoint takes to follow a change, unless its
+    /// declaration says otherwise. Zero applies changes immediately.
+    pub param_smoothing: Number,
+    /// Use the portable math functions of the `math` module for the transcendental builtins and `^`,
+    /// so that they give the same results on every platform. Intrinsics such as filters and
+    /// oscillators still call the platform's math library.
+    pub deterministic: bool,
+    /// Use the polynomial approximations of the `fastmath` module for the transcendental builtins
+    /// and `^`. Their `_exact` versions are unaffected.
+    pub fast_math: bool,
+    /// Let pure functions which are called from several places remember their last result within
+    /// a sample. See memo.rs.
+    pub memoize: bool,
+    /// Evaluate the parts of a function which don't change between samples only when the
+    /// parameters they read change. See hoist.rs.
+    pub hoist: bool,
+    /// Functions of at most this many instructions are inlined into their callers, unless they
+    /// are marked `@noinline`. Zero turns inlining off.
+    pub inline_threshold: usize,
+    /// How intrinsics read between the samples of tables, buffers and delay lines, unless a call
+    /// says otherwise. See dsp/interpolate.rs.
+    pub interpolation: Interpolation,
+}
+
+impl Options {
+    pub fn new() -> Options {
+        Options {
+            profile: false,
+            lint_levels: HashMap::new(),
+            deny_warnings: false,
+            strict: false,
+            include_paths: Vec::new(),
+            test: false,
+            checked: false,
+            trace: Vec::new(),
+            trace_interval: 1000,
+            debug: false,
+            case_insensitive: false,
+            sample_rate: None,
+            block_size: 64,
+            param_smoothing: 0.0,
+            deterministic: false,
+            fast_math: false,
+            memoize: true,
+            hoist: true,
+            inline_threshold: 40,
+            interpolation: Interpolation::Linear,
+        }
+    }
+
+    pub fn lint_level(&self, code: Code) -> LintLevel {
+        match self.lint_levels.get(&code) {
+            Some(&level) => level,
+            None if self.deny_warnings => LintLevel::Deny,
+            None if self.strict && (code == Code::ShadowedFunction || code == Code::ShadowedArgument) =>
+                LintLevel::Deny,
+            None => LintLevel::Warn,
+        }
+    }
+}
+
+/// One of the files a program is made of.
+#[derive(Clone, Debug)]
+pub struct SourceFile {
+    pub name: String,
     pub source: String,
+    /// Where the file starts among the indices of every file, which SourcePos indices count from.
+    pub offset: usize,
+}
+
+/// The files a program is made of, in the order they are lexed, such as a prelude followed by the
+/// file given on the command line. Which one is the main file decides the name of the program.
+#[derive(Clone, Debug)]
+pub struct SourceFiles {
+    files: Vec<SourceFile>,
+    main: FileId,
+}
+
+impl SourceFiles {
+    pub fn new() -> SourceFiles {
+        SourceFiles {
+            files: Vec::new(),
+            main: 0,
+        }
+    }
+
+    pub fn add(&mut self, name: String, source: String) -> FileId {
+        // leave a gap between files, so the end of one is not the start of the next
+        let offset = self.files.last().map_or(0, |x| x.offset + x.source.len() + 1);
+        self.files.push(SourceFile {
+            name: name,
+            source: source,
+            offset: offset,
+        });
+        self.files.len() - 1
+    }
+
+    pub fn set_main(&mut self, file: FileId) {
+        self.main = file;
+    }
+    pub fn main(&self) -> FileId {
+        self.main
+    }
+
+    pub fn get(&self, file: FileId) -> &SourceFile {
+        &self.files[file]
+    }
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+    pub fn iter(&self) -> slice::Iter<SourceFile> {
+        self.files.iter()
+    }
+
+    /// The source from a position to the end of its file.
+    pub fn source_from(&self, pos: SourcePos) -> &str {
+        let file = self.get(pos.file);
+        &file.source[pos.index - file.offset..]
+    }
+    /// The source between two positions in the same file.
+    pub fn slice(&self, from: SourcePos, to: SourcePos) -> &str {
+        assert_eq!(from.file, to.file);
+        let file = self.get(from.file);
+        &file.source[from.index - file.offset..to.index - file.offset]
+    }
+    /// The whole line a position is on, without the line break.
+    pub fn line(&self, pos: SourcePos) -> &str {
+        let file = self.get(pos.file);
+        let line = &file.source[pos.line_index - file.offset..];
+        &line[..line.find(|c| c == '\n' || c == '\r').unwrap_or(line.len())]
+    }
+}
+
+pub struct Context<'a> {
+    pub files: SourceFiles,
     pub issues: RefCell<IssueTracker<'a>>,
     pub types: RefCell<TypeTable>,
     pub names: RefCell<NameTable<'a>>,
     pub functions: RefCell<FunctionTable>,
     pub tokens: RefCell<Vec<Node<Token>>>,
+    pub docs: RefCell<Vec<Node<String>>>, // `///` comments, in source order
+    pub strings: RefCell<Vec<String>>, // contents of string literals, from Token::Str
+    pub records: RefCell<RecordTable>, // the shape of every record, see record.rs
+    // infix operators on complex numbers, by the function they are in and their position
+    pub complex_ops: RefCell<HashSet<(Option<Identifier>, usize)>>,
     pub ast: RefCell<Root>,
     pub callstack: RefCell<CallStack>,
+    pub instances: RefCell<InstanceTable>,
     pub entrypoints: RefCell<VecMap<FunctionType>>,
+    pub inputs: RefCell<VecMap<Vec<Input>>>, // of entrypoints, in the order of their llvm arguments
+    pub tests: RefCell<Vec<TestCase>>,
+    pub buses: RefCell<Vec<Bus>>, // in the order they are processed, once typechecked
+    pub buffers: RefCell<Vec<Buffer>>,
+    pub tempo: RefCell<Option<TempoMap>>, // declared with `tempo`
+    pub script: RefCell<Option<Expression>>, // the trailing expression of a script
+    pub impulses: RefCell<Vec<Impulse>>, // loaded for `convolve`, by the string naming their file
+    pub probes: RefCell<Vec<String>>, // distinct names passed to `probe`, in source order
+    pub noinline: RefCell<HashSet<String>>, // names of the functions marked `@noinline`
     pub llvm: CBox<llvm::Context>,
+    pub options: RefCell<Options>,
 }
 
 impl<'a> Context<'a> {
     pub fn new(filename: String, source: String) -> Context<'a> {
+        let mut files = SourceFiles::new();
+        files.add(filename, source);
+        Context::with_files(files)
+    }
+
+    /// A context for a program made of several files.
+    pub fn with_files(files: SourceFiles) -> Context<'a> {
         Context {
-            filename: filename,
-            source: source,
+            files: files,
             issues: RefCell::new(IssueTracker::new()),
             types: RefCell::new(TypeTable::new()),
             names: RefCell::new(NameTable::new()),
             functions: RefCell::new(FunctionTable::new()),
             tokens: RefCell::new(Vec::new()),
+            docs: RefCell::new(Vec::new()),
+            strings: RefCell::new(Vec::new()),
+            records: RefCell::new(RecordTable::new()),
+            complex_ops: RefCell::new(HashSet::new()),
             ast: RefCell::new(Vec::new()),
             callstack: RefCell::new(CallStack::new()),
+            instances: RefCell::new(InstanceTable::new()),
             entrypoints: RefCell::new(VecMap::new()),
+            inputs: RefCell::new(VecMap::new()),
+            tests: RefCell::new(Vec::new()),
+            buses: RefCell::new(Vec::new()),
+            buffers: RefCell::new(Vec::new()),
+            tempo: RefCell::new(None),
+            script: RefCell::new(None),
+            impulses: RefCell::new(Vec::new()),
+            probes: RefCell::new(Vec::new()),
+            noinline: RefCell::new(HashSet::new()),
             llvm: llvm::Context::new(),
+            options: RefCell::new(Options::new()),
         }
     }
 
-    pub fn emit_error<T>(&'a self, msg: T, pos: SourcePos) where T: Into<Cow<'static, str>> {
-        self.issues.borrow_mut().new_issue(self, pos, Level::Error, msg);
+    /// The name of the main file.
+    pub fn filename(&self) -> &str {
+        &self.files.get(self.files.main()).name
     }
-    pub fn emit_warning<T>(&'a self, msg: T, pos: SourcePos) where T: Into<Cow<'static, str>> {
-        self.issues.borrow_mut().new_issue(self, pos, Level::Warning, msg);
+
+    /// A path named in the program, such as of a file to load. Unless it is absolute, it is
+    /// looked for in the directory of the main file and then in each of the include paths, and
+    /// is left relative to the main file if none of them has it.
+    pub fn resolve_path(&self, path: &str) -> PathBuf {
+        let local = match Path::new(self.filename()).parent() {
+            Some(dir) => dir.join(path),
+            None => PathBuf::from(path),
+        };
+        if local.exists() || Path::new(path).is_absolute() {
+            return local;
+        }
+        let options = self.options.borrow();
+        options.include_paths.iter().map(|dir| dir.join(path)).find(|x| x.exists()).unwrap_or(local)
+    }
+
+    pub fn emit_error<T>(&'a self, code: Code, msg: T, pos: SourcePos) where T: Into<Cow<'static, str>> {
+        self.issues.borrow_mut().new_issue(self, pos, Level::Error, code, msg);
+    }
+    pub fn emit_warning<T>(&'a self, code: Code, msg: T, pos: SourcePos) where T: Into<Cow<'static, str>> {
+        self.issues.borrow_mut().new_issue(self, pos, Level::Warning, code, msg);
+    }
+    /// Attaches a note to the error or warning emitted just before it.
+    pub fn emit_note<T>(&'a self, code: Code, msg: T, pos: SourcePos) where T: Into<Cow<'static, str>> {
+        self.issues.borrow_mut().new_issue(self, pos, Level::Note, code, msg);
+    }
+
+    /// Attaches a suggestion for fixing it to the error or warning emitted just before it.
+    pub fn emit_help<T>(&'a self, msg: T) where T: Into<Cow<'static, str>> {
+        self.issues.borrow_mut().add_child(Level::Help, msg);
     }
 
     pub fn lookup_name(&'a self, id: Identifier) -> String {
-        self.names.borrow().get_name(id).unwrap().into()
+        self.names.borrow().name(id).into()
     }
+
+    /// Checks if the identifier refers to a builtin such as `assert` or `probe`, rather than a
+    /// function defined under the same name.
+    pub fn is_builtin(&'a self, id: Identifier, name: &str) -> bool {
+        match self.functions.borrow().get(id) {
+            Some(&Function::Pointer(_)) => self.names.borrow().get_name(id) == Some(name),
+            _ => false,
+        }
+    }
+
+    /// Checks if the identifier refers to a builtin which can only be called directly, such as
+    /// `seq`.
+    pub fn is_direct_builtin(&'a self, id: Identifier) -> bool {
+        match self.functions.borrow().get(id) {
+            Some(&Function::Pointer(ref def)) => def.direct,
+            _ => false,
+        }
+    }
+}
+
+/// The name shown in diagnostics for source read from standard input.
+pub const STDIN_NAME: &'static str = "<stdin>";
+
+/// Reads a source file, or standard input if the filename is `-`.
+pub fn read_source(filename: &str) -> Result<String, String> {
+    use std::io::{self, Read};
+
+    if filename != "-" {
+        return read_file(filename);
+    }
+    let mut code = String::new();
+    match io::stdin().read_to_string(&mut code) {
+        Err(why) => Err(format!("couldn't read standard input: {}", why)),
+        Ok(_) => Ok(code),
+    }
+}
+
+/// The name shown in diagnostics for a source file given on the command line.
+pub fn display_name(filename: &str) -> String {
+    if filename == "-" { STDIN_NAME.to_string() } else { filename.to_string() }
 }
 
 pub fn read_file(filename: &str) -> Result<String, String> {
@@ -1,23 +1,41 @@
 use super::common::Context;
-use super::codegen::{CodeGenerator, GLOBAL_INIT_FN_NAME};
+use super::codegen::{CodeGenerator, GLOBAL_INIT_FN_NAME, BUILTIN_VARIABLES};
 use super::lexer::lex;
 use super::parser::parse;
 use super::typecheck::typecheck;
+use super::flow::check_flow;
 use super::types::{Type, FunctionType};
-use super::functions::{ExternalFunction, PointerFunction, Function};
+use super::functions::{ExternalFunction, PointerFunction, IntrinsicFunction, UserFunction, Function};
+use super::runtime::{self, State, TraceLabel, DebugLabel, Input};
+use super::dsp;
+use super::int;
+use super::math;
+use super::bus;
+use super::buffer;
+use super::dsp::table::Table;
+use super::dsp::interpolate;
 use super::issue::IssueTracker;
 use super::ast;
-use super::tokens::{Number, SourcePos, Node};
+use super::tokens::{Number, SourcePos, Node, NodeImpl, Operator};
+use super::ident::Identifier;
+use super::source_map::SourceMap;
+use super::codes::Code;
 
 use llvm;
 use llvm::ExecutionEngine;
+use vec_map::VecMap;
+use std::cell::RefCell;
 use std::mem;
+use std::rc::Rc;
 
 pub struct Compiler<'a> {
     ctxt: &'a Context<'a>,
     codegen: Option<CodeGenerator<'a>>,
     engine: Option<llvm::JitEngine<'a>>,
     stage: Stage,
+    tables: RefCell<Vec<Table>>,
+    defines: RefCell<Vec<Identifier>>,
+    declared: RefCell<Vec<Identifier>>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -36,9 +54,16 @@ impl<'a> Compiler<'a> {
             codegen: None,
             engine: None,
             stage: Stage::Lex,
+            tables: RefCell::new(Vec::new()),
+            defines: RefCell::new(Vec::new()),
+            declared: RefCell::new(Vec::new()),
         }
     }
 
+    pub fn context(&self) -> &'a Context<'a> {
+        self.ctxt
+    }
+
     pub fn compile(&mut self) -> Result<IssueTracker<'a>, IssueTracker<'a>> {
         // front end
         self.define_intrinsics();
@@ -60,6 +85,8 @@ impl<'a> Compiler<'a> {
     pub fn lex(&mut self) -> bool {
         assert_eq!(self.stage, Stage::Lex);
         self.stage = Stage::Parse;
+        let case_insensitive = self.ctxt.options.borrow().case_insensitive;
+        self.ctxt.names.borrow_mut().set_case_insensitive(case_insensitive);
         lex(self.ctxt);
         return if self.ctxt.issues.borrow().has_errors() {
             false
@@ -80,7 +107,13 @@ impl<'a> Compiler<'a> {
     }
     pub fn typecheck(&mut self) -> bool {
         assert_eq!(self.stage, Stage::Typecheck);
+        self.check_defines();
+        self.wrap_script();
+        self.bind_entrypoints();
         typecheck(self.ctxt);
+        if !self.ctxt.issues.borrow().has_errors() {
+            check_flow(self.ctxt);
+        }
         return if self.ctxt.issues.borrow().has_errors() {
             false
         } else {
@@ -121,6 +154,54 @@ impl<'a> Compiler<'a> {
         self.ctxt.types.borrow_mut().set_val(id, 0, ty);
     }
 
+    /// Makes a host function callable from synthizer code under the given name. All arguments and
+    /// the return value must be of type `Number`. The function may keep memory between calls
+    /// through the `State`; each call in the source gets its own memory.
+    pub fn register_intrinsic<F>(&self, name: &'static str, ty: FunctionType, func: F)
+            where F: Fn(&[Number], &mut State) -> Number + Send + Sync + 'static {
+        assert_eq!(self.stage, Stage::Lex);
+        assert!(ty.returns == Type::Number && ty.args.values().all(|x| *x == Type::Number),
+                "intrinsics may only take and return numbers");
+        let id = self.ctxt.names.borrow_mut().new_id(name);
+        let func = Function::Intrinsic(IntrinsicFunction::new(ty, Box::new(func)));
+        let ty = Type::Function(id);
+        self.ctxt.functions.borrow_mut().insert(id, func);
+        self.ctxt.types.borrow_mut().set_val(id, 0, ty);
+    }
+
+    /// Defines a native function taking the named `Number` arguments and returning a `Number`.
+    /// Unlike register_intrinsic, arguments are passed and called positionally in the order given.
+    pub fn define_native_function<F>(&self, name: &'static str, args: &[&'static str], func: F)
+            where F: Fn(&[Number], &mut State) -> Number + Send + Sync + 'static {
+        assert_eq!(self.stage, Stage::Lex);
+        let mut arg_map = VecMap::new();
+        let mut order = Vec::new();
+        for arg in args {
+            let arg_id = self.ctxt.names.borrow_mut().new_id(arg);
+            arg_map.insert(arg_id, Type::Number);
+            order.push(arg_id);
+        }
+        let ty = FunctionType::with_order(arg_map, order.clone(), Type::Number);
+        let id = self.ctxt.names.borrow_mut().new_id(name);
+        let func = Function::Intrinsic(IntrinsicFunction::with_order(ty, order, Box::new(func)));
+        self.ctxt.functions.borrow_mut().insert(id, func);
+        self.ctxt.types.borrow_mut().set_val(id, 0, Type::Function(id));
+    }
+
+    /// Defines a lookup table for use with intrinsics such as `waveshape`. The table is referred
+    /// to in source by a global constant with the given name. Tables must be defined before the
+    /// intrinsics are.
+    pub fn define_table(&self, name: &'static str, table: Table) {
+        let mut tables = self.tables.borrow_mut();
+        self.define_global_constant(name, tables.len() as Number);
+        tables.push(table);
+    }
+
+    /// All tables defined so far, indexed by the value of their constants.
+    pub fn tables(&self) -> Vec<Table> {
+        self.tables.borrow().clone()
+    }
+
     pub fn define_global_constant(&self, name: &'static str, value: Number) {
         // must be done before typecheck
         let id = self.ctxt.names.borrow_mut().new_id(name);
@@ -128,20 +209,60 @@ impl<'a> Compiler<'a> {
         self.ctxt.ast.borrow_mut().insert(0, ast::Item::Assignment(Node(ast::Assignment {
             ident: Node(id, SourcePos::anon()),
             expr: ast::Expression::Constant(Node(value, SourcePos::anon())),
+            docs: None,
         }, SourcePos::anon())));
     }
 
+    /// Defines a constant given by the user, such as with `--define` on the command line. Unlike
+    /// global constants, the program may not define the name itself.
+    pub fn define_constant(&self, name: &'a str, value: Number) {
+        let id = self.ctxt.names.borrow_mut().new_id(name);
+        assert!(self.stage != Stage::Complete && self.stage != Stage::Codegen);
+        self.defines.borrow_mut().push(id);
+        self.ctxt.ast.borrow_mut().insert(0, ast::Item::Assignment(Node(ast::Assignment {
+            ident: Node(id, SourcePos::anon()),
+            expr: ast::Expression::Constant(Node(value, SourcePos::anon())),
+            docs: None,
+        }, SourcePos::anon())));
+    }
+
+    fn check_defines(&self) {
+        let defines = self.defines.borrow();
+        for item in self.ctxt.ast.borrow().iter() {
+            let (id, pos) = match *item {
+                ast::Item::Assignment(ref x) => (x.ident(), x.pos()),
+                ast::Item::FunctionDef(ref x) => (x.ident(), x.pos()),
+            };
+            if !pos.is_anon() && defines.contains(&id) {
+                self.ctxt.emit_error(Code::ConflictingDefine,
+                                     format!("`{}` is defined on the command line and in the source",
+                                             self.ctxt.lookup_name(id)),
+                                     pos);
+            }
+        }
+    }
+
     pub fn define_intrinsics(&self) {
         let num_num_ty = &make_fn_ty!(self.ctxt, fn(x: Number) -> Number);
         let num_2num_ty = &make_fn_ty!(self.ctxt, fn(x: Number, y: Number) -> Number);
 
-        self.define_external_function("sin", "llvm.sin.f64", num_num_ty.clone());
-        self.define_external_function("cos", "llvm.cos.f64", num_num_ty.clone());
-        self.define_external_function("log", "llvm.log.f64", num_num_ty.clone());
-        self.define_external_function("log10", "llvm.log10.f64", num_num_ty.clone());
-        self.define_external_function("log2", "llvm.log2.f64", num_num_ty.clone());
-        self.define_external_function("exp", "llvm.exp.f64", num_num_ty.clone());
-        self.define_external_function("exp2", "llvm.exp2.f64", num_num_ty.clone());
+        unsafe {
+            self.define_math_function("sin", "llvm.sin.f64", num_num_ty, math::sin as *mut (),
+                                      Some(("sin_exact", "*fastmath*sin")));
+            self.define_math_function("cos", "llvm.cos.f64", num_num_ty, math::cos as *mut (),
+                                      Some(("cos_exact", "*fastmath*cos")));
+            self.define_math_function("log", "llvm.log.f64", num_num_ty, math::log as *mut (),
+                                      Some(("log_exact", "*fastmath*log")));
+            self.define_math_function("log10", "llvm.log10.f64", num_num_ty, math::log10 as *mut (), None);
+            self.define_math_function("log2", "llvm.log2.f64", num_num_ty, math::log2 as *mut (),
+                                      Some(("log2_exact", "*fastmath*log2")));
+            self.define_math_function("exp", "llvm.exp.f64", num_num_ty, math::exp as *mut (),
+                                      Some(("exp_exact", "*fastmath*exp")));
+            self.define_math_function("exp2", "llvm.exp2.f64", num_num_ty, math::exp2 as *mut (),
+                                      Some(("exp2_exact", "*fastmath*exp2")));
+            self.define_math_function("pow", "llvm.pow.f64", num_2num_ty, math::pow as *mut (),
+                                      Some(("pow_exact", "*fastmath*pow")));
+        }
         self.define_external_function("sqrt", "llvm.sqrt.f64", num_num_ty.clone());
         self.define_external_function("abs", "llvm.fabs.f64", num_num_ty.clone());
         self.define_external_function("floor", "llvm.floor.f64", num_num_ty.clone());
@@ -149,9 +270,179 @@ impl<'a> Compiler<'a> {
         self.define_external_function("trunc", "llvm.trunc.f64", num_num_ty.clone());
         self.define_external_function("round", "llvm.round.f64", num_num_ty.clone());
 
-        self.define_external_function("pow", "llvm.pow.f64", num_2num_ty.clone());
         self.define_external_function("min", "llvm.minnum.f64", num_2num_ty.clone());
         self.define_external_function("max", "llvm.maxnum.f64", num_2num_ty.clone());
+
+        self.define_builtin_variables();
+        self.define_assert();
+        self.define_probe();
+        self.define_send();
+        self.define_buffers();
+        self.define_sequencers();
+        int::define_intrinsics(self);
+        dsp::define_intrinsics(self);
+    }
+
+    // Defines a transcendental builtin as the platform's version, or the portable one of the `math`
+    // module in deterministic mode. One with a fast version also gets a `_exact` twin, so that a
+    // call site can keep the exact result when the plain name is swapped for the fast version,
+    // whose symbol names the function codegen generates for it. See fastmath.rs.
+    unsafe fn define_math_function(&self, name: &'static str, symbol: &'static str, ty: &FunctionType,
+                                   portable: *mut (), fast: Option<(&'static str, &'static str)>) {
+        let (deterministic, fast_math) = {
+            let options = self.ctxt.options.borrow();
+            (options.deterministic, options.fast_math)
+        };
+        let define_exact = |name: &'static str| if deterministic {
+            self.define_pointer_function(name, ty.clone(), portable);
+        } else {
+            self.define_external_function(name, symbol, ty.clone());
+        };
+        match fast {
+            Some((exact, fast)) => {
+                define_exact(exact);
+                if fast_math {
+                    self.define_external_function(name, fast, ty.clone());
+                } else {
+                    define_exact(name);
+                }
+            }
+            None => define_exact(name),
+        }
+    }
+
+    fn define_builtin_variables(&self) {
+        assert_eq!(self.stage, Stage::Lex);
+        for &(name, _) in BUILTIN_VARIABLES {
+            let id = self.ctxt.names.borrow_mut().new_id(name);
+            self.ctxt.types.borrow_mut().set_val(id, 0, Type::Number);
+        }
+    }
+
+    // `assert` takes a condition and an optional message, which defaults to the empty string.
+    fn define_assert(&self) {
+        let ty = make_fn_ty!(self.ctxt, fn(cond: Boolean, message: Str) -> Number);
+        unsafe {
+            self.define_pointer_function("assert", ty, runtime::assert as *mut ());
+        }
+        let empty = {
+            let mut strings = self.ctxt.strings.borrow_mut();
+            strings.push(String::new());
+            strings.len() - 1
+        };
+        self.set_default_arg("assert", "message", ast::Expression::Str(Node(empty, SourcePos::anon())));
+    }
+
+    /// Gives an argument of a builtin function or intrinsic a default value, so that calls may
+    /// leave it out.
+    pub fn set_default_arg(&self, func: &str, arg: &str, value: ast::Expression) {
+        assert_eq!(self.stage, Stage::Lex);
+        let id = self.ctxt.names.borrow().get_id(func).unwrap();
+        let arg_id = self.ctxt.names.borrow().get_id(arg).unwrap();
+        let mut functions = self.ctxt.functions.borrow_mut();
+        let args = match functions.get_mut(id) {
+            Some(&mut Function::Pointer(ref mut def)) => &mut def.args,
+            Some(&mut Function::Intrinsic(ref mut def)) => &mut def.args,
+            _ => panic!("`{}` is not a builtin which can take defaults", func),
+        };
+        for x in args.iter_mut().filter(|x| x.ident() == Some(arg_id)) {
+            *x = ast::Argument::Assign(Node(arg_id, SourcePos::anon()), value.clone());
+        }
+    }
+
+    fn define_probe(&self) {
+        let ty = make_fn_ty!(self.ctxt, fn(name: Str, value: Number) -> Number);
+        unsafe {
+            self.define_pointer_function("probe", ty, runtime::probe as *mut ());
+        }
+    }
+
+    // `send` names its bus by a string, which is matched up with the buses of a program when it
+    // is created.
+    fn define_send(&self) {
+        unsafe {
+            self.define_direct_builtin("send", &[("bus", Type::Str), ("signal", Type::Number)],
+                                       Type::Number, bus::send as *mut ());
+        }
+    }
+
+    // `record` and `playbuf` name their buffer by a string, which is looked up among those the
+    // program declares.
+    fn define_buffers(&self) {
+        unsafe {
+            self.define_direct_builtin("record", &[("buffer", Type::Str), ("signal", Type::Number),
+                                                   ("trigger", Type::Number)],
+                                       Type::Number, buffer::record as *mut ());
+            self.define_direct_builtin("playbuf", &[("buffer", Type::Str), ("position", Type::Number),
+                                                    ("rate", Type::Number), ("interpolation", Type::Number)],
+                                       Type::Number, buffer::playbuf as *mut ());
+        }
+        interpolate::default_to_setting(self, "playbuf");
+    }
+
+    // `seq` and `gate` step through an array each time a clock fires, `choose` picks from one
+    // at random each time a trigger fires, and `select` picks the element at an index.
+    fn define_sequencers(&self) {
+        let args = [("clock", Type::Number), ("values", Type::Array(0))];
+        unsafe {
+            self.define_direct_builtin("seq", &args, Type::Number, runtime::seq as *mut ());
+            self.define_direct_builtin("gate", &args, Type::Number, runtime::gate as *mut ());
+            self.define_direct_builtin("choose", &[("trigger", Type::Number), ("values", Type::Array(0))],
+                                       Type::Number, runtime::choose as *mut ());
+            self.define_direct_builtin("select", &[("index", Type::Number), ("values", Type::Array(0))],
+                                       Type::Number, runtime::select as *mut ());
+        }
+    }
+
+    /// Defines a builtin which can only be called directly, and is passed its call site followed
+    /// by the arguments in the order given. Strings are passed as their index among the string
+    /// literals, and arrays and signals of several channels, which may be of any length, as a
+    /// pointer to their first element followed by their length. A builtin returning a number
+    /// returns it, and one returning several channels or an array is passed a pointer to write
+    /// them to after its arguments, and returns nothing.
+    pub unsafe fn define_direct_builtin(&self, name: &'static str, args: &[(&'static str, Type)], returns: Type,
+                                        ptr: *mut ()) {
+        let ids: Vec<Identifier> = args.iter().map(|&(arg, _)| self.ctxt.names.borrow_mut().new_id(arg)).collect();
+        let ty = FunctionType::with_order(ids.iter().zip(args.iter()).map(|(&id, &(_, ty))| (id, ty)).collect(),
+                                          ids.clone(), returns);
+        self.define_pointer_function(name, ty, ptr);
+        let id = self.ctxt.names.borrow().get_id(name).unwrap();
+        if let Some(&mut Function::Pointer(ref mut def)) = self.ctxt.functions.borrow_mut().get_mut(id) {
+            def.args = ids.iter().map(|&id| ast::Argument::Ident(Node(id, SourcePos::anon()))).collect();
+            def.direct = true;
+        }
+    }
+
+    /// Defines a direct builtin which returns a record of the given fields, such as a filter giving
+    /// each of its outputs. It writes them in the order given to a pointer passed last.
+    pub unsafe fn define_multi_output_builtin(&self, name: &'static str, args: &[(&'static str, Type)],
+                                              outputs: &[&'static str], ptr: *mut ()) {
+        let outputs: Vec<Identifier> = outputs.iter().map(|x| self.ctxt.names.borrow_mut().new_id(x)).collect();
+        let shape = self.ctxt.records.borrow_mut().intern(&outputs);
+        self.define_direct_builtin(name, args, Type::Record(shape), ptr);
+        let id = self.ctxt.names.borrow().get_id(name).unwrap();
+        if let Some(&mut Function::Pointer(ref mut def)) = self.ctxt.functions.borrow_mut().get_mut(id) {
+            def.outputs = outputs;
+        }
+    }
+
+    /// Defines a direct builtin which returns a signal of as many channels as the argument with the
+    /// given name has channels or elements, such as a matrix applied to a signal. It writes them to
+    /// a pointer passed last.
+    pub unsafe fn define_channel_builtin(&self, name: &'static str, args: &[(&'static str, Type)],
+                                         channels_of: &'static str, ptr: *mut ()) {
+        self.define_direct_builtin(name, args, Type::Channels(0), ptr);
+        let id = self.ctxt.names.borrow().get_id(name).unwrap();
+        let arg = self.ctxt.names.borrow_mut().new_id(channels_of);
+        if let Some(&mut Function::Pointer(ref mut def)) = self.ctxt.functions.borrow_mut().get_mut(id) {
+            def.channels_of = Some(arg);
+        }
+    }
+
+    /// Maps the generated code and each direct call to an intrinsic or builtin back to the
+    /// source. Only valid after codegen.
+    pub fn source_map(&self) -> SourceMap {
+        self.codegen.as_ref().unwrap().source_map()
     }
 
     /// Defines a function as externally accessible through get_fn after compilation
@@ -162,6 +453,195 @@ impl<'a> Compiler<'a> {
         self.ctxt.entrypoints.borrow_mut().insert(id, ty);
     }
 
+    /// Defines a function as externally accessible through Program, taking whichever arguments
+    /// its definition in the source declares. Each is bound to an Input when the program runs.
+    pub fn declare_entrypoint(&self, name: &'a str) {
+        assert!(self.stage != Stage::Complete && self.stage != Stage::Codegen);
+        let id = self.ctxt.names.borrow_mut().new_id(name);
+        self.declared.borrow_mut().push(id);
+    }
+
+    /// How each argument of an entrypoint given to declare_entrypoint() is bound, in the order
+    /// of its generated arguments. None for other entrypoints, and for ones missing from the
+    /// source.
+    pub fn entrypoint_inputs(&self, name: &str) -> Option<Vec<Input>> {
+        let id = match self.ctxt.names.borrow().get_id(name) {
+            Some(id) => id,
+            None => return None,
+        };
+        self.ctxt.inputs.borrow().get(id).cloned()
+    }
+
+    /// The number of channels of each sample of the entrypoint with the given name, which is more
+    /// than 1 when it returns a signal such as a stereo one.
+    pub fn entrypoint_channels(&self, name: &str) -> usize {
+        let id = match self.ctxt.names.borrow().get_id(name) {
+            Some(id) => id,
+            None => return 1,
+        };
+        let functions = self.ctxt.functions.borrow();
+        match functions.get(id).and_then(|x| x.ty()).map(|x| x.returns) {
+            Some(Type::Channels(count)) => count,
+            _ => 1,
+        }
+    }
+
+    /// The number of channels of the bus which gives the most, or 1 if there are no buses.
+    pub fn bus_channels(&self) -> usize {
+        let functions = self.ctxt.functions.borrow();
+        self.ctxt.buses.borrow().iter().map(|bus| {
+            match functions.get(bus.ident).and_then(|x| x.ty()).map(|x| x.returns) {
+                Some(Type::Channels(count)) => count,
+                _ => 1,
+            }
+        }).max().unwrap_or(1)
+    }
+
+    /// The names of the buses of the program, in the order they are processed.
+    pub fn bus_names(&self) -> Vec<String> {
+        self.ctxt.buses.borrow().iter().map(|x| x.name.clone()).collect()
+    }
+
+    // Makes the trailing expression of a script the body of the first declared entrypoint, which
+    // takes `time`, unless the program defines that function itself.
+    fn wrap_script(&self) {
+        let expr = match self.ctxt.script.borrow_mut().take() {
+            Some(expr) => expr,
+            None => return,
+        };
+        let id = match self.declared.borrow().first() {
+            Some(&id) => id,
+            None => return,
+        };
+        let pos = expr.pos();
+        let mut ast = self.ctxt.ast.borrow_mut();
+        let defined = ast.iter().filter_map(|item| match *item {
+            ast::Item::FunctionDef(ref def) if def.ident() == id => Some(def.ident_pos()),
+            _ => None,
+        }).last();
+        if let Some(defined) = defined {
+            let name = self.ctxt.lookup_name(id);
+            self.ctxt.emit_error(Code::ScriptWithEntrypoint,
+                                 format!("this expression would be the body of `{}`, which is already defined", name),
+                                 pos);
+            self.ctxt.emit_note(Code::ScriptWithEntrypoint, format!("`{}` is defined here", name), defined);
+            return;
+        }
+        let time = self.ctxt.names.borrow_mut().new_id("time");
+        let func = Node(ast::Function {
+            args: Node(vec![ast::Argument::Ident(Node(time, pos))], pos),
+            block: Rc::new(Node(vec![ast::Statement::Expression(expr)], pos)),
+            arg_types: vec![None],
+            returns: None,
+        }, pos);
+        self.ctxt.functions.borrow_mut().insert(id, Function::User(UserFunction {
+            ty: None,
+            node: func.clone(),
+        }));
+        ast.push(ast::Item::FunctionDef(Node(ast::FunctionDef {
+            ident: Node(id, pos),
+            func: func,
+            docs: None,
+        }, pos)));
+    }
+
+    // Gives each declared entrypoint found in the source a type from its arguments, which must
+    // all be numbers.
+    fn bind_entrypoints(&self) {
+        for &id in self.declared.borrow().iter() {
+            let ast = self.ctxt.ast.borrow();
+            let def = ast.iter().filter_map(|item| match *item {
+                ast::Item::FunctionDef(ref def) if def.ident() == id => Some(def),
+                _ => None,
+            }).last();
+            let def = match def {
+                Some(def) => def,
+                None => continue,
+            };
+            let mut args: Vec<&ast::Argument> = def.args().iter().collect();
+            args.sort_by(|a, b| a.ident().cmp(&b.ident()));
+            let mut arg_map = VecMap::new();
+            let mut inputs = Vec::new();
+            for arg in args {
+                arg_map.insert(arg.ident().unwrap(), Type::Number);
+                match self.bind_input(arg) {
+                    Some(input) => inputs.push(input),
+                    None => {
+                        let name = self.ctxt.lookup_name(arg.ident().unwrap());
+                        self.ctxt.emit_error(Code::UnboundInput,
+                                             format!("argument `{}` of entrypoint `{}` has no value to be given",
+                                                     name, self.ctxt.lookup_name(id)),
+                                             arg.pos());
+                        self.ctxt.emit_help(format!("give it a constant default, such as `{} = 0`, to make it a parameter",
+                                                    name));
+                    }
+                }
+            }
+            self.ctxt.entrypoints.borrow_mut().insert(id, FunctionType::new(arg_map, Type::Number));
+            self.ctxt.inputs.borrow_mut().insert(id, inputs);
+        }
+    }
+
+    fn bind_input(&self, arg: &ast::Argument) -> Option<Input> {
+        let name = self.ctxt.lookup_name(arg.ident().unwrap());
+        Some(match &name[..] {
+            "time" => Input::Time,
+            "sample_rate" => Input::SampleRate,
+            "beat" => Input::Beat,
+            "bar" => Input::Bar,
+            _ => match *arg {
+                ast::Argument::Assign(_, ref expr) => match constant_value(expr) {
+                    Some(default) => Input::Param(name, default, self.ctxt.options.borrow().param_smoothing),
+                    None => match self.smoothed_value(expr) {
+                        Some((default, time_ms)) => Input::Param(name, default, time_ms),
+                        None => return None,
+                    },
+                },
+                _ => return None,
+            },
+        })
+    }
+
+    // A default which calls the `smooth` builtin with constants gives a parameter its own
+    // smoothing time. The parameter is then smoothed by the program rather than by the call.
+    fn smoothed_value(&self, expr: &ast::Expression) -> Option<(Number, Number)> {
+        let call = match *expr {
+            ast::Expression::FunctionCall(ref call) => call,
+            _ => return None,
+        };
+        let id = match *call.callee() {
+            ast::Expression::Variable(Node(id, _)) => id,
+            _ => return None,
+        };
+        // the builtin rather than a function of the program which shadows it, whose arguments
+        // are its value and time in the order they were declared
+        let params: Vec<Identifier> = match self.ctxt.functions.borrow().get(id) {
+            Some(&Function::Intrinsic(ref def)) if self.ctxt.names.borrow().get_name(id) == Some("smooth") => {
+                def.args.iter().filter_map(|x| x.ident()).collect()
+            }
+            _ => return None,
+        };
+        let mut values = [None, None];
+        for (i, arg) in call.args().iter().enumerate() {
+            let (index, expr) = match *arg {
+                ast::Argument::Expr(ref expr) => (i, expr),
+                ast::Argument::Assign(Node(id, _), ref expr) => match params.iter().position(|&x| x == id) {
+                    Some(index) => (index, expr),
+                    None => return None,
+                },
+                _ => return None,
+            };
+            match values.get_mut(index) {
+                Some(value) => *value = constant_value(expr),
+                None => return None,
+            }
+        }
+        match (values[0], values[1]) {
+            (Some(default), Some(time_ms)) => Some((default, time_ms)),
+            _ => None,
+        }
+    }
+
     pub unsafe fn get_fn<A, R>(&self, name: &str) -> Option<extern fn(A) -> R> {
         assert_eq!(self.stage, Stage::Complete);
         match self.codegen.as_ref().unwrap().module.get_function(name) {
@@ -172,9 +652,40 @@ impl<'a> Compiler<'a> {
         }
     }
 
+    /// The address of a global variable of the generated code, if there is one with the name.
+    pub unsafe fn get_global<T>(&self, name: &str) -> Option<*mut T> {
+        assert_eq!(self.stage, Stage::Complete);
+        match self.codegen.as_ref().unwrap().module.get_global(name) {
+            Some(global) => Some(self.engine.as_ref().unwrap().get_global::<T>(global) as *const T as *mut T),
+            None => None,
+        }
+    }
+
+    /// Names of the functions instrumented for profiling. Only valid after codegen.
+    pub fn profile_labels(&self) -> Vec<String> {
+        self.codegen.as_ref().unwrap().profile_labels()
+    }
+
+    pub fn trace_labels(&self) -> Vec<TraceLabel> {
+        self.codegen.as_ref().unwrap().trace_labels()
+    }
+
+    pub fn debug_labels(&self) -> Vec<DebugLabel> {
+        self.codegen.as_ref().unwrap().debug_labels()
+    }
+
     pub fn get_init_fn(&self) -> extern fn(()) {
         unsafe {
             self.get_fn(GLOBAL_INIT_FN_NAME).unwrap()
         }
     }
 }
+
+// The value of a number literal, which may be negated.
+fn constant_value(expr: &ast::Expression) -> Option<Number> {
+    match *expr {
+        ast::Expression::Constant(Node(x, _)) => Some(x),
+        ast::Expression::Prefix(ref x) if x.op() == Operator::Sub => constant_value(x.expr()).map(|x| -x),
+        _ => None,
+    }
+}
@@ -0,0 +1,296 @@
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    PowerShell,
+}
+
+impl Shell {
+    pub fn parse(s: &str) -> Option<Shell> {
+        Some(match s {
+            "bash" => Shell::Bash,
+            "zsh" => Shell::Zsh,
+            "fish" => Shell::Fish,
+            "powershell" => Shell::PowerShell,
+            _ => return None,
+        })
+    }
+}
+
+/// A flag from the options section of a usage message.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Flag {
+    pub long: String,
+    pub short: Option<char>,
+    pub takes_value: bool,
+    pub description: String,
+}
+
+/// A subcommand along with the flags which may be passed to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Subcommand {
+    pub name: String,
+    pub flags: Vec<String>, // long names, without the leading dashes
+    pub takes_files: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommandSpec {
+    pub program: String,
+    pub subcommands: Vec<Subcommand>,
+    pub flags: Vec<Flag>,
+}
+
+impl CommandSpec {
+    /// Parses the `Usage:` and `Options:` sections of a docopt usage message.
+    pub fn parse(usage: &str) -> CommandSpec {
+        let mut spec = CommandSpec {
+            program: String::new(),
+            subcommands: Vec::new(),
+            flags: Vec::new(),
+        };
+        let mut section = "";
+        for line in usage.lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            if !line.starts_with(" ") && trimmed.ends_with(":") {
+                section = if trimmed == "Usage:" { "usage" } else if trimmed == "Options:" { "options" } else { "" };
+                continue;
+            }
+            match section {
+                "usage" => spec.parse_pattern(trimmed),
+                "options" => if let Some(flag) = parse_option(trimmed) {
+                    spec.flags.push(flag);
+                },
+                _ => { },
+            }
+        }
+        spec
+    }
+
+    fn parse_pattern(&mut self, pattern: &str) {
+        let mut words = pattern.split_whitespace();
+        self.program = match words.next() {
+            Some(x) => x.to_string(),
+            None => return,
+        };
+        let words: Vec<_> = words.collect();
+        let name = match words.first() {
+            Some(x) if !x.starts_with("-") && !x.starts_with("<") && !x.starts_with("[") => x.to_string(),
+            _ => return,
+        };
+        let mut sub = Subcommand {
+            name: name,
+            flags: Vec::new(),
+            takes_files: false,
+        };
+        for word in &words[1..] {
+            let word = word.trim_matches(|c| c == '[' || c == ']' || c == '(' || c == ')' || c == '.');
+            if word.starts_with("--") {
+                let flag = word[2..].split('=').next().unwrap().to_string();
+                if !sub.flags.contains(&flag) {
+                    sub.flags.push(flag);
+                }
+            } else if word.starts_with("<input") || word.starts_with("<file") {
+                sub.takes_files = true;
+            }
+        }
+        // a subcommand may be listed with several patterns
+        if let Some(existing) = self.subcommands.iter_mut().find(|x| x.name == sub.name) {
+            for flag in sub.flags {
+                if !existing.flags.contains(&flag) {
+                    existing.flags.push(flag);
+                }
+            }
+            existing.takes_files |= sub.takes_files;
+            return;
+        }
+        self.subcommands.push(sub);
+    }
+
+    pub fn flag(&self, long: &str) -> Option<&Flag> {
+        self.flags.iter().find(|x| x.long == long)
+    }
+
+    /// Renders a completion script for the given shell.
+    pub fn completions(&self, shell: Shell) -> String {
+        match shell {
+            Shell::Bash => self.bash(),
+            Shell::Zsh => self.zsh(),
+            Shell::Fish => self.fish(),
+            Shell::PowerShell => self.powershell(),
+        }
+    }
+
+    fn bash(&self) -> String {
+        let func = format!("_{}", self.program.replace("-", "_"));
+        let names: Vec<_> = self.subcommands.iter().map(|x| &x.name[..]).collect();
+        let mut out = String::new();
+        out.push_str(&format!("{}() {{\n", func));
+        out.push_str("    local cur prev cmd\n");
+        out.push_str("    cur=\"${COMP_WORDS[COMP_CWORD]}\"\n");
+        out.push_str("    prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n");
+        out.push_str("    cmd=\"${COMP_WORDS[1]}\"\n");
+        out.push_str("    if [[ $COMP_CWORD -eq 1 ]]; then\n");
+        out.push_str(&format!("        COMPREPLY=($(compgen -W \"{} --help\" -- \"$cur\"))\n", names.join(" ")));
+        out.push_str("        return\n    fi\n");
+        let valued: Vec<_> = self.flags.iter().filter(|x| x.takes_value)
+            .map(|x| format!("--{}", x.long)).collect();
+        if !valued.is_empty() {
+            out.push_str(&format!("    case \"$prev\" in\n        {})\n            return\n            ;;\n    esac\n",
+                                  valued.join("|")));
+        }
+        out.push_str("    case \"$cmd\" in\n");
+        for sub in &self.subcommands {
+            let flags: Vec<_> = sub.flags.iter().map(|x| format!("--{}", x)).collect();
+            out.push_str(&format!("        {})\n", sub.name));
+            out.push_str("            if [[ \"$cur\" == -* ]]; then\n");
+            out.push_str(&format!("                COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n", flags.join(" ")));
+            if sub.takes_files {
+                out.push_str("            else\n");
+                out.push_str("                COMPREPLY=($(compgen -d -- \"$cur\") $(compgen -f -X '!*.syn' -- \"$cur\"))\n");
+            }
+            out.push_str("            fi\n            ;;\n");
+        }
+        out.push_str("    esac\n}\n");
+        out.push_str(&format!("complete -o filenames -F {} {}\n", func, self.program));
+        out
+    }
+
+    fn zsh(&self) -> String {
+        let mut out = format!("#compdef {}\n\n", self.program);
+        out.push_str(&format!("_{}() {{\n", self.program));
+        out.push_str("    local state\n");
+        out.push_str("    _arguments '1:command:->command' '*::arg:->args'\n");
+        out.push_str("    case $state in\n");
+        out.push_str("        command)\n");
+        let names: Vec<_> = self.subcommands.iter().map(|x| &x.name[..]).collect();
+        out.push_str(&format!("            _values 'command' {}\n", names.join(" ")));
+        out.push_str("            ;;\n");
+        out.push_str("        args)\n");
+        out.push_str("            case $words[1] in\n");
+        for sub in &self.subcommands {
+            out.push_str(&format!("                {})\n", sub.name));
+            out.push_str("                    _arguments");
+            for name in &sub.flags {
+                let (desc, value) = match self.flag(name) {
+                    Some(flag) => (zsh_escape(&flag.description), if flag.takes_value { ":value:" } else { "" }),
+                    None => (String::new(), ""),
+                };
+                out.push_str(&format!(" \\\n                        '--{}[{}]{}'", name, desc, value));
+            }
+            if sub.takes_files {
+                out.push_str(" \\\n                        '*:file:_files -g \"*.syn\"'");
+            }
+            out.push_str("\n                    ;;\n");
+        }
+        out.push_str("            esac\n            ;;\n    esac\n}\n\n");
+        out.push_str(&format!("_{} \"$@\"\n", self.program));
+        out
+    }
+
+    fn fish(&self) -> String {
+        let mut out = String::new();
+        let prog = &self.program;
+        out.push_str(&format!("complete -c {} -f\n", prog));
+        for sub in &self.subcommands {
+            out.push_str(&format!("complete -c {} -n '__fish_use_subcommand' -a {}\n", prog, sub.name));
+        }
+        for sub in &self.subcommands {
+            let cond = format!("__fish_seen_subcommand_from {}", sub.name);
+            for name in &sub.flags {
+                let mut line = format!("complete -c {} -n '{}' -l {}", prog, cond, name);
+                if let Some(flag) = self.flag(name) {
+                    if let Some(short) = flag.short {
+                        line.push_str(&format!(" -s {}", short));
+                    }
+                    if flag.takes_value {
+                        line.push_str(" -r");
+                    }
+                    line.push_str(&format!(" -d '{}'", flag.description.replace("'", "\\'")));
+                }
+                out.push_str(&line);
+                out.push('\n');
+            }
+            if sub.takes_files {
+                out.push_str(&format!("complete -c {} -n '{}' -a '(__fish_complete_suffix .syn)'\n",
+                                      prog, cond));
+            }
+        }
+        out
+    }
+
+    fn powershell(&self) -> String {
+        let mut out = String::new();
+        out.push_str(&format!("Register-ArgumentCompleter -Native -CommandName {} -ScriptBlock {{\n",
+                              self.program));
+        out.push_str("    param($wordToComplete, $commandAst, $cursorPosition)\n");
+        out.push_str("    $commands = @{\n");
+        for sub in &self.subcommands {
+            let flags: Vec<_> = sub.flags.iter().map(|x| format!("'--{}'", x)).collect();
+            out.push_str(&format!("        '{}' = @({})\n", sub.name, flags.join(", ")));
+        }
+        out.push_str("    }\n");
+        let files: Vec<_> = self.subcommands.iter().filter(|x| x.takes_files)
+            .map(|x| format!("'{}'", x.name)).collect();
+        out.push_str(&format!("    $takesFiles = @({})\n", files.join(", ")));
+        out.push_str("    $words = $commandAst.CommandElements | ForEach-Object { $_.ToString() }\n");
+        out.push_str("    if ($words.Count -le 1 -or ($words.Count -eq 2 -and $wordToComplete)) {\n");
+        out.push_str("        $candidates = $commands.Keys\n");
+        out.push_str("    } elseif ($wordToComplete -like '-*') {\n");
+        out.push_str("        $candidates = $commands[$words[1]]\n");
+        out.push_str("    } elseif ($takesFiles -contains $words[1]) {\n");
+        out.push_str("        $candidates = Get-ChildItem -Name -Filter '*.syn'\n");
+        out.push_str("    } else {\n");
+        out.push_str("        $candidates = @()\n");
+        out.push_str("    }\n");
+        out.push_str("    $candidates | Where-Object { $_ -like \"$wordToComplete*\" } | ForEach-Object {\n");
+        out.push_str("        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)\n");
+        out.push_str("    }\n}\n");
+        out
+    }
+}
+
+/// Parses a line such as `-o, --oversample=<n>   Description [default: 1].`
+fn parse_option(line: &str) -> Option<Flag> {
+    if !line.starts_with("-") {
+        return None;
+    }
+    // docopt separates the flags from the description with at least two spaces
+    let (flags, description) = match line.find("  ") {
+        Some(idx) => (&line[..idx], line[idx..].trim()),
+        None => (line, ""),
+    };
+    let mut flag = Flag {
+        long: String::new(),
+        short: None,
+        takes_value: false,
+        description: description.to_string(),
+    };
+    for part in flags.split(|c| c == ',' || c == ' ').filter(|x| !x.is_empty()) {
+        let mut split = part.splitn(2, '=');
+        let name = split.next().unwrap();
+        if split.next().is_some() {
+            flag.takes_value = true;
+        }
+        if name.starts_with("--") {
+            flag.long = name[2..].to_string();
+        } else if name.starts_with("-") {
+            flag.short = name[1..].chars().next();
+        } else if name.starts_with("<") {
+            flag.takes_value = true;
+        }
+    }
+    if flag.long.is_empty() {
+        None
+    } else {
+        Some(flag)
+    }
+}
+
+fn zsh_escape(s: &str) -> String {
+    s.replace("'", "'\\''").replace("[", "\\[").replace("]", "\\]")
+}
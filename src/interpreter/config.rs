@@ -0,0 +1,170 @@
+use super::audio::{RenderSettings, Precision};
+use super::common::read_file;
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_FILENAME: &'static str = "synthizer.toml";
+
+/// Settings which apply to every file in a project. Any of them may be overridden on the command
+/// line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// The directory containing the config file, which relative paths are resolved against.
+    pub root: PathBuf,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+    pub oversample: Option<usize>,
+    pub precision: Option<Precision>,
+    /// Default length of rendered files, in seconds.
+    pub length: Option<f32>,
+    /// Directories searched for files included by a program, in order.
+    pub include_paths: Vec<PathBuf>,
+    /// The function called for each sample.
+    pub entrypoint: Option<String>,
+}
+
+enum Value {
+    Number(f64),
+    Str(String),
+    Array(Vec<String>),
+}
+
+impl Config {
+    pub fn new(root: PathBuf) -> Config {
+        Config {
+            root: root,
+            sample_rate: None,
+            channels: None,
+            oversample: None,
+            precision: None,
+            length: None,
+            include_paths: Vec::new(),
+            entrypoint: None,
+        }
+    }
+
+    /// Looks for a config file in the directory of `path` and each of its parents, so that
+    /// files in subdirectories of a project share its settings. Relative paths are taken from
+    /// the working directory.
+    pub fn find(path: &Path) -> Option<PathBuf> {
+        // a bare file name has no parent to start from, and `..` would stop the search early
+        let path = match path.canonicalize() {
+            Ok(path) => path,
+            Err(_) => match env::current_dir() {
+                Ok(dir) => dir.join(path),
+                Err(_) => path.to_path_buf(),
+            },
+        };
+        let mut dir = path.parent();
+        while let Some(d) = dir {
+            let candidate = d.join(CONFIG_FILENAME);
+            if candidate.is_file() {
+                return Some(candidate);
+            }
+            dir = d.parent();
+        }
+        None
+    }
+
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let source = try!(read_file(&path.to_string_lossy()));
+        let root = path.parent().map(|x| x.to_path_buf()).unwrap_or(PathBuf::new());
+        Config::parse(&source, root).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(source: &str, root: PathBuf) -> Result<Config, String> {
+        let mut config = Config::new(root);
+        for (i, line) in source.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let eq = match line.find('=') {
+                Some(x) => x,
+                None => return Err(format!("line {}: expected `key = value`", i + 1)),
+            };
+            let key = line[..eq].trim();
+            let value = match parse_value(line[eq + 1..].trim()) {
+                Some(x) => x,
+                None => return Err(format!("line {}: invalid value for `{}`", i + 1, key)),
+            };
+            try!(config.set(key, value).map_err(|e| format!("line {}: {}", i + 1, e)));
+        }
+        Ok(config)
+    }
+
+    fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+        match (key, value) {
+            ("sample_rate", Value::Number(x)) if x >= 1.0 => self.sample_rate = Some(x as u32),
+            ("channels", Value::Number(x)) if x >= 1.0 => self.channels = Some(x as u16),
+            ("oversample", Value::Number(x)) if x >= 1.0 => self.oversample = Some(x as usize),
+            ("precision", Value::Str(ref x)) if Precision::parse(x).is_some() => self.precision = Precision::parse(x),
+            ("length", Value::Number(x)) if x >= 0.0 => self.length = Some(x as f32),
+            ("entrypoint", Value::Str(x)) => self.entrypoint = Some(x),
+            ("include_paths", Value::Array(paths)) => {
+                let paths = paths.iter().map(|x| self.root.join(x)).collect();
+                self.include_paths = paths;
+            }
+            ("sample_rate", _) | ("channels", _) | ("oversample", _) | ("precision", _) | ("length", _) |
+            ("entrypoint", _) | ("include_paths", _) => return Err(format!("invalid value for `{}`", key)),
+            _ => return Err(format!("unknown setting `{}`", key)),
+        }
+        Ok(())
+    }
+
+    /// Render settings with the given sample rate, unless the config specifies one.
+    pub fn render_settings(&self, default_sample_rate: u32) -> RenderSettings {
+        let mut settings = RenderSettings::new(self.sample_rate.unwrap_or(default_sample_rate));
+        if let Some(channels) = self.channels {
+            settings.channels = channels;
+        }
+        if let Some(oversample) = self.oversample {
+            settings.oversample = oversample;
+        }
+        if let Some(precision) = self.precision {
+            settings.precision = precision;
+        }
+        settings
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => { },
+        }
+    }
+    line
+}
+
+fn parse_string(s: &str) -> Option<String> {
+    if s.len() >= 2 && s.starts_with("\"") && s.ends_with("\"") {
+        Some(s[1..s.len() - 1].to_string())
+    } else {
+        None
+    }
+}
+
+fn parse_value(s: &str) -> Option<Value> {
+    if s.starts_with("[") {
+        if !s.ends_with("]") {
+            return None;
+        }
+        let mut items = Vec::new();
+        for item in s[1..s.len() - 1].split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+            match parse_string(item) {
+                Some(x) => items.push(x),
+                None => return None,
+            }
+        }
+        Some(Value::Array(items))
+    } else if let Some(x) = parse_string(s) {
+        Some(Value::Str(x))
+    } else {
+        s.replace("_", "").parse().ok().map(Value::Number)
+    }
+}
@@ -0,0 +1,275 @@
+use super::runtime::{Program, DebugLabel};
+use super::tokens::Number;
+
+use std::fmt::Write;
+
+/// When to pause the program.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Breakpoint {
+    /// Pause before evaluating the first sample at or after a time, in seconds.
+    At(Number),
+    /// Pause after a sample in which the named probe satisfies a comparison.
+    Probe(String, Comparison, Number),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Comparison {
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Comparison {
+    pub fn test(&self, lhs: Number, rhs: Number) -> bool {
+        match *self {
+            Comparison::Less => lhs < rhs,
+            Comparison::Greater => lhs > rhs,
+            Comparison::LessEqual => lhs <= rhs,
+            Comparison::GreaterEqual => lhs >= rhs,
+            Comparison::Equal => lhs == rhs,
+            Comparison::NotEqual => lhs != rhs,
+        }
+    }
+}
+
+impl Breakpoint {
+    /// Parses a time such as `1.5` or `1.5s`, or a probe condition such as `env>0.5`.
+    pub fn parse(s: &str) -> Result<Breakpoint, String> {
+        let s = s.trim();
+        // longer operators first, so `<=` is not read as `<`
+        let ops = [("<=", Comparison::LessEqual), (">=", Comparison::GreaterEqual),
+                   ("==", Comparison::Equal), ("!=", Comparison::NotEqual),
+                   ("<", Comparison::Less), (">", Comparison::Greater)];
+        for &(op, cmp) in &ops {
+            if let Some(idx) = s.find(op) {
+                let name = s[..idx].trim();
+                let value = s[idx + op.len()..].trim();
+                return match value.parse() {
+                    Ok(x) if !name.is_empty() => Ok(Breakpoint::Probe(name.to_string(), cmp, x)),
+                    Ok(_) => Err(format!("expected a probe name in `{}`", s)),
+                    Err(_) => Err(format!("`{}` is not a number", value)),
+                };
+            }
+        }
+        let time = if s.ends_with("s") { &s[..s.len() - 1] } else { s };
+        match time.parse() {
+            Ok(x) if x >= 0.0 => Ok(Breakpoint::At(x)),
+            _ => Err(format!("expected a time in seconds or a probe condition, found `{}`", s)),
+        }
+    }
+}
+
+/// Steps through a program compiled with `Options::debug`, which records each variable as it is
+/// assigned.
+pub struct Debugger<'c> {
+    program: Program<'c>,
+    breakpoint: Option<Breakpoint>,
+    last_output: Option<Number>,
+    stepped: usize, // how many of the values recorded in the last sample `next` has shown
+}
+
+/// How many seconds of the program `continue` evaluates before giving up on a breakpoint.
+pub const MAX_CONTINUE_SECONDS: u64 = 60;
+
+impl<'c> Debugger<'c> {
+    pub fn new(program: Program<'c>) -> Debugger<'c> {
+        let mut program = program;
+        program.watch_probes();
+        Debugger {
+            program: program,
+            breakpoint: None,
+            last_output: None,
+            stepped: 0,
+        }
+    }
+
+    pub fn program(&self) -> &Program<'c> {
+        &self.program
+    }
+
+    pub fn set_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoint = Some(breakpoint);
+    }
+
+    /// The time of the next sample to be evaluated.
+    pub fn time(&self) -> Number {
+        self.program.sample_index() as Number / self.program.sample_rate() as Number
+    }
+
+    /// Evaluates one sample.
+    pub fn step(&mut self) -> Number {
+        let out = self.program.next();
+        self.last_output = Some(out);
+        self.stepped = self.program.debug_values().len();
+        out
+    }
+
+    /// Shows the next value assigned in the current sample, evaluating another sample once all
+    /// of them have been shown.
+    pub fn next_value(&mut self) -> String {
+        if self.stepped >= self.program.debug_values().len() {
+            self.step();
+            self.stepped = 0;
+        }
+        let shown = match self.program.debug_values().get(self.stepped) {
+            Some(&(label, value)) => format!("{} = {} at {} in {}", label.name, value, label.pos, label.function),
+            None => return format!("no variables were recorded, {}", self.status()),
+        };
+        self.stepped += 1;
+        shown
+    }
+
+    /// The last value of each variable and argument up to where `next` has stepped to.
+    pub fn variables(&self) -> String {
+        let values = self.program.debug_values();
+        let mut last: Vec<(&DebugLabel, &str)> = Vec::new();
+        for &(label, value) in &values[..self.stepped] {
+            match last.iter().position(|x| x.0 == label) {
+                Some(i) => last[i].1 = value,
+                None => last.push((label, value)),
+            }
+        }
+        if last.is_empty() {
+            return "no variables have been assigned in this sample".to_string();
+        }
+        let lines: Vec<_> = last.iter()
+            .map(|&(label, value)| format!("{} = {} ({} at {})", label.name, value, label.function, label.pos))
+            .collect();
+        lines.join("\n")
+    }
+
+    /// Evaluates samples until the breakpoint is hit. Returns false if it was not hit within
+    /// MAX_CONTINUE_SECONDS of the program.
+    pub fn resume(&mut self) -> bool {
+        let breakpoint = match self.breakpoint.clone() {
+            Some(x) => x,
+            None => return false,
+        };
+        let limit = MAX_CONTINUE_SECONDS * self.program.sample_rate() as u64;
+        for _ in 0..limit {
+            if let Breakpoint::At(time) = breakpoint {
+                if self.time() >= time {
+                    // a time is only ever reached once
+                    self.breakpoint = None;
+                    return true;
+                }
+            }
+            self.step();
+            if let Breakpoint::Probe(ref name, cmp, rhs) = breakpoint {
+                let hit = self.program.probe_values().iter()
+                    .any(|&(n, value)| n == &name[..] && value.map_or(false, |x| cmp.test(x, rhs)));
+                if hit {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// A summary of where the program is paused.
+    pub fn status(&self) -> String {
+        match self.last_output {
+            Some(out) => format!("paused at sample {} ({:.6}s), last output {}",
+                                 self.program.sample_index(), self.time(), out),
+            None => format!("paused at sample {} ({:.6}s)", self.program.sample_index(), self.time()),
+        }
+    }
+
+    pub fn probes(&self, name: Option<&str>) -> String {
+        let mut out = String::new();
+        for (probe, value) in self.program.probe_values() {
+            if name.map_or(false, |x| x != probe) {
+                continue;
+            }
+            match value {
+                Some(x) => writeln!(out, "{} = {}", probe, x).unwrap(),
+                None => writeln!(out, "{} was not evaluated", probe).unwrap(),
+            }
+        }
+        if out.is_empty() {
+            match name {
+                Some(x) => format!("no probe named `{}`", x),
+                None => "the program has no probes".to_string(),
+            }
+        } else {
+            out.trim_right().to_string()
+        }
+    }
+
+    /// The memory of each call site, along with where the call is.
+    pub fn memory(&self) -> String {
+        let mut out = String::new();
+        for (site, mem) in self.program.state().memories().iter().enumerate() {
+            if mem.is_empty() {
+                continue;
+            }
+            let shown: Vec<_> = mem.iter().take(8).map(|x| x.to_string()).collect();
+            let more = if mem.len() > 8 { format!(", ... ({} values)", mem.len()) } else { String::new() };
+            match self.program.source_map().call_site(site) {
+                Some(pos) => writeln!(out, "site {} at {}: [{}{}]", site, pos, shown.join(", "), more).unwrap(),
+                None => writeln!(out, "site {}: [{}{}]", site, shown.join(", "), more).unwrap(),
+            }
+        }
+        if out.is_empty() {
+            "no call site has memory".to_string()
+        } else {
+            out.trim_right().to_string()
+        }
+    }
+
+    /// Runs a command typed at the debugger prompt. Returns None when the session should end.
+    pub fn command(&mut self, line: &str) -> Option<String> {
+        let mut words = line.split_whitespace();
+        let cmd = words.next().unwrap_or("");
+        let arg = words.next();
+        Some(match cmd {
+            "" => String::new(),
+            "s" | "step" => {
+                let n = match arg.map(|x| x.parse::<u64>()) {
+                    Some(Ok(n)) => n,
+                    Some(Err(_)) => return Some(format!("`{}` is not a number of samples", arg.unwrap())),
+                    None => 1,
+                };
+                for _ in 0..n {
+                    self.step();
+                }
+                self.status()
+            }
+            "c" | "continue" => {
+                if let Some(arg) = arg {
+                    match Breakpoint::parse(arg) {
+                        Ok(x) => self.set_breakpoint(x),
+                        Err(e) => return Some(e),
+                    }
+                }
+                if self.resume() {
+                    self.status()
+                } else {
+                    format!("breakpoint not hit, {}", self.status())
+                }
+            }
+            "n" | "next" => self.next_value(),
+            "v" | "vars" => self.variables(),
+            "p" | "probes" => self.probes(arg),
+            "m" | "memory" => self.memory(),
+            "h" | "help" => HELP.trim().to_string(),
+            "q" | "quit" => return None,
+            _ => format!("unknown command `{}`, try `help`", cmd),
+        })
+    }
+}
+
+const HELP: &'static str = "
+step [n]          evaluate n samples, 1 if not given
+continue [break]  run until the breakpoint is hit, optionally setting a new one first
+next              show the next value assigned in the current sample, evaluating one if needed
+vars              show the value of each variable and argument assigned so far in the sample
+probes [name]     show the value of each probe in the last sample
+memory            show the memory of each intrinsic call site
+quit              stop debugging
+
+Breakpoints are a time such as 1.5s, or a probe condition such as env>0.5.
+";
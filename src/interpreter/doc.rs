@@ -0,0 +1,227 @@
+use super::common::Context;
+use super::ast::{Item, Function};
+use super::ident::Identifier;
+use super::tokens::{NodeImpl, SourcePos};
+use super::types::Type;
+
+use std::fmt;
+
+/// A top level definition along with its documentation.
+#[derive(Clone, Debug)]
+pub struct DocEntry {
+    pub ident: Identifier,
+    pub name: String,
+    pub signature: String,
+    pub docs: Option<String>,
+    pub pos: SourcePos,
+    /// The arguments if this is a function definition, None if it is an assignment.
+    pub args: Option<Vec<DocArg>>,
+    /// The type of an assignment or the return type of a function, once known.
+    pub ty: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct DocArg {
+    pub ident: Identifier,
+    pub name: String,
+    pub ty: Option<String>,
+    pub default: Option<String>,
+}
+
+impl DocEntry {
+    /// Definitions whose names start with `_` are considered private.
+    pub fn is_public(&self) -> bool {
+        !self.name.starts_with("_")
+    }
+}
+
+impl fmt::Display for DocEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}", self.signature));
+        if let Some(ref docs) = self.docs {
+            for line in docs.lines() {
+                try!(write!(f, "\n    {}", line));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lists the definitions in the parsed AST held by the context, in source order. Should be called
+/// after parsing, and before typechecking which removes unused functions from the AST.
+pub fn collect_docs<'a>(ctxt: &'a Context<'a>) -> Vec<DocEntry> {
+    let mut entries = Vec::new();
+    for item in ctxt.ast.borrow().iter() {
+        // skip constants defined by the compiler rather than the source
+        if item.pos().is_anon() {
+            continue;
+        }
+        let entry = match *item {
+            Item::FunctionDef(ref def) => {
+                // the signature is everything written between the name and the body
+                let source = ctxt.files.slice(def.pos(), def.block_pos());
+                DocEntry {
+                    ident: def.ident(),
+                    name: ctxt.lookup_name(def.ident()),
+                    signature: source.split_whitespace().collect::<Vec<_>>().join(" "),
+                    docs: def.docs.clone(),
+                    pos: def.pos(),
+                    args: Some(collect_args(ctxt, &def.func)),
+                    ty: def.returns().map(|x| x.item().to_string()),
+                }
+            }
+            Item::Assignment(ref assign) => {
+                let name = ctxt.lookup_name(assign.ident());
+                DocEntry {
+                    ident: assign.ident(),
+                    signature: name.clone(),
+                    name: name,
+                    docs: assign.docs.clone(),
+                    pos: assign.pos(),
+                    args: None,
+                    ty: None,
+                }
+            }
+        };
+        entries.push(entry);
+    }
+    entries
+}
+
+fn collect_args<'a>(ctxt: &'a Context<'a>, func: &Function) -> Vec<DocArg> {
+    let args = func.args();
+    let mut doc_args = Vec::new();
+    for (i, arg) in args.iter().enumerate() {
+        let id = match arg.ident() {
+            Some(id) => id,
+            None => continue,
+        };
+        // defaults run until the next argument, or the return annotation or body after the last
+        let default = arg.expr().map(|expr| {
+            let end = match args.get(i + 1) {
+                Some(next) => next.pos(),
+                None => func.returns().pos().unwrap_or(func.block_pos()),
+            };
+            let text = ctxt.files.slice(expr.pos(), end).trim_right();
+            let text = text.trim_right_matches("->").trim_right().trim_right_matches(',');
+            text.trim_right().to_string()
+        });
+        doc_args.push(DocArg {
+            ident: id,
+            name: ctxt.lookup_name(id),
+            ty: func.arg_types[i].map(|x| x.item().to_string()),
+            default: default,
+        });
+    }
+    doc_args
+}
+
+/// Fills in the types inferred by the typechecker for anything which was not annotated. Types
+/// which could not be inferred, such as those of unused functions, are left empty.
+pub fn add_inferred_types<'a>(ctxt: &'a Context<'a>, entries: &mut [DocEntry]) {
+    fn known(ty: Type) -> Option<String> {
+        match ty {
+            Type::Var(_) => None,
+            ty => Some(ty.to_string()),
+        }
+    }
+
+    let functions = ctxt.functions.borrow();
+    let types = ctxt.types.borrow();
+    for entry in entries.iter_mut() {
+        match entry.args {
+            Some(ref mut args) => {
+                let fn_ty = match functions.get(entry.ident).and_then(|x| x.ty()) {
+                    Some(ty) => ty,
+                    None => continue,
+                };
+                for arg in args.iter_mut() {
+                    if arg.ty.is_none() {
+                        arg.ty = fn_ty.args.get(&arg.ident).and_then(|&x| known(x));
+                    }
+                }
+                if entry.ty.is_none() {
+                    entry.ty = known(fn_ty.returns);
+                }
+            }
+            None => {
+                entry.ty = types.get_symbol(entry.ident).and_then(|x| known(x.val));
+            }
+        }
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Renders a standalone HTML page documenting the public definitions of a file.
+pub fn render_html(filename: &str, entries: &[DocEntry]) -> String {
+    let title = escape_html(filename);
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str(&format!("<title>{}</title>\n", title));
+    html.push_str("<style>\n\
+                   body { font-family: sans-serif; max-width: 50em; margin: 2em auto; }\n\
+                   code, pre { font-family: monospace; }\n\
+                   .def { border-top: 1px solid #ccc; padding: 0.5em 0; }\n\
+                   .ty { color: #276; }\n\
+                   </style>\n</head>\n<body>\n");
+    html.push_str(&format!("<h1>{}</h1>\n", title));
+
+    let public: Vec<_> = entries.iter().filter(|x| x.is_public()).collect();
+    if public.iter().any(|x| x.args.is_some()) {
+        html.push_str("<h2>Functions</h2>\n");
+        for entry in public.iter().filter(|x| x.args.is_some()) {
+            html.push_str(&render_entry(entry));
+        }
+    }
+    if public.iter().any(|x| x.args.is_none()) {
+        html.push_str("<h2>Values</h2>\n");
+        for entry in public.iter().filter(|x| x.args.is_none()) {
+            html.push_str(&render_entry(entry));
+        }
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn render_entry(entry: &DocEntry) -> String {
+    let name = escape_html(&entry.name);
+    let mut html = format!("<div class=\"def\" id=\"{}\">\n<h3><code>{}", name, name);
+    if let Some(ref ty) = entry.ty {
+        let sep = if entry.args.is_some() { " -&gt; " } else { ": " };
+        html.push_str(&format!("{}<span class=\"ty\">{}</span>", sep, escape_html(ty)));
+    }
+    html.push_str("</code></h3>\n");
+    if let Some(ref docs) = entry.docs {
+        for paragraph in docs.split("\n\n") {
+            html.push_str(&format!("<p>{}</p>\n", escape_html(paragraph)));
+        }
+    }
+    if let Some(ref args) = entry.args {
+        if !args.is_empty() {
+            html.push_str("<table>\n<tr><th>Argument</th><th>Type</th><th>Default</th></tr>\n");
+            for arg in args {
+                html.push_str(&format!(
+                    "<tr><td><code>{}</code></td><td class=\"ty\">{}</td><td><code>{}</code></td></tr>\n",
+                    escape_html(&arg.name),
+                    arg.ty.as_ref().map(|x| escape_html(x)).unwrap_or_else(|| "_".to_string()),
+                    arg.default.as_ref().map(|x| escape_html(x)).unwrap_or(String::new())));
+            }
+            html.push_str("</table>\n");
+        }
+    }
+    html.push_str("</div>\n");
+    html
+}
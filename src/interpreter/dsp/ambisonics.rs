@@ -0,0 +1,67 @@
+use super::super::compiler::Compiler;
+use super::super::runtime::CallSite;
+use super::super::tokens::Number;
+use super::super::types::Type;
+
+use std::slice;
+
+/// The number of channels of a first order ambisonic signal, which are W, Y, Z and X with SN3D
+/// weights. Azimuths go anticlockwise from straight ahead, in radians.
+pub const CHANNELS: usize = 4;
+
+/// The W, Y, Z and X channels of a signal coming from the given direction.
+pub fn encode(x: Number, azimuth: Number, elevation: Number) -> [Number; CHANNELS] {
+    let (sin_az, cos_az) = azimuth.sin_cos();
+    let (sin_el, cos_el) = elevation.sin_cos();
+    [x, x * sin_az * cos_el, x * sin_el, x * cos_az * cos_el]
+}
+
+/// Turns a sound field anticlockwise around the vertical axis by `yaw` radians.
+pub fn rotate(b: &[Number], yaw: Number) -> [Number; CHANNELS] {
+    let (sin, cos) = yaw.sin_cos();
+    let (w, y, z, x) = (b[0], b[1], b[2], b[3]);
+    [w, x * sin + y * cos, z, x * cos - y * sin]
+}
+
+/// The signal for a speaker at the given azimuth in a ring of `speakers` evenly spaced around the
+/// listener. With 3 or more speakers their gains for a source add up to 1 from any direction.
+pub fn decode(b: &[Number], azimuth: Number, speakers: usize) -> Number {
+    let (sin, cos) = azimuth.sin_cos();
+    (b[0] + 2.0 * (b[3] * cos + b[1] * sin)) / speakers as Number
+}
+
+unsafe fn write(out: *mut Number, b: [Number; CHANNELS]) {
+    slice::from_raw_parts_mut(out, CHANNELS).copy_from_slice(&b)
+}
+
+/// The implementation of `foa`.
+pub extern fn foa(_: CallSite, x: Number, azimuth: Number, elevation: Number, out: *mut Number) {
+    unsafe { write(out, encode(x, azimuth, elevation)) }
+}
+
+/// The implementation of `foa_rotate`.
+pub extern fn foa_rotate(_: CallSite, b: *const Number, len: usize, yaw: Number, out: *mut Number) {
+    unsafe { write(out, rotate(slice::from_raw_parts(b, len), yaw)) }
+}
+
+/// The implementation of `foa_decode`, which gives a channel for each speaker azimuth.
+pub extern fn foa_decode(_: CallSite, b: *const Number, b_len: usize, azimuths: *const Number,
+                         speakers: usize, out: *mut Number) {
+    let (b, azimuths) = unsafe { (slice::from_raw_parts(b, b_len), slice::from_raw_parts(azimuths, speakers)) };
+    let out = unsafe { slice::from_raw_parts_mut(out, speakers) };
+    for (out, &azimuth) in out.iter_mut().zip(azimuths) {
+        *out = decode(b, azimuth, speakers);
+    }
+}
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    unsafe {
+        compiler.define_direct_builtin("foa", &[("x", Type::Number), ("azimuth", Type::Number),
+                                                ("elevation", Type::Number)],
+                                       Type::Channels(CHANNELS), foa as *mut ());
+        compiler.define_direct_builtin("foa_rotate", &[("b", Type::Channels(0)), ("yaw", Type::Number)],
+                                       Type::Channels(CHANNELS), foa_rotate as *mut ());
+        compiler.define_channel_builtin("foa_decode", &[("b", Type::Channels(0)), ("azimuths", Type::Array(0))],
+                                        "azimuths", foa_decode as *mut ());
+    }
+}
@@ -0,0 +1,78 @@
+use super::super::compiler::Compiler;
+use super::super::runtime::{self, CallSite};
+use super::super::tokens::Number;
+use super::super::types::Type;
+use super::trigger;
+
+use std::slice;
+
+pub const MODES: &'static [&'static str] = &["up", "down", "updown", "order", "random"];
+
+/// The index of the note with the given rank among `notes`, counting from the lowest. Equal notes
+/// are ranked in the order they appear.
+pub fn nth_lowest(notes: &[Number], rank: usize) -> usize {
+    (0..notes.len()).find(|&i| {
+        notes.iter().enumerate().filter(|&(j, &x)| x < notes[i] || (x == notes[i] && j < i)).count() == rank
+    }).unwrap_or(0)
+}
+
+/// How many steps it takes to play every note once in the given mode, before starting over.
+pub fn cycle_len(mode: &str, len: usize) -> usize {
+    match mode {
+        "updown" if len > 1 => 2 * len - 2,
+        _ => len,
+    }
+}
+
+/// The note played at a step of the cycle of the given mode. Random notes are not chosen here.
+pub fn note_at(notes: &[Number], mode: &str, step: usize) -> Number {
+    let len = notes.len();
+    let index = match mode {
+        "down" => nth_lowest(notes, len - 1 - step % len),
+        "updown" => {
+            let step = step % cycle_len(mode, len);
+            nth_lowest(notes, if step < len { step } else { 2 * len - 2 - step })
+        }
+        "order" => step % len,
+        _ => nth_lowest(notes, step % len),
+    };
+    notes[index]
+}
+
+/// The implementation of `arp`, which moves on to the next note each time `clock` fires and
+/// returns the current one. Unknown modes play upwards.
+pub extern fn arp(site: CallSite, notes: *const Number, len: usize, clock: Number, mode: usize) -> Number {
+    let notes = unsafe { slice::from_raw_parts(notes, len) };
+    runtime::with_site_state(site, |state| {
+        let mode = match MODES.iter().find(|&&x| x == state.string(mode)) {
+            Some(&x) => x,
+            None => "up",
+        };
+        let fired = trigger::fired(clock);
+        let random = if fired && mode == "random" { state.rng().next_number() } else { 0.0 };
+        let mem = state.memory(2); // the current step, whether the clock has fired before
+        if fired {
+            mem[0] = if mode == "random" {
+                ((random * len as Number) as usize).min(len - 1) as Number
+            } else if mem[1] != 0.0 {
+                ((mem[0] as usize + 1) % cycle_len(mode, len)) as Number
+            } else {
+                mem[0]
+            };
+            mem[1] = 1.0;
+        }
+        if mode == "random" {
+            notes[(mem[0] as usize).min(len - 1)]
+        } else {
+            note_at(notes, mode, mem[0] as usize)
+        }
+    })
+}
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    unsafe {
+        compiler.define_direct_builtin("arp", &[("notes", Type::Array(0)), ("clock", Type::Number),
+                                                ("mode", Type::Str)],
+                                       Type::Number, arp as *mut ());
+    }
+}
@@ -0,0 +1,51 @@
+use super::super::compiler::Compiler;
+use super::super::int::to_bits;
+use super::super::runtime::CallSite;
+use super::super::tokens::Number;
+use super::super::types::Type;
+
+/// The sample rate bytebeat formulas are written for.
+pub const BYTEBEAT_RATE: Number = 8000.0;
+
+fn wrap(x: i64) -> Number {
+    x as i32 as Number
+}
+
+/// The implementations of `wadd`, `wsub` and `wmul`, which wrap their result to a 32 bit two's
+/// complement integer like C does.
+pub extern fn wadd(_: CallSite, a: Number, b: Number) -> Number {
+    wrap(to_bits(a).wrapping_add(to_bits(b)))
+}
+pub extern fn wsub(_: CallSite, a: Number, b: Number) -> Number {
+    wrap(to_bits(a).wrapping_sub(to_bits(b)))
+}
+pub extern fn wmul(_: CallSite, a: Number, b: Number) -> Number {
+    wrap(to_bits(a).wrapping_mul(to_bits(b)))
+}
+
+/// The implementation of `xor`, the exclusive or of the bits of two Ints.
+pub extern fn xor(_: CallSite, a: Number, b: Number) -> Number {
+    (to_bits(a) ^ to_bits(b)) as Number
+}
+
+/// The sample the low 8 bits of an Int stand for as an unsigned byte, from -1 for 0 to just under
+/// 1 for 255.
+pub fn byte_sample(x: Number) -> Number {
+    (to_bits(x) & 0xff) as Number / 128.0 - 1.0
+}
+
+/// The implementation of `byte`.
+pub extern fn byte(_: CallSite, x: Number) -> Number {
+    byte_sample(x)
+}
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    let ints = [("a", Type::Int), ("b", Type::Int)];
+    unsafe {
+        compiler.define_direct_builtin("wadd", &ints, Type::Int, wadd as *mut ());
+        compiler.define_direct_builtin("wsub", &ints, Type::Int, wsub as *mut ());
+        compiler.define_direct_builtin("wmul", &ints, Type::Int, wmul as *mut ());
+        compiler.define_direct_builtin("xor", &ints, Type::Int, xor as *mut ());
+        compiler.define_direct_builtin("byte", &[("x", Type::Int)], Type::Number, byte as *mut ());
+    }
+}
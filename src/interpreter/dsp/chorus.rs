@@ -0,0 +1,38 @@
+use super::super::compiler::Compiler;
+use super::super::tokens::Number;
+use super::delay::DelayLine;
+use super::interpolate::{self, Interpolation};
+use super::take;
+
+use std::f64::consts::PI;
+
+const VOICES: usize = 3;
+const BASE_DELAY_MS: Number = 15.0;
+const MAX_DEPTH_MS: Number = 5.0;
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    // Three modulated delay voices with evenly spread LFO phases. `rate` is the LFO frequency in
+    // Hz, `depth` ranges from 0 to 1, `mix` is the wet/dry balance.
+    compiler.define_native_function("chorus", &["x", "rate", "depth", "mix", "interpolation"], |args, state| {
+        let (x, rate, depth, mix) = (args[0], args[1], args[2], args[3]);
+        let mode = Interpolation::from_number(args[4]);
+        let sample_rate = state.sample_rate() as Number;
+        let ms = sample_rate / 1000.0;
+        let max_delay = ((BASE_DELAY_MS + MAX_DEPTH_MS) * ms) as usize + 2;
+        let mut mem = state.memory(1 + DelayLine::memory_len(max_delay));
+
+        let phase = take(&mut mem, 1);
+        let mut line = DelayLine::new(take(&mut mem, DelayLine::memory_len(max_delay)));
+        line.write(x);
+
+        let depth = depth.max(0.0).min(1.0) * MAX_DEPTH_MS * ms;
+        let mut wet = 0.0;
+        for v in 0..VOICES {
+            let lfo = (2.0 * PI * (phase[0] + v as Number / VOICES as Number)).sin();
+            wet += line.read_frac(BASE_DELAY_MS * ms + lfo * depth, mode);
+        }
+        phase[0] = (phase[0] + rate / sample_rate) % 1.0;
+        x * (1.0 - mix) + wet / VOICES as Number * mix
+    });
+    interpolate::default_to_setting(compiler, "chorus");
+}
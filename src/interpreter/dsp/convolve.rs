@@ -0,0 +1,184 @@
+use super::super::buffer::Audio;
+use super::super::audio::resample;
+use super::super::compiler::Compiler;
+use super::super::runtime::{self, CallSite};
+use super::super::tokens::Number;
+use super::super::types::Type;
+use super::{fft, take};
+
+use std::cmp;
+
+/// The number of samples in a block, which is also the length of each partition.
+pub const BLOCK: usize = 128;
+
+const FFT_SIZE: usize = 2 * BLOCK;
+
+/// An impulse response as it was loaded, and resampled to the rate of the program along with the
+/// spectra of its partitions.
+#[derive(Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct Impulse {
+    pub name: String,
+    audio: Audio,
+    taps: Vec<Number>,
+    spectra: Vec<Number>, // the real and then imaginary parts of each partition
+    rate: u32,
+}
+
+impl Impulse {
+    pub fn new(name: String, audio: Audio) -> Impulse {
+        Impulse {
+            name: name,
+            audio: audio,
+            taps: Vec::new(),
+            spectra: Vec::new(),
+            rate: 0,
+        }
+    }
+
+    pub fn taps(&self) -> &[Number] {
+        &self.taps
+    }
+
+    pub fn partitions(&self) -> usize {
+        partitions(self.taps.len())
+    }
+
+    pub fn allocate(&mut self, sample_rate: u32) {
+        if self.rate == sample_rate {
+            return;
+        }
+        self.taps = resample::resample(&self.audio.samples, self.audio.sample_rate, sample_rate)
+                        .iter().map(|&x| x as Number).collect();
+        self.spectra = vec![0.0; self.partitions() * 2 * FFT_SIZE];
+        let head = cmp::min(BLOCK, self.taps.len());
+        for (i, part) in self.taps[head..].chunks(BLOCK).enumerate() {
+            let (re, im) = self.spectra[i * 2 * FFT_SIZE..(i + 1) * 2 * FFT_SIZE].split_at_mut(FFT_SIZE);
+            re[..part.len()].copy_from_slice(part);
+            fft::fft(re, im);
+        }
+        self.rate = sample_rate;
+    }
+}
+
+/// The number of partitions after the first block of a response of the given length.
+pub fn partitions(taps: usize) -> usize {
+    (taps.saturating_sub(BLOCK) + BLOCK - 1) / BLOCK
+}
+
+// The memory of a convolution holds one more than the length of the response it was cleared for,
+// how far into the block the next sample is, which partition of the input is newest and whether
+// a full block is waiting to be transformed, then the last two blocks of input, what the later
+// partitions add to the current block and room to work out the next one. After that come the
+// spectra of each partition of the input.
+const HEADER: usize = 4;
+const STATE_LEN: usize = HEADER + 2 * BLOCK + BLOCK + 2 * FFT_SIZE;
+
+pub fn memory_len(taps: usize) -> usize {
+    STATE_LEN + partitions(taps) * 2 * FFT_SIZE
+}
+
+/// Clears the memory of a convolution with a response of the given length.
+pub fn prepare(mem: &mut [Number], taps: usize) {
+    for x in mem.iter_mut() {
+        *x = 0.0;
+    }
+    mem[0] = (taps + 1) as Number;
+}
+
+/// Convolves the next sample of a signal with the response. Returns the output, and whether the
+/// sample filled a block, which must be passed to process_block() before the next sample.
+pub fn next(mem: &mut [Number], impulse: &Impulse, signal: Number) -> (Number, bool) {
+    if mem[3] != 0.0 {
+        // called again before the runtime got to the last block
+        process_block(mem, impulse);
+    }
+    let mut mem = mem;
+    let header = take(&mut mem, HEADER);
+    let input = take(&mut mem, 2 * BLOCK);
+    let tail = take(&mut mem, BLOCK);
+    let pos = header[1] as usize;
+    input[BLOCK + pos] = signal;
+    let head = &impulse.taps()[..cmp::min(BLOCK, impulse.taps().len())];
+    let out = head.iter().enumerate().fold(tail[pos], |sum, (k, h)| sum + h * input[BLOCK + pos - k]);
+    if pos + 1 < BLOCK {
+        header[1] = (pos + 1) as Number;
+        return (out, false);
+    }
+    header[1] = 0.0;
+    header[3] = 1.0;
+    (out, true)
+}
+
+/// Works out what the later partitions of the response add to the next block, once a block of
+/// input is full. Does nothing if no block is waiting.
+pub fn process_block(mem: &mut [Number], impulse: &Impulse) {
+    let parts = impulse.partitions();
+    let mut mem = mem;
+    let header = take(&mut mem, HEADER);
+    if header[3] == 0.0 {
+        return;
+    }
+    header[3] = 0.0;
+    let input = take(&mut mem, 2 * BLOCK);
+    let tail = take(&mut mem, BLOCK);
+    if parts > 0 {
+        let newest = (header[2] as usize + 1) % parts;
+        header[2] = newest as Number;
+        let (re, im) = take(&mut mem, 2 * FFT_SIZE).split_at_mut(FFT_SIZE);
+        let inputs = mem;
+        {
+            let (x_re, x_im) = inputs[newest * 2 * FFT_SIZE..(newest + 1) * 2 * FFT_SIZE].split_at_mut(FFT_SIZE);
+            x_re.copy_from_slice(input);
+            for x in x_im.iter_mut() {
+                *x = 0.0;
+            }
+            fft::fft(x_re, x_im);
+        }
+        // partition i of the response meets the input from i blocks ago
+        for x in re.iter_mut().chain(im.iter_mut()) {
+            *x = 0.0;
+        }
+        for i in 0..parts {
+            let h = &impulse.spectra[i * 2 * FFT_SIZE..(i + 1) * 2 * FFT_SIZE];
+            let j = (newest + parts - i) % parts;
+            let x = &inputs[j * 2 * FFT_SIZE..(j + 1) * 2 * FFT_SIZE];
+            for k in 0..FFT_SIZE {
+                re[k] += h[k] * x[k] - h[FFT_SIZE + k] * x[FFT_SIZE + k];
+                im[k] += h[k] * x[FFT_SIZE + k] + h[FFT_SIZE + k] * x[k];
+            }
+        }
+        fft::ifft(re, im);
+        tail.copy_from_slice(&re[BLOCK..]);
+    }
+    let (older, newer) = input.split_at_mut(BLOCK);
+    older.copy_from_slice(newer);
+}
+
+/// The implementation of `convolve`. Responses which were not loaded give silence.
+pub extern fn convolve(site: CallSite, signal: Number, impulse: usize) -> Number {
+    runtime::with_site_state(site, |state| {
+        let index = match state.impulse_index(impulse) {
+            Some(index) => index,
+            None => return 0.0,
+        };
+        let (out, full) = state.with_impulse(index, |mem, impulse| {
+            let taps = impulse.taps().len();
+            if mem[0] != (taps + 1) as Number {
+                // the first sample, or the sample rate changed
+                prepare(mem, taps);
+            }
+            next(mem, impulse, signal)
+        });
+        if full && !state.schedule_block(index) {
+            state.with_impulse(index, process_block);
+        }
+        out
+    })
+}
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    unsafe {
+        compiler.define_direct_builtin("convolve", &[("signal", Type::Number), ("impulse", Type::Str)],
+                                       Type::Number, convolve as *mut ());
+    }
+}
@@ -0,0 +1,49 @@
+use super::super::tokens::Number;
+use super::interpolate::{interpolate, Interpolation};
+
+/// A delay line kept in a slice of state memory. The first value holds the write position.
+pub struct DelayLine<'a> {
+    mem: &'a mut [Number],
+}
+
+impl<'a> DelayLine<'a> {
+    /// The amount of memory needed for a line that can delay by up to `max_delay` samples.
+    pub fn memory_len(max_delay: usize) -> usize {
+        max_delay + 1
+    }
+
+    pub fn new(mem: &'a mut [Number]) -> DelayLine<'a> {
+        assert!(mem.len() > 1);
+        DelayLine {
+            mem: mem,
+        }
+    }
+
+    pub fn max_delay(&self) -> usize {
+        self.mem.len() - 1
+    }
+
+    fn pos(&self) -> usize {
+        self.mem[0] as usize
+    }
+
+    /// Pushes a new sample into the line.
+    pub fn write(&mut self, x: Number) {
+        let pos = self.pos();
+        self.mem[1 + pos] = x;
+        self.mem[0] = ((pos + 1) % self.max_delay()) as Number;
+    }
+
+    /// Returns the sample written `delay` writes ago, where `delay` is between 1 and max_delay().
+    pub fn read(&self, delay: usize) -> Number {
+        let len = self.max_delay();
+        let delay = delay.max(1).min(len);
+        self.mem[1 + (self.pos() + len - delay) % len]
+    }
+
+    /// Reads between samples with the given interpolation.
+    pub fn read_frac(&self, delay: Number, mode: Interpolation) -> Number {
+        let delay = delay.max(1.0).min((self.max_delay() - 1) as Number);
+        interpolate(mode, delay, |i| self.read(i.max(1) as usize))
+    }
+}
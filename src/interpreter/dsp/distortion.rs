@@ -0,0 +1,56 @@
+use super::super::compiler::Compiler;
+use super::super::tokens::Number;
+use super::table::Table;
+use super::interpolate::{self, Interpolation};
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// tanh saturation normalized so that an input of 1 always maps to 1.
+pub fn softclip(x: Number, drive: Number) -> Number {
+    if drive <= 0.0 {
+        x
+    } else {
+        (x * drive).tanh() / drive.tanh()
+    }
+}
+
+/// Quantizes a signal in [-1, 1] to the given bit depth.
+pub fn quantize(x: Number, bits: Number) -> Number {
+    let steps = 2f64.powf(bits.max(1.0)) / 2.0;
+    (x * steps).round() / steps
+}
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    compiler.define_table("shape_tanh", Table::from_fn(1024, |x| (3.0 * x).tanh()));
+    compiler.define_table("shape_fold", Table::from_fn(1024, |x| (x * PI).sin()));
+    compiler.define_table("shape_cheby3", Table::from_fn(1024, |x| 4.0*x*x*x - 3.0*x));
+
+    compiler.define_native_function("softclip", &["x", "drive"], |args, _| {
+        softclip(args[0], args[1])
+    });
+
+    // Looks the signal up in a table defined with Compiler::define_table. Unknown tables leave
+    // the signal untouched.
+    let tables = Arc::new(compiler.tables());
+    compiler.define_native_function("waveshape", &["x", "table", "interpolation"], move |args, _| {
+        match tables.get(args[1] as usize) {
+            Some(table) if args[1] >= 0.0 => table.lookup_with(args[0], Interpolation::from_number(args[2])),
+            _ => args[0],
+        }
+    });
+    interpolate::default_to_setting(compiler, "waveshape");
+
+    // Reduces bit depth, and holds samples to reduce the sample rate to `rate` Hz.
+    compiler.define_native_function("bitcrush", &["x", "bits", "rate"], |args, state| {
+        let step = args[2] / state.sample_rate() as Number;
+        let mem = state.memory(2);
+        let (phase, held) = mem.split_at_mut(1);
+        phase[0] += step;
+        if phase[0] >= 1.0 || step >= 1.0 {
+            phase[0] %= 1.0;
+            held[0] = quantize(args[0], args[1]);
+        }
+        held[0]
+    });
+}
@@ -0,0 +1,56 @@
+use super::super::ast::Expression;
+use super::super::compiler::Compiler;
+use super::super::tokens::{Number, Node, SourcePos};
+
+use std::f64;
+
+/// The coefficient of a one-pole filter which covers about 63% of the way to its input in the
+/// given number of seconds. Times of 0 jump straight to it.
+pub fn coefficient(time: Number, sample_rate: u32) -> Number {
+    if time <= 0.0 {
+        0.0
+    } else {
+        (-1.0 / (time * sample_rate as Number)).exp()
+    }
+}
+
+/// Moves the level of an envelope detector one sample towards the absolute value of `input`,
+/// with the attack coefficient when rising and the release one when falling.
+pub fn follow(level: Number, input: Number, attack: Number, release: Number) -> Number {
+    let input = input.abs();
+    let a = if input > level { attack } else { release };
+    input + (level - input) * a
+}
+
+pub fn to_db(amplitude: Number) -> Number {
+    20.0 * amplitude.max(1e-12).log10()
+}
+
+pub fn from_db(db: Number) -> Number {
+    10f64.powf(db / 20.0)
+}
+
+/// The gain in dB for a detected level in dB. A ratio of 4 lets through 1 dB for every 4 above the
+/// threshold, and one of 0.5 takes away 1 dB for every 1 below it.
+pub fn gain_db(level: Number, threshold: Number, ratio: Number) -> Number {
+    let over = level - threshold;
+    if (ratio >= 1.0 && over > 0.0) || (ratio < 1.0 && over < 0.0) {
+        over * (1.0 / ratio.max(1e-3) - 1.0)
+    } else {
+        0.0
+    }
+}
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    // The level is detected from `sidechain`, which defaults to the signal itself. Feeding it a
+    // kick drum ducks the signal in time with it.
+    compiler.define_native_function("compress", &["signal", "threshold", "ratio", "attack", "release",
+                                                  "sidechain"], |args, state| {
+        let sample_rate = state.sample_rate();
+        let detected = if args[5].is_nan() { args[0] } else { args[5] };
+        let mem = state.memory(1); // the detected level
+        mem[0] = follow(mem[0], detected, coefficient(args[3], sample_rate), coefficient(args[4], sample_rate));
+        args[0] * from_db(gain_db(to_db(mem[0]), args[1], args[2]))
+    });
+    compiler.set_default_arg("compress", "sidechain", Expression::Constant(Node(f64::NAN, SourcePos::anon())));
+}
@@ -0,0 +1,172 @@
+use super::super::tokens::Number;
+
+use std::f64::consts::PI;
+
+/// In place radix-2 FFT. The length of both slices must be the same power of two.
+pub fn fft(re: &mut [Number], im: &mut [Number]) {
+    transform(re, im, false);
+}
+
+/// In place inverse of fft(), including the 1/n scaling.
+pub fn ifft(re: &mut [Number], im: &mut [Number]) {
+    transform(re, im, true);
+    let scale = 1.0 / re.len() as Number;
+    for i in 0..re.len() {
+        re[i] *= scale;
+        im[i] *= scale;
+    }
+}
+
+fn transform(re: &mut [Number], im: &mut [Number], inverse: bool) {
+    let n = re.len();
+    assert!(n == im.len() && n.is_power_of_two(), "fft size must be a power of two");
+
+    // bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * PI / len as Number;
+        let (w_im, w_re) = angle.sin_cos();
+        for start in (0..n).filter(|x| x % len == 0) {
+            let (mut cur_re, mut cur_im) = (1.0, 0.0);
+            for k in 0..len/2 {
+                let (a, b) = (start + k, start + k + len/2);
+                let t_re = re[b]*cur_re - im[b]*cur_im;
+                let t_im = re[b]*cur_im + im[b]*cur_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+                let next_re = cur_re*w_re - cur_im*w_im;
+                cur_im = cur_re*w_im + cur_im*w_re;
+                cur_re = next_re;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Periodic Hann window, which sums to a constant when overlapped at a quarter of its length.
+pub fn hann(i: usize, n: usize) -> Number {
+    0.5 - 0.5 * (2.0 * PI * i as Number / n as Number).cos()
+}
+
+/// Short time Fourier transform with overlap-add resynthesis. All buffers, including those the
+/// spectrum is transformed in, live in a slice of state memory so that they persist across samples
+/// and are captured in snapshots, and nothing is allocated while it runs.
+///
+/// Output is delayed by `size` samples.
+pub struct Stft {
+    size: usize,
+    hop: usize,
+}
+
+const POS: usize = 0;
+const COUNT: usize = 1;
+const OUTPUT: usize = 2;
+const HEADER_LEN: usize = 3;
+
+impl Stft {
+    pub fn new(size: usize) -> Stft {
+        Stft {
+            size: size,
+            hop: size / 4,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+    pub fn hop(&self) -> usize {
+        self.hop
+    }
+
+    /// The amount of memory needed, given `extra` values of memory for the spectral process.
+    pub fn memory_len(&self, extra: usize) -> usize {
+        HEADER_LEN + 4*self.size + extra
+    }
+
+    /// Feeds a single sample and returns a single output sample. Once every hop the spectrum of
+    /// the last `size` samples is passed to `process` along with the extra memory.
+    pub fn next<F>(&self, mem: &mut [Number], x: Number, mut process: F) -> Number
+            where F: FnMut(&mut [Number], &mut [Number], &mut [Number]) {
+        let due = self.push(mem, x);
+        if due {
+            let (re, im, extra) = self.spectrum(mem);
+            process(re, im, extra);
+        }
+        self.output(mem, due)
+    }
+
+    /// Feeds a single sample. Returns true once every hop, when the spectrum of the last `size`
+    /// samples is ready to be changed through spectrum() before output() resynthesizes it.
+    pub fn push(&self, mem: &mut [Number], x: Number) -> bool {
+        let n = self.size;
+        let (header, rest) = mem.split_at_mut(HEADER_LEN);
+        let (input, rest) = rest.split_at_mut(n);
+        let (output, rest) = rest.split_at_mut(n);
+        let (re, rest) = rest.split_at_mut(n);
+        let im = &mut rest[..n];
+
+        let pos = header[POS] as usize;
+        input[pos] = x;
+        header[OUTPUT] = output[pos];
+        output[pos] = 0.0;
+        let pos = (pos + 1) % n;
+        header[POS] = pos as Number;
+        header[COUNT] += 1.0;
+        if (header[COUNT] as usize) < self.hop {
+            return false;
+        }
+        header[COUNT] = 0.0;
+        for i in 0..n {
+            re[i] = input[(pos + i) % n] * hann(i, n);
+            im[i] = 0.0;
+        }
+        fft(re, im);
+        true
+    }
+
+    /// The real and imaginary parts of the spectrum, and the extra memory.
+    pub fn spectrum<'m>(&self, mem: &'m mut [Number])
+            -> (&'m mut [Number], &'m mut [Number], &'m mut [Number]) {
+        let n = self.size;
+        let (re, rest) = mem[HEADER_LEN + 2*n..].split_at_mut(n);
+        let (im, extra) = rest.split_at_mut(n);
+        (re, im, extra)
+    }
+
+    /// Returns the output sample for the sample last pushed, after resynthesizing the spectrum if
+    /// push() returned true.
+    pub fn output(&self, mem: &mut [Number], due: bool) -> Number {
+        let n = self.size;
+        let (header, rest) = mem.split_at_mut(HEADER_LEN);
+        let (output, rest) = rest[n..].split_at_mut(n);
+        let (re, rest) = rest.split_at_mut(n);
+        let im = &mut rest[..n];
+        if due {
+            ifft(re, im);
+            // hann squared overlapped at a quarter of its length sums to 1.5
+            let norm = 1.0 / 1.5;
+            let pos = header[POS] as usize;
+            for i in 0..n {
+                output[(pos + i) % n] += re[i] * hann(i, n) * norm;
+            }
+        }
+        header[OUTPUT]
+    }
+}
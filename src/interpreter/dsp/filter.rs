@@ -0,0 +1,47 @@
+use super::super::compiler::Compiler;
+use super::super::runtime::{self, CallSite};
+use super::super::tokens::Number;
+use super::super::types::Type;
+
+use std::f64::consts::PI;
+use std::slice;
+
+/// The lowest resonance `svf` takes, below which it would be unstable.
+pub const MIN_Q: Number = 0.01;
+
+/// Runs a trapezoidal state variable filter one sample on from the integrator states `ic`,
+/// returning the lowpass, bandpass and highpass outputs. The cutoff is kept below the Nyquist
+/// frequency, where the filter would blow up.
+pub fn svf(x: Number, freq: Number, q: Number, sample_rate: u32, ic: &mut [Number]) -> (Number, Number, Number) {
+    let nyquist = sample_rate as Number * 0.5;
+    let g = (PI * freq.max(0.0).min(nyquist * 0.99) / sample_rate as Number).tan();
+    let k = 1.0 / q.max(MIN_Q);
+    let a1 = 1.0 / (1.0 + g * (g + k));
+    let (a2, a3) = (g * a1, g * g * a1);
+    let v3 = x - ic[1];
+    let v1 = a1 * ic[0] + a2 * v3;
+    let v2 = ic[1] + a2 * ic[0] + a3 * v3;
+    ic[0] = 2.0 * v1 - ic[0];
+    ic[1] = 2.0 * v2 - ic[1];
+    (v2, v1, x - k * v1 - v2)
+}
+
+/// The implementation of `svf`.
+pub extern fn svf_builtin(site: CallSite, x: Number, freq: Number, q: Number, out: *mut Number) {
+    let (lp, bp, hp) = runtime::with_site_state(site, |state| {
+        let sample_rate = state.sample_rate();
+        svf(x, freq, q, sample_rate, state.memory(2))
+    });
+    let out = unsafe { slice::from_raw_parts_mut(out, 3) };
+    out[0] = lp;
+    out[1] = bp;
+    out[2] = hp;
+}
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    unsafe {
+        compiler.define_multi_output_builtin("svf", &[("x", Type::Number), ("freq", Type::Number),
+                                                      ("q", Type::Number)],
+                                             &["lp", "bp", "hp"], svf_builtin as *mut ());
+    }
+}
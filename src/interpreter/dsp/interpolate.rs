@@ -0,0 +1,90 @@
+use super::super::ast::Expression;
+use super::super::audio::resample::kernel;
+use super::super::compiler::Compiler;
+use super::super::tokens::{Number, Node, SourcePos};
+
+// Number of zero crossings of the sinc kernel on each side of the position.
+const ZERO_CROSSINGS: isize = 8;
+
+/// How to read between samples, chosen with `--interpolation` or the `interpolation` argument of a
+/// call. Linear is the cheapest, and sinc the closest to band-limited.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    Cubic,
+    Sinc,
+}
+
+impl Interpolation {
+    pub fn parse(s: &str) -> Option<Interpolation> {
+        Some(match s {
+            "linear" => Interpolation::Linear,
+            "cubic" => Interpolation::Cubic,
+            "sinc" => Interpolation::Sinc,
+            _ => return None,
+        })
+    }
+
+    /// The mode passed as an `interpolation` argument. Numbers which name no mode read linearly.
+    pub fn from_number(x: Number) -> Interpolation {
+        match x.round() as i64 {
+            2 => Interpolation::Cubic,
+            3 => Interpolation::Sinc,
+            _ => Interpolation::Linear,
+        }
+    }
+
+    /// The value of the constant naming the mode in source.
+    pub fn to_number(self) -> Number {
+        match self {
+            Interpolation::Linear => 1.0,
+            Interpolation::Cubic => 2.0,
+            Interpolation::Sinc => 3.0,
+        }
+    }
+}
+
+/// The value at `position` between the samples given by `sample`, which is called with the index
+/// of each sample needed and must deal with those out of range itself, such as by wrapping round.
+pub fn interpolate<F>(mode: Interpolation, position: Number, sample: F) -> Number where F: Fn(isize) -> Number {
+    let whole = position.floor();
+    let frac = position - whole;
+    let i = whole as isize;
+    match mode {
+        Interpolation::Linear => {
+            let a = sample(i);
+            a + (sample(i + 1) - a) * frac
+        }
+        Interpolation::Cubic => {
+            let (y0, y1, y2, y3) = (sample(i - 1), sample(i), sample(i + 1), sample(i + 2));
+            let c1 = 0.5 * (y2 - y0);
+            let c2 = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+            let c3 = 0.5 * (y3 - y0) + 1.5 * (y1 - y2);
+            ((c3 * frac + c2) * frac + c1) * frac + y1
+        }
+        Interpolation::Sinc => {
+            let half_width = ZERO_CROSSINGS as Number;
+            (1 - ZERO_CROSSINGS..ZERO_CROSSINGS + 1).fold(0.0, |sum, k| {
+                sum + sample(i + k) * kernel(1.0, half_width, frac - k as Number)
+            })
+        }
+    }
+}
+
+/// Defines the constants which name the modes in source.
+pub fn define_intrinsics(compiler: &Compiler) {
+    for &mode in &[Interpolation::Linear, Interpolation::Cubic, Interpolation::Sinc] {
+        let name = match mode {
+            Interpolation::Linear => "interp_linear",
+            Interpolation::Cubic => "interp_cubic",
+            Interpolation::Sinc => "interp_sinc",
+        };
+        compiler.define_global_constant(name, mode.to_number());
+    }
+}
+
+/// Makes the `interpolation` argument of a builtin default to the mode chosen for the program.
+pub fn default_to_setting(compiler: &Compiler, func: &str) {
+    let mode = compiler.context().options.borrow().interpolation;
+    compiler.set_default_arg(func, "interpolation", Expression::Constant(Node(mode.to_number(), SourcePos::anon())));
+}
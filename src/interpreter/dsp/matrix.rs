@@ -0,0 +1,140 @@
+use super::super::compiler::Compiler;
+use super::super::runtime::CallSite;
+use super::super::tokens::Number;
+use super::super::types::Type;
+use super::ambisonics;
+
+use std::slice;
+
+/// The most rows and columns of a matrix.
+pub const MAX_SIZE: usize = 8;
+
+/// The builtins whose arguments must be of sizes which fit together, as `check_sizes` checks.
+pub const SIZED_BUILTINS: &'static [&'static str] = &["dot", "matmul", "channels", "foa_rotate", "foa_decode"];
+
+/// Checks that the arrays and signals given to one of `SIZED_BUILTINS` fit together, from the
+/// name of each argument which is one and its length or number of channels.
+pub fn check_sizes(name: &str, sizes: &[(&str, usize)]) -> Result<(), String> {
+    let size = |arg: &str| sizes.iter().find(|x| x.0 == arg).map(|x| x.1);
+    match name {
+        "dot" => match (size("a"), size("b")) {
+            (Some(a), Some(b)) if a != b =>
+                return Err(format!("cannot take the dot product of signals of {} and {} channels", a, b)),
+            _ => { }
+        },
+        "matmul" => match (size("matrix"), size("v")) {
+            (_, Some(n)) if n > MAX_SIZE =>
+                return Err(format!("matrices are at most {} by {}, but the signal has {} channels",
+                                   MAX_SIZE, MAX_SIZE, n)),
+            (Some(len), Some(n)) if len != n * n =>
+                return Err(format!("a matrix for a signal of {} channels has {} elements, not {}", n, n * n, len)),
+            _ => { }
+        },
+        "channels" => match size("values") {
+            Some(len) if len < 2 =>
+                return Err("a signal of several channels needs at least 2 values; one channel is a number".into()),
+            _ => { }
+        },
+        _ => {
+            match size("b") {
+                Some(len) if len != ambisonics::CHANNELS =>
+                    return Err(format!("`{}` takes a first order ambisonic signal of {} channels, not {}",
+                                       name, ambisonics::CHANNELS, len)),
+                _ => { }
+            }
+            match size("azimuths") {
+                Some(len) if len < 2 => return Err("decoding needs at least 2 speakers".into()),
+                _ => { }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Multiplies a vector by a square matrix given row by row, writing the result to `out`.
+pub fn matmul(matrix: &[Number], v: &[Number], out: &mut [Number]) {
+    let n = v.len();
+    for (row, out) in matrix.chunks(n).zip(out.iter_mut()) {
+        *out = row.iter().zip(v).fold(0.0, |sum, (a, b)| sum + a * b);
+    }
+}
+
+/// The implementation of `dot`.
+pub extern fn dot(_: CallSite, a: *const Number, a_len: usize, b: *const Number, b_len: usize) -> Number {
+    let (a, b) = unsafe { (slice::from_raw_parts(a, a_len), slice::from_raw_parts(b, b_len)) };
+    a.iter().zip(b).fold(0.0, |sum, (x, y)| sum + x * y)
+}
+
+/// The implementation of `matmul`.
+pub extern fn matmul_builtin(_: CallSite, matrix: *const Number, matrix_len: usize, v: *const Number, v_len: usize,
+                             out: *mut Number) {
+    unsafe {
+        matmul(slice::from_raw_parts(matrix, matrix_len), slice::from_raw_parts(v, v_len),
+               slice::from_raw_parts_mut(out, v_len));
+    }
+}
+
+/// The implementation of `channels`, which makes a signal of a channel for each element of an
+/// array.
+pub extern fn channels(_: CallSite, values: *const Number, len: usize, out: *mut Number) {
+    unsafe { slice::from_raw_parts_mut(out, len).copy_from_slice(slice::from_raw_parts(values, len)) }
+}
+
+/// The implementation of `channel`, which gives the channel of a signal at `index` rounded down.
+/// Indices outside of the signal give the nearest channel, like `select`.
+pub extern fn channel(_: CallSite, signal: *const Number, len: usize, index: Number) -> Number {
+    let signal = unsafe { slice::from_raw_parts(signal, len) };
+    // NaN is cast to 0
+    signal[(index.max(0.0) as usize).min(len - 1)]
+}
+
+unsafe fn stereo_pair(signal: *const Number, len: usize) -> (Number, Number) {
+    let signal = slice::from_raw_parts(signal, len);
+    (signal[0], signal[if len > 1 { 1 } else { 0 }])
+}
+
+unsafe fn write(out: *mut Number, (a, b): (Number, Number)) {
+    let out = slice::from_raw_parts_mut(out, 2);
+    out[0] = a;
+    out[1] = b;
+}
+
+/// The implementations of `midside`, which turns a stereo signal into its mid and side channels,
+/// and `leftright`, which turns them back.
+pub extern fn midside(_: CallSite, signal: *const Number, len: usize, out: *mut Number) {
+    unsafe {
+        let (left, right) = stereo_pair(signal, len);
+        write(out, ((left + right) * 0.5, (left - right) * 0.5))
+    }
+}
+pub extern fn leftright(_: CallSite, signal: *const Number, len: usize, out: *mut Number) {
+    unsafe {
+        let (mid, side) = stereo_pair(signal, len);
+        write(out, (mid + side, mid - side))
+    }
+}
+
+/// The implementation of `rotation`, the 2 by 2 matrix which rotates a stereo field by `angle`
+/// radians, moving the left channel towards the right.
+pub extern fn rotation(_: CallSite, angle: Number, out: *mut Number) {
+    let (sin, cos) = angle.sin_cos();
+    unsafe { slice::from_raw_parts_mut(out, 4).copy_from_slice(&[cos, -sin, sin, cos]) }
+}
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    let signal = [("signal", Type::Channels(0))];
+    unsafe {
+        compiler.define_direct_builtin("dot", &[("a", Type::Channels(0)), ("b", Type::Channels(0))],
+                                       Type::Number, dot as *mut ());
+        compiler.define_channel_builtin("matmul", &[("matrix", Type::Array(0)), ("v", Type::Channels(0))],
+                                        "v", matmul_builtin as *mut ());
+        compiler.define_channel_builtin("channels", &[("values", Type::Array(0))], "values",
+                                        channels as *mut ());
+        compiler.define_direct_builtin("channel", &[("signal", Type::Channels(0)), ("index", Type::Number)],
+                                       Type::Number, channel as *mut ());
+        compiler.define_direct_builtin("midside", &signal, Type::Channels(2), midside as *mut ());
+        compiler.define_direct_builtin("leftright", &signal, Type::Channels(2), leftright as *mut ());
+        compiler.define_direct_builtin("rotation", &[("angle", Type::Number)], Type::Array(4),
+                                       rotation as *mut ());
+    }
+}
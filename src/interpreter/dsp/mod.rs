@@ -0,0 +1,65 @@
+use super::compiler::Compiler;
+use super::tokens::Number;
+
+use std::mem;
+
+pub mod fft;
+pub mod delay;
+pub mod table;
+pub mod interpolate;
+pub mod distortion;
+pub mod smooth;
+pub mod trigger;
+pub mod pitch;
+pub mod stereo;
+pub mod oversample;
+pub mod osc;
+pub mod dynamics;
+pub mod vocoder;
+pub mod physical;
+pub mod arp;
+pub mod stretch;
+pub mod convolve;
+pub mod filter;
+pub mod complex;
+pub mod bytebeat;
+pub mod matrix;
+pub mod ambisonics;
+pub mod surround;
+pub mod spectral;
+mod reverb;
+mod chorus;
+mod noise;
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    interpolate::define_intrinsics(compiler);
+    spectral::define_intrinsics(compiler);
+    reverb::define_intrinsics(compiler);
+    chorus::define_intrinsics(compiler);
+    distortion::define_intrinsics(compiler);
+    noise::define_intrinsics(compiler);
+    smooth::define_intrinsics(compiler);
+    trigger::define_intrinsics(compiler);
+    pitch::define_intrinsics(compiler);
+    stereo::define_intrinsics(compiler);
+    osc::define_intrinsics(compiler);
+    dynamics::define_intrinsics(compiler);
+    vocoder::define_intrinsics(compiler);
+    physical::define_intrinsics(compiler);
+    arp::define_intrinsics(compiler);
+    stretch::define_intrinsics(compiler);
+    convolve::define_intrinsics(compiler);
+    filter::define_intrinsics(compiler);
+    complex::define_intrinsics(compiler);
+    bytebeat::define_intrinsics(compiler);
+    matrix::define_intrinsics(compiler);
+    ambisonics::define_intrinsics(compiler);
+    surround::define_intrinsics(compiler);
+}
+
+/// Splits `len` values off the front of a slice of state memory.
+pub fn take<'a>(mem: &mut &'a mut [Number], len: usize) -> &'a mut [Number] {
+    let (front, back) = mem::replace(mem, &mut []).split_at_mut(len);
+    *mem = back;
+    front
+}
@@ -0,0 +1,33 @@
+use super::super::compiler::Compiler;
+use super::trigger::fired;
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    // White noise in [-1, 1).
+    compiler.define_native_function("noise", &[], |_, state| {
+        state.rng().range(-1.0, 1.0)
+    });
+
+    // A new random number in [0, 1) on every call.
+    compiler.define_native_function("random", &[], |_, state| {
+        state.rng().next_number()
+    });
+
+    // Fires with probability `p` each time `trigger` does.
+    compiler.define_native_function("prob", &["trigger", "p"], |args, state| {
+        if fired(args[0]) && state.rng().next_number() < args[1] { 1.0 } else { 0.0 }
+    });
+
+    // A random number in [lo, hi) drawn each time `trigger` fires and held until the next time.
+    // Before the first trigger it is `lo`.
+    compiler.define_native_function("randrange", &["trigger", "lo", "hi"], |args, state| {
+        let (lo, hi) = (args[1], args[2]);
+        if fired(args[0]) {
+            let value = state.rng().range(lo, hi);
+            let mem = state.memory(2); // the value, whether one was drawn
+            mem[0] = value;
+            mem[1] = 1.0;
+        }
+        let mem = state.memory(2);
+        if mem[1] != 0.0 { mem[0] } else { lo }
+    });
+}
@@ -0,0 +1,51 @@
+use super::super::compiler::Compiler;
+use super::super::tokens::Number;
+use super::trigger;
+
+use std::f64::consts::PI;
+
+/// Wraps a number into [0, 1), so that phases beyond a cycle start it over.
+pub fn wrap(x: Number) -> Number {
+    x - x.floor()
+}
+
+/// Reflects a number back and forth between 0 and 1, so that a rising phase of twice the
+/// frequency makes a triangle.
+pub fn fold(x: Number) -> Number {
+    let y = wrap(x * 0.5) * 2.0;
+    if y > 1.0 { 2.0 - y } else { y }
+}
+
+/// The sine of a phase, completing a cycle from 0 to 1.
+pub fn sin01(phase: Number) -> Number {
+    (phase * 2.0 * PI).sin()
+}
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    compiler.define_native_function("wrap", &["x"], |args, _| wrap(args[0]));
+    compiler.define_native_function("fold", &["x"], |args, _| fold(args[0]));
+    compiler.define_native_function("sin01", &["phase"], |args, _| sin01(args[0]));
+
+    // A ramp from 0 to 1 which advances by `freq` cycles a second, starting at 0. Negative
+    // frequencies run it backwards.
+    compiler.define_native_function("phasor", &["freq"], |args, state| {
+        let step = args[0] / state.sample_rate() as Number;
+        let mem = state.memory(1); // phase
+        let phase = mem[0];
+        mem[0] = wrap(phase + step);
+        phase
+    });
+
+    // Hard sync: the phase starts its cycle over from 0 each time `trigger` fires, typically from
+    // a second oscillator, and otherwise runs on as it would have.
+    compiler.define_native_function("sync", &["phase", "trigger"], |args, state| {
+        let mem = state.memory(1); // the phase at the last restart
+        if trigger::fired(args[1]) {
+            mem[0] = args[0];
+        }
+        wrap(args[0] - mem[0])
+    });
+
+    // Ring modulation, which gives the sum and difference frequencies of its inputs.
+    compiler.define_native_function("ringmod", &["a", "b"], |args, _| args[0] * args[1]);
+}
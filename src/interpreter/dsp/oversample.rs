@@ -0,0 +1,76 @@
+use super::super::audio::resample;
+use super::super::runtime::{self, CallSite, State};
+use super::super::tokens::Number;
+
+/// The largest factor an expression may be oversampled by.
+pub const MAX_FACTOR: usize = 16;
+
+// Number of zero crossings of the decimation kernel on each side of its center, at the output
+// rate. Fewer than the offline resampler uses, to keep the delay short.
+const ZERO_CROSSINGS: usize = 4;
+
+fn taps(factor: usize) -> usize {
+    2 * ZERO_CROSSINGS * factor + 1
+}
+
+// The memory of a section holds whether its kernel has been made, where the next result goes in
+// its history, the kernel, the history of results and then the previous value of each input.
+fn section_memory(state: &mut State, factor: usize, inputs: usize) -> &mut [Number] {
+    let taps = taps(factor);
+    let mem = state.memory(2 + 2 * taps + inputs);
+    if mem[0] == 0.0 {
+        let half_width = (ZERO_CROSSINGS * factor) as Number;
+        let cutoff = 1.0 / factor as Number;
+        for i in 0..taps {
+            mem[2 + i] = resample::kernel(cutoff, half_width, i as Number - half_width);
+        }
+        mem[0] = 1.0;
+    }
+    mem
+}
+
+/// Called by generated code before an oversampled section for each number it reads from outside,
+/// with its value in this sample. Returns its value in the previous sample to interpolate from.
+pub extern fn oversample_input(site: CallSite, factor: usize, index: usize, value: Number) -> Number {
+    runtime::with_site_state(site, |state| {
+        let offset = 2 + 2 * taps(factor) + index;
+        let mem = section_memory(state, factor, index + 1);
+        let prev = mem[offset];
+        mem[offset] = value;
+        prev
+    })
+}
+
+/// Called by generated code before the first evaluation of an oversampled section, so that the
+/// intrinsics in it run at the raised rate.
+pub extern fn oversample_enter(site: CallSite, factor: usize) {
+    runtime::with_site_state(site, |state| {
+        let rate = state.sample_rate() * factor as u32;
+        state.set_sample_rate(rate);
+    })
+}
+
+/// Called by generated code with the result of each evaluation of an oversampled section.
+pub extern fn oversample_push(site: CallSite, factor: usize, value: Number) {
+    runtime::with_site_state(site, |state| {
+        let taps = taps(factor);
+        let mem = section_memory(state, factor, 0);
+        let pos = mem[1] as usize % taps;
+        mem[2 + taps + pos] = value;
+        mem[1] = ((pos + 1) % taps) as Number;
+    })
+}
+
+/// Called by generated code after the last evaluation of an oversampled section. Restores the
+/// sample rate and returns the filtered result for this sample.
+pub extern fn oversample_output(site: CallSite, factor: usize) -> Number {
+    runtime::with_site_state(site, |state| {
+        let rate = state.sample_rate() / factor as u32;
+        state.set_sample_rate(rate);
+        let taps = taps(factor);
+        let mem = section_memory(state, factor, 0);
+        let pos = mem[1] as usize;
+        let (kernel, history) = mem[2..2 + 2 * taps].split_at(taps);
+        kernel.iter().enumerate().fold(0.0, |sum, (i, k)| sum + k * history[(pos + i) % taps])
+    })
+}
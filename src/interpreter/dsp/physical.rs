@@ -0,0 +1,85 @@
+use super::super::compiler::Compiler;
+use super::super::runtime::{self, CallSite};
+use super::super::tokens::Number;
+use super::super::types::Type;
+use super::delay::DelayLine;
+use super::interpolate::{self, Interpolation};
+use super::take;
+use super::trigger;
+
+use std::f64::consts::PI;
+use std::slice;
+
+/// The lowest frequency a string can be tuned to.
+pub const LOWEST_FREQ: Number = 20.0;
+
+/// The length in samples of the loop of a string tuned to `freq`, less the delay of its lowpass,
+/// so that it rings at `freq` between samples too.
+pub fn string_delay(freq: Number, sample_rate: u32, smoothing: Number) -> Number {
+    sample_rate as Number / freq.max(LOWEST_FREQ) - smoothing
+}
+
+/// The coefficients `(2r cos w, r^2)` of a two-pole resonator at `freq` which falls by 60 dB in
+/// `decay` seconds.
+pub fn resonator(freq: Number, decay: Number, sample_rate: u32) -> (Number, Number) {
+    let w = 2.0 * PI * freq / sample_rate as Number;
+    let r = if decay > 0.0 { 10f64.powf(-3.0 / (decay * sample_rate as Number)) } else { 0.0 };
+    (2.0 * r * w.cos(), r * r)
+}
+
+/// The implementation of `modal`, which rings a resonator for each of `freqs` with the decay at
+/// the same index of `decays`. An impulse rings each at an amplitude of 1. Extra frequencies or
+/// decays are left out.
+pub extern fn modal(site: CallSite, exciter: Number, freqs: *const Number, freqs_len: usize,
+                    decays: *const Number, decays_len: usize) -> Number {
+    let freqs = unsafe { slice::from_raw_parts(freqs, freqs_len) };
+    let decays = unsafe { slice::from_raw_parts(decays, decays_len) };
+    runtime::with_site_state(site, |state| {
+        let sample_rate = state.sample_rate();
+        let modes = freqs.len().min(decays.len());
+        let mem = state.memory(2 * modes); // the last two outputs of each resonator
+        let mut out = 0.0;
+        for (i, (&freq, &decay)) in freqs.iter().zip(decays.iter()).enumerate() {
+            let (a1, a2) = resonator(freq, decay, sample_rate);
+            let gain = (2.0 * PI * freq / sample_rate as Number).sin();
+            let y = exciter * gain + a1 * mem[2 * i] - a2 * mem[2 * i + 1];
+            mem[2 * i + 1] = mem[2 * i];
+            mem[2 * i] = y;
+            out += y;
+        }
+        out
+    })
+}
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    // Each time `trigger` fires the string is filled with noise, which it then plays at `freq`.
+    // `damping` from 0 to 1 sets how quickly the high partials fade: with 0 the string rings on
+    // unchanged, and with 1 it averages each pair of samples as it goes round.
+    compiler.define_native_function("pluck", &["trigger", "freq", "damping", "interpolation"], |args, state| {
+        let (freq, smoothing) = (args[1], args[2].max(0.0).min(1.0) * 0.5);
+        let sample_rate = state.sample_rate();
+        let max_delay = (sample_rate as Number / LOWEST_FREQ) as usize + 2;
+        let len = 1 + DelayLine::memory_len(max_delay);
+        if trigger::fired(args[0]) {
+            for i in 0..max_delay {
+                let noise = state.rng().next_number() * 2.0 - 1.0;
+                state.memory(len)[2 + i] = noise;
+            }
+        }
+
+        let mut mem = state.memory(len);
+        let last = take(&mut mem, 1);
+        let mut line = DelayLine::new(mem);
+        let y = line.read_frac(string_delay(freq, sample_rate, smoothing), Interpolation::from_number(args[3]));
+        line.write(y * (1.0 - smoothing) + last[0] * smoothing);
+        last[0] = y;
+        y
+    });
+    interpolate::default_to_setting(compiler, "pluck");
+
+    unsafe {
+        compiler.define_direct_builtin("modal", &[("exciter", Type::Number), ("freqs", Type::Array(0)),
+                                                  ("decays", Type::Array(0))],
+                                       Type::Number, modal as *mut ());
+    }
+}
@@ -0,0 +1,114 @@
+use super::super::compiler::Compiler;
+use super::super::runtime::{self, CallSite};
+use super::super::tokens::Number;
+use super::super::types::Type;
+
+use std::f64;
+
+pub const SCALES: &'static [(&'static str, &'static [Number])] = &[
+    ("major", &[0.0, 2.0, 4.0, 5.0, 7.0, 9.0, 11.0]),
+    ("minor", &[0.0, 2.0, 3.0, 5.0, 7.0, 8.0, 10.0]),
+    ("harmonic_minor", &[0.0, 2.0, 3.0, 5.0, 7.0, 8.0, 11.0]),
+    ("melodic_minor", &[0.0, 2.0, 3.0, 5.0, 7.0, 9.0, 11.0]),
+    ("dorian", &[0.0, 2.0, 3.0, 5.0, 7.0, 9.0, 10.0]),
+    ("phrygian", &[0.0, 1.0, 3.0, 5.0, 7.0, 8.0, 10.0]),
+    ("lydian", &[0.0, 2.0, 4.0, 6.0, 7.0, 9.0, 11.0]),
+    ("mixolydian", &[0.0, 2.0, 4.0, 5.0, 7.0, 9.0, 10.0]),
+    ("locrian", &[0.0, 1.0, 3.0, 5.0, 6.0, 8.0, 10.0]),
+    ("pentatonic", &[0.0, 2.0, 4.0, 7.0, 9.0]),
+    ("minor_pentatonic", &[0.0, 3.0, 5.0, 7.0, 10.0]),
+    ("blues", &[0.0, 3.0, 5.0, 6.0, 7.0, 10.0]),
+    ("whole_tone", &[0.0, 2.0, 4.0, 6.0, 8.0, 10.0]),
+    ("chromatic", &[0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0]),
+];
+
+pub const CHORDS: &'static [(&'static str, &'static [Number])] = &[
+    ("maj", &[0.0, 4.0, 7.0]),
+    ("min", &[0.0, 3.0, 7.0]),
+    ("dim", &[0.0, 3.0, 6.0]),
+    ("aug", &[0.0, 4.0, 8.0]),
+    ("sus2", &[0.0, 2.0, 7.0]),
+    ("sus4", &[0.0, 5.0, 7.0]),
+    ("maj7", &[0.0, 4.0, 7.0, 11.0]),
+    ("min7", &[0.0, 3.0, 7.0, 10.0]),
+    ("7", &[0.0, 4.0, 7.0, 10.0]),
+    ("dim7", &[0.0, 3.0, 6.0, 9.0]),
+    ("min7b5", &[0.0, 3.0, 6.0, 10.0]),
+];
+
+/// Looks up a scale or chord by name in one of the tables above.
+pub fn find(table: &[(&'static str, &'static [Number])], name: &str) -> Option<&'static [Number]> {
+    table.iter().find(|x| x.0 == name).map(|x| x.1)
+}
+
+pub fn mtof(note: Number) -> Number {
+    440.0 * ((note - 69.0) / 12.0).exp2()
+}
+
+pub fn ftom(freq: Number) -> Number {
+    69.0 + 12.0 * (freq / 440.0).log2()
+}
+
+/// The note at a step of a scale or chord, counting from 0 at the root. Steps past the end
+/// continue in the octaves above, and negative steps in the octaves below.
+pub fn step(intervals: &[Number], root: Number, index: Number) -> Number {
+    let len = intervals.len() as isize;
+    let step = index.round() as isize;
+    let octave = if step < 0 { (step + 1) / len - 1 } else { step / len };
+    root + intervals[(step - octave * len) as usize] + 12.0 * octave as Number
+}
+
+/// The note of a scale on C which is nearest to the given one.
+pub fn quantize(intervals: &[Number], note: Number) -> Number {
+    let octave = (note / 12.0).floor();
+    let mut nearest = note;
+    let mut distance = f64::INFINITY;
+    for o in &[octave - 1.0, octave, octave + 1.0] {
+        for interval in intervals {
+            let candidate = o * 12.0 + interval;
+            if (candidate - note).abs() < distance {
+                nearest = candidate;
+                distance = (candidate - note).abs();
+            }
+        }
+    }
+    nearest
+}
+
+// Unknown names can only get this far through strings which were not known while compiling, and
+// leave every note in place.
+fn intervals(table: &[(&'static str, &'static [Number])], site: CallSite, name: usize) -> &'static [Number] {
+    runtime::with_site_state(site, |state| find(table, state.string(name))).unwrap_or(find(SCALES, "chromatic").unwrap())
+}
+
+/// The implementation of `scale`, which gives the note at a step of the named scale.
+pub extern fn scale(site: CallSite, degree: Number, mode: usize, root: Number) -> Number {
+    step(intervals(SCALES, site, mode), root, degree)
+}
+
+/// The implementation of `chord`, which gives one voice of the named chord.
+pub extern fn chord(site: CallSite, root: Number, quality: usize, voice: Number) -> Number {
+    step(intervals(CHORDS, site, quality), root, voice)
+}
+
+/// The implementation of `quantize`, which moves a frequency to the nearest note of the named
+/// scale on C.
+pub extern fn quantize_freq(site: CallSite, freq: Number, mode: usize) -> Number {
+    mtof(quantize(intervals(SCALES, site, mode), ftom(freq)))
+}
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    compiler.define_native_function("mtof", &["note"], |args, _| mtof(args[0]));
+    compiler.define_native_function("ftom", &["freq"], |args, _| ftom(args[0]));
+
+    unsafe {
+        compiler.define_direct_builtin("scale", &[("degree", Type::Number), ("mode", Type::Str),
+                                                  ("root", Type::Number)],
+                                       Type::Number, scale as *mut ());
+        compiler.define_direct_builtin("chord", &[("root", Type::Number), ("quality", Type::Str),
+                                                  ("voice", Type::Number)],
+                                       Type::Number, chord as *mut ());
+        compiler.define_direct_builtin("quantize", &[("freq", Type::Number), ("mode", Type::Str)],
+                                       Type::Number, quantize_freq as *mut ());
+    }
+}
@@ -0,0 +1,51 @@
+use super::super::compiler::Compiler;
+use super::super::tokens::Number;
+use super::delay::DelayLine;
+use super::take;
+
+// Freeverb tunings, in samples at 44.1kHz.
+const COMB_LENGTHS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_LENGTHS: [usize; 4] = [556, 441, 341, 225];
+const ALLPASS_FEEDBACK: Number = 0.5;
+const INPUT_GAIN: Number = 0.015;
+
+fn scale_length(len: usize, sample_rate: u32) -> usize {
+    (len as Number * sample_rate as Number / 44100.0) as usize + 1
+}
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    // Freeverb: parallel lowpass-feedback comb filters followed by series allpasses.
+    // `size` and `damping` range from 0 to 1, `mix` is the wet/dry balance.
+    compiler.define_native_function("reverb", &["x", "size", "damping", "mix"], |args, state| {
+        let (x, size, damping, mix) = (args[0], args[1], args[2], args[3]);
+        let feedback = 0.7 + 0.28 * size.max(0.0).min(1.0);
+        let damp = 0.4 * damping.max(0.0).min(1.0);
+        let sample_rate = state.sample_rate();
+
+        let len = COMB_LENGTHS.iter().chain(ALLPASS_LENGTHS.iter())
+            .map(|&l| DelayLine::memory_len(scale_length(l, sample_rate)) + 1)
+            .fold(0, |acc, l| acc + l);
+        let mut mem = state.memory(len);
+
+        let input = x * INPUT_GAIN;
+        let mut out = 0.0;
+        for &l in COMB_LENGTHS.iter() {
+            let l = scale_length(l, sample_rate);
+            let filter = take(&mut mem, 1);
+            let mut line = DelayLine::new(take(&mut mem, DelayLine::memory_len(l)));
+            let y = line.read(l);
+            filter[0] = y * (1.0 - damp) + filter[0] * damp;
+            line.write(input + filter[0] * feedback);
+            out += y;
+        }
+        for &l in ALLPASS_LENGTHS.iter() {
+            let l = scale_length(l, sample_rate);
+            take(&mut mem, 1); // keeps the layout uniform with the combs
+            let mut line = DelayLine::new(take(&mut mem, DelayLine::memory_len(l)));
+            let delayed = line.read(l);
+            line.write(out + delayed * ALLPASS_FEEDBACK);
+            out = delayed - out;
+        }
+        x * (1.0 - mix) + out * mix
+    });
+}
@@ -0,0 +1,48 @@
+use super::super::compiler::Compiler;
+use super::super::tokens::Number;
+
+use std::f64::INFINITY;
+
+/// The coefficient of a one pole lowpass which covers about two thirds of the distance to its
+/// target in `time_ms` milliseconds. A time of zero or less jumps straight to the target.
+pub fn coefficient(time_ms: Number, sample_rate: u32) -> Number {
+    if time_ms <= 0.0 {
+        0.0
+    } else {
+        (-1000.0 / (time_ms * sample_rate as Number)).exp()
+    }
+}
+
+/// Moves `value` one sample towards `target`.
+pub fn smooth(value: Number, target: Number, coefficient: Number) -> Number {
+    target + (value - target) * coefficient
+}
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    // Follows `x` smoothly, so that sudden jumps become short ramps. The first value is taken
+    // as is.
+    compiler.define_native_function("smooth", &["x", "time_ms"], |args, state| {
+        let (x, time_ms) = (args[0], args[1]);
+        let a = coefficient(time_ms, state.sample_rate());
+        let mem = state.memory(2);
+        mem[0] = if mem[1] == 0.0 { x } else { smooth(mem[0], x, a) };
+        mem[1] = 1.0;
+        mem[0]
+    });
+
+    // Follows `x` in a straight line, taking `time_ms` milliseconds to move by 1, so that a jump
+    // from 0 to 1 becomes a ramp of that length. The first value is taken as is. `switch` fades
+    // between its functions with it.
+    compiler.define_native_function("slew", &["x", "time_ms"], |args, state| {
+        let (x, time_ms) = (args[0], args[1]);
+        let step = if time_ms <= 0.0 {
+            INFINITY
+        } else {
+            1000.0 / (time_ms * state.sample_rate() as Number)
+        };
+        let mem = state.memory(2);
+        mem[0] = if mem[1] == 0.0 { x } else { mem[0] + (x - mem[0]).max(-step).min(step) };
+        mem[1] = 1.0;
+        mem[0]
+    });
+}
@@ -0,0 +1,134 @@
+use super::super::compiler::Compiler;
+use super::super::runtime::{self, CallSite};
+use super::super::tokens::Number;
+use super::fft::Stft;
+
+use std::f64::consts::PI;
+
+/// The number of samples each spectrum is taken over, which is also how long the output of an
+/// `@fft` section or a spectral intrinsic is delayed by.
+pub const FFT_SIZE: usize = 1024;
+
+// A hann window halves the peak of a sine, and its energy is split between the bin and its mirror.
+const BIN_SCALE: Number = FFT_SIZE as Number / 4.0;
+
+/// Called by generated code with the input of an `@fft` section in each sample. Returns the number
+/// of bins to evaluate the body for, which is 0 except once every hop.
+pub extern fn fft_input(site: CallSite, x: Number) -> Number {
+    runtime::with_site_state(site, |state| {
+        let stft = Stft::new(FFT_SIZE);
+        if stft.push(state.memory(stft.memory_len(0)), x) {
+            (FFT_SIZE/2 + 1) as Number
+        } else {
+            0.0
+        }
+    })
+}
+
+// Runs `f` with the real and imaginary parts of the spectrum of a section.
+fn with_spectrum<F, R>(site: CallSite, f: F) -> R where F: FnOnce(&mut [Number], &mut [Number]) -> R {
+    runtime::with_site_state(site, |state| {
+        let stft = Stft::new(FFT_SIZE);
+        let (re, im, _) = stft.spectrum(state.memory(stft.memory_len(0)));
+        f(re, im)
+    })
+}
+
+/// Called by generated code to fill in `bin_mag`, `bin_phase` and `bin_freq`.
+pub extern fn fft_bin_mag(site: CallSite, bin: Number) -> Number {
+    with_spectrum(site, |re, im| re[bin as usize].hypot(im[bin as usize]) / BIN_SCALE)
+}
+pub extern fn fft_bin_phase(site: CallSite, bin: Number) -> Number {
+    with_spectrum(site, |re, im| im[bin as usize].atan2(re[bin as usize]))
+}
+pub extern fn fft_bin_freq(site: CallSite, bin: Number) -> Number {
+    runtime::with_site_state(site, |state| bin * state.sample_rate() as Number / FFT_SIZE as Number)
+}
+
+/// Called by generated code with what the body of a section gave for a bin. The bin's mirror above
+/// half the sample rate is set to its conjugate, so that the output stays real.
+pub extern fn fft_set_bin(site: CallSite, bin: Number, re: Number, im: Number) {
+    with_spectrum(site, |spectrum_re, spectrum_im| {
+        let k = bin as usize;
+        spectrum_re[k] = re * BIN_SCALE;
+        spectrum_im[k] = im * BIN_SCALE;
+        if k > 0 && k < FFT_SIZE/2 {
+            spectrum_re[FFT_SIZE - k] = re * BIN_SCALE;
+            spectrum_im[FFT_SIZE - k] = -im * BIN_SCALE;
+        }
+    })
+}
+
+/// Called by generated code after a section, with what fft_input() returned. Gives the output for
+/// this sample.
+pub extern fn fft_output(site: CallSite, bins: Number) -> Number {
+    runtime::with_site_state(site, |state| {
+        let stft = Stft::new(FFT_SIZE);
+        stft.output(state.memory(stft.memory_len(0)), bins > 0.0)
+    })
+}
+
+// `@fft` sections are generated by codegen.rs, and only use the transforms here.
+pub fn define_intrinsics(compiler: &Compiler) {
+    // Removes all frequency content above `cutoff` Hz.
+    compiler.define_native_function("spectral_lowpass", &["x", "cutoff"], |args, state| {
+        let stft = Stft::new(FFT_SIZE);
+        let bin_width = state.sample_rate() as Number / FFT_SIZE as Number;
+        let cutoff_bin = (args[1] / bin_width).max(0.0) as usize;
+        let mem = state.memory(stft.memory_len(0));
+        stft.next(mem, args[0], |re, im, _| {
+            for k in 0..FFT_SIZE/2 + 1 {
+                if k > cutoff_bin {
+                    re[k] = 0.0;
+                    im[k] = 0.0;
+                    if k > 0 && k < FFT_SIZE/2 {
+                        re[FFT_SIZE - k] = 0.0;
+                        im[FFT_SIZE - k] = 0.0;
+                    }
+                }
+            }
+        })
+    });
+
+    // Removes all bins quieter than `threshold`, where a full scale sine has magnitude 1.
+    compiler.define_native_function("spectral_gate", &["x", "threshold"], |args, state| {
+        let stft = Stft::new(FFT_SIZE);
+        let threshold = args[1] * FFT_SIZE as Number / 4.0; // hann window halves the peak
+        let mem = state.memory(stft.memory_len(0));
+        stft.next(mem, args[0], |re, im, _| {
+            for k in 0..FFT_SIZE {
+                if (re[k]*re[k] + im[k]*im[k]).sqrt() < threshold {
+                    re[k] = 0.0;
+                    im[k] = 0.0;
+                }
+            }
+        })
+    });
+
+    // While `freeze` is greater than 0.5, holds the last spectrum indefinitely.
+    compiler.define_native_function("spectral_freeze", &["x", "freeze"], |args, state| {
+        let stft = Stft::new(FFT_SIZE);
+        let bins = FFT_SIZE/2 + 1;
+        let frozen = args[1] > 0.5;
+        let hop = stft.hop();
+        let mem = state.memory(stft.memory_len(2*bins));
+        stft.next(mem, args[0], |re, im, extra| {
+            let (mags, phases) = extra.split_at_mut(bins);
+            for k in 0..bins {
+                if frozen {
+                    // advance each bin's phase by what it would have moved in one hop
+                    phases[k] += 2.0 * PI * (k * hop) as Number / FFT_SIZE as Number;
+                    re[k] = mags[k] * phases[k].cos();
+                    im[k] = mags[k] * phases[k].sin();
+                } else {
+                    mags[k] = (re[k]*re[k] + im[k]*im[k]).sqrt();
+                    phases[k] = im[k].atan2(re[k]);
+                }
+                if k > 0 && k < FFT_SIZE/2 {
+                    re[FFT_SIZE - k] = re[k];
+                    im[FFT_SIZE - k] = -im[k];
+                }
+            }
+        })
+    });
+}
@@ -0,0 +1,78 @@
+use super::super::compiler::Compiler;
+use super::super::runtime::CallSite;
+use super::super::tokens::Number;
+use super::super::types::Type;
+
+use std::f64::consts::PI;
+use std::slice;
+
+/// The gains of the left and right channels of a signal panned to `position`, from -1 for hard
+/// left through 0 for the center to 1 for hard right. The power of the signal stays the same
+/// wherever it is, so it is at about -3 dB in each channel in the center.
+pub fn pan_gains(position: Number) -> (Number, Number) {
+    let angle = (position.max(-1.0).min(1.0) + 1.0) * PI / 4.0;
+    (angle.cos(), angle.sin())
+}
+
+/// Narrows or widens a stereo signal by scaling the difference between its channels. 0 makes it
+/// mono, 1 leaves it unchanged and more than 1 exaggerates it.
+pub fn widen(left: Number, right: Number, amount: Number) -> (Number, Number) {
+    let mid = (left + right) * 0.5;
+    let side = (left - right) * 0.5 * amount;
+    (mid + side, mid - side)
+}
+
+unsafe fn write(out: *mut Number, (left, right): (Number, Number)) {
+    let out = slice::from_raw_parts_mut(out, 2);
+    out[0] = left;
+    out[1] = right;
+}
+
+/// The implementation of `stereo`, which makes a stereo signal from one expression for each
+/// channel.
+pub extern fn stereo(_: CallSite, left: Number, right: Number, out: *mut Number) {
+    unsafe { write(out, (left, right)) }
+}
+
+/// The implementation of `pan`.
+pub extern fn pan(_: CallSite, signal: Number, position: Number, out: *mut Number) {
+    let (left, right) = pan_gains(position);
+    unsafe { write(out, (signal * left, signal * right)) }
+}
+
+/// The implementation of `width`.
+pub extern fn width(_: CallSite, left: Number, right: Number, amount: Number, out: *mut Number) {
+    unsafe { write(out, widen(left, right, amount)) }
+}
+
+/// The implementations of `left` and `right`, which give one channel of a signal. A signal of
+/// more than two channels gives its first two.
+pub extern fn left(_: CallSite, signal: *const Number, len: usize) -> Number {
+    unsafe { slice::from_raw_parts(signal, len)[0] }
+}
+pub extern fn right(_: CallSite, signal: *const Number, len: usize) -> Number {
+    unsafe { slice::from_raw_parts(signal, len)[if len > 1 { 1 } else { 0 }] }
+}
+
+/// The implementation of `mono`, which mixes every channel of a signal down to one.
+pub extern fn mono(_: CallSite, signal: *const Number, len: usize) -> Number {
+    let signal = unsafe { slice::from_raw_parts(signal, len) };
+    signal.iter().fold(0.0, |sum, x| sum + x) / len as Number
+}
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    let stereo_ty = Type::Channels(2);
+    let signal = [("signal", Type::Channels(0))];
+    unsafe {
+        compiler.define_direct_builtin("stereo", &[("left", Type::Number), ("right", Type::Number)],
+                                       stereo_ty, stereo as *mut ());
+        compiler.define_direct_builtin("pan", &[("signal", Type::Number), ("position", Type::Number)],
+                                       stereo_ty, pan as *mut ());
+        compiler.define_direct_builtin("width", &[("left", Type::Number), ("right", Type::Number),
+                                                  ("amount", Type::Number)],
+                                       stereo_ty, width as *mut ());
+        compiler.define_direct_builtin("left", &signal, Type::Number, left as *mut ());
+        compiler.define_direct_builtin("right", &signal, Type::Number, right as *mut ());
+        compiler.define_direct_builtin("mono", &signal, Type::Number, mono as *mut ());
+    }
+}
@@ -0,0 +1,65 @@
+use super::super::buffer;
+use super::super::compiler::Compiler;
+use super::super::runtime::{self, CallSite};
+use super::super::tokens::Number;
+use super::super::types::Type;
+use super::interpolate::{self, Interpolation};
+
+use std::f64::consts::PI;
+
+/// The length of a grain in seconds. Longer grains smear transients, shorter ones roughen tones.
+pub const GRAIN_SECONDS: Number = 0.05;
+
+/// The gain of a grain at a phase from 0 to 1 through it. Two grains half a grain apart always
+/// add up to 1.
+pub fn grain_window(phase: Number) -> Number {
+    (PI * phase).sin().powi(2)
+}
+
+/// Where in the buffer a grain at `phase` reads from, in samples, when grains start at `head`.
+/// Grains which play at the same speed as the head moves all read from it, which makes the
+/// buffer play back unchanged.
+pub fn grain_position(head: Number, phase: Number, grain_len: Number, rate: Number, pitch: Number) -> Number {
+    head + phase * grain_len * (pitch - rate)
+}
+
+/// The implementation of `stretch`, which plays a buffer from `position` seconds in, moving on by
+/// `rate` seconds every second, with its pitch scaled by `pitch`. A rate of 0.5 plays it at half
+/// speed and a pitch of 2 an octave up.
+pub extern fn stretch(site: CallSite, sample: usize, position: Number, rate: Number, pitch: Number,
+                      interpolation: Number) -> Number {
+    runtime::with_site_state(site, |state| {
+        let sample_rate = state.sample_rate() as Number;
+        let grain_len = GRAIN_SECONDS * sample_rate;
+        let (offset, phase) = {
+            let mem = state.memory(2); // how far the head has moved in samples, the phase of a grain
+            (mem[0], mem[1])
+        };
+        let head = position * sample_rate + offset;
+        let mode = Interpolation::from_number(interpolation);
+        let out = match state.buffer(sample) {
+            Some(samples) => {
+                let len = samples.len() as Number;
+                let out = [phase, (phase + 0.5) % 1.0].iter().fold(0.0, |sum, &p| {
+                    sum + grain_window(p) * buffer::read(samples, grain_position(head, p, grain_len, rate, pitch), mode)
+                });
+                (out, (offset + rate) % len)
+            }
+            None => (0.0, 0.0),
+        };
+        let mem = state.memory(2);
+        mem[0] = out.1;
+        mem[1] = (phase + 1.0 / grain_len) % 1.0;
+        out.0
+    })
+}
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    unsafe {
+        compiler.define_direct_builtin("stretch", &[("sample", Type::Str), ("position", Type::Number),
+                                                    ("rate", Type::Number), ("pitch", Type::Number),
+                                                    ("interpolation", Type::Number)],
+                                       Type::Number, stretch as *mut ());
+    }
+    interpolate::default_to_setting(compiler, "stretch");
+}
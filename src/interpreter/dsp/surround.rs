@@ -0,0 +1,27 @@
+use super::super::compiler::Compiler;
+use super::super::runtime::CallSite;
+use super::super::tokens::Number;
+use super::super::types::Type;
+
+use std::slice;
+
+/// The number of channels of a surround signal, in the order of a 7.1 WAV file.
+pub const CHANNELS: usize = 8;
+
+/// The implementation of `speaker`. A speaker past the last is the nearest one, as with the
+/// index given to `channel`.
+pub extern fn speaker(_: CallSite, x: Number, n: Number, out: *mut Number) {
+    let out = unsafe { slice::from_raw_parts_mut(out, CHANNELS) };
+    // NaN is cast to 0
+    let n = (n.max(0.0) as usize).min(CHANNELS - 1);
+    for (i, channel) in out.iter_mut().enumerate() {
+        *channel = if i == n { x } else { 0.0 };
+    }
+}
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    unsafe {
+        compiler.define_direct_builtin("speaker", &[("x", Type::Number), ("n", Type::Number)],
+                                       Type::Channels(CHANNELS), speaker as *mut ());
+    }
+}
@@ -0,0 +1,38 @@
+use super::super::tokens::Number;
+use super::interpolate::{interpolate, Interpolation};
+
+/// A function sampled at evenly spaced points over [-1, 1], read with interpolation.
+#[derive(Clone, Debug)]
+pub struct Table {
+    values: Vec<Number>,
+}
+
+impl Table {
+    pub fn new(values: Vec<Number>) -> Table {
+        assert!(values.len() >= 2, "a table needs at least two values");
+        Table {
+            values: values,
+        }
+    }
+
+    pub fn from_fn<F>(len: usize, f: F) -> Table where F: Fn(Number) -> Number {
+        let scale = 2.0 / (len - 1) as Number;
+        Table::new((0..len).map(|i| f(i as Number * scale - 1.0)).collect())
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Looks up `x`, which is clamped to [-1, 1], with linear interpolation.
+    pub fn lookup(&self, x: Number) -> Number {
+        self.lookup_with(x, Interpolation::Linear)
+    }
+
+    /// Looks up `x` with the given interpolation. Points beyond the ends repeat the end values.
+    pub fn lookup_with(&self, x: Number, mode: Interpolation) -> Number {
+        let last = self.values.len() - 1;
+        let pos = (x.max(-1.0).min(1.0) + 1.0) * 0.5 * last as Number;
+        interpolate(mode, pos, |i| self.values[i.max(0).min(last as isize) as usize])
+    }
+}
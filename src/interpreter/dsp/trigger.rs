@@ -0,0 +1,162 @@
+use super::super::compiler::Compiler;
+use super::super::rng::Rng;
+use super::super::tokens::Number;
+
+/// Whether a trigger fires in the sample it has this value.
+pub fn fired(trigger: Number) -> bool {
+    trigger > 0.0
+}
+
+fn trigger(fired: bool) -> Number {
+    if fired { 1.0 } else { 0.0 }
+}
+
+/// Whether the given step of a Euclidean rhythm, which spreads `pulses` as evenly as possible over
+/// `steps`, is a pulse. The first step always is, unless there are no pulses.
+pub fn euclid_pulse(step: usize, steps: usize, pulses: usize) -> bool {
+    steps > 0 && (step * pulses) % steps < pulses
+}
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    // Fires `rate` times a second, starting with the first sample.
+    compiler.define_native_function("clock", &["rate"], |args, state| {
+        let step = args[0].max(0.0) / state.sample_rate() as Number;
+        let mem = state.memory(2); // phase, whether the clock has started
+        let fired = mem[1] == 0.0 || mem[0] >= 1.0;
+        mem[0] = mem[0].fract() + step;
+        mem[1] = 1.0;
+        trigger(fired)
+    });
+
+    // Steps through a Euclidean rhythm each time `clock` fires, firing on the steps which are
+    // pulses. For example 3 pulses over 8 steps fire on steps 0, 3 and 6.
+    compiler.define_native_function("euclid", &["steps", "pulses", "clock"], |args, state| {
+        let steps = args[0].max(1.0) as usize;
+        let pulses = args[1].max(0.0).min(steps as Number) as usize;
+        let mem = state.memory(1); // the next step
+        if !fired(args[2]) {
+            return 0.0;
+        }
+        let step = mem[0] as usize % steps;
+        mem[0] = ((step + 1) % steps) as Number;
+        trigger(euclid_pulse(step, steps, pulses))
+    });
+
+    // An envelope which rises linearly from its current level to 1 over `attack` seconds each
+    // time `trigger` fires, then falls back to 0 over `decay` seconds.
+    compiler.define_native_function("ad", &["trigger", "attack", "decay"], |args, state| {
+        let (attack, decay) = (args[1], args[2]);
+        let sample_rate = state.sample_rate() as Number;
+        let mem = state.memory(2); // level, whether it is rising
+        if fired(args[0]) {
+            mem[1] = 1.0;
+        }
+        if mem[1] != 0.0 {
+            mem[0] = if attack > 0.0 { mem[0] + 1.0 / (attack * sample_rate) } else { 1.0 };
+            if mem[0] >= 1.0 {
+                mem[0] = 1.0;
+                mem[1] = 0.0;
+            }
+        } else if mem[0] > 0.0 {
+            mem[0] = if decay > 0.0 { (mem[0] - 1.0 / (decay * sample_rate)).max(0.0) } else { 0.0 };
+        }
+        mem[0]
+    });
+
+    // Holds the value `signal` had the last time `trigger` fired, or 0 before it first fires.
+    compiler.define_native_function("samplehold", &["signal", "trigger"], |args, state| {
+        let mem = state.memory(1); // the held value
+        if fired(args[1]) {
+            mem[0] = args[0];
+        }
+        mem[0]
+    });
+
+    // Fires on every `n`th time `clock` does, starting with the first.
+    compiler.define_native_function("clockdiv", &["clock", "n"], |args, state| {
+        let n = args[1].round().max(1.0);
+        let mem = state.memory(1); // how many times the clock has fired, modulo n
+        if !fired(args[0]) {
+            return 0.0;
+        }
+        let count = mem[0];
+        mem[0] = (count + 1.0) % n;
+        trigger(count == 0.0)
+    });
+
+    // Fires `n` times for each time `clock` does, evenly spaced over the time between the last two
+    // times it fired. Until it has fired twice there is nothing to go by, so only the clock itself
+    // comes through.
+    compiler.define_native_function("clockmult", &["clock", "n"], |args, state| {
+        let n = args[1].round().max(1.0);
+        // samples since the clock last fired, samples between its last two firings or 0 if unknown,
+        // and how many firings of this period have been made
+        let mem = state.memory(3);
+        mem[0] += 1.0;
+        if fired(args[0]) {
+            if mem[2] > 0.0 {
+                mem[1] = mem[0];
+            }
+            mem[0] = 0.0;
+            mem[2] = 1.0;
+            return trigger(true);
+        }
+        let due = mem[1] > 0.0 && mem[2] < n && mem[0] >= (mem[2] * mem[1] / n).round();
+        if due {
+            mem[2] += 1.0;
+        }
+        trigger(due)
+    });
+
+    // Delays every second time `clock` fires by `amount` times half the time between the last
+    // two, so 0 is straight and about 0.33 a triplet shuffle.
+    compiler.define_native_function("swing", &["clock", "amount"], |args, state| {
+        let amount = args[1].max(0.0).min(1.0);
+        // samples since the clock last fired, samples between its last two firings, how many
+        // times it has fired and samples until a delayed off-beat fires, or 0 if none
+        let mem = state.memory(4);
+        mem[0] += 1.0;
+        let mut fire = false;
+        if mem[3] > 0.0 {
+            mem[3] -= 1.0;
+            fire = mem[3] == 0.0;
+        }
+        if fired(args[0]) {
+            if mem[2] > 0.0 {
+                mem[1] = mem[0];
+            }
+            mem[0] = 0.0;
+            let delay = (amount * mem[1] / 2.0).round();
+            if mem[2] % 2.0 == 0.0 || delay == 0.0 {
+                fire = true;
+            } else {
+                mem[3] = delay;
+            }
+            mem[2] += 1.0;
+        }
+        trigger(fire)
+    });
+
+    // Delays each time `trigger` fires by a random time of up to `timing_ms`. The delays come from
+    // their own generator, seeded by `seed` and the seed of the program, so that they stay the
+    // same when randomness is added elsewhere. A trigger which fires again before its delay is up
+    // lets the delayed one through at once.
+    compiler.define_native_function("humanize", &["trigger", "timing_ms", "seed"], |args, state| {
+        let max_delay = args[1].max(0.0) * state.sample_rate() as Number / 1000.0;
+        let seed = state.seed() ^ (args[2] as i64 as u64).rotate_left(32);
+        // how many times the trigger has fired, and samples until a delayed firing or 0 if none
+        let mem = state.memory(2);
+        let mut fire = false;
+        if mem[1] > 0.0 {
+            mem[1] -= 1.0;
+            fire = mem[1] == 0.0;
+        }
+        if fired(args[0]) {
+            let delay = (Rng::new(seed ^ mem[0] as u64).next_number() * max_delay).round();
+            fire = fire || mem[1] > 0.0 || delay == 0.0;
+            mem[1] = delay;
+            mem[0] += 1.0;
+        }
+        trigger(fire)
+    });
+}
@@ -0,0 +1,85 @@
+use super::super::compiler::Compiler;
+use super::super::tokens::Number;
+use super::dynamics::{coefficient, follow};
+use super::take;
+
+use std::f64::consts::PI;
+
+/// The most bands a vocoder may have. More are clamped to this.
+pub const MAX_BANDS: usize = 64;
+
+const LOWEST_FREQ: Number = 80.0;
+const HIGHEST_FREQ: Number = 10000.0;
+const ATTACK: Number = 0.005;
+const RELEASE: Number = 0.02;
+
+// the coefficients of a biquad, then the state of each of its two filters and the level of the
+// modulator in the band
+const BAND_MEMORY: usize = 4 + 4 + 4 + 1;
+
+/// The range the bands of a vocoder cover, kept below the nyquist frequency at low sample rates.
+fn range(sample_rate: u32) -> (Number, Number) {
+    let high = HIGHEST_FREQ.min(sample_rate as Number * 0.45);
+    (LOWEST_FREQ.min(high / 2.0), high)
+}
+
+/// The center frequency of band `i` of `bands`, spread evenly in pitch across the range.
+pub fn band_frequency(i: usize, bands: usize, sample_rate: u32) -> Number {
+    let (low, high) = range(sample_rate);
+    low * (high / low).powf((i as Number + 0.5) / bands as Number)
+}
+
+/// The coefficients `[b0, b2, a1, a2]` of a band-pass biquad with a peak gain of 1 at `freq`,
+/// wide enough that `bands` of them side by side meet at their edges. `b1` is always 0.
+fn bandpass(freq: Number, bands: usize, sample_rate: u32) -> [Number; 4] {
+    let (low, high) = range(sample_rate);
+    let ratio = (high / low).powf(1.0 / bands as Number);
+    let q = ratio.sqrt() / (ratio - 1.0);
+    let w = 2.0 * PI * freq / sample_rate as Number;
+    let alpha = w.sin() / (2.0 * q);
+    let a0 = 1.0 + alpha;
+    [alpha / a0, -alpha / a0, -2.0 * w.cos() / a0, (1.0 - alpha) / a0]
+}
+
+/// Runs one sample through a biquad, keeping its last two inputs and outputs in `state`.
+fn biquad(c: &[Number], state: &mut [Number], x: Number) -> Number {
+    let y = c[0] * x + c[1] * state[1] - c[2] * state[2] - c[3] * state[3];
+    state[1] = state[0];
+    state[0] = x;
+    state[3] = state[2];
+    state[2] = y;
+    y
+}
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    // Each band follows the modulator with a 5 ms attack and a 20 ms release. The filters are
+    // worked out again when the number of bands or the sample rate changes.
+    compiler.define_native_function("vocode", &["modulator", "carrier", "bands"], |args, state| {
+        let (modulator, carrier) = (args[0], args[1]);
+        let bands = (args[2].round().max(1.0) as usize).min(MAX_BANDS);
+        let sample_rate = state.sample_rate();
+        let (attack, release) = (coefficient(ATTACK, sample_rate), coefficient(RELEASE, sample_rate));
+        let mut mem = state.memory(2 + MAX_BANDS * BAND_MEMORY);
+
+        let layout = take(&mut mem, 2);
+        let stale = layout[0] != bands as Number || layout[1] != sample_rate as Number;
+        layout[0] = bands as Number;
+        layout[1] = sample_rate as Number;
+
+        let mut out = 0.0;
+        for i in 0..bands {
+            let band = take(&mut mem, BAND_MEMORY);
+            let (coefficients, band) = band.split_at_mut(4);
+            if stale {
+                let freq = band_frequency(i, bands, sample_rate);
+                coefficients.copy_from_slice(&bandpass(freq, bands, sample_rate));
+            }
+            let (modulator_state, band) = band.split_at_mut(4);
+            let (carrier_state, level) = band.split_at_mut(4);
+            let m = biquad(coefficients, modulator_state, modulator);
+            level[0] = follow(level[0], m, attack, release);
+            out += biquad(coefficients, carrier_state, carrier) * level[0];
+        }
+        out
+    });
+}
@@ -0,0 +1,103 @@
+use super::tokens::Number;
+
+use std::f64::{self, consts};
+use std::mem;
+
+/// The symbols of the functions codegen.rs generates for `--fast-math` are this followed by the
+/// name of the builtin. The functions here follow the same steps, and are what they are tested
+/// against: sin and cos are within 1e-9 for |x| <= 10000, exp and exp2 within a relative 5e-10,
+/// log and log2 within 1e-10 for positive normal numbers and pow within a relative 1e-9 for
+/// |y| <= 16.
+pub const SYMBOL_PREFIX: &'static str = "*fastmath*";
+
+fn to_bits(x: Number) -> u64 {
+    unsafe { mem::transmute(x) }
+}
+
+fn from_bits(x: u64) -> Number {
+    unsafe { mem::transmute(x) }
+}
+
+// The Taylor series of sin up to x^13, which is within 7e-10 of it for |x| <= pi/2.
+pub const SIN: [Number; 7] = [
+    1.0,
+    -1.0 / 6.0,
+    1.0 / 120.0,
+    -1.0 / 5040.0,
+    1.0 / 362880.0,
+    -1.0 / 39916800.0,
+    1.0 / 6227020800.0,
+];
+
+// The Taylor series of 2^x up to x^8, which is within 3e-10 of it relative to its value for
+// |x| <= 1/2. The nth coefficient is ln(2)^n / n!.
+pub const EXP2: [Number; 9] = [
+    1.0,
+    6.931471805599453e-1,
+    2.402265069591007e-1,
+    5.550410866482158e-2,
+    9.618129107628477e-3,
+    1.333355814642844e-3,
+    1.540353039338161e-4,
+    1.525273380405984e-5,
+    1.321548679014431e-6,
+];
+
+fn horner(coefficients: &[Number], x: Number) -> Number {
+    coefficients.iter().rev().fold(0.0, |p, &c| p * x + c)
+}
+
+pub fn sin(x: Number) -> Number {
+    // into [-pi, pi], then folded into [-pi/2, pi/2] where sin is symmetric about the ends
+    let r = x - (x / (2.0 * consts::PI)).round() * (2.0 * consts::PI);
+    let r = r.min(consts::PI - r).max(-consts::PI - r);
+    r * horner(&SIN, r * r)
+}
+
+pub fn cos(x: Number) -> Number {
+    sin(x + consts::FRAC_PI_2)
+}
+
+pub fn exp2(x: Number) -> Number {
+    let x = x.max(-1022.0).min(1023.0);
+    let k = x.round();
+    let scale = from_bits(((k as i64 + 1023) as u64) << 52);
+    horner(&EXP2, x - k) * scale
+}
+
+pub fn exp(x: Number) -> Number {
+    exp2(x * consts::LOG2_E)
+}
+
+pub fn log2(x: Number) -> Number {
+    // x = m * 2^e, with m moved into [sqrt(1/2), sqrt(2)]
+    let bits = to_bits(x);
+    let m = from_bits((bits & ((1 << 52) - 1)) | (1023 << 52));
+    let high = if m > consts::SQRT_2 { 1.0 } else { 0.0 };
+    let e = ((bits >> 52) & 0x7ff) as Number - 1023.0 + high;
+    let m = m * (1.0 - 0.5 * high);
+    // ln(m) = 2 atanh(s), and |s| < 0.172 needs terms up to s^11
+    let s = (m - 1.0) / (m + 1.0);
+    let s2 = s * s;
+    let series = (0..6).rev().fold(0.0, |p, i| p * s2 + 1.0 / (2 * i + 1) as Number);
+    e + 2.0 * consts::LOG2_E * s * series
+}
+
+pub fn log(x: Number) -> Number {
+    log2(x) * consts::LN_2
+}
+
+pub fn pow(x: Number, y: Number) -> Number {
+    let magnitude = exp2(y * log2(x.abs()));
+    let integer = y == y.trunc();
+    let odd = integer && (y / 2.0).trunc() * 2.0 != y;
+    if x == 0.0 {
+        if y > 0.0 { 0.0 } else if y == 0.0 { 1.0 } else { f64::INFINITY }
+    } else if x > 0.0 || (integer && !odd) {
+        magnitude
+    } else if odd {
+        -magnitude
+    } else {
+        f64::NAN
+    }
+}
@@ -0,0 +1,229 @@
+use super::common::Context;
+use super::ast::{Item, FunctionDef, Expression, Statement, Argument, Block, FunctionCall};
+use super::codes::Code;
+use super::ident::Identifier;
+use super::purity::for_each_child;
+use super::tokens::{Node, NodeImpl, SourcePos};
+
+use std::collections::HashMap;
+use std::mem;
+
+/// The functions a value may be, each with the position of the expression which names it.
+type Origins = Vec<(Identifier, SourcePos)>;
+
+#[derive(Clone, Default)]
+struct Scope {
+    locals: HashMap<Identifier, Origins>,
+    assigned: HashMap<Identifier, SourcePos>,
+}
+
+// A function being checked.
+struct Frame {
+    args: HashMap<Identifier, SourcePos>,
+    scopes: Vec<Scope>, // the innermost last
+    ended: HashMap<Identifier, SourcePos>, // arguments assigned in blocks which have ended
+}
+
+struct Checker<'a> {
+    ctxt: &'a Context<'a>,
+    globals: HashMap<Identifier, Origins>,
+}
+
+/// Checks that every function a call may reach is given all of its arguments, and warns about
+/// arguments used after a block which assigned them.
+pub fn check_flow<'a>(ctxt: &'a Context<'a>) {
+    let ast = ctxt.ast.borrow();
+    let mut checker = Checker {
+        ctxt: ctxt,
+        globals: HashMap::new(),
+    };
+    let mut frame = Frame {
+        args: HashMap::new(),
+        scopes: vec![Scope::default()],
+        ended: HashMap::new(),
+    };
+    for item in ast.iter() {
+        if let Item::Assignment(ref assign) = *item {
+            checker.assignment(&mut frame, assign.ident(), assign.ident_pos(), assign.expr());
+        }
+    }
+    checker.globals = frame.scopes.pop().unwrap().locals;
+    for item in ast.iter() {
+        if let Item::FunctionDef(ref def) = *item {
+            checker.function(def, Scope::default());
+        }
+    }
+}
+
+impl<'a> Checker<'a> {
+    fn function(&self, def: &FunctionDef, outer: Scope) {
+        let mut frame = Frame {
+            args: def.args().iter().map(|arg| (arg.ident().unwrap(), arg.pos())).collect(),
+            scopes: vec![outer, Scope::default()],
+            ended: HashMap::new(),
+        };
+        for statement in &def.block {
+            self.statement(&mut frame, statement);
+        }
+    }
+
+    fn statement(&self, frame: &mut Frame, statement: &Statement) {
+        match *statement {
+            Statement::Assignment(ref assign) =>
+                self.assignment(frame, assign.ident(), assign.ident_pos(), assign.expr()),
+            Statement::Expression(ref expr) => self.expr(frame, expr),
+        }
+    }
+
+    fn assignment(&self, frame: &mut Frame, id: Identifier, pos: SourcePos, expr: &Expression) {
+        self.expr(frame, expr);
+        let origins = self.origins(frame, expr);
+        let scope = frame.scopes.last_mut().unwrap();
+        scope.locals.insert(id, origins);
+        scope.assigned.insert(id, pos);
+    }
+
+    fn block(&self, frame: &mut Frame, block: &Block) {
+        frame.scopes.push(Scope::default());
+        for statement in block {
+            self.statement(frame, statement);
+        }
+        let scope = frame.scopes.pop().unwrap();
+        for (id, pos) in scope.assigned {
+            if frame.args.contains_key(&id) && !frame.scopes.iter().skip(1).any(|x| x.locals.contains_key(&id)) {
+                frame.ended.insert(id, pos);
+            }
+        }
+    }
+
+    fn expr(&self, frame: &mut Frame, expr: &Expression) {
+        match *expr {
+            Expression::Variable(Node(id, pos)) => self.read(frame, id, pos),
+            Expression::Block(ref x) => self.block(frame, x.item()),
+            Expression::Closure(ref x) => {
+                // the closure sees everything visible where it is written
+                let mut outer = Scope::default();
+                for scope in &frame.scopes {
+                    outer.locals.extend(scope.locals.iter().map(|(&id, x)| (id, x.clone())));
+                }
+                for &id in frame.args.keys() {
+                    outer.locals.entry(id).or_insert_with(Vec::new);
+                }
+                self.function(x.item(), outer);
+            }
+            Expression::Conditional(ref x) => {
+                // only one of the branches runs, so one doesn't see what the other assigns
+                self.expr(frame, x.cond());
+                let before = frame.ended.clone();
+                self.expr(frame, x.then());
+                let then = mem::replace(&mut frame.ended, before);
+                self.expr(frame, x.els());
+                frame.ended.extend(then);
+            }
+            Expression::FunctionCall(ref call) => {
+                for arg in call.args() {
+                    match *arg {
+                        Argument::Ident(Node(id, pos)) | Argument::OpAssign(Node(id, pos), _, _) =>
+                            self.read(frame, id, pos),
+                        _ => { },
+                    }
+                }
+                for_each_child(expr, |child| self.expr(frame, child));
+                let origins = self.origins(frame, call.callee());
+                self.call(call, &origins);
+            }
+            _ => for_each_child(expr, |child| self.expr(frame, child)),
+        }
+    }
+
+    fn read(&self, frame: &mut Frame, id: Identifier, pos: SourcePos) {
+        if frame.scopes.iter().skip(1).any(|x| x.locals.contains_key(&id)) {
+            return;
+        }
+        let assigned = match frame.ended.remove(&id) {
+            Some(assigned) => assigned,
+            None => return,
+        };
+        let name = self.ctxt.lookup_name(id);
+        self.ctxt.emit_warning(Code::ShadowedArgument,
+                               format!("this reads the argument `{}`, not the value assigned to it in a block", name),
+                               pos);
+        self.ctxt.emit_note(Code::ShadowedArgument,
+                            format!("this assignment only changes `{}` until the end of its block", name), assigned);
+        self.ctxt.emit_help(format!("assign `{}` outside of the block to change it for the rest of the function",
+                                    name));
+    }
+
+    fn origins(&self, frame: &Frame, expr: &Expression) -> Origins {
+        match *expr {
+            Expression::Variable(Node(id, pos)) => {
+                if let Some(x) = frame.scopes[1..].iter().rev().filter_map(|x| x.locals.get(&id)).next() {
+                    return x.clone();
+                }
+                // arguments could be any function
+                if frame.args.contains_key(&id) {
+                    return Vec::new();
+                }
+                if let Some(x) = frame.scopes[0].locals.get(&id).or_else(|| self.globals.get(&id)) {
+                    return x.clone();
+                }
+                match self.ctxt.functions.borrow().get(id) {
+                    Some(_) => vec![(id, pos)],
+                    None => Vec::new(),
+                }
+            }
+            Expression::Closure(ref x) => vec![(x.ident(), x.pos())],
+            Expression::Conditional(ref x) => {
+                let mut origins = self.origins(frame, x.then());
+                for (id, pos) in self.origins(frame, x.els()) {
+                    if !origins.iter().any(|x| x.0 == id) {
+                        origins.push((id, pos));
+                    }
+                }
+                origins
+            }
+            Expression::Block(ref x) => match x.item().last() {
+                Some(&Statement::Expression(ref expr)) if x.item().len() == 1 => self.origins(frame, expr),
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    // Checks that a call gives every function it may reach all of the arguments it needs.
+    fn call(&self, call: &FunctionCall, origins: &Origins) {
+        if origins.len() < 2 {
+            return;
+        }
+        for &(id, pos) in origins {
+            let mut missing = match self.ctxt.functions.borrow().get(id) {
+                Some(func) => func.args().clone(),
+                None => continue,
+            };
+            for arg in call.args() {
+                match arg.ident() {
+                    Some(id) => missing.retain(|x| x.ident() != Some(id)),
+                    None if !missing.is_empty() => { missing.remove(0); },
+                    None => { },
+                }
+            }
+            for arg in missing {
+                if let Argument::Assign(..) = arg {
+                    continue;
+                }
+                let arg_name = self.ctxt.lookup_name(arg.ident().unwrap());
+                let func_name = if self.ctxt.names.borrow().is_anon(id) == Some(true) {
+                    "a closure".to_string()
+                } else {
+                    format!("`{}`", self.ctxt.lookup_name(id))
+                };
+                self.ctxt.emit_error(Code::PossiblyMissingArgument,
+                                     format!("argument `{}` is required by {}, which this call may reach",
+                                             arg_name, func_name),
+                                     call.args_pos());
+                self.ctxt.emit_note(Code::PossiblyMissingArgument, format!("{} may be chosen here", func_name), pos);
+                self.ctxt.emit_help(format!("pass `{}` in the call, or give it a default in {}", arg_name, func_name));
+            }
+        }
+    }
+}
@@ -2,9 +2,12 @@ use super::ast;
 use super::types::{FunctionType};
 use super::ident::Identifier;
 use super::tokens::{Node, SourcePos};
+use super::runtime::{Intrinsic, IntrinsicFn};
 
 use vec_map::VecMap;
+use std::collections::HashMap;
 use std::ops::Deref;
+use std::sync::Arc;
 use bit_set::BitSet;
 
 #[derive(Debug, Clone)]
@@ -12,6 +15,7 @@ pub enum Function {
     User(UserFunction),
     Pointer(PointerFunction),
     External(ExternalFunction),
+    Intrinsic(IntrinsicFunction),
 }
 
 impl Function {
@@ -19,7 +23,8 @@ impl Function {
         match *self {
             Function::User(ref f) => f.ty.is_some(),
             Function::Pointer(_) |
-            Function::External(_) => true,
+            Function::External(_) |
+            Function::Intrinsic(_) => true,
         }
     }
     pub fn args(&self) -> &ast::ArgumentList {
@@ -27,6 +32,7 @@ impl Function {
             Function::User(ref def) => { def.args() },
             Function::Pointer(ref def) => { &def.args }
             Function::External(ref def) => { &def.args }
+            Function::Intrinsic(ref def) => { &def.args }
         }
     }
     pub fn ty(&self) -> Option<&FunctionType> {
@@ -34,6 +40,14 @@ impl Function {
             Function::User(ref def) => { def.ty.as_ref() },
             Function::Pointer(ref def) => { Some(&def.ty) }
             Function::External(ref def) => { Some(&def.ty) }
+            Function::Intrinsic(ref def) => { Some(&def.ty) }
+        }
+    }
+    /// Where the function was defined, if it was defined in the source.
+    pub fn pos(&self) -> SourcePos {
+        match *self {
+            Function::User(ref def) => def.node.1,
+            _ => SourcePos::anon(),
         }
     }
     pub fn set_ty(&mut self, ty: FunctionType) {
@@ -41,6 +55,7 @@ impl Function {
             Function::User(ref mut def) => { def.ty = Some(ty) }
             Function::Pointer(ref mut def) => { def.ty = ty }
             Function::External(ref mut def) => { def.ty = ty }
+            Function::Intrinsic(ref mut def) => { def.ty = ty }
         }
     }
 }
@@ -86,6 +101,15 @@ pub struct PointerFunction {
     pub ty: FunctionType,
     pub args: ast::ArgumentList,
     pub ptr: *mut (),
+    /// Called directly with its call site and its arguments in the order of `args`, rather than
+    /// through a function value. Only these may take arrays, which can be of any length.
+    pub direct: bool,
+    /// The fields of the record a direct builtin returns, in the order it writes them, which need
+    /// not be the order of the record's shape.
+    pub outputs: Vec<Identifier>,
+    /// The argument a direct builtin declared to return `Channels(0)` takes its number of
+    /// channels from, which is the length of an array or the channels of a signal.
+    pub channels_of: Option<Identifier>,
 }
 
 impl PointerFunction {
@@ -98,6 +122,37 @@ impl PointerFunction {
             ty: ty,
             args: args,
             ptr: ptr,
+            direct: false,
+            outputs: Vec::new(),
+            channels_of: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IntrinsicFunction {
+    pub ty: FunctionType,
+    pub args: ast::ArgumentList,
+    pub intrinsic: Arc<Intrinsic>,
+}
+
+impl IntrinsicFunction {
+    pub fn new(ty: FunctionType, func: Box<IntrinsicFn>) -> IntrinsicFunction {
+        let order = ty.args.keys().collect();
+        IntrinsicFunction::with_order(ty, order, func)
+    }
+
+    /// Creates an intrinsic whose arguments are passed, and can be given positionally, in the
+    /// order of `order` rather than the order of the identifiers.
+    pub fn with_order(ty: FunctionType, order: Vec<Identifier>, func: Box<IntrinsicFn>) -> IntrinsicFunction {
+        let mut args = Vec::new();
+        for id in order {
+            args.push(ast::Argument::Ident(Node(id, SourcePos::anon())));
+        }
+        IntrinsicFunction {
+            ty: ty,
+            args: args,
+            intrinsic: Arc::new(Intrinsic::new(func)),
         }
     }
 }
@@ -128,6 +183,10 @@ impl FunctionTable {
     }
 }
 
+/// Records which instance of a generic function each call uses, keyed by the function containing
+/// the call (None at the top level) and the source index of the callee.
+pub type InstanceTable = HashMap<(Option<Identifier>, usize), Identifier>;
+
 #[derive(Debug)]
 pub struct CallStack {
     stack: Vec<Identifier>,
@@ -153,7 +212,17 @@ impl CallStack {
     pub fn pop(&mut self) {
         self.stack.pop();
     }
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+    pub fn top(&self) -> Option<Identifier> {
+        self.stack.last().cloned()
+    }
     pub fn is_recursive(&self, id: Identifier) -> bool {
         self.recursive.contains(&id)
     }
+    /// Checks if the function is currently being checked further up the stack.
+    pub fn contains(&self, id: Identifier) -> bool {
+        self.stack.contains(&id)
+    }
 }
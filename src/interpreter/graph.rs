@@ -0,0 +1,224 @@
+use super::common::Context;
+use super::ast::{Item, FunctionDef, Expression, Statement, Argument, Block};
+use super::functions::Function;
+use super::ident::Identifier;
+use super::tokens::{Node, NodeImpl};
+
+use std::collections::HashMap;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NodeKind {
+    Entrypoint,
+    Function,
+    /// An intrinsic, builtin or external function, which has no body to walk into.
+    Host,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GraphNode {
+    pub name: String,
+    pub kind: NodeKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EdgeKind {
+    Call,
+    /// The output of a call is passed as the named argument, or the argument at an index.
+    Signal(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Graph {
+    pub name: String,
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<Edge>,
+}
+
+struct GraphBuilder<'a, 'b> {
+    ctxt: &'a Context<'a>,
+    defs: HashMap<Identifier, &'b FunctionDef>,
+    graph: Graph,
+    node_ids: HashMap<Identifier, usize>,
+}
+
+/// Builds the graph of everything reachable from the entrypoint of the parsed AST held by the
+/// context. Returns None if there is no function with that name.
+pub fn build_graph<'a>(ctxt: &'a Context<'a>, entrypoint: &str) -> Option<Graph> {
+    let ast = ctxt.ast.borrow();
+    let mut defs = HashMap::new();
+    for item in ast.iter() {
+        if let Item::FunctionDef(ref def) = *item {
+            defs.insert(def.ident(), &**def);
+        }
+    }
+    let entry_id = match defs.values().find(|x| ctxt.lookup_name(x.ident()) == entrypoint) {
+        Some(def) => def.ident(),
+        None => return None,
+    };
+    let mut builder = GraphBuilder {
+        ctxt: ctxt,
+        defs: defs,
+        graph: Graph {
+            name: ctxt.filename().to_string(),
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        },
+        node_ids: HashMap::new(),
+    };
+    builder.node(entry_id, NodeKind::Entrypoint);
+    Some(builder.graph)
+}
+
+impl<'a, 'b> GraphBuilder<'a, 'b> {
+    // Returns the node for a function, walking its body the first time it is seen. Calls of
+    // values which are not known functions, such as arguments, have no node.
+    fn node(&mut self, id: Identifier, kind: NodeKind) -> usize {
+        if let Some(&node) = self.node_ids.get(&id) {
+            return node;
+        }
+        let node = self.graph.nodes.len();
+        self.graph.nodes.push(GraphNode {
+            name: self.ctxt.lookup_name(id),
+            kind: kind,
+        });
+        self.node_ids.insert(id, node);
+        if let Some(def) = self.defs.get(&id).cloned() {
+            self.walk_block(&def.block, node);
+        }
+        node
+    }
+
+    fn callee_node(&mut self, callee: &Expression) -> Option<usize> {
+        let id = match *callee {
+            Expression::Variable(Node(id, _)) => id,
+            _ => return None,
+        };
+        if self.defs.contains_key(&id) {
+            return Some(self.node(id, NodeKind::Function));
+        }
+        let is_host = match self.ctxt.functions.borrow().get(id) {
+            Some(&Function::Intrinsic(_)) | Some(&Function::Pointer(_)) | Some(&Function::External(_)) => true,
+            _ => false,
+        };
+        if is_host {
+            Some(self.node(id, NodeKind::Host))
+        } else {
+            None
+        }
+    }
+
+    fn edge(&mut self, from: usize, to: usize, kind: EdgeKind) {
+        let edge = Edge {
+            from: from,
+            to: to,
+            kind: kind,
+        };
+        if !self.graph.edges.contains(&edge) {
+            self.graph.edges.push(edge);
+        }
+    }
+
+    fn walk_block(&mut self, block: &Block, caller: usize) {
+        for statement in block {
+            match *statement {
+                Statement::Assignment(ref assign) => self.walk_expr(assign.expr(), caller, None),
+                Statement::Expression(ref expr) => self.walk_expr(expr, caller, None),
+            }
+        }
+    }
+
+    // `consumer` is the call whose argument is being walked, along with the argument's label.
+    fn walk_expr(&mut self, expr: &Expression, caller: usize, consumer: Option<(usize, &str)>) {
+        match *expr {
+            Expression::Infix(ref x) => {
+                self.walk_expr(x.left(), caller, consumer);
+                self.walk_expr(x.right(), caller, consumer);
+            }
+            Expression::Prefix(ref x) => self.walk_expr(x.expr(), caller, consumer),
+            Expression::BlockRate(ref x) => self.walk_expr(x.expr(), caller, consumer),
+            Expression::Oversample(ref x) => self.walk_expr(x.expr(), caller, consumer),
+            Expression::Fft(ref x) => {
+                self.walk_expr(x.input(), caller, consumer);
+                self.walk_expr(x.expr(), caller, consumer);
+            }
+            Expression::Voice(ref x) => self.walk_expr(x.expr(), caller, consumer),
+            Expression::Array(ref x) => {
+                for elem in x.iter() {
+                    self.walk_expr(elem, caller, consumer);
+                }
+            }
+            Expression::Record(ref x) => {
+                for &(_, ref value) in &x.fields {
+                    self.walk_expr(value, caller, consumer);
+                }
+            }
+            Expression::Field(ref x) => self.walk_expr(x.expr(), caller, consumer),
+            Expression::Conditional(ref x) => {
+                self.walk_expr(x.cond(), caller, None);
+                self.walk_expr(x.then(), caller, consumer);
+                self.walk_expr(x.els(), caller, consumer);
+            }
+            Expression::Block(ref x) => self.walk_block(x.item(), caller),
+            Expression::Closure(ref x) => self.walk_block(&x.block, caller),
+            Expression::FunctionCall(ref call) => {
+                let callee = self.callee_node(call.callee());
+                if let Some(callee) = callee {
+                    self.edge(caller, callee, EdgeKind::Call);
+                    if let Some((to, label)) = consumer {
+                        self.edge(callee, to, EdgeKind::Signal(label.to_string()));
+                    }
+                }
+                for (i, arg) in call.args().iter().enumerate() {
+                    let label = match arg.ident() {
+                        Some(id) => self.ctxt.lookup_name(id),
+                        None => i.to_string(),
+                    };
+                    let expr = match *arg {
+                        Argument::Ident(_) => continue,
+                        Argument::Assign(_, ref expr) |
+                        Argument::OpAssign(_, _, ref expr) |
+                        Argument::Expr(ref expr) => expr,
+                    };
+                    self.walk_expr(expr, caller, callee.map(|x| (x, &label[..])));
+                }
+            }
+            Expression::Constant(_) | Expression::Boolean(_) | Expression::Str(_) |
+            Expression::Variable(_) => { },
+        }
+    }
+}
+
+impl Graph {
+    pub fn to_dot(&self) -> String {
+        let mut out = format!("digraph \"{}\" {{\n    rankdir=LR;\n", escape(&self.name));
+        for (i, node) in self.nodes.iter().enumerate() {
+            let attrs = match node.kind {
+                NodeKind::Entrypoint => "shape=box, style=bold",
+                NodeKind::Function => "shape=box",
+                NodeKind::Host => "shape=ellipse",
+            };
+            out.push_str(&format!("    n{} [label=\"{}\", {}];\n", i, escape(&node.name), attrs));
+        }
+        for edge in &self.edges {
+            match edge.kind {
+                EdgeKind::Call => out.push_str(&format!("    n{} -> n{};\n", edge.from, edge.to)),
+                EdgeKind::Signal(ref label) =>
+                    out.push_str(&format!("    n{} -> n{} [style=dashed, label=\"{}\"];\n",
+                                          edge.from, edge.to, escape(label))),
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace("\\", "\\\\").replace("\"", "\\\"")
+}
@@ -0,0 +1,210 @@
+use super::common::Context;
+use super::ast::{Item, FunctionDef, Expression, Statement, Argument, Block};
+use super::ident::Identifier;
+use super::purity::{Purity, for_each_child};
+use super::runtime::Input;
+use super::tokens::{Node, NodeImpl};
+
+use std::collections::{BTreeSet, HashMap};
+use std::rc::Rc;
+
+/// The arguments an invariant expression reads, as the indices of the function's arguments sorted
+/// by name, which is the order of its llvm arguments. None if the expression varies.
+type Deps = Option<BTreeSet<usize>>;
+
+/// The hoisted expressions of each function, by the address of their node, since an infix
+/// expression has the same position as its left operand. Each maps to the arguments it reads.
+pub type Hoisted = HashMap<(Identifier, usize), Vec<usize>>;
+
+/// The address identifying an expression in the result of hoisted_expressions(), or None for
+/// expressions which are never hoisted.
+pub fn node_address(expr: &Expression) -> Option<usize> {
+    fn address<T>(node: &Rc<T>) -> Option<usize> {
+        Some(&**node as *const T as usize)
+    }
+    match *expr {
+        Expression::Infix(ref x) => address(x),
+        Expression::Prefix(ref x) => address(x),
+        Expression::FunctionCall(ref x) => address(x),
+        Expression::Conditional(ref x) => address(x),
+        _ => None,
+    }
+}
+
+struct Analysis<'a, 'b> {
+    ctxt: &'a Context<'a>,
+    purity: Purity<'a, 'b>,
+    trace: Vec<String>,
+    hoisted: Hoisted,
+}
+
+// The function being analyzed.
+struct Frame<'b> {
+    def: &'b FunctionDef,
+    args: Vec<Identifier>, // sorted
+    keys: Vec<bool>, // for each argument, whether it is allowed in an invariant expression
+}
+
+/// The expressions of the type checked AST held by the context which should be hoisted: the
+/// largest calls which read only constants, globals and the parameters and sample rate of an
+/// entrypoint, so that they are evaluated again only when a parameter changes.
+pub fn hoisted_expressions<'a>(ctxt: &'a Context<'a>) -> Hoisted {
+    let ast = ctxt.ast.borrow();
+    let mut analysis = Analysis {
+        ctxt: ctxt,
+        purity: Purity::new(ctxt, &ast),
+        trace: ctxt.options.borrow().trace.clone(),
+        hoisted: HashMap::new(),
+    };
+    for item in ast.iter() {
+        let def = match *item {
+            Item::FunctionDef(ref def) => &**def,
+            Item::Assignment(_) => continue,
+        };
+        let mut args: Vec<Identifier> = def.args().iter().filter_map(|x| x.ident()).collect();
+        args.sort();
+        // only the parameters and the sample rate of an entrypoint stay the same between samples
+        let keys = match ctxt.inputs.borrow().get(def.ident()) {
+            Some(inputs) if inputs.len() == args.len() => inputs.iter().map(|input| match *input {
+                Input::Param(..) | Input::SampleRate => true,
+                Input::Time | Input::Beat | Input::Bar => false,
+            }).collect(),
+            _ => vec![false; args.len()],
+        };
+        analysis.function_body(&Frame { def: def, args: args, keys: keys });
+    }
+    analysis.hoisted
+}
+
+/// The union of the arguments of invariant expressions, or None if any varies.
+fn union<I>(deps: I) -> Deps where I: IntoIterator<Item=Deps> {
+    let mut all = BTreeSet::new();
+    for x in deps {
+        match x {
+            Some(x) => all.extend(x),
+            None => return None,
+        }
+    }
+    Some(all)
+}
+
+impl<'a, 'b> Analysis<'a, 'b> {
+    fn function_body(&mut self, frame: &Frame) {
+        let mut candidates = Vec::new();
+        self.block(frame, &frame.def.block, &mut HashMap::new(), &mut candidates);
+        for (address, deps) in candidates {
+            self.hoisted.insert((frame.def.ident(), address), deps.into_iter().collect());
+        }
+    }
+
+    // Finds what the value of a block depends on, adding the largest hoistable expressions in it
+    // to `candidates`. `locals` are the variables in scope and what they depend on.
+    fn block(&mut self, frame: &Frame, block: &Block, locals: &mut HashMap<Identifier, Deps>,
+             candidates: &mut Vec<(usize, BTreeSet<usize>)>) -> Deps {
+        let mut deps = Vec::new();
+        for statement in block {
+            match *statement {
+                Statement::Assignment(ref assign) => {
+                    let x = self.expr(frame, assign.expr(), locals, candidates);
+                    locals.insert(assign.ident(), x);
+                }
+                Statement::Expression(ref expr) => deps.push(self.expr(frame, expr, locals, candidates)),
+            }
+        }
+        union(deps)
+    }
+
+    fn expr(&mut self, frame: &Frame, expr: &Expression, locals: &mut HashMap<Identifier, Deps>,
+            candidates: &mut Vec<(usize, BTreeSet<usize>)>) -> Deps {
+        let mut inner = Vec::new();
+        let deps = match *expr {
+            Expression::Variable(Node(id, _)) => self.variable(frame, id, locals),
+            Expression::Block(ref x) => self.block(frame, x.item(), &mut locals.clone(), &mut inner),
+            Expression::BlockRate(_) | Expression::Oversample(_) | Expression::Fft(_) |
+            Expression::Voice(_) | Expression::Closure(_) => {
+                // nothing is hoisted out of these, since they see other values of the arguments
+                // than the rest of the function, such as interpolated ones in `@oversample`
+                let mut children = Vec::new();
+                for_each_child(expr, |child| children.push(child));
+                for child in children {
+                    self.expr(frame, child, locals, &mut Vec::new());
+                }
+                None
+            }
+            Expression::FunctionCall(ref call) => {
+                let mut deps = vec![self.callee(frame, call.callee(), locals)];
+                if deps[0].is_some() && !self.purity.is_pure_call(call, frame.def.ident()) {
+                    deps[0] = None;
+                }
+                for arg in call.args() {
+                    if let Argument::Ident(Node(id, _)) = *arg {
+                        deps.push(self.variable(frame, id, locals));
+                    }
+                    if let Argument::OpAssign(Node(id, _), _, _) = *arg {
+                        deps.push(self.variable(frame, id, locals));
+                    }
+                    if let Some(expr) = arg.expr() {
+                        deps.push(self.expr(frame, expr, locals, &mut inner));
+                    }
+                }
+                union(deps)
+            }
+            _ => {
+                let mut children = Vec::new();
+                for_each_child(expr, |child| children.push(child));
+                let deps: Vec<Deps> = children.iter().map(|child| self.expr(frame, child, locals, &mut inner)).collect();
+                union(deps)
+            }
+        };
+        match (node_address(expr), &deps) {
+            (Some(address), &Some(ref keys)) if makes_calls(expr) => candidates.push((address, keys.clone())),
+            _ => candidates.extend(inner),
+        }
+        deps
+    }
+
+    fn variable(&self, frame: &Frame, id: Identifier, locals: &HashMap<Identifier, Deps>) -> Deps {
+        if let Some(deps) = locals.get(&id) {
+            return deps.clone();
+        }
+        if let Ok(i) = frame.args.binary_search(&id) {
+            return if frame.keys[i] { Some(Some(i).into_iter().collect()) } else { None };
+        }
+        if self.purity.is_builtin_variable(id) {
+            None
+        } else {
+            // globals are only assigned once, before the first sample
+            Some(BTreeSet::new())
+        }
+    }
+
+    // A called function, which is invariant if it is a pure top level function, instead of a
+    // local variable or argument holding a closure.
+    fn callee(&mut self, frame: &Frame, callee: &Expression, locals: &HashMap<Identifier, Deps>) -> Deps {
+        let id = match *callee {
+            Expression::Variable(Node(id, _)) => id,
+            _ => return None,
+        };
+        let shadowed = locals.contains_key(&id) || frame.args.binary_search(&id).is_ok();
+        let traced = self.trace.contains(&self.ctxt.lookup_name(id));
+        let callee = self.purity.callee(callee, Some(frame.def.ident()));
+        let numeric = match callee {
+            Some(x) => !self.purity.defs.contains_key(&x) || self.purity.has_numeric_signature(x),
+            None => false,
+        };
+        if shadowed || traced || !numeric {
+            None
+        } else {
+            Some(BTreeSet::new())
+        }
+    }
+}
+
+fn makes_calls(expr: &Expression) -> bool {
+    if let Expression::FunctionCall(_) = *expr {
+        return true;
+    }
+    let mut calls = false;
+    for_each_child(expr, |child| calls = calls || makes_calls(child));
+    calls
+}
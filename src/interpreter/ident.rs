@@ -1,13 +1,24 @@
 use std::collections::HashMap;
+use std::cmp;
+use std::mem;
+use std::slice;
 
-/// Represents an identifier name in program source code.
+/// Represents an identifier name in program source code. Identifiers are numbered in the order
+/// they are created, starting from 0, and are never reused.
 pub type Identifier = usize;
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Kind {
+    Named,
+    Anon,
+    Alias,
+}
+
 #[derive(Debug)]
 pub struct NameTable<'a> {
-    identifier_names: HashMap<Identifier, &'a str>,
-    identifier_ids: HashMap<&'a str, Identifier>,
-    max_id: usize,
+    names: Vec<(&'a str, Kind)>, // indexed by Identifier
+    identifier_ids: HashMap<String, Identifier>, // keyed by folded name
+    case_insensitive: bool,
 }
 
 const ANON_NAME: &'static str = "*anon*";
@@ -15,36 +26,150 @@ const ANON_NAME: &'static str = "*anon*";
 impl<'a> NameTable<'a> {
     pub fn new() -> NameTable<'a> {
         NameTable {
-            identifier_names: HashMap::new(),
+            names: Vec::new(),
             identifier_ids: HashMap::new(),
-            max_id: 0,
+            case_insensitive: false,
         }
     }
 
+    /// Makes names which differ only in case refer to the same identifier, which keeps the
+    /// spelling it was first created with. Names already in the table are merged the same way,
+    /// the earliest identifier winning.
+    pub fn set_case_insensitive(&mut self, case_insensitive: bool) {
+        if self.case_insensitive == case_insensitive {
+            return;
+        }
+        self.case_insensitive = case_insensitive;
+        self.identifier_ids.clear();
+        for id in 0..self.names.len() {
+            let (name, kind) = self.names[id];
+            if kind == Kind::Named {
+                let key = self.fold(name);
+                self.identifier_ids.entry(key).or_insert(id);
+            }
+        }
+    }
+    pub fn is_case_insensitive(&self) -> bool {
+        self.case_insensitive
+    }
+
+    fn fold(&self, name: &str) -> String {
+        if self.case_insensitive {
+            name.to_lowercase()
+        } else {
+            name.to_string()
+        }
+    }
+    fn push(&mut self, name: &'a str, kind: Kind) -> Identifier {
+        self.names.push((name, kind));
+        self.names.len() - 1
+    }
+
     pub fn new_id(&mut self, name: &'a str) -> Identifier {
         if let Some(id) = self.get_id(name) {
             return id;
         }
-        let id = self.max_id;
-        self.max_id += 1;
-        self.identifier_names.insert(id, name);
-        self.identifier_ids.entry(name).or_insert(id);
+        let id = self.push(name, Kind::Named);
+        let key = self.fold(name);
+        self.identifier_ids.insert(key, id);
         id
     }
     /// Creates a new identifier which cannot be looked up by name.
     pub fn new_anon(&mut self) -> Identifier {
-        let id = self.max_id;
-        self.max_id += 1;
-        self.identifier_names.insert(id, ANON_NAME);
-        id
+        self.push(ANON_NAME, Kind::Anon)
+    }
+    /// Creates a new identifier sharing the name of an existing one, which cannot be looked up
+    /// by name.
+    pub fn new_alias(&mut self, id: Identifier) -> Identifier {
+        let name = self.name(id);
+        self.push(name, Kind::Alias)
     }
     pub fn is_anon(&self, id: Identifier) -> Option<bool> {
-        self.identifier_names.get(&id).map(|&x| x == ANON_NAME)
+        self.names.get(id).map(|&(_, kind)| kind == Kind::Anon)
+    }
+    /// Checks if the identifier was created by new_alias().
+    pub fn is_alias(&self, id: Identifier) -> Option<bool> {
+        self.names.get(id).map(|&(_, kind)| kind == Kind::Alias)
+    }
+    pub fn get_id(&self, name: &str) -> Option<Identifier> {
+        self.identifier_ids.get(&self.fold(name)).map(|x| *x)
+    }
+    pub fn get_name(&self, id: Identifier) -> Option<&'a str> {
+        self.names.get(id).map(|&(name, _)| name)
+    }
+    /// The name of an identifier which is known to exist, such as one taken from the AST.
+    pub fn name(&self, id: Identifier) -> &'a str {
+        match self.get_name(id) {
+            Some(name) => name,
+            None => panic!("no identifier with id {}", id),
+        }
     }
-    pub fn get_id(&'a self, name: &'a str) -> Option<Identifier> {
-        self.identifier_ids.get(name).map(|x| *x)
+    pub fn len(&self) -> usize {
+        self.names.len()
     }
-    pub fn get_name(&'a self, id: Identifier) -> Option<&'a str> {
-        self.identifier_names.get(&id).map(|x| *x)
+    /// Iterates over every identifier and its name in the order they were created, including
+    /// anonymous ones and aliases.
+    pub fn iter<'b>(&'b self) -> Iter<'a, 'b> {
+        Iter {
+            names: self.names.iter().enumerate(),
+            named_only: false,
+        }
     }
+    /// Iterates over the identifiers which can be looked up by name, in the order they were
+    /// created, such as to suggest names for a misspelled one.
+    pub fn names<'b>(&'b self) -> Iter<'a, 'b> {
+        Iter {
+            names: self.names.iter().enumerate(),
+            named_only: true,
+        }
+    }
+}
+
+pub struct Iter<'a, 'b> where 'a: 'b {
+    names: ::std::iter::Enumerate<slice::Iter<'b, (&'a str, Kind)>>,
+    named_only: bool,
+}
+
+impl<'a, 'b> Iterator for Iter<'a, 'b> {
+    type Item = (Identifier, &'a str);
+
+    fn next(&mut self) -> Option<(Identifier, &'a str)> {
+        while let Some((id, &(name, kind))) = self.names.next() {
+            if !self.named_only || kind == Kind::Named {
+                return Some((id, name));
+            }
+        }
+        None
+    }
+}
+
+/// The number of single character insertions, deletions and substitutions needed to turn one
+/// string into the other.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..b.len() + 1).collect();
+    let mut cur = vec![0; b.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        cur[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitute = prev[j] + if ca == cb { 0 } else { 1 };
+            cur[j + 1] = *[substitute, prev[j + 1] + 1, cur[j] + 1].iter().min().unwrap();
+        }
+        mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
+/// Picks the candidates close enough to `name` to plausibly be what was meant by it, closest
+/// first.
+pub fn similar_names<I>(name: &str, candidates: I) -> Vec<String> where I: Iterator<Item=String> {
+    let max_distance = cmp::max(1, name.chars().count() / 3);
+    let mut similar: Vec<(usize, String)> = candidates
+        .filter(|x| x != name)
+        .map(|x| (edit_distance(name, &x), x))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .collect();
+    similar.sort();
+    similar.dedup();
+    similar.into_iter().take(3).map(|(_, x)| x).collect()
 }
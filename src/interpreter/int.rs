@@ -0,0 +1,56 @@
+use super::compiler::Compiler;
+use super::dsp::trigger;
+use super::runtime::{self, CallSite};
+use super::tokens::Number;
+use super::types::Type;
+
+/// The largest magnitude of an Int. Ints are held like numbers, so they are exact up to here.
+pub const MAX_INT: Number = 9007199254740992.0;
+
+/// The bits of an Int, as a 64 bit two's complement integer.
+pub fn to_bits(x: Number) -> i64 {
+    // NaN is cast to 0
+    x.max(-MAX_INT).min(MAX_INT) as i64
+}
+
+/// The implementation of `int`.
+pub extern fn int(_: CallSite, x: Number) -> Number {
+    x.floor().max(-MAX_INT).min(MAX_INT)
+}
+
+/// The implementations of the bitwise operators. Shifts are by their amount modulo 64, and `>>`
+/// keeps the sign.
+pub extern fn bit_and(a: Number, b: Number) -> Number {
+    (to_bits(a) & to_bits(b)) as Number
+}
+pub extern fn bit_or(a: Number, b: Number) -> Number {
+    (to_bits(a) | to_bits(b)) as Number
+}
+pub extern fn shift_left(a: Number, b: Number) -> Number {
+    to_bits(a).wrapping_shl(to_bits(b) as u32) as Number
+}
+pub extern fn shift_right(a: Number, b: Number) -> Number {
+    to_bits(a).wrapping_shr(to_bits(b) as u32) as Number
+}
+
+/// The implementation of `step`, which is 0 until `clock` has fired twice and goes up by 1 each
+/// time it fires after that, like the step of a `seq` with no end.
+pub extern fn step(site: CallSite, clock: Number) -> Number {
+    runtime::with_site_state(site, |state| {
+        let mem = state.memory(2); // the current step, whether the clock has fired before
+        if trigger::fired(clock) {
+            if mem[1] != 0.0 {
+                mem[0] = (mem[0] + 1.0).min(MAX_INT);
+            }
+            mem[1] = 1.0;
+        }
+        mem[0]
+    })
+}
+
+pub fn define_intrinsics(compiler: &Compiler) {
+    unsafe {
+        compiler.define_direct_builtin("int", &[("x", Type::Number)], Type::Int, int as *mut ());
+        compiler.define_direct_builtin("step", &[("clock", Type::Number)], Type::Int, step as *mut ());
+    }
+}
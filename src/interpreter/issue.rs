@@ -1,73 +1,262 @@
 use super::tokens::SourcePos;
 use super::common::Context;
+use super::codes::Code;
+use super::lexer::token_width;
+
+use libc;
 
 use std::fmt;
+use std::cmp;
 use std::borrow::Cow;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Level {
     Error,
     Warning,
+    /// Extra information about the issue reported before it, such as a related position.
+    Note,
+    /// A suggestion for fixing the issue it is attached to.
+    Help,
 }
 
+impl Level {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Note => "note",
+            Level::Help => "help",
+        }
+    }
+
+    // ANSI escape for the bold color the level is shown in.
+    fn color(&self) -> &'static str {
+        match *self {
+            Level::Error => "\x1b[1;31m",
+            Level::Warning => "\x1b[1;33m",
+            Level::Note => "\x1b[1;32m",
+            Level::Help => "\x1b[1;36m",
+        }
+    }
+}
+
+/// How the warnings of a lint are reported.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+/// Whether issues are rendered with colors.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ColorChoice {
+    /// Use colors when standard output is a terminal.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn parse(s: &str) -> Option<ColorChoice> {
+        Some(match s {
+            "auto" => ColorChoice::Auto,
+            "always" => ColorChoice::Always,
+            "never" => ColorChoice::Never,
+            _ => return None,
+        })
+    }
+
+    pub fn use_color(&self) -> bool {
+        match *self {
+            ColorChoice::Auto => stdout_is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn stdout_is_terminal() -> bool {
+    unsafe { libc::isatty(libc::STDOUT_FILENO) != 0 }
+}
+
+#[cfg(not(unix))]
+fn stdout_is_terminal() -> bool {
+    false
+}
+
+const BOLD: &'static str = "\x1b[1m";
+const GUTTER: &'static str = "\x1b[1;34m";
+const RESET: &'static str = "\x1b[0m";
+
 #[derive(Debug, Clone)]
 pub struct Issue<'a> {
-    pub source: &'a str,
+    pub line: &'a str, // the source line the issue is on
     pub filename: &'a str,
     pub pos: SourcePos,
+    pub width: usize, // of the underlined span, in characters
     pub msg: Cow<'static, str>,
     pub ty: Level,
+    pub code: Code,
+    /// Notes and help without a position of their own, shown below the source.
+    pub children: Vec<(Level, Cow<'static, str>)>,
 }
 
 impl<'a> Issue<'a> {
-    pub fn new(source: &'a str,
+    pub fn new(line: &'a str,
                filename: &'a str,
                pos: SourcePos,
+               width: usize,
                ty: Level,
+               code: Code,
                msg: Cow<'static, str>) -> Issue<'a> {
         Issue {
-            source: source,
+            line: line,
             filename: filename,
             pos: pos,
+            width: width,
             msg: msg,
             ty: ty,
+            code: code,
+            children: Vec::new(),
         }
     }
+
+    /// Renders the issue in the style of rustc, with ANSI colors if `color` is set:
+    ///
+    /// ```text
+    /// error[E100]: no variable named `y` is in scope
+    ///  --> patch.syn:1:5
+    ///   |
+    /// 1 | f { y }
+    ///   |     ^
+    /// ```
+    pub fn render(&self, color: bool) -> String {
+        let paint = |style: &str, text: &str| if color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        };
+        let mut out = format!("{}{}\n",
+                              paint(self.ty.color(), &format!("{}[{}]", self.ty.name(), self.code)),
+                              paint(BOLD, &format!(": {}", self.msg)));
+        let gutter = repeat(" ", if self.pos.is_anon() { 1 } else { self.pos.line.to_string().len() + 1 });
+        if self.pos.is_anon() {
+            out.push_str(&format!("{}{} {}\n", gutter, paint(GUTTER, "-->"), self.filename));
+        } else {
+            let line = self.line.replace("\t", " ");
+            let mark = if self.ty == Level::Error || self.ty == Level::Warning { "^" } else { "-" };
+            let underline = format!("{}{}", repeat(" ", self.pos.column - 1),
+                                    paint(self.ty.color(), &repeat(mark, cmp::max(self.width, 1))));
+            out.push_str(&format!("{}{} {}:{}\n", &gutter[1..], paint(GUTTER, "-->"), self.filename, self.pos));
+            out.push_str(&format!("{}{}\n", gutter, paint(GUTTER, "|")));
+            out.push_str(&format!("{}{} {}\n", paint(GUTTER, &format!("{} ", self.pos.line)), paint(GUTTER, "|"), line));
+            out.push_str(&format!("{}{} {}\n", gutter, paint(GUTTER, "|"), underline));
+        }
+        for &(level, ref msg) in &self.children {
+            out.push_str(&format!("{}{} {}: {}\n", gutter, paint(GUTTER, "="), paint(BOLD, level.name()), msg));
+        }
+        out
+    }
+}
+
+fn repeat(s: &str, n: usize) -> String {
+    (0..n).map(|_| s).collect()
 }
 
 impl<'a> fmt::Display for Issue<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        // oh god why
-        let line = &self.source[self.pos.line_index..];
-        let line = line[..line.find('\n').unwrap_or(line.len())].to_string();
-        let line = line.replace("\t", " ");
-        let align = self.filename.len() + self.pos.to_string().len() + 3;
-        write!(f, "{0}+{1} ┬ {2:?}: {3}\n{4:>5$} {6}\n{7:>5$}{8:─>9$}┘",
-               self.filename, self.pos, self.ty, self.msg,
-               "┃", align, line,
-               "└", "", self.pos.index - self.pos.line_index + 1
-        )
+        write!(f, "{}", self.render(false))
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct IssueTracker<'a> {
     issues: Vec<Issue<'a>>,
+    suppressed: bool, // whether the last issue was dropped, so its notes are dropped too
+    color: bool,
+    error_limit: Option<usize>,
 }
 
+/// The number of errors shown before the rest are summarized, unless set otherwise.
+pub const DEFAULT_ERROR_LIMIT: usize = 20;
+
 impl<'a> IssueTracker<'a> {
     pub fn new() -> IssueTracker<'a> {
         IssueTracker {
             issues: Vec::new(),
+            suppressed: false,
+            color: false,
+            error_limit: Some(DEFAULT_ERROR_LIMIT),
         }
     }
 
-    pub fn new_issue<T>(&mut self, ctxt: &'a Context, pos: SourcePos, ty: Level, msg: T)
+    /// Sets how many errors are shown when the issues are displayed, or None to show them all.
+    /// Warnings are always shown.
+    pub fn set_error_limit(&mut self, limit: Option<usize>) {
+        self.error_limit = limit;
+    }
+
+    /// Renders issues with ANSI colors when they are displayed.
+    pub fn set_color(&mut self, color: bool) {
+        self.color = color;
+    }
+
+    pub fn new_issue<T>(&mut self, ctxt: &'a Context, pos: SourcePos, ty: Level, code: Code, msg: T)
             where T: Into<Cow<'static, str>> {
-        let issue = Issue::new(&ctxt.source, &ctxt.filename, pos, ty, msg.into());
+        let ty = match ty {
+            Level::Warning => match ctxt.options.borrow().lint_level(code) {
+                LintLevel::Allow => {
+                    self.suppressed = true;
+                    return;
+                },
+                LintLevel::Warn => Level::Warning,
+                LintLevel::Deny => Level::Error,
+            },
+            Level::Note | Level::Help if self.suppressed => return,
+            ty => ty,
+        };
+        let msg = msg.into();
+        if ty != Level::Note && ty != Level::Help {
+            // cascading errors often report the same thing in the same place several times
+            let duplicate = self.issues.iter().any(|x| x.ty == ty && x.pos == pos && x.msg == msg);
+            self.suppressed = duplicate;
+            if duplicate {
+                return;
+            }
+        }
+        let width = if pos.is_anon() { 1 } else { token_width(ctxt.files.source_from(pos)) };
+        let issue = Issue::new(ctxt.files.line(pos), &ctxt.files.get(pos.file).name, pos, width, ty, code, msg);
         self.issues.push(issue);
     }
 
+    /// Attaches a note or help message without a position to the last issue.
+    pub fn add_child<T>(&mut self, level: Level, msg: T) where T: Into<Cow<'static, str>> {
+        if self.suppressed {
+            return;
+        }
+        if let Some(issue) = self.issues.last_mut() {
+            issue.children.push((level, msg.into()));
+        }
+    }
+
+    /// Each issue followed by the notes attached to it, ordered by the position of the issue
+    /// rather than the order they were found in. Issues without a position come last.
+    pub fn groups(&self) -> Vec<&[Issue<'a>]> {
+        let mut groups: Vec<&[Issue<'a>]> = Vec::new();
+        let mut start = 0;
+        for i in 1..self.issues.len() + 1 {
+            if i == self.issues.len() || (self.issues[i].ty != Level::Note && self.issues[i].ty != Level::Help) {
+                groups.push(&self.issues[start..i]);
+                start = i;
+            }
+        }
+        // sorting is stable, so issues at the same position keep their order
+        groups.sort_by_key(|x| (x[0].pos.is_anon(), x[0].pos.index));
+        groups
+    }
+
     pub fn has_errors(&self) -> bool {
         self.issues.iter().fold(false, |acc, ref item| acc | (item.ty == Level::Error))
     }
@@ -85,10 +274,25 @@ impl<'a> fmt::Display for IssueTracker<'a> {
         if self.issues.len() == 0 {
             write!(f, "No issues!")
         } else {
-            for issue in self.issues.iter() {
-                try!(write!(f, "{}\n", issue));
+            let mut errors = 0;
+            for group in self.groups() {
+                if group[0].ty == Level::Error {
+                    errors += 1;
+                    if self.error_limit.map_or(false, |x| errors > x) {
+                        continue;
+                    }
+                }
+                for issue in group {
+                    try!(write!(f, "{}\n", issue.render(self.color)));
+                }
+            }
+            match self.error_limit {
+                Some(limit) if errors > limit => {
+                    let hidden = errors - limit;
+                    write!(f, "... and {} more error{}\n", hidden, if hidden == 1 { "" } else { "s" })
+                }
+                _ => Ok(()),
             }
-            Ok(())
         }
     }
 }
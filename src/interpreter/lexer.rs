@@ -1,95 +1,424 @@
 use super::common::Context;
+use super::codes::Code;
 use super::tokens::*;
 
-use regex::Regex;
-use std::str::FromStr;
+use unicode_xid::UnicodeXID;
+use std::char;
+use std::cmp;
 
-static IDENT_REGEX: Regex = regex!(r"[a-zA-Z_~']+[a-zA-Z_~0-9']*");
-static WHITESPACE_REGEX: Regex = regex!(r"[ \t]+");
-static CONST_REGEX: Regex = regex!(r"([0-9]+\.?[0-9]*|[0-9]*\.?[0-9]+)([eE]-?[0-9]+)?");
-static OPERATOR_REGEX: Regex = regex!(r"\^\^|>=|<=|!=|[\+\*/\^><!%-]|&&|\|\||==");
-static SYMBOL_REGEX: Regex = regex!(r"if|else|[\.,=:;\?\(\)\{\}\]\[\\@]");
-static BOOLEAN_REGEX: Regex = regex!(r"true|false");
-static COMMENT_REGEX: Regex = regex!(r"//.*");
-static NEWLINE_REGEX: Regex = regex!(r"[\n\r]");
+/// Walks the source one character at a time, keeping track of the position of the next one.
+struct Scanner<'a> {
+    source: &'a str,
+    offset: usize,
+    pos: SourcePos,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(source: &'a str, start: SourcePos) -> Scanner<'a> {
+        Scanner {
+            source: source,
+            offset: 0,
+            pos: start,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.offset..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    /// The character after the next one.
+    fn peek_second(&self) -> Option<char> {
+        let mut chars = self.rest().chars();
+        chars.next();
+        chars.next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return None,
+        };
+        self.offset += c.len_utf8();
+        match c {
+            // a \r\n pair is a single line break
+            '\r' if self.peek() == Some('\n') => self.pos.add_chars(1),
+            '\n' | '\r' => self.pos.add_line(),
+            c => self.pos.add_char(c),
+        }
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_str(&mut self, s: &str) -> bool {
+        if self.rest().starts_with(s) {
+            for _ in s.chars() {
+                self.bump();
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consumes characters while they satisfy `f`, returning them.
+    fn eat_while<F>(&mut self, f: F) -> &'a str where F: Fn(char) -> bool {
+        let start = self.offset;
+        while self.peek().map_or(false, |c| f(c)) {
+            self.bump();
+        }
+        &self.source[start..self.offset]
+    }
+
+    /// Consumes an identifier, returning it. A `~` which starts the `~=` operator is not part of
+    /// it, so `a~=b` compares `a` and `b`.
+    fn eat_ident(&mut self) -> &'a str {
+        let start = self.offset;
+        while self.peek().map_or(false, is_ident_continue) && !self.rest().starts_with("~=") {
+            self.bump();
+        }
+        &self.source[start..self.offset]
+    }
+}
+
+/// The version of the language a file is read as when it has no `#version` pragma.
+pub const DEFAULT_VERSION: u32 = 1;
+/// The newest version of the language.
+pub const LATEST_VERSION: u32 = 2;
 
 pub fn lex<'a>(ctxt: &'a Context<'a>) {
-    let mut walk = &ctxt.source[..];
+    for (id, file) in ctxt.files.iter().enumerate() {
+        lex_file(ctxt, &mut Scanner::new(&file.source, SourcePos::start_of(id, file.offset)));
+    }
+}
+
+fn lex_file<'a>(ctxt: &'a Context<'a>, scanner: &mut Scanner<'a>) {
+    let version = lex_version(ctxt, scanner);
     let mut tokens = ctxt.tokens.borrow_mut();
-    let mut pos = SourcePos::new();
-
-    while walk.len() > 0 {
-        // Strip whitespace
-        if let Some((0, x)) = WHITESPACE_REGEX.find(walk) {
-            walk = &walk[x..];
-            pos.add_chars(x);
-            continue;
-        }
-
-        // Strip comments
-        if let Some((0, x)) = COMMENT_REGEX.find(walk) {
-            walk = &walk[x..];
-            pos.add_chars(x);
-            continue;
-        }
-
-        // Add operators
-        if let Some((0, x)) = OPERATOR_REGEX.find(walk) {
-            // If this fails either the regex or the parser is wrong.
-            let op = Operator::parse(&walk[0..x]).unwrap();
-            tokens.push(Node(Token::Operator(op), pos));
-            walk = &walk[x..];
-            pos.add_chars(x);
-            continue;
-        }
-
-        // Add symbols
-        if let Some((0, x)) = SYMBOL_REGEX.find(walk) {
-            // If this fails either the regex or the parser is wrong.
-            let sym = Symbol::parse(&walk[0..x]).unwrap();
-            tokens.push(Node(Token::Symbol(sym), pos));
-            walk = &walk[x..];
-            pos.add_chars(x);
-            continue;
-        }
-
-        // Add boolean literals
-        if let Some((0, x)) = BOOLEAN_REGEX.find(walk) {
-            // If this fails either the regex or the parser is wrong.
-            let val = bool::from_str(&walk[0..x]).unwrap();
-            tokens.push(Node(Token::Boolean(val), pos));
-            walk = &walk[x..];
-            pos.add_chars(x);
-            continue;
-        }
-
-        // Add identifiers
-        if let Some((0, x)) = IDENT_REGEX.find(walk) {
-            let id = ctxt.names.borrow_mut().new_id(&walk[0..x]);
-            tokens.push(Node(Token::Ident(id), pos));
-            walk = &walk[x..];
-            pos.add_chars(x);
-            continue;
-        }
-
-        // Strip newlines
-        if let Some((0, x)) = NEWLINE_REGEX.find(walk) {
-            walk = &walk[x..];
-            pos.add_line();
-            continue;
-        }
-
-        if let Some((0, x)) = CONST_REGEX.find(walk) {
-            let v = walk[0..x].parse().unwrap(); // If this fails either the regex or the parser is wrong.
-            tokens.push(Node(Token::Const(v), pos));
-            walk = &walk[x..];
-            pos.add_chars(x);
-            continue;
-        }
-
-        // If none of the checks above found a token, then it's not supported.
-        ctxt.emit_error("unrecognized token", pos);
-        walk = &walk[1..];
-        pos.add_chars(1);
+
+    while let Some(c) = scanner.peek() {
+        let pos = scanner.pos;
+        let token = match c {
+            c if c.is_whitespace() => {
+                scanner.bump();
+                continue;
+            }
+            '/' if scanner.rest().starts_with("//") => {
+                let comment = scanner.eat_while(|c| c != '\n' && c != '\r');
+                // doc comments are kept aside for the parser to attach to the definition that
+                // follows
+                if comment.starts_with("///") {
+                    let text = &comment[3..];
+                    let text = if text.starts_with(" ") { &text[1..] } else { text };
+                    ctxt.docs.borrow_mut().push(Node(text.trim_right().to_string(), pos));
+                }
+                continue;
+            }
+            '/' if scanner.rest().starts_with("/*") => {
+                // block comments may span several lines, and do not nest
+                scanner.eat_str("/*");
+                loop {
+                    if scanner.eat_str("*/") {
+                        break;
+                    }
+                    if scanner.bump().is_none() {
+                        ctxt.emit_error(Code::UnterminatedComment, "unterminated block comment", pos);
+                        break;
+                    }
+                }
+                continue;
+            }
+            '#' if scanner.rest().starts_with("#version") => {
+                scanner.eat_while(|c| c != '\n' && c != '\r');
+                ctxt.emit_error(Code::InvalidVersion, "the version pragma must come before anything else in a file",
+                                pos);
+                continue;
+            }
+            '"' => match lex_string(scanner) {
+                Ok(text) => {
+                    let mut strings = ctxt.strings.borrow_mut();
+                    strings.push(text);
+                    Token::Str(strings.len() - 1)
+                }
+                Err((code, msg, pos)) => {
+                    ctxt.emit_error(code, msg, pos);
+                    continue;
+                }
+            },
+            c if c.is_digit(10) || (c == '.' && scanner.peek_second().map_or(false, |x| x.is_digit(10))) => {
+                match lex_number(scanner) {
+                    Ok(x) => Token::Const(x),
+                    Err(msg) => {
+                        ctxt.emit_error(Code::InvalidNumber, msg, pos);
+                        continue;
+                    }
+                }
+            }
+            // `~` may start an identifier, but not the `~=` operator
+            c if is_ident_start(c) && !scanner.rest().starts_with("~=") => {
+                let word = scanner.eat_ident();
+                match keyword(version, word) {
+                    Some(token) => token,
+                    None => Token::Ident(ctxt.names.borrow_mut().new_id(word)),
+                }
+            }
+            _ => match lex_punctuation(scanner) {
+                Some(token) => token,
+                None => {
+                    scanner.bump();
+                    ctxt.emit_error(Code::UnrecognizedToken, format!("unrecognized character `{}`", c), pos);
+                    continue;
+                }
+            },
+        };
+        tokens.push(Node(token, pos));
     }
 }
+
+// A file may start with `#version 2` to be read as that version of the language, and is otherwise
+// read as version 1. Whitespace may come before the pragma, but nothing else may.
+fn lex_version(ctxt: &Context, scanner: &mut Scanner) -> u32 {
+    scanner.eat_while(|c| c.is_whitespace());
+    let pos = scanner.pos;
+    if !scanner.eat_str("#version") {
+        return DEFAULT_VERSION;
+    }
+    scanner.eat_while(|c| c == ' ' || c == '\t');
+    let digits = scanner.eat_while(|c| c.is_digit(10));
+    match digits.parse() {
+        Ok(version) if version >= 1 && version <= LATEST_VERSION => version,
+        Ok(version) => {
+            ctxt.emit_error(Code::InvalidVersion,
+                            format!("unsupported language version {}, expected 1 to {}", version, LATEST_VERSION),
+                            pos);
+            DEFAULT_VERSION
+        }
+        Err(_) => {
+            ctxt.emit_error(Code::InvalidVersion, "expected a version number after `#version`", pos);
+            DEFAULT_VERSION
+        }
+    }
+}
+
+// The words which are not identifiers in each version of the language. Version 2 adds `and`, `or`
+// and `not` as spellings of `&&`, `||` and `!`.
+fn keyword(version: u32, word: &str) -> Option<Token> {
+    Some(match word {
+        "true" => Token::Boolean(true),
+        "false" => Token::Boolean(false),
+        "if" => Token::Symbol(Symbol::If),
+        "else" => Token::Symbol(Symbol::Else),
+        "and" if version >= 2 => Token::Operator(Operator::And),
+        "or" if version >= 2 => Token::Operator(Operator::Or),
+        "not" if version >= 2 => Token::Operator(Operator::Not),
+        _ => return None,
+    })
+}
+
+// Strings may not span lines. Quotes, backslashes and control characters can be written with the
+// escapes `\"`, `\\`, `\n`, `\r`, `\t` and `\0`, and any character as `\u{1F3B5}`.
+fn lex_string(scanner: &mut Scanner) -> Result<String, (Code, String, SourcePos)> {
+    let start = scanner.pos;
+    scanner.bump();
+    let mut text = String::new();
+    let mut error = None;
+    loop {
+        let pos = scanner.pos;
+        match scanner.peek() {
+            Some('"') => {
+                scanner.bump();
+                break;
+            }
+            None | Some('\n') | Some('\r') => {
+                return Err((Code::UnterminatedString, "unterminated string literal".to_string(), start));
+            }
+            Some('\\') => {
+                scanner.bump();
+                match lex_escape(scanner) {
+                    // the rest of the string is still read, so the error is only reported once
+                    Err(msg) => if error.is_none() {
+                        error = Some((Code::InvalidEscape, msg, pos));
+                    },
+                    Ok(c) => text.push(c),
+                }
+            }
+            Some(c) => {
+                scanner.bump();
+                text.push(c);
+            }
+        }
+    }
+    match error {
+        Some(e) => Err(e),
+        None => Ok(text),
+    }
+}
+
+// Reads the rest of an escape sequence, after the backslash.
+fn lex_escape(scanner: &mut Scanner) -> Result<char, String> {
+    let c = match scanner.peek() {
+        // an unterminated string is reported instead
+        None | Some('\n') | Some('\r') => return Ok('\\'),
+        Some(c) => c,
+    };
+    scanner.bump();
+    Ok(match c {
+        '"' => '"',
+        '\\' => '\\',
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        '0' => '\0',
+        'u' => {
+            if !scanner.eat('{') {
+                return Err("expected `{` after `\\u`".to_string());
+            }
+            let digits = scanner.eat_while(|c| c.is_digit(16));
+            if !scanner.eat('}') || digits.is_empty() || digits.len() > 6 {
+                return Err("expected 1 to 6 hexadecimal digits in braces after `\\u`".to_string());
+            }
+            match u32::from_str_radix(digits, 16).ok().and_then(char::from_u32) {
+                Some(c) => c,
+                None => return Err(format!("`\\u{{{}}}` is not a valid character", digits)),
+            }
+        }
+        c => return Err(format!("unknown escape sequence `\\{}`", c)),
+    })
+}
+
+/// How many characters the token at the start of `source` spans, for underlining it in
+/// diagnostics. Anything which is not a token counts as one character.
+pub fn token_width(source: &str) -> usize {
+    let mut scanner = Scanner::new(source, SourcePos::new());
+    match scanner.peek() {
+        Some('"') => { let _ = lex_string(&mut scanner); }
+        Some(c) if c.is_digit(10) => { let _ = lex_number(&mut scanner); }
+        Some(c) if is_ident_start(c) && !source.starts_with("~=") => { scanner.eat_ident(); }
+        Some(_) => if lex_punctuation(&mut scanner).is_none() {
+            scanner.bump();
+        },
+        None => { },
+    }
+    // an unterminated string stops at the end of its line
+    cmp::max(scanner.pos.column - 1, 1)
+}
+
+/// The quoted string literal at the start of `source`, as written.
+pub fn raw_string_literal(source: &str) -> &str {
+    let mut scanner = Scanner::new(source, SourcePos::new());
+    let _ = lex_string(&mut scanner);
+    &source[..scanner.offset]
+}
+
+// Operators and symbols, longest first so that `->` is not lexed as `-` followed by `>`.
+const PUNCTUATION: &'static [&'static str] = &[
+    "->", "^^", ">=", "<=", "!=", "~=", "&&", "||", "==", "<<", ">>",
+    "+", "-", "*", "/", "^", ">", "<", "!", "%", "&", "|",
+    ".", ",", "=", ":", ";", "?", "(", ")", "{", "}", "[", "]", "\\", "@",
+];
+
+fn lex_punctuation(scanner: &mut Scanner) -> Option<Token> {
+    for &text in PUNCTUATION {
+        if scanner.rest().starts_with(text) {
+            scanner.eat_str(text);
+            return Some(match Operator::parse(text) {
+                Some(op) => Token::Operator(op),
+                // If this fails either the table or the parser is wrong.
+                None => Token::Symbol(Symbol::parse(text).unwrap()),
+            });
+        }
+    }
+    None
+}
+
+// Numbers are digits with an optional fraction, such as `1`, `1.5`, `1.` or `.5`, followed by an
+// optional exponent such as `e3`, `E+3` or `e-3`. Hexadecimal integers start with `0x`. Digits may
+// be separated with underscores, as in `1_000`.
+fn lex_number(scanner: &mut Scanner) -> Result<Number, String> {
+    let start = scanner.offset;
+    let value = if scanner.rest().starts_with("0x") || scanner.rest().starts_with("0X") {
+        if !scanner.eat_str("0x") {
+            scanner.eat_str("0X");
+        }
+        let digits = scanner.eat_while(|c| c.is_digit(16) || c == '_').replace("_", "");
+        if digits.is_empty() {
+            return Err(malformed_number(scanner, start, "expected hexadecimal digits after `0x` in"));
+        }
+        match u64::from_str_radix(&digits, 16) {
+            Ok(x) => Some(x as Number),
+            Err(_) => None,
+        }
+    } else {
+        lex_digits(scanner);
+        if scanner.peek() == Some('.') && scanner.peek_second() != Some('_') {
+            scanner.bump();
+            lex_digits(scanner);
+        }
+        if scanner.peek() == Some('e') || scanner.peek() == Some('E') {
+            scanner.bump();
+            if !scanner.eat('-') {
+                scanner.eat('+');
+            }
+            if lex_digits(scanner).is_empty() {
+                return Err(malformed_number(scanner, start, "expected digits in the exponent of"));
+            }
+        }
+        let text = scanner.source[start..scanner.offset].replace("_", "");
+        // If this fails either the scanner or the parser is wrong.
+        let value: Number = text.parse().unwrap();
+        if value.is_finite() { Some(value) } else { None }
+    };
+    // a number running into letters or another fraction is a typo rather than two tokens, except
+    // for a count of bars such as `8bar`, which places a section of an arrangement
+    let runs_on = scanner.peek().map_or(false, is_ident_continue) && !scanner.rest().starts_with("~=");
+    if (runs_on && !at_bar_unit(scanner)) ||
+       (scanner.peek() == Some('.') && scanner.peek_second().map_or(false, |x| x.is_digit(10))) {
+        return Err(malformed_number(scanner, start, "invalid number"));
+    }
+    match value {
+        Some(x) => Ok(x),
+        None => Err(format!("`{}` is too large to be represented",
+                            &scanner.source[start..scanner.offset])),
+    }
+}
+
+// `bar` directly after a number, as a whole word.
+fn at_bar_unit(scanner: &Scanner) -> bool {
+    let rest = scanner.rest();
+    rest.starts_with("bar") && !rest[3..].chars().next().map_or(false, is_ident_continue)
+}
+
+// A digit followed by any digits and underscores, or nothing if the next character is not a digit.
+fn lex_digits<'a>(scanner: &mut Scanner<'a>) -> &'a str {
+    if scanner.peek().map_or(false, |c| c.is_digit(10)) {
+        scanner.eat_while(|c| c.is_digit(10) || c == '_')
+    } else {
+        ""
+    }
+}
+
+// Skips the rest of a malformed number, so it is reported once rather than as several tokens.
+fn malformed_number(scanner: &mut Scanner, start: usize, msg: &str) -> String {
+    scanner.eat_while(|c| is_ident_continue(c) || c == '.');
+    format!("{} `{}`", msg, &scanner.source[start..scanner.offset])
+}
+
+/// Identifiers follow the Unicode XID rules, with `_`, `~` and `'` also allowed anywhere.
+pub fn is_ident_start(c: char) -> bool {
+    UnicodeXID::is_xid_start(c) || c == '_' || c == '~' || c == '\''
+}
+
+pub fn is_ident_continue(c: char) -> bool {
+    UnicodeXID::is_xid_continue(c) || c == '~' || c == '\''
+}
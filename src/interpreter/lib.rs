@@ -1,7 +1,7 @@
-#![feature(plugin, optin_builtin_traits, vec_push_all)]
-#![plugin(regex_macros, docopt_macros)]
+#![feature(plugin, optin_builtin_traits, integer_atomics)]
+#![plugin(docopt_macros)]
 
-extern crate regex;
+extern crate rustc_serialize;
 extern crate llvm;
 extern crate cbox;
 extern crate bit_set;
@@ -9,6 +9,8 @@ extern crate sound_stream;
 extern crate hound;
 extern crate vec_map;
 extern crate llvm_sys;
+extern crate unicode_xid;
+extern crate libc;
 
 pub mod common;
 pub mod ident;
@@ -16,14 +18,46 @@ pub mod ident;
 pub mod tokens;
 pub mod ast;
 pub mod issue;
+pub mod codes;
 pub mod lexer;
 pub mod parser;
 pub mod functions;
 pub mod typecheck;
+pub mod flow;
 pub mod codegen;
 pub mod scope;
 pub mod compiler;
+pub mod pipeline;
 pub mod audio;
+pub mod runtime;
+pub mod automation;
+pub mod tempo;
+pub mod arrange;
+pub mod transition;
+pub mod record;
+pub mod int;
+pub mod rng;
+pub mod math;
+pub mod fastmath;
+pub mod purity;
+pub mod memo;
+pub mod hoist;
+pub mod dsp;
+pub mod alloc;
+pub mod bench;
+pub mod batch;
+pub mod sweep;
+pub mod serialize;
+pub mod doc;
+pub mod completions;
+pub mod config;
+pub mod testing;
+pub mod bus;
+pub mod buffer;
+pub mod poly;
+pub mod debugger;
+pub mod source_map;
+pub mod graph;
 
 #[macro_use]
 pub mod tests;
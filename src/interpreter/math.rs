@@ -0,0 +1,187 @@
+use super::tokens::Number;
+
+use std::f64::{self, consts};
+use std::mem;
+
+// Used instead of the platform's math library when deterministic, with nothing but arithmetic
+// IEEE 754 defines exactly, so they give the same bits everywhere.
+
+// ln 2 and pi/2 split into parts whose products with small integers are exact.
+const LN2_HI: Number = 6.93147180369123816490e-01;
+const LN2_LO: Number = 1.90821492927058770002e-10;
+const PIO2_1: Number = 1.57079632673412561417e+00;
+const PIO2_2: Number = 6.07710050630396597660e-11;
+const PIO2_3: Number = 2.02226624879595063154e-21;
+
+fn to_bits(x: Number) -> u64 {
+    unsafe { mem::transmute(x) }
+}
+
+fn from_bits(x: u64) -> Number {
+    unsafe { mem::transmute(x) }
+}
+
+// 2^n for an exponent in the normal range.
+fn pow2(n: i64) -> Number {
+    from_bits(((n + 1023) as u64) << 52)
+}
+
+// x * 2^n, in two steps so that neither factor leaves the normal range.
+fn scale(x: Number, n: i64) -> Number {
+    x * pow2(n / 2) * pow2(n - n / 2)
+}
+
+pub extern fn exp(x: Number) -> Number {
+    if x.is_nan() {
+        return x;
+    }
+    if x > 709.8 {
+        return f64::INFINITY;
+    }
+    if x < -745.2 {
+        return 0.0;
+    }
+    let k = (x / consts::LN_2).round();
+    let r = (x - k * LN2_HI) - k * LN2_LO;
+    // |r| <= ln(2)/2, where 13 terms of the series are enough
+    let p = (1..14).rev().fold(1.0, |p, i| 1.0 + p * r / i as Number);
+    scale(p, k as i64)
+}
+
+pub extern fn exp2(x: Number) -> Number {
+    if !x.is_finite() {
+        return exp(x);
+    }
+    let k = x.round().max(-1100.0).min(1100.0);
+    scale(exp((x - k) * consts::LN_2), k as i64)
+}
+
+// Splits a positive finite number into a power of two and the natural log of what is left,
+// which is between sqrt(1/2) and sqrt(2).
+fn split_log(x: Number) -> (Number, Number) {
+    let (x, e0) = if x < f64::MIN_POSITIVE { (x * pow2(54), -54) } else { (x, 0) };
+    let bits = to_bits(x);
+    let mut e = ((bits >> 52) & 0x7ff) as i64 - 1023 + e0;
+    let mut m = from_bits((bits & ((1 << 52) - 1)) | (1023 << 52));
+    if m > consts::SQRT_2 {
+        m /= 2.0;
+        e += 1;
+    }
+    // ln(m) = 2 atanh(s), and |s| < 0.172 needs terms up to s^21
+    let s = (m - 1.0) / (m + 1.0);
+    let s2 = s * s;
+    let series = (0..11).rev().fold(0.0, |p, i| p * s2 + 1.0 / (2 * i + 1) as Number);
+    (e as Number, 2.0 * s * series)
+}
+
+// The logarithms of numbers which have no real logarithm, or an infinite one.
+fn log_special(x: Number) -> Option<Number> {
+    if x.is_nan() || x < 0.0 {
+        Some(f64::NAN)
+    } else if x == 0.0 {
+        Some(f64::NEG_INFINITY)
+    } else if x == f64::INFINITY {
+        Some(x)
+    } else {
+        None
+    }
+}
+
+pub extern fn log(x: Number) -> Number {
+    if let Some(y) = log_special(x) {
+        return y;
+    }
+    let (e, ln_m) = split_log(x);
+    e * LN2_HI + (ln_m + e * LN2_LO)
+}
+
+pub extern fn log2(x: Number) -> Number {
+    if let Some(y) = log_special(x) {
+        return y;
+    }
+    let (e, ln_m) = split_log(x);
+    e + ln_m / consts::LN_2
+}
+
+pub extern fn log10(x: Number) -> Number {
+    log(x) / consts::LN_10
+}
+
+pub extern fn pow(x: Number, y: Number) -> Number {
+    if y == 0.0 || x == 1.0 {
+        return 1.0;
+    }
+    if x.is_nan() || y.is_nan() {
+        return f64::NAN;
+    }
+    let integer = y == y.trunc();
+    if integer && y.abs() <= 64.0 {
+        // by squaring, which is exact for small results like x * x
+        let mut n = y.abs() as u32;
+        let (mut base, mut result) = (x, 1.0);
+        while n > 0 {
+            if n & 1 == 1 {
+                result *= base;
+            }
+            base *= base;
+            n >>= 1;
+        }
+        return if y < 0.0 { 1.0 / result } else { result };
+    }
+    if x < 0.0 {
+        if !integer {
+            return f64::NAN;
+        }
+        let odd = (y / 2.0).trunc() * 2.0 != y;
+        let magnitude = pow(-x, y);
+        return if odd { -magnitude } else { magnitude };
+    }
+    if x == 0.0 {
+        return if y > 0.0 { 0.0 } else { f64::INFINITY };
+    }
+    exp(y * log(x))
+}
+
+// Reduces an angle to within pi/4 of a multiple of pi/2, giving which multiple mod 4.
+fn reduce(x: Number) -> (Number, i64) {
+    let k = (x * consts::FRAC_2_PI).round();
+    let r = ((x - k * PIO2_1) - k * PIO2_2) - k * PIO2_3;
+    (r, (k as i64) & 3)
+}
+
+// sin and cos for |r| <= pi/4, where terms up to r^19 are enough.
+fn sin_kernel(r: Number) -> Number {
+    let r2 = r * r;
+    r * (1..10).rev().fold(1.0, |p, n| 1.0 - p * r2 / ((2 * n) * (2 * n + 1)) as Number)
+}
+
+fn cos_kernel(r: Number) -> Number {
+    let r2 = r * r;
+    (1..10).rev().fold(1.0, |p, n| 1.0 - p * r2 / ((2 * n - 1) * (2 * n)) as Number)
+}
+
+pub extern fn sin(x: Number) -> Number {
+    if !x.is_finite() {
+        return f64::NAN;
+    }
+    let (r, quadrant) = reduce(x);
+    match quadrant {
+        0 => sin_kernel(r),
+        1 => cos_kernel(r),
+        2 => -sin_kernel(r),
+        _ => -cos_kernel(r),
+    }
+}
+
+pub extern fn cos(x: Number) -> Number {
+    if !x.is_finite() {
+        return f64::NAN;
+    }
+    let (r, quadrant) = reduce(x);
+    match quadrant {
+        0 => cos_kernel(r),
+        1 => -sin_kernel(r),
+        2 => -cos_kernel(r),
+        _ => sin_kernel(r),
+    }
+}
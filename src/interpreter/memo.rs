@@ -0,0 +1,64 @@
+use super::common::Context;
+use super::ast::{Item, Expression, Statement, Block};
+use super::ident::Identifier;
+use super::purity::{Purity, for_each_child, walk_block};
+use super::tokens::NodeImpl;
+
+use std::collections::{HashMap, HashSet};
+
+/// The functions of the type checked AST held by the context which should be memoized: pure ones
+/// taking and returning numbers, which call a function and are called from several places.
+pub fn memoized_functions<'a>(ctxt: &'a Context<'a>) -> HashSet<Identifier> {
+    let ast = ctxt.ast.borrow();
+    let mut purity = Purity::new(ctxt, &ast);
+    let mut calls = HashMap::new();
+    for item in ast.iter() {
+        match *item {
+            Item::FunctionDef(ref def) => count_block(&purity, &mut calls, &def.block, Some(def.ident())),
+            Item::Assignment(ref assign) => count_expr(&purity, &mut calls, assign.expr(), None),
+        }
+    }
+    let trace = ctxt.options.borrow().trace.clone();
+    let ids: Vec<Identifier> = purity.defs.keys().cloned().collect();
+    ids.into_iter().filter(|&id| {
+        calls.get(&id).map_or(false, |&n| n > 1) &&
+            purity.has_numeric_signature(id) &&
+            makes_calls(&purity.defs[&id].block) &&
+            !trace.contains(&ctxt.lookup_name(id)) &&
+            purity.is_pure(id)
+    }).collect()
+}
+
+// Counts the call sites of each function.
+fn count_block(purity: &Purity, calls: &mut HashMap<Identifier, usize>, block: &Block, caller: Option<Identifier>) {
+    for statement in block {
+        match *statement {
+            Statement::Assignment(ref assign) => count_expr(purity, calls, assign.expr(), caller),
+            Statement::Expression(ref expr) => count_expr(purity, calls, expr, caller),
+        }
+    }
+}
+
+fn count_expr(purity: &Purity, calls: &mut HashMap<Identifier, usize>, expr: &Expression, caller: Option<Identifier>) {
+    for_each_child(expr, |child| count_expr(purity, calls, child, caller));
+    match *expr {
+        Expression::Block(ref x) => count_block(purity, calls, x.item(), caller),
+        Expression::Closure(ref x) => count_block(purity, calls, &x.block, caller),
+        Expression::FunctionCall(ref call) => {
+            if let Some(id) = purity.callee(call.callee(), caller) {
+                *calls.entry(id).or_insert(0) += 1;
+            }
+        }
+        _ => { },
+    }
+}
+
+fn makes_calls(block: &Block) -> bool {
+    let mut calls = false;
+    walk_block(block, &mut |expr| {
+        if let Expression::FunctionCall(_) = *expr {
+            calls = true;
+        }
+    });
+    calls
+}
@@ -1,12 +1,26 @@
 use super::issue::Level;
-use super::tokens::{SourcePos, Token, Symbol, Bracket, Associativity, Node, NodeImpl};
+use super::codes::Code;
+use super::tokens::{Number, SourcePos, Token, Symbol, Bracket, Operator, Associativity, Node, NodeImpl};
 use super::ident::Identifier;
 use super::common::Context;
+use super::lexer::raw_string_literal;
 use super::ast::*;
 use super::functions::{self, FunctionTable};
+use super::testing::TestCase;
+use super::bus::Bus;
+use super::buffer::{self, Buffer};
+use super::tempo::{self, TempoMap};
+use super::arrange::{self, Section};
+use super::transition;
+use super::audio;
+use super::dsp::oversample;
+use super::types::{Type, FunctionType};
+
+use vec_map::VecMap;
 
 use std::borrow::Cow;
 use std::cell::{Ref, RefMut};
+use std::rc::Rc;
 
 macro_rules! try_opt(
     ( $val:expr ) => {
@@ -49,6 +63,9 @@ struct Parser<'a> {
     tokens: Ref<'a, Vec<Node<Token>>>,
     functions: RefMut<'a, FunctionTable>,
     sub_stack: Vec<(usize, usize, usize)>,
+    test_names: Vec<String>,
+    in_voice: bool, // whether a voice block is being parsed
+    in_fft: bool, // whether the body of an `@fft` section is being parsed
 }
 
 impl<'a> Parser<'a> {
@@ -59,6 +76,9 @@ impl<'a> Parser<'a> {
             tokens: ctxt.tokens.borrow(),
             functions: ctxt.functions.borrow_mut(),
             sub_stack: vec![(0, 0, len)],
+            test_names: Vec::new(),
+            in_voice: false,
+            in_fft: false,
         }
     }
 
@@ -200,7 +220,7 @@ impl<'a> Parser<'a> {
                     depth -= 1;
                 }
                 None => {
-                    self.emit_error_here(format!("expected `{}`", close));
+                    self.emit_error_here(Code::ExpectedSymbol, format!("expected `{}`", close));
                     return false;
                 }
                 _ => { }
@@ -209,21 +229,109 @@ impl<'a> Parser<'a> {
         return true;
     }
 
-    fn emit_error_here<S>(&self, msg: S) where S: Into<Cow<'static, str>> {
+    fn emit_error_here<S>(&self, code: Code, msg: S) where S: Into<Cow<'static, str>> {
         self.ctxt.issues.borrow_mut().new_issue(self.ctxt, self.peek_source_pos_or_end(-1),
-                                                Level::Error, msg);
+                                                Level::Error, code, msg);
     }
 
     pub fn parse(&mut self) {
         let mut items = self.ctxt.ast.borrow_mut();
         while !self.is_empty() {
+            if self.at_test() {
+                match self.parse_test() {
+                    Some(def) => if self.ctxt.options.borrow().test {
+                        items.push(Item::FunctionDef(def));
+                    },
+                    None => return,
+                }
+                continue;
+            }
+            if self.at_buffer() {
+                match self.parse_buffer() {
+                    Some(()) => continue,
+                    None => return,
+                }
+            }
+            if self.at_tempo() {
+                match self.parse_tempo() {
+                    Some(()) => continue,
+                    None => return,
+                }
+            }
+            if self.at_arrange() {
+                match self.parse_arrange() {
+                    Some(mut def) => {
+                        def.0.docs = self.docs_before(def.pos());
+                        items.push(Item::FunctionDef(def));
+                    }
+                    None => return,
+                }
+                continue;
+            }
+            if self.at_bus() {
+                match self.parse_bus() {
+                    Some(mut def) => {
+                        def.0.docs = self.docs_before(def.pos());
+                        items.push(Item::FunctionDef(def));
+                    }
+                    None => return,
+                }
+                continue;
+            }
+            if !self.at_noinline() && self.at_script() {
+                self.parse_script();
+                return;
+            }
+            // `@noinline` before a function keeps it from being inlined into its callers
+            let start = self.peek_source_pos_or_end(0);
+            let noinline = self.at_noinline();
+            if noinline {
+                self.seek(2);
+            }
             match self.parse_item() {
-                Some(i) => items.push(i),
+                Some(mut item) => {
+                    let docs = self.docs_before(start);
+                    match item {
+                        Item::Assignment(ref mut x) => {
+                            x.0.docs = docs;
+                            if noinline {
+                                self.ctxt.emit_error(Code::ExpectedItem,
+                                                     "expected a function definition after `@noinline`", start);
+                            }
+                        }
+                        Item::FunctionDef(ref mut x) => {
+                            x.0.docs = docs;
+                            if noinline {
+                                self.ctxt.noinline.borrow_mut().insert(self.ctxt.lookup_name(x.ident()));
+                            }
+                        }
+                    }
+                    items.push(item);
+                },
                 None => return,
             }
         }
     }
 
+    /// Joins the doc comments on the lines directly above the given position.
+    fn docs_before(&self, pos: SourcePos) -> Option<String> {
+        let docs = self.ctxt.docs.borrow();
+        let mut line = pos.line;
+        let mut lines = Vec::new();
+        for doc in docs.iter().rev().skip_while(|x| x.pos().index >= pos.index) {
+            if doc.pos().line != line - 1 {
+                break;
+            }
+            line -= 1;
+            lines.push(&doc.item()[..]);
+        }
+        if lines.is_empty() {
+            return None;
+        }
+        lines.reverse();
+        Some(lines.join("\n"))
+    }
+
     fn parse_expression(&mut self) -> Option<Expression> {
         let expr = try_opt!(self.pratt_expression(0));
         self.seek(1);
@@ -252,11 +360,35 @@ impl<'a> Parser<'a> {
 
             Some(Token::Boolean(v)) => Some(Expression::Boolean(Node(v, token.pos().unwrap()))),
 
+            Some(Token::Str(v)) => Some(Expression::Str(Node(v, token.pos().unwrap()))),
+
+            // `voice` is only a keyword when followed by a block
+            Some(Token::Ident(id)) if self.ctxt.lookup_name(id) == "voice" &&
+                                      self.peek_token(0) == Some(Token::Symbol(Symbol::LeftBracket(Bracket::Curly))) => {
+                if self.in_voice {
+                    self.ctxt.emit_error(Code::NestedVoice, "voice blocks cannot be nested", token.pos().unwrap());
+                    return None;
+                }
+                self.in_voice = true;
+                let block = self.parse_block();
+                self.in_voice = false;
+                Some(Expression::Voice(Rc::new(Node(VoiceBlock {
+                    expr: Expression::Block(Rc::new(try_opt!(block))),
+                }, token.pos().unwrap()))))
+            }
+
+            // `xfade(...)` and `switch(...)` are transitions between functions, see transition.rs
+            Some(Token::Ident(id)) if transition::is_transition(&self.ctxt.lookup_name(id)) &&
+                                      self.peek_token(0) == Some(Token::Symbol(Symbol::LeftBracket(Bracket::Round))) => {
+                let call = try_opt!(self.parse_function_call(Expression::Variable(Node(id, token.pos().unwrap()))));
+                transition::expand(self.ctxt, &self.ctxt.lookup_name(id), call)
+            }
+
             Some(Token::Ident(id)) => Some(Expression::Variable(Node(id, token.pos().unwrap()))),
 
             // unary operator
             Some(Token::Operator(op)) if op.can_take_x_args(1) => {
-                Some(Expression::Prefix(Box::new(Node(Prefix {
+                Some(Expression::Prefix(Rc::new(Node(Prefix {
                     op: Node(op, token.pos().unwrap()),
                     expr: try_opt!(self.pratt_expression(100))
                 }, token.pos().unwrap()))))
@@ -266,31 +398,138 @@ impl<'a> Parser<'a> {
             Some(Token::Symbol(Symbol::LeftBracket(Bracket::Round))) => {
                 let expr = try_opt!(self.pratt_expression(1));
                 if expect!(self, Token::Symbol(Symbol::RightBracket(Bracket::Round))).is_none() {
-                    self.emit_error_here("expected `)`");
+                    self.emit_error_here(Code::ExpectedSymbol, "expected `)`");
                     return None;
                 }
                 Some(expr)
             }
 
+            // record, which starts like a block but with `name:`
+            Some(Token::Symbol(Symbol::LeftBracket(Bracket::Curly))) if
+                    match (self.peek_token(0), self.peek_token(1)) {
+                        (Some(Token::Ident(_)), Some(Token::Symbol(Symbol::Colon))) => true,
+                        _ => false,
+                    } => {
+                let record = try_opt!(self.parse_record(token.pos().unwrap()));
+                Some(Expression::Record(Rc::new(record)))
+            }
+
             // start of block
             Some(Token::Symbol(Symbol::LeftBracket(Bracket::Curly))) => {
                 self.seek(-1);
                 let block = try_opt!(self.parse_block());
-                Some(Expression::Block(block))
+                Some(Expression::Block(Rc::new(block)))
+            }
+
+            // array
+            Some(Token::Symbol(Symbol::LeftBracket(Bracket::Square))) => {
+                let end = self.find_smart(Token::Symbol(Symbol::RightBracket(Bracket::Square)));
+                if end.is_none() {
+                    self.ctxt.emit_error(Code::ExpectedSymbol, "expected `]`", self.end_source_pos());
+                    return None;
+                }
+                let idx = self.index();
+                self.enter_subsection(idx, end.unwrap());
+                let elements = try_opt!(self.parse_arg_list(Some(CallType::Ordered)));
+                self.integrate_subsection();
+                self.seek(1); //consume closing bracket
+                let elements = elements.iter().map(|x| x.expr().unwrap().clone()).collect();
+                Some(Expression::Array(Rc::new(Node(elements, token.pos().unwrap()))))
             }
 
             // closure
             Some(Token::Symbol(Symbol::Backslash)) => {
                 self.seek(-1);
                 let def = try_opt!(self.parse_closure());
-                Some(Expression::Closure(Box::new(def)))
+                Some(Expression::Closure(Rc::new(def)))
+            }
+
+            // `@block expr`, `@smooth expr`, `@oversample(n) expr` or `@fft(x) expr`, which extends
+            // as far as it can
+            Some(Token::Symbol(Symbol::At)) => {
+                let smooth = match self.next_token() {
+                    Some(Token::Ident(id)) if self.ctxt.lookup_name(id) == "block" => false,
+                    Some(Token::Ident(id)) if self.ctxt.lookup_name(id) == "smooth" => true,
+                    Some(Token::Ident(id)) if self.ctxt.lookup_name(id) == "oversample" => {
+                        let factor = try_opt!(self.parse_oversample_factor());
+                        return Some(Expression::Oversample(Rc::new(Node(Oversample {
+                            factor: factor,
+                            expr: try_opt!(self.pratt_expression(1)),
+                        }, token.pos().unwrap()))));
+                    }
+                    Some(Token::Ident(id)) if self.ctxt.lookup_name(id) == "fft" => {
+                        return self.parse_fft_section(token.pos().unwrap());
+                    }
+                    _ => {
+                        self.seek(-1);
+                        self.emit_error_here(Code::ExpectedIdentifier,
+                                             "expected `block`, `smooth`, `oversample` or `fft` after `@`");
+                        return None;
+                    }
+                };
+                Some(Expression::BlockRate(Rc::new(Node(BlockRate {
+                    smooth: smooth,
+                    expr: try_opt!(self.pratt_expression(1)),
+                }, token.pos().unwrap()))))
             }
 
             _ => {
-                self.emit_error_here("expected constant, variable, opening bracket or unary operator");
+                self.emit_error_here(Code::ExpectedExpression, "expected constant, variable, opening bracket or unary operator");
+                return None;
+            }
+        }
+    }
+
+    // The rest of `@fft(x) expr`, after `fft`.
+    fn parse_fft_section(&mut self, pos: SourcePos) -> Option<Expression> {
+        if expect!(self, Token::Symbol(Symbol::LeftBracket(Bracket::Round))).is_none() {
+            self.emit_error_here(Code::ExpectedSymbol, "expected `(` after `@fft`");
+            return None;
+        }
+        let input = try_opt!(self.pratt_expression(1));
+        if expect!(self, Token::Symbol(Symbol::RightBracket(Bracket::Round))).is_none() {
+            self.emit_error_here(Code::ExpectedSymbol, "expected `)`");
+            return None;
+        }
+        if self.in_fft {
+            self.ctxt.emit_error(Code::NestedFft, "@fft sections cannot be nested", pos);
+            return None;
+        }
+        self.in_fft = true;
+        let expr = self.pratt_expression(1);
+        self.in_fft = false;
+        Some(Expression::Fft(Rc::new(Node(FftSection {
+            input: input,
+            expr: try_opt!(expr),
+        }, pos))))
+    }
+
+    // The factor of `@oversample(n)`, which must be written as a whole number from 2 to 16.
+    fn parse_oversample_factor(&mut self) -> Option<usize> {
+        if expect!(self, Token::Symbol(Symbol::LeftBracket(Bracket::Round))).is_none() {
+            self.emit_error_here(Code::ExpectedSymbol, "expected `(` after `@oversample`");
+            return None;
+        }
+        let factor = match expect_value!(self, Token::Const) {
+            Some(x) => x,
+            None => {
+                self.emit_error_here(Code::ExpectedExpression, "expected the oversampling factor");
                 return None;
             }
+        };
+        if expect!(self, Token::Symbol(Symbol::RightBracket(Bracket::Round))).is_none() {
+            self.emit_error_here(Code::ExpectedSymbol, "expected `)`");
+            return None;
+        }
+        let value = *factor.item();
+        if value.fract() != 0.0 || value < 2.0 || value > oversample::MAX_FACTOR as Number {
+            self.ctxt.emit_error(Code::InvalidOversampling,
+                                 format!("cannot oversample by {}; expected a whole number from 2 to {}",
+                                         value, oversample::MAX_FACTOR),
+                                 factor.pos());
+            return None;
         }
+        Some(value as usize)
     }
 
     fn pratt_led(&mut self, left: Expression, right: Option<Node<Token>>) -> Option<Expression> {
@@ -299,22 +538,28 @@ impl<'a> Parser<'a> {
             // binary operator
             Some(Token::Operator(op)) => {
                 if !op.can_take_x_args(2) {
-                    self.emit_error_here("expected binary operator");
+                    self.emit_error_here(Code::ExpectedOperator, "expected binary operator");
                     return None;
                 }
                 let precedence = op.precedence() -
                     if op.associativity() == Associativity::Right { 1 } else { 0 };
                 let pos = left.pos();
-                Some(Expression::Infix(Box::new(Node(Infix {
+                let rhs = try_opt!(self.pratt_expression(precedence));
+                if op == Operator::ApproxEqual && self.at_within() {
+                    self.seek(1);
+                    let tolerance = try_opt!(self.pratt_expression(precedence));
+                    return Some(self.within_tolerance(left, rhs, tolerance, right.pos().unwrap()));
+                }
+                Some(Expression::Infix(Rc::new(Node(Infix {
                     op: Node(op, right.pos().unwrap()),
                     left: left,
-                    right: try_opt!(self.pratt_expression(precedence)),
+                    right: rhs,
                 }, pos))))
             }
 
             // extra closing paren
             Some(Token::Symbol(Symbol::RightBracket(Bracket::Round))) => {
-                self.emit_error_here("unexpected `)`");
+                self.emit_error_here(Code::ExpectedOperator, "unexpected `)`");
                 return None;
             }
 
@@ -323,7 +568,17 @@ impl<'a> Parser<'a> {
             Some(Token::Symbol(Symbol::LeftBracket(Bracket::Square))) => {
                 self.seek(-1);
                 let call = try_opt!(self.parse_function_call(left));
-                Some(Expression::FunctionCall(Box::new(call)))
+                Some(Expression::FunctionCall(Rc::new(call)))
+            }
+
+            // field of a record
+            Some(Token::Symbol(Symbol::Period)) => {
+                let pos = left.pos();
+                let field = try_opt!(self.parse_ident());
+                Some(Expression::Field(Rc::new(Node(Field {
+                    expr: left,
+                    field: field,
+                }, pos))))
             }
 
             // if
@@ -333,14 +588,14 @@ impl<'a> Parser<'a> {
                 let cond = try_opt!(self.pratt_expression(1));
                 if let Some(Token::Symbol(Symbol::Else)) = self.next_token() {
                     let els = try_opt!(self.pratt_expression(1));
-                    Some(Expression::Conditional(Box::new(Node(Conditional {
+                    Some(Expression::Conditional(Rc::new(Node(Conditional {
                         cond: cond,
                         then: then,
                         els: els,
                     }, pos))))
                 } else {
                     self.seek(-1);
-                    self.emit_error_here("expected `else`");
+                    self.emit_error_here(Code::ExpectedSymbol, "expected `else`");
                     None
                 }
             }
@@ -351,10 +606,43 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // Whether the next token is the `within` of `a ~= b within tolerance`. It's only a keyword
+    // there, so it can still name a variable.
+    fn at_within(&self) -> bool {
+        match self.peek_token(0) {
+            Some(Token::Ident(id)) => self.ctxt.lookup_name(id) == "within",
+            _ => false,
+        }
+    }
+
+    // `a ~= b within tolerance` is `abs(a - b) <= tolerance`, so that `a` and `b` are evaluated once.
+    fn within_tolerance(&self, left: Expression, right: Expression, tolerance: Expression,
+                        op_pos: SourcePos) -> Expression {
+        let pos = left.pos();
+        let abs = self.ctxt.names.borrow_mut().new_id("abs");
+        let diff = Expression::Infix(Rc::new(Node(Infix {
+            op: Node(Operator::Sub, op_pos),
+            left: left,
+            right: right,
+        }, pos)));
+        let call = Expression::FunctionCall(Rc::new(Node(FunctionCall {
+            callee: Expression::Variable(Node(abs, op_pos)),
+            args: Node(vec![Argument::Expr(diff)], pos),
+            ty: CallType::Ordered,
+        }, pos)));
+        Expression::Infix(Rc::new(Node(Infix {
+            op: Node(Operator::LessEqual, op_pos),
+            left: call,
+            right: tolerance,
+        }, pos)))
+    }
+
     fn pratt_lbp(&mut self, token: Option<Node<Token>>) -> Option<i32> {
         //println!("lbp {:?}", token);
         match token.item() {
             Some(Token::Operator(op)) => Some(op.precedence()),
+            // ends the right side of `~=`, see at_within()
+            Some(Token::Ident(id)) if self.ctxt.lookup_name(id) == "within" => Some(0),
 
             Some(Token::Symbol(Symbol::If)) => Some(2),
             Some(Token::Symbol(Symbol::Else)) => Some(0),
@@ -362,18 +650,56 @@ impl<'a> Parser<'a> {
             // end of group
             Some(Token::Symbol(Symbol::RightBracket(Bracket::Round))) => Some(1),
 
-            // function call
+            // function call, or field of a record
             Some(Token::Symbol(Symbol::LeftBracket(Bracket::Round))) |
-            Some(Token::Symbol(Symbol::LeftBracket(Bracket::Square))) => Some(1000),
+            Some(Token::Symbol(Symbol::LeftBracket(Bracket::Square))) |
+            Some(Token::Symbol(Symbol::Period)) => Some(1000),
 
             None => Some(0),
             _ => {
-                self.emit_error_here("expected binary operator or function call");
+                self.emit_error_here(Code::ExpectedOperator, "expected binary operator or function call");
                 return None;
             }
         }
     }
 
+    // The fields of a record literal, as `name: value` separated by commas, from after its `{`.
+    fn parse_record(&mut self, pos: SourcePos) -> Option<Node<Record>> {
+        let end = self.find_smart(Token::Symbol(Symbol::RightBracket(Bracket::Curly)));
+        if end.is_none() {
+            self.ctxt.emit_error(Code::ExpectedSymbol, "expected `}`", self.end_source_pos());
+            return None;
+        }
+        let idx = self.index();
+        self.enter_subsection(idx, end.unwrap());
+        let mut fields: Vec<(Node<Identifier>, Expression)> = Vec::new();
+        loop {
+            let idx = self.index();
+            let comma = self.find_smart(Token::Symbol(Symbol::Comma));
+            let token_idx = comma.unwrap_or(self.end_index());
+            self.enter_subsection(idx, token_idx);
+            let field = try_opt!(self.parse_ident());
+            try_opt!(self.parse_symbol(Symbol::Colon));
+            let value = try_opt!(self.parse_expression());
+            if fields.iter().any(|x| x.0.item() == field.item()) {
+                self.ctxt.emit_error(Code::InvalidRecord,
+                                     format!("the field `{}` is given twice", self.ctxt.lookup_name(*field)),
+                                     field.pos());
+            }
+            fields.push((field, value));
+            self.integrate_subsection();
+            if comma.is_none() {
+                break;
+            }
+        }
+        self.seek(-1); // no comma after the last field
+        self.integrate_subsection();
+        self.seek(1); // consume the closing brace
+        Some(Node(Record {
+            fields: fields,
+        }, pos))
+    }
+
     fn parse_assignment(&mut self) -> Option<Node<Assignment>> {
         let pos = self.peek_source_pos_or_end(0);
         let ident = try_opt!(self.parse_ident());
@@ -382,7 +708,8 @@ impl<'a> Parser<'a> {
 
         Some(Node(Assignment {
             ident: ident,
-            expr: expr
+            expr: expr,
+            docs: None,
         }, pos))
     }
 
@@ -390,7 +717,7 @@ impl<'a> Parser<'a> {
         match self.next_token() {
             Some(Token::Symbol(x)) if x == symbol => Some(()),
             _ => {
-                self.emit_error_here(format!("expected {}", symbol));
+                self.emit_error_here(Code::ExpectedSymbol, format!("expected {}", symbol));
                 None
             },
         }
@@ -399,7 +726,7 @@ impl<'a> Parser<'a> {
     fn parse_ident(&mut self) -> Option<Node<Identifier>> {
         match expect_value!(self, Token::Ident) {
             None => {
-                self.emit_error_here("expected identifier");
+                self.emit_error_here(Code::ExpectedIdentifier, "expected identifier");
                 None
             }
             x => x,
@@ -420,6 +747,7 @@ impl<'a> Parser<'a> {
         Some(Node(FunctionDef {
             ident: ident,
             func: func,
+            docs: None,
         }, pos))
     }
 
@@ -437,6 +765,7 @@ impl<'a> Parser<'a> {
         Some(Node(FunctionDef {
             ident: Node(ident, pos),
             func: func,
+            docs: None,
         }, pos))
     }
 
@@ -484,29 +813,62 @@ impl<'a> Parser<'a> {
         let block_idx = match self.find_smart(Token::Symbol(Symbol::LeftBracket(Bracket::Curly))) {
             Some(x) => x,
             None => {
-                self.ctxt.emit_error("expected `{` to start function block", self.end_source_pos());
+                self.ctxt.emit_error(Code::ExpectedSymbol, "expected `{` to start function block", self.end_source_pos());
                 return None;
             }
         };
-        // parse arg list (everything until start of block)
+        // an optional return type annotation goes between the args and the block
         self.enter_subsection(idx, block_idx);
-        let args = try_opt!(self.parse_arg_list(None));
+        let args_end = self.find_smart(Token::Symbol(Symbol::Arrow)).unwrap_or(block_idx);
+        self.leave_subsection();
+
+        // parse arg list (everything until the arrow or start of block)
+        self.enter_subsection(idx, args_end);
+        let (args, arg_types) = try_opt!(self.parse_param_list());
         self.integrate_subsection();
 
+        let returns = if args_end < block_idx {
+            self.enter_subsection(args_end + 1, block_idx);
+            let returns = try_opt!(self.parse_type_name());
+            if self.len() > 0 {
+                self.ctxt.emit_error(Code::ExpectedSymbol, "expected `{` after return type",
+                                     self.peek_source_pos_or_end(0));
+                return None;
+            }
+            self.integrate_subsection();
+            Some(returns)
+        } else {
+            None
+        };
+
         let block = try_opt!(self.parse_block());
 
         Some(Node(Function {
             args: args,
-            block: block,
+            block: Rc::new(block),
+            arg_types: arg_types,
+            returns: returns,
         }, pos))
     }
 
+    fn parse_type_name(&mut self) -> Option<Node<TypeName>> {
+        let ident = try_opt!(self.parse_ident());
+        let name = self.ctxt.lookup_name(*ident);
+        match TypeName::parse(&name) {
+            Some(ty) => Some(Node(ty, ident.pos())),
+            None => {
+                self.ctxt.emit_error(Code::UnknownType, format!("unknown type `{}`", name), ident.pos());
+                None
+            }
+        }
+    }
+
     fn parse_block(&mut self) -> Option<Node<Block>> {
         let pos = self.peek_source_pos_or_end(0);
         try_opt!(self.parse_symbol(Symbol::LeftBracket(Bracket::Curly)));
         let brace = self.find_smart(Token::Symbol(Symbol::RightBracket(Bracket::Curly)));
         if brace.is_none() {
-            self.ctxt.emit_error("expected `}`", self.end_source_pos());
+            self.ctxt.emit_error(Code::ExpectedSymbol, "expected `}`", self.end_source_pos());
             return None;
         }
         let mut stmts = Vec::new();
@@ -520,8 +882,16 @@ impl<'a> Parser<'a> {
             }
             let idx = self.index();
             self.enter_subsection(idx, semi_idx);
-            let stmt = try_opt!(self.parse_statement());
-            stmts.push(stmt);
+            let destructuring = match (self.peek_token(0), self.peek_token(1)) {
+                (Some(Token::Ident(_)), Some(Token::Symbol(Symbol::Comma))) => true,
+                _ => false,
+            };
+            if destructuring {
+                stmts.extend(try_opt!(self.parse_destructuring()));
+            } else {
+                let stmt = try_opt!(self.parse_statement());
+                stmts.push(stmt);
+            }
             self.integrate_subsection();
             if semi.is_none() {
                 self.seek(-1);
@@ -533,6 +903,48 @@ impl<'a> Parser<'a> {
         Some(Node(stmts, pos))
     }
 
+    // `lp, bp, hp = svf(x, 800, 1);`, which assigns a record to a local and then each name the
+    // field of the same name.
+    fn parse_destructuring(&mut self) -> Option<Vec<Statement>> {
+        let mut names: Vec<Node<Identifier>> = Vec::new();
+        loop {
+            let name = try_opt!(self.parse_ident());
+            if names.iter().any(|x| x.item() == name.item()) {
+                self.ctxt.emit_error(Code::InvalidRecord,
+                                     format!("`{}` is assigned twice", self.ctxt.lookup_name(*name)), name.pos());
+            }
+            names.push(name);
+            match self.next_token() {
+                Some(Token::Symbol(Symbol::Comma)) => { },
+                Some(Token::Symbol(Symbol::Equals)) => break,
+                _ => {
+                    self.emit_error_here(Code::ExpectedSymbol, "expected `,` or `=`");
+                    return None;
+                }
+            }
+        }
+        let expr = try_opt!(self.parse_expression());
+        let record = self.ctxt.names.borrow_mut().new_anon();
+        let pos = names[0].pos();
+        let mut stmts = vec![Statement::Assignment(Node(Assignment {
+            ident: Node(record, pos),
+            expr: expr,
+            docs: None,
+        }, pos))];
+        for name in names {
+            let field = Expression::Field(Rc::new(Node(Field {
+                expr: Expression::Variable(Node(record, name.pos())),
+                field: name,
+            }, name.pos())));
+            stmts.push(Statement::Assignment(Node(Assignment {
+                ident: name,
+                expr: field,
+                docs: None,
+            }, name.pos())));
+        }
+        Some(stmts)
+    }
+
     fn parse_statement(&mut self) -> Option<Statement> {
         match (self.next_token(), self.next_token()) {
             (Some(Token::Ident(_)), Some(Token::Symbol(Symbol::Equals))) => {
@@ -546,6 +958,405 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // `test` is only a keyword when followed by the name of a test
+    fn at_test(&self) -> bool {
+        match (self.peek_token(0), self.peek_token(1)) {
+            (Some(Token::Ident(id)), Some(Token::Str(_))) => self.ctxt.lookup_name(id) == "test",
+            _ => false,
+        }
+    }
+
+    // A test block is a function without arguments, named by the string literal as written so
+    // that it cannot clash with any identifier. It is only registered when tests are compiled.
+    fn parse_test(&mut self) -> Option<Node<FunctionDef>> {
+        let pos = self.peek_source_pos_or_end(0);
+        self.seek(1);
+        let name = try_opt!(expect_value!(self, Token::Str));
+        let text = self.ctxt.strings.borrow()[*name].clone();
+        let literal = raw_string_literal(self.ctxt.files.source_from(name.pos()));
+        let block = try_opt!(self.parse_block());
+        let block_pos = block.pos();
+
+        if self.test_names.contains(&text) {
+            self.ctxt.emit_error(Code::DuplicateTest, format!("test `{}` is already defined", text),
+                                 name.pos());
+            return None;
+        }
+        self.test_names.push(text.clone());
+        let ident = self.ctxt.names.borrow_mut().new_id(literal);
+        let func = Node(Function {
+            args: Node(Vec::new(), block_pos),
+            block: Rc::new(block),
+            arg_types: Vec::new(),
+            returns: Some(Node(TypeName::Number, pos)),
+        }, block_pos);
+        if self.ctxt.options.borrow().test {
+            self.ctxt.tests.borrow_mut().push(TestCase {
+                name: text,
+                ident: ident,
+                pos: pos,
+            });
+            self.functions.insert(ident, functions::Function::User(
+                    functions::UserFunction {
+                        ty: None,
+                        node: Node(func.item().clone(), pos),
+                    }));
+            self.ctxt.entrypoints.borrow_mut().insert(ident, FunctionType::new(VecMap::new(), Type::Number));
+        }
+
+        Some(Node(FunctionDef {
+            ident: Node(ident, name.pos()),
+            func: func,
+            docs: None,
+        }, pos))
+    }
+
+    // `bus` is only a keyword when followed by the name of a bus
+    fn at_noinline(&self) -> bool {
+        match (self.peek_token(0), self.peek_token(1)) {
+            (Some(Token::Symbol(Symbol::At)), Some(Token::Ident(id))) => self.ctxt.lookup_name(id) == "noinline",
+            _ => false,
+        }
+    }
+
+    fn at_bus(&self) -> bool {
+        match (self.peek_token(0), self.peek_token(1)) {
+            (Some(Token::Ident(id)), Some(Token::Str(_))) => self.ctxt.lookup_name(id) == "bus",
+            _ => false,
+        }
+    }
+
+    // A bus is a function of its input, named by `bus` and the string literal as written so that
+    // it cannot clash with any identifier or test. It is an entrypoint the runtime evaluates after
+    // the main one, see bus.rs.
+    fn parse_bus(&mut self) -> Option<Node<FunctionDef>> {
+        let pos = self.peek_source_pos_or_end(0);
+        self.seek(1);
+        let name = try_opt!(expect_value!(self, Token::Str));
+        let text = self.ctxt.strings.borrow()[*name].clone();
+        let literal = raw_string_literal(self.ctxt.files.source_from(name.pos()));
+        let keyword = self.ctxt.files.slice(pos, name.pos());
+        let source = self.ctxt.files.source_from(pos);
+        let func = try_opt!(self.parse_function());
+
+        if self.ctxt.buses.borrow().iter().any(|x| x.name == text) {
+            self.ctxt.emit_error(Code::InvalidBus, format!("bus `{}` is already defined", text), name.pos());
+            return None;
+        }
+        let input = match func.args().first() {
+            Some(&Argument::Ident(Node(id, _))) if func.args().len() == 1 => id,
+            _ => {
+                self.ctxt.emit_error(Code::InvalidBus, format!("bus `{}` must take exactly one argument, its input, \
+                                                                without a default", text),
+                                     func.args_pos());
+                return None;
+            }
+        };
+        let ident = self.ctxt.names.borrow_mut().new_id(&source[..keyword.len() + literal.len()]);
+        self.ctxt.buses.borrow_mut().push(Bus {
+            name: text,
+            ident: ident,
+            pos: pos,
+            sends: Vec::new(),
+        });
+        self.functions.insert(ident, functions::Function::User(
+                functions::UserFunction {
+                    ty: None,
+                    node: Node(func.item().clone(), pos),
+                }));
+        let mut args = VecMap::new();
+        args.insert(input, Type::Number);
+        self.ctxt.entrypoints.borrow_mut().insert(ident, FunctionType::new(args, Type::Number));
+
+        Some(Node(FunctionDef {
+            ident: Node(ident, name.pos()),
+            func: func,
+            docs: None,
+        }, pos))
+    }
+
+    // `buffer` is only a keyword when followed by the name of a buffer
+    fn at_buffer(&self) -> bool {
+        match (self.peek_token(0), self.peek_token(1)) {
+            (Some(Token::Ident(id)), Some(Token::Str(_))) => self.ctxt.lookup_name(id) == "buffer",
+            _ => false,
+        }
+    }
+
+    // A buffer is declared by its name and either its length in seconds, as in `buffer "loop" 2;`,
+    // or a file to load, as in `buffer "drums" "drums.wav";`. It only needs to be known to the
+    // runtime, so it does not become an item.
+    fn parse_buffer(&mut self) -> Option<()> {
+        let pos = self.peek_source_pos_or_end(0);
+        self.seek(1);
+        let name = try_opt!(expect_value!(self, Token::Str));
+        let text = self.ctxt.strings.borrow()[*name].clone();
+        let length = self.next();
+        if expect!(self, Token::Symbol(Symbol::Semicolon)).is_none() {
+            self.emit_error_here(Code::ExpectedSymbol, "expected `;`");
+            return None;
+        }
+
+        if self.ctxt.buffers.borrow().iter().any(|x| x.name == text) {
+            self.ctxt.emit_error(Code::InvalidBuffer, format!("buffer `{}` is already declared", text), name.pos());
+            return None;
+        }
+        let (seconds, audio) = match length.item() {
+            Some(Token::Const(seconds)) => (seconds, None),
+            Some(Token::Str(file)) => {
+                let path = self.ctxt.resolve_path(&self.ctxt.strings.borrow()[file]);
+                match audio::read_wav(&path) {
+                    Ok(audio) => (audio.samples.len() as Number / audio.sample_rate as Number, Some(audio)),
+                    Err(why) => {
+                        self.ctxt.emit_error(Code::InvalidBuffer, why, length.pos().unwrap());
+                        return None;
+                    }
+                }
+            }
+            _ => {
+                self.ctxt.emit_error(Code::ExpectedExpression,
+                                     "expected the length of the buffer in seconds or a file to load",
+                                     length.pos().unwrap_or(name.pos()));
+                return None;
+            }
+        };
+        if seconds <= 0.0 || seconds > buffer::MAX_SECONDS {
+            self.ctxt.emit_error(Code::InvalidBuffer,
+                                 format!("buffer `{}` cannot be {} seconds long; expected more than 0 and at \
+                                          most {}", text, seconds, buffer::MAX_SECONDS),
+                                 length.pos().unwrap());
+            return None;
+        }
+        self.ctxt.buffers.borrow_mut().push(Buffer {
+            name: text,
+            seconds: seconds,
+            pos: pos,
+            audio: audio,
+        });
+        Some(())
+    }
+
+    // `tempo` is only a keyword when followed by a time or the file of a tempo map
+    fn at_tempo(&self) -> bool {
+        match (self.peek_token(0), self.peek_token(1)) {
+            (Some(Token::Ident(id)), Some(Token::Const(_))) |
+            (Some(Token::Ident(id)), Some(Token::Str(_))) => self.ctxt.lookup_name(id) == "tempo",
+            _ => false,
+        }
+    }
+
+    // A tempo map is declared by times in seconds with the tempo at each, as in
+    // `tempo 0: 120, 30: 90;`, or by a file to load, as in `tempo "song.json";`. Like a buffer, it
+    // only needs to be known to the runtime.
+    fn parse_tempo(&mut self) -> Option<()> {
+        let pos = self.peek_source_pos_or_end(0);
+        self.seek(1);
+        let map = match self.peek_token(0) {
+            Some(Token::Str(file)) => {
+                self.seek(1);
+                let path = self.ctxt.resolve_path(&self.ctxt.strings.borrow()[file]);
+                TempoMap::load(&path.to_string_lossy())
+            }
+            _ => {
+                let mut points = Vec::new();
+                loop {
+                    let time = match expect_value!(self, Token::Const) {
+                        Some(x) => x,
+                        None => {
+                            self.emit_error_here(Code::ExpectedExpression, "expected a time in seconds");
+                            return None;
+                        }
+                    };
+                    if expect!(self, Token::Symbol(Symbol::Colon)).is_none() {
+                        self.emit_error_here(Code::ExpectedSymbol, "expected `:`");
+                        return None;
+                    }
+                    let bpm = match expect_value!(self, Token::Const) {
+                        Some(x) => x,
+                        None => {
+                            self.emit_error_here(Code::ExpectedExpression, "expected a tempo in beats per minute");
+                            return None;
+                        }
+                    };
+                    points.push(tempo::Point { time: *time, bpm: *bpm });
+                    match self.peek_token(0) {
+                        Some(Token::Symbol(Symbol::Comma)) => self.seek(1),
+                        _ => break,
+                    }
+                }
+                TempoMap::new(points)
+            }
+        };
+        if expect!(self, Token::Symbol(Symbol::Semicolon)).is_none() {
+            self.emit_error_here(Code::ExpectedSymbol, "expected `;`");
+            return None;
+        }
+
+        if self.ctxt.tempo.borrow().is_some() {
+            self.ctxt.emit_error(Code::InvalidTempo, "the tempo map is already declared", pos);
+            return None;
+        }
+        match map {
+            Ok(map) => *self.ctxt.tempo.borrow_mut() = Some(map),
+            Err(why) => {
+                self.ctxt.emit_error(Code::InvalidTempo, why, pos);
+                return None;
+            }
+        }
+        Some(())
+    }
+
+    // `arrange` is only a keyword when followed by the name of an arrangement and then its first
+    // section or its fade, which could not start a function definition
+    fn at_arrange(&self) -> bool {
+        let keyword = match (self.peek_token(0), self.peek_token(1), self.peek_token(2)) {
+            (Some(Token::Ident(id)), Some(Token::Ident(_)), Some(Token::Symbol(Symbol::LeftBracket(Bracket::Curly)))) =>
+                self.ctxt.lookup_name(id) == "arrange",
+            (Some(Token::Ident(id)), Some(Token::Ident(_)), Some(Token::Ident(fade))) =>
+                self.ctxt.lookup_name(id) == "arrange" && self.ctxt.lookup_name(fade) == "fade",
+            _ => false,
+        };
+        keyword && match (self.peek_token(3), self.peek_token(4)) {
+            (Some(Token::Const(_)), Some(Token::Ident(unit))) => self.ctxt.lookup_name(unit) == "bar",
+            _ => false,
+        }
+    }
+
+    // An arrangement is named like a function and lists its sections by the bar each starts at,
+    // as in `arrange song fade 1bar { 0bar: intro; 8bar: drop; }`. It becomes a function of `time`
+    // and `bar` which plays them in turn, see arrange.rs.
+    fn parse_arrange(&mut self) -> Option<Node<FunctionDef>> {
+        let pos = self.peek_source_pos_or_end(0);
+        self.seek(1);
+        let ident = try_opt!(self.parse_ident());
+        let fade = match self.peek_token(0) {
+            Some(Token::Ident(id)) if self.ctxt.lookup_name(id) == "fade" => {
+                self.seek(1);
+                *try_opt!(self.parse_bars())
+            }
+            _ => 0.0,
+        };
+        try_opt!(self.parse_symbol(Symbol::LeftBracket(Bracket::Curly)));
+        let mut sections: Vec<Section> = Vec::new();
+        loop {
+            match self.peek_token(0) {
+                Some(Token::Symbol(Symbol::RightBracket(Bracket::Curly))) => {
+                    self.seek(1);
+                    break;
+                }
+                None => {
+                    self.ctxt.emit_error(Code::ExpectedSymbol, "expected `}`", self.end_source_pos());
+                    return None;
+                }
+                _ => { }
+            }
+            let bar = try_opt!(self.parse_bars());
+            try_opt!(self.parse_symbol(Symbol::Colon));
+            let func = try_opt!(self.parse_ident());
+            if let Some(last) = sections.last() {
+                if *bar <= last.bar {
+                    self.ctxt.emit_error(Code::InvalidArrangement,
+                                         format!("the section at bar {} must come after the one at bar {}",
+                                                 *bar, last.bar),
+                                         bar.pos());
+                    return None;
+                }
+                if *bar - last.bar < fade {
+                    self.ctxt.emit_error(Code::InvalidArrangement,
+                                         format!("the section at bar {} is shorter than the fade of {} bars",
+                                                 last.bar, fade),
+                                         last.pos);
+                    return None;
+                }
+            }
+            sections.push(Section {
+                bar: *bar,
+                func: func,
+                pos: bar.pos(),
+            });
+            match self.peek_token(0) {
+                Some(Token::Symbol(Symbol::Semicolon)) => self.seek(1),
+                Some(Token::Symbol(Symbol::RightBracket(Bracket::Curly))) => { },
+                _ => {
+                    self.seek(1);
+                    self.emit_error_here(Code::ExpectedSymbol, "expected `;` or `}`");
+                    return None;
+                }
+            }
+        }
+        if sections.is_empty() {
+            self.ctxt.emit_error(Code::InvalidArrangement, "an arrangement needs at least one section", pos);
+            return None;
+        }
+
+        let (time, bar) = {
+            let mut names = self.ctxt.names.borrow_mut();
+            (names.new_id("time"), names.new_id("bar"))
+        };
+        let func = Node(Function {
+            args: Node(vec![Argument::Ident(Node(time, pos)), Argument::Ident(Node(bar, pos))], pos),
+            block: Rc::new(Node(arrange::body(time, bar, &sections, fade), pos)),
+            arg_types: vec![None, None],
+            returns: None,
+        }, pos);
+        self.functions.insert(*ident, functions::Function::User(
+                functions::UserFunction {
+                    ty: None,
+                    node: Node(func.item().clone(), pos),
+                }));
+
+        Some(Node(FunctionDef {
+            ident: ident,
+            func: func,
+            docs: None,
+        }, pos))
+    }
+
+    // A number of bars, such as `8bar`.
+    fn parse_bars(&mut self) -> Option<Node<Number>> {
+        let bars = match expect_value!(self, Token::Const) {
+            Some(x) => x,
+            None => {
+                self.emit_error_here(Code::ExpectedExpression, "expected a number of bars, such as `8bar`");
+                return None;
+            }
+        };
+        match self.next_token() {
+            Some(Token::Ident(id)) if self.ctxt.lookup_name(id) == "bar" => Some(bars),
+            _ => {
+                self.emit_error_here(Code::ExpectedIdentifier, "expected `bar` after the number of bars");
+                None
+            }
+        }
+    }
+
+    // Items start with a name followed by `=`, the arguments of a function or its block, and end
+    // with `;` or `}`. Anything else, up to the end of the file without a `;`, is the trailing
+    // expression of a script.
+    fn at_script(&mut self) -> bool {
+        let item = match (self.peek_token(0), self.peek_token(1)) {
+            (Some(Token::Ident(_)), Some(Token::Ident(_))) |
+            (Some(Token::Ident(_)), Some(Token::Symbol(Symbol::Equals))) |
+            (Some(Token::Ident(_)), Some(Token::Symbol(Symbol::Arrow))) |
+            (Some(Token::Ident(_)), Some(Token::Symbol(Symbol::LeftBracket(Bracket::Curly)))) => true,
+            _ => false,
+        };
+        !item && self.find_smart(Token::Symbol(Symbol::Semicolon)).is_none()
+    }
+
+    // A script is a file which ends with an expression rather than defining its entrypoint. The
+    // compiler makes the expression the body of the entrypoint, as if it were written
+    // `main time { ... }`.
+    fn parse_script(&mut self) -> Option<()> {
+        let idx = self.index();
+        let end = self.end_index();
+        self.enter_subsection(idx, end);
+        let expr = try_opt!(self.parse_expression());
+        self.integrate_subsection();
+        *self.ctxt.script.borrow_mut() = Some(expr);
+        Some(())
+    }
+
     // an item is a top level construct: either an assignment or a function definition
     fn parse_item(&mut self) -> Option<Item> {
         try_opt!(self.parse_ident());
@@ -555,7 +1366,7 @@ impl<'a> Parser<'a> {
                 self.seek(-2);
                 let semi = self.find_smart(Token::Symbol(Symbol::Semicolon));
                 if semi.is_none() {
-                    self.ctxt.emit_error("expected `;`", self.end_source_pos());
+                    self.ctxt.emit_error(Code::ExpectedSymbol, "expected `;`", self.end_source_pos());
                     return None;
                 }
                 let idx = self.index();
@@ -571,7 +1382,7 @@ impl<'a> Parser<'a> {
                     self.seek(-1);
                     Some(Item::FunctionDef(try_opt!(self.parse_function_def())))
                 } else {
-                    self.emit_error_here("expected assignment or function definition");
+                    self.emit_error_here(Code::ExpectedItem, "expected assignment or function definition");
                     None
                 }
             }
@@ -597,7 +1408,7 @@ impl<'a> Parser<'a> {
             };
             if let Some(id) = arg.ident() {
                 if args.iter().any(|x| x.ident().unwrap() == id) {
-                    self.ctxt.emit_error("argument already previously defined", arg.pos());
+                    self.ctxt.emit_error(Code::DuplicateArgument, "argument already previously defined", arg.pos());
                 }
             }
             args.push(arg);
@@ -610,6 +1421,61 @@ impl<'a> Parser<'a> {
         Some(Node(args, pos))
     }
 
+    // Parses the argument list of a function definition, which may carry type annotations
+    fn parse_param_list(&mut self) -> Option<(Node<ArgumentList>, Vec<Option<Node<TypeName>>>)> {
+        let pos = self.peek_source_pos_or_end(0);
+        let mut args = Vec::new();
+        let mut types = Vec::new();
+        if self.len() == 0 {
+            return Some((Node(args, pos), types))
+        }
+        loop {
+            let idx = self.index();
+            let comma = self.find_smart(Token::Symbol(Symbol::Comma));
+            let token_idx = comma.unwrap_or(self.end_index());
+            self.enter_subsection(idx, token_idx);
+            let (arg, ty) = try_opt!(self.parse_param());
+            if let Some(id) = arg.ident() {
+                if args.iter().any(|x: &Argument| x.ident().unwrap() == id) {
+                    self.ctxt.emit_error(Code::DuplicateArgument, "argument already previously defined", arg.pos());
+                }
+            }
+            args.push(arg);
+            types.push(ty);
+            self.integrate_subsection();
+            if comma.is_none() {
+                break;
+            }
+        }
+        self.seek(-1); // no comma on the last one
+        Some((Node(args, pos), types))
+    }
+
+    fn parse_param(&mut self) -> Option<(Argument, Option<Node<TypeName>>)> {
+        let ident = try_opt!(self.parse_ident());
+        let ty = match self.peek_token(0) {
+            Some(Token::Symbol(Symbol::Colon)) => {
+                self.seek(1);
+                Some(try_opt!(self.parse_type_name()))
+            }
+            _ => None,
+        };
+        match self.next_token() {
+            None => {
+                self.seek(-1);
+                Some((Argument::Ident(ident), ty))
+            }
+            Some(Token::Symbol(Symbol::Equals)) => {
+                let expr = try_opt!(self.parse_expression());
+                Some((Argument::Assign(ident, expr), ty))
+            }
+            _ => {
+                self.emit_error_here(Code::ExpectedSymbol, "expected `:`, `=` or `,`");
+                None
+            }
+        }
+    }
+
     fn parse_arg_named(&mut self, allow_op: bool) -> Option<Argument> {
         let (one, two, three) = (self.next(), self.next(), self.next());
         match (one.item(), two.item(), three.item()) {
@@ -617,7 +1483,7 @@ impl<'a> Parser<'a> {
              Some(Token::Operator(op)),
              Some(Token::Symbol(Symbol::Equals))) if allow_op => {
                 if !op.can_take_x_args(2) {
-                    self.ctxt.emit_error("expected binary operator", two.pos().unwrap());
+                    self.ctxt.emit_error(Code::ExpectedOperator, "expected binary operator", two.pos().unwrap());
                     return None;
                 }
                 let expr = try_opt!(self.parse_expression());
@@ -637,7 +1503,7 @@ impl<'a> Parser<'a> {
                 Some(Argument::Ident(Node(id, one.pos().unwrap())))
             },
             _ => {
-                self.ctxt.emit_error("expected argument", self.peek_source_pos_or_end(-3));
+                self.ctxt.emit_error(Code::ExpectedExpression, "expected argument", self.peek_source_pos_or_end(-3));
                 None
             }
         }
@@ -656,14 +1522,14 @@ impl<'a> Parser<'a> {
             Some(Token::Symbol(Symbol::LeftBracket(Bracket::Square))) =>
                 (CallType::Named, Bracket::Square),
             _ => {
-                self.emit_error_here("expected `(` or `[`");
+                self.emit_error_here(Code::ExpectedSymbol, "expected `(` or `[`");
                 return None;
             }
         };
 
         let end = self.find_smart(Token::Symbol(Symbol::RightBracket(brace)));
         if end.is_none() {
-            self.ctxt.emit_error(format!("expected `{}`", Symbol::RightBracket(brace)),
+            self.ctxt.emit_error(Code::ExpectedSymbol, format!("expected `{}`", Symbol::RightBracket(brace)),
                                  self.end_source_pos());
             return None;
         }
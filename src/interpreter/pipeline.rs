@@ -0,0 +1,123 @@
+use super::common::Context;
+use super::compiler::Compiler;
+use super::runtime::Program;
+use super::issue::IssueTracker;
+use super::serialize::{serialize_ast, Format};
+use super::tokens::{Node, Token, SourcePos};
+use super::types::FunctionType;
+use super::ast::Root;
+use super::codes::Code;
+
+use std::cell::Ref;
+
+/// The tokens of every source file, in order.
+pub struct Tokens<'a> {
+    ctxt: &'a Context<'a>,
+}
+
+impl<'a> Tokens<'a> {
+    pub fn tokens(&self) -> Ref<'a, Vec<Node<Token>>> {
+        self.ctxt.tokens.borrow()
+    }
+
+    /// `///` comments, which are kept aside from the tokens.
+    pub fn docs(&self) -> Ref<'a, Vec<Node<String>>> {
+        self.ctxt.docs.borrow()
+    }
+}
+
+/// The parsed items, before any types are known.
+pub struct Ast<'a> {
+    ctxt: &'a Context<'a>,
+}
+
+impl<'a> Ast<'a> {
+    pub fn items(&self) -> Ref<'a, Root> {
+        self.ctxt.ast.borrow()
+    }
+
+    pub fn serialize(&self, format: Format) -> String {
+        serialize_ast(self.ctxt, format)
+    }
+}
+
+/// The items after type checking, with the type of every function used.
+pub struct TypedAst<'a> {
+    ctxt: &'a Context<'a>,
+}
+
+impl<'a> TypedAst<'a> {
+    /// Functions which were never called have been removed by now.
+    pub fn items(&self) -> Ref<'a, Root> {
+        self.ctxt.ast.borrow()
+    }
+
+    /// The concrete type of the function with the given name, if it has one.
+    pub fn function_type(&self, name: &str) -> Option<FunctionType> {
+        let id = match self.ctxt.names.borrow().get_id(name) {
+            Some(id) => id,
+            None => return None,
+        };
+        self.ctxt.functions.borrow().get(id).and_then(|f| f.ty()).cloned()
+    }
+
+    pub fn serialize(&self, format: Format) -> String {
+        serialize_ast(self.ctxt, format)
+    }
+}
+
+/// Runs the phases of a compiler one at a time. The intrinsics are defined up front, and host
+/// functions, constants and entrypoints can be added through compiler() before lexing.
+pub struct Pipeline<'a> {
+    compiler: Compiler<'a>,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn new(ctxt: &'a Context<'a>) -> Pipeline<'a> {
+        let compiler = Compiler::new(ctxt);
+        compiler.define_intrinsics();
+        Pipeline {
+            compiler: compiler,
+        }
+    }
+
+    /// Programs instantiated from the compiler borrow it, so they can't outlive the pipeline.
+    pub fn compiler(&self) -> &Compiler<'a> {
+        &self.compiler
+    }
+
+    pub fn lex(&mut self) -> Result<Tokens<'a>, IssueTracker<'a>> {
+        if self.compiler.lex() { Ok(Tokens { ctxt: self.compiler.context() }) } else { Err(self.issues()) }
+    }
+
+    pub fn parse(&mut self, _tokens: Tokens<'a>) -> Result<Ast<'a>, IssueTracker<'a>> {
+        if self.compiler.parse() { Ok(Ast { ctxt: self.compiler.context() }) } else { Err(self.issues()) }
+    }
+
+    pub fn typecheck(&mut self, _ast: &Ast<'a>) -> Result<TypedAst<'a>, IssueTracker<'a>> {
+        if self.compiler.typecheck() { Ok(TypedAst { ctxt: self.compiler.context() }) } else { Err(self.issues()) }
+    }
+
+    /// Generates code and instantiates the entrypoint with the given name, which must have been
+    /// defined before type checking. The program borrows the pipeline, since it runs code owned by
+    /// the compiler.
+    pub fn codegen<'p>(&'p mut self, _typed: &TypedAst<'a>, entrypoint: &str, sample_rate: u32)
+            -> Result<Program<'p>, IssueTracker<'a>> {
+        if !self.compiler.codegen() {
+            return Err(self.issues());
+        }
+        match Program::new(&self.compiler, entrypoint, sample_rate) {
+            Some(program) => Ok(program),
+            None => {
+                self.compiler.context().emit_error(Code::UnknownVariable,
+                                                   format!("no entrypoint named `{}`", entrypoint),
+                                                   SourcePos::anon());
+                Err(self.issues())
+            }
+        }
+    }
+
+    fn issues(&self) -> IssueTracker<'a> {
+        self.compiler.context().issues.borrow().clone()
+    }
+}
@@ -0,0 +1,95 @@
+use super::dsp::pitch;
+use super::runtime::{self, CallSite};
+use super::tokens::Number;
+
+/// How many notes can play at once.
+pub const VOICES: usize = 8;
+
+#[derive(Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+struct Voice {
+    note: Option<Number>, // None until the voice first plays
+    velocity: Number,
+    gate: bool,
+    started: u64, // how many notes had started before this one
+}
+
+/// The voices of a program and the notes they are playing.
+#[derive(Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct Voices {
+    voices: Vec<Voice>,
+    started: u64,
+}
+
+impl Voices {
+    pub fn new() -> Voices {
+        Voices {
+            voices: vec![Voice { note: None, velocity: 0.0, gate: false, started: 0 }; VOICES],
+            started: 0,
+        }
+    }
+
+    /// Starts playing a MIDI note number at a velocity from 0 to 1. It takes the voice which was
+    /// started longest ago among those which are not held, or if every voice is held, the one
+    /// started longest ago.
+    pub fn note_on(&mut self, note: Number, velocity: Number) {
+        let index = {
+            let oldest = |held: bool| self.voices.iter().enumerate().filter(|&(_, v)| v.gate == held)
+                                                   .min_by_key(|&(_, v)| v.started).map(|(i, _)| i);
+            oldest(false).or(oldest(true)).unwrap()
+        };
+        self.voices[index] = Voice {
+            note: Some(note),
+            velocity: velocity,
+            gate: true,
+            started: self.started,
+        };
+        self.started += 1;
+    }
+
+    /// Releases every voice holding the given note.
+    pub fn note_off(&mut self, note: Number) {
+        for voice in self.voices.iter_mut().filter(|v| v.note == Some(note)) {
+            voice.gate = false;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Voices::new();
+    }
+
+    fn freq(&self, index: usize) -> Number {
+        self.voices[index].note.map(pitch::mtof).unwrap_or(0.0)
+    }
+
+    fn gate(&self, index: usize) -> Number {
+        if self.voices[index].gate { 1.0 } else { 0.0 }
+    }
+
+    fn velocity(&self, index: usize) -> Number {
+        self.voices[index].velocity
+    }
+}
+
+// Voices are counted in floating point by the generated code.
+
+/// Called by generated code before the body of a voice block is evaluated for a voice, so that
+/// intrinsics use that voice's memory.
+pub extern fn voice_enter(site: CallSite, index: Number) {
+    runtime::with_site_state(site, |state| state.set_voice(Some(index as usize)))
+}
+
+/// Called by generated code after the last voice of a voice block.
+pub extern fn voice_exit(site: CallSite) {
+    runtime::with_site_state(site, |state| state.set_voice(None))
+}
+
+/// Called by generated code to fill in `note_freq`, `note_gate` and `note_velocity`.
+pub extern fn voice_freq(site: CallSite, index: Number) -> Number {
+    runtime::with_site_state(site, |state| state.voices().freq(index as usize))
+}
+pub extern fn voice_gate(site: CallSite, index: Number) -> Number {
+    runtime::with_site_state(site, |state| state.voices().gate(index as usize))
+}
+pub extern fn voice_velocity(site: CallSite, index: Number) -> Number {
+    runtime::with_site_state(site, |state| state.voices().velocity(index as usize))
+}
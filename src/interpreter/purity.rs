@@ -0,0 +1,186 @@
+use super::common::Context;
+use super::ast::{Root, Item, FunctionDef, FunctionCall, Expression, Statement, Argument, Block};
+use super::codegen::BUILTIN_VARIABLES;
+use super::functions::Function;
+use super::ident::Identifier;
+use super::tokens::{Node, NodeImpl};
+use super::types::Type;
+
+use std::collections::HashMap;
+
+/// Builtins which are pointer functions but have no state or side effects.
+const PURE_BUILTINS: &'static [&'static str] = &[
+    "sin", "cos", "log", "log10", "log2", "exp", "exp2", "pow",
+    "sin_exact", "cos_exact", "log_exact", "log2_exact", "exp_exact", "exp2_exact", "pow_exact",
+];
+
+#[derive(Copy, Clone, PartialEq)]
+enum State {
+    Visiting,
+    Pure,
+    Impure,
+}
+
+/// Which functions are pure, so that their results depend on nothing but their arguments and may
+/// be memoized or hoisted.
+pub struct Purity<'a, 'b> {
+    ctxt: &'a Context<'a>,
+    /// The top level functions of the AST, including the instances of generic ones.
+    pub defs: HashMap<Identifier, &'b FunctionDef>,
+    states: HashMap<Identifier, State>,
+}
+
+impl<'a, 'b> Purity<'a, 'b> {
+    /// An analysis of the type checked AST, which must be the one held by the context.
+    pub fn new(ctxt: &'a Context<'a>, ast: &'b Root) -> Purity<'a, 'b> {
+        let mut defs = HashMap::new();
+        for item in ast.iter() {
+            if let Item::FunctionDef(ref def) = *item {
+                defs.insert(def.ident(), &**def);
+            }
+        }
+        Purity {
+            ctxt: ctxt,
+            defs: defs,
+            states: HashMap::new(),
+        }
+    }
+
+    /// The function called by a call in `caller`, with the instance of a generic function
+    /// resolved like the code generator does.
+    pub fn callee(&self, callee: &Expression, caller: Option<Identifier>) -> Option<Identifier> {
+        match *callee {
+            Expression::Variable(Node(id, pos)) => Some(self.ctxt.instances.borrow()
+                .get(&(caller, pos.index)).cloned().unwrap_or(id)),
+            _ => None,
+        }
+    }
+
+    pub fn is_builtin_variable(&self, id: Identifier) -> bool {
+        let name = self.ctxt.lookup_name(id);
+        BUILTIN_VARIABLES.iter().any(|x| x.0 == name)
+    }
+
+    /// Checks if the function takes and returns only numbers.
+    pub fn has_numeric_signature(&self, id: Identifier) -> bool {
+        match self.ctxt.functions.borrow().get(id).and_then(|x| x.ty()) {
+            Some(ty) => ty.returns == Type::Number && ty.args.values().all(|&x| x == Type::Number),
+            None => false,
+        }
+    }
+
+    pub fn is_pure(&mut self, id: Identifier) -> bool {
+        match self.states.get(&id) {
+            Some(&State::Pure) => return true,
+            // recursion is left alone
+            Some(&State::Impure) | Some(&State::Visiting) => return false,
+            None => { },
+        }
+        let def = match self.defs.get(&id) {
+            Some(&def) => def,
+            None => return self.is_pure_builtin(id),
+        };
+        self.states.insert(id, State::Visiting);
+        let mut exprs = Vec::new();
+        walk_block(&def.block, &mut |expr| exprs.push(expr.clone()));
+        let pure = exprs.iter().all(|expr| self.is_pure_expr(expr, id));
+        self.states.insert(id, if pure { State::Pure } else { State::Impure });
+        pure
+    }
+
+    fn is_pure_builtin(&self, id: Identifier) -> bool {
+        match self.ctxt.functions.borrow().get(id) {
+            Some(&Function::External(_)) => true,
+            Some(&Function::Pointer(_)) => PURE_BUILTINS.contains(&&self.ctxt.lookup_name(id)[..]),
+            _ => false,
+        }
+    }
+
+    /// Checks if a call made in `caller` is of a pure function, and passes no builtin variables
+    /// by name.
+    pub fn is_pure_call(&mut self, call: &FunctionCall, caller: Identifier) -> bool {
+        let reads_builtin = call.args().iter().any(|arg| match *arg {
+            Argument::Ident(Node(id, _)) | Argument::OpAssign(Node(id, _), _, _) => self.is_builtin_variable(id),
+            _ => false,
+        });
+        // calls of arguments and local variables could be calls of anything
+        !reads_builtin && match self.callee(call.callee(), Some(caller)) {
+            Some(id) => self.is_pure(id),
+            None => false,
+        }
+    }
+
+    // Checks a single expression of a function, without its children.
+    fn is_pure_expr(&mut self, expr: &Expression, func: Identifier) -> bool {
+        match *expr {
+            Expression::BlockRate(_) | Expression::Oversample(_) | Expression::Fft(_) |
+            Expression::Voice(_) | Expression::Closure(_) => false,
+            Expression::Variable(Node(id, _)) => !self.is_builtin_variable(id) ||
+                self.defs[&func].args().iter().any(|arg| arg.ident() == Some(id)),
+            Expression::FunctionCall(ref call) => self.is_pure_call(call, func),
+            _ => true,
+        }
+    }
+}
+
+/// Calls `f` with each expression directly below `expr`, not looking into blocks or closures.
+pub fn for_each_child<'e, F>(expr: &'e Expression, mut f: F) where F: FnMut(&'e Expression) {
+    match *expr {
+        Expression::Infix(ref x) => {
+            f(x.left());
+            f(x.right());
+        }
+        Expression::Prefix(ref x) => f(x.expr()),
+        Expression::BlockRate(ref x) => f(x.expr()),
+        Expression::Oversample(ref x) => f(x.expr()),
+        Expression::Fft(ref x) => {
+            f(x.input());
+            f(x.expr());
+        }
+        Expression::Voice(ref x) => f(x.expr()),
+        Expression::Array(ref x) => for elem in x.iter() {
+            f(elem);
+        },
+        Expression::Record(ref x) => for &(_, ref value) in &x.fields {
+            f(value);
+        },
+        Expression::Field(ref x) => f(x.expr()),
+        Expression::Conditional(ref x) => {
+            f(x.cond());
+            f(x.then());
+            f(x.els());
+        }
+        Expression::FunctionCall(ref call) => {
+            f(call.callee());
+            for arg in call.args() {
+                match *arg {
+                    Argument::Ident(_) => { },
+                    Argument::Assign(_, ref expr) |
+                    Argument::OpAssign(_, _, ref expr) |
+                    Argument::Expr(ref expr) => f(expr),
+                }
+            }
+        }
+        Expression::Block(_) | Expression::Closure(_) | Expression::Constant(_) |
+        Expression::Boolean(_) | Expression::Str(_) | Expression::Variable(_) => { },
+    }
+}
+
+/// Calls `f` with every expression in a block, including those in nested blocks but not those in
+/// closures.
+pub fn walk_block<F>(block: &Block, f: &mut F) where F: FnMut(&Expression) {
+    for statement in block {
+        match *statement {
+            Statement::Assignment(ref assign) => walk_expr(assign.expr(), f),
+            Statement::Expression(ref expr) => walk_expr(expr, f),
+        }
+    }
+}
+
+fn walk_expr<F>(expr: &Expression, f: &mut F) where F: FnMut(&Expression) {
+    f(expr);
+    if let Expression::Block(ref x) = *expr {
+        walk_block(x.item(), f);
+    }
+    for_each_child(expr, |child| walk_expr(child, f));
+}
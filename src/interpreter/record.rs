@@ -0,0 +1,57 @@
+use super::ident::Identifier;
+
+use std::collections::HashMap;
+
+/// Identifies a shape among those of a program, as the `Type::Record` of records of that shape.
+pub type ShapeId = usize;
+
+/// The shapes of every record in a program, each given once, and where each field which is read
+/// is found.
+#[derive(Clone, Debug)]
+pub struct RecordTable {
+    shapes: Vec<Vec<Identifier>>, // the fields of each shape, in order of their identifiers
+    // From the function a field is read in and its position to where its value is stored, since
+    // each instance of a generic function may read records of another shape.
+    reads: HashMap<(Option<Identifier>, usize), usize>,
+}
+
+impl RecordTable {
+    pub fn new() -> RecordTable {
+        RecordTable {
+            shapes: Vec::new(),
+            reads: HashMap::new(),
+        }
+    }
+
+    /// The shape with the given fields, which must all differ.
+    pub fn intern(&mut self, fields: &[Identifier]) -> ShapeId {
+        let mut fields = fields.to_vec();
+        fields.sort();
+        match self.shapes.iter().position(|x| *x == fields) {
+            Some(id) => id,
+            None => {
+                self.shapes.push(fields);
+                self.shapes.len() - 1
+            }
+        }
+    }
+
+    /// The fields of a shape, in the order their values are stored.
+    pub fn fields(&self, shape: ShapeId) -> &[Identifier] {
+        &self.shapes[shape]
+    }
+
+    /// Where the value of a field is stored in records of a shape, if it has the field.
+    pub fn index(&self, shape: ShapeId, field: Identifier) -> Option<usize> {
+        self.shapes[shape].iter().position(|&x| x == field)
+    }
+
+    pub fn set_read(&mut self, func: Option<Identifier>, field_index: usize, index: usize) {
+        self.reads.insert((func, field_index), index);
+    }
+
+    /// Where the value of the field read at the given source index in a function is stored.
+    pub fn read(&self, func: Option<Identifier>, field_index: usize) -> Option<usize> {
+        self.reads.get(&(func, field_index)).cloned()
+    }
+}
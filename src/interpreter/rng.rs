@@ -0,0 +1,38 @@
+use super::tokens::Number;
+
+/// A small, fast and seedable xorshift64* generator. Its whole state is one integer, which keeps
+/// it cheap to snapshot and identical on every platform.
+#[derive(Copy, Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // splitmix64 spreads similar seeds apart and avoids the all zero state
+        let mut z = seed.wrapping_add(0x9E3779B97F4A7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z = z ^ (z >> 31);
+        Rng {
+            state: if z == 0 { 1 } else { z },
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+
+    /// Uniformly distributed in [0, 1).
+    pub fn next_number(&mut self) -> Number {
+        (self.next_u64() >> 11) as Number / (1u64 << 53) as Number
+    }
+
+    /// Uniformly distributed in [lo, hi).
+    pub fn range(&mut self, lo: Number, hi: Number) -> Number {
+        lo + (hi - lo) * self.next_number()
+    }
+}
@@ -0,0 +1,1553 @@
+use super::tokens::{Number, SourcePos};
+use super::compiler::Compiler;
+use super::codegen::{ENTRY_FN_SUFFIX, BUSES_FN_NAME, RESET_CACHES_FN_NAME, BUILTIN_VARIABLES};
+use super::bus::{BusInputs, with_bus_inputs};
+use super::buffer::SampleBuffer;
+use super::poly::{Voices, VOICES};
+use super::rng::Rng;
+use super::source_map::SourceMap;
+use super::dsp::{smooth, trigger};
+use super::dsp::convolve::{self, Impulse};
+use super::automation::Automation;
+use super::tempo::{TempoMap, BEATS_PER_BAR};
+use super::audio::Sample;
+
+use rustc_serialize::json;
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::fmt;
+use std::io::{self, BufWriter, Write};
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+use std::slice;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// The signature of a host-registered intrinsic. Arguments are passed in the order of the
+/// function type's arguments.
+pub type IntrinsicFn = Fn(&[Number], &mut State) -> Number + Send + Sync;
+
+/// A function implemented by the host which can be called from synthizer code.
+pub struct Intrinsic {
+    pub func: Box<IntrinsicFn>,
+}
+
+impl Intrinsic {
+    pub fn new(func: Box<IntrinsicFn>) -> Intrinsic {
+        Intrinsic {
+            func: func,
+        }
+    }
+}
+
+impl fmt::Debug for Intrinsic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Intrinsic({:p})", self)
+    }
+}
+
+/// Identifies a single call to an intrinsic in the source code. Site 0 is used when an intrinsic
+/// is called indirectly through a function value.
+pub type CallSite = usize;
+
+/// Everything that persists between evaluations of a program. Intrinsics use this to keep memory
+/// (filter history, delay lines, ...) across samples.
+#[derive(Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct State {
+    memory: Vec<Vec<Number>>, // from CallSite
+    voice_memory: Vec<Vec<Vec<Number>>>, // from the index of a voice, then CallSite
+    site: CallSite,
+    voice: Option<usize>, // the voice being evaluated, if any
+    voices: Voices,
+    sample_rate: u32,
+    seed: u64,
+    rng: Rng,
+    failed_asserts: Vec<FailedAssert>,
+    strings: Arc<Vec<String>>, // the string literals of the program
+    buffers: Vec<SampleBuffer>,
+    impulses: Vec<Impulse>,
+    pending_blocks: Vec<(CallSite, Option<usize>, usize)>, // convolutions with a full block: site, voice and response
+}
+
+impl State {
+    pub fn new(sample_rate: u32) -> State {
+        State {
+            memory: Vec::new(),
+            voice_memory: Vec::new(),
+            site: 0,
+            voice: None,
+            voices: Voices::new(),
+            sample_rate: sample_rate,
+            seed: 0,
+            rng: Rng::new(0),
+            failed_asserts: Vec::new(),
+            strings: Arc::new(Vec::new()),
+            buffers: Vec::new(),
+            impulses: Vec::new(),
+            pending_blocks: Vec::new(),
+        }
+    }
+
+    /// The generator which every source of randomness must draw from, so that renders are
+    /// reproducible for a given seed.
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Restarts the random number generator from a new seed.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = Rng::new(seed);
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Changes the sample rate intrinsics see, such as while an oversampled section is evaluated.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// The string literal with the given index, which is how strings are passed to builtins.
+    pub fn string(&self, index: usize) -> &str {
+        self.strings.get(index).map(|x| &x[..]).unwrap_or("")
+    }
+
+    pub fn set_strings(&mut self, strings: Arc<Vec<String>>) {
+        self.strings = strings;
+    }
+
+    /// The samples of the buffer named by the string literal with the given index, if the program
+    /// declares it.
+    pub fn buffer(&mut self, name: usize) -> Option<&mut [Number]> {
+        let name = self.strings.get(name).map(|x| &x[..]).unwrap_or("");
+        self.buffers.iter_mut().find(|x| x.name == name).map(|x| &mut x.samples[..])
+    }
+
+    pub fn set_buffers(&mut self, buffers: Vec<SampleBuffer>) {
+        self.buffers = buffers;
+        self.allocate_buffers();
+    }
+
+    /// Makes room for every buffer at the sample rate, and empties them.
+    pub fn allocate_buffers(&mut self) {
+        for buffer in &mut self.buffers {
+            buffer.allocate(self.sample_rate);
+        }
+    }
+
+    /// The index of the impulse response loaded for `convolve` from the file named by the string
+    /// literal with the given index.
+    pub fn impulse_index(&self, name: usize) -> Option<usize> {
+        let name = self.string(name);
+        self.impulses.iter().position(|x| x.name == name)
+    }
+
+    /// Runs `f` with the memory of the current call site and the impulse response with the given
+    /// index, for `convolve`.
+    pub fn with_impulse<F, R>(&mut self, index: usize, f: F) -> R where F: FnOnce(&mut [Number], &Impulse) -> R {
+        let impulse = &self.impulses[index];
+        let len = convolve::memory_len(impulse.taps().len());
+        f(site_memory(&mut self.memory, &mut self.voice_memory, self.voice, self.site, len), impulse)
+    }
+
+    /// Leaves the full block of the convolution at the current call site to be transformed by
+    /// process_blocks(). Returns false if there is no room to, and the caller must do it.
+    pub fn schedule_block(&mut self, impulse: usize) -> bool {
+        if self.pending_blocks.len() == self.pending_blocks.capacity() {
+            return false;
+        }
+        self.pending_blocks.push((self.site, self.voice, impulse));
+        true
+    }
+
+    /// Transforms the blocks of input which convolutions filled in the last sample.
+    pub fn process_blocks(&mut self) {
+        for &(site, voice, index) in &self.pending_blocks {
+            let impulse = &self.impulses[index];
+            let len = convolve::memory_len(impulse.taps().len());
+            convolve::process_block(site_memory(&mut self.memory, &mut self.voice_memory, voice, site, len), impulse);
+        }
+        self.pending_blocks.clear();
+    }
+
+    pub fn set_impulses(&mut self, impulses: Vec<Impulse>) {
+        self.impulses = impulses;
+        self.allocate_impulses();
+    }
+
+    /// Resamples every impulse response to the sample rate.
+    pub fn allocate_impulses(&mut self) {
+        for impulse in &mut self.impulses {
+            impulse.allocate(self.sample_rate);
+        }
+    }
+
+    /// The call site of the intrinsic currently being evaluated.
+    pub fn site(&self) -> CallSite {
+        self.site
+    }
+
+    pub fn voices(&mut self) -> &mut Voices {
+        &mut self.voices
+    }
+
+    /// Makes memory() give the memory of a voice, while the body of a voice block is evaluated.
+    pub fn set_voice(&mut self, voice: Option<usize>) {
+        self.voice = voice;
+    }
+
+    /// Returns the memory belonging to the current call site, and to the current voice inside a
+    /// voice block, which is at least `len` long. Newly allocated memory is zeroed.
+    pub fn memory(&mut self, len: usize) -> &mut [Number] {
+        site_memory(&mut self.memory, &mut self.voice_memory, self.voice, self.site, len)
+    }
+
+    /// The memory of every call site which has used any, indexed by call site.
+    pub fn memories(&self) -> &[Vec<Number>] {
+        &self.memory
+    }
+
+    /// Every `assert` which has failed, in the order they failed.
+    pub fn failed_asserts(&self) -> &[FailedAssert] {
+        &self.failed_asserts
+    }
+
+    /// Makes room for the memory of `sites` call sites, so that looking up the memory of any of
+    /// them never has to grow the table.
+    pub fn reserve_sites(&mut self, sites: usize) {
+        if self.memory.len() < sites {
+            self.memory.resize(sites, Vec::new());
+        }
+        // each site of each voice fills at most one block of a convolution per sample
+        let blocks = sites * (VOICES + 1);
+        if self.pending_blocks.capacity() < blocks {
+            self.pending_blocks.reserve(blocks);
+        }
+    }
+
+    /// Forgets all memory, as if the program was just started. Memory is zeroed rather than freed,
+    /// so a program which has run before does not allocate again.
+    pub fn reset(&mut self) {
+        for mem in self.memory.iter_mut().chain(self.voice_memory.iter_mut().flat_map(|x| x.iter_mut())) {
+            for x in mem.iter_mut() {
+                *x = 0.0;
+            }
+        }
+        self.voice = None;
+        self.voices.reset();
+        for buffer in &mut self.buffers {
+            buffer.clear();
+        }
+        self.pending_blocks.clear();
+        self.site = 0;
+        self.rng = Rng::new(self.seed);
+        self.failed_asserts.clear();
+    }
+}
+
+// The memory of a call site, in the memory of a voice if one is given, grown to at least `len`.
+fn site_memory<'m>(memory: &'m mut Vec<Vec<Number>>, voice_memory: &'m mut Vec<Vec<Vec<Number>>>,
+                   voice: Option<usize>, site: CallSite, len: usize) -> &'m mut [Number] {
+    let memory = match voice {
+        Some(voice) => {
+            if voice_memory.len() <= voice {
+                voice_memory.resize(voice + 1, Vec::new());
+            }
+            &mut voice_memory[voice]
+        }
+        None => memory,
+    };
+    if memory.len() <= site {
+        memory.resize(site + 1, Vec::new());
+    }
+    let mem = &mut memory[site];
+    if mem.len() < len {
+        mem.resize(len, 0.0);
+    }
+    &mut mem[..]
+}
+
+thread_local!(static CURRENT_STATE: Cell<*mut State> = Cell::new(ptr::null_mut()));
+
+/// Runs `f` with `state` as the state used by any intrinsics it calls on this thread.
+pub fn with_state<F, R>(state: &mut State, f: F) -> R where F: FnOnce() -> R {
+    let prev = CURRENT_STATE.with(|cur| {
+        let prev = cur.get();
+        cur.set(state);
+        prev
+    });
+    let res = f();
+    CURRENT_STATE.with(|cur| cur.set(prev));
+    res
+}
+
+/// Called by generated code immediately before a direct call to an intrinsic.
+pub extern fn set_call_site(site: CallSite) {
+    CURRENT_STATE.with(|cur| {
+        let state = cur.get();
+        if !state.is_null() {
+            unsafe { (*state).site = site; }
+        }
+    });
+}
+
+/// Runs `f` with the state of the current thread, as seen from the given call site. Used by
+/// builtins which are passed their call site directly rather than through set_call_site().
+pub fn with_site_state<F, R>(site: CallSite, f: F) -> R where F: FnOnce(&mut State) -> R {
+    CURRENT_STATE.with(|cur| {
+        let state = cur.get();
+        assert!(!state.is_null(), "builtin evaluated without a runtime state");
+        let state = unsafe { &mut *state };
+        state.site = site;
+        let res = f(state);
+        state.site = 0;
+        res
+    })
+}
+
+// Moves the sequencer at the current call site to its next step if `clock` fired, and returns
+// the step it is on. The first time the clock fires it stays on the first step.
+fn sequence_step(state: &mut State, clock: Number, len: usize) -> usize {
+    let mem = state.memory(2); // the current step, whether the clock has fired before
+    if trigger::fired(clock) {
+        if mem[1] != 0.0 {
+            mem[0] = ((mem[0] as usize + 1) % len) as Number;
+        }
+        mem[1] = 1.0;
+    }
+    mem[0] as usize
+}
+
+/// The implementation of `seq`, which steps through an array each time `clock` fires and
+/// returns the value of the current step.
+pub extern fn seq(site: CallSite, clock: Number, values: *const Number, len: usize) -> Number {
+    let values = unsafe { slice::from_raw_parts(values, len) };
+    with_site_state(site, |state| values[sequence_step(state, clock, len)])
+}
+
+/// The implementation of `gate`, which steps through an array like `seq` and fires whenever
+/// `clock` does, except on steps which are 0. Given the same array as a `seq`, it triggers the
+/// notes and leaves out the rests.
+pub extern fn gate(site: CallSite, clock: Number, values: *const Number, len: usize) -> Number {
+    let values = unsafe { slice::from_raw_parts(values, len) };
+    with_site_state(site, |state| {
+        let step = sequence_step(state, clock, len);
+        if trigger::fired(clock) && values[step] != 0.0 { 1.0 } else { 0.0 }
+    })
+}
+
+/// The implementation of `choose`, which picks one of `values` at random each time `trigger`
+/// fires and returns it until the next time. Before the first trigger it returns the first.
+pub extern fn choose(site: CallSite, trigger: Number, values: *const Number, len: usize) -> Number {
+    let values = unsafe { slice::from_raw_parts(values, len) };
+    with_site_state(site, |state| {
+        if trigger::fired(trigger) {
+            let index = ((state.rng().next_number() * len as Number) as usize).min(len - 1);
+            state.memory(1)[0] = index as Number;
+        }
+        values[state.memory(1)[0] as usize]
+    })
+}
+
+/// The implementation of `select`, which returns the element of `values` at `index` rounded
+/// down. Indices outside of the array give the nearest element.
+pub extern fn select(_: CallSite, index: Number, values: *const Number, len: usize) -> Number {
+    let values = unsafe { slice::from_raw_parts(values, len) };
+    // NaN is cast to 0
+    values[(index.max(0.0) as usize).min(len - 1)]
+}
+
+/// How an argument of an entrypoint is given its value each time the program is evaluated.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Input {
+    Time,
+    SampleRate,
+    /// The time in beats, following the program's tempo map.
+    Beat,
+    /// The time in bars of the tempo map.
+    Bar,
+    /// A value set by the host with Program::set_param(), starting at the argument's default.
+    /// Changes are smoothed over the given number of milliseconds.
+    Param(String, Number, Number),
+}
+
+/// Where the generated code keeps the built-in variables `sample_rate`, `channel` and
+/// `sample_index`, which must be set before the program is initialized or evaluated.
+#[derive(Clone, Debug)]
+pub struct Builtins {
+    // addresses of the globals, if they were generated
+    sample_rate: Option<usize>,
+    channel: Option<usize>,
+    sample_index: Option<usize>,
+}
+
+impl Builtins {
+    pub fn new(compiler: &Compiler) -> Builtins {
+        let global = |name: &str| {
+            let &(_, global_name) = BUILTIN_VARIABLES.iter().find(|x| x.0 == name).unwrap();
+            unsafe { compiler.get_global::<Number>(global_name) }.map(|x| x as usize)
+        };
+        Builtins {
+            sample_rate: global("sample_rate"),
+            channel: global("channel"),
+            sample_index: global("sample_index"),
+        }
+    }
+
+    pub fn set(&self, sample_rate: u32, channel: u32, sample_index: u64) {
+        let values = [(self.sample_rate, sample_rate as Number),
+                      (self.channel, channel as Number),
+                      (self.sample_index, sample_index as Number)];
+        for &(global, value) in &values {
+            if let Some(global) = global {
+                unsafe { *(global as *mut Number) = value; }
+            }
+        }
+    }
+}
+
+thread_local!(static CURRENT_INPUTS: Cell<(*const Number, usize)> = Cell::new((ptr::null(), 0)));
+
+// Runs `f` with `values` as the inputs of the entrypoint it evaluates on this thread.
+fn with_inputs<F, R>(values: &[Number], f: F) -> R where F: FnOnce() -> R {
+    let prev = CURRENT_INPUTS.with(|cur| {
+        let prev = cur.get();
+        cur.set((values.as_ptr(), values.len()));
+        prev
+    });
+    let res = f();
+    CURRENT_INPUTS.with(|cur| cur.set(prev));
+    res
+}
+
+/// Called by the generated wrapper of an entrypoint for each of its inputs other than `time`,
+/// indexed in the order of the wrapped function's arguments.
+pub extern fn entry_input(index: usize) -> Number {
+    CURRENT_INPUTS.with(|cur| {
+        let (values, len) = cur.get();
+        assert!(index < len, "entrypoint input evaluated outside of the program");
+        unsafe { *values.offset(index as isize) }
+    })
+}
+
+thread_local!(static CURRENT_OUTPUTS: Cell<(*mut Number, usize)> = Cell::new((ptr::null_mut(), 0)));
+
+// Runs `f` with `values` receiving the channels after the first of the entrypoint it evaluates
+// on this thread.
+fn with_outputs<F, R>(values: &mut [Number], f: F) -> R where F: FnOnce() -> R {
+    let prev = CURRENT_OUTPUTS.with(|cur| {
+        let prev = cur.get();
+        cur.set((values.as_mut_ptr(), values.len()));
+        prev
+    });
+    let res = f();
+    CURRENT_OUTPUTS.with(|cur| cur.set(prev));
+    res
+}
+
+/// Called by the generated wrapper of an entrypoint which returns several channels, for each
+/// channel after the first, which it returns instead.
+pub extern fn entry_output(index: usize, value: Number) {
+    with_current_outputs(|outputs| {
+        assert!(index < outputs.len(), "entrypoint output evaluated outside of the program");
+        outputs[index] = value;
+    })
+}
+
+/// Called by the generated code which processes the buses for each of the `count` channels a bus
+/// gives, to add them to the outputs. A bus of one channel is heard on every channel.
+pub extern fn bus_output(count: usize, index: usize, value: Number) {
+    with_current_outputs(|outputs| {
+        if count == 1 {
+            for x in outputs.iter_mut() {
+                *x += value;
+            }
+        } else if index < outputs.len() {
+            outputs[index] += value;
+        }
+    })
+}
+
+fn with_current_outputs<F, R>(f: F) -> R where F: FnOnce(&mut [Number]) -> R {
+    CURRENT_OUTPUTS.with(|cur| {
+        let (values, len) = cur.get();
+        f(unsafe { slice::from_raw_parts_mut(values, len) })
+    })
+}
+
+// Evaluates the entrypoint and then every bus, leaving the mix of their channels in the current
+// outputs and returning the first. An entrypoint of one channel is heard on every channel.
+fn eval_mix(main_fn: extern fn(Number) -> Number, buses_fn: extern fn(()), main_channels: usize,
+            time: Number) -> Number {
+    let res = main_fn(time);
+    with_current_outputs(|outputs| {
+        outputs[0] = res;
+        if main_channels == 1 {
+            for x in outputs[1..].iter_mut() {
+                *x = res;
+            }
+        }
+    });
+    buses_fn(());
+    with_current_outputs(|outputs| outputs[0])
+}
+
+/// Called by the generated wrapper of each intrinsic. `func` is a `*const Intrinsic`.
+pub extern fn call_intrinsic(func: usize, args: *const Number, argc: usize) -> Number {
+    let func = unsafe { &*(func as *const Intrinsic) };
+    let args = unsafe { slice::from_raw_parts(args, argc) };
+    CURRENT_STATE.with(|cur| {
+        let state = cur.get();
+        assert!(!state.is_null(), "intrinsic called without a runtime state");
+        let state = unsafe { &mut *state };
+        let res = (func.func)(args, state);
+        state.site = 0;
+        res
+    })
+}
+
+/// An `assert` whose condition was false.
+#[derive(Copy, Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct FailedAssert {
+    pub site: CallSite,
+    pub message: usize, // index among the string literals of the source
+}
+
+/// A failed `assert` along with where it is in the source, if known.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssertFailure {
+    pub pos: Option<SourcePos>,
+    pub message: String,
+}
+
+impl AssertFailure {
+    pub fn new<S>(failed: &FailedAssert, source_map: &SourceMap, strings: &[S]) -> AssertFailure
+            where S: AsRef<str> {
+        AssertFailure {
+            pos: source_map.call_site(failed.site),
+            message: strings.get(failed.message).map(|x| x.as_ref().to_string()).unwrap_or(String::new()),
+        }
+    }
+}
+
+impl fmt::Display for AssertFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "assertion failed"));
+        if let Some(pos) = self.pos {
+            try!(write!(f, " at {}", pos));
+        }
+        if !self.message.is_empty() {
+            try!(write!(f, ": {}", self.message));
+        }
+        Ok(())
+    }
+}
+
+/// The implementation of `assert`, called through a pointer by generated code. The condition is
+/// passed as an LLVM `i1`, so only its lowest bit is meaningful. Returns 0 so that asserts can be
+/// added to the value of a block.
+pub extern fn assert(cond: u8, message: usize) -> Number {
+    CURRENT_STATE.with(|cur| {
+        let state = cur.get();
+        if !state.is_null() {
+            let state = unsafe { &mut *state };
+            if cond & 1 == 0 {
+                let site = state.site;
+                state.failed_asserts.push(FailedAssert {
+                    site: site,
+                    message: message,
+                });
+            }
+            state.site = 0;
+        }
+    });
+    0.0
+}
+
+/// The implementation of `probe`, which passes `value` through unchanged and records it under
+/// the given name if probes are being recorded.
+pub extern fn probe(name: usize, value: Number) -> Number {
+    CURRENT_PROBES.with(|cur| {
+        let probes = cur.get();
+        if !probes.is_null() {
+            unsafe { (*probes).record(name, value); }
+        }
+    });
+    CURRENT_STATE.with(|cur| {
+        let state = cur.get();
+        if !state.is_null() {
+            unsafe { (*state).site = 0; }
+        }
+    });
+    value
+}
+
+/// Collects the values passed to `probe` for each sample, with a column for each probe name.
+/// Probes which were not evaluated during a sample are left empty, and when a probe is evaluated
+/// several times the last value is kept. Rows may also be written as CSV, with the time followed
+/// by the columns.
+pub struct ProbeLog {
+    out: Option<BufWriter<Box<Write + Send>>>,
+    columns: Vec<Option<usize>>, // from the index of a string literal
+    row: Vec<Option<Number>>,
+    last: Vec<Option<Number>>,
+    max_samples: Option<u64>,
+}
+
+impl ProbeLog {
+    pub fn new<S>(out: Option<Box<Write + Send>>, names: &[S], strings: &[S], max_samples: Option<u64>)
+            -> io::Result<ProbeLog> where S: AsRef<str> {
+        let out = match out {
+            Some(out) => {
+                let mut out = BufWriter::new(out);
+                try!(write!(out, "time"));
+                for name in names {
+                    try!(write!(out, ",{}", csv_field(name.as_ref())));
+                }
+                try!(writeln!(out, ""));
+                Some(out)
+            }
+            None => None,
+        };
+        Ok(ProbeLog {
+            out: out,
+            columns: strings.iter()
+                            .map(|s| names.iter().position(|n| n.as_ref() == s.as_ref()))
+                            .collect(),
+            row: vec![None; names.len()],
+            last: vec![None; names.len()],
+            max_samples: max_samples,
+        })
+    }
+
+    /// The values of each column in the last complete sample.
+    pub fn last_values(&self) -> &[Option<Number>] {
+        &self.last
+    }
+
+    fn record(&mut self, name: usize, value: Number) {
+        if let Some(&Some(column)) = self.columns.get(name) {
+            self.row[column] = Some(value);
+        }
+    }
+
+    /// Writes the values recorded since the last sample.
+    fn end_sample(&mut self, sample_index: u64, time: Number) -> io::Result<()> {
+        mem::swap(&mut self.last, &mut self.row);
+        for value in self.row.iter_mut() {
+            *value = None;
+        }
+        if self.max_samples.map_or(false, |max| sample_index >= max) {
+            return Ok(());
+        }
+        let out = match self.out {
+            Some(ref mut out) => out,
+            None => return Ok(()),
+        };
+        try!(write!(out, "{}", time));
+        for value in &self.last {
+            match *value {
+                Some(x) => try!(write!(out, ",{}", x)),
+                None => try!(write!(out, ",")),
+            }
+        }
+        writeln!(out, "")
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self.out {
+            Some(ref mut out) => out.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+// Quotes a field which would otherwise be split or end the row, doubling the quotes in it.
+fn csv_field(s: &str) -> String {
+    if s.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", s.replace("\"", "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+thread_local!(static CURRENT_PROBES: Cell<*mut ProbeLog> = Cell::new(ptr::null_mut()));
+
+/// Runs `f` with calls to `probe` on this thread recorded by `probes`.
+pub fn with_probes<F, R>(probes: &mut ProbeLog, f: F) -> R where F: FnOnce() -> R {
+    let prev = CURRENT_PROBES.with(|cur| {
+        let prev = cur.get();
+        cur.set(probes);
+        prev
+    });
+    let res = f();
+    CURRENT_PROBES.with(|cur| cur.set(prev));
+    res
+}
+
+/// The name and argument names of a traced function.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceLabel {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+/// Logs calls to traced functions with their arguments and result. A function is logged at most
+/// once every `interval` samples, so that its values can be followed without flooding the output.
+pub struct Tracer {
+    labels: Vec<TraceLabel>,
+    interval: u64,
+    sample_index: u64,
+    time: Number,
+    last_logged: Vec<Option<u64>>, // from the id of a label
+    frames: Vec<TraceFrame>,
+    out: Box<Write + Send>,
+}
+
+struct TraceFrame {
+    id: usize,
+    logged: bool,
+    values: Vec<String>, // the arguments, followed by the result
+}
+
+impl Tracer {
+    pub fn new(labels: Vec<TraceLabel>, interval: u64, out: Box<Write + Send>) -> Tracer {
+        let len = labels.len();
+        Tracer {
+            labels: labels,
+            interval: interval.max(1),
+            sample_index: 0,
+            time: 0.0,
+            last_logged: vec![None; len],
+            frames: Vec::new(),
+            out: out,
+        }
+    }
+
+    fn set_sample(&mut self, sample_index: u64, time: Number) {
+        self.sample_index = sample_index;
+        self.time = time;
+    }
+
+    fn enter(&mut self, id: usize) {
+        let due = match self.last_logged[id] {
+            Some(last) => self.sample_index < last || self.sample_index - last >= self.interval,
+            None => true,
+        };
+        if due {
+            self.last_logged[id] = Some(self.sample_index);
+        }
+        self.frames.push(TraceFrame {
+            id: id,
+            logged: due,
+            values: Vec::new(),
+        });
+    }
+
+    fn value(&mut self, value: String) {
+        if let Some(frame) = self.frames.last_mut() {
+            if frame.logged {
+                frame.values.push(value);
+            }
+        }
+    }
+
+    fn exit(&mut self) {
+        let frame = match self.frames.pop() {
+            Some(frame) => frame,
+            None => return,
+        };
+        if !frame.logged {
+            return;
+        }
+        let label = &self.labels[frame.id];
+        let args: Vec<_> = label.args.iter().zip(frame.values.iter())
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        let line = match frame.values.get(label.args.len()) {
+            Some(result) => format!("[{:.6}s] {}({}) = {}", self.time, label.name, args.join(", "), result),
+            None => format!("[{:.6}s] {}({})", self.time, label.name, args.join(", ")),
+        };
+        // tracing is best effort, a closed output should not stop the program
+        let _ = writeln!(self.out, "{}", line);
+    }
+}
+
+thread_local!(static CURRENT_TRACER: Cell<*mut Tracer> = Cell::new(ptr::null_mut()));
+
+/// Runs `f` with calls to traced functions on this thread logged by `tracer`.
+pub fn with_tracer<F, R>(tracer: &mut Tracer, f: F) -> R where F: FnOnce() -> R {
+    let prev = CURRENT_TRACER.with(|cur| {
+        let prev = cur.get();
+        cur.set(tracer);
+        prev
+    });
+    let res = f();
+    CURRENT_TRACER.with(|cur| cur.set(prev));
+    res
+}
+
+fn with_current_tracer<F>(f: F) where F: FnOnce(&mut Tracer) {
+    CURRENT_TRACER.with(|cur| {
+        let tracer = cur.get();
+        if !tracer.is_null() {
+            f(unsafe { &mut *tracer });
+        }
+    });
+}
+
+/// Called by generated code on entry to a traced function, before its arguments are traced.
+pub extern fn trace_enter(id: usize) {
+    with_current_tracer(|t| t.enter(id));
+}
+
+/// Called by generated code with each numeric argument or result of a traced function.
+pub extern fn trace_number(_: usize, value: Number) {
+    with_current_tracer(|t| t.value(value.to_string()));
+}
+
+/// Called by generated code with each boolean argument or result of a traced function. The value
+/// is passed as an LLVM `i1`.
+pub extern fn trace_boolean(_: usize, value: u8) {
+    with_current_tracer(|t| t.value((value & 1 == 1).to_string()));
+}
+
+/// Called by generated code for arguments and results which cannot be shown, such as functions.
+pub extern fn trace_opaque(_: usize) {
+    with_current_tracer(|t| t.value("<function>".to_string()));
+}
+
+/// Called by generated code just before a traced function returns.
+pub extern fn trace_exit(_: usize) {
+    with_current_tracer(|t| t.exit());
+}
+
+/// A variable or argument whose values are recorded for the debugger, with the function it is in
+/// and where it is assigned.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DebugLabel {
+    pub name: String,
+    pub function: String,
+    pub pos: SourcePos,
+}
+
+/// The values given to variables and arguments in the last sample, in the order they were
+/// evaluated, for programs compiled with `Options::debug`.
+pub struct DebugLog {
+    labels: Vec<DebugLabel>,
+    values: Vec<(usize, String)>, // from the id of a label
+}
+
+impl DebugLog {
+    pub fn new(labels: Vec<DebugLabel>) -> DebugLog {
+        DebugLog {
+            labels: labels,
+            values: Vec::new(),
+        }
+    }
+
+    /// Each value with its label, in the order they were evaluated.
+    pub fn values(&self) -> Vec<(&DebugLabel, &str)> {
+        self.values.iter().map(|&(id, ref value)| (&self.labels[id], &value[..])).collect()
+    }
+
+    fn record(&mut self, id: usize, value: String) {
+        self.values.push((id, value));
+    }
+}
+
+thread_local!(static CURRENT_DEBUG_LOG: Cell<*mut DebugLog> = Cell::new(ptr::null_mut()));
+
+fn with_debug_log<F, R>(log: &mut DebugLog, f: F) -> R where F: FnOnce() -> R {
+    let prev = CURRENT_DEBUG_LOG.with(|cur| {
+        let prev = cur.get();
+        cur.set(log);
+        prev
+    });
+    let res = f();
+    CURRENT_DEBUG_LOG.with(|cur| cur.set(prev));
+    res
+}
+
+fn with_current_debug_log<F>(f: F) where F: FnOnce(&mut DebugLog) {
+    CURRENT_DEBUG_LOG.with(|cur| {
+        let log = cur.get();
+        if !log.is_null() {
+            f(unsafe { &mut *log });
+        }
+    });
+}
+
+/// Called by generated code with the value of a numeric variable or argument, by its label.
+pub extern fn debug_number(id: usize, value: Number) {
+    with_current_debug_log(|log| log.record(id, value.to_string()));
+}
+
+/// Called by generated code with the value of a boolean variable or argument, as an LLVM `i1`.
+pub extern fn debug_boolean(id: usize, value: u8) {
+    with_current_debug_log(|log| log.record(id, (value & 1 == 1).to_string()));
+}
+
+/// Call counts and timings of each instrumented function, identified by the index of its label.
+/// Shared between the thread running the program and whoever reports on it.
+pub struct Profiler {
+    labels: Vec<String>,
+    calls: Vec<AtomicUsize>,
+    total_ns: Vec<AtomicUsize>,
+    self_ns: Vec<AtomicUsize>,
+}
+
+impl Profiler {
+    pub fn new(labels: Vec<String>) -> Profiler {
+        let len = labels.len();
+        Profiler {
+            labels: labels,
+            calls: (0..len).map(|_| AtomicUsize::new(0)).collect(),
+            total_ns: (0..len).map(|_| AtomicUsize::new(0)).collect(),
+            self_ns: (0..len).map(|_| AtomicUsize::new(0)).collect(),
+        }
+    }
+
+    fn record(&self, id: usize, total_ns: usize, self_ns: usize) {
+        self.calls[id].fetch_add(1, Ordering::Relaxed);
+        self.total_ns[id].fetch_add(total_ns, Ordering::Relaxed);
+        self.self_ns[id].fetch_add(self_ns, Ordering::Relaxed);
+    }
+
+    /// A table of all functions which were called, sorted by the time spent in the function
+    /// itself.
+    pub fn report(&self) -> String {
+        let mut rows: Vec<_> = (0..self.labels.len())
+            .map(|i| (self.self_ns[i].load(Ordering::Relaxed),
+                      self.total_ns[i].load(Ordering::Relaxed),
+                      self.calls[i].load(Ordering::Relaxed),
+                      &self.labels[i]))
+            .filter(|row| row.2 > 0)
+            .collect();
+        rows.sort_by(|a, b| b.0.cmp(&a.0));
+        let mut out = format!("{:>12} {:>12} {:>12}  {}\n", "self ms", "total ms", "calls", "function");
+        for (self_ns, total_ns, calls, label) in rows {
+            out.push_str(&format!("{:>12.3} {:>12.3} {:>12}  {}\n",
+                                  self_ns as f64 / 1e6, total_ns as f64 / 1e6, calls, label));
+        }
+        out
+    }
+}
+
+struct ProfileFrame {
+    id: usize,
+    start: Instant,
+    child_ns: usize,
+}
+
+thread_local!(static CURRENT_PROFILER: Cell<*const Profiler> = Cell::new(ptr::null()));
+thread_local!(static PROFILE_STACK: RefCell<Vec<ProfileFrame>> = RefCell::new(Vec::new()));
+
+/// Runs `f` with calls to instrumented functions on this thread recorded by `profiler`.
+pub fn with_profiler<F, R>(profiler: &Profiler, f: F) -> R where F: FnOnce() -> R {
+    let prev = CURRENT_PROFILER.with(|cur| {
+        let prev = cur.get();
+        cur.set(profiler);
+        prev
+    });
+    let res = f();
+    CURRENT_PROFILER.with(|cur| cur.set(prev));
+    res
+}
+
+/// Called by generated code on entry to an instrumented function.
+pub extern fn profile_enter(id: usize) {
+    PROFILE_STACK.with(|stack| stack.borrow_mut().push(ProfileFrame {
+        id: id,
+        start: Instant::now(),
+        child_ns: 0,
+    }));
+}
+
+/// Called by generated code just before an instrumented function returns.
+pub extern fn profile_exit(_: usize) {
+    let frame = match PROFILE_STACK.with(|stack| stack.borrow_mut().pop()) {
+        Some(frame) => frame,
+        None => return,
+    };
+    let elapsed = frame.start.elapsed();
+    let total_ns = elapsed.as_secs() as usize * 1_000_000_000 + elapsed.subsec_nanos() as usize;
+    PROFILE_STACK.with(|stack| {
+        if let Some(parent) = stack.borrow_mut().last_mut() {
+            parent.child_ns += total_ns;
+        }
+    });
+    CURRENT_PROFILER.with(|cur| {
+        let profiler = cur.get();
+        if !profiler.is_null() {
+            let self_ns = total_ns.saturating_sub(frame.child_ns);
+            unsafe { (*profiler).record(frame.id, total_ns, self_ns); }
+        }
+    });
+}
+
+/// A saved copy of everything needed to resume a program exactly where it left off.
+#[derive(Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct Snapshot {
+    pub sample_index: u64,
+    pub call_sites: usize, // of the program it was saved from
+    pub state: State,
+}
+
+impl Snapshot {
+    pub fn to_json(&self) -> String {
+        json::encode(self).unwrap()
+    }
+
+    pub fn from_json(s: &str) -> Result<Snapshot, String> {
+        json::decode(s).map_err(|e| format!("invalid snapshot: {}", e))
+    }
+}
+
+/// Supplies the time at which each sample is evaluated, so that a host such as a game loop or a
+/// DAW transport can own the clock.
+pub trait Transport: Send {
+    fn time(&mut self, sample_index: u64, sample_rate: u32) -> Number;
+}
+
+impl<F> Transport for F where F: FnMut(u64, u32) -> Number + Send {
+    fn time(&mut self, sample_index: u64, sample_rate: u32) -> Number {
+        self(sample_index, sample_rate)
+    }
+}
+
+/// A compiled entrypoint together with its runtime state. By default time advances by one sample
+/// per call to next(), but any time can be evaluated with eval() or supplied by a Transport.
+///
+/// The program refers to code owned by the compiler, so it borrows the compiler for as long as it
+/// lives. The programs of one compiler share its globals, so only one of them may run at a time,
+/// and the one which runs next must be given them with reset_caches().
+///
+/// Everything the program needs is allocated when it is created, so that rendering with next() or
+/// fill() does not allocate unless probes, tracing or profiling are enabled. The exception is the
+/// memory of an intrinsic which is not called in the first sample, which is allocated the first
+/// time it is called.
+pub struct Program<'c> {
+    init_fn: extern fn(()),
+    main_fn: extern fn(Number) -> Number,
+    main_channels: usize,
+    buses_fn: extern fn(()),
+    reset_caches_fn: extern fn(()),
+    bus_inputs: BusInputs,
+    outputs: Vec<Number>, // each channel of the last sample
+    inputs: Vec<Input>,
+    input_values: Vec<Number>, // from the index of an input
+    params: Vec<(String, Number)>,
+    param_values: Vec<Number>, // the smoothed value of each param
+    param_smoothing: Vec<Number>, // in milliseconds
+    param_coefficients: Vec<Number>,
+    automation: Option<Automation>,
+    tempo: TempoMap,
+    builtins: Builtins,
+    channel: u32,
+    state: State,
+    sample_index: u64,
+    transport: Option<Box<Transport>>,
+    profiler: Option<Arc<Profiler>>,
+    filename: String,
+    source_map: SourceMap,
+    strings: Arc<Vec<String>>,
+    probe_names: Vec<String>,
+    probes: Option<ProbeLog>,
+    tracer: Option<Tracer>,
+    debug_log: Option<DebugLog>,
+    failure: Option<String>, // why the program can't go on, see failure()
+    compiler: PhantomData<&'c ()>, // the compiler this was instantiated from
+}
+
+impl<'c> Program<'c> {
+    /// Instantiates the entrypoint with the given name. An entrypoint defined with
+    /// define_entrypoint() must take only `time`, one declared with declare_entrypoint() is given
+    /// its inputs.
+    pub fn new(compiler: &'c Compiler, entrypoint: &str, sample_rate: u32) -> Option<Program<'c>> {
+        let inputs = compiler.entrypoint_inputs(entrypoint);
+        let main_fn = match inputs {
+            Some(_) => unsafe { compiler.get_fn(&format!("{}{}", entrypoint, ENTRY_FN_SUFFIX)) },
+            None => unsafe { compiler.get_fn(entrypoint) },
+        };
+        let main_fn = match main_fn {
+            Some(f) => f,
+            None => return None,
+        };
+        let inputs = inputs.unwrap_or(vec![Input::Time]);
+        let main_channels = compiler.entrypoint_channels(entrypoint);
+        let mut program = Program {
+            init_fn: compiler.get_init_fn(),
+            main_fn: main_fn,
+            main_channels: main_channels,
+            buses_fn: unsafe { compiler.get_fn(BUSES_FN_NAME).unwrap() },
+            reset_caches_fn: unsafe { compiler.get_fn(RESET_CACHES_FN_NAME).unwrap() },
+            bus_inputs: BusInputs::new(&compiler.bus_names(), &compiler.context().strings.borrow()),
+            outputs: vec![0.0; cmp::max(main_channels, compiler.bus_channels())],
+            params: inputs.iter().filter_map(|x| match *x {
+                Input::Param(ref name, default, _) => Some((name.clone(), default)),
+                _ => None,
+            }).collect(),
+            param_smoothing: inputs.iter().filter_map(|x| match *x {
+                Input::Param(_, _, time_ms) => Some(time_ms),
+                _ => None,
+            }).collect(),
+            param_values: Vec::new(),
+            param_coefficients: Vec::new(),
+            input_values: vec![0.0; inputs.len()],
+            inputs: inputs,
+            automation: None,
+            tempo: compiler.context().tempo.borrow().clone().unwrap_or(TempoMap::constant(120.0)),
+            builtins: Builtins::new(compiler),
+            channel: 0,
+            state: State::new(sample_rate),
+            sample_index: 0,
+            transport: None,
+            profiler: if compiler.context().options.borrow().profile {
+                Some(Arc::new(Profiler::new(compiler.profile_labels())))
+            } else {
+                None
+            },
+            filename: compiler.context().filename().to_string(),
+            source_map: compiler.source_map(),
+            strings: Arc::new(compiler.context().strings.borrow().iter().map(|x| x.to_string()).collect()),
+            probe_names: compiler.context().probes.borrow().iter().map(|x| x.to_string()).collect(),
+            probes: None,
+            tracer: {
+                let options = compiler.context().options.borrow();
+                if options.trace.is_empty() {
+                    None
+                } else {
+                    Some(Tracer::new(compiler.trace_labels(), options.trace_interval, Box::new(io::stdout())))
+                }
+            },
+            debug_log: if compiler.context().options.borrow().debug {
+                Some(DebugLog::new(compiler.debug_labels()))
+            } else {
+                None
+            },
+            failure: None,
+            compiler: PhantomData,
+        };
+        let params = program.params.len();
+        program.param_values = vec![0.0; params];
+        program.param_coefficients = vec![0.0; params];
+        let sites = program.source_map.call_site_count();
+        program.state.reserve_sites(sites);
+        program.state.set_strings(program.strings.clone());
+        program.state.set_buffers(compiler.context().buffers.borrow().iter().map(SampleBuffer::new).collect());
+        program.state.set_impulses(compiler.context().impulses.borrow().clone());
+        program.reset();
+        program.prime();
+        Some(program)
+    }
+
+    // Evaluates the first sample once and then resets, so that the memory of every intrinsic
+    // called in it is allocated before the program is handed to an audio thread. Nothing is
+    // profiled, traced or recorded.
+    fn prime(&mut self) {
+        let (main_fn, buses_fn, main_channels) = (self.main_fn, self.buses_fn, self.main_channels);
+        self.set_inputs(0.0);
+        {
+            let state = &mut self.state;
+            let outputs = &mut self.outputs;
+            let bus_inputs = &mut self.bus_inputs;
+            with_bus_inputs(bus_inputs, || with_outputs(outputs, || with_inputs(&self.input_values, || {
+                with_state(state, || eval_mix(main_fn, buses_fn, main_channels, 0.0))
+            })));
+        }
+        self.reset();
+    }
+
+    // Fills in the value of each input of the entrypoint at the given time.
+    fn set_inputs(&mut self, time: Number) {
+        let mut params = self.param_values.iter();
+        for (input, value) in self.inputs.iter().zip(self.input_values.iter_mut()) {
+            *value = match *input {
+                Input::Time => time,
+                Input::SampleRate => self.state.sample_rate as Number,
+                Input::Beat => self.tempo.beat_at(time),
+                Input::Bar => self.tempo.beat_at(time) / BEATS_PER_BAR,
+                // params are kept in the order of the inputs
+                Input::Param(..) => *params.next().unwrap(),
+            };
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.state.sample_rate
+    }
+    /// Globals computed from `sample_rate` keep the rate the program was last reset at.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.state.sample_rate = sample_rate;
+        self.state.allocate_buffers();
+        self.state.allocate_impulses();
+        for (a, &time_ms) in self.param_coefficients.iter_mut().zip(self.param_smoothing.iter()) {
+            *a = smooth::coefficient(time_ms, sample_rate);
+        }
+    }
+
+    /// The value of `channel`, for hosts which evaluate the program once per output channel.
+    /// Defaults to 0.
+    pub fn channel(&self) -> u32 {
+        self.channel
+    }
+    pub fn set_channel(&mut self, channel: u32) {
+        self.channel = channel;
+    }
+
+    /// The parameters of the entrypoint with their current values, in the order of its arguments.
+    pub fn params(&self) -> &[(String, Number)] {
+        &self.params
+    }
+
+    /// Changes a parameter of the entrypoint from the next sample on. A smoothed parameter
+    /// follows the change gradually, one step each time next() is called, and jumps to it when
+    /// the program is reset. Returns false if the entrypoint has no such parameter.
+    pub fn set_param(&mut self, name: &str, value: Number) -> bool {
+        match self.params.iter().position(|x| x.0 == name) {
+            Some(index) => {
+                self.set_param_at(index, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the parameter at the given index of params() has its own smoothing time, from a
+    /// default which calls `smooth`. Panics if there is no such parameter.
+    pub fn param_smoothed(&self, index: usize) -> bool {
+        self.param_smoothing[index] > 0.0
+    }
+
+    /// Changes the parameter at the given index of params(). Panics if there is no such parameter.
+    pub fn set_param_at(&mut self, index: usize, value: Number) {
+        self.params[index].1 = value;
+        if self.param_smoothing[index] <= 0.0 {
+            self.param_values[index] = value;
+        }
+    }
+
+    /// Changes parameters as scheduled by `automation` while the program runs with next(). Fails
+    /// if it automates a parameter the entrypoint does not have.
+    pub fn set_automation(&mut self, automation: Automation) -> Result<(), String> {
+        let mut automation = automation;
+        try!(automation.bind(&self.params));
+        self.automation = Some(automation);
+        Ok(())
+    }
+
+    /// The tempo in beats per minute at the start, which defaults to 120.
+    pub fn tempo(&self) -> Number {
+        self.tempo.bpm_at(0.0)
+    }
+    /// Replaces the tempo map with one which stays at the given tempo.
+    pub fn set_tempo(&mut self, tempo: Number) {
+        self.tempo = TempoMap::constant(tempo);
+    }
+
+    /// The tempo map `beat` and `bar` follow, which is the one the program declares, if any.
+    pub fn tempo_map(&self) -> &TempoMap {
+        &self.tempo
+    }
+    pub fn set_tempo_map(&mut self, tempo: TempoMap) {
+        self.tempo = tempo;
+    }
+
+    /// The index of the next sample to be rendered by next().
+    pub fn sample_index(&self) -> u64 {
+        self.sample_index
+    }
+    pub fn seek(&mut self, sample_index: u64) {
+        self.sample_index = sample_index;
+    }
+
+    /// Evaluates and discards samples until the next is `sample_index`, so that everything which
+    /// keeps memory is where it would be had the program played up to there. Starting from a
+    /// state loaded with load_state() saves evaluating what came before it. Probes are not
+    /// recorded on the way.
+    pub fn fast_forward(&mut self, sample_index: u64) {
+        let probes = self.probes.take();
+        while self.sample_index < sample_index {
+            self.next();
+        }
+        self.probes = probes;
+    }
+
+    /// Uses `transport` to decide the time of each sample instead of the sample index.
+    pub fn set_transport<T>(&mut self, transport: T) where T: Transport + 'static {
+        self.transport = Some(Box::new(transport));
+    }
+    pub fn clear_transport(&mut self) {
+        self.transport = None;
+    }
+
+    /// The profiler recording this program, if it was compiled with profiling enabled.
+    pub fn profiler(&self) -> Option<Arc<Profiler>> {
+        self.profiler.clone()
+    }
+
+    /// Starts writing the values passed to `probe` to `out` as CSV, with a row for each sample
+    /// rendered by next(). Rows stop after `max_samples` if given.
+    pub fn record_probes<W>(&mut self, out: W, max_samples: Option<u64>) -> io::Result<()>
+            where W: Write + Send + 'static {
+        let probes = try!(ProbeLog::new(Some(Box::new(out)), &self.probe_names, &self.strings[..], max_samples));
+        self.probes = Some(probes);
+        Ok(())
+    }
+
+    /// Collects the values passed to `probe` without writing them anywhere, so they can be read
+    /// with probe_values().
+    pub fn watch_probes(&mut self) {
+        if self.probes.is_none() {
+            self.probes = ProbeLog::new(None, &self.probe_names, &self.strings[..], None).ok();
+        }
+    }
+
+    /// The name of each probe with the value it had in the last sample rendered by next(), if
+    /// probes are being watched or recorded and it was evaluated.
+    pub fn probe_values(&self) -> Vec<(&str, Option<Number>)> {
+        let last = self.probes.as_ref().map(|x| x.last_values());
+        self.probe_names.iter().enumerate()
+            .map(|(i, name)| (&name[..], last.and_then(|x| x[i])))
+            .collect()
+    }
+
+    /// The values given to variables and arguments in the last sample, in the order they were
+    /// evaluated. Empty unless the program was compiled with `Options::debug`.
+    pub fn debug_values(&self) -> Vec<(&DebugLabel, &str)> {
+        self.debug_log.as_ref().map_or(Vec::new(), |x| x.values())
+    }
+
+    /// Maps the code of the program back to its source.
+    pub fn source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
+
+    /// Sends the log of traced functions to `out` instead of standard output. Does nothing if the
+    /// program was compiled without tracing.
+    pub fn set_trace_output<W>(&mut self, out: W) where W: Write + Send + 'static {
+        if let Some(ref mut tracer) = self.tracer {
+            tracer.out = Box::new(out);
+        }
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+    pub fn state_mut(&mut self) -> &mut State {
+        &mut self.state
+    }
+
+    /// Plays a MIDI note number at a velocity from 0 to 1 on the next free voice, see poly.rs.
+    pub fn note_on(&mut self, note: Number, velocity: Number) {
+        self.state.voices.note_on(note, velocity);
+    }
+
+    pub fn note_off(&mut self, note: Number) {
+        self.state.voices.note_off(note);
+    }
+
+    /// Seeds the random number generator and restarts the program.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.state.set_seed(seed);
+        self.reset();
+    }
+
+    /// Returns the program to the state it was in when it was created.
+    pub fn reset(&mut self) {
+        self.failure = None;
+        self.state.reset();
+        self.bus_inputs.reset();
+        self.sample_index = 0;
+        let sample_rate = self.state.sample_rate;
+        self.set_sample_rate(sample_rate);
+        for (value, param) in self.param_values.iter_mut().zip(self.params.iter()) {
+            *value = param.1;
+        }
+        self.builtins.set(self.state.sample_rate, self.channel, 0);
+        self.reset_caches();
+        let init_fn = self.init_fn;
+        with_state(&mut self.state, || init_fn(()));
+        self.check_asserts();
+    }
+
+    /// Empties the caches of memoized functions and hoisted expressions, which may hold what
+    /// another program of the same compiler computed.
+    pub fn reset_caches(&mut self) {
+        (self.reset_caches_fn)(());
+    }
+
+    // Records the first assert which failed, which can only happen when the program was compiled
+    // with checked asserts.
+    fn check_asserts(&mut self) {
+        if self.failure.is_some() {
+            return;
+        }
+        if let Some(failed) = self.state.failed_asserts().first() {
+            let failure = AssertFailure::new(failed, &self.source_map, &self.strings[..]);
+            self.failure = Some(format!("{}: {}", self.filename, failure));
+        }
+    }
+
+    // Stops recording probes which couldn't be written, which the program can't go on without.
+    fn check_probes(&mut self, written: io::Result<()>) {
+        if let Err(e) = written {
+            self.probes = None;
+            if self.failure.is_none() {
+                self.failure = Some(format!("couldn't write probes: {}", e));
+            }
+        }
+    }
+
+    /// Why the program can't go on, if it can't: the first checked assert which failed, or probes
+    /// which couldn't be written. Samples can still be evaluated, but whoever is rendering them
+    /// should stop and report this. Cleared by reset().
+    pub fn failure(&self) -> Option<&str> {
+        self.failure.as_ref().map(|x| &x[..])
+    }
+
+    /// Captures the current state so it can be restored later with load_state().
+    pub fn save_state(&self) -> Snapshot {
+        Snapshot {
+            sample_index: self.sample_index,
+            call_sites: self.source_map.call_site_count(),
+            state: self.state.clone(),
+        }
+    }
+
+    /// Resumes from a snapshot. Memory is matched up by the number of each call site, so the
+    /// snapshot may come from a different version of the source only if it makes the same calls
+    /// in the same order, such as one with other constants. Fails, leaving the program as it was,
+    /// if the snapshot has a different number of call sites.
+    pub fn load_state(&mut self, snapshot: &Snapshot) -> Result<(), String> {
+        let call_sites = self.source_map.call_site_count();
+        if snapshot.call_sites != call_sites {
+            return Err(format!("the snapshot was saved from a program with {} call sites, but this one has {}",
+                               snapshot.call_sites, call_sites));
+        }
+        self.sample_index = snapshot.sample_index;
+        self.state = snapshot.state.clone();
+        self.state.reserve_sites(call_sites);
+        self.state.set_strings(self.strings.clone());
+        Ok(())
+    }
+
+    /// The number of channels of each sample, which is 1 unless the entrypoint or a bus returns a
+    /// signal of several channels.
+    pub fn channels(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// Each channel of the sample last evaluated.
+    pub fn frame(&self) -> &[Number] {
+        &self.outputs
+    }
+
+    /// Evaluates the entrypoint at the given time without advancing the sample index, returning
+    /// its first channel. If a checked assert fails, failure() says which.
+    pub fn eval(&mut self, time: Number) -> Number {
+        let (main_fn, buses_fn, main_channels) = (self.main_fn, self.buses_fn, self.main_channels);
+        self.builtins.set(self.state.sample_rate, self.channel, self.sample_index);
+        self.set_inputs(time);
+        if let Some(ref mut tracer) = self.tracer {
+            tracer.set_sample(self.sample_index, time);
+        }
+        let res = {
+            let state = &mut self.state;
+            let profiler = &self.profiler;
+            let probes = &mut self.probes;
+            let inputs = &self.input_values;
+            let outputs = &mut self.outputs;
+            let bus_inputs = &mut self.bus_inputs;
+            let eval = move || eval_mix(main_fn, buses_fn, main_channels, time);
+            let run = move || with_bus_inputs(bus_inputs, || with_outputs(outputs, || with_inputs(inputs, || {
+                match *profiler {
+                    Some(ref profiler) => with_profiler(profiler, || with_state(state, eval)),
+                    None => with_state(state, eval),
+                }
+            })));
+            let run = move || match *probes {
+                Some(ref mut probes) => with_probes(probes, run),
+                None => run(),
+            };
+            let tracer = &mut self.tracer;
+            let run = move || match *tracer {
+                Some(ref mut tracer) => with_tracer(tracer, run),
+                None => run(),
+            };
+            match self.debug_log {
+                Some(ref mut log) => {
+                    log.values.clear();
+                    with_debug_log(log, run)
+                }
+                None => run(),
+            }
+        };
+        self.state.process_blocks();
+        self.check_asserts();
+        res
+    }
+
+    /// Evaluates the next sample and advances the sample index. Returns its first channel, and
+    /// the others can be read with frame().
+    pub fn next(&mut self) -> Number {
+        let time = match self.transport {
+            Some(ref mut t) => t.time(self.sample_index, self.state.sample_rate),
+            None => self.sample_index as Number / self.state.sample_rate as Number,
+        };
+        if let Some(mut automation) = self.automation.take() {
+            automation.apply(time, |index, value| self.set_param_at(index, value));
+            self.automation = Some(automation);
+        }
+        for ((value, param), &a) in self.param_values.iter_mut().zip(self.params.iter())
+                                                         .zip(self.param_coefficients.iter()) {
+            *value = smooth::smooth(*value, param.1, a);
+        }
+        // `sample_index` is the index of the sample being evaluated
+        let res = self.eval(time);
+        let written = match self.probes {
+            Some(ref mut probes) => probes.end_sample(self.sample_index, time),
+            None => Ok(()),
+        };
+        self.check_probes(written);
+        self.sample_index += 1;
+        res
+    }
+
+    /// Fills the buffer with consecutive samples, with the channels of each interleaved. Non-finite
+    /// samples are replaced by the previous sample of the same channel.
+    pub fn fill<S: Sample>(&mut self, buffer: &mut [S]) {
+        for i in 0..buffer.len() / self.channels() {
+            self.next();
+            self.write_frame(buffer, i);
+        }
+        let written = match self.probes {
+            Some(ref mut probes) => probes.flush(),
+            None => Ok(()),
+        };
+        self.check_probes(written);
+    }
+
+    /// Writes the channels of the sample last evaluated to the frame at the given index of an
+    /// interleaved buffer, as fill() does.
+    pub fn write_frame<S: Sample>(&self, buffer: &mut [S], index: usize) {
+        let channels = self.channels();
+        for (channel, &value) in self.outputs.iter().enumerate() {
+            let i = index * channels + channel;
+            buffer[i] = S::from_number(value);
+            if !buffer[i].to_number().is_finite() && index > 0 {
+                buffer[i] = buffer[i - channels];
+            }
+        }
+    }
+
+}
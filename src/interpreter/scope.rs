@@ -1,130 +1,204 @@
 use super::ident::Identifier;
 
-use std::collections::{HashMap};
 use vec_map::VecMap;
 
-/// Represents a single scope, translates to source code locations.
+/// Represents a single scope, translates to source code locations. Scope 0 is the global scope.
 pub type ScopeId = usize;
 
-/// Holds an exact location within a ScopedTable.
-#[derive(Clone, Debug)]
-pub struct ScopePos {
-    pub scope: Vec<ScopeId>,
-    pub scope_lengths: Vec<usize>,
-}
+/// A node in the tree of scopes held by a ScopedTable.
+pub type ScopeRef = usize;
+
+const ROOT: ScopeRef = 0;
 
 #[derive(Clone, Debug)]
 pub struct Symbol<T> where T: Clone {
-    pub scope: ScopePos,
+    /// The scope the symbol was defined in.
+    pub scope: ScopeRef,
     pub val: T,
 }
 
+struct Scope<T> where T: Clone {
+    id: ScopeId,
+    parent: Option<ScopeRef>,
+    children: Vec<ScopeRef>,
+    symbols: VecMap<Symbol<T>>,
+}
+
+// Scopes which are entered together and left together by pop(): the innermost one, and each of
+// its ancestors up to but not including `stop`. A group without a stop reaches the global scope.
+struct Group {
+    innermost: ScopeRef,
+    stop: Option<ScopeRef>,
+}
+
+/// A tree of lexical scopes, each holding the symbols defined in it. A scope's parent is the
+/// scope it was entered from, and the same scope is reused whenever it is entered from the same
+/// parent, so symbols persist for later lookups such as calls to a function defined inside it.
+///
+/// Defining a symbol opens a new scope for the rest of the enclosing one, so a symbol is only
+/// visible to code after its definition. The scopes currently visible are kept as a stack of
+/// groups, searched from the top.
 pub struct ScopedTable<T> where T: Clone {
-    symbols: HashMap<ScopeId, VecMap<Symbol<T>>>,
-    scope: Vec<ScopeId>,
-    scope_lengths: Vec<usize>,
+    scopes: Vec<Scope<T>>,
+    groups: Vec<Group>,
 }
 
 impl<T> ScopedTable<T> where T: Clone {
     pub fn new() -> ScopedTable<T> {
-        let mut symbols = HashMap::new();
-        symbols.insert(0, VecMap::new());
         ScopedTable {
-            symbols: symbols,
-            scope: vec![0],
-            scope_lengths: vec![0],
+            scopes: vec![Scope {
+                id: 0,
+                parent: None,
+                children: Vec::new(),
+                symbols: VecMap::new(),
+            }],
+            groups: vec![Group {
+                innermost: ROOT,
+                stop: None,
+            }],
+        }
+    }
+
+    // Finds the child of a scope with the given id, creating it if it has not been entered yet.
+    fn child(&mut self, parent: ScopeRef, id: ScopeId) -> ScopeRef {
+        if let Some(&child) = self.scopes[parent].children.iter().find(|&&x| self.scopes[x].id == id) {
+            return child;
         }
+        let child = self.scopes.len();
+        self.scopes.push(Scope {
+            id: id,
+            parent: Some(parent),
+            children: Vec::new(),
+            symbols: VecMap::new(),
+        });
+        self.scopes[parent].children.push(child);
+        child
     }
 
-    /// Push a list of scopes which will all be popped off at once when pop() is called.
-    pub fn push_scope(&mut self, scopes: &[ScopeId]) {
-        self.scope.push_all(scopes);
-        self.scope_lengths.push(scopes.len());
+    /// The innermost scope currently visible.
+    pub fn current(&self) -> ScopeRef {
+        self.groups.last().unwrap().innermost
     }
 
-    /// Push a single scope, which will be popped off when pop() is called.
+    /// Enters a scope nested in the current one, which will be left when pop() is called.
     pub fn push(&mut self, scope: ScopeId) {
-        self.scope.push(scope);
-        self.scope_lengths.push(1);
-        self.symbols.entry(scope).or_insert(VecMap::new());
+        let current = self.current();
+        let child = self.child(current, scope);
+        self.groups.push(Group {
+            innermost: child,
+            stop: Some(current),
+        });
     }
 
-    /// Push a single scope onto the end of the current group.
-    /// It will be popped off along with the rest of the group when pop() is called.
+    /// Enters a scope nested in the current one, which will be left along with the rest of the
+    /// current group when pop() is called.
     pub fn push_to_scope(&mut self, scope: ScopeId) {
-        self.scope.push(scope);
-        *self.scope_lengths.last_mut().unwrap() += 1;
-        self.symbols.entry(scope).or_insert(VecMap::new());
+        let current = self.current();
+        let child = self.child(current, scope);
+        self.groups.last_mut().unwrap().innermost = child;
     }
 
-    /// Pops the topmost group of scopes from the stack.
-    pub fn pop(&mut self) {
-        assert!(self.scope_lengths.len() > 1, "tried to leave the outermost scope!");
-        for _ in 0..self.scope_lengths.pop().unwrap() {
-            self.scope.pop().unwrap();
-        }
+    /// Makes a scope and all of its ancestors visible until pop() is called, such as the scope a
+    /// function was defined in while its body is checked. They are searched before the scopes
+    /// which were visible already.
+    pub fn enter(&mut self, scope: ScopeRef) {
+        self.groups.push(Group {
+            innermost: scope,
+            stop: None,
+        });
     }
 
-    /// Returns the current stack of scopes.
-    pub fn get_scope_pos(&self) -> ScopePos {
-        ScopePos {
-            scope: self.scope.clone(),
-            scope_lengths: self.scope_lengths.clone(),
-        }
+    /// Leaves the scopes entered by the last call to push() or enter().
+    pub fn pop(&mut self) {
+        assert!(self.groups.len() > 1, "tried to leave the outermost scope!");
+        self.groups.pop();
     }
 
-    /// Sets a source identifer with the given scope identifier to a value.
+    /// Sets a source identifer with the given scope identifier to a value. Values set in scope 0
+    /// are global, and visible from everywhere.
     pub fn set_val(&mut self, id: Identifier, scope: ScopeId, val: T) {
-        self.push_to_scope(scope);
-        let scope_pos = self.get_scope_pos();
-        let id_map = self.symbols.get_mut(&scope).unwrap();
-        id_map.insert(id, Symbol {
-                scope: scope_pos,
-                val: val,
-            });
+        let target = if scope == 0 {
+            ROOT
+        } else {
+            self.push_to_scope(scope);
+            self.current()
+        };
+        self.scopes[target].symbols.insert(id, Symbol {
+            scope: target,
+            val: val,
+        });
     }
 
-    /// Searches backwards through the scope stack until a symbol with the given identifier is
-    /// found, and returns the symbol.
-    pub fn get_symbol(&self, id: Identifier) -> Option<&Symbol<T>> {
-        for block_pos in self.scope.iter().rev() {
-            let id_map = self.symbols.get(&block_pos).unwrap();
-            if let Some(symbol) = id_map.get(&id) {
-                return Some(symbol)
+    // The scopes of a group, from the innermost outwards.
+    fn group_scopes(&self, group: &Group) -> Vec<ScopeRef> {
+        let mut scopes = Vec::new();
+        let mut scope = Some(group.innermost);
+        while let Some(s) = scope {
+            if Some(s) == group.stop {
+                break;
+            }
+            scopes.push(s);
+            scope = self.scopes[s].parent;
+        }
+        scopes
+    }
+
+    // Finds the symbol visible under an identifier, along with how many groups down the stack
+    // it was found.
+    fn find(&self, id: Identifier) -> Option<(&Symbol<T>, usize)> {
+        for (depth, group) in self.groups.iter().rev().enumerate() {
+            for scope in self.group_scopes(group) {
+                if let Some(symbol) = self.scopes[scope].symbols.get(&id) {
+                    return Some((symbol, depth));
+                }
             }
         }
         None
     }
 
-    /// Searches backwards through the scope stack until a symbol with the given identifier is
-    /// found, and returns the depth down the stack it was found at.
+    /// Searches outwards from the current scope until a symbol with the given identifier is
+    /// found, and returns the symbol.
+    pub fn get_symbol(&self, id: Identifier) -> Option<&Symbol<T>> {
+        self.find(id).map(|x| x.0)
+    }
+
+    /// Searches outwards from the current scope until a symbol with the given identifier is
+    /// found, and returns how many calls to pop() would make it invisible, less one.
     pub fn get_symbol_depth(&self, id: Identifier) -> Option<usize> {
-        self.get_symbol_height(id).map(|x| self.scope_lengths.len() - x - 1)
+        self.find(id).map(|x| x.1)
     }
 
-    /// Searches backwards through the scope stack until a symbol with the given identifier is
-    /// found, and returns the height up the stack it was found at.
-    pub fn get_symbol_height(&self, id: Identifier) -> Option<usize> {
-        let mut len_iter = self.scope_lengths.iter().rev();
-        let mut len = match len_iter.next() {
-            Some(x) => *x,
-            None => return None
-        };
-        let mut count = len_iter.len();
-        for block_pos in self.scope.iter().rev() {
-            len -= 1;
-            if len == 0 {
-                len = match len_iter.next() {
-                    Some(x) => *x,
-                    None => return None
-                };
-                count -= 1;
-            }
-            let id_map = self.symbols.get(&block_pos).unwrap();
-            if let Some(_) = id_map.get(&id) {
-                return Some(count)
+    /// Every symbol visible from the current scope, innermost first. Shadowed symbols are
+    /// skipped.
+    pub fn visible_symbols(&self) -> Vec<(Identifier, &Symbol<T>)> {
+        let mut visible: Vec<(Identifier, &Symbol<T>)> = Vec::new();
+        for group in self.groups.iter().rev() {
+            for scope in self.group_scopes(group) {
+                for (id, symbol) in self.scopes[scope].symbols.iter() {
+                    if !visible.iter().any(|x| x.0 == id) {
+                        visible.push((id, symbol));
+                    }
+                }
             }
         }
-        None
+        visible
+    }
+
+    /// The source location a scope corresponds to.
+    pub fn scope_id(&self, scope: ScopeRef) -> ScopeId {
+        self.scopes[scope].id
+    }
+
+    pub fn parent(&self, scope: ScopeRef) -> Option<ScopeRef> {
+        self.scopes[scope].parent
+    }
+
+    pub fn children(&self, scope: ScopeRef) -> &[ScopeRef] {
+        &self.scopes[scope].children
+    }
+
+    /// The symbols defined directly in a scope.
+    pub fn symbols(&self, scope: ScopeRef) -> Vec<(Identifier, &Symbol<T>)> {
+        self.scopes[scope].symbols.iter().collect()
     }
 }
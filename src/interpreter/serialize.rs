@@ -0,0 +1,46 @@
+use super::common::Context;
+use super::ast::Root;
+use super::ident::Identifier;
+
+use rustc_serialize::json;
+use std::collections::BTreeMap;
+
+/// The document produced when exporting an AST. Identifiers in the tree are plain integers,
+/// so the name table is included to let external tools map them back to source names.
+#[derive(RustcEncodable)]
+struct AstDocument<'a> {
+    filename: &'a str,
+    names: BTreeMap<Identifier, String>,
+    items: &'a Root,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Format {
+    Json,
+    PrettyJson,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Format> {
+        Some(match s {
+            "json" => Format::Json,
+            "pretty-json" => Format::PrettyJson,
+            _ => return None,
+        })
+    }
+}
+
+/// Serializes the parsed AST held by the context. Should be called after parsing.
+pub fn serialize_ast<'a>(ctxt: &'a Context<'a>, format: Format) -> String {
+    let names = ctxt.names.borrow();
+    let ast = ctxt.ast.borrow();
+    let doc = AstDocument {
+        filename: ctxt.filename(),
+        names: names.iter().map(|(id, name)| (id, name.to_string())).collect(),
+        items: &*ast,
+    };
+    match format {
+        Format::Json => json::encode(&doc).unwrap(),
+        Format::PrettyJson => format!("{}", json::as_pretty_json(&doc)),
+    }
+}
@@ -0,0 +1,55 @@
+use super::runtime::CallSite;
+use super::tokens::SourcePos;
+
+/// The instructions of one generated function, in the order LLVM lists them, along with the
+/// expression each was generated for. Instructions which are not part of any expression, such as
+/// returns and profiler hooks, have no position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionMap {
+    pub name: String,
+    pub instructions: Vec<Option<SourcePos>>,
+}
+
+/// Positions for the code as it was generated, before LLVM optimized it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceMap {
+    functions: Vec<FunctionMap>,
+    call_sites: Vec<Option<SourcePos>>, // from CallSite
+}
+
+impl SourceMap {
+    pub fn new(functions: Vec<FunctionMap>, call_sites: Vec<Option<SourcePos>>) -> SourceMap {
+        SourceMap {
+            functions: functions,
+            call_sites: call_sites,
+        }
+    }
+
+    /// An empty map, for code which was generated without one.
+    pub fn empty() -> SourceMap {
+        SourceMap::new(Vec::new(), Vec::new())
+    }
+
+    pub fn functions(&self) -> &[FunctionMap] {
+        &self.functions
+    }
+
+    pub fn function(&self, name: &str) -> Option<&FunctionMap> {
+        self.functions.iter().find(|x| x.name == name)
+    }
+
+    /// The position of the expression an instruction was generated for, by the index of the
+    /// instruction within its function.
+    pub fn lookup(&self, function: &str, instruction: usize) -> Option<SourcePos> {
+        self.function(function).and_then(|x| x.instructions.get(instruction).and_then(|x| *x))
+    }
+
+    /// Where in the source a direct call to an intrinsic or builtin is.
+    pub fn call_site(&self, site: CallSite) -> Option<SourcePos> {
+        self.call_sites.get(site).and_then(|x| *x)
+    }
+
+    pub fn call_site_count(&self) -> usize {
+        self.call_sites.len()
+    }
+}
@@ -0,0 +1,84 @@
+use super::rng::Rng;
+use super::tokens::Number;
+
+/// The values of one parameter to sweep over.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Range {
+    pub param: String,
+    pub low: Number,
+    pub high: Number,
+    pub steps: usize,
+}
+
+impl Range {
+    /// Parses a range written as `name=low..high:steps`.
+    pub fn parse(s: &str) -> Result<Range, String> {
+        let invalid = || format!("expected name=low..high:steps in --param {}", s);
+        let mut split = s.splitn(2, '=');
+        let param = split.next().unwrap().trim();
+        let rest = try!(split.next().ok_or_else(&invalid));
+        let mut split = rest.splitn(2, ':');
+        let bounds = split.next().unwrap();
+        let steps = try!(split.next().and_then(|x| x.trim().parse().ok()).ok_or_else(&invalid));
+        let mut split = bounds.splitn(2, "..");
+        let low = try!(split.next().and_then(|x| x.trim().parse().ok()).ok_or_else(&invalid));
+        let high = try!(split.next().and_then(|x| x.trim().parse().ok()).ok_or_else(&invalid));
+        if param.is_empty() || steps == 0 {
+            return Err(invalid());
+        }
+        Ok(Range {
+            param: param.to_string(),
+            low: low,
+            high: high,
+            steps: steps,
+        })
+    }
+
+    /// The value at a step, from `low` at the first to `high` at the last.
+    pub fn value(&self, step: usize) -> Number {
+        if self.steps < 2 {
+            self.low
+        } else {
+            self.low + (self.high - self.low) * step as Number / (self.steps - 1) as Number
+        }
+    }
+}
+
+/// Every combination of the values of the ranges, with the last range changing fastest.
+pub fn grid(ranges: &[Range]) -> Vec<Vec<Number>> {
+    let count = ranges.iter().fold(1, |n, x| n * x.steps);
+    (0..count).map(|i| {
+        let mut rest = i;
+        let mut point: Vec<_> = ranges.iter().rev().map(|range| {
+            let step = rest % range.steps;
+            rest /= range.steps;
+            range.value(step)
+        }).collect();
+        point.reverse();
+        point
+    }).collect()
+}
+
+/// `count` combinations drawn uniformly from the ranges, the same for the same seed.
+pub fn random(ranges: &[Range], count: usize, seed: u64) -> Vec<Vec<Number>> {
+    let mut rng = Rng::new(seed);
+    (0..count).map(|_| ranges.iter().map(|x| rng.range(x.low, x.high)).collect()).collect()
+}
+
+/// The index of a sweep as CSV, with a row for each file and the parameters it was rendered with.
+pub fn index_csv(ranges: &[Range], files: &[String], points: &[Vec<Number>]) -> String {
+    let mut csv = String::from("file");
+    for range in ranges {
+        csv.push(',');
+        csv.push_str(&range.param);
+    }
+    csv.push('\n');
+    for (file, point) in files.iter().zip(points) {
+        csv.push_str(file);
+        for value in point {
+            csv.push_str(&format!(",{}", value));
+        }
+        csv.push('\n');
+    }
+    csv
+}
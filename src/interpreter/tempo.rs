@@ -0,0 +1,125 @@
+use super::common::read_file;
+use super::tokens::Number;
+
+use rustc_serialize::json;
+use std::cmp::Ordering;
+
+pub const BEATS_PER_BAR: Number = 4.0;
+
+#[derive(Clone, Debug, PartialEq, RustcDecodable)]
+pub struct Point {
+    pub time: Number,
+    pub bpm: Number,
+}
+
+/// The tempo in beats per minute over time, moving linearly from each point to the next, as given
+/// by a `tempo` declaration or `--tempo-map`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TempoMap {
+    points: Vec<Point>,
+    beats: Vec<Number>, // the beat at each point
+}
+
+impl TempoMap {
+    /// A map which stays at the same tempo throughout.
+    pub fn constant(bpm: Number) -> TempoMap {
+        TempoMap {
+            points: vec![Point { time: 0.0, bpm: bpm }],
+            beats: vec![0.0],
+        }
+    }
+
+    /// A map through the given points, which need not be in order.
+    pub fn new(points: Vec<Point>) -> Result<TempoMap, String> {
+        if points.is_empty() {
+            return Err("a tempo map needs at least one point".to_string());
+        }
+        for point in &points {
+            if !point.time.is_finite() || point.time < 0.0 {
+                return Err(format!("the time of a tempo must be a number of seconds, not {}", point.time));
+            }
+            if !point.bpm.is_finite() || point.bpm <= 0.0 {
+                return Err(format!("a tempo must be more than 0 beats per minute, not {}", point.bpm));
+            }
+        }
+        let mut points = points;
+        // stable, so that points at the same time keep their order
+        points.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal));
+        let mut beats = vec![points[0].time * points[0].bpm / 60.0];
+        for pair in points.windows(2) {
+            let last = *beats.last().unwrap();
+            beats.push(last + (pair[0].bpm + pair[1].bpm) * 0.5 * (pair[1].time - pair[0].time) / 60.0);
+        }
+        Ok(TempoMap {
+            points: points,
+            beats: beats,
+        })
+    }
+
+    pub fn from_json(s: &str) -> Result<TempoMap, String> {
+        let points: Vec<Point> = try!(json::decode(s).map_err(|e| format!("invalid tempo map: {}", e)));
+        TempoMap::new(points)
+    }
+
+    pub fn load(filename: &str) -> Result<TempoMap, String> {
+        let source = try!(read_file(filename));
+        TempoMap::from_json(&source).map_err(|e| format!("{}: {}", filename, e))
+    }
+
+    pub fn points(&self) -> &[Point] {
+        &self.points
+    }
+
+    // The last point at or before `time`, or None before the first. Along with it, how fast the
+    // tempo changes after it, in beats per minute per second.
+    fn segment(&self, time: Number) -> Option<(usize, Number)> {
+        let i = match self.points.iter().rposition(|x| x.time <= time) {
+            Some(i) => i,
+            None => return None,
+        };
+        let slope = match self.points.get(i + 1) {
+            Some(next) => (next.bpm - self.points[i].bpm) / (next.time - self.points[i].time),
+            None => 0.0,
+        };
+        Some((i, slope))
+    }
+
+    /// The tempo in beats per minute at a time in seconds.
+    pub fn bpm_at(&self, time: Number) -> Number {
+        match self.segment(time) {
+            Some((i, slope)) => self.points[i].bpm + slope * (time - self.points[i].time),
+            None => self.points[0].bpm,
+        }
+    }
+
+    /// The number of beats from the start to a time in seconds.
+    pub fn beat_at(&self, time: Number) -> Number {
+        match self.segment(time) {
+            Some((i, slope)) => {
+                let dt = time - self.points[i].time;
+                self.beats[i] + (self.points[i].bpm * dt + slope * dt * dt * 0.5) / 60.0
+            }
+            None => time * self.points[0].bpm / 60.0,
+        }
+    }
+
+    /// The time in seconds at which the given beat is reached, the inverse of beat_at().
+    pub fn time_at_beat(&self, beat: Number) -> Number {
+        let i = match self.beats.iter().rposition(|&x| x <= beat) {
+            Some(i) => i,
+            None => return beat * 60.0 / self.points[0].bpm,
+        };
+        let (bpm, beats) = (self.points[i].bpm, (beat - self.beats[i]) * 60.0);
+        let slope = match self.points.get(i + 1) {
+            Some(next) if next.time > self.points[i].time => (next.bpm - bpm) / (next.time - self.points[i].time),
+            _ => 0.0,
+        };
+        // solves bpm * dt + slope * dt^2 / 2 = beats for dt
+        let dt = if slope.abs() < 1e-12 {
+            beats / bpm
+        } else {
+            (-bpm + (bpm * bpm + 2.0 * slope * beats).sqrt()) / slope
+        };
+        self.points[i].time + dt
+    }
+}
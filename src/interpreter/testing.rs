@@ -0,0 +1,59 @@
+use super::compiler::Compiler;
+use super::ident::Identifier;
+use super::runtime::{State, AssertFailure, Builtins, with_state};
+use super::tokens::{Number, SourcePos};
+
+/// A test block, compiled to a function which takes no arguments.
+#[derive(Clone, Debug)]
+pub struct TestCase {
+    pub name: String,
+    pub ident: Identifier,
+    pub pos: SourcePos,
+}
+
+#[derive(Clone, Debug)]
+pub struct TestResult {
+    pub name: String,
+    pub pos: SourcePos,
+    /// The asserts which failed, in the order they were evaluated.
+    pub failures: Vec<AssertFailure>,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Runs every test block of a program compiled with the `test` option. Each test starts from
+/// freshly initialized state, so tests cannot affect each other.
+pub fn run_tests(compiler: &Compiler, sample_rate: u32, seed: u64) -> Vec<TestResult> {
+    let ctxt = compiler.context();
+    let init_fn = compiler.get_init_fn();
+    let source_map = compiler.source_map();
+    let builtins = Builtins::new(compiler);
+    let strings = ctxt.strings.borrow();
+    let mut results = Vec::new();
+    for test in ctxt.tests.borrow().iter() {
+        let name = ctxt.lookup_name(test.ident);
+        let test_fn: extern fn(()) -> Number = match unsafe { compiler.get_fn(&name) } {
+            Some(f) => f,
+            None => panic!("test `{}` was not compiled", test.name),
+        };
+        let mut state = State::new(sample_rate);
+        state.set_seed(seed);
+        builtins.set(sample_rate, 0, 0);
+        with_state(&mut state, || {
+            init_fn(());
+            test_fn(());
+        });
+        results.push(TestResult {
+            name: test.name.clone(),
+            pos: test.pos,
+            failures: state.failed_asserts().iter()
+                           .map(|x| AssertFailure::new(x, &source_map, &strings[..]))
+                           .collect(),
+        });
+    }
+    results
+}
@@ -1,5 +1,6 @@
 use super::ident::Identifier;
 
+use rustc_serialize::{Encodable, Encoder};
 use std::fmt;
 use std::ops::Deref;
 
@@ -12,11 +13,12 @@ pub enum Token {
     Ident(Identifier),
     Const(Number),
     Boolean(Boolean),
+    Str(usize), // index into the string literals of the context
     Operator(Operator),
     Symbol(Symbol),
 }
 
-#[derive(Debug, Copy, PartialEq, Clone)]
+#[derive(Debug, Copy, PartialEq, Eq, Hash, Clone, RustcEncodable)]
 pub enum Operator {
     Add,
     Sub,
@@ -35,6 +37,10 @@ pub enum Operator {
     Xor,
     GreaterEqual,
     LessEqual,
+    BitAnd,
+    BitOr,
+    ShiftLeft,
+    ShiftRight,
 }
 
 #[derive(PartialEq)]
@@ -64,6 +70,10 @@ impl Operator {
             "&&" => And,
             "||" => Or,
             "^^" => Xor,
+            "&" => BitAnd,
+            "|" => BitOr,
+            "<<" => ShiftLeft,
+            ">>" => ShiftRight,
             _ => return None,
         })
     }
@@ -73,6 +83,9 @@ impl Operator {
             And | Or | Xor => 10,
             Equal | NotEqual | ApproxEqual => 20,
             Less | Greater | GreaterEqual | LessEqual => 30,
+            BitOr => 33,
+            BitAnd => 35,
+            ShiftLeft | ShiftRight => 37,
             Add | Sub => 40,
             Mul | Div | Mod => 50,
             Not | Exp => 60,
@@ -109,6 +122,7 @@ pub enum Symbol {
     If,
     Else,
     At,
+    Arrow,
     LeftBracket(Bracket),
     RightBracket(Bracket),
 }
@@ -127,6 +141,7 @@ impl Symbol {
             "if" => If,
             "else" => Else,
             "@" => At,
+            "->" => Arrow,
             "(" => LeftBracket(Bracket::Round),
             ")" => RightBracket(Bracket::Round),
             "{" => LeftBracket(Bracket::Curly),
@@ -152,6 +167,7 @@ impl fmt::Display for Symbol {
             If => "if",
             Else => "else",
             At => "@",
+            Arrow => "->",
             LeftBracket(Bracket::Round) => "(",
             RightBracket(Bracket::Round) => ")",
             LeftBracket(Bracket::Curly) => "{",
@@ -172,6 +188,7 @@ impl fmt::Display for Token {
             Const(x) => write!(f, "{}", x),
             Symbol(x) => write!(f, "{}", x),
             Boolean(x) => write!(f, "{}", x),
+            Str(x) => write!(f, "Str({})", x),
         }
     }
 }
@@ -183,11 +200,15 @@ pub enum Bracket {
     Curly,
 }
 
-#[derive(Copy, Clone, PartialEq)]
+/// Identifies one of the files a program is made of, by its position in `SourceFiles`.
+pub type FileId = usize;
+
+#[derive(Copy, Clone, PartialEq, RustcEncodable)]
 pub struct SourcePos {
+    pub file: FileId,
     pub line: isize,
-    pub column: usize,
-    pub index: usize,
+    pub column: usize, // in characters
+    pub index: usize, // in bytes, counted across every file so that it is unique
     pub line_index: usize, //index of first character of line
 }
 
@@ -195,16 +216,22 @@ static mut anon_count: isize = 0;
 
 impl SourcePos {
     pub fn new() -> SourcePos {
+        SourcePos::start_of(0, 0)
+    }
+    /// The first position in a file, which starts at `offset` among all files.
+    pub fn start_of(file: FileId, offset: usize) -> SourcePos {
         SourcePos {
+            file: file,
             line: 1,
             column: 1,
-            index: 0,
-            line_index: 0,
+            index: offset,
+            line_index: offset,
         }
     }
     pub fn anon() -> SourcePos {
         unsafe { anon_count -= 1; }
         SourcePos {
+            file: 0,
             line: unsafe { anon_count },
             column: 0,
             index: 0,
@@ -219,11 +246,24 @@ impl SourcePos {
         self.line_index = self.index;
     }
 
+    /// Advances past `num` ASCII characters.
     pub fn add_chars(&mut self, num: usize) {
         self.column += num;
         self.index += num;
     }
 
+    /// Advances past a character, which counts as one column however many bytes it takes.
+    pub fn add_char(&mut self, c: char) {
+        self.column += 1;
+        self.index += c.len_utf8();
+    }
+
+    /// Advances past text which does not contain newlines.
+    pub fn add_str(&mut self, text: &str) {
+        self.column += text.chars().count();
+        self.index += text.len();
+    }
+
     pub fn is_anon(&self) -> bool {
         self.line < 0
     }
@@ -294,6 +334,15 @@ impl<T> fmt::Display for Node<T> where T: fmt::Display {
     }
 }
 
+impl<T> Encodable for Node<T> where T: Encodable {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_struct("Node", 2, |s| {
+            try!(s.emit_struct_field("item", 0, |s| self.0.encode(s)));
+            s.emit_struct_field("pos", 1, |s| self.1.encode(s))
+        })
+    }
+}
+
 impl<T> Copy for Node<T> where T: Copy { }
 
 impl<T> Clone for Node<T> where T: Clone {
@@ -0,0 +1,137 @@
+use super::ast::*;
+use super::codes::Code;
+use super::common::Context;
+use super::ident::Identifier;
+use super::tokens::{Number, Node, NodeImpl, Operator, SourcePos};
+
+use std::rc::Rc;
+
+/// Checks if a call of the given name is a transition.
+pub fn is_transition(name: &str) -> bool {
+    name == "xfade" || name == "switch"
+}
+
+/// The expression a call of `xfade` or `switch` stands for.
+pub fn expand(ctxt: &Context, name: &str, call: Node<FunctionCall>) -> Option<Expression> {
+    let pos = call.pos();
+    let args: Vec<Expression> = match call.ty() {
+        CallType::Ordered => call.args().iter().filter_map(|x| x.expr().cloned()).collect(),
+        CallType::Named => Vec::new(),
+    };
+    if args.len() != 3 {
+        ctxt.emit_error(Code::InvalidTransition,
+                        format!("`{}` takes three arguments in order: {}", name,
+                                if name == "xfade" { "a, b, t" } else { "selector, [fns], fade_ms" }),
+                        call.args_pos());
+        return None;
+    }
+    // every argument is evaluated once, into a local which cannot clash with any name
+    let mut stmts = Vec::new();
+    let result = if name == "xfade" {
+        xfade(ctxt, &mut stmts, &args, pos)
+    } else {
+        match switch(ctxt, &mut stmts, &args, pos) {
+            Some(x) => x,
+            None => return None,
+        }
+    };
+    stmts.push(Statement::Expression(result));
+    Some(Expression::Block(Rc::new(Node(stmts, pos))))
+}
+
+fn xfade(ctxt: &Context, stmts: &mut Vec<Statement>, args: &[Expression], pos: SourcePos) -> Expression {
+    let (a, b, t) = (local(ctxt, stmts, &args[0]), local(ctxt, stmts, &args[1]), local(ctxt, stmts, &args[2]));
+    // the position is kept between 0 and 1
+    let t = variable(t, pos);
+    let clamped = conditional(infix(Operator::Less, t.clone(), constant(0.0, pos), pos), constant(0.0, pos),
+                              conditional(infix(Operator::Greater, t.clone(), constant(1.0, pos), pos),
+                                          constant(1.0, pos), t, pos), pos);
+    let x = variable(local(ctxt, stmts, &clamped), pos);
+    let fade_out = infix(Operator::Sub, constant(1.0, pos), x.clone(), pos);
+    infix(Operator::Add, infix(Operator::Mul, call_of(a, pos), fade_out, pos),
+          infix(Operator::Mul, call_of(b, pos), x, pos), pos)
+}
+
+// Each function is weighted by `slew` following 1 while the selector picks it and 0 otherwise.
+fn switch(ctxt: &Context, stmts: &mut Vec<Statement>, args: &[Expression], pos: SourcePos) -> Option<Expression> {
+    let funcs = match args[1] {
+        Expression::Array(ref x) if !x.is_empty() => x.item().clone(),
+        _ => {
+            ctxt.emit_error(Code::InvalidTransition,
+                            "`switch` takes its functions as an array such as `[intro, verse]`", args[1].pos());
+            return None;
+        }
+    };
+    let funcs: Vec<Identifier> = funcs.iter().map(|x| local(ctxt, stmts, x)).collect();
+    let (selector, fade_ms) = (local(ctxt, stmts, &args[0]), local(ctxt, stmts, &args[2]));
+    let slew = ctxt.names.borrow_mut().new_id("slew");
+    let below = |bound: usize| {
+        infix(Operator::Less, variable(selector, pos), constant(bound as Number, pos), pos)
+    };
+    let count = funcs.len();
+    let mut sum = None;
+    for (i, func) in funcs.into_iter().enumerate() {
+        let mut target = if i + 1 < count {
+            conditional(below(i + 1), constant(1.0, pos), constant(0.0, pos), pos)
+        } else {
+            constant(1.0, pos)
+        };
+        if i > 0 {
+            target = conditional(below(i), constant(0.0, pos), target, pos);
+        }
+        let weight = Expression::FunctionCall(Rc::new(Node(FunctionCall {
+            callee: variable(slew, pos),
+            args: Node(vec![Argument::Expr(target), Argument::Expr(variable(fade_ms, pos))], pos),
+            ty: CallType::Ordered,
+        }, pos)));
+        let term = infix(Operator::Mul, call_of(func, pos), weight, pos);
+        sum = Some(match sum {
+            Some(sum) => infix(Operator::Add, sum, term, pos),
+            None => term,
+        });
+    }
+    sum
+}
+
+fn local(ctxt: &Context, stmts: &mut Vec<Statement>, expr: &Expression) -> Identifier {
+    let id = ctxt.names.borrow_mut().new_anon();
+    let pos = expr.pos();
+    stmts.push(Statement::Assignment(Node(Assignment {
+        ident: Node(id, pos),
+        expr: expr.clone(),
+        docs: None,
+    }, pos)));
+    id
+}
+
+fn call_of(func: Identifier, pos: SourcePos) -> Expression {
+    Expression::FunctionCall(Rc::new(Node(FunctionCall {
+        callee: variable(func, pos),
+        args: Node(Vec::new(), pos),
+        ty: CallType::Ordered,
+    }, pos)))
+}
+
+fn variable(id: Identifier, pos: SourcePos) -> Expression {
+    Expression::Variable(Node(id, pos))
+}
+
+fn constant(x: Number, pos: SourcePos) -> Expression {
+    Expression::Constant(Node(x, pos))
+}
+
+fn conditional(cond: Expression, then: Expression, els: Expression, pos: SourcePos) -> Expression {
+    Expression::Conditional(Rc::new(Node(Conditional {
+        cond: cond,
+        then: then,
+        els: els,
+    }, pos)))
+}
+
+fn infix(op: Operator, left: Expression, right: Expression, pos: SourcePos) -> Expression {
+    Expression::Infix(Rc::new(Node(Infix {
+        op: Node(op, pos),
+        left: left,
+        right: right,
+    }, pos)))
+}
@@ -1,12 +1,33 @@
 use super::ast::*;
 use super::types::*;
-use super::tokens::{Operator, Node, NodeImpl};
+use super::tokens::{Number, Operator, Node, NodeImpl, SourcePos};
 use super::common::Context;
-use super::ident::Identifier;
+use super::codes::Code;
+use super::ident::{self, Identifier};
 use super::functions;
+use super::scope::ScopeId;
+use super::codegen::APPROX_EQUAL_EPSILON;
+use super::dsp::{arp, pitch, matrix};
+use super::bus;
+use super::math;
+use super::audio;
+use super::dsp::convolve::Impulse;
 
 use std::cell::RefMut;
+use std::cmp;
+use std::f64;
 use vec_map::VecMap;
+use bit_set::BitSet;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// The value of an expression which is known without running the program.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ConstValue {
+    Number(Number),
+    Boolean(bool),
+    Str(usize), // index into the string literals of the context
+}
 
 pub fn typecheck<'a>(ctxt: &'a Context<'a>) {
     let mut t = TypeChecker::new(ctxt);
@@ -16,6 +37,14 @@ pub fn typecheck<'a>(ctxt: &'a Context<'a>) {
 pub struct TypeChecker<'a> {
     types: RefMut<'a, TypeTable>,
     ctxt: &'a Context<'a>,
+    unifier: Unifier,
+    recursion: VecMap<Type>, // From function Identifier to the type its recursive calls return
+    instances: VecMap<Vec<Identifier>>, // From generic function Identifier to its other instances
+    new_items: Vec<Item>,
+    assignments: Vec<Node<Identifier>>,
+    used: BitSet,
+    definitions: HashMap<(ScopeId, Identifier), SourcePos>,
+    constants: HashMap<(isize, usize), ConstValue>, // from the line and index of a definition
 }
 
 impl<'a> TypeChecker<'a> {
@@ -23,11 +52,167 @@ impl<'a> TypeChecker<'a> {
         TypeChecker {
             ctxt: ctxt,
             types: ctxt.types.borrow_mut(),
+            unifier: Unifier::new(),
+            recursion: VecMap::new(),
+            instances: VecMap::new(),
+            new_items: Vec::new(),
+            assignments: Vec::new(),
+            used: BitSet::new(),
+            definitions: HashMap::new(),
+            constants: HashMap::new(),
         }
     }
 
     pub fn check(&mut self) {
         self.check_root(&mut *self.ctxt.ast.borrow_mut());
+        self.resolve_functions();
+        self.check_unused_variables();
+        self.order_buses();
+    }
+
+    /// Sets the type of a symbol, remembering where it was defined for later diagnostics.
+    fn define(&mut self, id: Identifier, scope: ScopeId, ty: Type, pos: SourcePos) {
+        self.types.set_val(id, scope, ty);
+        self.definitions.insert((scope, id), pos);
+    }
+
+    /// Finds where the symbol currently visible under this name was defined.
+    fn previous_definition(&self, id: Identifier) -> Option<SourcePos> {
+        self.types.get_symbol(id)
+            .map(|sym| self.types.scope_id(sym.scope))
+            .and_then(|scope| self.definitions.get(&(scope, id)).cloned())
+    }
+
+    fn note_previous_definition(&self, code: Code, id: Identifier) {
+        if let Some(pos) = self.previous_definition(id) {
+            self.ctxt.emit_note(code, format!("`{}` was previously defined here",
+                                              self.ctxt.lookup_name(id)), pos);
+        }
+    }
+
+    fn check_unused_variables(&mut self) {
+        let mut reported = BitSet::new();
+        for ident in &self.assignments {
+            let name = self.ctxt.lookup_name(*ident.item());
+            if self.used.contains(ident.item()) || name.starts_with("_") ||
+               !reported.insert(ident.pos().index) {
+                continue;
+            }
+            self.ctxt.emit_warning(Code::UnusedVariable,
+                                   format!("variable `{}` is never used", name), ident.pos());
+            self.ctxt.emit_help(format!("if this is intentional, name it `_{}` instead", name));
+        }
+    }
+
+    // Substitutes everything inferred into the signatures of the functions that were used.
+    fn resolve_functions(&mut self) {
+        let mut functions = self.ctxt.functions.borrow_mut();
+        for (id, func) in functions.map.iter_mut() {
+            let ty = match func.ty() {
+                Some(ty) => ty.clone(),
+                None => continue,
+            };
+            let args: VecMap<Type> = ty.args.iter().map(|(id, &ty)| (id, self.unifier.resolve(ty))).collect();
+            let returns = self.unifier.resolve(ty.returns);
+            let ambiguous = args.values().chain(Some(&returns)).any(|ty|
+                if let Type::Var(_) = *ty { true } else { false });
+            if ambiguous {
+                self.ctxt.emit_error(Code::AmbiguousType, format!("could not infer the type of function `{}`",
+                                             self.ctxt.lookup_name(id)), func.pos());
+            }
+            func.set_ty(FunctionType::with_order(args, ty.order.clone(), returns));
+        }
+    }
+
+    // Generic functions get a separate instance for every set of argument types they are called
+    // with. Returns the instance to use for a call, creating it if needed.
+    fn instantiate(&mut self, func_id: Identifier, arg_types: &VecMap<Type>,
+                   callee_pos: SourcePos) -> Identifier {
+        let func = match self.ctxt.functions.borrow().get(func_id) {
+            Some(&functions::Function::User(ref def)) => def.clone(),
+            _ => return func_id,
+        };
+        // closures can capture their environment, so only top level functions are generic
+        if self.ctxt.names.borrow().is_anon(func_id) == Some(true) {
+            return func_id;
+        }
+
+        let mut instance = None;
+        for &id in Some(&func_id).into_iter().chain(self.instances.get(&func_id).into_iter().flat_map(|x| x)) {
+            match self.ctxt.functions.borrow().get(id).unwrap().ty() {
+                None => instance = Some(id),
+                Some(ty) => {
+                    let matches = ty.args.iter().all(|(arg, &ty)|
+                        self.unifier.resolve(ty) == self.unifier.resolve(arg_types[arg]));
+                    if matches {
+                        instance = Some(id);
+                    }
+                }
+            }
+            if instance.is_some() {
+                break;
+            }
+        }
+
+        let instance = match instance {
+            Some(id) => id,
+            None => {
+                let id = self.ctxt.names.borrow_mut().new_alias(func_id);
+                let returns = self.unifier.fresh();
+                let ty = FunctionType::with_order(arg_types.clone(), declared_order(func.args()), returns);
+                self.unifier.set_signature(id, ty.clone());
+                self.ctxt.functions.borrow_mut().insert(id, functions::Function::User(
+                    functions::UserFunction {
+                        ty: Some(ty),
+                        node: func.node.clone(),
+                    }));
+                self.types.set_val(id, 0, Type::Function(id));
+                if !self.instances.contains_key(&func_id) {
+                    self.instances.insert(func_id, Vec::new());
+                }
+                self.instances.get_mut(&func_id).unwrap().push(id);
+                self.new_items.push(Item::FunctionDef(Node(FunctionDef {
+                    ident: Node(id, func.pos()),
+                    func: func.node.clone(),
+                    docs: None,
+                }, func.pos())));
+                id
+            }
+        };
+        if instance != func_id {
+            let caller = self.ctxt.callstack.borrow().top();
+            self.ctxt.instances.borrow_mut().insert((caller, callee_pos.index), instance);
+        }
+        instance
+    }
+
+    // Suggests similarly named candidates for a name which was not found, formatted to be
+    // appended to an error message.
+    fn did_you_mean<I>(&self, name: &str, candidates: I) -> String where I: Iterator<Item=Identifier> {
+        let names: Vec<String> = candidates.map(|id| self.ctxt.lookup_name(id)).collect();
+        did_you_mean(name, names)
+    }
+
+    fn unify_or_emit(&mut self, expected: Type, expected_pos: SourcePos, found: Type, found_pos: SourcePos,
+                     context: &str, pos: SourcePos) -> Option<Type> {
+        match self.unifier.unify(expected, expected_pos, found, found_pos) {
+            Ok(ty) => Some(ty),
+            Err(mismatch) => {
+                self.ctxt.emit_error(Code::TypeMismatch, format!("{}: {}", context, mismatch), pos);
+                None
+            }
+        }
+    }
+
+    // Unifies the type recursive calls to a function were assumed to return with the type it
+    // actually returns.
+    fn finish_recursion(&mut self, func_id: Identifier, ty: Type, pos: SourcePos) -> Option<Type> {
+        match self.recursion.remove(&func_id) {
+            Some(var) => self.unify_or_emit(ty, pos, var, pos,
+                                            "recursive call does not match the function's return type",
+                                            pos),
+            None => Some(ty),
+        }
     }
 
     fn check_root(&mut self, root: &mut Root) {
@@ -41,11 +226,20 @@ impl<'a> TypeChecker<'a> {
                 }
             };
         }
+        // fully annotated functions can be checked without waiting for a call site
+        for item in root.iter() {
+            if let Item::FunctionDef(ref f) = *item {
+                if !self.ctxt.entrypoints.borrow().contains_key(&f.ident()) {
+                    self.check_annotated_function(f);
+                }
+            }
+        }
+        root.extend(self.new_items.drain(..));
         root.retain(|item|
             match *item {
                 Item::FunctionDef(ref f) => {
                     if !self.ctxt.functions.borrow().get(f.ident()).unwrap().has_concrete_type() {
-                        self.ctxt.emit_warning("function is never used", f.pos());
+                        self.ctxt.emit_warning(Code::UnusedFunction, "function is never used", f.pos());
                         false
                     } else {
                         true
@@ -57,24 +251,42 @@ impl<'a> TypeChecker<'a> {
     }
 
     pub fn typeof_assignment(&mut self, assign: &Node<Assignment>) -> Option<Type> {
+        self.assignments.push(assign.ident);
         let ty = self.typeof_expr(&assign.expr());
         match ty {
             None =>
-                self.ctxt.emit_error("could not determine type of assignment", assign.pos()),
+                self.ctxt.emit_error(Code::UndeterminedType, "could not determine type of assignment", assign.pos()),
             Some(ty) => {
-                if let Some(old_sym) = self.types.get_symbol(assign.ident()) {
-                    if old_sym.val != ty && Some(0) == self.types.get_symbol_depth(assign.ident()) {
-                        self.ctxt.emit_warning(format!("variable was previously assigned type `{}`",
-                                                     old_sym.val),
+                let ty = self.unifier.resolve(ty);
+                // Shadowing a symbol from an enclosing scope is fine, only redefinitions are reported
+                let same_scope = Some(0) == self.types.get_symbol_depth(assign.ident());
+                if let (true, Some(old_sym)) = (same_scope, self.types.get_symbol(assign.ident())) {
+                    let old_ty = self.unifier.resolve(old_sym.val);
+                    if old_ty == Type::Function(assign.ident()) {
+                        self.ctxt.emit_error(Code::RedefinedFunction,
+                                             format!("assignment redefines function `{}`",
+                                                     self.ctxt.lookup_name(assign.ident())),
+                                             assign.pos());
+                        self.note_previous_definition(Code::RedefinedFunction, assign.ident());
+                        return None;
+                    } else if old_ty != ty {
+                        self.ctxt.emit_warning(Code::ReassignedType, format!("variable was previously assigned type `{}`",
+                                                     old_ty),
                                              assign.pos());
+                        self.note_previous_definition(Code::ReassignedType, assign.ident());
                     }
                 }
-                if let Type::Indeterminate = ty {
-                    self.ctxt.emit_error("expression references a function with ambiguous type",
-                                         assign.expr_pos());
-                    // TODO should this set ty=None?
+                // Inside a function the type may still be inferred from how the variable is used
+                if let Type::Var(_) = ty {
+                    if self.ctxt.callstack.borrow().is_empty() {
+                        self.ctxt.emit_error(Code::AmbiguousType, "expression references a function with ambiguous type",
+                                             assign.expr_pos());
+                    }
+                }
+                self.define(assign.ident(), assign.pos().index, ty, assign.pos());
+                if let Some(value) = self.const_value(assign.expr()) {
+                    self.constants.insert((assign.pos().line, assign.pos().index), value);
                 }
-                self.types.set_val(assign.ident(), assign.pos().index, ty);
             }
         }
         ty
@@ -82,10 +294,14 @@ impl<'a> TypeChecker<'a> {
 
     pub fn typeof_function_def(&mut self, def: &Node<FunctionDef>) -> Option<Type> {
         if let Some(0) = self.types.get_symbol_depth(def.ident()) {
-            self.ctxt.emit_warning("function declaration shadows previous declaration of same name", def.pos());
+            self.ctxt.emit_warning(Code::ShadowedFunction,
+                                   format!("function `{}` shadows previous declaration of same name",
+                                           self.ctxt.lookup_name(def.ident())),
+                                   def.pos());
+            self.note_previous_definition(Code::ShadowedFunction, def.ident());
         }
         let ty = Type::Function(def.ident());
-        self.types.set_val(def.ident(), 0, ty);
+        self.define(def.ident(), 0, ty, def.pos());
         if let Some(fn_ty) = self.ctxt.entrypoints.borrow().get(&def.ident()) {
             match self.typeof_predeclared_function(def, fn_ty) {
                 None => return None,
@@ -95,10 +311,72 @@ impl<'a> TypeChecker<'a> {
         Some(ty)
     }
 
+    // Checks the body of a function whose arguments are all annotated with
+    // non-function types against its return type annotation.
+    fn check_annotated_function(&mut self, def: &Node<FunctionDef>) {
+        let mut arg_types = Vec::new();
+        for ty in def.func.arg_types.iter() {
+            match ty.map(|x| *x.item()) {
+                Some(TypeName::Number) => arg_types.push(Type::Number),
+                Some(TypeName::Int) => arg_types.push(Type::Int),
+                Some(TypeName::Boolean) => arg_types.push(Type::Boolean),
+                _ => return,
+            }
+        }
+        if def.func.returns().is_none() {
+            return;
+        }
+        let type_def = self.types.get_symbol(def.ident()).unwrap().clone();
+        self.ctxt.callstack.borrow_mut().push(def.ident());
+        self.types.enter(type_def.scope);
+        for (ty, arg) in arg_types.iter().zip(def.args().iter()) {
+            self.types.set_val(arg.ident().unwrap(), arg.pos().index, *ty);
+        }
+        let ty = self.typeof_block(&def.block);
+        self.types.pop();
+        self.ctxt.callstack.borrow_mut().pop();
+        if let Some(ty) = ty {
+            if let Some(ty) = self.finish_recursion(def.ident(), ty, def.block_pos()) {
+                self.check_return_annotation(&def.func, ty);
+            }
+        }
+    }
+
+    fn check_arg_annotation(&mut self, func: &Function, arg: &Argument, ty: Type) -> bool {
+        let id = arg.ident().unwrap();
+        let ty = self.unifier.resolve(ty);
+        match func.arg_type(id) {
+            Some(annotation) if !annotation.matches(ty) => {
+                self.ctxt.emit_error(Code::AnnotationMismatch, format!("expected type `{}` for argument `{}` as annotated at {}, got `{}`",
+                                             annotation.item(),
+                                             self.ctxt.lookup_name(id),
+                                             annotation.pos(),
+                                             ty),
+                                     arg.pos());
+                false
+            }
+            _ => true,
+        }
+    }
+
+    fn check_return_annotation(&mut self, func: &Function, ty: Type) -> bool {
+        let ty = self.unifier.resolve(ty);
+        match func.returns() {
+            Some(annotation) if !annotation.matches(ty) => {
+                self.ctxt.emit_error(Code::AnnotationMismatch, format!("function is annotated to return `{}`, but returns `{}`",
+                                             annotation.item(), ty),
+                                     annotation.pos());
+                false
+            }
+            _ => true,
+        }
+    }
+
     pub fn typeof_expr(&mut self, expr: &Expression) -> Option<Type> {
         match *expr {
             Expression::Constant(_) => Some(Type::Number),
             Expression::Boolean(_) => Some(Type::Boolean),
+            Expression::Str(_) => Some(Type::Str),
             Expression::Variable(ref id) => self.typeof_var(id),
             Expression::Infix(ref v) => self.typeof_infix(v),
             Expression::Prefix(ref v) => self.typeof_prefix(v),
@@ -106,6 +384,13 @@ impl<'a> TypeChecker<'a> {
             Expression::Block(ref b) => self.typeof_block(b),
             Expression::FunctionCall(ref c) => self.typeof_function_call(c),
             Expression::Closure(ref c) => self.typeof_function_def(c),
+            Expression::BlockRate(ref r) => self.typeof_block_rate(r),
+            Expression::Oversample(ref o) => self.typeof_oversample(o),
+            Expression::Fft(ref s) => self.typeof_fft(s),
+            Expression::Voice(ref v) => self.typeof_voice(v),
+            Expression::Array(ref a) => self.typeof_array(a),
+            Expression::Record(ref r) => self.typeof_record(r),
+            Expression::Field(ref f) => self.typeof_field(f),
         }
     }
 
@@ -118,12 +403,14 @@ impl<'a> TypeChecker<'a> {
                          .chain(fn_ty.args.keys().map(|x| (x, None))) {
             let arg_name = self.ctxt.lookup_name(id);
             if !def.args().iter().any(|x| x.ident().unwrap() == id) {
-                self.ctxt.emit_error(format!("expected argument `{}` for entrypoint `{}`", arg_name, name),
+                self.ctxt.emit_error(Code::MissingArgument, format!("expected argument `{}` for entrypoint `{}`", arg_name, name),
                                      def.args_pos());
                 args_match = false;
             }
             if !fn_ty.args.contains_key(&id) {
-                self.ctxt.emit_error(format!("unexpected argument `{}` for entrypoint `{}`", arg_name, name),
+                let suggestion = self.did_you_mean(&arg_name, fn_ty.args.keys());
+                self.ctxt.emit_error(Code::UnexpectedArgument, format!("unexpected argument `{}` for entrypoint `{}`{}",
+                                             arg_name, name, suggestion),
                                      pos.unwrap());
                 args_match = false;
             }
@@ -132,37 +419,63 @@ impl<'a> TypeChecker<'a> {
             return None;
         }
         let type_def = self.types.get_symbol(def.ident()).unwrap().clone();
-        self.types.push_scope(&type_def.scope.scope);
+        self.types.enter(type_def.scope);
         for ((id, ty), ref arg) in fn_ty.args.iter().zip(def.args().iter()) {
             self.types.set_val(id, arg.pos().index, *ty);
             if let Type::Function(func_id) = *ty {
                 self.types.set_val(func_id, arg.pos().index, Type::Function(func_id));
             }
         }
+        self.ctxt.callstack.borrow_mut().push(def.ident());
         let ty = self.typeof_block(&def.block);
+        self.ctxt.callstack.borrow_mut().pop();
         self.types.pop();
         let return_ty = match ty {
-            Some(ty) => Some(ty),
+            Some(ty) => Some(self.unifier.resolve(ty)),
             None => {
-                self.ctxt.emit_error("could not determine return type of function", def.pos());
+                self.ctxt.emit_error(Code::UndeterminedType, "could not determine return type of function", def.pos());
                 return None;
             }
         };
-        self.ctxt.functions.borrow_mut().get_mut(def.ident()).unwrap().set_ty(fn_ty.clone());
+        let mut annotations_match = self.check_return_annotation(&def.func, return_ty.unwrap());
+        for arg in def.args().iter() {
+            let ty = fn_ty.args[arg.ident().unwrap()];
+            annotations_match &= self.check_arg_annotation(&def.func, arg, ty);
+        }
+        if !annotations_match {
+            return None;
+        }
+        // entrypoints given their inputs by a Program, and buses, may give it several channels
+        let mut fn_ty = fn_ty.clone();
+        if let Some(ty @ Type::Channels(_)) = return_ty {
+            let is_bus = self.ctxt.buses.borrow().iter().any(|x| x.ident == def.ident());
+            if !is_bus && !self.ctxt.inputs.borrow().contains_key(def.ident()) {
+                self.ctxt.emit_error(Code::TypeMismatch, format!("entrypoint `{}` must return `Number`, not `{}`", name, ty),
+                                     def.pos());
+                return None;
+            }
+            fn_ty.returns = ty;
+        }
+        self.ctxt.functions.borrow_mut().get_mut(def.ident()).unwrap().set_ty(fn_ty);
         return_ty
     }
 
     pub fn typeof_function_call(&mut self, call: &Node<FunctionCall>) -> Option<Type> {
-        let func_id = match self.typeof_expr(call.callee()) {
+        let callee_ty = match *call.callee() {
+            // some builtins may only be called directly, see typeof_var()
+            Expression::Variable(Node(id, _)) if self.ctxt.is_direct_builtin(id) => Some(Type::Function(id)),
+            ref callee => self.typeof_expr(callee),
+        };
+        let func_id = match callee_ty {
             Some(Type::Function(f)) => f,
 
             Some(ref ty) => {
-                self.ctxt.emit_error(format!("expected function, got type `{}`", ty), call.callee_pos());
+                self.ctxt.emit_error(Code::NotAFunction, format!("expected function, got type `{}`", ty), call.callee_pos());
                 return None;
             },
 
             None => {
-                self.ctxt.emit_error("could not determine type of function", call.callee_pos());
+                self.ctxt.emit_error(Code::UndeterminedType, "could not determine type of function", call.callee_pos());
                 return None;
             },
         };
@@ -181,14 +494,16 @@ impl<'a> TypeChecker<'a> {
                         undef_args.remove(pos);
                     }
                     None => {
-                        self.ctxt.emit_error(format!("unexpected argument `{}`",
-                                                     self.ctxt.lookup_name(id)), arg.pos());
+                        let name = self.ctxt.lookup_name(id);
+                        let suggestion = self.did_you_mean(&name, func.args().iter().filter_map(|x| x.ident()));
+                        self.ctxt.emit_error(Code::UnexpectedArgument, format!("unexpected argument `{}`{}", name, suggestion),
+                                             arg.pos());
                         return None;
                     }
                 }
             } else {
                 if undef_args.is_empty() {
-                        self.ctxt.emit_error("unexpected argument", arg.pos());
+                        self.ctxt.emit_error(Code::UnexpectedArgument, "unexpected argument", arg.pos());
                         return None;
                 }
                 let expr = match *arg {
@@ -212,7 +527,7 @@ impl<'a> TypeChecker<'a> {
 
         // if any arguments were not defined above, die
         for unassigned in undef_args.iter() {
-            self.ctxt.emit_error(format!("argument `{}` is required",
+            self.ctxt.emit_error(Code::MissingArgument, format!("argument `{}` is required",
                                          self.ctxt.lookup_name(unassigned.ident().unwrap())),
                                  call.args_pos());
         }
@@ -222,19 +537,14 @@ impl<'a> TypeChecker<'a> {
 
         let mut arg_types = VecMap::new();
 
-        let recursive = self.ctxt.callstack.borrow().is_recursive(func_id);
-        if recursive {
-            return Some(Type::Indeterminate);
-        }
-
         // determine the type of the arguments
         for &(ref arg, is_default) in &def_args {
             if is_default {
-                self.types.push_scope(&func_def.scope.scope);
+                self.types.enter(func_def.scope);
             }
             match *arg {
                 Argument::OpAssign(id, op, ref expr) => {
-                    let ty = self.typeof_expr(&Expression::Infix(Box::new(Node(Infix {
+                    let ty = self.typeof_expr(&Expression::Infix(Rc::new(Node(Infix {
                         op: op,
                         left: Expression::Variable(id),
                         right: expr.clone(),
@@ -242,7 +552,7 @@ impl<'a> TypeChecker<'a> {
                     match ty {
                         None => return None,
                         Some(ty) => {
-                            arg_types.insert(*id, ty);
+                            arg_types.insert(*id, self.unifier.resolve(ty));
                         }
                     }
                 }
@@ -251,7 +561,7 @@ impl<'a> TypeChecker<'a> {
                     match ty {
                         None => return None,
                         Some(ty) => {
-                            arg_types.insert(*id, ty);
+                            arg_types.insert(*id, self.unifier.resolve(ty));
                         }
                     }
                 }
@@ -260,7 +570,7 @@ impl<'a> TypeChecker<'a> {
                     match ty {
                         None => return None,
                         Some(ty) => {
-                            arg_types.insert(*id, ty);
+                            arg_types.insert(*id, self.unifier.resolve(ty));
                         }
                     }
                 }
@@ -271,11 +581,50 @@ impl<'a> TypeChecker<'a> {
             }
         }
 
+        // calls are matched with their instance by the position of the callee, so only calls
+        // through a variable are unambiguous
+        let func_id = match *call.callee() {
+            Expression::Variable(_) => self.instantiate(func_id, &arg_types, call.callee_pos()),
+            _ => func_id,
+        };
+        let func = self.ctxt.functions.borrow().get(func_id).unwrap().clone();
+
+        if self.ctxt.callstack.borrow().contains(func_id) {
+            // an annotated return type resolves what would otherwise need to be inferred
+            let returns = match func {
+                functions::Function::User(ref def) => def.returns().map(|x| *x.item()),
+                _ => None,
+            };
+            return Some(match returns {
+                Some(TypeName::Number) => Type::Number,
+                Some(TypeName::Int) => Type::Int,
+                Some(TypeName::Boolean) => Type::Boolean,
+                _ => {
+                    if !self.recursion.contains_key(&func_id) {
+                        let var = self.unifier.fresh();
+                        self.recursion.insert(func_id, var);
+                    }
+                    self.recursion[func_id]
+                }
+            });
+        }
+
+        if let functions::Function::User(ref def) = func {
+            let mut annotations_match = true;
+            for &(ref arg, _) in &def_args {
+                let ty = arg_types[arg.ident().unwrap()];
+                annotations_match &= self.check_arg_annotation(def, arg, ty);
+            }
+            if !annotations_match {
+                return None;
+            }
+        }
+
         self.ctxt.callstack.borrow_mut().push(func_id);
 
         let return_ty = match func {
             functions::Function::User(ref def) => {
-                self.types.push_scope(&func_def.scope.scope);
+                self.types.enter(func_def.scope);
                 for ((id, ty), &(ref arg, _)) in arg_types.iter().zip(def_args.iter()) {
                     self.types.set_val(id, arg.pos().index, *ty);
                     if let Type::Function(func_id) = *ty {
@@ -285,32 +634,57 @@ impl<'a> TypeChecker<'a> {
                 let ty = self.typeof_block(&def.block);
                 self.types.pop();
                 match ty {
-                    Some(ty) => ty,
+                    Some(ty) => {
+                        match self.finish_recursion(func_id, ty, def.block_pos()) {
+                            Some(ty) if self.check_return_annotation(def, ty) => Some(ty),
+                            _ => None,
+                        }
+                    }
                     None => {
-                        self.ctxt.emit_error("could not determine return type of function", def.pos());
-                        return None;
+                        self.ctxt.emit_error(Code::UndeterminedType, "could not determine return type of function", def.pos());
+                        None
                     }
                 }
             }
 
-            functions::Function::Pointer(ref def) => { def.ty.returns }
-            functions::Function::External(ref def) => { def.ty.returns }
+            functions::Function::Pointer(ref def) => {
+                Some(match (def.ty.returns, def.channels_of.map(|id| arg_types.get(id).cloned())) {
+                    (Type::Channels(0), Some(Some(Type::Array(len)))) |
+                    (Type::Channels(0), Some(Some(Type::Channels(len)))) => Type::Channels(len),
+                    (ty, _) => ty,
+                })
+            }
+            functions::Function::External(ref def) => { Some(def.ty.returns) }
+            functions::Function::Intrinsic(ref def) => { Some(def.ty.returns) }
         };
 
         self.ctxt.callstack.borrow_mut().pop();
 
-        let calcd_type = FunctionType::new(arg_types, return_ty);
+        let return_ty = match return_ty {
+            Some(ty) => self.unifier.resolve(ty),
+            None => return None,
+        };
+
+        let calcd_type = FunctionType::with_order(arg_types, declared_order(func.args()), return_ty);
         if let Some(def_type) = func.ty() {
             let mut types_match = true;
-            for ((old, new), &(ref arg, _)) in def_type.args.iter().zip(calcd_type.args.iter()).zip(def_args.iter()) {
-                if let ((_, &Type::Function(_)), (_, &Type::Function(_))) = (new, old) {
-                    // If they are both functions, do nothing.
-                    // Their compatibility will already have been validated
-                } else if old != new {
-                    self.ctxt.emit_error(format!("expected type `{}` for argument `{}`, got `{}`",
-                                                 old.1,
-                                                 self.ctxt.lookup_name(arg.ident().unwrap()),
-                                                 new.1),
+            for &(ref arg, _) in &def_args {
+                let id = arg.ident().unwrap();
+                let (old, new) = (def_type.args[id], calcd_type.args[id]);
+                match (&func, old, new) {
+                    (&functions::Function::Pointer(_), Type::Array(_), Type::Array(_)) |
+                    (&functions::Function::Pointer(_), Type::Channels(0), Type::Channels(_)) => continue,
+                    // a whole constant can be given where a builtin takes an Int
+                    (&functions::Function::Pointer(_), Type::Int, Type::Number)
+                        if arg.expr().map_or(false, |x| self.is_int(x, new)) => continue,
+                    _ => { }
+                }
+                if let Err(mismatch) = self.unifier.unify(old, func.pos(), new, arg.pos()) {
+                    self.ctxt.emit_error(Code::ArgumentTypeMismatch, format!("expected type `{}` for argument `{}` (from {}), got `{}`",
+                                                 mismatch.expected,
+                                                 self.ctxt.lookup_name(id),
+                                                 mismatch.expected_pos,
+                                                 mismatch.found),
                                          arg.pos());
                     types_match = false;
                 }
@@ -319,10 +693,373 @@ impl<'a> TypeChecker<'a> {
                 return None;
             }
         }
-        self.ctxt.functions.borrow_mut().get_mut(func_id).unwrap().set_ty(calcd_type);
+        // builtins keep their declared types, whose arrays may be of any length
+        match func {
+            functions::Function::Pointer(_) => { }
+            _ => {
+                self.unifier.set_signature(func_id, calcd_type.clone());
+                self.ctxt.functions.borrow_mut().get_mut(func_id).unwrap().set_ty(calcd_type);
+            }
+        }
+        if self.ctxt.is_builtin(func_id, "assert") {
+            self.check_const_assert(&def_args, call.pos());
+        } else if self.ctxt.is_builtin(func_id, "probe") {
+            self.register_probe(&def_args);
+        } else if self.ctxt.is_builtin(func_id, "scale") || self.ctxt.is_builtin(func_id, "quantize") {
+            self.check_pitch_name(&def_args, "mode", "scale", pitch::SCALES);
+        } else if self.ctxt.is_builtin(func_id, "chord") {
+            self.check_pitch_name(&def_args, "quality", "chord", pitch::CHORDS);
+        } else if self.ctxt.is_builtin(func_id, "arp") {
+            self.check_known_name(&def_args, "mode", "arpeggiator mode", arp::MODES, Code::UnknownArpMode);
+        } else if self.ctxt.is_builtin(func_id, "send") {
+            self.check_send(&def_args);
+        } else if self.ctxt.is_builtin(func_id, "record") || self.ctxt.is_builtin(func_id, "playbuf") {
+            self.check_buffer_name(&def_args, "buffer");
+        } else if self.ctxt.is_builtin(func_id, "stretch") {
+            self.check_buffer_name(&def_args, "sample");
+        } else if self.ctxt.is_builtin(func_id, "convolve") {
+            self.load_impulse(&def_args);
+        } else if self.ctxt.is_builtin(func_id, "select") {
+            self.check_select(&def_args, &arg_types);
+        } else if matrix::SIZED_BUILTINS.iter().any(|&x| self.ctxt.is_builtin(func_id, x)) {
+            if !self.check_sizes(func_id, &def_args, &arg_types, call.pos()) {
+                return None;
+            }
+        }
         Some(return_ty)
     }
 
+    // Asserts whose condition is known without running the program are checked right away.
+    fn check_const_assert(&self, args: &[(Argument, bool)], pos: SourcePos) {
+        let mut cond = None;
+        let mut message = String::new();
+        for &(ref arg, _) in args {
+            let name = self.ctxt.lookup_name(arg.ident().unwrap());
+            let value = match *arg {
+                Argument::Ident(id) => Expression::Variable(id),
+                Argument::Assign(_, ref expr) => expr.clone(),
+                _ => continue,
+            };
+            match (&name[..], value) {
+                ("cond", value) => cond = self.const_value(&value),
+                ("message", Expression::Str(Node(idx, _))) => message = self.ctxt.strings.borrow()[idx].to_string(),
+                _ => { },
+            }
+        }
+        if cond == Some(ConstValue::Boolean(false)) {
+            let msg = if message.is_empty() {
+                "assertion failed".to_string()
+            } else {
+                format!("assertion failed: {}", message)
+            };
+            self.ctxt.emit_error(Code::AssertionFailed, msg, pos);
+        }
+    }
+
+    // Scales and chords named by a constant must be ones the runtime knows.
+    fn check_pitch_name(&self, args: &[(Argument, bool)], arg_name: &str, kind: &str,
+                        table: &[(&'static str, &'static [Number])]) {
+        let names: Vec<&str> = table.iter().map(|x| x.0).collect();
+        self.check_known_name(args, arg_name, kind, &names, Code::UnknownPitchName);
+    }
+
+    // The string passed as `arg_name` must be one of `names` if it is a constant.
+    fn check_known_name(&self, args: &[(Argument, bool)], arg_name: &str, kind: &str, names: &[&str], code: Code) {
+        for &(ref arg, _) in args {
+            let value = match *arg {
+                Argument::Ident(id) => Expression::Variable(id),
+                Argument::Assign(_, ref expr) => expr.clone(),
+                _ => continue,
+            };
+            if self.ctxt.lookup_name(arg.ident().unwrap()) != arg_name {
+                continue;
+            }
+            if let Some(ConstValue::Str(idx)) = self.const_value(&value) {
+                let name = self.ctxt.strings.borrow()[idx].clone();
+                if names.contains(&&name[..]) {
+                    continue;
+                }
+                let mut similar = ident::similar_names(&name, names.iter().map(|x| x.to_string()));
+                if similar.is_empty() {
+                    similar = names.iter().map(|x| x.to_string()).collect();
+                }
+                let similar: Vec<String> = similar.iter().map(|x| format!("`{}`", x)).collect();
+                self.ctxt.emit_error(code, format!("unknown {} `{}`; expected one of {}",
+                                                   kind, name, similar.join(", ")),
+                                     arg.pos());
+            }
+        }
+    }
+
+    // Sends must go to a bus which exists, and those from inside a bus decide the order buses are
+    // processed in.
+    fn check_send(&self, args: &[(Argument, bool)]) {
+        for &(ref arg, _) in args {
+            let value = match *arg {
+                Argument::Ident(id) => Expression::Variable(id),
+                Argument::Assign(_, ref expr) => expr.clone(),
+                _ => continue,
+            };
+            if self.ctxt.lookup_name(arg.ident().unwrap()) != "bus" {
+                continue;
+            }
+            if let Some(ConstValue::Str(idx)) = self.const_value(&value) {
+                let name = self.ctxt.strings.borrow()[idx].clone();
+                let mut buses = self.ctxt.buses.borrow_mut();
+                if !buses.iter().any(|x| x.name == name) {
+                    let suggestion = did_you_mean(&name, buses.iter().map(|x| x.name.clone()).collect());
+                    self.ctxt.emit_error(Code::UnknownBus, format!("unknown bus `{}`{}", name, suggestion),
+                                         arg.pos());
+                    continue;
+                }
+                let callstack = self.ctxt.callstack.borrow();
+                for bus in buses.iter_mut().filter(|x| callstack.contains(x.ident)) {
+                    if !bus.sends.contains(&name) {
+                        bus.sends.push(name.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    // The arrays and signals passed to the builtins for vectors and matrices must fit together,
+    // which their lengths tell while compiling.
+    fn check_sizes(&self, func_id: Identifier, args: &[(Argument, bool)], arg_types: &VecMap<Type>,
+                   pos: SourcePos) -> bool {
+        let sizes: Vec<(String, usize)> = args.iter().filter_map(|&(ref arg, _)| {
+            let id = arg.ident().unwrap();
+            match arg_types.get(id) {
+                Some(&Type::Array(len)) | Some(&Type::Channels(len)) => Some((self.ctxt.lookup_name(id), len)),
+                _ => None,
+            }
+        }).collect();
+        let sizes: Vec<(&str, usize)> = sizes.iter().map(|&(ref name, len)| (&name[..], len)).collect();
+        match matrix::check_sizes(&self.ctxt.lookup_name(func_id), &sizes) {
+            Ok(()) => true,
+            Err(msg) => {
+                self.ctxt.emit_error(Code::InvalidMatrix, msg, pos);
+                false
+            }
+        }
+    }
+
+    // The index of a `select` which is a constant, or comes from a sequencer stepping through
+    // constants, should stay within the array it selects from.
+    fn check_select(&self, args: &[(Argument, bool)], arg_types: &VecMap<Type>) {
+        let mut index = None;
+        let mut len = None;
+        for &(ref arg, _) in args {
+            let value = match *arg {
+                Argument::Ident(id) => Expression::Variable(id),
+                Argument::Assign(_, ref expr) => expr.clone(),
+                _ => continue,
+            };
+            match &self.ctxt.lookup_name(arg.ident().unwrap())[..] {
+                "index" => index = Some((value, arg.pos())),
+                "values" => if let Type::Array(n) = arg_types[arg.ident().unwrap()] {
+                    len = Some((n, arg.pos()));
+                },
+                _ => { },
+            }
+        }
+        let (index, index_pos, len, values_pos) = match (index, len) {
+            (Some((index, index_pos)), Some((len, values_pos))) => (index, index_pos, len, values_pos),
+            _ => return,
+        };
+        let (low, high) = match self.index_range(&index) {
+            Some(range) => range,
+            None => return,
+        };
+        if low == high && (low < 0.0 || low >= len as Number) {
+            self.ctxt.emit_warning(Code::IndexOutOfRange,
+                                   format!("index {} is outside of the array, which has {} elements", low, len),
+                                   index_pos);
+        } else if low < 0.0 || high >= len as Number {
+            self.ctxt.emit_warning(Code::IndexOutOfRange,
+                                   format!("index goes from {} to {}, but the array only has {} elements",
+                                           low, high, len),
+                                   index_pos);
+        } else {
+            return;
+        }
+        self.ctxt.emit_note(Code::IndexOutOfRange, format!("the array has indices 0 to {}", len - 1), values_pos);
+        self.ctxt.emit_help("indices outside of the array select its nearest element");
+    }
+
+    // The lowest and highest values an index may take, if they are known while compiling: those
+    // of constants, of `seq` and `choose` over arrays of constants, and of conditionals between
+    // such expressions.
+    fn index_range(&self, expr: &Expression) -> Option<(Number, Number)> {
+        if let Some(ConstValue::Number(x)) = self.const_value(expr) {
+            return Some((x.floor(), x.floor()));
+        }
+        match *expr {
+            Expression::Conditional(ref cond) => match (self.index_range(cond.then()), self.index_range(cond.els())) {
+                (Some((a, b)), Some((c, d))) => Some((a.min(c), b.max(d))),
+                _ => None,
+            },
+            Expression::FunctionCall(ref call) => {
+                let sequencer = match *call.callee() {
+                    Expression::Variable(Node(id, _)) =>
+                        self.ctxt.is_builtin(id, "seq") || self.ctxt.is_builtin(id, "choose"),
+                    _ => false,
+                };
+                let values = match call.args().get(1) {
+                    Some(&Argument::Expr(Expression::Array(ref values))) if sequencer => values,
+                    _ => return None,
+                };
+                values.iter().fold(Some((f64::INFINITY, f64::NEG_INFINITY)), |range, value|
+                    match (range, self.index_range(value)) {
+                        (Some((a, b)), Some((c, d))) => Some((a.min(c), b.max(d))),
+                        _ => None,
+                    })
+            }
+            _ => None,
+        }
+    }
+
+    // A buffer named by a constant must be declared.
+    fn check_buffer_name(&self, args: &[(Argument, bool)], arg_name: &str) {
+        for &(ref arg, _) in args {
+            let value = match *arg {
+                Argument::Ident(id) => Expression::Variable(id),
+                Argument::Assign(_, ref expr) => expr.clone(),
+                _ => continue,
+            };
+            if self.ctxt.lookup_name(arg.ident().unwrap()) != arg_name {
+                continue;
+            }
+            if let Some(ConstValue::Str(idx)) = self.const_value(&value) {
+                let name = self.ctxt.strings.borrow()[idx].clone();
+                let buffers = self.ctxt.buffers.borrow();
+                if !buffers.iter().any(|x| x.name == name) {
+                    let suggestion = did_you_mean(&name, buffers.iter().map(|x| x.name.clone()).collect());
+                    self.ctxt.emit_error(Code::UnknownBuffer, format!("unknown buffer `{}`{}", name, suggestion),
+                                         arg.pos());
+                }
+            }
+        }
+    }
+
+    // Puts the buses in the order they are processed, each after every bus which sends to it.
+    fn order_buses(&self) {
+        let mut buses = self.ctxt.buses.borrow_mut();
+        match bus::order(&buses) {
+            Ok(order) => {
+                let ordered: Vec<_> = order.iter().map(|&i| buses[i].clone()).collect();
+                *buses = ordered;
+            }
+            Err(cycle) => {
+                let names: Vec<String> = cycle.iter().map(|&i| format!("`{}`", buses[i].name)).collect();
+                self.ctxt.emit_error(Code::BusCycle, format!("buses {} send to each other in a cycle",
+                                                             names.join(", ")),
+                                     buses[cycle[0]].pos);
+            }
+        }
+    }
+
+    // Impulse responses are loaded while compiling, once for each file however often it is used.
+    fn load_impulse(&self, args: &[(Argument, bool)]) {
+        for &(ref arg, _) in args {
+            let value = match *arg {
+                Argument::Ident(id) => Expression::Variable(id),
+                Argument::Assign(_, ref expr) => expr.clone(),
+                _ => continue,
+            };
+            if self.ctxt.lookup_name(arg.ident().unwrap()) != "impulse" {
+                continue;
+            }
+            let name = match self.const_value(&value) {
+                Some(ConstValue::Str(idx)) => self.ctxt.strings.borrow()[idx].clone(),
+                _ => {
+                    self.ctxt.emit_error(Code::InvalidImpulse, "the impulse response must be named by a constant string",
+                                         arg.pos());
+                    continue;
+                }
+            };
+            if self.ctxt.impulses.borrow().iter().any(|x| x.name == name) {
+                continue;
+            }
+            match audio::read_wav(&self.ctxt.resolve_path(&name)) {
+                Ok(audio) => self.ctxt.impulses.borrow_mut().push(Impulse::new(name, audio)),
+                Err(why) => self.ctxt.emit_error(Code::InvalidImpulse, why, arg.pos()),
+            }
+        }
+    }
+
+    // Probes named by a literal get a column when probes are recorded.
+    fn register_probe(&self, args: &[(Argument, bool)]) {
+        for &(ref arg, _) in args {
+            if let Argument::Assign(Node(id, _), Expression::Str(Node(idx, _))) = *arg {
+                if self.ctxt.lookup_name(id) != "name" {
+                    continue;
+                }
+                let name = self.ctxt.strings.borrow()[idx].clone();
+                let mut probes = self.ctxt.probes.borrow_mut();
+                if !probes.contains(&name) {
+                    probes.push(name);
+                }
+            }
+        }
+    }
+
+    // Evaluates expressions made only of constants, operators, conditionals and variables which
+    // were assigned such expressions.
+    fn const_value(&self, expr: &Expression) -> Option<ConstValue> {
+        use self::ConstValue::{Number, Boolean};
+
+        Some(match *expr {
+            Expression::Constant(Node(x, _)) => Number(x),
+            Expression::Boolean(Node(x, _)) => Boolean(x),
+            Expression::Str(Node(x, _)) => ConstValue::Str(x),
+            Expression::Variable(Node(id, _)) => {
+                return self.previous_definition(id)
+                    .and_then(|pos| self.constants.get(&(pos.line, pos.index)).cloned());
+            }
+            Expression::Prefix(ref prefix) => match (prefix.op(), self.const_value(prefix.expr())) {
+                (Operator::Sub, Some(Number(x))) => Number(-x),
+                (Operator::Not, Some(Boolean(x))) => Boolean(!x),
+                _ => return None,
+            },
+            Expression::Conditional(ref cond) => match self.const_value(cond.cond()) {
+                Some(Boolean(true)) => return self.const_value(cond.then()),
+                Some(Boolean(false)) => return self.const_value(cond.els()),
+                _ => return None,
+            },
+            Expression::Infix(ref infix) => {
+                let (left, right) = match (self.const_value(infix.left()), self.const_value(infix.right())) {
+                    (Some(left), Some(right)) => (left, right),
+                    _ => return None,
+                };
+                match (infix.op(), left, right) {
+                    (Operator::Add, Number(a), Number(b)) => Number(a + b),
+                    (Operator::Sub, Number(a), Number(b)) => Number(a - b),
+                    (Operator::Mul, Number(a), Number(b)) => Number(a * b),
+                    (Operator::Div, Number(a), Number(b)) => Number(a / b),
+                    (Operator::Mod, Number(a), Number(b)) => Number(a % b),
+                    (Operator::Exp, Number(a), Number(b)) => Number(if self.ctxt.options.borrow().deterministic {
+                        math::pow(a, b)
+                    } else {
+                        a.powf(b)
+                    }),
+                    (Operator::Less, Number(a), Number(b)) => Boolean(a < b),
+                    (Operator::Greater, Number(a), Number(b)) => Boolean(a > b),
+                    (Operator::LessEqual, Number(a), Number(b)) => Boolean(a <= b),
+                    (Operator::GreaterEqual, Number(a), Number(b)) => Boolean(a >= b),
+                    (Operator::ApproxEqual, Number(a), Number(b)) => Boolean((a - b).abs() <= APPROX_EQUAL_EPSILON),
+                    (Operator::And, Boolean(a), Boolean(b)) => Boolean(a && b),
+                    (Operator::Or, Boolean(a), Boolean(b)) => Boolean(a || b),
+                    (Operator::Xor, Boolean(a), Boolean(b)) => Boolean(a ^ b),
+                    (_, ConstValue::Str(_), _) | (_, _, ConstValue::Str(_)) => return None,
+                    (Operator::Equal, a, b) => Boolean(a == b),
+                    (Operator::NotEqual, a, b) => Boolean(a != b),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        })
+    }
+
     pub fn typeof_block(&mut self, block: &Node<Block>) -> Option<Type> {
         self.types.push(block.pos().index);
         let count = block.iter().filter(|&x| {
@@ -332,34 +1069,43 @@ impl<'a> TypeChecker<'a> {
             }
         }).count();
         let mut ty = None;
+        let mut first = None;
         for stmnt in block.item() {
             match stmnt {
                 &Statement::Assignment(ref a) => {
                     if self.typeof_assignment(a).is_none() {
-                        self.ctxt.emit_error("could not determine type of block assignment", a.pos());
+                        self.ctxt.emit_error(Code::UndeterminedType, "could not determine type of block assignment", a.pos());
+                        self.types.pop();
                         return None
                     }
                 }
                 &Statement::Expression(ref e) => {
                     ty = self.typeof_expr(e);
                     match ty {
-                        Some(Type::Number) => { },
-                        Some(Type::Indeterminate) => {
-                            if count > 1 {
-                                ty = Some(Type::Number);
-                            } else {
-                                self.ctxt.emit_error("type of expression is ambiguous", e.pos());
-                                ty = None;
-                            }
-                        }
-                        Some(_) => {
-                            if count > 1 {
-                                self.ctxt.emit_error(format!("expected type `Number`, got `{}`", ty.unwrap()), e.pos());
-                                ty = None;
+                        Some(expr_ty) => {
+                            // blocks with several expressions sum them, so they must be numbers, or
+                            // signals which all have the same channels
+                            let expr_ty = self.unifier.resolve(expr_ty);
+                            match (first, expr_ty) {
+                                _ if count == 1 => { }
+                                (None, Type::Channels(_)) => { }
+                                (Some(Type::Channels(a)), Type::Channels(b)) if a == b => { }
+                                (Some(Type::Channels(_)), _) | (_, Type::Channels(_)) => {
+                                    self.ctxt.emit_error(Code::ChannelMismatch,
+                                                         "expected every expression in the block to have the same channels",
+                                                         e.pos());
+                                    ty = None;
+                                }
+                                _ => {
+                                    ty = self.unify_or_emit(Type::Number, e.pos(), expr_ty, e.pos(),
+                                                            "expected every expression in the block to be a number",
+                                                            e.pos());
+                                }
                             }
+                            first = first.or(Some(expr_ty));
                         }
                         None => {
-                            self.ctxt.emit_error("could not determine type of statement", e.pos());
+                            self.ctxt.emit_error(Code::UndeterminedType, "could not determine type of statement", e.pos());
                         }
 
                     }
@@ -370,61 +1116,253 @@ impl<'a> TypeChecker<'a> {
         ty
     }
 
+    // Numbers are never taken to be true or false, so one where a boolean is expected is reported
+    // with how to write the comparison which was likely meant.
+    fn number_as_boolean(&mut self, ty: Type, pos: SourcePos) -> bool {
+        match self.unifier.resolve(ty) {
+            Type::Number | Type::Int => {
+                self.ctxt.emit_error(Code::TypeMismatch, "expected a boolean, found a number", pos);
+                self.ctxt.emit_help("numbers are not true or false; compare it instead, as in `x != 0`");
+                true
+            }
+            _ => false,
+        }
+    }
+
     pub fn typeof_conditional(&mut self, cond: &Node<Conditional>) -> Option<Type> {
         match self.typeof_expr(&cond.cond()) {
             Some(x) => {
-                if x != Type::Boolean {
-                    self.ctxt.emit_error(format!("expected type `Boolean`, got `{}`", x), cond.cond_pos());
+                if self.number_as_boolean(x, cond.cond_pos()) {
+                    return None;
+                }
+                if self.unify_or_emit(Type::Boolean, cond.cond_pos(), x, cond.cond_pos(),
+                                      "condition must be a boolean", cond.cond_pos()).is_none() {
                     return None;
                 }
             },
             None => {
-                self.ctxt.emit_error("type of condition could not be determined", cond.cond_pos());
+                self.ctxt.emit_error(Code::UndeterminedType, "type of condition could not be determined", cond.cond_pos());
                 return None;
             }
         }
         let then_ty = match self.typeof_expr(cond.then()) {
             Some(x) => x,
             None => {
-                self.ctxt.emit_error("type of conditional then expression could not be determined", cond.then_pos());
+                self.ctxt.emit_error(Code::UndeterminedType, "type of conditional then expression could not be determined", cond.then_pos());
                 return None;
             }
         };
         let else_ty = match self.typeof_expr(cond.els()) {
             Some(x) => x,
             None => {
-                self.ctxt.emit_error("type of conditional else expression could not be determined", cond.els_pos());
+                self.ctxt.emit_error(Code::UndeterminedType, "type of conditional else expression could not be determined", cond.els_pos());
+                return None;
+            }
+        };
+        // an Int in one branch widens to a Number in the other either way round
+        let (then_ty, else_ty) = match (self.unifier.resolve(then_ty), self.unifier.resolve(else_ty)) {
+            (Type::Int, Type::Number) => (else_ty, then_ty),
+            _ => (then_ty, else_ty),
+        };
+        let ty = match self.unifier.unify(then_ty, cond.then_pos(), else_ty, cond.els_pos()) {
+            Ok(ty) => Some(ty),
+            Err(mismatch) => {
+                self.emit_branch_mismatch(cond, mismatch.expected, mismatch.found);
                 return None;
             }
         };
-        if else_ty == Type::Indeterminate && then_ty != Type::Indeterminate {
-            return Some(then_ty);
+        // strings only exist while compiling, so which one is used must be known by then
+        if ty == Some(Type::Str) && self.const_value(cond.cond()).is_none() {
+            self.ctxt.emit_error(Code::RuntimeString,
+                                 "cannot choose between strings with a condition which is only known at runtime",
+                                 cond.cond_pos());
+            return None;
         }
-        if then_ty == Type::Indeterminate && else_ty != Type::Indeterminate {
-            return Some(else_ty);
+        ty
+    }
+
+    // Points at both branches of a conditional whose types differ, with the type of each.
+    fn emit_branch_mismatch(&self, cond: &Node<Conditional>, then_ty: Type, else_ty: Type) {
+        self.ctxt.emit_error(Code::BranchTypeMismatch,
+                             format!("branches of conditional have different types, `{}` and `{}`", then_ty, else_ty),
+                             cond.pos());
+        self.ctxt.emit_note(Code::BranchTypeMismatch, format!("this branch is `{}`", then_ty), cond.then_pos());
+        self.ctxt.emit_note(Code::BranchTypeMismatch, format!("this branch is `{}`", else_ty), cond.els_pos());
+        let channels = |ty| match ty {
+            Type::Number => Some(1),
+            Type::Channels(count) => Some(count),
+            _ => None,
+        };
+        match (channels(then_ty), channels(else_ty)) {
+            (Some(1), Some(_)) | (Some(_), Some(1)) =>
+                self.ctxt.emit_help("make the mono branch stereo as well, for example with `pan(signal, 0)`"),
+            (Some(_), Some(_)) => self.ctxt.emit_help("give both branches the same number of channels"),
+            _ => { },
         }
-        if let (Type::Function(_), Type::Function(_)) = (then_ty, else_ty) {
-            return Some(then_ty);
-            //unimplemented!();
+    }
+
+    pub fn typeof_block_rate(&mut self, rate: &Node<BlockRate>) -> Option<Type> {
+        let ty = match self.typeof_expr(rate.expr()) {
+            Some(x) => x,
+            None => {
+                self.ctxt.emit_error(Code::UndeterminedType, "type of block rate expression could not be determined",
+                                     rate.expr_pos());
+                return None;
+            }
+        };
+        self.unify_or_emit(Type::Number, rate.pos(), ty, rate.expr_pos(),
+                           "only numbers can be evaluated once per block", rate.expr_pos())
+    }
+
+    pub fn typeof_oversample(&mut self, section: &Node<Oversample>) -> Option<Type> {
+        let ty = match self.typeof_expr(section.expr()) {
+            Some(x) => x,
+            None => {
+                self.ctxt.emit_error(Code::UndeterminedType, "type of oversampled expression could not be determined",
+                                     section.expr_pos());
+                return None;
+            }
+        };
+        self.unify_or_emit(Type::Number, section.pos(), ty, section.expr_pos(),
+                           "only numbers can be oversampled", section.expr_pos())
+    }
+
+    pub fn typeof_fft(&mut self, section: &Node<FftSection>) -> Option<Type> {
+        let input = match self.typeof_expr(section.input()) {
+            Some(x) => x,
+            None => {
+                self.ctxt.emit_error(Code::UndeterminedType, "type of the input of an @fft section could not be determined",
+                                     section.input().pos());
+                return None;
+            }
+        };
+        if self.unify_or_emit(Type::Number, section.pos(), input, section.input().pos(),
+                              "only numbers can be transformed", section.input().pos()).is_none() {
+            return None;
         }
-        if else_ty != then_ty {
-            self.ctxt.emit_error(format!(
-                "then branch of conditional is of type `{}` but else branch is of type `{}`",
-                then_ty, else_ty), cond.els_pos());
+        let ty = match self.typeof_expr(section.expr()) {
+            Some(x) => x,
+            None => {
+                self.ctxt.emit_error(Code::UndeterminedType, "type of the bins of an @fft section could not be determined",
+                                     section.expr_pos());
+                return None;
+            }
+        };
+        self.unify_or_emit(Type::Complex, section.pos(), ty, section.expr_pos(),
+                           "the bins of an @fft section must be complex numbers", section.expr_pos())
+            .map(|_| Type::Number)
+    }
+
+    pub fn typeof_voice(&mut self, voice: &Node<VoiceBlock>) -> Option<Type> {
+        let ty = match self.typeof_expr(voice.expr()) {
+            Some(x) => x,
+            None => {
+                self.ctxt.emit_error(Code::UndeterminedType, "type of voice block could not be determined",
+                                     voice.expr_pos());
+                return None;
+            }
+        };
+        self.unify_or_emit(Type::Number, voice.pos(), ty, voice.expr_pos(),
+                           "only numbers can be summed over voices", voice.expr_pos())
+    }
+
+    pub fn typeof_array(&mut self, array: &Node<Vec<Expression>>) -> Option<Type> {
+        if array.is_empty() {
+            self.ctxt.emit_error(Code::EmptyArray, "arrays must have at least one element", array.pos());
             return None;
         }
+        for elem in array.iter() {
+            let ty = match self.typeof_expr(elem) {
+                Some(x) => x,
+                None => {
+                    self.ctxt.emit_error(Code::UndeterminedType, "type of array element could not be determined",
+                                         elem.pos());
+                    return None;
+                }
+            };
+            if self.unify_or_emit(Type::Number, array.pos(), ty, elem.pos(),
+                                  "arrays may only hold numbers", elem.pos()).is_none() {
+                return None;
+            }
+        }
+        Some(Type::Array(array.len()))
+    }
+
+    pub fn typeof_record(&mut self, record: &Node<Record>) -> Option<Type> {
+        for &(_, ref value) in &record.fields {
+            let ty = match self.typeof_expr(value) {
+                Some(x) => x,
+                None => {
+                    self.ctxt.emit_error(Code::UndeterminedType, "type of field could not be determined",
+                                         value.pos());
+                    return None;
+                }
+            };
+            if self.unify_or_emit(Type::Number, record.pos(), ty, value.pos(),
+                                  "records may only hold numbers", value.pos()).is_none() {
+                return None;
+            }
+        }
+        let fields: Vec<Identifier> = record.fields.iter().map(|x| *x.0.item()).collect();
+        Some(Type::Record(self.ctxt.records.borrow_mut().intern(&fields)))
+    }
 
-        Some(then_ty)
+    pub fn typeof_field(&mut self, field: &Node<Field>) -> Option<Type> {
+        let ty = match self.typeof_expr(field.expr()) {
+            Some(x) => self.unifier.resolve(x),
+            None => return None,
+        };
+        let name = self.ctxt.lookup_name(field.field());
+        match ty {
+            Type::Record(shape) => {
+                let index = self.ctxt.records.borrow().index(shape, field.field());
+                if let Some(index) = index {
+                    let func = self.ctxt.callstack.borrow().top();
+                    self.ctxt.records.borrow_mut().set_read(func, field.field_pos().index, index);
+                    return Some(Type::Number);
+                }
+                let fields: Vec<String> = self.ctxt.records.borrow().fields(shape).iter()
+                    .map(|&x| format!("`{}`", self.ctxt.lookup_name(x))).collect();
+                self.ctxt.emit_error(Code::InvalidRecord, format!("the record has no field `{}`", name),
+                                     field.field_pos());
+                self.ctxt.emit_help(format!("its fields are {}", fields.join(", ")));
+            }
+            Type::Var(_) => self.ctxt.emit_error(Code::UndeterminedType,
+                                                 format!("type of the record whose field `{}` is read could not be determined", name),
+                                                 field.expr_pos()),
+            ty => self.ctxt.emit_error(Code::InvalidRecord,
+                                       format!("cannot read the field `{}` of a value of type `{}`", name, ty),
+                                       field.field_pos()),
+        }
+        None
     }
 
     pub fn typeof_var(&mut self, ident: &Node<Identifier>) -> Option<Type> {
+        self.used.insert(*ident.item());
         match self.types.get_symbol(*ident.item()) {
             Some(s) => {
-                Some(s.val)
+                let ty = self.unifier.resolve(s.val);
+                if let Type::Function(id) = ty {
+                    if self.ctxt.is_direct_builtin(id) {
+                        self.ctxt.emit_error(Code::BuiltinValue, format!("`{}` can only be called directly",
+                                                     self.ctxt.lookup_name(id)),
+                                             ident.pos());
+                        return None;
+                    }
+                }
+                Some(ty)
             }
             None => {
-                self.ctxt.emit_error(format!("no variable named `{}` is in scope",
-                                             self.ctxt.lookup_name(*ident.item())),
+                let name = self.ctxt.lookup_name(*ident.item());
+                let in_scope: Vec<Identifier> = {
+                    let names = self.ctxt.names.borrow();
+                    names.names()
+                         .map(|(id, _)| id)
+                         .filter(|&id| self.types.get_symbol(id).is_some())
+                         .collect()
+                };
+                let suggestion = self.did_you_mean(&name, in_scope.into_iter());
+                self.ctxt.emit_error(Code::UnknownVariable, format!("no variable named `{}` is in scope{}", name, suggestion),
                                      ident.pos());
                 None
             }
@@ -435,7 +1373,7 @@ impl<'a> TypeChecker<'a> {
         let lhs_ty = match self.typeof_expr(infix.left()) {
             Some(x) => x,
             None => {
-                self.ctxt.emit_error("type of infix lhs could not be determined",
+                self.ctxt.emit_error(Code::UndeterminedType, "type of infix lhs could not be determined",
                                      infix.left_pos());
                 return None;
             }
@@ -443,85 +1381,205 @@ impl<'a> TypeChecker<'a> {
         let rhs_ty = match self.typeof_expr(infix.right()) {
             Some(x) => x,
             None => {
-                self.ctxt.emit_error("type of infix rhs could not be determined",
+                self.ctxt.emit_error(Code::UndeterminedType, "type of infix rhs could not be determined",
                                      infix.right_pos());
                 return None;
             }
         };
+        if infix.op() == Operator::ApproxEqual && self.ctxt.options.borrow().strict {
+            self.ctxt.emit_error(Code::ImplicitTolerance,
+                                 "`~=` compares within a hidden tolerance, which strict mode forbids",
+                                 infix.pos());
+            self.ctxt.emit_help("give the tolerance, as in `a ~= b within 0.001`");
+        } else if let (Operator::ApproxEqual, &Expression::Constant(_), &Expression::Constant(_)) =
+               (infix.op(), infix.left(), infix.right()) {
+            self.ctxt.emit_warning(Code::ApproxEqualConstants,
+                                   "approximate comparison of two constants is always the same",
+                                   infix.pos());
+        }
+        match (self.unifier.resolve(lhs_ty), self.unifier.resolve(rhs_ty)) {
+            (lhs @ Type::Channels(_), rhs) | (lhs, rhs @ Type::Channels(_)) =>
+                return self.typeof_channel_infix(infix, lhs, rhs),
+            (lhs @ Type::Complex, rhs) | (lhs, rhs @ Type::Complex) =>
+                return self.typeof_complex_infix(infix, lhs, rhs),
+            _ => { }
+        }
         match infix.op() {
+            Operator::BitAnd | Operator::BitOr | Operator::ShiftLeft | Operator::ShiftRight =>
+                return self.typeof_bitwise_infix(infix, lhs_ty, rhs_ty),
+            // integer arithmetic keeps Ints whole, so needs no widening
+            Operator::Add | Operator::Sub | Operator::Mul | Operator::Mod
+                    if self.is_int(infix.left(), lhs_ty) && self.is_int(infix.right(), rhs_ty) &&
+                       (self.unifier.resolve(lhs_ty) == Type::Int || self.unifier.resolve(rhs_ty) == Type::Int) =>
+                return Some(Type::Int),
+            _ => { }
+        }
+        let (operand_ty, result_ty, context) = match infix.op() {
             Operator::Add |
             Operator::Sub |
             Operator::Mul |
             Operator::Div |
             Operator::Exp |
-            Operator::Mod => {
-                if lhs_ty == rhs_ty && (lhs_ty == Type::Number || lhs_ty == Type::Indeterminate) {
-                    Some(lhs_ty)
-                } else if lhs_ty == Type::Number && rhs_ty == Type::Indeterminate ||
-                          rhs_ty == Type::Number && lhs_ty == Type::Indeterminate {
-                    Some(Type::Number)
-                } else {
-                    self.ctxt.emit_error(format!(
-                        "cannot apply numerical operator to types `{}` and `{}`",
-                        lhs_ty, rhs_ty), infix.op_pos());
-                    None
-                }
-            }
+            Operator::Mod => (Some(Type::Number), Type::Number, "cannot apply numerical operator"),
             Operator::Less |
             Operator::ApproxEqual |
             Operator::GreaterEqual |
             Operator::LessEqual |
-            Operator::Greater => {
-                if lhs_ty == rhs_ty && lhs_ty == Type::Number {
-                    Some(Type::Boolean)
-                } else if lhs_ty == rhs_ty && lhs_ty == Type::Indeterminate {
-                    Some(Type::Indeterminate)
-                } else if lhs_ty == Type::Number && rhs_ty == Type::Indeterminate ||
-                          rhs_ty == Type::Number && lhs_ty == Type::Indeterminate {
-                    Some(Type::Boolean)
-                } else {
-                    self.ctxt.emit_error(format!(
-                        "cannot apply comparison operator to types `{}` and `{}`",
-                        lhs_ty, rhs_ty), infix.op_pos());
-                    None
+            Operator::Greater => (Some(Type::Number), Type::Boolean, "cannot apply comparison operator"),
+            Operator::Equal |
+            Operator::NotEqual => (None, Type::Boolean, "cannot apply equality operator"),
+            Operator::And |
+            Operator::Or |
+            Operator::Xor => (Some(Type::Boolean), Type::Boolean, "cannot apply logical operator"),
+            _ => {
+                unreachable!();
+            }
+        };
+        if operand_ty == Some(Type::Boolean) &&
+           (self.number_as_boolean(lhs_ty, infix.left_pos()) | self.number_as_boolean(rhs_ty, infix.right_pos())) {
+            return None;
+        }
+        match operand_ty {
+            Some(operand_ty) => {
+                if self.unify_or_emit(operand_ty, infix.op_pos(), lhs_ty, infix.left_pos(),
+                                      context, infix.op_pos()).is_none() ||
+                   self.unify_or_emit(operand_ty, infix.op_pos(), rhs_ty, infix.right_pos(),
+                                      context, infix.op_pos()).is_none() {
+                    return None;
                 }
             }
-            Operator::Equal |
-            Operator::NotEqual => {
-                if lhs_ty == rhs_ty &&
-                   (lhs_ty == Type::Number || lhs_ty == Type::Boolean) {
-                    Some(Type::Boolean)
-                } else if lhs_ty == rhs_ty && lhs_ty == Type::Indeterminate {
-                    Some(Type::Indeterminate)
-                } else if (lhs_ty == Type::Number || lhs_ty == Type::Boolean)
-                              && rhs_ty == Type::Indeterminate ||
-                          (rhs_ty == Type::Number || rhs_ty == Type::Boolean)
-                              && lhs_ty == Type::Indeterminate {
-                    Some(Type::Boolean)
+            None => {
+                // equality works on any two values of the same type, except functions, and an Int
+                // on the left widens to a Number on the right like one on the right does
+                let ((lhs_ty, lhs_pos), (rhs_ty, rhs_pos)) = if self.unifier.resolve(lhs_ty) == Type::Int {
+                    ((rhs_ty, infix.right_pos()), (lhs_ty, infix.left_pos()))
                 } else {
-                    self.ctxt.emit_error(format!(
-                        "cannot apply equality operator to types `{}` and `{}`",
-                        lhs_ty, rhs_ty), infix.op_pos());
-                    None
+                    ((lhs_ty, infix.left_pos()), (rhs_ty, infix.right_pos()))
+                };
+                match self.unify_or_emit(lhs_ty, lhs_pos, rhs_ty, rhs_pos, context, infix.op_pos()) {
+                    Some(Type::Function(_)) => {
+                        self.ctxt.emit_error(Code::TypeMismatch, format!("{} to functions", context), infix.op_pos());
+                        return None;
+                    }
+                    Some(Type::Str) => {
+                        self.ctxt.emit_error(Code::RuntimeString, format!("{} to strings", context), infix.op_pos());
+                        return None;
+                    }
+                    Some(Type::Array(_)) => {
+                        self.ctxt.emit_error(Code::TypeMismatch, format!("{} to arrays", context), infix.op_pos());
+                        return None;
+                    }
+                    Some(Type::Record(_)) => {
+                        self.ctxt.emit_error(Code::TypeMismatch, format!("{} to records", context), infix.op_pos());
+                        return None;
+                    }
+                    Some(_) => { }
+                    None => return None,
                 }
             }
-            Operator::And |
-            Operator::Or |
-            Operator::Xor => {
-                if lhs_ty == rhs_ty && (lhs_ty == Type::Boolean || lhs_ty == Type::Indeterminate) {
-                    Some(lhs_ty)
-                } else if lhs_ty == Type::Boolean && rhs_ty == Type::Indeterminate ||
-                          rhs_ty == Type::Boolean && lhs_ty == Type::Indeterminate {
-                    Some(Type::Boolean)
-                } else {
-                    self.ctxt.emit_error(format!(
-                        "cannot apply logical operator to types `{}` and `{}`",
-                        lhs_ty, rhs_ty), infix.op_pos());
-                    None
+        }
+        Some(result_ty)
+    }
+
+    // Whether an operand can be used as an Int, which a whole constant can.
+    fn is_int(&self, expr: &Expression, ty: Type) -> bool {
+        match (self.unifier.resolve(ty), self.const_value(expr)) {
+            (Type::Int, _) => true,
+            (Type::Number, Some(ConstValue::Number(x))) => x.is_finite() && x.fract() == 0.0,
+            _ => false,
+        }
+    }
+
+    fn typeof_bitwise_infix(&mut self, infix: &Node<Infix>, lhs_ty: Type, rhs_ty: Type) -> Option<Type> {
+        let mut valid = true;
+        for &(expr, ty, pos) in &[(infix.left(), lhs_ty, infix.left_pos()), (infix.right(), rhs_ty, infix.right_pos())] {
+            if !self.is_int(expr, ty) {
+                self.ctxt.emit_error(Code::TypeMismatch, format!("bitwise operators take `Int`s, found `{}`",
+                                                                 self.unifier.resolve(ty)),
+                                     pos);
+                if self.unifier.resolve(ty) == Type::Number {
+                    self.ctxt.emit_help("round a number down to an Int with `int`");
                 }
+                valid = false;
+            }
+        }
+        if valid { Some(Type::Int) } else { None }
+    }
+
+    // Complex numbers are added, subtracted, multiplied and divided with each other and with
+    // numbers, which are taken to have no imaginary part.
+    fn typeof_complex_infix(&mut self, infix: &Node<Infix>, lhs: Type, rhs: Type) -> Option<Type> {
+        let operand = |ty| ty == Type::Complex || ty == Type::Number;
+        if !operand(lhs) || !operand(rhs) {
+            self.ctxt.emit_error(Code::TypeMismatch, format!("cannot combine `{}` with `{}`", lhs, rhs),
+                                 infix.op_pos());
+            return None;
+        }
+        match infix.op() {
+            Operator::Add | Operator::Sub | Operator::Mul | Operator::Div => {
+                let func = self.ctxt.callstack.borrow().top();
+                self.ctxt.complex_ops.borrow_mut().insert((func, infix.op_pos().index));
+                Some(Type::Complex)
             }
             _ => {
-                unreachable!();
+                self.ctxt.emit_error(Code::TypeMismatch, format!("cannot combine `{}` with `{}`; complex numbers can \
+                                                                  only be added, subtracted, multiplied and divided",
+                                                                 lhs, rhs),
+                                     infix.op_pos());
+                None
+            }
+        }
+    }
+
+    // Signals with several channels are mixed by adding them and scaled by multiplying them,
+    // channel by channel. A number scales every channel, but must be made into a signal with the
+    // same channels before it is added to one, so that it is clear where it should be heard.
+    fn typeof_channel_infix(&mut self, infix: &Node<Infix>, lhs: Type, rhs: Type) -> Option<Type> {
+        let count = |ty| match ty {
+            Type::Channels(count) => Some(count),
+            Type::Number => Some(1),
+            _ => None,
+        };
+        let (lhs_count, rhs_count) = match (count(lhs), count(rhs)) {
+            (Some(lhs), Some(rhs)) => (lhs, rhs),
+            _ => {
+                self.ctxt.emit_error(Code::TypeMismatch, format!("cannot combine `{}` with `{}`", lhs, rhs),
+                                     infix.op_pos());
+                return None;
+            }
+        };
+        let ty = Type::Channels(cmp::max(lhs_count, rhs_count));
+        match infix.op() {
+            Operator::Add | Operator::Sub | Operator::Mul if lhs_count == rhs_count => Some(ty),
+            Operator::Mul if lhs_count == 1 || rhs_count == 1 => Some(ty),
+            Operator::Div if rhs_count == 1 => Some(ty),
+            Operator::Add | Operator::Sub => {
+                self.ctxt.emit_error(Code::ChannelMismatch, format!("cannot mix a signal of {} channel{} with one of {}",
+                                                                    lhs_count, if lhs_count == 1 { "" } else { "s" },
+                                                                    rhs_count),
+                                     infix.op_pos());
+                if lhs_count == 1 || rhs_count == 1 {
+                    self.ctxt.emit_help("make the number a signal with `pan` or `stereo` first");
+                }
+                None
+            }
+            Operator::Mul => {
+                self.ctxt.emit_error(Code::ChannelMismatch, format!("cannot multiply a signal of {} channels by one of {}",
+                                                                    lhs_count, rhs_count),
+                                     infix.op_pos());
+                None
+            }
+            Operator::Div => {
+                self.ctxt.emit_error(Code::ChannelMismatch, format!("cannot divide by a signal of {} channels", rhs_count),
+                                     infix.op_pos());
+                None
+            }
+            _ => {
+                self.ctxt.emit_error(Code::TypeMismatch, format!("cannot combine `{}` with `{}`; signals of several channels \
+                                                                  can only be added, subtracted, multiplied and divided",
+                                                                 lhs, rhs),
+                                     infix.op_pos());
+                None
             }
         }
     }
@@ -530,32 +1588,41 @@ impl<'a> TypeChecker<'a> {
         let expr_ty = match self.typeof_expr(prefix.expr()) {
             Some(x) => x,
             None => {
-                self.ctxt.emit_error("type of prefix expression could not be determined", prefix.expr_pos());
+                self.ctxt.emit_error(Code::UndeterminedType, "type of prefix expression could not be determined", prefix.expr_pos());
                 return None;
             }
         };
-        match prefix.op() {
-            Operator::Sub => {
-                if expr_ty == Type::Number || expr_ty == Type::Indeterminate {
-                    Some(Type::Number)
-                } else {
-                    self.ctxt.emit_error(format!("expected `Number`, got `{}`", expr_ty),
-                                         prefix.expr_pos());
-                    None
-                }
-            }
-            Operator::Not => {
-                if expr_ty == Type::Boolean || expr_ty == Type::Indeterminate {
-                    Some(Type::Boolean)
-                } else {
-                    self.ctxt.emit_error(format!("expected `Boolean`, got `{}`", expr_ty),
-                                         prefix.expr_pos());
-                    None
+        let ty = match (prefix.op(), self.unifier.resolve(expr_ty)) {
+            (Operator::Sub, ty @ Type::Channels(_)) => return Some(ty),
+            (Operator::Sub, Type::Complex) => return Some(Type::Complex),
+            (Operator::Sub, Type::Int) => return Some(Type::Int),
+            (Operator::Sub, _) => Type::Number,
+            (Operator::Not, ty) => {
+                if self.number_as_boolean(ty, prefix.expr_pos()) {
+                    return None;
                 }
+                Type::Boolean
             }
             _ => {
                 unreachable!();
             }
-        }
+        };
+        self.unify_or_emit(ty, prefix.op_pos(), expr_ty, prefix.expr_pos(),
+                           "cannot apply prefix operator", prefix.expr_pos())
+    }
+}
+
+// Suggests whichever of `names` are similar to a name which was not found.
+// The identifiers of a function's arguments in the order they were declared.
+fn declared_order(args: &ArgumentList) -> Vec<Identifier> {
+    args.iter().filter_map(|x| x.ident()).collect()
+}
+
+fn did_you_mean(name: &str, names: Vec<String>) -> String {
+    let similar = ident::similar_names(name, names.into_iter());
+    if similar.is_empty() {
+        return String::new();
     }
+    let similar: Vec<String> = similar.iter().map(|x| format!("`{}`", x)).collect();
+    format!("; did you mean {}?", similar.join(" or "))
 }
@@ -1,5 +1,7 @@
 use super::ident::Identifier;
+use super::record::ShapeId;
 use super::scope::ScopedTable;
+use super::tokens::SourcePos;
 
 use vec_map::VecMap;
 use std::fmt;
@@ -7,22 +9,200 @@ use std::fmt;
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Type {
     Number,
+    /// A whole number, such as an index or a count, which widens to a `Number` wherever one is
+    /// expected. See int.rs.
+    Int,
     Boolean,
+    /// A string literal, represented at runtime by its index among the literals of the source.
+    Str,
     Function(Identifier),
+    /// An array of numbers of the given length, written `[1, 2, 3]`. Builtins which take arrays
+    /// declare them with a length of 0, and accept arrays of any length.
+    Array(usize),
+    /// A signal with a value for each of several output channels, such as the left and right of
+    /// a stereo signal, made by builtins like `pan` and `stereo`. A number is a single channel.
+    /// Builtins which take signals declare them with 0 channels, and accept any number.
+    Channels(usize),
+    /// A record of numbers, such as `{freq: 440, amp: 0.5}`, of the shape with the given id. See
+    /// record.rs.
+    Record(ShapeId),
+    /// A complex number, made by builtins like `complex` and `analytic`. See dsp/complex.rs.
+    Complex,
 
-    /// With recursive functions, it may not be possible to tell exactly what the type is without
-    /// further information. This is different from a None type, which means that this is a
-    /// logically inconsistent or indeterminable type. The user should never see this.
-    Indeterminate,
+    /// A type that has not been inferred yet, such as the return type of a recursive call that
+    /// is still being checked. These are resolved through a `Unifier` and the user should never
+    /// see one in a successfully checked program.
+    Var(TypeVar),
 }
 
+pub type TypeVar = usize;
+
 impl fmt::Display for Type {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Type::Number => write!(f, "Number"),
+            Type::Int => write!(f, "Int"),
             Type::Boolean => write!(f, "Boolean"),
+            Type::Str => write!(f, "String"),
             Type::Function(_) => write!(f, "Function"),
-            Type::Indeterminate => write!(f, "Indeterminate"),
+            Type::Array(len) => write!(f, "Array({})", len),
+            Type::Channels(2) => write!(f, "Stereo"),
+            Type::Channels(count) => write!(f, "Channels({})", count),
+            Type::Record(_) => write!(f, "Record"),
+            Type::Complex => write!(f, "Complex"),
+            Type::Var(_) => write!(f, "_"),
+        }
+    }
+}
+
+/// Two types which could not be unified, along with where each was inferred.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TypeMismatch {
+    pub expected: Type,
+    pub expected_pos: SourcePos,
+    pub found: Type,
+    pub found_pos: SourcePos,
+}
+
+impl fmt::Display for TypeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected type `{}` (from {}), found `{}` (at {})",
+               self.expected, self.expected_pos, self.found, self.found_pos)
+    }
+}
+
+/// Holds the bindings of type variables, along with the position each binding was inferred
+/// from so that conflicts can point at both sides, and the signatures of the functions whose
+/// types are unified.
+#[derive(Clone, Debug)]
+pub struct Unifier {
+    bindings: Vec<Option<(Type, SourcePos)>>,
+    signatures: VecMap<FunctionType>, // from function Identifier
+    unifying: Vec<(Identifier, Identifier)>, // functions whose signatures are being unified
+}
+
+impl Unifier {
+    pub fn new() -> Unifier {
+        Unifier {
+            bindings: Vec::new(),
+            signatures: VecMap::new(),
+            unifying: Vec::new(),
+        }
+    }
+
+    /// Records the signature of a function, so that it can be unified with others. Functions
+    /// without one unify with any function.
+    pub fn set_signature(&mut self, func: Identifier, ty: FunctionType) {
+        self.signatures.insert(func, ty);
+    }
+
+    /// Creates a new unbound type variable.
+    pub fn fresh(&mut self) -> Type {
+        self.bindings.push(None);
+        Type::Var(self.bindings.len() - 1)
+    }
+
+    /// Follows the bindings of a type variable to the most specific type known.
+    pub fn resolve(&self, ty: Type) -> Type {
+        self.resolve_at(ty, SourcePos::anon()).0
+    }
+
+    fn resolve_at(&self, mut ty: Type, mut pos: SourcePos) -> (Type, SourcePos) {
+        while let Type::Var(var) = ty {
+            match self.bindings[var] {
+                Some((bound, bound_pos)) => {
+                    ty = bound;
+                    pos = bound_pos;
+                }
+                None => break,
+            }
+        }
+        (ty, pos)
+    }
+
+    /// Checks if binding `var` to `ty` would make the variable refer to itself, directly or
+    /// through the signature of a function.
+    fn occurs(&self, var: TypeVar, ty: Type) -> bool {
+        self.occurs_in(var, ty, &mut Vec::new())
+    }
+
+    fn occurs_in(&self, var: TypeVar, ty: Type, seen: &mut Vec<Identifier>) -> bool {
+        match self.resolve(ty) {
+            Type::Var(x) => x == var,
+            Type::Function(func) if !seen.contains(&func) => {
+                seen.push(func);
+                match self.signatures.get(func) {
+                    Some(sig) => sig.args.values().chain(Some(&sig.returns))
+                                    .any(|&ty| self.occurs_in(var, ty, seen)),
+                    None => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    // Unifies the arguments, in the order they were declared, and the results of two functions.
+    fn unify_signatures(&mut self, a: Identifier, a_pos: SourcePos, b: Identifier, b_pos: SourcePos)
+            -> Result<(), TypeMismatch> {
+        // functions which take each other are assumed to match while they are compared
+        let (a_sig, b_sig) = match (self.signatures.get(a), self.signatures.get(b)) {
+            (Some(x), Some(y)) if a != b && !self.unifying.contains(&(a, b)) && !self.unifying.contains(&(b, a)) => {
+                (x.clone(), y.clone())
+            }
+            _ => return Ok(()),
+        };
+        if a_sig.args.len() != b_sig.args.len() {
+            return Err(TypeMismatch {
+                expected: Type::Function(a),
+                expected_pos: a_pos,
+                found: Type::Function(b),
+                found_pos: b_pos,
+            });
+        }
+        self.unifying.push((a, b));
+        let mut res = Ok(());
+        let pairs = a_sig.ordered_args().into_iter().zip(b_sig.ordered_args())
+                         .chain(Some((a_sig.returns, b_sig.returns)));
+        for (x, y) in pairs {
+            res = self.unify(x, a_pos, y, b_pos).map(|_| ());
+            if res.is_err() {
+                break;
+            }
+        }
+        self.unifying.pop();
+        res
+    }
+
+    /// Makes two types equal, binding type variables as necessary. Returns the unified type.
+    pub fn unify(&mut self, expected: Type, expected_pos: SourcePos, found: Type, found_pos: SourcePos)
+            -> Result<Type, TypeMismatch> {
+        let (expected, expected_pos) = self.resolve_at(expected, expected_pos);
+        let (found, found_pos) = self.resolve_at(found, found_pos);
+        let mismatch = TypeMismatch {
+            expected: expected,
+            expected_pos: expected_pos,
+            found: found,
+            found_pos: found_pos,
+        };
+        match (expected, found) {
+            (Type::Var(a), Type::Var(b)) if a == b => Ok(expected),
+            // a variable can't stand for a function which takes or returns it
+            (Type::Var(var), ty) | (ty, Type::Var(var)) if self.occurs(var, ty) => Err(mismatch),
+            (Type::Var(var), ty) => {
+                self.bindings[var] = Some((ty, found_pos));
+                Ok(ty)
+            }
+            (ty, Type::Var(var)) => {
+                self.bindings[var] = Some((ty, expected_pos));
+                Ok(ty)
+            }
+            (Type::Function(a), Type::Function(b)) => {
+                try!(self.unify_signatures(a, expected_pos, b, found_pos));
+                Ok(expected)
+            }
+            (Type::Number, Type::Int) => Ok(Type::Number),
+            (a, b) if a == b => Ok(a),
+            _ => Err(mismatch),
         }
     }
 }
@@ -30,16 +210,31 @@ impl fmt::Display for Type {
 #[derive(Clone, Debug)]
 pub struct FunctionType {
     pub args: VecMap<Type>, // From Identifier to Type
+    pub order: Vec<Identifier>, // the arguments in the order they were declared
     pub returns: Type,
 }
 
 impl FunctionType {
     pub fn new(args: VecMap<Type>, returns: Type) -> FunctionType {
+        let order = args.keys().collect();
+        FunctionType::with_order(args, order, returns)
+    }
+
+    /// Creates the type of a function whose arguments were declared in the order of `order`
+    /// rather than the order of their identifiers.
+    pub fn with_order(args: VecMap<Type>, order: Vec<Identifier>, returns: Type) -> FunctionType {
         FunctionType {
             args: args,
+            order: order,
             returns: returns,
         }
     }
+
+    /// The types of the arguments in the order they were declared, which is the order they are
+    /// given in positionally.
+    pub fn ordered_args(&self) -> Vec<Type> {
+        self.order.iter().filter_map(|&id| self.args.get(id).cloned()).collect()
+    }
 }
 
 pub type TypeTable = ScopedTable<Type>;
@@ -51,12 +246,16 @@ macro_rules! make_fn_ty {
         use $crate::types::FunctionType;
         use $crate::types::Type::*;
         let mut arg_map = VecMap::new();
+        let mut order = Vec::new();
         $(
-            arg_map.insert($ctxt.names.borrow_mut().new_id(stringify!($name)), $ty);
+            let id = $ctxt.names.borrow_mut().new_id(stringify!($name));
+            arg_map.insert(id, $ty);
+            order.push(id);
         )*
         FunctionType {
             returns: $ret,
-            args: arg_map
+            args: arg_map,
+            order: order,
         }
     }}
 }
@@ -0,0 +1,58 @@
+extern crate interpreter;
+
+use interpreter::audio::{speaker_azimuths, OutputFormat};
+use interpreter::audio::binaural::{head_response, Binaural};
+use interpreter::dsp::ambisonics;
+
+use std::f64::consts::PI;
+
+#[test]
+fn speaker_rings() {
+    let square = speaker_azimuths(4);
+    for (a, b) in square.iter().zip(&[PI / 4.0, -PI / 4.0, 3.0 * PI / 4.0, -3.0 * PI / 4.0]) {
+        assert!((a - b).abs() < 1e-12, "{:?}", square);
+    }
+    assert_eq!(speaker_azimuths(2), vec![PI / 2.0, -PI / 2.0]);
+    assert_eq!(speaker_azimuths(3)[0], 0.0);
+    assert_eq!(speaker_azimuths(5).len(), 5);
+
+    assert_eq!(OutputFormat::parse("ambisonics-foa"), Some(OutputFormat::AmbisonicsFoa));
+    assert_eq!(OutputFormat::parse("binaural"), None);
+    assert!(OutputFormat::AmbisonicsFoa.check(4).is_ok());
+    assert!(OutputFormat::Binaural.check(2).unwrap_err().contains("the program gives 2"));
+    assert!(OutputFormat::Channels.check(2).is_ok());
+}
+
+#[test]
+fn head_shadows_the_far_ear() {
+    // at 0 Hz the head makes no difference, but the far ear hears a source later
+    let near = head_response(PI / 2.0, PI / 2.0, 48000);
+    let far = head_response(PI / 2.0, -PI / 2.0, 48000);
+    let gain = |x: &[f64]| x.iter().fold(0.0, |sum, x| sum + x);
+    assert!((gain(&near) - 1.0).abs() < 1e-6);
+    assert!((gain(&far) - 1.0).abs() < 1e-6);
+    let first = |x: &[f64]| x.iter().position(|x| x.abs() > 1e-3).unwrap();
+    assert_eq!(first(&near), 0);
+    assert!(first(&far) > 20);
+}
+
+#[test]
+fn binaural_sources_are_heard_on_their_side() {
+    let energy = |azimuth: f64| {
+        let mut binaural = Binaural::new(48000);
+        let (mut left, mut right) = (0.0, 0.0);
+        for i in 0..1000 {
+            let b = ambisonics::encode(if i == 0 { 1.0 } else { 0.0 }, azimuth, 0.0);
+            let (l, r) = binaural.process(&b);
+            left += l * l;
+            right += r * r;
+        }
+        (left, right)
+    };
+    let (left, right) = energy(PI / 2.0);
+    assert!(left > 4.0 * right, "{} {}", left, right);
+    let (left, right) = energy(-PI / 2.0);
+    assert!(right > 4.0 * left, "{} {}", left, right);
+    let (left, right) = energy(0.0);
+    assert!((left - right).abs() < 1e-9, "{} {}", left, right);
+}
@@ -0,0 +1,49 @@
+extern crate interpreter;
+
+use interpreter::common::Context;
+use interpreter::compiler::Compiler;
+use interpreter::runtime::Program;
+
+#[test]
+fn sections_follow_the_bar() {
+    // at the default 120 bpm a bar lasts two seconds
+    let ctxt = Context::new("<test>".into(), r"
+        low time { 1 }
+        high time { 3 }
+        arrange main fade 1bar { 0bar: low; 2bar: high; }
+        arrange later { 1bar: high; 3bar: low }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    compiler.declare_entrypoint("later");
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+
+    let mut program = Program::new(&compiler, "main", 10).unwrap();
+    assert_eq!(program.eval(1.0), 1.0);
+    assert_eq!(program.eval(4.0), 1.0);
+    // halfway through the fade
+    assert_eq!(program.eval(5.0), 2.0);
+    assert_eq!(program.eval(8.0), 3.0);
+
+    let mut program = Program::new(&compiler, "later", 10).unwrap();
+    assert_eq!(program.eval(1.0), 0.0);
+    assert_eq!(program.eval(3.0), 3.0);
+    assert_eq!(program.eval(7.0), 1.0);
+}
+
+#[test]
+fn invalid_arrangements() {
+    for &(source, msg) in &[
+        ("arrange main { 4bar: a; 2bar: b; }", "the section at bar 2 must come after the one at bar 4"),
+        ("arrange main fade 2bar { 0bar: a; 1bar: b; }", "the section at bar 0 is shorter than the fade of 2 bars"),
+        ("arrange main { 0bar: a; 2: b; }", "expected `bar` after the number of bars"),
+    ] {
+        let ctxt = Context::new("<test>".into(), format!("a time {{ 1 }} b time {{ 2 }} {}", source));
+        let mut compiler = Compiler::new(&ctxt);
+        compiler.declare_entrypoint("main");
+        let issues = compiler.compile().err().unwrap().to_string();
+        assert!(issues.contains(msg), "{}", issues);
+    }
+}
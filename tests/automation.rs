@@ -0,0 +1,58 @@
+extern crate interpreter;
+
+use interpreter::common::Context;
+use interpreter::compiler::Compiler;
+use interpreter::runtime::Program;
+use interpreter::automation::Automation;
+
+#[test]
+fn parse_automation() {
+    let automation = Automation::from_json(r#"[
+        {"time": 1, "param": "gain", "value": 2},
+        {"time": 0, "param": "cutoff", "value": 200, "ramp": true}
+    ]"#).unwrap();
+    assert_eq!(automation.params(), vec!["gain", "cutoff"]);
+
+    let err = Automation::from_json(r#"[{"time": -1, "param": "gain", "value": 2}]"#).err().unwrap();
+    assert!(err.contains("`gain`"), "{}", err);
+    assert!(Automation::from_json(r#"{"gain": 2}"#).is_err());
+}
+
+#[test]
+fn automated_params() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time, a = -1, b = -1 { a * 100 + b }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+
+    let automation = Automation::from_json(r#"[
+        {"time": 0.5, "param": "a", "value": 1},
+        {"time": 1, "param": "a", "value": 3, "ramp": true},
+        {"time": 1.25, "param": "a", "value": 5},
+        {"time": 0.25, "param": "b", "value": 7}
+    ]"#).unwrap();
+    let mut program = Program::new(&compiler, "main", 4).unwrap();
+    program.set_automation(automation.clone()).unwrap();
+    let samples: Vec<_> = (0..6).map(|_| program.next()).collect();
+    assert_eq!(samples, vec![-101.0, -93.0, 107.0, 207.0, 307.0, 507.0]);
+
+    // seeking back starts over from the first point
+    program.seek(3);
+    assert_eq!(program.next(), 207.0);
+
+    let ctxt = Context::new("<test>".into(), r"
+        main time, a = 0 { a }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+    let mut program = Program::new(&compiler, "main", 10).unwrap();
+    let err = program.set_automation(automation).err().unwrap();
+    assert_eq!(err, "the entrypoint has no parameter `b` to automate");
+}
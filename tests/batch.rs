@@ -0,0 +1,58 @@
+extern crate interpreter;
+
+use interpreter::batch::{Manifest, render_batch};
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+
+#[test]
+fn manifest_groups() {
+    let manifest = Manifest::from_json(r#"[
+        {"input": "kick.syn", "output": "kicks/soft.wav", "defines": {"decay": 0.2}},
+        {"input": "hat.syn", "output": "hats/1.wav", "seed": 1, "length": 0.5},
+        {"input": "kick.syn", "output": "kicks/soft-2.wav", "defines": {"decay": 0.2}, "seed": 2},
+        {"input": "hat.syn", "output": "hats/2.wav", "seed": 2, "sample_rate": 48000}
+    ]"#, Path::new("pack")).unwrap();
+    assert_eq!(manifest.jobs[0].input, Path::new("pack").join("kick.syn").to_string_lossy());
+    assert_eq!(manifest.jobs[1].length, Some(0.5));
+    // jobs only share a compilation with the same input, defines and sample rate
+    assert_eq!(manifest.groups(), vec![vec![0, 2], vec![1], vec![3]]);
+
+    assert!(Manifest::from_json(r#"[{"input": "kick.syn"}]"#, Path::new("")).is_err());
+}
+
+#[test]
+fn failed_jobs() {
+    let manifest = Manifest::from_json(r#"[
+        {"input": "no such file.syn", "output": "out.wav"},
+        {"input": "no such file.syn", "output": "out-2.wav", "seed": 1}
+    ]"#, Path::new("")).unwrap();
+    let results = render_batch(&manifest, 2);
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|x| x.as_ref().err().map_or(false, |e| e.contains("no such file.syn"))));
+}
+
+#[test]
+fn jobs_of_one_compilation_render_alike() {
+    // both jobs share the globals of one compiler, including the memo cache of `shape`
+    let dir = env::temp_dir().join("synthizer-batch-test");
+    fs::create_dir_all(&dir).unwrap();
+    File::create(dir.join("memo.syn")).unwrap()
+        .write_all(b"shape x { sin(x * 2) + 1 }\nmain time { shape(time) * 0.2 + shape(time) * sample_index / 100000 }\n")
+        .unwrap();
+    let manifest = Manifest::from_json(r#"[
+        {"input": "memo.syn", "output": "a.wav", "length": 1},
+        {"input": "memo.syn", "output": "b.wav", "length": 1}
+    ]"#, &dir).unwrap();
+    let results = render_batch(&manifest, 2);
+    assert!(results.iter().all(|x| x.is_ok()));
+    let read = |name: &str| {
+        let mut bytes = Vec::new();
+        File::open(dir.join(name)).unwrap().read_to_end(&mut bytes).unwrap();
+        bytes
+    };
+    assert!(read("a.wav") == read("b.wav"));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,84 @@
+extern crate interpreter;
+
+use interpreter::common::Context;
+use interpreter::compiler::Compiler;
+use interpreter::dsp::bytebeat::{byte_sample, BYTEBEAT_RATE};
+use interpreter::runtime::Program;
+
+// Classic formulas, each as written here and as C evaluates it with a 32 bit `int t`.
+const FORMULAS: &'static [(&'static str, fn(i32) -> i32)] = &[
+    ("wmul(t, t >> 5 | t >> 8) >> (t >> 16)",
+     c_formula_1),
+    ("wmul(t, (t >> 12 | t >> 8) & 63 & t >> 4)",
+     c_formula_2),
+    ("t * 5 & t >> 7 | t * 3 & t >> 10",
+     c_formula_3),
+    ("wsub(wmul(t, xor(t >> 6, t >> 8 | t >> 12)), t >> 3)",
+     c_formula_4),
+];
+
+// t*(t>>5|t>>8)>>(t>>16)
+fn c_formula_1(t: i32) -> i32 {
+    t.wrapping_mul(t >> 5 | t >> 8) >> (t >> 16)
+}
+
+// t*((t>>12|t>>8)&63&t>>4)
+fn c_formula_2(t: i32) -> i32 {
+    t.wrapping_mul((t >> 12 | t >> 8) & 63 & t >> 4)
+}
+
+// (t*5&t>>7)|(t*3&t>>10)
+fn c_formula_3(t: i32) -> i32 {
+    (t * 5 & t >> 7) | (t * 3 & t >> 10)
+}
+
+// t*(t>>6^(t>>8|t>>12))-(t>>3)
+fn c_formula_4(t: i32) -> i32 {
+    t.wrapping_mul(t >> 6 ^ (t >> 8 | t >> 12)).wrapping_sub(t >> 3)
+}
+
+#[test]
+fn formulas_match_c() {
+    for &(formula, reference) in FORMULAS {
+        let source = format!("main time {{ t = int(time * {}); byte({}) }}", BYTEBEAT_RATE, formula);
+        let ctxt = Context::new("<test>".into(), source);
+        let mut compiler = Compiler::new(&ctxt);
+        compiler.declare_entrypoint("main");
+        if let Err(issues) = compiler.compile() {
+            panic!("compile failed for `{}`:\n{}", formula, issues);
+        }
+        let mut program = Program::new(&compiler, "main", BYTEBEAT_RATE as u32).unwrap();
+        // on past t = 65536, where the first formula starts shifting its product
+        for t in (0..100000).filter(|t| t % 7 == 0) {
+            // half a sample in, so that the time rounds down to `t`
+            let time = (t as f64 + 0.5) / BYTEBEAT_RATE;
+            assert_eq!(program.eval(time), byte_sample(reference(t) as f64), "`{}` at t = {}", formula, t);
+        }
+    }
+}
+
+#[test]
+fn wrapping_arithmetic() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time {
+            ok = (wadd(2147483647, 1) == -2147483648) && (wsub(-2147483648, 1) == 2147483647) &&
+                 (wmul(65536, 65536) == 0) && (wmul(int(time), 3) == 9) && (xor(12, 10) == 6);
+            1 if ok else 0
+        }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+    let mut program = Program::new(&compiler, "main", 10).unwrap();
+    assert_eq!(program.eval(3.0), 1.0);
+}
+
+#[test]
+fn byte_takes_the_low_bits() {
+    assert_eq!(byte_sample(0.0), -1.0);
+    assert_eq!(byte_sample(128.0), 0.0);
+    assert_eq!(byte_sample(256.0 + 64.0), -0.5);
+    assert_eq!(byte_sample(-1.0), 127.0 / 128.0);
+}
@@ -35,6 +35,8 @@ fn comparisons() {
             f = 1 != 2;
             g = true == false;
             h = true != false;
+            i = 0.1 + 0.2 ~= 0.3;
+            j = 1 ~= 1.05 within 0.1;
 
         "
     );
@@ -364,3 +366,35 @@ fn complex_closure_capture() {
             x = fn(3)(2);
         ");
 }
+
+#[test]
+fn generic_functions() {
+    run_test!(
+        should_pass(lex, parse, typecheck, codegen)
+        => r"
+            twice f, x {
+                f(f(x))
+            }
+            a = twice(\x { x*2 }, 1);
+            b = twice(\b { !b }, true);
+        ");
+}
+
+#[test]
+fn source_map() {
+    use interpreter::common::Context;
+    use interpreter::compiler::Compiler;
+
+    let ctxt = Context::new("<test>".into(), "f x { x * 2 + 1 }\ny = f(3);\n".into());
+    let mut compiler = Compiler::new(&ctxt);
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+    let map = compiler.source_map();
+    let f = map.function("f").unwrap();
+    let lines: Vec<_> = f.instructions.iter().filter_map(|x| x.map(|pos| pos.line)).collect();
+    assert!(!lines.is_empty());
+    assert!(lines.iter().all(|&line| line == 1));
+    let init = map.function("*globalinit*").unwrap();
+    assert!(init.instructions.iter().any(|x| x.map_or(false, |pos| pos.line == 2)));
+}
@@ -0,0 +1,14 @@
+extern crate interpreter;
+
+use interpreter::codes::Code;
+
+#[test]
+fn error_codes_are_unique_and_explained() {
+    for (i, code) in Code::all().iter().enumerate() {
+        assert!(!code.explanation().is_empty());
+        assert_eq!(Code::parse(code.as_str()), Some(*code));
+        assert!(Code::all()[i+1..].iter().all(|x| x.as_str() != code.as_str()));
+    }
+    assert_eq!(Code::parse("e101"), Some(Code::TypeMismatch));
+    assert_eq!(Code::parse("E999"), None);
+}
@@ -0,0 +1,49 @@
+extern crate interpreter;
+
+use interpreter::completions::{CommandSpec, Shell};
+
+static USAGE: &'static str = "
+Usage:
+  synthizer stream <input> [--oversample=<n>] [--profile]
+  synthizer ast <input> [--format=<fmt>]
+  synthizer explain <code>
+  synthizer --help
+
+Options:
+  -h, --help             Show this message.
+  -f, --format=<fmt>     AST output format [default: json].
+  -o, --oversample=<n>   Render at n times the sample rate [default: 1].
+  -p, --profile          Report the time spent in each function.
+";
+
+#[test]
+fn parse_usage() {
+    let spec = CommandSpec::parse(USAGE);
+    assert_eq!(spec.program, "synthizer");
+    let names: Vec<_> = spec.subcommands.iter().map(|x| &x.name[..]).collect();
+    assert_eq!(names, vec!["stream", "ast", "explain"]);
+    assert_eq!(spec.subcommands[0].flags, vec!["oversample".to_string(), "profile".to_string()]);
+    assert!(spec.subcommands[0].takes_files);
+    assert!(!spec.subcommands[2].takes_files);
+
+    let oversample = spec.flag("oversample").unwrap();
+    assert_eq!(oversample.short, Some('o'));
+    assert!(oversample.takes_value);
+    assert!(!spec.flag("profile").unwrap().takes_value);
+    assert_eq!(spec.flag("help").unwrap().description, "Show this message.");
+}
+
+#[test]
+fn scripts_cover_subcommands_and_flags() {
+    let spec = CommandSpec::parse(USAGE);
+    for shell in &["bash", "zsh", "fish", "powershell"] {
+        let script = spec.completions(Shell::parse(shell).unwrap());
+        for word in &["stream", "ast", "explain", "oversample", "format"] {
+            assert!(script.contains(word), "{} completions are missing {}", shell, word);
+        }
+    }
+    assert!(spec.completions(Shell::Bash).contains("complete -o filenames -F _synthizer synthizer"));
+    assert!(spec.completions(Shell::Fish)
+        .contains("complete -c synthizer -n '__fish_seen_subcommand_from ast' -l format -s f -r"));
+    assert_eq!(Shell::parse("tcsh"), None);
+}
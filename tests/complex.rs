@@ -0,0 +1,53 @@
+extern crate interpreter;
+
+use interpreter::common::Context;
+use interpreter::compiler::Compiler;
+use interpreter::dsp::complex;
+use interpreter::runtime::Program;
+
+use std::f64::consts::PI;
+
+#[test]
+fn complex_arithmetic() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time {
+            z = complex(3, 4) * complex(1, 2) / complex(0, 1) - 1 + -complex(0, 1);
+            real(z) * 1000 + imag(z) * 10 + mag(complex(3, 4)) + arg(conj(polar(2, 0.25)))
+        }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+    // (3 + 4i)(1 + 2i) / i - 1 - i = (-5 + 10i) / i - 1 - i = 9 + 4i
+    let mut program = Program::new(&compiler, "main", 10).unwrap();
+    assert!((program.eval(0.0) - (9000.0 + 40.0 + 5.0 - 0.25)).abs() < 1e-9);
+}
+
+#[test]
+fn analytic_signal_has_constant_magnitude() {
+    let mut mem = vec![0.0; complex::ANALYTIC_MEMORY];
+    let freq = 1000.0 / 44100.0;
+    for i in 0..4000 {
+        let (re, im) = complex::analytic((2.0 * PI * freq * i as f64).sin(), &mut mem);
+        if i > 2000 {
+            assert!((re.hypot(im) - 1.0).abs() < 0.01, "sample {}: {}", i, re.hypot(im));
+        }
+    }
+}
+
+#[test]
+fn invalid_complex_operations() {
+    for &(source, msg) in &[
+        ("main time { real(complex(1, 0) < 1) }", "complex numbers can only be added"),
+        ("main time { real(complex(1, 0) + stereo(1, 1)) }", "cannot combine"),
+        ("main time { real(time) }", "error[E"),
+    ] {
+        let ctxt = Context::new("<test>".into(), source.into());
+        let mut compiler = Compiler::new(&ctxt);
+        compiler.declare_entrypoint("main");
+        let issues = compiler.compile().err().unwrap().to_string();
+        assert!(issues.contains(msg), "{}", issues);
+    }
+}
@@ -0,0 +1,70 @@
+extern crate interpreter;
+
+use interpreter::audio::Precision;
+use interpreter::common::Context;
+use interpreter::config::{Config, CONFIG_FILENAME};
+use std::env;
+use std::fs::{self, File};
+use std::path::PathBuf;
+
+#[test]
+fn parse_config() {
+    let source = r#"
+# render settings
+sample_rate = 48_000
+channels = 2
+length = 12.5  # seconds
+include_paths = ["lib", "vendor/#shared"]
+entrypoint = "voice"
+"#;
+    let config = Config::parse(source, PathBuf::from("project")).unwrap();
+    assert_eq!(config.sample_rate, Some(48000));
+    assert_eq!(config.channels, Some(2));
+    assert_eq!(config.length, Some(12.5));
+    assert_eq!(config.oversample, None);
+    assert_eq!(config.include_paths, vec![PathBuf::from("project/lib"),
+                                          PathBuf::from("project/vendor/#shared")]);
+    assert_eq!(config.entrypoint, Some("voice".to_string()));
+
+    let settings = config.render_settings(44100);
+    assert_eq!(settings.sample_rate, 48000);
+    assert_eq!(settings.channels, 2);
+    assert_eq!(settings.oversample, 1);
+    assert_eq!(settings.precision, Precision::Single);
+}
+
+#[test]
+fn precision_config() {
+    let config = Config::parse("precision = \"double\"", PathBuf::new()).unwrap();
+    assert_eq!(config.render_settings(44100).precision, Precision::Double);
+    assert!(Config::parse("precision = \"half\"", PathBuf::new()).is_err());
+}
+
+#[test]
+fn invalid_config() {
+    let root = PathBuf::new();
+    assert!(Config::parse("sample_rate = \"fast\"", root.clone()).is_err());
+    assert!(Config::parse("channels = 0", root.clone()).is_err());
+    assert!(Config::parse("volume = 11", root.clone()).unwrap_err().contains("unknown setting"));
+    assert!(Config::parse("length", root.clone()).unwrap_err().starts_with("line 1"));
+}
+
+#[test]
+fn files_are_found_in_the_project() {
+    let dir = env::temp_dir().canonicalize().unwrap().join("synthizer-include-test");
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::create_dir_all(dir.join("songs")).unwrap();
+    File::create(dir.join(CONFIG_FILENAME)).unwrap();
+    File::create(dir.join("lib/hall.wav")).unwrap();
+    File::create(dir.join("songs/room.wav")).unwrap();
+
+    // the file needn't exist yet for its project to be found
+    assert_eq!(Config::find(&dir.join("songs/tune.syn")), Some(dir.join(CONFIG_FILENAME)));
+
+    let ctxt = Context::new(dir.join("songs/tune.syn").to_string_lossy().into_owned(), String::new());
+    ctxt.options.borrow_mut().include_paths = vec![dir.join("lib")];
+    assert_eq!(ctxt.resolve_path("room.wav"), dir.join("songs/room.wav"));
+    assert_eq!(ctxt.resolve_path("hall.wav"), dir.join("lib/hall.wav"));
+    assert_eq!(ctxt.resolve_path("missing.wav"), dir.join("songs/missing.wav"));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,46 @@
+extern crate interpreter;
+
+use interpreter::common::Context;
+use interpreter::compiler::Compiler;
+use interpreter::runtime::Program;
+use interpreter::audio::control::{ring, Control, Command};
+
+#[test]
+fn ring_buffer_is_bounded() {
+    let (mut producer, mut consumer) = ring(2);
+    assert_eq!(consumer.pop(), None);
+    assert_eq!(producer.push(1), Ok(()));
+    assert_eq!(producer.push(2), Ok(()));
+    assert_eq!(producer.push(3), Err(3));
+    assert_eq!(consumer.pop(), Some(1));
+    assert_eq!(producer.push(4), Ok(()));
+    assert_eq!(consumer.pop(), Some(2));
+    assert_eq!(consumer.pop(), Some(4));
+    assert_eq!(consumer.pop(), None);
+}
+
+#[test]
+fn parameter_changes_are_slewed() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time, gain = 0 { gain }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+
+    let mut program = Program::new(&compiler, "main", 10).unwrap();
+    let (mut control, mut remote) = Control::new(&program, 0.4);
+    assert!(remote.set_param("gain", 4.0));
+    assert!(!remote.params().set("volume", 1.0));
+    assert_eq!(remote.params().get("gain"), Some(4.0));
+
+    let mut buffer = [0f32; 6];
+    control.fill(&mut program, &mut buffer);
+    assert_eq!(buffer, [1.0, 2.0, 3.0, 4.0, 4.0, 4.0]);
+
+    assert!(remote.send(Command::Seek(100)));
+    control.fill(&mut program, &mut buffer[..1]);
+    assert_eq!(program.sample_index(), 101);
+}
@@ -0,0 +1,73 @@
+#[macro_use(make_fn_ty)]
+extern crate interpreter;
+extern crate vec_map;
+
+use interpreter::common::Context;
+use interpreter::compiler::Compiler;
+use interpreter::debugger::{Debugger, Breakpoint, Comparison};
+use interpreter::runtime::Program;
+
+#[test]
+fn parse_breakpoints() {
+    assert_eq!(Breakpoint::parse("1.5s"), Ok(Breakpoint::At(1.5)));
+    assert_eq!(Breakpoint::parse("2"), Ok(Breakpoint::At(2.0)));
+    assert_eq!(Breakpoint::parse("env >= 0.5"),
+               Ok(Breakpoint::Probe("env".to_string(), Comparison::GreaterEqual, 0.5)));
+    assert!(Breakpoint::parse("<1").is_err());
+    assert!(Breakpoint::parse("soon").is_err());
+}
+
+#[test]
+fn pause_at_time_and_probe() {
+    let ctxt = Context::new("<test>".into(), r#"
+        main time { probe("ramp", time * 10) }
+    "#.into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.define_entrypoint("main", make_fn_ty!(&ctxt, fn(time: Number) -> Number));
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+
+    let mut debugger = Debugger::new(Program::new(&compiler, "main", 4).unwrap());
+    debugger.set_breakpoint(Breakpoint::At(0.5));
+    assert!(debugger.resume());
+    assert_eq!(debugger.program().sample_index(), 2);
+
+    debugger.command("step 2").unwrap();
+    assert_eq!(debugger.command("probes ramp").unwrap(), "ramp = 7.5");
+
+    debugger.command("continue ramp>9").unwrap();
+    assert_eq!(debugger.program().sample_index(), 5);
+    assert!(debugger.command("quit").is_none());
+}
+
+#[test]
+fn step_through_assignments() {
+    let ctxt = Context::new("<test>".into(), r#"
+        main time {
+            x = time * 10;
+            y = x + 1;
+            y
+        }
+    "#.into());
+    ctxt.options.borrow_mut().debug = true;
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.define_entrypoint("main", make_fn_ty!(&ctxt, fn(time: Number) -> Number));
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+
+    let mut debugger = Debugger::new(Program::new(&compiler, "main", 4).unwrap());
+    assert!(debugger.command("next").unwrap().starts_with("time = 0 at "));
+    assert!(debugger.command("next").unwrap().starts_with("x = 0 at "));
+    let vars = debugger.command("vars").unwrap();
+    assert!(vars.contains("time = 0 (main at "));
+    assert!(vars.contains("x = 0 (main at "));
+    assert!(!vars.contains("y ="));
+    assert!(debugger.command("next").unwrap().starts_with("y = 1 at "));
+    assert!(debugger.command("next").unwrap().starts_with("time = 0.25 at "));
+    assert_eq!(debugger.program().sample_index(), 2);
+
+    debugger.command("step").unwrap();
+    assert!(debugger.command("vars").unwrap().contains("y = 6 ("));
+}
@@ -0,0 +1,40 @@
+extern crate interpreter;
+
+use interpreter::common::Context;
+use interpreter::compiler::Compiler;
+use interpreter::doc::{collect_docs, add_inferred_types, render_html};
+
+#[test]
+fn html_documents_public_definitions() {
+    let ctxt = Context::new("filters.syn".into(), r"
+/// Scales a signal.
+///
+/// Amounts above 1 amplify.
+scale sig, amount=0.5 * 2 -> Number { sig * amount }
+_helper x { x }
+/// Always on.
+gate = true;
+y = scale(1);
+".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.define_intrinsics();
+    assert!(compiler.lex() && compiler.parse());
+    let mut entries = collect_docs(&ctxt);
+    compiler.typecheck();
+    add_inferred_types(&ctxt, &mut entries);
+
+    let scale = &entries[0];
+    let args = scale.args.as_ref().unwrap();
+    assert_eq!(args[0].ty, Some("Number".to_string()));
+    assert_eq!(args[0].default, None);
+    assert_eq!(args[1].default, Some("0.5 * 2".to_string()));
+    assert_eq!(scale.ty, Some("Number".to_string()));
+    assert_eq!(entries[1].ty, None); // never called, so nothing was inferred
+    assert_eq!(entries[2].ty, Some("Boolean".to_string()));
+
+    let html = render_html("filters.syn", &entries);
+    assert!(html.contains("<title>filters.syn</title>"));
+    assert!(html.contains("<p>Scales a signal.</p>\n<p>Amounts above 1 amplify.</p>"));
+    assert!(html.contains("id=\"gate\""));
+    assert!(!html.contains("_helper"));
+}
@@ -0,0 +1,258 @@
+#[macro_use(run_test)]
+extern crate interpreter;
+
+use interpreter::dsp::fft::{fft, ifft};
+use interpreter::dsp::table::Table;
+use interpreter::dsp::interpolate::{interpolate, Interpolation};
+
+#[test]
+fn fft_round_trip() {
+    let input: Vec<f64> = (0..64).map(|x| ((x * 7) % 13) as f64 - 6.0).collect();
+    let mut re = input.clone();
+    let mut im = vec![0.0; 64];
+    fft(&mut re, &mut im);
+    ifft(&mut re, &mut im);
+    for (a, b) in re.iter().zip(input.iter()) {
+        assert!((a - b).abs() < 1e-9);
+    }
+    assert!(im.iter().all(|x| x.abs() < 1e-9));
+}
+
+#[test]
+fn fft_of_impulse_is_flat() {
+    let mut re = vec![0.0; 16];
+    let mut im = vec![0.0; 16];
+    re[0] = 1.0;
+    fft(&mut re, &mut im);
+    assert!(re.iter().all(|x| (x - 1.0).abs() < 1e-12));
+    assert!(im.iter().all(|x| x.abs() < 1e-12));
+}
+
+#[test]
+fn spectral_intrinsics() {
+    run_test!(
+        should_pass(lex, parse, typecheck, codegen)
+        => r"
+            main time {
+                x = sin(time*440);
+                spectral_lowpass(x, 1000) + spectral_gate[x=x, threshold=0.01] +
+                    spectral_freeze(x, 1 if time > 1 else 0);
+            }
+            y = main(0);
+        ");
+}
+
+#[test]
+fn reverb_and_chorus() {
+    run_test!(
+        should_pass(lex, parse, typecheck, codegen)
+        => r"
+            main time {
+                x = sin(time*440);
+                chorus[x=reverb(x, 0.8, 0.5, 0.3), rate=0.5, depth=0.7, mix=0.5];
+            }
+            y = main(0);
+        ");
+}
+
+#[test]
+fn distortion() {
+    run_test!(
+        should_pass(lex, parse, typecheck, codegen)
+        => r"
+            main time {
+                x = sin(time*440);
+                softclip(x, 3) + waveshape(x, shape_tanh) + bitcrush(x, 8, 11025);
+            }
+            y = main(0);
+        ");
+}
+
+#[test]
+fn table_lookup_interpolates() {
+    let table = Table::new(vec![-1.0, 0.0, 4.0]);
+    assert_eq!(table.lookup(-1.0), -1.0);
+    assert_eq!(table.lookup(-0.5), -0.5);
+    assert_eq!(table.lookup(0.5), 2.0);
+    assert_eq!(table.lookup(2.0), 4.0);
+}
+
+#[test]
+fn interpolation_modes() {
+    // every mode passes through the samples themselves
+    let samples: Vec<f64> = (0..64).map(|i| (i as f64 * 0.3).sin()).collect();
+    let sample = |i: isize| samples[(i.max(0) as usize).min(63)];
+    for &mode in &[Interpolation::Linear, Interpolation::Cubic, Interpolation::Sinc] {
+        assert!((interpolate(mode, 20.0, &sample) - samples[20]).abs() < 1e-9);
+    }
+    // and the higher quality ones come closer to the signal between them
+    let error = |mode| (20..40).map(|i| {
+        let x = i as f64 + 0.5;
+        (interpolate(mode, x, &sample) - (x * 0.3).sin()).abs()
+    }).fold(0.0, f64::max);
+    assert!(error(Interpolation::Cubic) < error(Interpolation::Linear) / 4.0);
+    assert!(error(Interpolation::Sinc) < error(Interpolation::Cubic));
+}
+
+#[test]
+fn interpolation_argument() {
+    run_test!(
+        should_pass(lex, parse, typecheck, codegen)
+        => r"
+            main time {
+                x = sin(time*440);
+                waveshape(x, shape_fold, interp_cubic) + chorus[x=x, rate=0.5, depth=0.7, mix=0.5, interpolation=interp_sinc] +
+                    pluck(clock(0), 110, 0.5, interp_linear);
+            }
+            y = main(0);
+        ");
+}
+
+#[test]
+fn resample_preserves_low_frequencies() {
+    use interpreter::audio::resample::resample;
+    let input: Vec<f32> = (0..4800).map(|i| (i as f32 * 0.01).sin()).collect();
+    let output = resample(&input, 48000, 24000);
+    assert_eq!(output.len(), 2400);
+    for i in 100..2300 {
+        let expected = (i as f32 * 0.02).sin();
+        assert!((output[i] - expected).abs() < 0.01);
+    }
+}
+
+#[test]
+fn decimator_passes_dc() {
+    use interpreter::audio::resample::Decimator;
+    let mut decimator = Decimator::new(4);
+    let mut output = Vec::new();
+    decimator.process(&vec![1.0; 4096], &mut output);
+    assert_eq!(output.len(), 1024);
+    assert!((output[1000] - 1.0).abs() < 0.01);
+}
+
+#[test]
+fn decimator_keeps_channels_apart() {
+    use interpreter::audio::resample::Decimator;
+    let mut decimator = Decimator::with_channels(2, 2);
+    let input: Vec<f32> = (0..4096).map(|i| if i % 2 == 0 { 1.0 } else { -0.5 }).collect();
+    let mut output = Vec::new();
+    decimator.process(&input, &mut output);
+    assert_eq!(output.len(), 2048);
+    assert!((output[1000] - 1.0).abs() < 0.01);
+    assert!((output[1001] + 0.5).abs() < 0.01);
+}
+
+#[test]
+fn decimator_in_double_precision() {
+    use interpreter::audio::resample::Decimator;
+    // an offset too small for f32 to tell apart from 1 survives filtering in f64
+    let offset = 1e-9;
+    let decimate = |input: Vec<f64>| {
+        let mut output = Vec::new();
+        Decimator::new(2).process(&input, &mut output);
+        output[1000]
+    };
+    let difference = decimate(vec![1.0 + offset; 4096]) - decimate(vec![1.0; 4096]);
+    assert!((difference - offset).abs() < offset * 0.05);
+}
+
+#[test]
+fn phase_helpers() {
+    use interpreter::dsp::osc::{wrap, fold, sin01};
+    assert_eq!(wrap(1.25), 0.25);
+    assert_eq!(wrap(-0.25), 0.75);
+    assert_eq!(fold(0.25), 0.25);
+    assert_eq!(fold(1.25), 0.75);
+    assert_eq!(fold(-0.25), 0.25);
+    assert!((sin01(0.25) - 1.0).abs() < 1e-12);
+}
+
+#[test]
+fn envelope_detector() {
+    use interpreter::dsp::dynamics::{coefficient, follow};
+    // covers about 63% of the way in the attack time, and falls more slowly
+    let (attack, release) = (coefficient(0.01, 1000), coefficient(0.1, 1000));
+    let mut level = 0.0;
+    for _ in 0..10 {
+        level = follow(level, -1.0, attack, release);
+    }
+    assert!((level - (1.0 - (-1f64).exp())).abs() < 1e-9);
+    for _ in 0..10 {
+        level = follow(level, 0.0, attack, release);
+    }
+    assert!(level > 0.5);
+    assert_eq!(follow(0.2, 1.0, coefficient(0.0, 1000), release), 1.0);
+}
+
+#[test]
+fn compressor_gain() {
+    use interpreter::dsp::dynamics::gain_db;
+    assert_eq!(gain_db(-12.0, -20.0, 4.0), -6.0);
+    assert_eq!(gain_db(-30.0, -20.0, 4.0), 0.0);
+    assert_eq!(gain_db(-30.0, -20.0, 0.5), -10.0);
+    assert_eq!(gain_db(-10.0, -20.0, 0.5), 0.0);
+}
+
+#[test]
+fn vocoder_bands() {
+    use interpreter::dsp::vocoder::band_frequency;
+    let freqs: Vec<_> = (0..8).map(|i| band_frequency(i, 8, 44100)).collect();
+    assert!(freqs[0] > 80.0 && freqs[7] < 10000.0);
+    // evenly spread in pitch
+    for w in freqs.windows(3) {
+        assert!((w[1] / w[0] - w[2] / w[1]).abs() < 1e-9);
+    }
+    assert!(band_frequency(7, 8, 8000) < 4000.0);
+}
+
+#[test]
+fn resonators() {
+    use interpreter::dsp::physical::{resonator, string_delay};
+    let (a1, a2) = resonator(250.0, 1.0, 1000);
+    assert!(a1.abs() < 1e-12);
+    // falls by 60 dB over a second
+    assert!((a2.powf(500.0) - 1e-3).abs() < 1e-12);
+    assert_eq!(resonator(250.0, 0.0, 1000).1, 0.0);
+    assert_eq!(string_delay(100.0, 1000, 0.5), 9.5);
+    assert_eq!(string_delay(1.0, 1000, 0.0), 50.0);
+}
+
+#[test]
+fn arpeggiator_orders() {
+    use interpreter::dsp::arp::note_at;
+    let notes = [64.0, 60.0, 67.0];
+    let play = |mode| (0..5).map(|i| note_at(&notes, mode, i)).collect::<Vec<_>>();
+    assert_eq!(play("up"), vec![60.0, 64.0, 67.0, 60.0, 64.0]);
+    assert_eq!(play("down"), vec![67.0, 64.0, 60.0, 67.0, 64.0]);
+    assert_eq!(play("updown"), vec![60.0, 64.0, 67.0, 64.0, 60.0]);
+    assert_eq!(play("order"), vec![64.0, 60.0, 67.0, 64.0, 60.0]);
+    assert_eq!(note_at(&[62.0], "updown", 3), 62.0);
+}
+
+#[test]
+fn partitioned_convolution() {
+    use interpreter::buffer::Audio;
+    use interpreter::dsp::convolve::{memory_len, next, partitions, prepare, process_block, Impulse, BLOCK};
+    assert_eq!(partitions(BLOCK), 0);
+    assert_eq!(partitions(BLOCK + 1), 1);
+    assert_eq!(partitions(3 * BLOCK), 2);
+
+    // matches convolving directly, across the first block and the partitions after it
+    let samples: Vec<f32> = (0..300).map(|x| ((x * 5) % 11) as f32 / 11.0 - 0.5).collect();
+    let mut impulse = Impulse::new("room.wav".to_string(), Audio { samples: samples, sample_rate: 100 });
+    impulse.allocate(100);
+    let taps = impulse.taps().to_vec();
+    let input: Vec<f64> = (0..700).map(|x| ((x * 7) % 13) as f64 - 6.0).collect();
+    let mut mem = vec![0.0; memory_len(taps.len())];
+    prepare(&mut mem, taps.len());
+    for n in 0..input.len() {
+        let expected = (0..taps.len()).filter(|&k| k <= n).fold(0.0, |sum, k| sum + taps[k] * input[n - k]);
+        // full blocks are transformed after the sample, or at the start of the next one
+        let (out, full) = next(&mut mem, &impulse, input[n]);
+        assert!((out - expected).abs() < 1e-9);
+        assert_eq!(full, n % BLOCK == BLOCK - 1);
+        if full && (n / BLOCK) % 2 == 0 {
+            process_block(&mut mem, &impulse);
+        }
+    }
+}
@@ -0,0 +1,23 @@
+extern crate interpreter;
+
+use interpreter::audio::encode::{self, Codec};
+
+use std::path::Path;
+
+#[test]
+fn codecs_follow_the_extension() {
+    assert_eq!(Codec::from_path(Path::new("bounce.mp3")), Some(Codec::Mp3));
+    assert_eq!(Codec::from_path(Path::new("out/bounce.M4A")), Some(Codec::Aac));
+    assert_eq!(Codec::from_path(Path::new("bounce.aac")), Some(Codec::Aac));
+    assert_eq!(Codec::from_path(Path::new("bounce.wav")), None);
+    assert_eq!(Codec::from_path(Path::new("mp3")), None);
+    assert_eq!(encode::encoder(encode::DEFAULT_BITRATE).is_some(), cfg!(feature = "encode"));
+}
+
+#[cfg(feature = "encode")]
+#[test]
+fn ffmpeg_arguments() {
+    let ffmpeg = encode::Ffmpeg::new(128);
+    assert_eq!(ffmpeg.arguments(Path::new("a.wav"), Path::new("a.mp3"), Codec::Mp3),
+               vec!["-y", "-loglevel", "error", "-i", "a.wav", "-c:a", "libmp3lame", "-b:a", "128k", "a.mp3"]);
+}
@@ -0,0 +1,29 @@
+extern crate interpreter;
+
+use interpreter::common::Context;
+use interpreter::compiler::Compiler;
+use interpreter::graph::{build_graph, NodeKind, EdgeKind};
+
+#[test]
+fn calls_and_signals() {
+    let ctxt = Context::new("patch.syn".into(), r"
+        unused x { x }
+        voice freq { sin(freq * 2) }
+        main time { voice[freq=abs(time)] + voice(1) }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.define_intrinsics();
+    assert!(compiler.lex() && compiler.parse());
+
+    let graph = build_graph(&ctxt, "main").unwrap();
+    let names: Vec<_> = graph.nodes.iter().map(|x| (&x.name[..], x.kind)).collect();
+    assert_eq!(names, vec![("main", NodeKind::Entrypoint), ("voice", NodeKind::Function),
+                           ("sin", NodeKind::Host), ("abs", NodeKind::Host)]);
+    assert!(graph.edges.iter().any(|x| x.from == 3 && x.to == 1 && x.kind == EdgeKind::Signal("freq".to_string())));
+    assert_eq!(graph.edges.iter().filter(|x| x.kind == EdgeKind::Call).count(), 3);
+
+    let dot = graph.to_dot();
+    assert!(dot.starts_with("digraph \"patch.syn\" {"));
+    assert!(dot.contains("n3 -> n1 [style=dashed, label=\"freq\"];"));
+    assert!(build_graph(&ctxt, "missing").is_none());
+}
@@ -0,0 +1,47 @@
+extern crate interpreter;
+
+use interpreter::common::Context;
+use interpreter::compiler::Compiler;
+use interpreter::ident::NameTable;
+
+#[test]
+fn ids_are_stable() {
+    let mut names = NameTable::new();
+    let freq = names.new_id("freq");
+    let anon = names.new_anon();
+    let alias = names.new_alias(freq);
+    assert_eq!((freq, anon, alias), (0, 1, 2));
+    assert_eq!(names.new_id("freq"), freq);
+    assert_eq!(names.name(alias), "freq");
+    assert_eq!(names.get_id("freq"), Some(freq));
+    assert_eq!(names.iter().count(), 3);
+    // only identifiers which can be looked up by name are listed
+    assert_eq!(names.names().collect::<Vec<_>>(), vec![(freq, "freq")]);
+}
+
+#[test]
+fn case_insensitive_names() {
+    let mut names = NameTable::new();
+    let first = names.new_id("Gain");
+    let other = names.new_id("gain");
+    assert!(first != other);
+
+    names.set_case_insensitive(true);
+    assert_eq!(names.get_id("GAIN"), Some(first));
+    assert_eq!(names.new_id("gAiN"), first);
+    assert_eq!(names.name(first), "Gain");
+}
+
+#[test]
+fn ignore_case_option() {
+    let source = r"
+        Half x { X / 2 }
+        main time { half(SIN(time)) }
+    ";
+    let ctxt = Context::new("<test>".into(), source.into());
+    ctxt.options.borrow_mut().case_insensitive = true;
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.define_intrinsics();
+    assert!(compiler.lex() && compiler.parse());
+    assert!(compiler.typecheck(), "{}", *ctxt.issues.borrow());
+}
@@ -0,0 +1,61 @@
+extern crate interpreter;
+
+use interpreter::common::Context;
+use interpreter::compiler::Compiler;
+use interpreter::runtime::Program;
+
+fn run(source: &str, time: f64) -> f64 {
+    let ctxt = Context::new("<test>".into(), source.into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+    let mut program = Program::new(&compiler, "main", 10).unwrap();
+    program.eval(time)
+}
+
+#[test]
+fn bitwise_operators() {
+    assert_eq!(run("main time { int(time) & 6 }", 13.5), 4.0);
+    assert_eq!(run("main time { int(time) | 2 }", 13.5), 15.0);
+    assert_eq!(run("main time { 1 << int(time) }", 4.0), 16.0);
+    assert_eq!(run("main time { int(time) >> 1 }", -7.0), -4.0);
+    // shifts bind tighter than `&`, and `&` than `|`
+    assert_eq!(run("main time { 1 | int(time) & 3 << 1 }", 6.0), 7.0);
+}
+
+#[test]
+fn ints_widen_to_numbers() {
+    assert_eq!(run("main time { int(time) / 2 }", 5.5), 2.5);
+    assert_eq!(run("main time { sin(int(time)) + (int(time) * 2 + 1) % 4 }", 0.5), 1.0);
+    assert_eq!(run("main time { 0.5 if int(time) == 3 else int(time) }", 3.9), 0.5);
+    assert_eq!(run("main time { select(int(time), [10, 20, 30]) }", 1.9), 20.0);
+}
+
+#[test]
+fn step_counts_clock_firings() {
+    let ctxt = Context::new("<test>".into(), "main time { step(clock(2)) % 3 << 1 }".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    assert!(compiler.compile().is_ok());
+    let mut program = Program::new(&compiler, "main", 10).unwrap();
+    // the clock fires on every fifth sample, the first time without moving on
+    let values: Vec<f64> = (0..20).map(|_| program.eval(0.0)).collect();
+    assert_eq!((values[0], values[5], values[10], values[15]), (0.0, 2.0, 4.0, 0.0));
+}
+
+#[test]
+fn bitwise_operators_take_ints() {
+    for &(source, msg) in &[
+        ("main time { time & 1 }", "bitwise operators take `Int`s, found `Number`"),
+        ("main time { int(time) << 0.5 }", "bitwise operators take `Int`s, found `Number`"),
+        ("main time { int(time) & true }", "found `Boolean`"),
+    ] {
+        let ctxt = Context::new("<test>".into(), source.into());
+        let mut compiler = Compiler::new(&ctxt);
+        compiler.declare_entrypoint("main");
+        let issues = compiler.compile().err().unwrap().to_string();
+        assert!(issues.contains(msg), "{}", issues);
+    }
+}
@@ -18,7 +18,7 @@ fn all_tokens() {
 
             abcABC_~'0123
 
-            + - * / ^ ^^ >= <= < > ! % && || == !=
+            + - * / ^ ^^ >= <= < > ! % && || == != ~=
             if else . , = : ; ? ( ) { } [ ] \ @
             true false
             // #&*GR^@&(G#^&(G@&*YFD*B@Y^&#(VT@^(f367g9@&*
@@ -33,3 +33,271 @@ fn non_tokens() {
         => "` # $ & | ' \""
     );
 }
+
+#[test]
+fn block_comments() {
+    use interpreter::common::Context;
+    use interpreter::lexer::lex;
+
+    let ctxt = Context::new("<test>".into(), "x /* a\ncomment */ = 1;".into());
+    lex(&ctxt);
+    assert!(!ctxt.issues.borrow().has_errors());
+    let tokens = ctxt.tokens.borrow();
+    assert_eq!(tokens.len(), 4);
+    assert_eq!(tokens[1].1.line, 2);
+    assert_eq!(tokens[1].1.column, 12);
+
+    run_test!(
+        should_fail(lex)
+        => r"
+            x = 1; /* never closed
+        "
+    );
+}
+
+#[test]
+fn doc_comments() {
+    use interpreter::common::Context;
+    use interpreter::lexer::lex;
+
+    let ctxt = Context::new("<test>".into(), "/// first\n///second  \n// plain\nx = 1;".into());
+    lex(&ctxt);
+    let docs = ctxt.docs.borrow();
+    let text: Vec<_> = docs.iter().map(|x| &x.0[..]).collect();
+    assert_eq!(text, vec!["first", "second"]);
+    assert_eq!(ctxt.tokens.borrow().len(), 4);
+}
+
+#[test]
+fn stdin_source_name() {
+    use interpreter::common::{Context, display_name, STDIN_NAME};
+    use interpreter::lexer::lex;
+
+    assert_eq!(display_name("-"), STDIN_NAME);
+    assert_eq!(display_name("patch.syn"), "patch.syn");
+    let ctxt = Context::new(display_name("-"), "x = $;".into());
+    lex(&ctxt);
+    assert!(ctxt.issues.borrow().to_string().contains(" --> <stdin>:1:5"));
+}
+
+#[test]
+fn string_literals() {
+    use interpreter::common::Context;
+    use interpreter::lexer::lex;
+    use interpreter::tokens::Token;
+
+    let ctxt = Context::new("<test>".into(), r#"test "decays to zero" x"#.into());
+    lex(&ctxt);
+    assert!(!ctxt.issues.borrow().has_errors());
+    assert_eq!(ctxt.tokens.borrow()[1].0, Token::Str(0));
+    assert_eq!(ctxt.tokens.borrow()[2].1.column, 23);
+    assert_eq!(*ctxt.strings.borrow(), vec!["decays to zero"]);
+
+    let ctxt = Context::new("<test>".into(), r#""say \"hi\"\t\\ \u{1F3B5}" x"#.into());
+    lex(&ctxt);
+    assert!(!ctxt.issues.borrow().has_errors());
+    assert_eq!(*ctxt.strings.borrow(), vec!["say \"hi\"\t\\ \u{1F3B5}"]);
+    assert_eq!(ctxt.tokens.borrow()[1].1.column, 28);
+
+    for &(source, msg) in &[(r#""C:\samples""#, "unknown escape sequence `\\s`"),
+                            (r#""\u{110000}""#, "`\\u{110000}` is not a valid character"),
+                            (r#""\u41""#, "expected `{` after `\\u`")] {
+        let ctxt = Context::new("<test>".into(), source.into());
+        lex(&ctxt);
+        let issues = ctxt.issues.borrow().to_string();
+        assert!(issues.contains(msg), "{}", issues);
+        assert!(issues.contains("[E013]"), "{}", issues);
+    }
+
+    run_test!(
+        should_fail(lex)
+        => "
+            test \"never closed
+            { }
+        "
+    );
+}
+
+#[test]
+fn unicode_source() {
+    use interpreter::common::Context;
+    use interpreter::lexer::lex;
+    use interpreter::tokens::Token;
+
+    let ctxt = Context::new("<test>".into(), "naïve = \"é\"; 音\u{3000}= naïve $".into());
+    lex(&ctxt);
+    let tokens = ctxt.tokens.borrow();
+    assert_eq!(tokens[0].0, tokens[6].0);
+    match tokens[4].0 {
+        Token::Ident(id) => assert_eq!(ctxt.names.borrow().name(id), "音"),
+        ref x => panic!("expected an identifier, found {:?}", x),
+    }
+    // columns count characters, while indices count bytes
+    assert_eq!(tokens[2].1.column, 9);
+    assert_eq!(tokens[4].1.column, 14);
+    assert_eq!(tokens[4].1.index, 15);
+    assert_eq!(tokens[6].1.column, 18);
+
+    // the caret lines up with the unrecognized `$`
+    let issues = ctxt.issues.borrow().to_string();
+    assert!(issues.contains(" --> <test>:1:24"));
+    let caret: String = (0..23).map(|_| " ").collect();
+    assert!(issues.contains(&format!("  | {}^\n", caret)), "{}", issues);
+}
+
+#[test]
+fn keywords_are_whole_words() {
+    use interpreter::common::Context;
+    use interpreter::lexer::lex;
+    use interpreter::tokens::{Token, Symbol, Operator};
+
+    let ctxt = Context::new("<test>".into(), "if iffy else truest a~=b ~c\r\nx".into());
+    lex(&ctxt);
+    let tokens: Vec<_> = ctxt.tokens.borrow().iter().map(|x| x.0).collect();
+    assert_eq!(tokens[0], Token::Symbol(Symbol::If));
+    assert!(match tokens[1] { Token::Ident(_) => true, _ => false });
+    assert_eq!(tokens[2], Token::Symbol(Symbol::Else));
+    assert!(match tokens[3] { Token::Ident(_) => true, _ => false });
+    assert_eq!(tokens[5], Token::Operator(Operator::ApproxEqual));
+    assert_eq!(tokens.len(), 9);
+    // \r\n is a single line break
+    assert_eq!(ctxt.tokens.borrow()[8].1.line, 2);
+    assert_eq!(ctxt.tokens.borrow()[8].1.column, 1);
+}
+
+#[test]
+fn approx_equal_after_operands() {
+    use interpreter::common::Context;
+    use interpreter::lexer::lex;
+    use interpreter::tokens::{Token, Operator};
+
+    let ctxt = Context::new("<test>".into(), "x~=1 1~=x x~ ~=y".into());
+    lex(&ctxt);
+    assert!(!ctxt.issues.borrow().has_errors());
+    let tokens: Vec<_> = ctxt.tokens.borrow().iter().map(|x| x.0).collect();
+    assert_eq!(tokens.len(), 9);
+    assert_eq!(tokens[1], Token::Operator(Operator::ApproxEqual));
+    assert_eq!(tokens[3], Token::Const(1.0));
+    assert_eq!(tokens[4], Token::Operator(Operator::ApproxEqual));
+    // a `~` which doesn't start the operator is still part of the identifier
+    assert!(match tokens[6] { Token::Ident(_) => true, _ => false });
+    assert_eq!(tokens[7], Token::Operator(Operator::ApproxEqual));
+}
+
+#[test]
+fn version_pragma() {
+    use interpreter::common::Context;
+    use interpreter::lexer::lex;
+    use interpreter::tokens::{Token, Operator};
+
+    let lex_tokens = |source: &str| {
+        let ctxt = Context::new("<test>".into(), source.into());
+        lex(&ctxt);
+        assert!(!ctxt.issues.borrow().has_errors(), "{}", *ctxt.issues.borrow());
+        let tokens: Vec<_> = ctxt.tokens.borrow().iter().map(|x| x.0).collect();
+        tokens
+    };
+    // `and` is only a keyword from version 2
+    let tokens = lex_tokens("a and b");
+    assert!(match tokens[1] { Token::Ident(_) => true, _ => false });
+    let tokens = lex_tokens("\n#version 2\na and not b or c");
+    assert_eq!(tokens[1], Token::Operator(Operator::And));
+    assert_eq!(tokens[2], Token::Operator(Operator::Not));
+    assert_eq!(tokens[4], Token::Operator(Operator::Or));
+    assert_eq!(tokens.len(), 6);
+    assert_eq!(lex_tokens("#version 1\nand").len(), 1);
+
+    for source in &["#version 3\nx", "#version two", "x = 1;\n#version 2"] {
+        let ctxt = Context::new("<test>".into(), source.to_string());
+        lex(&ctxt);
+        let issues = ctxt.issues.borrow().to_string();
+        assert!(issues.contains("error[E134]"), "{}", issues);
+    }
+}
+
+#[test]
+fn invalid_numbers() {
+    use interpreter::common::Context;
+    use interpreter::lexer::lex;
+
+    let ctxt = Context::new("<test>".into(), "x = 1.5e-;".into());
+    lex(&ctxt);
+    let issues = ctxt.issues.borrow().to_string();
+    assert!(issues.contains("[E012]: expected digits in the exponent of `1.5e-`"), "{}", issues);
+    assert_eq!(ctxt.tokens.borrow().len(), 3);
+}
+
+#[test]
+fn number_literals() {
+    use interpreter::common::Context;
+    use interpreter::lexer::lex;
+    use interpreter::tokens::Token;
+
+    let ctxt = Context::new("<test>".into(), "0x1F 0XfF_ff 1_000 1_000.25 2.5E+2 1e-1_0 .5".into());
+    lex(&ctxt);
+    assert!(!ctxt.issues.borrow().has_errors(), "{}", *ctxt.issues.borrow());
+    let values: Vec<_> = ctxt.tokens.borrow().iter().map(|x| x.0).collect();
+    assert_eq!(values, vec![Token::Const(31.0), Token::Const(65535.0), Token::Const(1000.0),
+                            Token::Const(1000.25), Token::Const(250.0), Token::Const(1e-10),
+                            Token::Const(0.5)]);
+
+    // each malformed literal is one error, rather than being split into several tokens
+    for &(source, msg) in &[("x = 2x;", "invalid number `2x`"),
+                            ("x = 1.2.3;", "invalid number `1.2.3`"),
+                            ("x = 0x;", "expected hexadecimal digits after `0x` in `0x`"),
+                            ("x = 0x1G;", "invalid number `0x1G`"),
+                            ("x = 2bars;", "invalid number `2bars`"),
+                            ("x = 1e999;", "`1e999` is too large to be represented")] {
+        let ctxt = Context::new("<test>".into(), source.into());
+        lex(&ctxt);
+        let issues = ctxt.issues.borrow().to_string();
+        assert!(issues.contains(msg), "{}", issues);
+        assert_eq!(issues.matches("[E012]").count(), 1);
+        assert_eq!(ctxt.tokens.borrow().len(), 3);
+    }
+}
+
+#[test]
+fn multiple_files() {
+    use interpreter::common::{Context, SourceFiles};
+    use interpreter::compiler::Compiler;
+
+    let mut files = SourceFiles::new();
+    files.add("prelude.syn".into(), "double x { x * 2 }\nhalf x { x / 2 $ }".into());
+    let main = files.add("patch.syn".into(), "main time {\n    double(time) + triple(time)\n}".into());
+    files.set_main(main);
+    let ctxt = Context::with_files(files);
+    assert_eq!(ctxt.filename(), "patch.syn");
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.define_intrinsics();
+    assert!(!compiler.lex());
+    let issues = ctxt.issues.borrow().to_string();
+    assert!(issues.contains(" --> prelude.syn:2:16"), "{}", issues);
+    assert!(issues.contains("half x { x / 2 $ }"), "{}", issues);
+
+    // positions in different files never share an index
+    let tokens = ctxt.tokens.borrow();
+    let first_main = tokens.iter().find(|x| x.1.file == main).unwrap().1;
+    assert_eq!((first_main.line, first_main.column), (1, 1));
+    assert!(tokens.iter().all(|x| x.1.file == main || x.1.index < first_main.index));
+    assert_eq!(ctxt.files.line(tokens[tokens.len() - 1].1), "}");
+}
+
+#[test]
+fn issues_name_their_file() {
+    use interpreter::common::{Context, SourceFiles};
+    use interpreter::compiler::Compiler;
+
+    let mut files = SourceFiles::new();
+    files.add("prelude.syn".into(), "double x { x * 2 }".into());
+    let main = files.add("patch.syn".into(), "main time {\n    double(time) + triple(time)\n}".into());
+    files.set_main(main);
+    let ctxt = Context::with_files(files);
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.define_intrinsics();
+    assert!(compiler.lex() && compiler.parse());
+    assert!(!compiler.typecheck());
+    let issues = ctxt.issues.borrow().to_string();
+    assert!(issues.contains(" --> patch.syn:2:20"), "{}", issues);
+    assert!(issues.contains("    double(time) + triple(time)"), "{}", issues);
+}
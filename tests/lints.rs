@@ -0,0 +1,274 @@
+#[macro_use]
+extern crate interpreter;
+
+use interpreter::common::{Context, Options};
+use interpreter::compiler::Compiler;
+use interpreter::codes::Code;
+use interpreter::issue::LintLevel;
+
+/// Typechecks `source` with the given options, returning whether there were errors and warnings.
+fn check(source: &str, options: Options) -> (bool, bool) {
+    let ctxt = Context::new("<test>".into(), source.into());
+    *ctxt.options.borrow_mut() = options;
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.define_intrinsics();
+    assert!(compiler.lex() && compiler.parse());
+    compiler.typecheck();
+    let issues = ctxt.issues.borrow();
+    (issues.has_errors(), issues.has_warnings())
+}
+
+#[test]
+fn unused_variable() {
+    run_test!(
+        should_pass(lex, parse, typecheck),
+        should_warn(typecheck)
+        => r"
+            f x { y = x * 2; x }
+            z = f(1);
+        ");
+}
+
+#[test]
+fn underscore_variable_is_not_reported() {
+    let source = r"
+        f x { _y = x * 2; x }
+        _z = f(1);
+    ";
+    assert_eq!(check(source, Options::new()), (false, false));
+}
+
+#[test]
+fn approx_equality_on_constants() {
+    let mut options = Options::new();
+    options.lint_levels.insert(Code::UnusedVariable, LintLevel::Allow);
+    assert_eq!(check("x = 0.1 + 0.2 ~= 0.3;", options), (false, true));
+}
+
+#[test]
+fn allowed_lint_is_silent() {
+    let source = r"
+        f x { y = x * 2; x }
+        z = f(1);
+    ";
+    let mut options = Options::new();
+    options.lint_levels.insert(Code::UnusedVariable, LintLevel::Allow);
+    assert_eq!(check(source, options), (false, false));
+}
+
+#[test]
+fn denied_lint_is_an_error() {
+    let source = r"
+        f x { y = x * 2; x }
+        z = f(1);
+    ";
+    let mut options = Options::new();
+    options.lint_levels.insert(Code::UnusedVariable, LintLevel::Deny);
+    assert_eq!(check(source, options).0, true);
+}
+
+#[test]
+fn deny_warnings() {
+    let source = r"
+        x = 1;
+        x = true;
+    ";
+    let mut options = Options::new();
+    options.deny_warnings = true;
+    assert_eq!(check(source, options).0, true);
+
+    let mut options = Options::new();
+    options.deny_warnings = true;
+    options.lint_levels.insert(Code::ReassignedType, LintLevel::Warn);
+    options.lint_levels.insert(Code::UnusedVariable, LintLevel::Allow);
+    assert_eq!(check(source, options), (false, true));
+}
+
+#[test]
+fn strict_mode() {
+    let source = r"
+        f x { x }
+        f x { x * 2 }
+        z = f(1);
+    ";
+    let mut options = Options::new();
+    options.strict = true;
+    assert_eq!(check(source, options), (true, false));
+
+    let mut options = Options::new();
+    options.strict = true;
+    options.lint_levels.insert(Code::ShadowedFunction, LintLevel::Warn);
+    assert_eq!(check(source, options), (false, true));
+
+    let source = r"
+        f x { 1 if x ~= 0.5 else 0 }
+        z = f(1);
+    ";
+    assert_eq!(check(source, Options::new()), (false, false));
+    let mut options = Options::new();
+    options.strict = true;
+    assert_eq!(check(source, options), (true, false));
+
+    // a tolerance given with `within` is explicit
+    let source = r"
+        f x { 1 if x ~= 0.5 within 0.01 && x > 0 else 0 }
+        z = f(1);
+    ";
+    let mut options = Options::new();
+    options.strict = true;
+    assert_eq!(check(source, options), (false, false));
+
+    // numbers are never coerced to booleans
+    for source in &["f x { 1 if x else 0 } z = f(1);",
+                    "f x { 1 if !x else 0 } z = f(1);",
+                    "f x { 1 if x > 0 && x else 0 } z = f(1);"] {
+        assert_eq!(check(source, Options::new()), (true, false));
+        let mut options = Options::new();
+        options.strict = true;
+        assert_eq!(check(source, options), (true, false));
+    }
+}
+
+#[test]
+fn lint_names_round_trip() {
+    for code in Code::all() {
+        if let Some(name) = code.lint_name() {
+            assert_eq!(Code::from_lint_name(name), Some(*code));
+        }
+    }
+    assert_eq!(Code::from_lint_name("shadowing"), Some(Code::ShadowedFunction));
+    assert_eq!(Code::from_lint_name("no-such-lint"), None);
+}
+
+#[test]
+fn redefinition_notes_previous_definition() {
+    let ctxt = Context::new("<test>".into(), "x = 1;\nx = true;\n_y = x;".into());
+    let mut compiler = Compiler::new(&ctxt);
+    assert!(compiler.lex() && compiler.parse());
+    compiler.typecheck();
+    let report = ctxt.issues.borrow().to_string();
+    assert!(report.contains("warning[W003]"));
+    assert!(report.contains("note[W003]: `x` was previously defined here"));
+
+    let ctxt = Context::new("<test>".into(), "x = 1;\nx = true;\n_y = x;".into());
+    ctxt.options.borrow_mut().lint_levels.insert(Code::ReassignedType, LintLevel::Allow);
+    let mut compiler = Compiler::new(&ctxt);
+    assert!(compiler.lex() && compiler.parse());
+    compiler.typecheck();
+    assert!(!ctxt.issues.borrow().to_string().contains("note"));
+}
+
+#[test]
+fn rendered_issues() {
+    let ctxt = Context::new("patch.syn".into(), "f x { x }\nhalf = f(1);\nz = halve;".into());
+    let mut compiler = Compiler::new(&ctxt);
+    assert!(compiler.lex() && compiler.parse());
+    compiler.typecheck();
+    let report = ctxt.issues.borrow().to_string();
+    assert!(report.contains("\
+error[E100]: no variable named `halve` is in scope; did you mean `half`?
+ --> patch.syn:3:5
+  |
+3 | z = halve;
+  |     ^^^^^
+"), "{}", report);
+    assert!(report.contains("  = help: if this is intentional, name it `_half` instead"), "{}", report);
+
+    ctxt.issues.borrow_mut().set_color(true);
+    let report = ctxt.issues.borrow().to_string();
+    assert!(report.contains("\x1b[1;31merror[E100]\x1b[0m"), "{}", report);
+}
+
+#[test]
+fn issues_are_deduplicated_sorted_and_limited() {
+    let ctxt = Context::new("<test>".into(), "a b\nc d".into());
+    let mut compiler = Compiler::new(&ctxt);
+    assert!(compiler.lex());
+    let pos: Vec<_> = ctxt.tokens.borrow().iter().map(|x| x.1).collect();
+    for &i in &[3, 1, 1, 2, 0] {
+        ctxt.emit_error(Code::UnknownVariable, format!("error {}", i), pos[i]);
+        ctxt.emit_note(Code::UnknownVariable, format!("note {}", i), pos[i]);
+    }
+    ctxt.issues.borrow_mut().set_error_limit(Some(3));
+    let report = ctxt.issues.borrow().to_string();
+    assert_eq!(report.matches("error 1").count(), 1);
+    assert_eq!(report.matches("note 1").count(), 1);
+    let order: Vec<_> = ["error 0", "note 0", "error 1", "note 1", "error 2"].iter()
+        .map(|x| report.find(x).unwrap()).collect();
+    assert!(order.windows(2).all(|x| x[0] < x[1]), "{}", report);
+    assert!(!report.contains("error 3"));
+    assert!(report.ends_with("... and 1 more error\n"), "{}", report);
+}
+
+#[test]
+fn argument_assigned_in_a_block() {
+    let source = r"
+        f x, gain { if x > 1 { gain = 0.5; gain } else { gain }; x * gain }
+        _y = f(2, 1);
+    ";
+    let ctxt = Context::new("<test>".into(), source.into());
+    let mut compiler = Compiler::new(&ctxt);
+    assert!(compiler.lex() && compiler.parse());
+    compiler.typecheck();
+    let report = ctxt.issues.borrow().to_string();
+    assert_eq!(report.matches("warning[W006]").count(), 1, "{}", report);
+    assert!(report.contains("note[W006]: this assignment only changes `gain` until the end of its block"),
+            "{}", report);
+
+    let source = r"
+        f x, gain { gain = gain * 2; if x > 1 { gain = 0.5; gain } else { 0 } + x * gain }
+        _y = f(2, 1);
+    ";
+    assert_eq!(check(source, Options::new()), (false, false));
+}
+
+#[test]
+fn call_which_may_miss_an_argument() {
+    let ctxt = Context::new("<test>".into(), r"
+        loud x, gain = 2 { x * gain }
+        quiet x, gain { x * gain }
+        pick c { f = if c > 1 { loud } else { quiet }; f(c) }
+        _y = pick(2);
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    assert!(compiler.lex() && compiler.parse());
+    assert!(!compiler.typecheck());
+    let report = ctxt.issues.borrow().to_string();
+    assert!(report.contains("error[E127]: argument `gain` is required by `quiet`, which this call may reach"),
+            "{}", report);
+    assert!(report.contains("note[E127]: `quiet` may be chosen here"), "{}", report);
+}
+
+#[test]
+fn conditional_branches_with_different_types() {
+    let ctxt = Context::new("<test>".into(), "f x { if x > 1 { x } else { x > 0 } }\n_y = f(2);".into());
+    let mut compiler = Compiler::new(&ctxt);
+    assert!(compiler.lex() && compiler.parse());
+    assert!(!compiler.typecheck());
+    let report = ctxt.issues.borrow().to_string();
+    assert!(report.contains("error[E128]: branches of conditional have different types, `Number` and `Boolean`"),
+            "{}", report);
+    assert!(report.contains("note[E128]: this branch is `Number`"), "{}", report);
+    assert!(report.contains("note[E128]: this branch is `Boolean`"), "{}", report);
+
+    let ctxt = Context::new("<test>".into(), "f x { if x > 1 { pan(x, 0) } else { x } }\n_y = f(2);".into());
+    let mut compiler = Compiler::new(&ctxt);
+    assert!(compiler.lex() && compiler.parse());
+    assert!(!compiler.typecheck());
+    let report = ctxt.issues.borrow().to_string();
+    assert!(report.contains("`Stereo` and `Number`"), "{}", report);
+    assert!(report.contains("help: make the mono branch stereo"), "{}", report);
+}
+
+#[test]
+fn select_index_out_of_range() {
+    let mut options = Options::new();
+    options.lint_levels.insert(Code::UnusedVariable, LintLevel::Allow);
+    assert_eq!(check("x = select(3, [1, 2, 3]);", options.clone()), (false, true));
+    assert_eq!(check("x = select(2.5, [1, 2, 3]);", options.clone()), (false, false));
+    assert_eq!(check("f t { select(seq(t, [0, 1, 2, 3]), [1, 2, 3]) }\nx = f(1);", options.clone()),
+               (false, true));
+    assert_eq!(check("f t { select(choose(t, [0, 2]), [1, 2, 3]) }\nx = f(1);", options.clone()),
+               (false, false));
+    assert_eq!(check("f t { select(t, [1, 2, 3]) }\nx = f(1);", options), (false, false));
+}
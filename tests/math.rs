@@ -0,0 +1,67 @@
+extern crate interpreter;
+
+use interpreter::{math, fastmath};
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() <= 4.0 * std::f64::EPSILON * b.abs().max(1.0)
+}
+
+#[test]
+fn portable_functions_match_the_platform() {
+    for i in -2000..2000 {
+        let x = i as f64 * 0.0137;
+        assert!(close(math::sin(x), x.sin()), "sin({})", x);
+        assert!(close(math::cos(x), x.cos()), "cos({})", x);
+        assert!(close(math::exp(x / 4.0), (x / 4.0).exp()), "exp({})", x / 4.0);
+        assert!(close(math::exp2(x / 4.0), (x / 4.0).exp2()), "exp2({})", x / 4.0);
+        let y = (x * 3.0).abs() + 1e-3;
+        assert!(close(math::log(y), y.ln()), "log({})", y);
+        assert!(close(math::log2(y), y.log2()), "log2({})", y);
+        assert!(close(math::log10(y), y.log10()), "log10({})", y);
+        // through exp and log, so the error grows with the size of the exponent
+        let (a, b) = (math::pow(y, x / 8.0), y.powf(x / 8.0));
+        assert!((a - b).abs() <= 1e-13 * b.abs().max(1.0), "pow({}, {})", y, x / 8.0);
+    }
+}
+
+#[test]
+fn exact_and_special_values() {
+    assert_eq!(math::exp(0.0), 1.0);
+    assert_eq!(math::exp2(10.0), 1024.0);
+    assert_eq!(math::log2(0.125), -3.0);
+    assert_eq!(math::log(1.0), 0.0);
+    assert_eq!(math::pow(3.0, 4.0), 81.0);
+    assert_eq!(math::pow(-2.0, 3.0), -8.0);
+    assert_eq!(math::pow(2.0, -2.0), 0.25);
+    assert_eq!(math::sin(0.0), 0.0);
+    assert_eq!(math::cos(0.0), 1.0);
+    assert!(math::pow(-2.0, 0.5).is_nan());
+    assert!(math::log(-1.0).is_nan());
+    assert_eq!(math::log(0.0), std::f64::NEG_INFINITY);
+    assert_eq!(math::exp(1000.0), std::f64::INFINITY);
+    assert_eq!(math::exp(-1000.0), 0.0);
+    assert!(math::sin(std::f64::INFINITY).is_nan());
+}
+
+#[test]
+fn fast_functions_stay_within_their_bounds() {
+    for i in -20000..20000 {
+        let x = i as f64 * 0.5;
+        assert!((fastmath::sin(x) - x.sin()).abs() < 1e-9, "sin({})", x);
+        assert!((fastmath::cos(x) - x.cos()).abs() < 1e-9, "cos({})", x);
+        let x = i as f64 * 0.035;
+        assert!((fastmath::exp(x) - x.exp()).abs() < 5e-10 * x.exp(), "exp({})", x);
+        assert!((fastmath::exp2(x) - x.exp2()).abs() < 5e-10 * x.exp2(), "exp2({})", x);
+        let y = 10f64.powf(i as f64 * 0.015);
+        assert!((fastmath::log(y) - y.ln()).abs() < 1e-10, "log({})", y);
+        assert!((fastmath::log2(y) - y.log2()).abs() < 1e-10, "log2({})", y);
+        let (base, exponent) = ((i as f64 * 0.05).abs() + 1e-3, (i % 320) as f64 * 0.05);
+        let exact = base.powf(exponent);
+        assert!((fastmath::pow(base, exponent) - exact).abs() < 1e-9 * exact, "pow({}, {})", base, exponent);
+    }
+    assert_eq!(fastmath::pow(-2.0, 3.0), -8.0);
+    assert_eq!(fastmath::pow(-2.0, 2.0), 4.0);
+    assert_eq!(fastmath::pow(0.0, 2.0), 0.0);
+    assert_eq!(fastmath::pow(5.0, 0.0), 1.0);
+    assert!(fastmath::pow(-2.0, 0.5).is_nan());
+}
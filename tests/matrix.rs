@@ -0,0 +1,77 @@
+extern crate interpreter;
+
+use interpreter::common::Context;
+use interpreter::compiler::Compiler;
+use interpreter::dsp::ambisonics;
+use interpreter::runtime::Program;
+
+use std::f64::consts::PI;
+
+fn run(source: &str, time: f64) -> f64 {
+    let ctxt = Context::new("<test>".into(), source.into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+    let mut program = Program::new(&compiler, "main", 10).unwrap();
+    program.eval(time)
+}
+
+#[test]
+fn vectors_and_matrices() {
+    assert_eq!(run("main time { dot(channels([1, 2, 3]), channels([time, 1, 2])) }", 4.0), 12.0);
+    assert_eq!(run("main time {
+        v = matmul([0, 1, 0,  1, 0, 0,  2, 0, 1], channels([time, 2, 3]));
+        channel(v, 0) * 100 + channel(v, 1) * 10 + channel(v, 2)
+    }", 1.0), 215.0);
+    assert_eq!(run("main time { ms = midside(stereo(time, 1)); left(ms) * 10 + right(leftright(ms)) }", 3.0),
+               21.0);
+    // a quarter turn moves the left channel to the right
+    assert!((run("main time { right(matmul(rotation(1.5707963267948966), stereo(time, 0))) }", 2.0) - 2.0).abs()
+            < 1e-12);
+}
+
+#[test]
+fn first_order_ambisonics() {
+    // a source stays where it is in a field turned all the way round
+    let source = ambisonics::encode(1.0, 1.0, 0.0);
+    let turned = ambisonics::rotate(&source, 2.0 * PI);
+    for (a, b) in turned.iter().zip(source.iter()) {
+        assert!((a - b).abs() < 1e-12);
+    }
+    // the speakers of a ring share a source between them at its level
+    let speakers = [0.5, 0.5 + PI / 2.0, 0.5 + PI, 0.5 + 3.0 * PI / 2.0];
+    let total = speakers.iter().fold(0.0, |sum, &x| sum + ambisonics::decode(&source, x, 4));
+    assert!((total - 1.0).abs() < 1e-12);
+
+    let front = run("main time {
+        field = foa(1, time, 0);
+        left(foa_decode(foa_rotate(field, -time), [0, 3.1415926535897931]))
+    }", 1.2);
+    assert!((front - 1.5).abs() < 1e-12, "{}", front);
+}
+
+#[test]
+fn sizes_must_fit() {
+    for &(source, msg) in &[
+        ("main time { channel(matmul([1, 0, 0, 1], channels([1, 2, 3])), 0) }",
+         "error[E136]: a matrix for a signal of 3 channels has 9 elements, not 4"),
+        ("main time { dot(stereo(1, 2), channels([1, 2, 3])) }",
+         "error[E136]: cannot take the dot product of signals of 2 and 3 channels"),
+        ("main time { channel(channels([1]), 0) }", "error[E136]: a signal of several channels needs at least 2"),
+        ("main time { left(foa_rotate(stereo(1, 2), 0)) }",
+         "error[E136]: `foa_rotate` takes a first order ambisonic signal of 4 channels, not 2"),
+        ("main time { channel(matmul([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, \
+          23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48, \
+          49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, \
+          75, 76, 77, 78, 79, 80, 81], channels([1, 2, 3, 4, 5, 6, 7, 8, 9])), 0) }",
+         "error[E136]: matrices are at most 8 by 8"),
+    ] {
+        let ctxt = Context::new("<test>".into(), source.into());
+        let mut compiler = Compiler::new(&ctxt);
+        compiler.declare_entrypoint("main");
+        let issues = compiler.compile().err().unwrap().to_string();
+        assert!(issues.contains(msg), "{}", issues);
+    }
+}
@@ -0,0 +1,71 @@
+extern crate interpreter;
+
+use interpreter::audio::network::{JitterBuffer, Packet};
+
+fn packet(sequence: u16, timestamp: u32, samples: Vec<f32>) -> Packet {
+    Packet {
+        sequence: sequence,
+        timestamp: timestamp,
+        source: 7,
+        channels: 2,
+        sample_rate: 48000,
+        samples: samples,
+    }
+}
+
+fn play(jitter: &mut JitterBuffer, frames: usize) -> Vec<Option<Vec<f32>>> {
+    (0..frames).map(|_| jitter.next_frame().map(|x| x.to_vec())).collect()
+}
+
+#[test]
+fn packets_round_trip() {
+    let sent = packet(65535, 4000000000, vec![0.5, -0.25, 1e-3, -1.0]);
+    let bytes = sent.encode();
+    assert_eq!(bytes.len(), 18 + 16);
+    assert_eq!(&bytes[..4], &[0x80, 96, 0xff, 0xff]);
+    assert_eq!(Packet::decode(&bytes), Ok(sent));
+    assert!(Packet::decode(&bytes[..bytes.len() - 2]).is_err());
+    assert!(Packet::decode(b"not audio").is_err());
+}
+
+#[test]
+fn jitter_buffer_reorders_packets() {
+    let mut jitter = JitterBuffer::new(2, 4, 8);
+    jitter.push(packet(0, 100, vec![1.0, -1.0, 2.0, -2.0]));
+    // nothing plays until the latency is buffered
+    assert_eq!(play(&mut jitter, 1), vec![None]);
+    jitter.push(packet(2, 104, vec![5.0, -5.0, 6.0, -6.0]));
+    jitter.push(packet(1, 102, vec![3.0, -3.0, 4.0, -4.0]));
+    let played: Vec<_> = play(&mut jitter, 6).into_iter().map(|x| x.unwrap()[0]).collect();
+    assert_eq!(played, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    // a packet after its time is dropped, and running out waits for the latency again
+    jitter.push(packet(0, 100, vec![1.0, -1.0, 2.0, -2.0]));
+    assert_eq!(jitter.late, 1);
+    assert_eq!(play(&mut jitter, 1), vec![None]);
+    assert_eq!(jitter.underruns, 1);
+}
+
+#[test]
+fn jitter_buffer_fills_gaps_with_silence() {
+    let mut jitter = JitterBuffer::new(2, 2, 8);
+    // the timestamps wrap around between these packets, and the one between is lost
+    jitter.push(packet(0, 4294967294, vec![1.0, 1.0, 2.0, 2.0]));
+    jitter.push(packet(2, 2, vec![5.0, 5.0, 6.0, 6.0]));
+    let played: Vec<_> = play(&mut jitter, 6).into_iter().map(|x| x.map(|x| x[1])).collect();
+    assert_eq!(played, vec![Some(1.0), Some(2.0), None, None, Some(5.0), Some(6.0)]);
+    assert_eq!(jitter.lost, 2);
+}
+
+#[test]
+fn jitter_buffer_hands_back_buffers() {
+    let mut jitter = JitterBuffer::new(2, 2, 2);
+    jitter.push(packet(0, 0, vec![1.0, 1.0]));
+    jitter.push(packet(1, 1, vec![2.0, 2.0]));
+    // there is no room for a third packet
+    jitter.push(packet(2, 2, vec![3.0, 3.0]));
+    assert_eq!(jitter.dropped, 1);
+    assert_eq!(jitter.take_spent(), Some(vec![3.0, 3.0]));
+    assert_eq!(play(&mut jitter, 2), vec![Some(vec![1.0, 1.0]), Some(vec![2.0, 2.0])]);
+    assert_eq!(jitter.take_spent(), Some(vec![1.0, 1.0]));
+    assert_eq!(jitter.take_spent(), None);
+}
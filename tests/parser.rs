@@ -290,3 +290,285 @@ fn same_arg_twice() {
             }
         ");
 }
+
+#[test]
+fn type_annotations() {
+    run_test!(
+        should_pass(lex, parse)
+        => r"
+            lowpass sig: Number, cutoff: Number = 0.5 -> Number {
+                sig * cutoff
+            }
+            apply f: Function, x {
+                f(x)
+            }
+        ");
+}
+
+#[test]
+fn unknown_type_annotation() {
+    run_test!(
+        should_pass(lex),
+        should_fail(parse)
+        => r"
+            fn x: Banana { x }
+        ");
+}
+
+#[test]
+fn doc_comments_attach_to_definitions() {
+    use interpreter::common::Context;
+    use interpreter::compiler::Compiler;
+    use interpreter::ast::Item;
+    use interpreter::doc::collect_docs;
+
+    let ctxt = Context::new("<test>".into(), r"
+/// The cutoff frequency.
+/// Measured in hertz.
+cutoff = 440;
+
+lowpass sig, amount=0.5 {
+    /// not attached to anything
+    sig * amount
+}
+/// Doubles its argument.
+double x { x * 2 }
+".into());
+    let mut compiler = Compiler::new(&ctxt);
+    assert!(compiler.lex() && compiler.parse());
+    let docs: Vec<_> = ctxt.ast.borrow().iter().map(|item| match *item {
+        Item::Assignment(ref x) => x.docs.clone(),
+        Item::FunctionDef(ref x) => x.docs.clone(),
+    }).collect();
+    assert_eq!(docs, vec![Some("The cutoff frequency.\nMeasured in hertz.".to_string()),
+                          None,
+                          Some("Doubles its argument.".to_string())]);
+
+    let entries = collect_docs(&ctxt);
+    assert_eq!(entries[1].signature, "lowpass sig, amount=0.5");
+    assert_eq!(entries[2].to_string(), "double x\n    Doubles its argument.");
+}
+
+#[test]
+fn test_blocks() {
+    use interpreter::common::Context;
+    use interpreter::compiler::Compiler;
+
+    let source = r#"
+        double x { x * 2 }
+        test "doubles" { assert(double(2) == 4); }
+        test = 1;
+    "#;
+    let ctxt = Context::new("<test>".into(), source.into());
+    let mut compiler = Compiler::new(&ctxt);
+    assert!(compiler.lex() && compiler.parse());
+    assert_eq!(ctxt.ast.borrow().len(), 2);
+    assert!(ctxt.tests.borrow().is_empty());
+
+    let ctxt = Context::new("<test>".into(), source.into());
+    ctxt.options.borrow_mut().test = true;
+    let mut compiler = Compiler::new(&ctxt);
+    assert!(compiler.lex() && compiler.parse());
+    assert_eq!(ctxt.ast.borrow().len(), 3);
+    let tests = ctxt.tests.borrow();
+    assert_eq!(tests[0].name, "doubles");
+    assert_eq!(tests[0].pos.line, 3);
+
+    run_test!(
+        should_fail(parse),
+        should_pass(lex)
+        => r#"
+            test "same" { assert(true); }
+            test "same" { assert(false); }
+        "#);
+}
+
+#[test]
+fn block_rate_annotations() {
+    run_test!(
+        should_pass(lex, parse)
+        => r"
+            main time { @block 1000 + 500 * sin(time) }
+            other time { (@smooth time) * 2 }
+        "
+    );
+    run_test!(
+        should_fail(parse),
+        should_pass(lex)
+        => r"
+            main time { @often time }
+        "
+    );
+}
+
+#[test]
+fn array_literals() {
+    run_test!(
+        should_pass(lex, parse)
+        => r"
+            notes = [60, 62, (64 + 3), f(1, 2)];
+            main time { seq(clock(4), [1, 2]) }
+        "
+    );
+    run_test!(
+        should_fail(parse),
+        should_pass(lex)
+        => r"
+            notes = [60, 62;
+        "
+    );
+}
+
+#[test]
+fn bus_definitions() {
+    run_test!(
+        should_pass(lex, parse)
+        => r#"
+            bus = 2;
+            bus "reverb" input { reverb(input, 0.8, 0.5, 1) * bus }
+            main time { send("reverb", sin(time)) }
+        "#
+    );
+    run_test!(
+        should_fail(parse),
+        should_pass(lex)
+        => r#"
+            bus "delay" a, b { a + b }
+        "#
+    );
+    run_test!(
+        should_fail(parse),
+        should_pass(lex)
+        => r#"
+            bus "echo" input { input }
+            bus "echo" x { x }
+        "#
+    );
+}
+
+#[test]
+fn oversampled_sections() {
+    run_test!(
+        should_pass(lex, parse)
+        => r"
+            main time { @oversample(4) softclip(sin(time * 2000) * 3, 10) + 1 }
+        "
+    );
+    run_test!(
+        should_fail(parse),
+        should_pass(lex)
+        => r"
+            main time { @oversample(3.5) time }
+        "
+    );
+    run_test!(
+        should_fail(parse),
+        should_pass(lex)
+        => r"
+            main time { @oversample time }
+        "
+    );
+}
+
+#[test]
+fn fft_sections() {
+    run_test!(
+        should_pass(lex, parse)
+        => r"
+            main time { @fft(sin(time * 440)) polar(bin_mag if bin_freq < 1000 else 0, bin_phase) + 1 }
+        "
+    );
+    run_test!(
+        should_fail(parse),
+        should_pass(lex)
+        => r"
+            main time { @fft time }
+        "
+    );
+    run_test!(
+        should_fail(parse),
+        should_pass(lex)
+        => r"
+            main time { @fft(time) @fft(bin_mag) polar(bin_mag, bin_phase) }
+        "
+    );
+}
+
+#[test]
+fn voice_blocks() {
+    run_test!(
+        should_pass(lex, parse)
+        => r"
+            voice = 2;
+            main time { voice { sin(time * note_freq) * note_gate } * voice }
+        "
+    );
+    run_test!(
+        should_fail(parse),
+        should_pass(lex)
+        => r"
+            main time { voice { voice { note_freq } } }
+        "
+    );
+}
+
+#[test]
+fn buffer_declarations() {
+    run_test!(
+        should_pass(lex, parse)
+        => r#"
+            buffer = 1;
+            buffer "loop" 2.5;
+            main time { playbuf("loop", 0, buffer) }
+        "#
+    );
+    run_test!(
+        should_fail(parse),
+        should_pass(lex)
+        => r#"
+            buffer "loop" 0;
+        "#
+    );
+    run_test!(
+        should_fail(parse),
+        should_pass(lex)
+        => r#"
+            buffer "loop" 2;
+            buffer "loop" 4;
+        "#
+    );
+    run_test!(
+        should_fail(parse),
+        should_pass(lex)
+        => r#"
+            buffer "loop";
+        "#
+    );
+    run_test!(
+        should_fail(parse),
+        should_pass(lex)
+        => r#"
+            buffer "drums" "no such file.wav";
+        "#
+    );
+}
+
+#[test]
+fn noinline_annotations() {
+    run_test!(
+        should_pass(lex, parse)
+        => r"
+            /// Kept out of line.
+            @noinline
+            shape x { sin(x) * 2 }
+            main time { shape(time) }
+        "
+    );
+    run_test!(
+        should_fail(parse),
+        should_pass(lex)
+        => r"
+            @noinline gain = 2;
+        "
+    );
+}
@@ -0,0 +1,37 @@
+#[macro_use(make_fn_ty)]
+extern crate interpreter;
+extern crate vec_map;
+
+use interpreter::common::Context;
+use interpreter::pipeline::Pipeline;
+use interpreter::tokens::Token;
+use interpreter::types::Type;
+
+#[test]
+fn phases_return_artifacts() {
+    let ctxt = Context::new("<test>".into(), r"
+        half x { x / 2 }
+        main time { half(time) }
+    ".into());
+    let mut pipeline = Pipeline::new(&ctxt);
+    pipeline.compiler().define_entrypoint("main", make_fn_ty!(&ctxt, fn(time: Number) -> Number));
+
+    let tokens = pipeline.lex().unwrap();
+    assert!(tokens.tokens().iter().any(|x| x.0 == Token::Const(2.0)));
+    let ast = pipeline.parse(tokens).unwrap();
+    assert_eq!(ast.items().len(), 2);
+    let typed = pipeline.typecheck(&ast).unwrap();
+    assert_eq!(typed.function_type("half").map(|x| x.returns), Some(Type::Number));
+
+    let mut program = pipeline.codegen(&typed, "main", 44100).unwrap();
+    assert_eq!(program.eval(3.0), 1.5);
+}
+
+#[test]
+fn phases_stop_at_errors() {
+    let ctxt = Context::new("<test>".into(), "main time { time + }".into());
+    let mut pipeline = Pipeline::new(&ctxt);
+    let tokens = pipeline.lex().unwrap();
+    let issues = pipeline.parse(tokens).err().unwrap();
+    assert!(issues.has_errors());
+}
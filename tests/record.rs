@@ -0,0 +1,70 @@
+extern crate interpreter;
+
+use interpreter::common::Context;
+use interpreter::compiler::Compiler;
+use interpreter::runtime::Program;
+
+#[test]
+fn records_are_passed_and_returned() {
+    let ctxt = Context::new("<test>".into(), r"
+        note time { {freq: time * 10, amp: 0.5} }
+        louder n { {amp: n.amp * 2, freq: n.freq} }
+        main time { n = louder(note(time)); n.freq + n.amp + {a: 1, b: 2}.b }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+    let mut program = Program::new(&compiler, "main", 10).unwrap();
+    assert_eq!(program.eval(0.0), 3.0);
+    assert_eq!(program.eval(2.0), 23.0);
+}
+
+#[test]
+fn destructuring_assigns_fields_by_name() {
+    let ctxt = Context::new("<test>".into(), r"
+        pair t { {a: t, b: 2 * t} }
+        main time { b, a = pair(time); lp, bp, hp = svf(1, 100, 1); a * 10 + b + (lp + bp + hp) * 100 }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+    // with a resonance of 1 the three outputs of `svf` always add up to its input
+    let mut program = Program::new(&compiler, "main", 1000).unwrap();
+    for i in 0..200 {
+        assert!((program.eval(1.0) - 112.0).abs() < 1e-9, "sample {}", i);
+    }
+
+    let ctxt = Context::new("<test>".into(), "main time { lp = svf(1, 100, 1).lp; lp }".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    assert!(compiler.compile().is_ok());
+    let mut program = Program::new(&compiler, "main", 1000).unwrap();
+    let mut lp = 0.0;
+    for _ in 0..200 {
+        lp = program.eval(0.0);
+    }
+    assert!((lp - 1.0).abs() < 1e-3, "{}", lp);
+}
+
+#[test]
+fn invalid_records() {
+    for &(source, msg) in &[
+        ("main time { {a: 1, a: 2}.a }", "error[E135]: the field `a` is given twice"),
+        ("main time { {a: 1, b: 2}.c }", "error[E135]: the record has no field `c`"),
+        ("main time { time.freq }", "error[E135]: cannot read the field `freq` of a value of type `Number`"),
+        ("main time { {a: true}.a }", "records may only hold numbers"),
+        ("main time { 1 if {a: 1} == {a: 1} else 0 }", "cannot apply equality operator to records"),
+        ("main time { lp, notch = svf(time, 100, 1); lp }", "error[E135]: the record has no field `notch`"),
+        ("main time { a, a = {a: 1}; a }", "error[E135]: `a` is assigned twice"),
+    ] {
+        let ctxt = Context::new("<test>".into(), source.into());
+        let mut compiler = Compiler::new(&ctxt);
+        compiler.declare_entrypoint("main");
+        let issues = compiler.compile().err().unwrap().to_string();
+        assert!(issues.contains(msg), "{}", issues);
+    }
+}
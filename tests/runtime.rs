@@ -0,0 +1,1096 @@
+#[macro_use(make_fn_ty)]
+extern crate interpreter;
+extern crate vec_map;
+
+use interpreter::common::Context;
+use interpreter::compiler::Compiler;
+use interpreter::runtime::{Program, Snapshot};
+#[cfg(feature = "count-allocations")]
+use interpreter::alloc::{self, CountingAllocator};
+
+#[cfg(feature = "count-allocations")]
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+macro_rules! compile {
+    ( $compiler:ident ) => {
+        $compiler.define_entrypoint("main", make_fn_ty!($compiler.context(), fn(time: Number) -> Number));
+        if let Err(issues) = $compiler.compile() {
+            panic!("compile failed:\n{}", issues);
+        }
+    }
+}
+
+#[test]
+fn stateful_intrinsic_per_call_site() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time {
+            accum(1) + accum(10)
+        }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.register_intrinsic("accum", make_fn_ty!(&ctxt, fn(x: Number) -> Number),
+                                |args, state| {
+        let mem = state.memory(1);
+        mem[0] += args[0];
+        mem[0]
+    });
+    compile!(compiler);
+
+    let mut program = Program::new(&compiler, "main", 44100).unwrap();
+    assert_eq!(program.next(), 11.0);
+    assert_eq!(program.next(), 22.0);
+    assert_eq!(program.next(), 33.0);
+    program.reset();
+    assert_eq!(program.next(), 11.0);
+}
+
+#[test]
+fn external_transport() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time {
+            time
+        }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compile!(compiler);
+
+    let mut program = Program::new(&compiler, "main", 10).unwrap();
+    assert_eq!(program.next(), 0.0);
+    assert_eq!(program.next(), 0.1);
+    assert_eq!(program.eval(7.0), 7.0);
+    program.set_transport(|index, _| 100.0 + index as f64);
+    assert_eq!(program.next(), 102.0);
+    assert_eq!(program.sample_index(), 3);
+}
+
+#[test]
+fn native_function_argument_order() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time {
+            sub(10, 3)
+        }
+    ".into());
+    // intern `a` first so that the declared order differs from the identifier order
+    ctxt.names.borrow_mut().new_id("a");
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.define_native_function("sub", &["b", "a"], |args, _| args[0] - args[1]);
+    compiler.define_entrypoint("main", make_fn_ty!(&ctxt, fn(time: Number) -> Number));
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+
+    let mut program = Program::new(&compiler, "main", 44100).unwrap();
+    assert_eq!(program.next(), 7.0);
+}
+
+#[test]
+fn snapshot_restore() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time {
+            accum(1)
+        }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.register_intrinsic("accum", make_fn_ty!(&ctxt, fn(x: Number) -> Number),
+                                |args, state| {
+        let mem = state.memory(1);
+        mem[0] += args[0];
+        mem[0]
+    });
+    compile!(compiler);
+
+    let mut program = Program::new(&compiler, "main", 44100).unwrap();
+    program.next();
+    program.next();
+    let snapshot = Snapshot::from_json(&program.save_state().to_json()).unwrap();
+    assert_eq!(program.next(), 3.0);
+    assert_eq!(program.next(), 4.0);
+    program.load_state(&snapshot).unwrap();
+    assert_eq!(program.sample_index(), 2);
+    assert_eq!(program.next(), 3.0);
+
+    // memory can't be matched up with a program which makes other calls
+    let ctxt = Context::new("<test>".into(), r"
+        main time {
+            accum(1) + accum(2)
+        }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.register_intrinsic("accum", make_fn_ty!(&ctxt, fn(x: Number) -> Number),
+                                |args, state| {
+        let mem = state.memory(1);
+        mem[0] += args[0];
+        mem[0]
+    });
+    compile!(compiler);
+    let mut edited = Program::new(&compiler, "main", 44100).unwrap();
+    assert!(edited.load_state(&snapshot).is_err());
+    assert_eq!(edited.sample_index(), 0);
+}
+
+#[test]
+fn fast_forward_matches_playing_through() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time { smooth(noise(), 50) + sin(time * 40) }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compile!(compiler);
+
+    let mut played = Program::new(&compiler, "main", 100).unwrap();
+    let expected: Vec<_> = (0..300).map(|_| played.next()).skip(250).collect();
+    let mut program = Program::new(&compiler, "main", 100).unwrap();
+    program.fast_forward(250);
+    assert_eq!(program.sample_index(), 250);
+    let samples: Vec<_> = (0..50).map(|_| program.next()).collect();
+    assert_eq!(samples, expected);
+
+    // from a saved state, only what comes after it is evaluated again
+    let snapshot = {
+        let mut program = Program::new(&compiler, "main", 100).unwrap();
+        program.fast_forward(200);
+        program.save_state()
+    };
+    program.load_state(&snapshot).unwrap();
+    program.fast_forward(250);
+    let samples: Vec<_> = (0..50).map(|_| program.next()).collect();
+    assert_eq!(samples, expected);
+}
+
+#[test]
+fn deterministic_math() {
+    use interpreter::math;
+    let ctxt = Context::new("<test>".into(), r"
+        main time { sin(time * 3) + exp(time) + time ^ 1.5 }
+    ".into());
+    ctxt.options.borrow_mut().deterministic = true;
+    let mut compiler = Compiler::new(&ctxt);
+    compile!(compiler);
+
+    let mut program = Program::new(&compiler, "main", 10).unwrap();
+    for i in 0..20 {
+        let time = i as f64 / 10.0;
+        assert_eq!(program.next(), math::sin(time * 3.0) + math::exp(time) + math::pow(time, 1.5));
+    }
+}
+
+#[test]
+fn fast_math() {
+    use interpreter::fastmath;
+    let ctxt = Context::new("<test>".into(), r"
+        main time { sin(time * 3) + exp(time) + time ^ 1.5 }
+        others time { cos(time * 5) + exp2(time) + log(time + 1) + log2(time + 0.1) }
+        exact time { sin_exact(time * 3) + exp_exact(time) + pow_exact(time, 1.5) }
+    ".into());
+    ctxt.options.borrow_mut().fast_math = true;
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("others");
+    compiler.declare_entrypoint("exact");
+    compile!(compiler);
+
+    // the generated code gives exactly what the Rust versions do
+    let mut program = Program::new(&compiler, "main", 10).unwrap();
+    let mut others = Program::new(&compiler, "others", 10).unwrap();
+    let mut exact = Program::new(&compiler, "exact", 10).unwrap();
+    for i in 0..20 {
+        let time = i as f64 / 10.0;
+        assert_eq!(program.next(), fastmath::sin(time * 3.0) + fastmath::exp(time) + fastmath::pow(time, 1.5));
+        assert_eq!(others.next(), fastmath::cos(time * 5.0) + fastmath::exp2(time) + fastmath::log(time + 1.0) +
+                                  fastmath::log2(time + 0.1));
+        assert_eq!(exact.next(), (time * 3.0).sin() + time.exp() + time.powf(1.5));
+    }
+}
+
+#[test]
+fn inlining_leaves_results_unchanged() {
+    let source = r"
+        square x { x * x }
+        @noinline
+        shape x { sin(x) * 2 }
+        main time { square(time) + square(3) + shape(time) + shape(square(time)) }
+    ";
+    let render = |threshold: usize| {
+        let ctxt = Context::new("<test>".into(), source.into());
+        ctxt.options.borrow_mut().inline_threshold = threshold;
+        let mut compiler = Compiler::new(&ctxt);
+        compile!(compiler);
+        assert!(ctxt.noinline.borrow().contains("shape"));
+        let mut program = Program::new(&compiler, "main", 100).unwrap();
+        (0..100).map(|_| program.next()).collect::<Vec<_>>()
+    };
+    assert_eq!(render(0), render(40));
+}
+
+#[test]
+fn memoizing_leaves_results_unchanged() {
+    use interpreter::memo::memoized_functions;
+    let source = r"
+        shape x { sin(x * 2) + 1 }
+        once x { sin(x) }
+        stateful x { phasor(x) + 1 }
+        indexed x { sin(x + sample_index) }
+        arith x { x * 2 }
+        main time {
+            shape(time) + shape(time) + shape(time * 2) + once(time) + stateful(time) + stateful(time) +
+                indexed(time) + indexed(time) + arith(time) + arith(time)
+        }
+    ";
+    let render = |memoize: bool| {
+        let ctxt = Context::new("<test>".into(), source.into());
+        ctxt.options.borrow_mut().memoize = memoize;
+        let mut compiler = Compiler::new(&ctxt);
+        compile!(compiler);
+        // only pure functions called from several places, which do more than arithmetic
+        let names: Vec<_> = memoized_functions(&ctxt).into_iter().map(|x| ctxt.lookup_name(x)).collect();
+        assert_eq!(names, vec!["shape".to_string()]);
+        let mut program = Program::new(&compiler, "main", 100).unwrap();
+        (0..200).map(|_| program.next()).collect::<Vec<_>>()
+    };
+    assert_eq!(render(true), render(false));
+}
+
+#[test]
+fn hoisting_leaves_results_unchanged() {
+    use interpreter::hoist::hoisted_expressions;
+    let source = r"
+        shape x { sin(x * 2) + 1 }
+        main time, sample_rate, gain = 1, cutoff = 1000 {
+            coefficient = exp(-cutoff / sample_rate) * 0.5;
+            sin(time * 100) * gain * shape(coefficient) + phasor(gain) + cos(channel) + log2(cutoff) * 2
+        }
+    ";
+    let render = |hoist: bool| {
+        let ctxt = Context::new("<test>".into(), source.into());
+        ctxt.options.borrow_mut().hoist = hoist;
+        let mut compiler = Compiler::new(&ctxt);
+        compiler.declare_entrypoint("main");
+        if let Err(issues) = compiler.compile() {
+            panic!("compile failed:\n{}", issues);
+        }
+        // the coefficient and shape(coefficient), which read `cutoff` and `sample_rate`, and
+        // log2(cutoff) * 2, which reads just `cutoff`
+        let hoisted: Vec<_> = hoisted_expressions(&ctxt).into_iter()
+            .map(|((id, _), args)| (ctxt.lookup_name(id), args)).collect();
+        assert_eq!(hoisted.len(), 3);
+        assert!(hoisted.iter().all(|&(ref name, _)| name == "main"));
+        assert_eq!(hoisted.iter().filter(|x| x.1.len() == 1).count(), 1);
+        let mut program = Program::new(&compiler, "main", 100).unwrap();
+        let mut samples: Vec<_> = (0..100).map(|_| program.next()).collect();
+        program.set_param("cutoff", 20.0);
+        samples.extend((0..100).map(|_| program.next()));
+        samples
+    };
+    assert_eq!(render(true), render(false));
+}
+
+#[test]
+fn strength_reduced_operations() {
+    let ctxt = Context::new("<test>".into(), r"
+        x time { time * 7.3 - 5 }
+        main time {
+            x(time) / 3 + x(time) / 0.25 + x(time) ^ 2 + x(time) ^ 3 + x(time) ^ 4 + x(time) ^ -1 +
+                x(time) % 1 + x(time) * 1
+        }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compile!(compiler);
+
+    let mut program = Program::new(&compiler, "main", 10).unwrap();
+    for i in 0..20 {
+        let x = i as f64 / 10.0 * 7.3 - 5.0;
+        let expected = x / 3.0 + x / 0.25 + x.powf(2.0) + x.powf(3.0) + x.powf(4.0) + x.powf(-1.0) + x % 1.0 + x;
+        let value = program.next();
+        assert!((value - expected).abs() <= 1e-12 * expected.abs().max(1.0), "{} != {}", value, expected);
+    }
+}
+
+#[test]
+fn profiler_counts_calls() {
+    let ctxt = Context::new("<test>".into(), r"
+        square x { x*x }
+        main time {
+            square(time) + square(2)
+        }
+    ".into());
+    ctxt.options.borrow_mut().profile = true;
+    let mut compiler = Compiler::new(&ctxt);
+    compile!(compiler);
+
+    let mut program = Program::new(&compiler, "main", 44100).unwrap();
+    for _ in 0..10 {
+        program.next();
+    }
+    let report = program.profiler().unwrap().report();
+    let square = report.lines().find(|l| l.ends_with(" square")).unwrap();
+    assert!(square.split_whitespace().nth(2) == Some("20"));
+}
+
+#[test]
+fn seeded_noise_is_reproducible() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time {
+            noise() + random()
+        }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compile!(compiler);
+
+    let render = |seed| {
+        let mut program = Program::new(&compiler, "main", 44100).unwrap();
+        program.set_seed(seed);
+        (0..16).map(|_| program.next()).collect::<Vec<_>>()
+    };
+    assert_eq!(render(1), render(1));
+    assert!(render(1) != render(2));
+}
+
+#[test]
+fn random_choices_are_drawn_on_triggers() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time {
+            trigger = clock(1);
+            choose(trigger, [1, 2, 3]) + prob(trigger, 1) * 100 + prob(trigger, 0) * 1000
+        }
+        range time { randrange(clock(1), 10, 20) }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("range");
+    compile!(compiler);
+
+    let render = |entrypoint, seed| {
+        let mut program = Program::new(&compiler, entrypoint, 4).unwrap();
+        program.set_seed(seed);
+        (0..16).map(|_| program.next()).collect::<Vec<_>>()
+    };
+    // drawn once a second and held in between
+    let samples = render("main", 1);
+    for chunk in samples.chunks(4) {
+        assert!([101.0, 102.0, 103.0].contains(&chunk[0]), "{:?}", samples);
+        assert!(chunk[1..].iter().all(|&x| x == chunk[0] - 100.0), "{:?}", samples);
+    }
+    let samples = render("range", 1);
+    for chunk in samples.chunks(4) {
+        assert!(chunk[0] >= 10.0 && chunk[0] < 20.0, "{:?}", samples);
+        assert!(chunk.iter().all(|&x| x == chunk[0]), "{:?}", samples);
+    }
+    assert_eq!(samples, render("range", 1));
+    assert!(samples != render("range", 2));
+}
+
+#[test]
+fn pitch_builtins() {
+    let ctxt = Context::new("<test>".into(), r#"
+        main time { mtof(69) }
+        degrees time { scale(2, "minor", 60) * 1000 + scale(7, "major", 60) }
+        below time { scale(-1, "major", 60) * 1000 + chord(60, "maj7", 3) }
+        nearest time { ftom(quantize(mtof(61.2), "pentatonic")) }
+    "#.into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("degrees");
+    compiler.declare_entrypoint("below");
+    compiler.declare_entrypoint("nearest");
+    compile!(compiler);
+
+    let first = |entrypoint| Program::new(&compiler, entrypoint, 4).unwrap().next();
+    assert_eq!(first("main"), 440.0);
+    assert_eq!(first("degrees"), 63072.0);
+    assert_eq!(first("below"), 59071.0);
+    assert!((first("nearest") - 62.0).abs() < 1e-9);
+}
+
+#[test]
+fn phasor_follows_modulated_frequency() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time { phasor(if time < 0.5 { 1 } else { 2 }) }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compile!(compiler);
+
+    let mut program = Program::new(&compiler, "main", 4).unwrap();
+    let samples: Vec<_> = (0..5).map(|_| program.next()).collect();
+    assert_eq!(samples, vec![0.0, 0.25, 0.5, 0.0, 0.5]);
+}
+
+#[test]
+fn sync_and_sample_and_hold() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time { sync(phasor(1), clock(2)) }
+        held time { samplehold(time * 4, clock(1)) + ringmod(time, 2) }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("held");
+    compile!(compiler);
+
+    let mut program = Program::new(&compiler, "main", 4).unwrap();
+    let samples: Vec<_> = (0..5).map(|_| program.next()).collect();
+    assert_eq!(samples, vec![0.0, 0.25, 0.0, 0.25, 0.0]);
+
+    let mut program = Program::new(&compiler, "held", 4).unwrap();
+    let samples: Vec<_> = (0..5).map(|_| program.next()).collect();
+    assert_eq!(samples, vec![0.0, 0.5, 1.0, 1.5, 6.0]);
+}
+
+#[test]
+fn clock_dividers_and_arpeggiators() {
+    let ctxt = Context::new("<test>".into(), r#"
+        main time { clockdiv(clock(4), 2) }
+        multiplied time { clockmult(clock(1), 2) }
+        arpeggiated time { arp([64, 60, 67], clock(4), "updown") }
+    "#.into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("multiplied");
+    compiler.declare_entrypoint("arpeggiated");
+    compile!(compiler);
+
+    let mut program = Program::new(&compiler, "main", 4).unwrap();
+    let samples: Vec<_> = (0..5).map(|_| program.next()).collect();
+    assert_eq!(samples, vec![1.0, 0.0, 1.0, 0.0, 1.0]);
+
+    // the first period only passes the clock through, since its length is not known yet
+    let mut program = Program::new(&compiler, "multiplied", 4).unwrap();
+    let samples: Vec<_> = (0..9).map(|_| program.next()).collect();
+    assert_eq!(samples, vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0]);
+
+    let mut program = Program::new(&compiler, "arpeggiated", 4).unwrap();
+    let samples: Vec<_> = (0..5).map(|_| program.next()).collect();
+    assert_eq!(samples, vec![60.0, 64.0, 67.0, 64.0, 60.0]);
+}
+
+#[test]
+fn swing_and_humanize() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time { swing(clock(1), 0.5) }
+        loose time { humanize(clock(1), 100, 7) }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("loose");
+    compile!(compiler);
+
+    let fired = |program: &mut Program, samples| {
+        (0..samples).filter(|_| program.next() != 0.0).collect::<Vec<_>>()
+    };
+    // off-beats are a quarter of the time between beats late
+    let mut program = Program::new(&compiler, "main", 8).unwrap();
+    assert_eq!(fired(&mut program, 32), vec![0, 10, 16, 26]);
+
+    let mut program = Program::new(&compiler, "loose", 100).unwrap();
+    let times = fired(&mut program, 400);
+    assert_eq!(times.len(), 4);
+    for (i, &t) in times.iter().enumerate() {
+        assert!(t >= i * 100 && t <= i * 100 + 10);
+    }
+    program.reset();
+    assert_eq!(fired(&mut program, 400), times);
+    program.set_seed(5);
+    assert!(fired(&mut program, 400) != times);
+}
+
+#[test]
+fn compressor_with_sidechain() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time { compress(1, -20, 2, 0, 1) }
+        ducked time { compress(0.5, -20, 1000, 0, 1, if time < 0.5 { 0 } else { 1 }) }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("ducked");
+    compile!(compiler);
+
+    // with an instant attack, 20 dB over the threshold comes out 10 dB over it
+    let mut program = Program::new(&compiler, "main", 4).unwrap();
+    assert!((program.next() - 10f64.powf(-0.5)).abs() < 1e-9);
+
+    // the signal is only turned down once the sidechain is loud
+    let mut program = Program::new(&compiler, "ducked", 4).unwrap();
+    let samples: Vec<_> = (0..3).map(|_| program.next()).collect();
+    assert_eq!(&samples[..2], &[0.5, 0.5]);
+    assert!(samples[2] < 0.06);
+}
+
+#[test]
+fn vocoder_follows_its_modulator() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time { vocode(sin(2 * 3.14159 * 440 * time), sin(2 * 3.14159 * 440 * time), 16) }
+        silent time { vocode(0, sin(2 * 3.14159 * 440 * time), 16) }
+        apart time { vocode(sin(2 * 3.14159 * 200 * time), sin(2 * 3.14159 * 3000 * time), 16) }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("silent");
+    compiler.declare_entrypoint("apart");
+    compile!(compiler);
+
+    let power = |name| {
+        let mut program = Program::new(&compiler, name, 8000).unwrap();
+        (0..4000).fold(0.0, |sum, _| sum + program.next().powi(2)) / 4000.0
+    };
+    let (main, silent, apart) = (power("main"), power("silent"), power("apart"));
+    assert!(main > 0.05);
+    assert_eq!(silent, 0.0);
+    // bands the modulator has nothing in let little of the carrier through
+    assert!(apart < main / 10.0);
+}
+
+#[test]
+fn plucked_strings_and_resonators() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time { pluck(clock(0), 100, 0) }
+        damped time { pluck(clock(0), 100, 1) }
+        struck time { modal(clock(0), [250], [1, 3]) }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("damped");
+    compiler.declare_entrypoint("struck");
+    compile!(compiler);
+
+    // an undamped string repeats its noise every period
+    let mut program = Program::new(&compiler, "main", 1000).unwrap();
+    let samples: Vec<_> = (0..30).map(|_| program.next()).collect();
+    assert!(samples.iter().any(|&x| x != 0.0));
+    assert_eq!(&samples[..20], &samples[10..]);
+
+    let mut program = Program::new(&compiler, "damped", 1000).unwrap();
+    let samples: Vec<_> = (0..1000).map(|_| program.next().abs()).collect();
+    let peak = |samples: &[f64]| samples.iter().fold(0.0, |max: f64, &x| max.max(x));
+    assert!(peak(&samples[900..]) < peak(&samples[..100]) / 2.0);
+
+    // a mode at a quarter of the sample rate alternates, and the extra decay is left out
+    let r2 = 10f64.powf(-6.0 / 1000.0);
+    let mut program = Program::new(&compiler, "struck", 1000).unwrap();
+    let samples: Vec<_> = (0..5).map(|_| program.next()).collect();
+    for (x, y) in samples.iter().zip(&[1.0, 0.0, -r2, 0.0, r2 * r2]) {
+        assert!((x - y).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn recorded_buffers_play_back() {
+    let ctxt = Context::new("<test>".into(), r#"
+        buffer "loop" 0.5;
+        main time {
+            record("loop", time * 10, clock(0));
+            playbuf("loop", 0, if time < 0.5 { 0 } else { 1 })
+        }
+        scrub time { record("loop", time * 10, clock(0)); playbuf("loop", 0.25, -1) }
+    "#.into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("scrub");
+    compile!(compiler);
+
+    // nothing plays until the recording has filled the buffer, which then loops
+    let mut program = Program::new(&compiler, "main", 4).unwrap();
+    let samples: Vec<_> = (0..6).map(|_| program.next()).collect();
+    assert_eq!(samples, vec![0.0, 0.0, 0.0, 2.5, 0.0, 2.5]);
+
+    // backwards from the middle, reading what was just written
+    let mut program = Program::new(&compiler, "scrub", 4).unwrap();
+    let samples: Vec<_> = (0..3).map(|_| program.next()).collect();
+    assert_eq!(samples, vec![0.0, 0.0, 2.5]);
+}
+
+#[test]
+fn stretched_buffers() {
+    let ctxt = Context::new("<test>".into(), r#"
+        buffer "loop" 1;
+        main time { record("loop", sin(time * 300), clock(0)); stretch("loop", 0, 0.5, 0.5) - playbuf("loop", 0, 0.5) }
+        shifted time { record("loop", sin(time * 300), clock(0)); stretch("loop", 0, 0.5, 1) - playbuf("loop", 0, 0.5) }
+    "#.into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("shifted");
+    compile!(compiler);
+
+    // grains which play at the speed the head moves overlap into the buffer unchanged
+    let mut program = Program::new(&compiler, "main", 100).unwrap();
+    for _ in 0..300 {
+        assert!(program.next().abs() < 1e-9);
+    }
+
+    let mut program = Program::new(&compiler, "shifted", 100).unwrap();
+    let differences: Vec<_> = (0..300).map(|_| program.next()).collect();
+    assert!(differences.iter().skip(100).any(|x| x.abs() > 0.1));
+}
+
+#[test]
+fn voices_sum_their_notes() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time { voice { note_freq * note_velocity * note_gate } + note_freq }
+        counted time { voice { phasor(1000) * note_gate } }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("counted");
+    compile!(compiler);
+
+    let mut program = Program::new(&compiler, "main", 4).unwrap();
+    assert_eq!(program.next(), 0.0);
+    program.note_on(69.0, 0.5);
+    assert_eq!(program.next(), 220.0);
+    program.note_on(57.0, 1.0);
+    assert_eq!(program.next(), 440.0);
+    program.note_off(69.0);
+    assert_eq!(program.next(), 220.0);
+    // once every voice is held, a new note takes the one started longest ago
+    for i in 0..7 {
+        program.note_on(60.0 + i as f64, 0.0);
+    }
+    program.note_on(81.0, 1.0);
+    assert_eq!(program.next(), 880.0);
+
+    // each voice keeps its own memory
+    let mut program = Program::new(&compiler, "counted", 4000).unwrap();
+    program.note_on(60.0, 1.0);
+    let samples: Vec<_> = (0..3).map(|_| program.next()).collect();
+    assert_eq!(samples, vec![0.0, 0.25, 0.5]);
+}
+
+#[test]
+fn stereo_programs() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time { pan(1, -1) + stereo(time, 2) * 0.5 + width(1, 0, 0) }
+        center time { left(pan(1, 0)) * 1000 + mono(-stereo(1, 3)) }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("center");
+    compile!(compiler);
+
+    let mut program = Program::new(&compiler, "main", 4).unwrap();
+    assert_eq!(program.channels(), 2);
+    assert_eq!(program.next(), 1.5);
+    assert_eq!(program.frame(), &[1.5, 1.5]);
+    let mut buffer = [0f32; 4];
+    program.fill(&mut buffer);
+    assert_eq!(buffer, [1.625, 1.5, 1.75, 1.5]);
+
+    let mut program = Program::new(&compiler, "center", 4).unwrap();
+    assert_eq!(program.channels(), 1);
+    assert!((program.next() - (0.5f64.sqrt() * 1000.0 - 2.0)).abs() < 1e-9);
+}
+
+#[test]
+fn buses_mix_their_sends() {
+    let ctxt = Context::new("<test>".into(), r#"
+        bus "double" input { input * 2 }
+        bus "pre" input { send("double", input + 1); 0 }
+        bus "wide" input { pan(input, -1) }
+        main time { send("pre", 1); send("double", 10); send("wide", 1); time * 4 }
+    "#.into());
+    let mut compiler = Compiler::new(&ctxt);
+    compile!(compiler);
+
+    // "pre" is processed before "double", which it sends to
+    let mut program = Program::new(&compiler, "main", 4).unwrap();
+    assert_eq!(program.channels(), 2);
+    assert_eq!(program.next(), 25.0);
+    assert!((program.frame()[1] - 24.0).abs() < 1e-9);
+    assert_eq!(program.next(), 26.0);
+}
+
+#[test]
+fn oversampled_sections_are_delayed_copies() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time { @oversample(4) time * 8 }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compile!(compiler);
+
+    // a ramp passes through unchanged apart from the delay of the filter
+    let mut program = Program::new(&compiler, "main", 100).unwrap();
+    for _ in 0..20 {
+        program.next();
+    }
+    assert!((program.next() - 8.0 * 16.0 / 100.0).abs() < 0.01);
+}
+
+#[test]
+fn fft_sections_change_the_spectrum() {
+    use std::f64::consts::PI;
+
+    // both sines fall on the center of a bin, so once the first frames have passed the spectrum
+    // is resynthesized exactly, a transform later
+    let render = |body: &str| -> Vec<f64> {
+        let ctxt = Context::new("<test>".into(), format!(r"
+            main time {{
+                x = sin(time * 2 * {pi} * 375) + sin(time * 2 * {pi} * 3000);
+                {}
+            }}
+        ", body, pi = PI));
+        let mut compiler = Compiler::new(&ctxt);
+        compile!(compiler);
+        let mut program = Program::new(&compiler, "main", 24000).unwrap();
+        (0..4096).map(|_| program.next()).collect()
+    };
+    let input = render("x");
+    let same = render("@fft(x) polar(bin_mag, bin_phase)");
+    let low = render("@fft(x) polar(bin_mag if bin_freq < 1000 else 0, bin_phase)");
+    for i in 3072..4096 {
+        let t = (i - 1024) as f64 / 24000.0;
+        assert!((same[i] - input[i - 1024]).abs() < 1e-9);
+        assert!((low[i] - (t * 2.0 * PI * 375.0).sin()).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn checked_asserts_stop_the_program() {
+    let ctxt = Context::new("<test>".into(), r#"
+        main time { assert(time < 2, "too late"); time }
+    "#.into());
+    ctxt.options.borrow_mut().checked = true;
+    let mut compiler = Compiler::new(&ctxt);
+    compile!(compiler);
+
+    let mut program = Program::new(&compiler, "main", 1).unwrap();
+    for _ in 0..2 {
+        program.next();
+    }
+    assert_eq!(program.failure(), None);
+    program.next();
+    assert!(program.failure().unwrap().contains("too late"));
+    program.reset();
+    assert_eq!(program.failure(), None);
+}
+
+#[test]
+fn probes_are_written_as_csv() {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Read;
+
+    let ctxt = Context::new("<test>".into(), r#"
+        main time {
+            x = probe("doubled", time * 2);
+            probe("late, \"x\"", x) if time > 1 else x
+        }
+    "#.into());
+    let mut compiler = Compiler::new(&ctxt);
+    compile!(compiler);
+
+    let path = env::temp_dir().join("synthizer-probes.csv");
+    let mut program = Program::new(&compiler, "main", 1).unwrap();
+    program.record_probes(File::create(&path).unwrap(), Some(3)).unwrap();
+    let mut buffer = [0f32; 4];
+    program.fill(&mut buffer);
+    assert_eq!(buffer, [0.0, 2.0, 4.0, 6.0]);
+
+    let mut csv = String::new();
+    File::open(&path).unwrap().read_to_string(&mut csv).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(csv, "time,doubled,\"late, \"\"x\"\"\"\n0,0,\n1,2,\n2,4,4\n");
+}
+
+#[test]
+fn probe_write_errors_stop_the_program() {
+    use std::io::{self, Write};
+
+    struct Full;
+
+    impl Write for Full {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::Other, "disk full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let ctxt = Context::new("<test>".into(), r#"
+        main time { probe("x", time) }
+    "#.into());
+    let mut compiler = Compiler::new(&ctxt);
+    compile!(compiler);
+
+    let mut program = Program::new(&compiler, "main", 1).unwrap();
+    program.record_probes(Full, None).unwrap();
+    let mut buffer = [0f32; 4];
+    program.fill(&mut buffer);
+    assert_eq!(buffer, [0.0, 1.0, 2.0, 3.0]);
+    assert!(program.failure().unwrap().contains("disk full"));
+}
+
+#[test]
+fn traced_calls_are_throttled() {
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let ctxt = Context::new("<test>".into(), r"
+        half x, loud { x if loud else x / 2 }
+        main time { half(time, false) }
+    ".into());
+    {
+        let mut options = ctxt.options.borrow_mut();
+        options.trace = vec!["half".to_string()];
+        options.trace_interval = 2;
+    }
+    let mut compiler = Compiler::new(&ctxt);
+    compile!(compiler);
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let mut program = Program::new(&compiler, "main", 1).unwrap();
+    program.set_trace_output(Shared(log.clone()));
+    for _ in 0..4 {
+        program.next();
+    }
+    let log = String::from_utf8(log.lock().unwrap().clone()).unwrap();
+    assert_eq!(log, "[0.000000s] half(x=0, loud=false) = 0\n\
+                     [2.000000s] half(x=2, loud=false) = 1\n");
+}
+
+#[test]
+fn declared_entrypoints_bind_their_inputs() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time, sample_rate, beat, gain = -0.5 { (time + sample_rate + beat) * gain }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+
+    let mut program = Program::new(&compiler, "main", 10).unwrap();
+    assert_eq!(program.params(), &[("gain".to_string(), -0.5)][..]);
+    // two beats a second at the default of 120 bpm
+    assert_eq!(program.eval(1.0), -6.5);
+    assert!(program.set_param("gain", 2.0));
+    assert!(!program.set_param("volume", 1.0));
+    program.set_tempo(60.0);
+    assert_eq!(program.eval(1.0), 24.0);
+}
+
+#[test]
+#[cfg(feature = "count-allocations")]
+fn freeing_counts_as_heap_use() {
+    let boxed = Box::new(1);
+    let ((), count) = alloc::count_allocations(|| drop(boxed));
+    assert_eq!(count, 1);
+
+    let before = alloc::violations();
+    alloc::check_no_alloc(|| ());
+    assert_eq!(alloc::violations(), before);
+    let boxed = Box::new(1);
+    alloc::check_no_alloc(|| drop(boxed));
+    assert_eq!(alloc::violations(), before + 1);
+}
+
+#[test]
+#[cfg(feature = "count-allocations")]
+fn rendering_does_not_allocate() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time, gain = 0.5 {
+            reverb(accum(time) * gain, 0.5, 0.5, 0.3) + @smooth time
+        }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.register_intrinsic("accum", make_fn_ty!(&ctxt, fn(x: Number) -> Number),
+                                |args, state| {
+        let mem = state.memory(1);
+        mem[0] += args[0];
+        mem[0]
+    });
+    compiler.declare_entrypoint("main");
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+
+    let mut program = Program::new(&compiler, "main", 44100).unwrap();
+    let mut buffer = vec![0f32; 512];
+    let ((), allocations) = alloc::count_allocations(|| program.fill(&mut buffer));
+    assert_eq!(allocations, 0);
+    program.reset();
+    let ((), allocations) = alloc::count_allocations(|| program.fill(&mut buffer));
+    assert_eq!(allocations, 0);
+}
+
+#[test]
+fn smoothing() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time, gain = smooth(1, 1000), offset = 0 { gain + offset }
+        named time, gain = smooth(time_ms = 1000, x = 1) { gain }
+        follow time { smooth(if time < 0.2 { 0 } else { 1 }, 100) }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    compiler.declare_entrypoint("named");
+    compiler.declare_entrypoint("follow");
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+
+    // a time constant of one sample covers 1 - 1/e of the distance each sample
+    let mut program = Program::new(&compiler, "follow", 10).unwrap();
+    let samples: Vec<_> = (0..4).map(|_| program.next()).collect();
+    assert_eq!(&samples[..2], &[0.0, 0.0]);
+    assert!((samples[2] - (1.0 - (-1.0f64).exp())).abs() < 1e-12);
+    assert!(samples[3] > samples[2] && samples[3] < 1.0);
+
+    let mut program = Program::new(&compiler, "main", 1000).unwrap();
+    assert_eq!(program.next(), 1.0);
+    program.set_param("gain", 2.0);
+    program.set_param("offset", 10.0);
+    let next = program.next();
+    assert!(next > 11.0 && next < 11.01, "{}", next);
+    program.reset();
+    assert_eq!(program.next(), 12.0);
+
+    // the arguments of smooth may be given by name in any order
+    let mut program = Program::new(&compiler, "named", 1000).unwrap();
+    assert_eq!(program.next(), 1.0);
+    assert!(program.param_smoothed(0));
+}
+
+#[test]
+fn triggers() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time { clock(2) + euclid(8, 3, clock(8)) * 10 }
+        envelope time { ad(clock(1), 0.25, 0.5) }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    compiler.declare_entrypoint("envelope");
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+
+    let mut program = Program::new(&compiler, "main", 8).unwrap();
+    let samples: Vec<_> = (0..8).map(|_| program.next()).collect();
+    assert_eq!(samples, vec![11.0, 0.0, 0.0, 10.0, 1.0, 0.0, 10.0, 0.0]);
+
+    let mut program = Program::new(&compiler, "envelope", 8).unwrap();
+    let samples: Vec<_> = (0..9).map(|_| program.next()).collect();
+    assert_eq!(samples, vec![0.5, 1.0, 0.75, 0.5, 0.25, 0.0, 0.0, 0.0, 0.5]);
+}
+
+#[test]
+fn sequencer() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time { seq(clock(2), [60, 62, 64]) + gate[clock = clock(2), values = [1, 0, 1]] * 1000 }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+
+    let mut program = Program::new(&compiler, "main", 4).unwrap();
+    let samples: Vec<_> = (0..8).map(|_| program.next()).collect();
+    assert_eq!(samples, vec![1060.0, 60.0, 62.0, 62.0, 1064.0, 64.0, 1060.0, 60.0]);
+}
+
+#[test]
+fn select_holds_its_index_within_the_array() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time { select(time * 4 - 1, [10, 20, 30]) }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+
+    let mut program = Program::new(&compiler, "main", 4).unwrap();
+    let samples: Vec<_> = (0..6).map(|_| program.next()).collect();
+    assert_eq!(samples, vec![10.0, 10.0, 20.0, 30.0, 30.0, 30.0]);
+}
+
+#[test]
+fn entrypoint_inputs_need_a_value() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time, cutoff { time * cutoff }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    let issues = format!("{}", compiler.compile().err().unwrap());
+    assert!(issues.contains("error[E113]: argument `cutoff` of entrypoint `main` has no value to be given"), "{}", issues);
+}
+
+#[test]
+fn builtin_variables() {
+    let ctxt = Context::new("<test>".into(), r"
+        period = 1 / sample_rate;
+        main time { period * 1000 + sample_index * 10 + channel }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compile!(compiler);
+
+    let mut program = Program::new(&compiler, "main", 4).unwrap();
+    assert_eq!(program.next(), 250.0);
+    assert_eq!(program.next(), 260.0);
+    program.set_channel(1);
+    assert_eq!(program.next(), 271.0);
+}
+
+#[test]
+fn known_sample_rate_is_constant() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time { sample_rate }
+    ".into());
+    ctxt.options.borrow_mut().sample_rate = Some(8);
+    let mut compiler = Compiler::new(&ctxt);
+    compile!(compiler);
+
+    let mut program = Program::new(&compiler, "main", 8).unwrap();
+    program.set_sample_rate(16);
+    assert_eq!(program.next(), 8.0);
+}
+
+#[test]
+fn block_rate_expressions() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time { (@block time) * 100 + @smooth time }
+    ".into());
+    ctxt.options.borrow_mut().block_size = 4;
+    let mut compiler = Compiler::new(&ctxt);
+    compile!(compiler);
+
+    let mut program = Program::new(&compiler, "main", 1).unwrap();
+    let samples: Vec<_> = (0..12).map(|_| program.next()).collect();
+    // `@smooth` ramps towards each new value over the block after it was evaluated
+    assert_eq!(samples, vec![0.0, 0.0, 0.0, 0.0, 400.0, 401.0, 402.0, 403.0, 804.0, 805.0, 806.0, 807.0]);
+}
+
+#[test]
+fn blocks_follow_the_sample_index() {
+    let ctxt = Context::new("<test>".into(), r"
+        hold x { @block x }
+        main time { hold(time) + hold(time) if sample_index % 3 < 1 else -1 }
+    ".into());
+    ctxt.options.borrow_mut().block_size = 4;
+    let mut compiler = Compiler::new(&ctxt);
+    compile!(compiler);
+
+    let mut program = Program::new(&compiler, "main", 1).unwrap();
+    let samples: Vec<_> = (0..10).map(|_| program.next()).filter(|&x| x != -1.0).collect();
+    // skipped samples and calls from two places don't move the block along
+    assert_eq!(samples, vec![0.0, 0.0, 12.0, 18.0]);
+}
+
+#[test]
+fn script_becomes_the_entrypoint() {
+    let ctxt = Context::new("<test>".into(), r"
+        gain = 2;
+        time * gain + 1
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+    let mut program = Program::new(&compiler, "main", 10).unwrap();
+    assert_eq!(program.eval(3.0), 7.0);
+
+    let ctxt = Context::new("<test>".into(), r"
+        main time { time }
+        time * 2
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    let issues = format!("{}", compiler.compile().err().unwrap());
+    assert!(issues.contains("error[E132]: this expression would be the body of `main`, which is already defined"),
+            "{}", issues);
+}
@@ -0,0 +1,40 @@
+extern crate interpreter;
+
+use interpreter::scope::ScopedTable;
+
+#[test]
+fn nested_scopes() {
+    let mut table = ScopedTable::new();
+    table.set_val(1, 0, "global");
+    table.push(10);
+    table.set_val(2, 11, "local");
+    table.set_val(1, 12, "shadow");
+    assert_eq!(table.get_symbol(1).unwrap().val, "shadow");
+    assert_eq!(table.get_symbol_depth(2), Some(0));
+    assert_eq!(table.visible_symbols().len(), 2);
+    let local = table.get_symbol(2).unwrap().scope;
+    table.pop();
+
+    assert_eq!(table.get_symbol(1).unwrap().val, "global");
+    assert!(table.get_symbol(2).is_none());
+    assert_eq!(table.scope_id(local), 11);
+
+    // scopes can be re-entered later, such as for the body of a function
+    table.enter(local);
+    assert_eq!(table.get_symbol(2).unwrap().val, "local");
+    assert_eq!(table.get_symbol(1).unwrap().val, "global");
+    table.pop();
+}
+
+#[test]
+fn reentered_scopes_are_reused() {
+    let mut table: ScopedTable<()> = ScopedTable::new();
+    table.push(5);
+    let first = table.current();
+    table.pop();
+    table.push(5);
+    assert_eq!(table.current(), first);
+    table.pop();
+    assert_eq!(table.children(0), &[first]);
+    assert_eq!(table.parent(first), Some(0));
+}
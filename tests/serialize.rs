@@ -0,0 +1,24 @@
+extern crate interpreter;
+extern crate rustc_serialize;
+
+use interpreter::common::Context;
+use interpreter::compiler::Compiler;
+use interpreter::serialize::{serialize_ast, Format};
+
+use rustc_serialize::json::Json;
+
+#[test]
+fn ast_round_trips_through_json() {
+    let ctxt = Context::new("<test>".into(), r"
+        square x { x*x }
+        y = square(2) if true else 0;
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    assert!(compiler.lex());
+    assert!(compiler.parse());
+    let json = Json::from_str(&serialize_ast(&ctxt, Format::Json)).unwrap();
+    let items = json.find("items").unwrap().as_array().unwrap();
+    assert_eq!(items.len(), 2);
+    assert!(json.find("names").unwrap().as_object().unwrap()
+                .values().any(|x| x.as_string() == Some("square")));
+}
@@ -0,0 +1,75 @@
+extern crate interpreter;
+
+use interpreter::audio::{write_wav, RenderSettings};
+use interpreter::audio::layout::ChannelLayout;
+use interpreter::common::Context;
+use interpreter::compiler::Compiler;
+use interpreter::runtime::Program;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+
+fn with_program<F: FnOnce(Program)>(source: &str, f: F) {
+    let ctxt = Context::new("<test>".into(), source.into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+    f(Program::new(&compiler, "main", 8000).unwrap())
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    bytes[offset..offset + 4].iter().rev().fold(0, |x, &b| x << 8 | b as u32)
+}
+
+#[test]
+fn layouts() {
+    assert_eq!(ChannelLayout::parse("5.1"), Some(ChannelLayout::Surround51));
+    assert_eq!(ChannelLayout::parse("5.0"), None);
+    assert_eq!(ChannelLayout::Surround51.mask(), 0x3f);
+    assert_eq!(ChannelLayout::Surround71.mask(), 0x63f);
+    assert_eq!(ChannelLayout::Quad.channels(), 4);
+    assert_eq!(ChannelLayout::Surround71.channels(), 8);
+    // the LFE channel has no direction
+    let azimuths = ChannelLayout::Surround51.azimuths();
+    assert_eq!(azimuths.len(), 6);
+    assert_eq!(azimuths[3], None);
+    assert_eq!(azimuths[2], Some(0.0));
+}
+
+#[test]
+fn speakers_route_to_their_channel() {
+    with_program("main time { channel(speaker(time, 2) + speaker(1, 3), 2) * 10 + \
+                              channel(speaker(time, 2) + speaker(1, 3), 3) + \
+                              channel(speaker(time, 20), 7) * 100 }", |mut program| {
+        assert_eq!(program.eval(5.0), 551.0);
+    });
+}
+
+#[test]
+fn files_record_their_layout() {
+    let path = env::temp_dir().join("synthizer-surround-test.wav");
+    let mut settings = RenderSettings::new(8000);
+    settings.channel_layout = Some(ChannelLayout::Surround51);
+    settings.channels = 6;
+    with_program("main time { speaker(0.5, 1) }", |program| {
+        write_wav(program, path.to_string_lossy().into_owned(), 0.5, &settings).unwrap();
+    });
+    let mut bytes = Vec::new();
+    File::open(&path).unwrap().read_to_end(&mut bytes).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let samples = 4000 * 6;
+    assert_eq!(&bytes[..4], b"RIFF");
+    assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+    // WAVE_FORMAT_EXTENSIBLE, with 6 channels, and the 5.1 mask
+    assert_eq!(&bytes[20..24], &[0xfe, 0xff, 6, 0]);
+    assert_eq!(u32_at(&bytes, 40), 0x3f);
+    assert_eq!(&bytes[60..64], b"data");
+    assert_eq!(u32_at(&bytes, 64) as usize, samples * 2);
+    assert_eq!(bytes.len(), 68 + samples * 2);
+    // the front right channel of the first frame is at half of full scale
+    assert_eq!(&bytes[68..80], &[0, 0, 0xff, 0x3f, 0, 0, 0, 0, 0, 0, 0, 0]);
+}
@@ -0,0 +1,34 @@
+extern crate interpreter;
+
+use interpreter::sweep::{Range, grid, random, index_csv};
+
+#[test]
+fn parse_ranges() {
+    let range = Range::parse("cutoff=200..8000:8").unwrap();
+    assert_eq!(range.param, "cutoff");
+    assert_eq!((range.low, range.high, range.steps), (200.0, 8000.0, 8));
+    assert_eq!(range.value(0), 200.0);
+    assert_eq!(range.value(7), 8000.0);
+    assert!(Range::parse("cutoff=200").is_err());
+    assert!(Range::parse("cutoff=200..8000").is_err());
+    assert!(Range::parse("cutoff=200..8000:0").is_err());
+    assert!(Range::parse("=0..1:2").is_err());
+}
+
+#[test]
+fn grid_and_random_points() {
+    let ranges = vec![Range::parse("a=0..1:2").unwrap(), Range::parse("b=10..30:3").unwrap()];
+    assert_eq!(grid(&ranges), vec![
+        vec![0.0, 10.0], vec![0.0, 20.0], vec![0.0, 30.0],
+        vec![1.0, 10.0], vec![1.0, 20.0], vec![1.0, 30.0],
+    ]);
+
+    let points = random(&ranges, 5, 7);
+    assert_eq!(points.len(), 5);
+    assert!(points.iter().all(|x| x[0] >= 0.0 && x[0] <= 1.0 && x[1] >= 10.0 && x[1] <= 30.0));
+    assert_eq!(points, random(&ranges, 5, 7));
+    assert!(points != random(&ranges, 5, 8));
+
+    let files = vec!["pad-1.wav".to_string(), "pad-2.wav".to_string()];
+    assert_eq!(index_csv(&ranges, &files, &grid(&ranges)[..2]), "file,a,b\npad-1.wav,0,10\npad-2.wav,0,20\n");
+}
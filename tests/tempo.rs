@@ -0,0 +1,63 @@
+extern crate interpreter;
+
+use interpreter::common::Context;
+use interpreter::compiler::Compiler;
+use interpreter::runtime::Program;
+use interpreter::tempo::TempoMap;
+
+#[test]
+fn beats_follow_the_tempo() {
+    // 60 bpm for two seconds, then down to 120 over the next two, then an immediate jump to 240
+    let map = TempoMap::from_json(r#"[
+        {"time": 4, "bpm": 120},
+        {"time": 0, "bpm": 60},
+        {"time": 2, "bpm": 60},
+        {"time": 4, "bpm": 240}
+    ]"#).unwrap();
+    assert_eq!(map.beat_at(1.0), 1.0);
+    assert_eq!(map.bpm_at(3.0), 90.0);
+    assert_eq!(map.beat_at(4.0), 5.0);
+    assert_eq!(map.bpm_at(5.0), 240.0);
+    assert_eq!(map.beat_at(5.0), 9.0);
+    for &time in &[0.5, 2.5, 3.0, 3.9, 4.5, 10.0] {
+        assert!((map.time_at_beat(map.beat_at(time)) - time).abs() < 1e-9);
+    }
+
+    assert_eq!(TempoMap::constant(120.0).beat_at(3.0), 6.0);
+    assert!(TempoMap::from_json(r#"[{"time": 0, "bpm": 0}]"#).is_err());
+    assert!(TempoMap::from_json("[]").is_err());
+}
+
+#[test]
+fn declared_tempo_map() {
+    let ctxt = Context::new("<test>".into(), r"
+        tempo 0: 60, 2: 60, 4: 120;
+        main beat, bar { beat * 100 + bar }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+
+    let mut program = Program::new(&compiler, "main", 10).unwrap();
+    assert_eq!(program.eval(2.0), 200.5);
+    assert_eq!(program.eval(4.0), 500.0 + 5.0 / 4.0);
+    // a single tempo replaces the map
+    program.set_tempo(120.0);
+    assert_eq!(program.eval(2.0), 400.0 + 1.0);
+}
+
+#[test]
+fn invalid_tempo_map() {
+    let ctxt = Context::new("<test>".into(), r"
+        tempo 0: 120;
+        tempo 0: 90;
+        main time { time }
+    ".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.declare_entrypoint("main");
+    let issues = compiler.compile().err().unwrap();
+    let issues = issues.to_string();
+    assert!(issues.contains("error[E129]: the tempo map is already declared"), "{}", issues);
+}
@@ -0,0 +1,65 @@
+extern crate interpreter;
+
+use interpreter::common::Context;
+use interpreter::compiler::Compiler;
+use interpreter::testing::run_tests;
+
+#[test]
+fn failing_asserts_are_reported() {
+    let ctxt = Context::new("<test>".into(), r#"
+double x { x * 2 }
+test "doubles" {
+    assert(double(2) == 4);
+    assert(double(0.5) ~= 1);
+}
+test "broken" {
+    assert(double(1) == 2);
+    assert(double(1) == 3);
+    assert(double(2) == 5);
+}
+"#.into());
+    ctxt.options.borrow_mut().test = true;
+    let mut compiler = Compiler::new(&ctxt);
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+
+    let results = run_tests(&compiler, 44100, 0);
+    assert_eq!(results.len(), 2);
+    assert!(results[0].passed());
+    assert_eq!(results[1].name, "broken");
+    let lines: Vec<_> = results[1].failures.iter().map(|x| x.pos.unwrap().line).collect();
+    assert_eq!(lines, vec![9, 10]);
+}
+
+#[test]
+fn assert_messages() {
+    let ctxt = Context::new("<test>".into(), r#"
+one { 1 }
+test "messages" {
+    assert(one() == 2, "not equal");
+}
+"#.into());
+    ctxt.options.borrow_mut().test = true;
+    let mut compiler = Compiler::new(&ctxt);
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+
+    let results = run_tests(&compiler, 44100, 0);
+    assert_eq!(results[0].failures[0].message, "not equal");
+    assert!(results[0].failures[0].to_string().ends_with(": not equal"));
+}
+
+#[test]
+fn tests_do_not_need_an_entrypoint() {
+    let ctxt = Context::new("<test>".into(), r#"
+main time { time }
+"#.into());
+    ctxt.options.borrow_mut().test = true;
+    let mut compiler = Compiler::new(&ctxt);
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+    assert!(run_tests(&compiler, 44100, 0).is_empty());
+}
@@ -0,0 +1,55 @@
+extern crate interpreter;
+
+use interpreter::common::Context;
+use interpreter::compiler::Compiler;
+use interpreter::runtime::Program;
+
+fn compile<'a>(ctxt: &'a Context<'a>) -> Compiler<'a> {
+    let mut compiler = Compiler::new(ctxt);
+    compiler.declare_entrypoint("main");
+    if let Err(issues) = compiler.compile() {
+        panic!("compile failed:\n{}", issues);
+    }
+    compiler
+}
+
+#[test]
+fn xfade_mixes_two_functions() {
+    let ctxt = Context::new("<test>".into(), r"
+        main time { low = \{ 1 }; xfade(low, \{ 3 }, time) }
+    ".into());
+    let compiler = compile(&ctxt);
+    let mut program = Program::new(&compiler, "main", 10).unwrap();
+    assert_eq!(program.eval(0.0), 1.0);
+    assert_eq!(program.eval(0.25), 1.5);
+    // the position is clamped
+    assert_eq!(program.eval(2.0), 3.0);
+}
+
+#[test]
+fn switch_fades_to_the_selected_function() {
+    // at 10 samples a second, a fade of 200ms takes two samples
+    let ctxt = Context::new("<test>".into(), r"
+        main time { switch(if time < 1 { 0 } else { 1 }, [\{ 1 }, \{ 3 }], 200) }
+    ".into());
+    let compiler = compile(&ctxt);
+    let mut program = Program::new(&compiler, "main", 10).unwrap();
+    assert_eq!(program.eval(0.0), 1.0);
+    assert_eq!(program.eval(1.0), 2.0);
+    assert_eq!(program.eval(1.1), 3.0);
+    assert_eq!(program.eval(5.0), 3.0);
+}
+
+#[test]
+fn invalid_transitions() {
+    for &(source, msg) in &[
+        (r"main time { xfade(\{ 1 }, \{ 2 }) }", "error[E131]: `xfade` takes three arguments in order"),
+        (r"main time { switch(0, \{ 1 }, 50) }", "error[E131]: `switch` takes its functions as an array"),
+    ] {
+        let ctxt = Context::new("<test>".into(), source.into());
+        let mut compiler = Compiler::new(&ctxt);
+        compiler.declare_entrypoint("main");
+        let issues = compiler.compile().err().unwrap().to_string();
+        assert!(issues.contains(msg), "{}", issues);
+    }
+}
@@ -1,5 +1,6 @@
 #[macro_use]
 extern crate interpreter;
+extern crate vec_map;
 
 #[test]
 fn reassign_different_type() {
@@ -93,18 +94,16 @@ fn ambiguous_recursion() {
 }
 
 #[test]
-fn wrong_arg_type() {
+fn generic_function() {
     run_test!(
-        should_fail(typecheck),
-        should_pass(lex, parse)
+        should_pass(lex, parse, typecheck)
         => r"
             fn x { x }
             x = fn(1);
             y = fn(true);
         ");
     run_test!(
-        should_fail(typecheck),
-        should_pass(lex, parse)
+        should_pass(lex, parse, typecheck)
         => r"
             fn x { x }
             z = fn;
@@ -113,6 +112,17 @@ fn wrong_arg_type() {
         ");
 }
 
+#[test]
+fn wrong_arg_type() {
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r"
+            fn x { -x }
+            y = fn(true);
+        ");
+}
+
 #[test]
 fn wrong_block_type() {
     run_test!(
@@ -368,3 +378,408 @@ fn recursion_closure_issue() {
             x = summer(\n { fact(n) }, 5);
         ");
 }
+
+#[test]
+fn annotated_function() {
+    run_test!(
+        should_pass(lex, parse, typecheck)
+        => r"
+            fn x: Number, y: Boolean -> Number { x if y else -x }
+            z = fn(1, true);
+        ");
+}
+
+#[test]
+fn annotated_argument_mismatch() {
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r"
+            fn x: Number { x }
+            z = fn(true);
+        ");
+}
+
+#[test]
+fn annotated_return_mismatch_without_call() {
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r"
+            fn x: Number -> Boolean { x * 2 }
+        ");
+}
+
+#[test]
+fn annotated_recursion() {
+    run_test!(
+        should_pass(lex, parse, typecheck)
+        => r"
+            fact n: Number -> Number { 1 if n <= 1 else n * fact(n - 1) }
+            x = fact(5);
+        ");
+}
+
+#[test]
+fn recursive_function_called_twice() {
+    run_test!(
+        should_pass(lex, parse, typecheck)
+        => r"
+            fact n { n*fact(n-1) if n > 1 else 1 }
+            x = fact(3);
+            y = fact(4);
+        ");
+}
+
+#[test]
+fn mutual_recursion() {
+    run_test!(
+        should_pass(lex, parse, typecheck)
+        => r"
+            a x { b(x-1) if x > 0 else 0 }
+            b x { a(x) + 1 }
+            y = a(3);
+        ");
+}
+
+#[test]
+fn recursion_conflicting_types() {
+    run_test!(
+        should_pass(lex, parse),
+        should_fail(typecheck)
+        => r"
+            a x { !a(x) if x > 0 else 0 }
+            y = a(3);
+        ");
+}
+
+#[test]
+fn unifier_binds_and_reports_both_positions() {
+    use interpreter::types::{Type, Unifier};
+    use interpreter::tokens::SourcePos;
+
+    let mut unifier = Unifier::new();
+    let var = unifier.fresh();
+    let (mut first, mut second) = (SourcePos::new(), SourcePos::new());
+    first.add_chars(1);
+    second.add_chars(5);
+
+    assert_eq!(unifier.unify(var, first, Type::Number, first), Ok(Type::Number));
+    assert_eq!(unifier.resolve(var), Type::Number);
+
+    let mismatch = unifier.unify(var, second, Type::Boolean, second).unwrap_err();
+    assert_eq!(mismatch.expected, Type::Number);
+    assert_eq!(mismatch.expected_pos, first);
+    assert_eq!(mismatch.found, Type::Boolean);
+    assert_eq!(mismatch.found_pos, second);
+}
+
+#[test]
+fn unifier_compares_function_signatures() {
+    use interpreter::types::{FunctionType, Type, Unifier};
+    use interpreter::tokens::SourcePos;
+    use vec_map::VecMap;
+
+    // arguments are declared in the order given
+    let signature = |args: &[(usize, Type)], returns| {
+        FunctionType::with_order(args.iter().cloned().collect::<VecMap<_>>(),
+                                 args.iter().map(|x| x.0).collect(), returns)
+    };
+    let pos = SourcePos::new();
+    let mut unifier = Unifier::new();
+    let var = unifier.fresh();
+    unifier.set_signature(10, signature(&[(1, var)], Type::Number));
+    unifier.set_signature(11, signature(&[(2, Type::Number), (3, Type::Number)], Type::Number));
+    unifier.set_signature(12, signature(&[(4, Type::Boolean)], Type::Number));
+    unifier.set_signature(13, signature(&[(5, Type::Boolean)], Type::Boolean));
+    unifier.set_signature(14, signature(&[(7, Type::Boolean), (6, Type::Number)], Type::Number));
+    unifier.set_signature(15, signature(&[(8, Type::Boolean), (9, Type::Number)], Type::Number));
+
+    // a variable can't stand for a function which takes it
+    assert!(unifier.unify(var, pos, Type::Function(10), pos).is_err());
+    assert_eq!(unifier.resolve(var), var);
+
+    assert!(unifier.unify(Type::Function(10), pos, Type::Function(11), pos).is_err());
+    assert_eq!(unifier.unify(Type::Function(10), pos, Type::Function(12), pos), Ok(Type::Function(10)));
+    assert_eq!(unifier.resolve(var), Type::Boolean);
+    assert!(unifier.unify(Type::Function(12), pos, Type::Function(13), pos).is_err());
+
+    // arguments are matched by their position, not their identifiers
+    assert_eq!(unifier.unify(Type::Function(14), pos, Type::Function(15), pos), Ok(Type::Function(14)));
+}
+
+#[test]
+fn similar_names() {
+    use interpreter::ident::{edit_distance, similar_names};
+
+    assert_eq!(edit_distance("cutoff", "cutoff"), 0);
+    assert_eq!(edit_distance("cutof", "cutoff"), 1);
+    assert_eq!(edit_distance("lowpass", "highpass"), 3);
+
+    let candidates = vec!["cutoff", "cutout", "resonance", "cut"];
+    assert_eq!(similar_names("cutof", candidates.into_iter().map(|x| x.to_string())),
+               vec!["cutoff".to_string()]);
+    let candidates = vec!["resonance", "resonant", "reso"];
+    assert_eq!(similar_names("resonence", candidates.into_iter().map(|x| x.to_string())),
+               vec!["resonance".to_string(), "resonant".to_string()]);
+}
+
+#[test]
+fn assignment_redefines_function() {
+    run_test!(
+        should_pass(lex, parse),
+        should_fail(typecheck)
+        => r"
+            f x { x * 2 }
+            f = 3;
+        ");
+}
+
+#[test]
+fn inner_scope_shadows_function() {
+    run_test!(
+        should_pass(lex, parse, typecheck)
+        => r"
+            f x { x * 2 }
+            g x {
+                f = x + 1;
+                f * 2
+            }
+            y = g(f(1));
+        ");
+}
+
+#[test]
+fn defined_constants() {
+    use interpreter::common::Context;
+    use interpreter::compiler::Compiler;
+
+    let ctxt = Context::new("<test>".into(), "x = voices * 2;".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.define_constant("voices", 8.0);
+    assert!(compiler.lex() && compiler.parse());
+    assert!(compiler.typecheck(), "{}", *ctxt.issues.borrow());
+
+    let ctxt = Context::new("<test>".into(), "voices = 4;\nx = voices * 2;".into());
+    let mut compiler = Compiler::new(&ctxt);
+    compiler.define_constant("voices", 8.0);
+    assert!(compiler.lex() && compiler.parse());
+    assert!(!compiler.typecheck());
+    assert!(ctxt.issues.borrow().to_string().contains("E110"));
+}
+
+#[test]
+fn constant_asserts() {
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r#"
+            voices = 2 - 2;
+            f x { assert(voices > 0, "no voices"); x }
+        "#);
+    run_test!(
+        should_pass(lex, parse, typecheck)
+        => r#"
+            voices = 4;
+            f x { assert(voices > 0); assert(x > 0); x }
+        "#);
+}
+
+#[test]
+fn strings_are_compile_time_only() {
+    run_test!(
+        should_pass(lex, parse, typecheck)
+        => r#"
+            label = "env";
+            watch name, x { probe(name, x) }
+            f x { watch(if true { label } else { "other" }, x) }
+        "#);
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r#"
+            f x { probe(if x > 0 { "up" } else { "down" }, x) }
+        "#);
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r#"
+            same = "a" == "a";
+        "#);
+}
+
+#[test]
+fn stereo_signals() {
+    run_test!(
+        should_pass(lex, parse, typecheck)
+        => r"
+            wide = pan(1, -0.5) + stereo(0.5, 1) - width(1, 0, 2);
+            quiet = wide * 0.5 / 2 + 0.5 * -wide;
+            mixed = mono(quiet) + left(quiet) * right(quiet * quiet);
+            center = if mixed > 0 { pan(mixed, 0) } else { stereo(0, 0) };
+        ");
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r"
+            sum = pan(1, 0) + 1;
+        ");
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r"
+            ratio = 1 / pan(1, 0);
+        ");
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r"
+            loud = pan(1, 0) > 1;
+        ");
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r"
+            level = mono(1);
+        ");
+}
+
+#[test]
+fn bus_sends() {
+    run_test!(
+        should_pass(lex, parse, typecheck)
+        => r#"
+            bus "room" input { reverb(input, 0.5, 0.5, 1) }
+            bus "delay" input { send("room", input * 0.5); pan(input, 1) }
+            sent = send("delay", 1) + send("room", 0.5);
+        "#);
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r#"
+            bus "room" input { input }
+            sent = send("rooom", 1);
+        "#);
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r#"
+            echo x = send("b", x);
+            bus "a" input { echo(input) }
+            bus "b" input { send("a", input * 0.5); input }
+        "#);
+}
+
+#[test]
+fn buffer_names() {
+    run_test!(
+        should_pass(lex, parse, typecheck)
+        => r#"
+            buffer "loop" 1;
+            played = record("loop", 0.5, 1) + playbuf("loop", 0, 1);
+        "#);
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r#"
+            buffer "loop" 1;
+            played = playbuf("lop", 0, 1);
+        "#);
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r#"
+            buffer "loop" 1;
+            played = stretch("lop", 0, 1, 2);
+        "#);
+}
+
+#[test]
+fn impulse_responses() {
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r#"
+            wet = convolve(1, "no such room.wav");
+        "#);
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r#"
+            reverb x room { convolve(x, room) }
+            wet = reverb(1, "hall.wav");
+        "#);
+}
+
+#[test]
+fn pitch_names() {
+    run_test!(
+        should_pass(lex, parse, typecheck)
+        => r#"
+            mode = if true { "dorian" } else { "nope" };
+            note = scale(2, mode, 60) + chord(60, "min7", 1) + quantize(440, "blues");
+        "#);
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r#"
+            note = scale(2, "minr", 60);
+        "#);
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r#"
+            quality = "major";
+            note = chord[root = 60, quality, voice = 0];
+        "#);
+}
+
+#[test]
+fn arp_modes() {
+    run_test!(
+        should_pass(lex, parse, typecheck)
+        => r#"
+            note = arp([60, 64, 67], 1, "updown") + arp([60], 1, if true { "up" } else { "sideways" });
+        "#);
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r#"
+            note = arp([60, 64, 67], 1, "upp");
+        "#);
+}
+
+#[test]
+fn arrays() {
+    run_test!(
+        should_pass(lex, parse, typecheck)
+        => r"
+            notes = [60, 62, 64 + 3];
+            pitch = seq(1, notes);
+            on = gate[clock = 1, values = [1, 0]];
+        ");
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r"
+            notes = [];
+        ");
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r"
+            notes = [1, true];
+        ");
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r"
+            step = seq;
+        ");
+    run_test!(
+        should_fail(typecheck),
+        should_pass(lex, parse)
+        => r"
+            same = [1] == [1];
+        ");
+}
@@ -0,0 +1,25 @@
+extern crate interpreter;
+
+use interpreter::audio::watchdog::Watchdog;
+
+use std::time::Duration;
+
+#[test]
+fn loads_and_warnings() {
+    let watchdog = Watchdog::new(false);
+    let budget = Duration::from_millis(40);
+    assert_eq!(watchdog.record(Duration::from_millis(10), budget), 0.25);
+    assert_eq!(watchdog.warning(0.25), None);
+
+    // warnings are spaced out while the load stays high
+    let load = watchdog.record(Duration::from_millis(36), budget);
+    assert!(watchdog.warning(load).is_some());
+    let load = watchdog.record(Duration::from_millis(60), budget);
+    assert!(load > 1.0);
+    assert_eq!(watchdog.warning(load), None);
+
+    watchdog.underrun();
+    watchdog.reduced(2);
+    assert_eq!(watchdog.report(),
+               "3 buffers rendered, 88.3% load on average, 150.0% at peak, 1 underruns, oversampling reduced to 2x");
+}